@@ -0,0 +1,138 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use sqlx::postgres::replication::{decode_logical, LogicalDecodeContext, LogicalReplication};
+use sqlx::postgres::types::Oid;
+
+/// The OID of the relation of the frames.
+const RELATION_ID: u32 = 16384;
+
+/// A value of a column in the text format, or `NULL`.
+enum Value<'a> {
+    Text(&'a [u8]),
+    Null,
+}
+
+/// Encode a `TupleData` of `pgoutput`.
+fn tuple_data(buf: &mut Vec<u8>, values: &[Value<'_>]) {
+    buf.extend_from_slice(&i16::try_from(values.len()).unwrap().to_be_bytes());
+
+    for value in values {
+        match value {
+            Value::Text(text) => {
+                buf.push(b't');
+                buf.extend_from_slice(&i32::try_from(text.len()).unwrap().to_be_bytes());
+                buf.extend_from_slice(text);
+            }
+            Value::Null => buf.push(b'n'),
+        }
+    }
+}
+
+/// An `Insert` message of a row, as `pgoutput` sends it.
+fn insert(values: &[Value<'_>]) -> Vec<u8> {
+    let mut buf = vec![b'I'];
+    buf.extend_from_slice(&RELATION_ID.to_be_bytes());
+    buf.push(b'N');
+    tuple_data(&mut buf, values);
+    buf
+}
+
+/// An `Update` message of a row whose key was changed, with the key columns of the old row.
+fn update(key: &[Value<'_>], values: &[Value<'_>]) -> Vec<u8> {
+    let mut buf = vec![b'U'];
+    buf.extend_from_slice(&RELATION_ID.to_be_bytes());
+    buf.push(b'K');
+    tuple_data(&mut buf, key);
+    buf.push(b'N');
+    tuple_data(&mut buf, values);
+    buf
+}
+
+/// The frames to decode: a narrow row like `(id, name, created_at)`, a row of a wide table of
+/// 40 columns, some of them `NULL`, and a row with a large text value.
+fn corpus() -> Vec<(&'static str, Vec<u8>)> {
+    let narrow = [
+        Value::Text(b"4711"),
+        Value::Text(b"Jane Doe"),
+        Value::Text(b"2024-05-17 09:26:53.154829+00"),
+    ];
+
+    let wide_values: Vec<String> = (0..40).map(|i| format!("value of column {i}")).collect();
+    let wide: Vec<_> = (wide_values.iter().enumerate())
+        .map(|(i, value)| match i % 5 {
+            4 => Value::Null,
+            _ => Value::Text(value.as_bytes()),
+        })
+        .collect();
+    let wide_key: Vec<_> = (0..40)
+        .map(|i| match i {
+            0 => Value::Text(b"4711"),
+            _ => Value::Null,
+        })
+        .collect();
+
+    let text = "lorem ipsum dolor sit amet ".repeat(40_000);
+    let large = [
+        Value::Text(b"4711"),
+        Value::Text(text.as_bytes()),
+        Value::Null,
+    ];
+
+    vec![
+        ("insert/narrow", insert(&narrow)),
+        ("insert/wide", insert(&wide)),
+        ("insert/large_text", insert(&large)),
+        (
+            "update/narrow",
+            update(&[Value::Text(b"4711"), Value::Null, Value::Null], &narrow),
+        ),
+        ("update/wide", update(&wide_key, &wide)),
+    ]
+}
+
+/// Decode the frames, including their `Tuples`.
+fn bench_decode(c: &mut Criterion) {
+    let ctx = LogicalDecodeContext::new(1);
+    let mut group = c.benchmark_group("replication_decode");
+
+    for (name, frame) in corpus() {
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frame, |b, frame| {
+            b.iter(|| decode_logical(frame, ctx).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+/// Decode the frames and then the values of all of their columns as text, the hot loop of a
+/// consumer that maps every column.
+fn bench_decode_values(c: &mut Criterion) {
+    let ctx = LogicalDecodeContext::new(1);
+    let mut group = c.benchmark_group("replication_decode_values");
+
+    for (name, frame) in corpus() {
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &frame, |b, frame| {
+            b.iter(|| {
+                let new_data = match decode_logical(frame, ctx).unwrap() {
+                    LogicalReplication::Insert(insert) => insert.new_data,
+                    LogicalReplication::Update(update) => update.new_data,
+                    message => panic!("unexpected message: {message:?}"),
+                };
+
+                // the OID of `text`
+                new_data
+                    .iter()
+                    .filter(|data| !data.is_null())
+                    .map(|data| data.try_decode::<&str>(Oid(25)).unwrap().len())
+                    .sum::<usize>()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_decode_values);
+criterion_main!(benches);
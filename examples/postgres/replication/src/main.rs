@@ -0,0 +1,175 @@
+use std::env;
+use std::io::{self, Write};
+use std::time::UNIX_EPOCH;
+
+use clap::Parser;
+use serde_json::{json, Value};
+use sqlx::postgres::replication::{
+    BeforeImage, CreateReplicationSlot, LogicalReplication, LogicalReplicationStream,
+    PgOutputOptions, PgReplicationConnection, SnapshotAction,
+};
+use sqlx::postgres::types::PgLsn;
+
+/// Print the changes of a logical replication slot as JSON Lines.
+#[derive(Parser)]
+struct Args {
+    /// The name of the replication slot.
+    #[arg(long)]
+    slot: String,
+    /// The publications whose tables to replicate; can be repeated.
+    #[arg(long = "publication", required = true)]
+    publications: Vec<String>,
+    /// The position to start at, e.g. `0/1A2B3C4`; defaults to the position of the slot.
+    #[arg(long)]
+    start_lsn: Option<PgLsn>,
+    /// The version of the `pgoutput` protocol.
+    #[arg(long, default_value_t = 1)]
+    proto_version: u32,
+    /// Create the slot if it doesn't exist yet.
+    #[arg(long)]
+    create_slot: bool,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let mut conn = PgReplicationConnection::connect(&env::var("DATABASE_URL")?).await?;
+
+    if args.create_slot && conn.replication_slot(&args.slot).await?.is_none() {
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical(&args.slot, "pgoutput")
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+    }
+
+    let options = PgOutputOptions::new(&args.publications).proto_version(args.proto_version);
+
+    let mut stream = conn
+        .start_logical_replication(
+            &args.slot,
+            args.start_lsn.unwrap_or(PgLsn::INVALID),
+            options,
+        )
+        .await?;
+
+    // `Relation` and `Type` messages are only used to map the rows
+    stream.set_schema_messages(false);
+
+    let mut stdout = io::stdout().lock();
+
+    loop {
+        let message = tokio::select! {
+            // `recv()` is cancel-safe, so no message is lost
+            message = stream.recv() => message?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        let Some(message) = message else {
+            break;
+        };
+
+        let Some(record) = to_record(&stream, &message)? else {
+            continue;
+        };
+
+        writeln!(stdout, "{record}")?;
+
+        // the slot advances once the transaction was written
+        if let LogicalReplication::Commit(commit) = message {
+            stdout.flush()?;
+            stream.set_confirmed_lsn(commit.end_lsn);
+        }
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+/// Map a message to a JSON record, or `None` for the messages that are not printed.
+fn to_record(
+    stream: &LogicalReplicationStream,
+    message: &LogicalReplication,
+) -> anyhow::Result<Option<Value>> {
+    let relation = |relation_id| {
+        stream
+            .relation(relation_id)
+            .ok_or_else(|| anyhow::anyhow!("change of unknown relation {relation_id:?}"))
+    };
+
+    let record = match message {
+        LogicalReplication::Begin(begin) => json!({
+            "type": "begin",
+            "xid": begin.xid,
+            "final_lsn": begin.final_lsn.to_string(),
+            "commit_time_ms": begin.commit_time().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+        }),
+        LogicalReplication::Commit(commit) => json!({
+            "type": "commit",
+            "commit_lsn": commit.commit_lsn.to_string(),
+            "end_lsn": commit.end_lsn.to_string(),
+        }),
+        LogicalReplication::Insert(insert) => {
+            let relation = relation(insert.relation_id)?;
+
+            json!({
+                "type": "insert",
+                "table": relation.qualified_name(),
+                "new": insert.new_data.to_json(relation)?,
+            })
+        }
+        LogicalReplication::Update(update) => {
+            let relation = relation(update.relation_id)?;
+            let (before, after) = update.to_json_images(relation)?;
+
+            // the old row is only sent for `REPLICA IDENTITY FULL`, or if the key changed
+            let (old, key) = match before {
+                Some(BeforeImage::Full(old)) => (Some(old), None),
+                Some(BeforeImage::Key(key)) => (None, Some(key)),
+                None => (None, None),
+            };
+
+            json!({
+                "type": "update",
+                "table": relation.qualified_name(),
+                "old": old,
+                "key": key,
+                "new": after,
+            })
+        }
+        LogicalReplication::Delete(delete) => {
+            let relation = relation(delete.relation_id)?;
+
+            json!({
+                "type": "delete",
+                "table": relation.qualified_name(),
+                "key": delete.key(relation)?.to_json()?,
+            })
+        }
+        LogicalReplication::Truncate(truncate) => {
+            let tables = truncate
+                .relation_ids
+                .iter()
+                .map(|&relation_id| Ok(relation(relation_id)?.qualified_name()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            json!({
+                "type": "truncate",
+                "tables": tables,
+                "cascade": truncate.cascade(),
+            })
+        }
+        LogicalReplication::Message(message) => json!({
+            "type": "message",
+            "transactional": message.transactional,
+            "lsn": message.lsn.to_string(),
+            "prefix": message.prefix,
+            "content": String::from_utf8_lossy(&message.content),
+        }),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(record))
+}
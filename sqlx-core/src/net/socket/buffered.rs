@@ -24,6 +24,9 @@ pub struct WriteBuffer {
 pub struct ReadBuffer {
     read: BytesMut,
     available: BytesMut,
+    /// The spare capacity to reserve before reading from the socket, if more than needed for
+    /// the message being read; `0` reserves only what is needed.
+    min_capacity: usize,
 }
 
 impl<S: Socket> BufferedSocket<S> {
@@ -41,6 +44,7 @@ impl<S: Socket> BufferedSocket<S> {
             read_buf: ReadBuffer {
                 read: BytesMut::new(),
                 available: BytesMut::with_capacity(DEFAULT_BUF_SIZE),
+                min_capacity: 0,
             },
         }
     }
@@ -145,6 +149,16 @@ impl<S: Socket> BufferedSocket<S> {
         self.read_buf.shrink();
     }
 
+    /// Reserve at least `capacity` bytes before each read from the socket, so that a single
+    /// read can receive several small messages at once, or `0` to only reserve the space
+    /// needed for the message being read (the default).
+    ///
+    /// Lowering the capacity doesn't release memory by itself; see
+    /// [`shrink_buffers()`][Self::shrink_buffers].
+    pub fn set_read_buffer_capacity(&mut self, capacity: usize) {
+        self.read_buf.min_capacity = capacity;
+    }
+
     pub fn into_inner(self) -> S {
         self.socket
     }
@@ -301,6 +315,8 @@ impl ReadBuffer {
     }
 
     fn reserve(&mut self, amt: usize) {
+        let amt = cmp::max(amt, self.min_capacity);
+
         if let Some(additional) = amt.checked_sub(self.available.capacity()) {
             self.available.reserve(additional);
         }
@@ -311,7 +327,9 @@ impl ReadBuffer {
     }
 
     fn shrink(&mut self) {
-        if self.available.capacity() > DEFAULT_BUF_SIZE {
+        let capacity = cmp::max(self.min_capacity, DEFAULT_BUF_SIZE);
+
+        if self.available.capacity() > capacity {
             // `BytesMut` doesn't have a way to shrink its capacity,
             // but we only use `available` for spare capacity anyway so we can just replace it.
             //
@@ -323,7 +341,7 @@ impl ReadBuffer {
             // but that's also kind of unavoidable.
             //
             // We should be warning the user not to call this often.
-            self.available = BytesMut::with_capacity(DEFAULT_BUF_SIZE);
+            self.available = BytesMut::with_capacity(capacity);
         }
     }
 }
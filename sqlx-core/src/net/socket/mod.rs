@@ -3,6 +3,7 @@ use std::io;
 use std::path::Path;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use bytes::BufMut;
 use futures_core::ready;
@@ -185,10 +186,85 @@ impl<S: Socket + ?Sized> Socket for Box<S> {
     }
 }
 
+/// The TCP keepalive settings of a connection, which let the operating system detect a peer
+/// that went away, e.g. behind a NAT or firewall that silently drops idle connections.
+///
+/// A setting left at `None` keeps the default of the operating system.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// The time the connection is idle before the first keepalive probe is sent
+    /// (`TCP_KEEPIDLE`).
+    pub idle: Option<Duration>,
+    /// The time between two keepalive probes (`TCP_KEEPINTVL`).
+    pub interval: Option<Duration>,
+    /// The number of unanswered probes after which the connection is considered dead
+    /// (`TCP_KEEPCNT`); ignored on platforms that don't support setting it, like Windows.
+    pub retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    #[cfg(any(feature = "_rt-tokio", feature = "_rt-async-std"))]
+    fn apply(&self, socket: socket2::SockRef<'_>) -> io::Result<()> {
+        let mut keepalive = socket2::TcpKeepalive::new();
+
+        if let Some(idle) = self.idle {
+            keepalive = keepalive.with_time(idle);
+        }
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "tvos",
+            target_os = "watchos",
+            target_os = "windows",
+        ))]
+        if let Some(interval) = self.interval {
+            keepalive = keepalive.with_interval(interval);
+        }
+
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "illumos",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "tvos",
+            target_os = "watchos",
+        ))]
+        if let Some(retries) = self.retries {
+            keepalive = keepalive.with_retries(retries);
+        }
+
+        socket.set_tcp_keepalive(&keepalive)
+    }
+}
+
 pub async fn connect_tcp<Ws: WithSocket>(
     host: &str,
     port: u16,
     with_socket: Ws,
+) -> crate::Result<Ws::Output> {
+    connect_tcp_with_keepalive(host, port, None, with_socket).await
+}
+
+/// Connect to `host` and `port` like [`connect_tcp()`], enabling TCP keepalives on the socket
+/// with the given settings.
+pub async fn connect_tcp_with_keepalive<Ws: WithSocket>(
+    host: &str,
+    port: u16,
+    keepalive: Option<&TcpKeepalive>,
+    with_socket: Ws,
 ) -> crate::Result<Ws::Output> {
     // IPv6 addresses in URLs will be wrapped in brackets and the `url` crate doesn't trim those.
     let host = host.trim_matches(&['[', ']'][..]);
@@ -200,6 +276,10 @@ pub async fn connect_tcp<Ws: WithSocket>(
         let stream = TcpStream::connect((host, port)).await?;
         stream.set_nodelay(true)?;
 
+        if let Some(keepalive) = keepalive {
+            keepalive.apply(socket2::SockRef::from(&stream))?;
+        }
+
         return Ok(with_socket.with_socket(stream).await);
     }
 
@@ -217,6 +297,11 @@ pub async fn connect_tcp<Ws: WithSocket>(
                 .await
                 .and_then(|s| {
                     s.get_ref().set_nodelay(true)?;
+
+                    if let Some(keepalive) = keepalive {
+                        keepalive.apply(socket2::SockRef::from(s.get_ref()))?;
+                    }
+
                     Ok(s)
                 });
             match stream {
@@ -239,7 +324,7 @@ pub async fn connect_tcp<Ws: WithSocket>(
 
     #[cfg(not(feature = "_rt-async-std"))]
     {
-        crate::rt::missing_rt((host, port, with_socket))
+        crate::rt::missing_rt((host, port, keepalive, with_socket))
     }
 }
 
@@ -1,3 +1,7 @@
+use std::io;
+
+use sqlx_core::rt;
+
 use crate::HashMap;
 
 use crate::common::StatementCache;
@@ -16,6 +20,21 @@ use super::PgConnectionInner;
 
 impl PgConnection {
     pub(crate) async fn establish(options: &PgConnectOptions) -> Result<Self, Error> {
+        let Some(timeout) = options.connect_timeout else {
+            return Self::establish_inner(options).await;
+        };
+
+        rt::timeout(timeout, Self::establish_inner(options))
+            .await
+            .map_err(|_| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("connecting timed out after {timeout:?}"),
+                ))
+            })?
+    }
+
+    async fn establish_inner(options: &PgConnectOptions) -> Result<Self, Error> {
         // Upgrade to TLS if we were asked to and the server supports it
         let mut stream = PgStream::connect(options).await?;
 
@@ -45,6 +64,12 @@ impl PgConnection {
             params.push(("options", options));
         }
 
+        if let Some(replication) = options.replication {
+            // Starts a walsender instead of a regular backend, which accepts
+            // the replication commands in addition to (simple) queries.
+            params.push(("replication", replication));
+        }
+
         stream.write(Startup {
             username: Some(&options.username),
             database: options.database.as_deref(),
@@ -42,8 +42,8 @@ pub struct PgConnectionInner {
 
     // process id of this backend
     // used to send cancel requests
-    #[allow(dead_code)]
-    process_id: u32,
+    #[cfg_attr(not(feature = "replication-stream"), allow(dead_code))]
+    pub(crate) process_id: u32,
 
     // secret key of this backend
     // used to send cancel requests
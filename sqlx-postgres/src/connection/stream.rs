@@ -25,6 +25,8 @@ use crate::{PgConnectOptions, PgDatabaseError, PgSeverity};
 // in other words, `self` in any PgConnection method is a live connection to postgres that
 // is fully prepared to receive queries
 
+pub(crate) type NoticeHandler = Box<dyn FnMut(&Notice) + Send>;
+
 pub struct PgStream {
     // A trait object is okay here as the buffering amortizes the overhead of both the dynamic
     // function call as well as the syscall.
@@ -38,13 +40,25 @@ pub struct PgStream {
     pub(crate) parameter_statuses: BTreeMap<String, String>,
 
     pub(crate) server_version_num: Option<u32>,
+
+    // called with notices instead of logging them
+    // this is set by a replication stream when the user provides a notice handler
+    pub(crate) notice_handler: Option<NoticeHandler>,
 }
 
 impl PgStream {
     pub(super) async fn connect(options: &PgConnectOptions) -> Result<Self, Error> {
         let socket_result = match options.fetch_socket() {
             Some(ref path) => net::connect_uds(path, MaybeUpgradeTls(options)).await?,
-            None => net::connect_tcp(&options.host, options.port, MaybeUpgradeTls(options)).await?,
+            None => {
+                net::connect_tcp_with_keepalive(
+                    &options.host,
+                    options.port,
+                    options.tcp_keepalive.as_ref(),
+                    MaybeUpgradeTls(options),
+                )
+                .await?
+            }
         };
 
         let socket = socket_result?;
@@ -54,9 +68,23 @@ impl PgStream {
             notifications: None,
             parameter_statuses: BTreeMap::default(),
             server_version_num: None,
+            notice_handler: None,
         })
     }
 
+    /// A stream over an already connected `socket`, e.g. one that replays the messages of a
+    /// server in tests.
+    #[cfg(test)]
+    pub(crate) fn from_socket(socket: impl Socket) -> Self {
+        Self {
+            inner: BufferedSocket::new(Box::new(socket)),
+            notifications: None,
+            parameter_statuses: BTreeMap::default(),
+            server_version_num: None,
+            notice_handler: None,
+        }
+    }
+
     #[inline(always)]
     pub(crate) fn write_msg(&mut self, message: impl FrontendMessage) -> Result<(), Error> {
         self.write(EncodeMessage(message))
@@ -118,6 +146,26 @@ impl PgStream {
             .await
     }
 
+    /// The format code and the length of the next message, including the length prefix but
+    /// not the format code, without receiving it.
+    ///
+    /// This is cancel-safe, as nothing is consumed from the buffer.
+    #[cfg_attr(not(feature = "replication-stream"), allow(dead_code))]
+    pub(crate) async fn peek_header(&mut self) -> Result<(u8, usize), Error> {
+        self.inner
+            .try_read(|buf| {
+                let Some(mut header) = buf.get(..5) else {
+                    return Ok(ControlFlow::Continue(5));
+                };
+
+                Ok(ControlFlow::Break((
+                    header.get_u8(),
+                    header.get_u32() as usize,
+                )))
+            })
+            .await
+    }
+
     // Get the next message from the server
     // May wait for more data from the server
     pub(crate) async fn recv(&mut self) -> Result<ReceivedMessage, Error> {
@@ -149,6 +197,7 @@ impl PgStream {
                     match name.as_str() {
                         "server_version" => {
                             self.server_version_num = parse_server_version(&value);
+                            self.parameter_statuses.insert(name, value);
                         }
                         _ => {
                             self.parameter_statuses.insert(name, value);
@@ -164,6 +213,11 @@ impl PgStream {
 
                     let notice: Notice = message.decode()?;
 
+                    if let Some(handler) = &mut self.notice_handler {
+                        handler(&notice);
+                        continue;
+                    }
+
                     let (log_level, tracing_level) = match notice.severity() {
                         PgSeverity::Fatal | PgSeverity::Panic | PgSeverity::Error => {
                             (Level::Error, tracing::Level::ERROR)
@@ -17,6 +17,7 @@ mod listener;
 mod message;
 mod options;
 mod query_result;
+pub mod replication;
 mod row;
 mod statement;
 mod transaction;
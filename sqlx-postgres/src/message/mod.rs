@@ -35,6 +35,8 @@ pub use backend_key_data::BackendKeyData;
 pub use bind::Bind;
 pub use close::Close;
 pub use command_complete::CommandComplete;
+#[cfg(feature = "replication-stream")]
+pub use copy::CopyBothResponse;
 pub use copy::{CopyData, CopyDone, CopyFail, CopyInResponse, CopyOutResponse, CopyResponseData};
 pub use data_row::DataRow;
 pub use describe::Describe;
@@ -95,6 +97,7 @@ pub enum BackendMessageFormat {
     BindComplete,
     CloseComplete,
     CommandComplete,
+    CopyBothResponse,
     CopyData,
     CopyDone,
     CopyInResponse,
@@ -151,6 +154,7 @@ impl BackendMessageFormat {
             b'2' => BackendMessageFormat::BindComplete,
             b'3' => BackendMessageFormat::CloseComplete,
             b'C' => BackendMessageFormat::CommandComplete,
+            b'W' => BackendMessageFormat::CopyBothResponse,
             b'd' => BackendMessageFormat::CopyData,
             b'c' => BackendMessageFormat::CopyDone,
             b'G' => BackendMessageFormat::CopyInResponse,
@@ -2,10 +2,12 @@ use std::borrow::Cow;
 use std::env::var;
 use std::fmt::{Display, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub use ssl_mode::PgSslMode;
 
-use crate::{connection::LogSettings, net::tls::CertificateInput};
+use crate::connection::LogSettings;
+use crate::net::{tls::CertificateInput, TcpKeepalive};
 
 mod connect;
 mod parse;
@@ -41,6 +43,11 @@ mod ssl_mode;
 /// | `port` | `5432` | Port number to connect to at the server host, or socket file name extension for Unix-domain connections. |
 /// | `dbname` | `None` | The database name. |
 /// | `options` | `None` | The runtime parameters to send to the server at connection start. |
+/// | `keepalives` | `0` | Whether to enable TCP keepalives (`1`) with the defaults of the operating system. |
+/// | `keepalives_idle` | `None` | The number of seconds a connection is idle before a TCP keepalive probe is sent; enables keepalives. |
+/// | `keepalives_interval` | `None` | The number of seconds between two TCP keepalive probes; enables keepalives. |
+/// | `keepalives_count` | `None` | The number of unanswered TCP keepalive probes after which the connection is dead; enables keepalives. |
+/// | `connect_timeout` | `None` | The maximum number of seconds to wait while connecting, including authentication. |
 ///
 /// The URL scheme designator can be either `postgresql://` or `postgres://`.
 /// Each of the URL parts is optional.
@@ -103,6 +110,9 @@ pub struct PgConnectOptions {
     pub(crate) log_settings: LogSettings,
     pub(crate) extra_float_digits: Option<Cow<'static, str>>,
     pub(crate) options: Option<String>,
+    pub(crate) replication: Option<&'static str>,
+    pub(crate) tcp_keepalive: Option<TcpKeepalive>,
+    pub(crate) connect_timeout: Option<Duration>,
 }
 
 impl Default for PgConnectOptions {
@@ -169,6 +179,9 @@ impl PgConnectOptions {
             extra_float_digits: Some("2".into()),
             log_settings: Default::default(),
             options: var("PGOPTIONS").ok(),
+            replication: None,
+            tcp_keepalive: None,
+            connect_timeout: None,
         }
     }
 
@@ -520,6 +533,68 @@ impl PgConnectOptions {
         self
     }
 
+    /// Enables or disables TCP keepalives, with the defaults of the operating system for the
+    /// settings not set with [`keepalives_idle()`][Self::keepalives_idle],
+    /// [`keepalives_interval()`][Self::keepalives_interval] and
+    /// [`keepalives_count()`][Self::keepalives_count].
+    ///
+    /// Keepalives let the operating system detect a server that went away without closing the
+    /// connection, e.g. behind a NAT or firewall that silently drops idle connections, which
+    /// matters most for long-lived connections like replication connections. They are disabled
+    /// by default, and are not used for Unix-domain sockets.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use sqlx_postgres::PgConnectOptions;
+    /// let options = PgConnectOptions::new()
+    ///     .keepalives_idle(Duration::from_secs(30))
+    ///     .keepalives_interval(Duration::from_secs(10))
+    ///     .keepalives_count(3);
+    /// ```
+    pub fn keepalives(mut self, keepalives: bool) -> Self {
+        self.tcp_keepalive = keepalives.then(|| self.tcp_keepalive.unwrap_or_default());
+        self
+    }
+
+    /// Sets the time a connection is idle before the first TCP keepalive probe is sent, and
+    /// enables keepalives; see [`keepalives()`][Self::keepalives].
+    pub fn keepalives_idle(mut self, idle: Duration) -> Self {
+        self.tcp_keepalive.get_or_insert_with(Default::default).idle = Some(idle);
+        self
+    }
+
+    /// Sets the time between two TCP keepalive probes, and enables keepalives; see
+    /// [`keepalives()`][Self::keepalives].
+    pub fn keepalives_interval(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive
+            .get_or_insert_with(Default::default)
+            .interval = Some(interval);
+        self
+    }
+
+    /// Sets the number of unanswered TCP keepalive probes after which the connection is
+    /// considered dead, and enables keepalives; see [`keepalives()`][Self::keepalives].
+    ///
+    /// This is ignored on platforms that don't support it, like Windows.
+    pub fn keepalives_count(mut self, count: u32) -> Self {
+        self.tcp_keepalive
+            .get_or_insert_with(Default::default)
+            .retries = Some(count);
+        self
+    }
+
+    /// Sets the maximum time to wait for a connection to be established, including TLS
+    /// negotiation and authentication. Defaults to `None`, i.e. no limit.
+    ///
+    /// Connecting fails with an [`io::ErrorKind::TimedOut`][std::io::ErrorKind::TimedOut]
+    /// error when it elapses.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
     /// We try using a socket if hostname starts with `/` or if socket parameter
     /// is specified.
     pub(crate) fn fetch_socket(&self) -> Option<String> {
@@ -4,6 +4,7 @@ use sqlx_core::percent_encoding::{percent_decode_str, utf8_percent_encode, NON_A
 use sqlx_core::Url;
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
 impl PgConnectOptions {
     pub(crate) fn parse_from_url(url: &Url) -> Result<Self, Error> {
@@ -47,6 +48,8 @@ impl PgConnectOptions {
             );
         }
 
+        let mut keepalives = None;
+
         for (key, value) in url.query_pairs().into_iter() {
             match &*key {
                 "sslmode" | "ssl-mode" => {
@@ -98,6 +101,22 @@ impl PgConnectOptions {
                     }
                 }
 
+                "keepalives" => keepalives = Some(value != "0"),
+
+                "keepalives_idle" => {
+                    options = options.keepalives_idle(parse_seconds(&value)?);
+                }
+
+                "keepalives_interval" => {
+                    options = options.keepalives_interval(parse_seconds(&value)?);
+                }
+
+                "keepalives_count" => {
+                    options = options.keepalives_count(value.parse().map_err(Error::config)?);
+                }
+
+                "connect_timeout" => options = options.connect_timeout(parse_seconds(&value)?),
+
                 k if k.starts_with("options[") => {
                     if let Some(key) = k.strip_prefix("options[").unwrap().strip_suffix(']') {
                         options = options.options([(key, &*value)]);
@@ -108,6 +127,11 @@ impl PgConnectOptions {
             }
         }
 
+        // like for libpq, `keepalives=0` disables keepalives regardless of the other settings
+        if let Some(keepalives) = keepalives {
+            options = options.keepalives(keepalives);
+        }
+
         let options = options.apply_pgpass();
 
         Ok(options)
@@ -170,6 +194,12 @@ impl PgConnectOptions {
     }
 }
 
+/// Parse a number of seconds, like libpq does for `connect_timeout` and the `keepalives_*`
+/// parameters.
+fn parse_seconds(value: &str) -> Result<Duration, Error> {
+    Ok(Duration::from_secs(value.parse().map_err(Error::config)?))
+}
+
 impl FromStr for PgConnectOptions {
     type Err = Error;
 
@@ -340,3 +370,24 @@ fn built_url_can_be_parsed() {
 
     assert!(parsed.is_ok());
 }
+
+#[test]
+fn it_parses_keepalives_and_connect_timeout() {
+    let url = "postgres://localhost/?keepalives_idle=30&keepalives_interval=10&keepalives_count=3&connect_timeout=5";
+    let opts = PgConnectOptions::from_str(url).unwrap();
+
+    let keepalive = opts.tcp_keepalive.unwrap();
+    assert_eq!(keepalive.idle, Some(Duration::from_secs(30)));
+    assert_eq!(keepalive.interval, Some(Duration::from_secs(10)));
+    assert_eq!(keepalive.retries, Some(3));
+    assert_eq!(opts.connect_timeout, Some(Duration::from_secs(5)));
+
+    let opts = PgConnectOptions::from_str("postgres://localhost/?keepalives=1").unwrap();
+    assert_eq!(opts.tcp_keepalive, Some(Default::default()));
+
+    let url = "postgres://localhost/?keepalives_idle=30&keepalives=0";
+    let opts = PgConnectOptions::from_str(url).unwrap();
+    assert_eq!(opts.tcp_keepalive, None);
+
+    assert!(PgConnectOptions::from_str("postgres://localhost/?connect_timeout=soon").is_err());
+}
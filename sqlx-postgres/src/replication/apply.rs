@@ -0,0 +1,562 @@
+use sqlx_core::encode::{Encode, IsNull};
+use sqlx_core::error::BoxDynError;
+use sqlx_core::executor::Executor;
+use sqlx_core::types::Type as SqlxType;
+
+use crate::arguments::{PgArgumentBuffer, PgArguments};
+use crate::error::Error;
+use crate::type_info::PgType;
+use crate::types::Oid;
+use crate::{PgConnection, PgQueryResult, PgTypeInfo, Postgres};
+
+use super::{
+    quote_ident, Column, Delete, Insert, LogicalReplication, Projection, Relation, ReplicaIdentity,
+    ReplicationError, Truncate, TupleData, Type, Update,
+};
+
+/// A parameterized statement that applies a change received from `pgoutput` to another
+/// Postgres database, e.g. a replica of the published tables.
+///
+/// This is a building block for a sink that writes to Postgres: it turns one change into one
+/// statement, and leaves batching, transactions and the confirmed position to the caller.
+///
+/// * An insert is an upsert by the key columns of the relation
+///   (`INSERT ... ON CONFLICT (key) DO UPDATE`), so that a change received again after a
+///   restart is applied idempotently. Relations without key columns or with
+///   `REPLICA IDENTITY FULL` are inserted into without a conflict target.
+/// * An update sets all columns with a known value: columns with an unchanged TOAST value are
+///   left out of the `SET` list, as their value was not sent and is already in the row. The
+///   row is found by its old key if it was sent, i.e. if the key changed or for
+///   `REPLICA IDENTITY FULL`, otherwise by the key columns of the new row.
+/// * A delete is by its [key][Delete::key], i.e. the key columns of the relation, or all
+///   columns for `REPLICA IDENTITY FULL`.
+/// * A truncate is the [statement][super::ResolvedTruncate::to_sql] of the original.
+///
+/// [`ReplicationError::MissingKey`] is returned for an update or delete of a relation without
+/// key columns, e.g. with `REPLICA IDENTITY NOTHING`, whose rows can't be found.
+///
+/// Rows are matched with `=` for each column, or `IS NULL`; with `REPLICA IDENTITY FULL` this
+/// includes columns of types without an equality operator, like `json`, which fails. Columns
+/// with an unchanged TOAST value in an old row are not compared.
+///
+/// Values in the text format are bound as `text` and cast to the type of the column by name,
+/// e.g. `$1::pg_catalog.text::pg_catalog."int4"`, so they are parsed by the target database;
+/// the names of types that are not built in are taken from the [`Type`] message the server
+/// sent for them, usually with [`LogicalReplicationStream::custom_type()`]. Values in the
+/// binary format are bound as they are, which is only possible for built-in types, whose OIDs
+/// are the same on every database.
+///
+/// Tables are named by the [qualified name][Relation::qualified_name] of the relation; to
+/// apply the changes to tables of another schema or name, pass a copy of the relation with its
+/// `namespace` and `name` changed.
+///
+/// ```rust,no_run
+/// # async fn example(
+/// #     stream: &mut sqlx::postgres::replication::LogicalReplicationStream,
+/// #     target: &mut sqlx::PgConnection,
+/// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use sqlx::postgres::replication::ApplyStatement;
+///
+/// while let Some(change) = stream.recv().await? {
+///     let statement = ApplyStatement::from_change(
+///         &change,
+///         |relation_id| stream.relation(relation_id),
+///         |type_id| stream.custom_type(type_id),
+///     )?;
+///
+///     if let Some(statement) = statement {
+///         statement.execute(&mut *target).await?;
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`LogicalReplicationStream::custom_type()`]: super::LogicalReplicationStream::custom_type
+#[derive(Default)]
+pub struct ApplyStatement {
+    sql: String,
+    arguments: PgArguments,
+    count: usize,
+}
+
+impl ApplyStatement {
+    /// The statement that applies `change`, with its relations resolved with `relations` and
+    /// the types of its columns that are not built in with `types`, or `None` for messages
+    /// that don't change rows, like [`Begin`][super::Begin] or [`Relation`].
+    ///
+    /// Returns an error if a relation is unknown to `relations`.
+    pub fn from_change<'r, 't>(
+        change: &LogicalReplication,
+        relations: impl Fn(Oid) -> Option<&'r Relation>,
+        types: impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<Option<Self>, ReplicationError> {
+        let relation = |relation_id: Oid| {
+            relations(relation_id).ok_or_else(|| {
+                err_protocol!(
+                    "relation {} was not described by a Relation message",
+                    relation_id.0
+                )
+            })
+        };
+
+        let statement = match change {
+            LogicalReplication::Insert(insert) => {
+                Self::insert(relation(insert.relation_id)?, insert, types)?
+            }
+            LogicalReplication::Update(update) => {
+                Self::update(relation(update.relation_id)?, update, types)?
+            }
+            LogicalReplication::Delete(delete) => {
+                Self::delete(relation(delete.relation_id)?, delete, types)?
+            }
+            LogicalReplication::Truncate(truncate) => Self::truncate(truncate, relations)?,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(statement))
+    }
+
+    /// An upsert of the row of `insert` into the table of `relation`.
+    pub fn insert<'t>(
+        relation: &Relation,
+        insert: &Insert,
+        types: impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<Self, ReplicationError> {
+        insert.new_data.check_relation(relation)?;
+
+        let mut statement = Self::default();
+        let mut values = Vec::with_capacity(relation.columns.len());
+
+        for (column, data) in relation.columns.iter().zip(insert.new_data.iter()) {
+            values.push(statement.bind(column, data, &types)?);
+        }
+
+        let names: Vec<_> = (relation.columns.iter())
+            .map(|column| quote_ident(&column.name))
+            .collect();
+
+        statement.sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            relation.qualified_name(),
+            names.join(", "),
+            values.join(", ")
+        );
+
+        let key = Projection::key(relation);
+
+        if relation.replica_identity != ReplicaIdentity::Full && !key.indices().is_empty() {
+            let conflict: Vec<_> = (key.indices().iter())
+                .map(|&index| quote_ident(&relation.columns[index].name))
+                .collect();
+
+            let set: Vec<_> = (relation.columns.iter())
+                .filter(|column| column.flags & 1 == 0)
+                .map(|column| {
+                    let name = quote_ident(&column.name);
+                    format!("{name} = EXCLUDED.{name}")
+                })
+                .collect();
+
+            statement.sql += &format!(" ON CONFLICT ({})", conflict.join(", "));
+
+            if set.is_empty() {
+                statement.sql += " DO NOTHING";
+            } else {
+                statement.sql += &format!(" DO UPDATE SET {}", set.join(", "));
+            }
+        }
+
+        Ok(statement)
+    }
+
+    /// An update of the row of `update` in the table of `relation`.
+    pub fn update<'t>(
+        relation: &Relation,
+        update: &Update,
+        types: impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<Self, ReplicationError> {
+        update.new_data.check_relation(relation)?;
+
+        let mut statement = Self::default();
+        let mut set = Vec::with_capacity(relation.columns.len());
+
+        for (column, data) in relation.columns.iter().zip(update.new_data.iter()) {
+            // the value of an unchanged TOAST column was not sent, and stays as it is
+            if *data != TupleData::UnchangedToast {
+                let value = statement.bind(column, data, &types)?;
+                set.push(format!("{} = {value}", quote_ident(&column.name)));
+            }
+        }
+
+        // the row is found by its old key or row if one was sent, i.e. if the key changed or
+        // for `REPLICA IDENTITY FULL`, and otherwise by the key of the new row
+        let (row, projection) = match (&update.key_data, &update.old_data) {
+            (Some(key), _) => (key, Projection::key(relation)),
+            (None, Some(old)) => (old, Projection::all(relation)),
+            (None, None) => (&update.new_data, Projection::key(relation)),
+        };
+
+        if projection.indices().is_empty() {
+            return Err(ReplicationError::MissingKey {
+                relation: relation.qualified_name(),
+            });
+        }
+
+        row.check_relation(relation)?;
+
+        let columns =
+            (projection.indices().iter()).map(|&index| (&relation.columns[index], &row[index]));
+        let condition = statement.condition(relation, columns, &types)?;
+
+        statement.sql = format!(
+            "UPDATE {} SET {} WHERE {condition}",
+            relation.qualified_name(),
+            set.join(", ")
+        );
+
+        Ok(statement)
+    }
+
+    /// A delete of the row of `delete` from the table of `relation`, by its
+    /// [key][Delete::key].
+    pub fn delete<'t>(
+        relation: &Relation,
+        delete: &Delete,
+        types: impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<Self, ReplicationError> {
+        let key = delete.key(relation)?;
+
+        if key.is_empty() {
+            return Err(ReplicationError::MissingKey {
+                relation: relation.qualified_name(),
+            });
+        }
+
+        let mut statement = Self::default();
+        let condition = statement.condition(relation, key.columns(), &types)?;
+
+        statement.sql = format!(
+            "DELETE FROM {} WHERE {condition}",
+            relation.qualified_name()
+        );
+
+        Ok(statement)
+    }
+
+    /// The `TRUNCATE` statement of `truncate`, with its relations resolved with `relations`;
+    /// see [`Truncate::to_sql()`].
+    pub fn truncate<'r>(
+        truncate: &Truncate,
+        relations: impl Fn(Oid) -> Option<&'r Relation>,
+    ) -> Result<Self, ReplicationError> {
+        Ok(Self {
+            sql: truncate.to_sql(relations)?,
+            ..Self::default()
+        })
+    }
+
+    /// The SQL of the statement, with a `$n` parameter for each value.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The SQL of the statement and the arguments to bind to it, e.g. to run it with
+    /// [`query_with()`][crate::query::query_with] on a transaction.
+    pub fn into_parts(self) -> (String, PgArguments) {
+        (self.sql, self.arguments)
+    }
+
+    /// Run the statement on `conn`, the target database.
+    pub async fn execute(self, conn: &mut PgConnection) -> Result<PgQueryResult, Error> {
+        conn.execute(crate::query::query_with(&self.sql, self.arguments))
+            .await
+    }
+
+    /// The condition that matches a row by the values of `columns`.
+    fn condition<'a, 't>(
+        &mut self,
+        relation: &Relation,
+        columns: impl Iterator<Item = (&'a Column, &'a TupleData)>,
+        types: &impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<String, Error> {
+        let mut conditions = Vec::new();
+
+        for (column, data) in columns {
+            let name = quote_ident(&column.name);
+
+            match data {
+                TupleData::Null => conditions.push(format!("{name} IS NULL")),
+                // the value was not sent, so the row can't be matched by it
+                TupleData::UnchangedToast => {}
+                data => {
+                    let value = self.bind(column, data, types)?;
+                    conditions.push(format!("{name} = {value}"));
+                }
+            }
+        }
+
+        if conditions.is_empty() {
+            return Err(Error::Protocol(format!(
+                "no value to find a row of relation {} by",
+                relation.qualified_name()
+            )));
+        }
+
+        Ok(conditions.join(" AND "))
+    }
+
+    /// Bind the value `data` of `column`, returning the expression of the parameter.
+    fn bind<'t>(
+        &mut self,
+        column: &Column,
+        data: &TupleData,
+        types: &impl Fn(Oid) -> Option<&'t Type>,
+    ) -> Result<String, Error> {
+        let (bytes, type_info, cast) = match data {
+            TupleData::Null => return Ok("NULL".into()),
+            TupleData::UnchangedToast => {
+                return Err(Error::Protocol(format!(
+                    "unchanged TOAST value of column {:?} cannot be applied",
+                    column.name
+                )))
+            }
+            TupleData::Text(bytes) => (bytes, PgTypeInfo::TEXT, Some(type_name(column, types)?)),
+            // the binary format of a type that is not built in may contain OIDs of the source
+            TupleData::Binary(bytes) => match PgType::try_from_oid(column.type_id) {
+                Some(ty) => (bytes, PgTypeInfo(ty), None),
+                None => {
+                    return Err(Error::Protocol(format!(
+                        "binary value of column {:?} of type OID {} cannot be applied; \
+                         stream without `PgOutputOptions::binary()`",
+                        column.name, column.type_id.0
+                    )))
+                }
+            },
+        };
+
+        self.arguments
+            .add(RawValue { bytes, type_info })
+            .map_err(Error::Encode)?;
+        self.count += 1;
+
+        Ok(match cast {
+            Some(name) => format!("${}::pg_catalog.text::{name}", self.count),
+            None => format!("${}", self.count),
+        })
+    }
+}
+
+/// The quoted, schema-qualified name of the type of `column`, to cast a value to.
+fn type_name<'t>(
+    column: &Column,
+    types: &impl Fn(Oid) -> Option<&'t Type>,
+) -> Result<String, Error> {
+    if let Some(ty) = PgType::try_from_oid(column.type_id) {
+        return Ok(format!("pg_catalog.{}", quote_ident(ty.name())));
+    }
+
+    let ty = types(column.type_id).ok_or_else(|| {
+        Error::Protocol(format!(
+            "type OID {} of column {:?} was not described by a Type message",
+            column.type_id.0, column.name
+        ))
+    })?;
+
+    // an empty namespace stands for `pg_catalog`
+    let namespace = if ty.namespace.is_empty() {
+        "pg_catalog"
+    } else {
+        &ty.namespace
+    };
+
+    Ok(format!(
+        "{}.{}",
+        quote_ident(namespace),
+        quote_ident(&ty.name)
+    ))
+}
+
+/// A value bound as it was received, in the binary format of `type_info`.
+struct RawValue<'a> {
+    bytes: &'a [u8],
+    type_info: PgTypeInfo,
+}
+
+impl SqlxType<Postgres> for RawValue<'_> {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::TEXT
+    }
+}
+
+impl Encode<'_, Postgres> for RawValue<'_> {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        buf.extend_from_slice(self.bytes);
+
+        Ok(IsNull::No)
+    }
+
+    fn produces(&self) -> Option<PgTypeInfo> {
+        Some(self.type_info.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::replication::Tuples;
+
+    fn relation(replica_identity: ReplicaIdentity) -> Relation {
+        let column = |flags: u8, name: &str, type_id: u32| Column {
+            flags,
+            name: name.to_owned(),
+            type_id: Oid(type_id),
+            type_modifier: -1,
+        };
+
+        Relation {
+            xid: None,
+            relation_id: Oid(16384),
+            namespace: "public".to_owned(),
+            name: "users".to_owned(),
+            replica_identity,
+            columns: vec![
+                column(1, "id", 23),
+                column(0, "name", 25),
+                column(0, "mood", 16400),
+            ],
+        }
+    }
+
+    fn mood() -> Type {
+        Type {
+            xid: None,
+            type_id: Oid(16400),
+            namespace: "public".to_owned(),
+            name: "mood".to_owned(),
+        }
+    }
+
+    fn text(value: &'static str) -> TupleData {
+        TupleData::Text(Bytes::from_static(value.as_bytes()))
+    }
+
+    #[test]
+    fn it_builds_upserts_for_inserts() {
+        let mood = mood();
+        let types = |type_id| (type_id == mood.type_id).then_some(&mood);
+        let insert = Insert {
+            xid: None,
+            relation_id: Oid(16384),
+            new_data: Tuples(vec![text("1"), TupleData::Null, text("happy")]),
+        };
+
+        let statement =
+            ApplyStatement::insert(&relation(ReplicaIdentity::Default), &insert, types).unwrap();
+        assert_eq!(
+            statement.sql(),
+            r#"INSERT INTO "public"."users" ("id", "name", "mood") VALUES ($1::pg_catalog.text::pg_catalog."int4", NULL, $2::pg_catalog.text::"public"."mood") ON CONFLICT ("id") DO UPDATE SET "name" = EXCLUDED."name", "mood" = EXCLUDED."mood""#
+        );
+
+        // without a key the row is inserted as it is
+        let statement =
+            ApplyStatement::insert(&relation(ReplicaIdentity::Full), &insert, types).unwrap();
+        assert_eq!(
+            statement.sql(),
+            r#"INSERT INTO "public"."users" ("id", "name", "mood") VALUES ($1::pg_catalog.text::pg_catalog."int4", NULL, $2::pg_catalog.text::"public"."mood")"#
+        );
+
+        // the name of a type that is not built in must be known
+        assert!(
+            ApplyStatement::insert(&relation(ReplicaIdentity::Default), &insert, |_| None).is_err()
+        );
+    }
+
+    #[test]
+    fn it_leaves_unchanged_toast_values_out_of_updates() {
+        let mut update = Update {
+            xid: None,
+            relation_id: Oid(16384),
+            key_data: None,
+            old_data: None,
+            new_data: Tuples(vec![
+                TupleData::Binary(Bytes::from_static(&[0, 0, 0, 1])),
+                TupleData::UnchangedToast,
+                TupleData::Null,
+            ]),
+        };
+
+        let statement =
+            ApplyStatement::update(&relation(ReplicaIdentity::Default), &update, |_| None).unwrap();
+        assert_eq!(
+            statement.sql(),
+            r#"UPDATE "public"."users" SET "id" = $1, "mood" = NULL WHERE "id" = $2"#
+        );
+
+        // the old key, if the key changed
+        update.key_data = Some(Tuples(vec![text("2"), TupleData::Null, TupleData::Null]));
+        let statement =
+            ApplyStatement::update(&relation(ReplicaIdentity::Default), &update, |_| None).unwrap();
+        assert_eq!(
+            statement.sql(),
+            r#"UPDATE "public"."users" SET "id" = $1, "mood" = NULL WHERE "id" = $2::pg_catalog.text::pg_catalog."int4""#
+        );
+
+        // a relation without a key has no rows to update
+        let mut relation = relation(ReplicaIdentity::Nothing);
+        relation.columns[0].flags = 0;
+        update.key_data = None;
+        assert!(matches!(
+            ApplyStatement::update(&relation, &update, |_| None),
+            Err(ReplicationError::MissingKey { .. })
+        ));
+    }
+
+    #[test]
+    fn it_deletes_by_the_old_row() {
+        let mood = mood();
+        let mut relation = relation(ReplicaIdentity::Full);
+        for column in &mut relation.columns {
+            column.flags = 1;
+        }
+
+        let delete = Delete {
+            xid: None,
+            relation_id: Oid(16384),
+            key_data: None,
+            old_data: Some(Tuples(vec![
+                text("1"),
+                TupleData::UnchangedToast,
+                TupleData::Null,
+            ])),
+        };
+
+        let statement = ApplyStatement::delete(&relation, &delete, |_| Some(&mood)).unwrap();
+        assert_eq!(
+            statement.sql(),
+            r#"DELETE FROM "public"."users" WHERE "id" = $1::pg_catalog.text::pg_catalog."int4" AND "mood" IS NULL"#
+        );
+    }
+
+    #[test]
+    fn it_skips_messages_without_changes() {
+        let relation = relation(ReplicaIdentity::Default);
+        let change = LogicalReplication::Relation(relation.clone());
+
+        let statement = ApplyStatement::from_change(&change, |_| Some(&relation), |_| None);
+        assert!(statement.unwrap().is_none());
+
+        let change = LogicalReplication::Truncate(Truncate {
+            xid: None,
+            options: 1,
+            relation_ids: vec![Oid(16384)],
+        });
+        let statement = ApplyStatement::from_change(&change, |_| Some(&relation), |_| None);
+        assert_eq!(
+            statement.unwrap().unwrap().sql(),
+            r#"TRUNCATE "public"."users" CASCADE"#
+        );
+    }
+}
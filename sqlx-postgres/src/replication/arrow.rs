@@ -0,0 +1,609 @@
+//! Accumulation of decoded changes into Apache Arrow [`RecordBatch`]es, one per relation, e.g.
+//! to write them to a data lake in a columnar format.
+//!
+//! [`ArrowBatcher`] appends the rows of [`Insert`][super::Insert], [`Update`][super::Update]
+//! and [`Delete`][super::Delete] messages to the pending batch of their relation and returns it
+//! once it reached a number of rows or an age.
+//! The schema of a batch, as returned by [`arrow_schema()`], consists of the [`OP_COLUMN`]
+//! followed by the columns of the relation:
+//!
+//! | Postgres type | Arrow type |
+//! |---|---|
+//! | `bool` | `Boolean` |
+//! | `int2`, `int4`, `int8` | `Int16`, `Int32`, `Int64` |
+//! | `float4`, `float8` | `Float32`, `Float64` |
+//! | `bytea` | `Binary` |
+//! | any other type | `Utf8` |
+//!
+//! Values of other types are only mapped in the text format. As the `Relation` message
+//! doesn't tell whether a column is `NOT NULL`, all columns are nullable.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow_array::builder::{
+    ArrayBuilder, BinaryBuilder, BooleanBuilder, Float32Builder, Float64Builder, Int16Builder,
+    Int32Builder, Int64Builder, StringBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use crate::error::Error;
+use crate::type_info::PgType;
+use crate::types::Oid;
+
+use super::{Column, LogicalReplication, Relation, TupleData, Tuples};
+
+/// The name of the first column of every batch, with the kind of change of each row: `"I"`
+/// for an insert, `"U"` for an update and `"D"` for a delete.
+pub const OP_COLUMN: &str = "_op";
+
+/// The Arrow schema of the batches of `relation`: the [`OP_COLUMN`] followed by the columns of
+/// the relation, mapped as described in the [module documentation][self].
+///
+/// The name and namespace of the relation are stored in the `name` and `namespace` metadata
+/// of the schema.
+pub fn arrow_schema(relation: &Relation) -> Schema {
+    let fields = std::iter::once(Field::new(OP_COLUMN, DataType::Utf8, false))
+        .chain(
+            (relation.columns.iter())
+                .map(|column| Field::new(&column.name, data_type(column.type_id), true)),
+        )
+        .collect::<Vec<_>>();
+
+    let metadata = HashMap::from([
+        ("namespace".to_owned(), relation.namespace.clone()),
+        ("name".to_owned(), relation.name.clone()),
+    ]);
+
+    Schema::new_with_metadata(fields, metadata)
+}
+
+fn data_type(type_id: Oid) -> DataType {
+    match PgType::try_from_oid(type_id) {
+        Some(PgType::Bool) => DataType::Boolean,
+        Some(PgType::Int2) => DataType::Int16,
+        Some(PgType::Int4) => DataType::Int32,
+        Some(PgType::Int8) => DataType::Int64,
+        Some(PgType::Float4) => DataType::Float32,
+        Some(PgType::Float8) => DataType::Float64,
+        Some(PgType::Bytea) => DataType::Binary,
+        _ => DataType::Utf8,
+    }
+}
+
+/// A batch of the changes of one relation, returned by [`ArrowBatcher`].
+#[derive(Debug, Clone)]
+pub struct RelationBatch {
+    /// The OID of the relation, matching [`Relation::relation_id`].
+    pub relation_id: Oid,
+    /// The rows, with the schema returned by [`arrow_schema()`] for the relation.
+    pub batch: RecordBatch,
+}
+
+/// Accumulates the changes of each relation into Arrow record batches.
+///
+/// Each row holds the new row of an [`Insert`][super::Insert] or [`Update`][super::Update], or
+/// the old row of a [`Delete`][super::Delete]; for a relation without `REPLICA IDENTITY FULL`,
+/// only the key columns of a deleted row are known and the other columns are null. A column
+/// with an unchanged TOAST value, which is not sent, is null as well: when merging the rows of
+/// updates into a table, a null value in a column that can be TOASTed (e.g. `text` or `bytea`)
+/// may thus mean that the value was not changed.
+///
+/// ```rust,no_run
+/// # async fn example(
+/// #     mut stream: sqlx::postgres::replication::LogicalReplicationStream,
+/// # ) -> Result<(), Box<dyn std::error::Error>> {
+/// use std::time::Duration;
+///
+/// use sqlx::postgres::replication::arrow::ArrowBatcher;
+/// use sqlx::postgres::replication::{Delete, Insert, LogicalReplication, Update};
+///
+/// let mut batcher = ArrowBatcher::new(10_000).max_age(Duration::from_secs(60));
+///
+/// while let Some(message) = stream.recv().await? {
+///     let batches = match &message {
+///         LogicalReplication::Insert(Insert { relation_id, .. })
+///         | LogicalReplication::Update(Update { relation_id, .. })
+///         | LogicalReplication::Delete(Delete { relation_id, .. }) => {
+///             let relation = stream.relation(*relation_id).expect("relation not sent");
+///             batcher.push(relation, &message)?
+///         }
+///         _ => batcher.flush_expired()?,
+///     };
+///
+///     for batch in batches {
+///         // write `batch.batch` to the data lake
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ArrowBatcher {
+    max_rows: usize,
+    max_age: Option<Duration>,
+    pending: HashMap<Oid, PendingBatch>,
+}
+
+impl ArrowBatcher {
+    /// Create a batcher that returns the batch of a relation once it has `max_rows` rows.
+    pub fn new(max_rows: usize) -> Self {
+        Self {
+            max_rows,
+            max_age: None,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Also return the batch of a relation once its first row was added `max_age` ago.
+    ///
+    /// The age is checked when a row is added and with [`flush_expired()`][Self::flush_expired],
+    /// which should be called periodically, e.g. for every message received, so that the
+    /// batches of relations without further changes are returned as well.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Add the row of `change`, a change of `relation`, returning the batches that are
+    /// complete.
+    ///
+    /// Messages other than [`Insert`][super::Insert], [`Update`][super::Update] and
+    /// [`Delete`][super::Delete] are ignored. If the columns of `relation` changed since the
+    /// pending batch was started, that batch is returned before the row is added to a new one.
+    ///
+    /// Returns an error, without adding the row, if a value can't be decoded, or is in the
+    /// binary format and of a type that is mapped to `Utf8`.
+    pub fn push(
+        &mut self,
+        relation: &Relation,
+        change: &LogicalReplication,
+    ) -> Result<Vec<RelationBatch>, Error> {
+        let (op, row) = match change {
+            LogicalReplication::Insert(insert) => ("I", &insert.new_data),
+            LogicalReplication::Update(update) => ("U", &update.new_data),
+            LogicalReplication::Delete(delete) => {
+                match delete.old_data.as_ref().or(delete.key_data.as_ref()) {
+                    Some(row) => ("D", row),
+                    // nothing is sent for a relation with `REPLICA IDENTITY NOTHING`
+                    None => return self.flush_expired(),
+                }
+            }
+            _ => return self.flush_expired(),
+        };
+
+        let values = decode_row(relation, row)?;
+        let mut batches = Vec::new();
+
+        if let Some(pending) = self.pending.get(&relation.relation_id) {
+            if !pending.has_columns(&relation.columns) {
+                batches.extend(self.flush(relation.relation_id)?);
+            }
+        }
+
+        let pending = self
+            .pending
+            .entry(relation.relation_id)
+            .or_insert_with(|| PendingBatch::new(relation));
+
+        pending.append(op, values);
+
+        if pending.rows >= self.max_rows {
+            batches.extend(self.flush(relation.relation_id)?);
+        }
+
+        batches.extend(self.flush_expired()?);
+
+        Ok(batches)
+    }
+
+    /// Return the batches whose first row was added longer than the
+    /// [maximum age][Self::max_age] ago.
+    pub fn flush_expired(&mut self) -> Result<Vec<RelationBatch>, Error> {
+        let Some(max_age) = self.max_age else {
+            return Ok(Vec::new());
+        };
+
+        let expired = (self.pending.iter())
+            .filter(|(_, pending)| pending.started.elapsed() >= max_age)
+            .map(|(&relation_id, _)| relation_id)
+            .collect::<Vec<_>>();
+
+        let mut batches = Vec::with_capacity(expired.len());
+
+        for relation_id in expired {
+            batches.extend(self.flush(relation_id)?);
+        }
+
+        Ok(batches)
+    }
+
+    /// Return all pending batches, e.g. before committing the position of the stream.
+    pub fn flush_all(&mut self) -> Result<Vec<RelationBatch>, Error> {
+        let relation_ids = self.pending.keys().copied().collect::<Vec<_>>();
+        let mut batches = Vec::with_capacity(relation_ids.len());
+
+        for relation_id in relation_ids {
+            batches.extend(self.flush(relation_id)?);
+        }
+
+        Ok(batches)
+    }
+
+    /// The number of rows that were added but not returned yet, over all relations.
+    pub fn pending_rows(&self) -> usize {
+        self.pending.values().map(|pending| pending.rows).sum()
+    }
+
+    fn flush(&mut self, relation_id: Oid) -> Result<Option<RelationBatch>, Error> {
+        let Some(pending) = self.pending.remove(&relation_id) else {
+            return Ok(None);
+        };
+
+        let batch = pending.finish()?;
+
+        Ok(Some(RelationBatch { relation_id, batch }))
+    }
+}
+
+/// The rows of one relation that were not returned yet.
+#[derive(Debug)]
+struct PendingBatch {
+    schema: SchemaRef,
+    /// The names and types of the columns of the relation the batch was started for.
+    columns: Vec<(String, Oid)>,
+    ops: StringBuilder,
+    builders: Vec<ColumnBuilder>,
+    rows: usize,
+    started: Instant,
+}
+
+impl PendingBatch {
+    fn new(relation: &Relation) -> Self {
+        Self {
+            schema: Arc::new(arrow_schema(relation)),
+            columns: (relation.columns.iter())
+                .map(|column| (column.name.clone(), column.type_id))
+                .collect(),
+            ops: StringBuilder::new(),
+            builders: (relation.columns.iter())
+                .map(|column| ColumnBuilder::new(column.type_id))
+                .collect(),
+            rows: 0,
+            started: Instant::now(),
+        }
+    }
+
+    fn has_columns(&self, columns: &[Column]) -> bool {
+        self.columns.len() == columns.len()
+            && (self.columns.iter().zip(columns))
+                .all(|((name, type_id), column)| *name == column.name && *type_id == column.type_id)
+    }
+
+    fn append(&mut self, op: &str, values: Vec<Value>) {
+        self.ops.append_value(op);
+
+        for (builder, value) in self.builders.iter_mut().zip(values) {
+            builder.append(value);
+        }
+
+        self.rows += 1;
+    }
+
+    fn finish(mut self) -> Result<RecordBatch, Error> {
+        let columns = std::iter::once(Arc::new(self.ops.finish()) as ArrayRef)
+            .chain(self.builders.iter_mut().map(ColumnBuilder::finish))
+            .collect();
+
+        RecordBatch::try_new(self.schema, columns).map_err(|error| Error::Decode(error.into()))
+    }
+}
+
+/// A decoded value, which is only appended once all values of a row were decoded, so a
+/// value that can't be decoded doesn't leave the builders with different lengths.
+enum Value {
+    Null,
+    Bool(bool),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    Binary(Vec<u8>),
+    Utf8(String),
+}
+
+fn decode_row(relation: &Relation, row: &Tuples) -> Result<Vec<Value>, Error> {
+    if row.len() != relation.columns.len() {
+        return Err(Error::Decode(
+            format!(
+                "row has {} values, but relation {:?} has {} columns",
+                row.len(),
+                relation.name,
+                relation.columns.len()
+            )
+            .into(),
+        ));
+    }
+
+    (relation.columns.iter().zip(row))
+        .map(|(column, data)| {
+            decode_value(data, column.type_id).map_err(|error| match error {
+                Error::Decode(source) => Error::ColumnDecode {
+                    index: format!("{:?}", column.name),
+                    source,
+                },
+                error => error,
+            })
+        })
+        .collect()
+}
+
+fn decode_value(data: &TupleData, type_id: Oid) -> Result<Value, Error> {
+    if matches!(data, TupleData::Null | TupleData::UnchangedToast) {
+        return Ok(Value::Null);
+    }
+
+    let ty = PgType::try_from_oid(type_id);
+
+    Ok(match ty {
+        Some(PgType::Bool) => Value::Bool(data.try_decode(type_id)?),
+        Some(PgType::Int2) => Value::Int16(data.try_decode(type_id)?),
+        Some(PgType::Int4) => Value::Int32(data.try_decode(type_id)?),
+        Some(PgType::Int8) => Value::Int64(data.try_decode(type_id)?),
+        Some(PgType::Float4) => Value::Float32(data.try_decode(type_id)?),
+        Some(PgType::Float8) => Value::Float64(data.try_decode(type_id)?),
+        Some(PgType::Bytea) => Value::Binary(data.try_decode(type_id)?),
+        Some(PgType::Text | PgType::Varchar | PgType::Bpchar | PgType::Name) => {
+            Value::Utf8(data.try_decode(type_id)?)
+        }
+
+        _ => match data {
+            TupleData::Text(_) => Value::Utf8(
+                data.as_str()
+                    .ok_or_else(|| Error::Decode("invalid UTF-8 in text value".into()))?
+                    .to_owned(),
+            ),
+            _ => {
+                return Err(Error::Decode(
+                    format!(
+                        "cannot map binary value of type {} to Arrow",
+                        ty.map_or_else(|| type_id.0.to_string(), |ty| ty.display_name().to_owned())
+                    )
+                    .into(),
+                ))
+            }
+        },
+    })
+}
+
+/// The builder of the values of one column, matching [`data_type()`].
+#[derive(Debug)]
+enum ColumnBuilder {
+    Bool(BooleanBuilder),
+    Int16(Int16Builder),
+    Int32(Int32Builder),
+    Int64(Int64Builder),
+    Float32(Float32Builder),
+    Float64(Float64Builder),
+    Binary(BinaryBuilder),
+    Utf8(StringBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(type_id: Oid) -> Self {
+        match data_type(type_id) {
+            DataType::Boolean => ColumnBuilder::Bool(BooleanBuilder::new()),
+            DataType::Int16 => ColumnBuilder::Int16(Int16Builder::new()),
+            DataType::Int32 => ColumnBuilder::Int32(Int32Builder::new()),
+            DataType::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+            DataType::Float32 => ColumnBuilder::Float32(Float32Builder::new()),
+            DataType::Float64 => ColumnBuilder::Float64(Float64Builder::new()),
+            DataType::Binary => ColumnBuilder::Binary(BinaryBuilder::new()),
+            _ => ColumnBuilder::Utf8(StringBuilder::new()),
+        }
+    }
+
+    /// Append `value`, which was decoded for the type of the column.
+    fn append(&mut self, value: Value) {
+        match (self, value) {
+            (ColumnBuilder::Bool(builder), Value::Bool(value)) => builder.append_value(value),
+            (ColumnBuilder::Int16(builder), Value::Int16(value)) => builder.append_value(value),
+            (ColumnBuilder::Int32(builder), Value::Int32(value)) => builder.append_value(value),
+            (ColumnBuilder::Int64(builder), Value::Int64(value)) => builder.append_value(value),
+            (ColumnBuilder::Float32(builder), Value::Float32(value)) => builder.append_value(value),
+            (ColumnBuilder::Float64(builder), Value::Float64(value)) => builder.append_value(value),
+            (ColumnBuilder::Binary(builder), Value::Binary(value)) => builder.append_value(value),
+            (ColumnBuilder::Utf8(builder), Value::Utf8(value)) => builder.append_value(value),
+            (builder, _) => builder.append_null(),
+        }
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            ColumnBuilder::Bool(builder) => builder.append_null(),
+            ColumnBuilder::Int16(builder) => builder.append_null(),
+            ColumnBuilder::Int32(builder) => builder.append_null(),
+            ColumnBuilder::Int64(builder) => builder.append_null(),
+            ColumnBuilder::Float32(builder) => builder.append_null(),
+            ColumnBuilder::Float64(builder) => builder.append_null(),
+            ColumnBuilder::Binary(builder) => builder.append_null(),
+            ColumnBuilder::Utf8(builder) => builder.append_null(),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Bool(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Int16(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Int32(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Int64(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Float32(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Float64(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Binary(builder) => ArrayBuilder::finish(builder),
+            ColumnBuilder::Utf8(builder) => ArrayBuilder::finish(builder),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int32Type;
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::replication::{Delete, Insert, ReplicaIdentity, Update};
+
+    fn relation(columns: &[(&str, u32)]) -> Relation {
+        Relation {
+            xid: None,
+            relation_id: Oid(16384),
+            namespace: "public".to_owned(),
+            name: "users".to_owned(),
+            replica_identity: ReplicaIdentity::Default,
+            columns: columns
+                .iter()
+                .map(|&(name, type_id)| Column {
+                    flags: u8::from(name == "id"),
+                    name: name.to_owned(),
+                    type_id: Oid(type_id),
+                    type_modifier: -1,
+                })
+                .collect(),
+        }
+    }
+
+    fn insert(values: Vec<TupleData>) -> LogicalReplication {
+        LogicalReplication::Insert(Insert {
+            xid: None,
+            relation_id: Oid(16384),
+            new_data: Tuples(values),
+        })
+    }
+
+    fn text(value: &'static str) -> TupleData {
+        TupleData::Text(Bytes::from_static(value.as_bytes()))
+    }
+
+    #[test]
+    fn it_maps_relations_to_schemas() {
+        let schema = arrow_schema(&relation(&[("id", 23), ("name", 25), ("created", 1184)]));
+
+        let fields: Vec<_> = (schema.fields().iter())
+            .map(|field| (field.name().as_str(), field.data_type().clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                (OP_COLUMN, DataType::Utf8),
+                ("id", DataType::Int32),
+                ("name", DataType::Utf8),
+                ("created", DataType::Utf8),
+            ]
+        );
+        assert!(schema.fields()[1].is_nullable());
+        assert_eq!(schema.metadata()["name"], "users");
+    }
+
+    #[test]
+    fn it_batches_changes() {
+        let relation = relation(&[("id", 23), ("name", 25)]);
+        let mut batcher = ArrowBatcher::new(3);
+
+        let batches = batcher
+            .push(&relation, &insert(vec![text("1"), text("foo")]))
+            .unwrap();
+        assert!(batches.is_empty());
+
+        let update = LogicalReplication::Update(Update {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data: None,
+            old_data: None,
+            new_data: Tuples(vec![text("1"), TupleData::UnchangedToast]),
+        });
+        assert!(batcher.push(&relation, &update).unwrap().is_empty());
+
+        // ignored
+        assert!(batcher
+            .push(&relation, &LogicalReplication::StreamStop)
+            .unwrap()
+            .is_empty());
+        assert_eq!(batcher.pending_rows(), 2);
+
+        let delete = LogicalReplication::Delete(Delete {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data: Some(Tuples(vec![text("2"), TupleData::Null])),
+            old_data: None,
+        });
+        let batches = batcher.push(&relation, &delete).unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batcher.pending_rows(), 0);
+
+        let batch = &batches[0].batch;
+        assert_eq!(batch.num_rows(), 3);
+
+        let ops: Vec<_> = batch.column(0).as_string::<i32>().iter().collect();
+        assert_eq!(ops, [Some("I"), Some("U"), Some("D")]);
+
+        let ids: Vec<_> = (batch.column(1).as_primitive::<Int32Type>().iter()).collect();
+        assert_eq!(ids, [Some(1), Some(1), Some(2)]);
+
+        // the unchanged TOAST value and the column that is not part of the key are null
+        let names: Vec<_> = batch.column(2).as_string::<i32>().iter().collect();
+        assert_eq!(names, [Some("foo"), None, None]);
+    }
+
+    #[test]
+    fn it_flushes_batches_on_schema_changes_and_age() {
+        let mut batcher = ArrowBatcher::new(100).max_age(Duration::from_secs(3600));
+
+        let old = relation(&[("id", 23)]);
+        assert!(batcher
+            .push(&old, &insert(vec![text("1")]))
+            .unwrap()
+            .is_empty());
+        assert!(batcher.flush_expired().unwrap().is_empty());
+
+        let new = relation(&[("id", 23), ("name", 25)]);
+        let batches = batcher
+            .push(&new, &insert(vec![text("2"), text("foo")]))
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].batch.num_columns(), 2);
+
+        let mut batcher = ArrowBatcher::new(100).max_age(Duration::ZERO);
+        assert_eq!(
+            batcher
+                .push(&new, &insert(vec![text("3"), text("bar")]))
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let batches = ArrowBatcher::new(100).flush_all().unwrap();
+        assert!(batches.is_empty());
+    }
+
+    #[test]
+    fn it_rejects_rows_that_cant_be_mapped() {
+        let relation = relation(&[("id", 23), ("created", 1184)]);
+        let mut batcher = ArrowBatcher::new(100);
+
+        let row = vec![text("1"), TupleData::Binary(Bytes::from_static(&[0; 8]))];
+        assert!(matches!(
+            batcher.push(&relation, &insert(row)),
+            Err(Error::ColumnDecode { index, .. }) if index == "\"created\""
+        ));
+
+        // nothing was added
+        assert_eq!(batcher.pending_rows(), 0);
+        assert!(batcher
+            .push(&relation, &insert(vec![text("x"), text("")]))
+            .is_err());
+    }
+}
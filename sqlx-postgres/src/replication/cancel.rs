@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+
+use futures_util::future;
+use futures_util::task::AtomicWaker;
+
+/// A handle to interrupt a replication stream from another task, e.g. from a signal handler,
+/// created with [`LogicalReplicationStream::cancel_handle()`][super::LogicalReplicationStream::cancel_handle].
+///
+/// Once [cancelled][Self::cancel], a pending or later call to `recv()` returns `Ok(None)`
+/// instead of waiting for the next message. The stream can then still be
+/// [finished][super::LogicalReplicationStream::finish], which sends a final status update with
+/// the confirmed position.
+///
+/// The handle is cheap to clone; all clones cancel the same stream.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl CancelHandle {
+    /// Cancel the stream.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+        self.0.waker.wake();
+    }
+
+    /// Returns `true` if the stream was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Wait until the stream is cancelled.
+    pub(crate) async fn cancelled(&self) {
+        future::poll_fn(|cx| {
+            if self.is_cancelled() {
+                return Poll::Ready(());
+            }
+
+            self.0.waker.register(cx.waker());
+
+            // cancelled before the waker was registered
+            if self.is_cancelled() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
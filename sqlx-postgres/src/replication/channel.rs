@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::pin::pin;
+
+use futures_channel::mpsc;
+use futures_util::future::{self, Either};
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use sqlx_core::rt::JoinHandle;
+
+use crate::types::PgLsn;
+
+use super::{
+    CancelHandle, LogicalReplication, LogicalReplicationStream, PgReplicationConnection,
+    ReplicationError,
+};
+
+/// A handle to a [`LogicalReplicationStream`] that was moved into a background task by
+/// [`LogicalReplicationStream::into_channel()`], to confirm the changes received from the
+/// channel and to stop the task.
+///
+/// The task answers keepalives and sends status updates on its own, also while the channel is
+/// full: it then [pauses][LogicalReplicationStream::pause] the stream until there is room
+/// again, so a slow consumer holds back the server instead of buffering without bound.
+///
+/// The channel ends once the server ended the stream, or the stream was cancelled, e.g. by
+/// [`finish()`][Self::finish]; a message that didn't fit into the channel when it was
+/// cancelled is dropped, and, as it wasn't confirmed, streamed again after a restart. The task
+/// also ends if the receiver was dropped, once the next message cannot be delivered.
+pub struct ReplicationChannel {
+    confirm: mpsc::UnboundedSender<PgLsn>,
+    cancel: CancelHandle,
+    task: JoinHandle<Result<PgReplicationConnection, ReplicationError>>,
+}
+
+impl ReplicationChannel {
+    pub(crate) fn spawn(
+        stream: LogicalReplicationStream,
+        capacity: usize,
+    ) -> (mpsc::Receiver<LogicalReplication>, Self) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let (confirm, confirmed) = mpsc::unbounded();
+        let cancel = stream.cancel_handle();
+        let task = crate::rt::spawn(run(stream, sender, confirmed));
+
+        (
+            receiver,
+            Self {
+                confirm,
+                cancel,
+                task,
+            },
+        )
+    }
+
+    /// Confirm the changes up to `lsn`, like
+    /// [`LogicalReplicationStream::set_confirmed_lsn()`]; the position is reported with the
+    /// next status update of the task.
+    pub fn confirm(&self, lsn: PgLsn) {
+        // the task only ends with the handle, or once the stream ended
+        let _ = self.confirm.unbounded_send(lsn);
+    }
+
+    /// A handle to cancel the stream, which ends the channel; see [`CancelHandle`].
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Stop streaming and return the replication connection, once the positions confirmed
+    /// before were reported to the server with a final status update.
+    ///
+    /// Returns the error that ended the stream instead, if any. The messages still in the
+    /// channel can be received after the task ended.
+    pub async fn finish(self) -> Result<PgReplicationConnection, ReplicationError> {
+        self.cancel.cancel();
+
+        self.task.await
+    }
+
+    /// Wait until the stream ended, e.g. because the server ended it or the receiver was
+    /// dropped, without cancelling it; see [`finish()`][Self::finish].
+    pub async fn join(self) -> Result<PgReplicationConnection, ReplicationError> {
+        self.task.await
+    }
+}
+
+impl Debug for ReplicationChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicationChannel")
+            .field("cancelled", &self.cancel.is_cancelled())
+            .finish_non_exhaustive()
+    }
+}
+
+/// What the task waited for.
+enum Event {
+    Received(Result<Option<LogicalReplication>, ReplicationError>),
+    Confirmed(Option<PgLsn>),
+    /// The channel has room for a message, or the receiver was dropped.
+    Ready,
+}
+
+async fn run(
+    mut stream: LogicalReplicationStream,
+    mut sender: mpsc::Sender<LogicalReplication>,
+    mut confirmed: mpsc::UnboundedReceiver<PgLsn>,
+) -> Result<PgReplicationConnection, ReplicationError> {
+    // the messages received while the channel is full
+    let mut outgoing = VecDeque::new();
+    // set once all handles were dropped, after which nothing is confirmed anymore
+    let mut handle_dropped = false;
+
+    'run: loop {
+        while !outgoing.is_empty() {
+            match future::poll_fn(|cx| sender.poll_ready(cx)).now_or_never() {
+                Some(Ok(())) => {
+                    let message = outgoing.pop_front().expect("no outgoing message");
+
+                    if sender.start_send(message).is_err() {
+                        break 'run;
+                    }
+                }
+                // the receiver was dropped
+                Some(Err(_)) => break 'run,
+                None => break,
+            }
+        }
+
+        // keep the connection alive while the channel is full; the paused stream only sends
+        // status updates until it is resumed
+        if outgoing.is_empty() {
+            stream.resume();
+        } else {
+            stream.pause();
+        }
+
+        let event = {
+            // `recv()` is cancel-safe, so nothing is lost if another event comes first
+            let recv = pin!(stream.recv());
+            let confirm = pin!(confirm_next(&mut confirmed, handle_dropped));
+            let ready = pin!(async {
+                if outgoing.is_empty() {
+                    future::pending::<()>().await;
+                }
+
+                let _ = future::poll_fn(|cx| sender.poll_ready(cx)).await;
+            });
+
+            match future::select(future::select(recv, confirm), ready).await {
+                Either::Left((Either::Left((received, _)), _)) => Event::Received(received),
+                Either::Left((Either::Right((lsn, _)), _)) => Event::Confirmed(lsn),
+                Either::Right(((), _)) => Event::Ready,
+            }
+        };
+
+        match event {
+            Event::Received(Ok(Some(message))) => outgoing.push_back(message),
+            // the server ended the stream, or it was cancelled
+            Event::Received(Ok(None)) => {
+                if !stream.cancel_handle().is_cancelled() {
+                    // there's no connection to keep alive anymore
+                    for message in outgoing.drain(..) {
+                        if sender.send(message).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+
+                break;
+            }
+            Event::Received(Err(error)) => return Err(error),
+            Event::Confirmed(Some(lsn)) => stream.set_confirmed_lsn(lsn),
+            Event::Confirmed(None) => handle_dropped = true,
+            Event::Ready => {}
+        }
+    }
+
+    while let Ok(Some(lsn)) = confirmed.try_next() {
+        stream.set_confirmed_lsn(lsn);
+    }
+
+    stream.finish().await
+}
+
+/// The next confirmed position, or a future that never completes once all handles were
+/// dropped.
+async fn confirm_next(
+    confirmed: &mut mpsc::UnboundedReceiver<PgLsn>,
+    handle_dropped: bool,
+) -> Option<PgLsn> {
+    if handle_dropped {
+        return future::pending().await;
+    }
+
+    confirmed.next().await
+}
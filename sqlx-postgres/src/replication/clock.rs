@@ -0,0 +1,67 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A source of the current time for the timestamps of standby status updates and for lag
+/// calculations, set with [`LogicalReplicationStream::set_clock()`][super::LogicalReplicationStream::set_clock].
+///
+/// The default is [`SystemClock`]. Tests can use a fixed or manually advanced clock instead,
+/// which is also implemented for closures:
+///
+/// ```rust
+/// # use std::time::{Duration, SystemTime};
+/// # use sqlx::postgres::replication::Clock;
+/// let fixed = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+/// let clock = move || fixed;
+///
+/// assert_eq!(clock.now(), fixed);
+/// ```
+pub trait Clock: Send + Sync + 'static {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock, i.e. [`SystemTime::now()`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> SystemTime + Send + Sync + 'static,
+{
+    fn now(&self) -> SystemTime {
+        self()
+    }
+}
+
+/// A shared [`Clock`].
+#[derive(Clone)]
+pub(crate) struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub(crate) fn new(clock: impl Clock) -> Self {
+        Self(Arc::new(clock))
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        self.0.now()
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl Debug for SharedClock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedClock")
+    }
+}
@@ -0,0 +1,886 @@
+use std::cmp;
+use std::fmt::{self, Debug, Formatter};
+use std::str::FromStr;
+
+use sqlx_core::connection::{ConnectOptions, Connection};
+use sqlx_core::executor::Executor;
+use sqlx_core::row::Row;
+
+use crate::error::Error;
+use crate::message::{CopyBothResponse, CopyData, Query, ReadyForQuery};
+use crate::types::PgLsn;
+use crate::{PgConnectOptions, PgConnection, PgRow};
+
+use super::copy_both::CopyBothReader;
+use super::slot::{CreateReplicationSlot, IdentifySystem, PgReplicationSlot, StartPosition};
+use super::{
+    quote_ident, quote_literal, LogicalReplicationStream, OffsetStore, PgOutputOptions,
+    PhysicalReplicationStream, ReplicationError, LIFECYCLE_TARGET,
+};
+
+/// The output plugin that [`PgOutputOptions`] and the decoders in this module are written for.
+const PGOUTPUT: &str = "pgoutput";
+
+/// The `application_name` of replication connections if none is configured.
+const DEFAULT_APPLICATION_NAME: &str =
+    concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+/// The output plugins whose messages can be decoded.
+const KNOWN_PLUGINS: &[&str] = &[
+    PGOUTPUT,
+    #[cfg(feature = "decoderbufs")]
+    "decoderbufs",
+];
+
+/// A connection in replication mode (`replication=database`).
+///
+/// In addition to simple SQL queries, a replication connection accepts the commands of the
+/// [streaming replication protocol], like `CREATE_REPLICATION_SLOT` and `START_REPLICATION`.
+///
+/// ```rust,no_run
+/// # async fn example() -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use sqlx::postgres::replication::{CreateReplicationSlot, PgOutputOptions, PgReplicationConnection};
+/// use sqlx::postgres::types::PgLsn;
+///
+/// let mut conn = PgReplicationConnection::connect("postgres://localhost/mydb").await?;
+///
+/// conn.create_replication_slot(&CreateReplicationSlot::logical("my_slot", "pgoutput"))
+///     .await?;
+///
+/// let mut stream = conn
+///     .start_logical_replication("my_slot", PgLsn::INVALID, PgOutputOptions::new(["my_pub"]))
+///     .await?;
+///
+/// while let Some(message) = stream.recv().await? {
+///     println!("{message:?}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [streaming replication protocol]: https://www.postgresql.org/docs/current/protocol-replication.html
+pub struct PgReplicationConnection {
+    pub(crate) conn: PgConnection,
+    expected_role: Option<ServerRole>,
+}
+
+/// The outcome of [`PgReplicationConnection::validate_start()`].
+#[derive(Debug, Clone)]
+pub struct StartValidation {
+    /// The slot that would be streamed from.
+    pub slot: PgReplicationSlot,
+    /// The position that streaming would start at, i.e. the later of the requested position
+    /// and the position confirmed for the slot.
+    pub start_lsn: PgLsn,
+    /// The position up to which the server wrote WAL (on a standby, replayed it).
+    pub server_lsn: PgLsn,
+}
+
+/// The role of a server, told apart by whether it is in recovery (`pg_is_in_recovery()`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerRole {
+    /// The server accepts writes.
+    Primary,
+    /// The server is a standby that replays the WAL of a primary.
+    Standby,
+}
+
+impl fmt::Display for ServerRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ServerRole::Primary => "primary",
+            ServerRole::Standby => "standby",
+        })
+    }
+}
+
+impl PgReplicationConnection {
+    /// Open a new replication connection to the database at `url`.
+    pub async fn connect(url: &str) -> Result<Self, ReplicationError> {
+        Self::connect_with(&PgConnectOptions::from_str(url)?).await
+    }
+
+    /// Open a new replication connection with the given options.
+    ///
+    /// The connection shows up with the
+    /// [`application_name`][PgConnectOptions::application_name] of `options` in
+    /// `pg_stat_replication`, which allows telling the consumers of different slots apart.
+    /// If none is set, neither in `options` nor with `PGAPPNAME`, the name and version of this
+    /// crate are used, e.g. `sqlx-postgres 0.8.2`.
+    ///
+    /// A connection that streams for a long time should detect a server that went away without
+    /// closing it, e.g. behind a NAT or firewall that drops idle connections, before the
+    /// stream's own timeouts do; enable TCP keepalives for it with
+    /// [`PgConnectOptions::keepalives_idle()`] and related options, and limit the time to
+    /// connect with [`PgConnectOptions::connect_timeout()`]. A connection over a Unix-domain
+    /// socket ([`PgConnectOptions::socket()`], or a `host` that is a directory) works like one
+    /// over TCP; keepalives are not used for it, as there is no network to drop it.
+    ///
+    /// The connection is established and authenticated like a [`PgConnection`] with the same
+    /// options, so every authentication method works, including `scram-sha-256` and client
+    /// certificates ([`PgConnectOptions::ssl_client_cert()`]). Logical replication connections
+    /// are matched by their database in `pg_hba.conf`, like other connections, not by the
+    /// `replication` keyword.
+    ///
+    /// Returns [`ReplicationError::UnsupportedServerParameter`] if the server reports
+    /// `integer_datetimes = off`, as the timestamps of the replication protocol could not be
+    /// interpreted.
+    pub async fn connect_with(options: &PgConnectOptions) -> Result<Self, ReplicationError> {
+        let mut options = options.clone();
+        options.replication = Some("database");
+        options
+            .application_name
+            .get_or_insert_with(|| DEFAULT_APPLICATION_NAME.to_owned());
+
+        let this = Self {
+            conn: options.connect().await?,
+            expected_role: None,
+        };
+
+        check_integer_datetimes(this.parameter_status("integer_datetimes"))?;
+
+        Ok(this)
+    }
+
+    /// The version number of the server in `libpq` format.
+    pub fn server_version_num(&self) -> Option<u32> {
+        self.conn.server_version_num()
+    }
+
+    /// The value of a parameter the server reported with `ParameterStatus`, like
+    /// `server_version`, `server_encoding` or `integer_datetimes`.
+    pub fn parameter_status(&self, name: &str) -> Option<&str> {
+        self.conn
+            .inner
+            .stream
+            .parameter_statuses
+            .get(name)
+            .map(String::as_str)
+    }
+
+    /// The character set encoding of the database, in which the text values of the
+    /// replication stream are encoded.
+    pub fn server_encoding(&self) -> Option<&str> {
+        self.parameter_status("server_encoding")
+    }
+
+    /// The process ID of the backend serving this connection, as sent by the server in
+    /// `BackendKeyData` when the connection was opened; the `pg_backend_pid()` of the
+    /// connection.
+    ///
+    /// Compared to [`slot_active_pid()`][Self::slot_active_pid], this tells whether a slot
+    /// reported [in use][ReplicationError::SlotInUse] is held by this connection or another one,
+    /// e.g. a leftover consumer.
+    pub fn backend_pid(&self) -> u32 {
+        self.conn.inner.process_id
+    }
+
+    /// The process ID of the backend streaming from the replication slot `slot`
+    /// (`pg_replication_slots.active_pid`), or `None` if no connection is streaming from it.
+    ///
+    /// Returns [`ReplicationError::SlotNotFound`] if the slot doesn't exist.
+    pub async fn slot_active_pid(&mut self, slot: &str) -> Result<Option<u32>, ReplicationError> {
+        let query = format!(
+            "SELECT active_pid FROM pg_catalog.pg_replication_slots WHERE slot_name = {}",
+            quote_literal(slot)
+        );
+
+        let Some(row) = self.conn.fetch_optional(&*query).await? else {
+            return Err(ReplicationError::SlotNotFound {
+                slot: slot.to_owned(),
+            });
+        };
+
+        let active_pid: Option<i32> = row.try_get(0)?;
+
+        active_pid
+            .map(u32::try_from)
+            .transpose()
+            .map_err(|error| Error::Decode(error.into()).into())
+    }
+
+    /// The server's `wal_level`; logical replication requires `logical`.
+    ///
+    /// This is the replication connection's counterpart of
+    /// [`ReplicationSettings::wal_level`][super::ReplicationSettings::wal_level], which
+    /// [`replication_settings()`][super::replication_settings] reads on a normal connection.
+    pub async fn wal_level(&mut self) -> Result<String, Error> {
+        self.fetch_one("SHOW wal_level").await?.try_get(0)
+    }
+
+    /// Returns `true` if the server is a standby, i.e. still in recovery.
+    pub async fn is_in_recovery(&mut self) -> Result<bool, Error> {
+        let row = self
+            .fetch_one("SELECT pg_catalog.pg_is_in_recovery()")
+            .await?;
+
+        row.try_get(0)
+    }
+
+    /// The role of the server: a standby if it is in recovery, a primary otherwise.
+    pub async fn server_role(&mut self) -> Result<ServerRole, Error> {
+        Ok(match self.is_in_recovery().await? {
+            true => ServerRole::Standby,
+            false => ServerRole::Primary,
+        })
+    }
+
+    /// Check that the server has the role `expected`, and return
+    /// [`ReplicationError::UnexpectedRole`] otherwise, e.g. if the host name of the primary
+    /// resolves to a standby after a misconfiguration.
+    pub async fn check_role(&mut self, expected: ServerRole) -> Result<(), ReplicationError> {
+        let actual = self.server_role().await?;
+
+        if actual != expected {
+            return Err(ReplicationError::UnexpectedRole { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    /// Check that the server has the role `role` before replication is started, with
+    /// [`check_role()`][Self::check_role]; `None`, the default, doesn't check it.
+    ///
+    /// A physical stream from a standby streams the WAL the standby replayed, and some
+    /// commands are not available on a standby, so an archiver that must stream from the
+    /// primary should require it instead of failing later with a less obvious error.
+    pub fn set_expected_role(&mut self, role: Option<ServerRole>) {
+        self.expected_role = role;
+    }
+
+    /// Run `IDENTIFY_SYSTEM`.
+    pub async fn identify_system(&mut self) -> Result<IdentifySystem, Error> {
+        let row = self.fetch_one("IDENTIFY_SYSTEM").await?;
+
+        Ok(IdentifySystem {
+            systemid: row.try_get(0)?,
+            timeline: row.try_get(1)?,
+            xlogpos: parse_lsn(row.try_get(2)?)?,
+            dbname: row.try_get(3)?,
+        })
+    }
+
+    /// Create a replication slot.
+    ///
+    /// Logical slots can also be created on a standby running Postgres 16 or later; otherwise
+    /// [`ReplicationError::StandbyNotSupported`] is returned. See the
+    /// [module documentation][super#replication-from-a-standby] for the prerequisites.
+    ///
+    /// Before creating a logical slot, the server's `wal_level` is checked, and
+    /// [`ReplicationError::WalLevelInsufficient`] returned if it isn't `logical`, unless the
+    /// check is turned off with [`CreateReplicationSlot::check_wal_level()`].
+    pub async fn create_replication_slot(
+        &mut self,
+        slot: &CreateReplicationSlot,
+    ) -> Result<PgReplicationSlot, ReplicationError> {
+        if slot.plugin.is_some() && slot.check_wal_level {
+            let found = self.wal_level().await?;
+
+            if found != "logical" {
+                return Err(ReplicationError::WalLevelInsufficient { found });
+            }
+        }
+
+        let row = self
+            .fetch_one(&slot.to_command(self.server_version_num())?)
+            .await
+            .map_err(|error| ReplicationError::from_server(error, &slot.name))?;
+
+        let created = PgReplicationSlot {
+            slot_name: row.try_get(0)?,
+            consistent_point: parse_lsn(row.try_get(1)?)?,
+            snapshot_name: row.try_get(2)?,
+            output_plugin: row.try_get(3)?,
+            temporary: slot.temporary,
+            two_phase: slot.two_phase,
+        };
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = created.slot_name,
+            lsn = %created.consistent_point,
+            plugin = created.output_plugin,
+            temporary = created.temporary,
+            "replication slot created"
+        );
+
+        Ok(created)
+    }
+
+    /// Look up an existing replication slot, e.g. one created by another tool, returning
+    /// `None` if it doesn't exist.
+    ///
+    /// The returned slot can be streamed from like a newly created one. Its
+    /// [`consistent_point`][PgReplicationSlot::consistent_point] is the position confirmed by
+    /// the last consumer (for physical slots, the restart position), or
+    /// [`PgLsn::INVALID`] if the slot never reserved WAL.
+    pub async fn replication_slot(
+        &mut self,
+        slot: &str,
+    ) -> Result<Option<PgReplicationSlot>, Error> {
+        let query = format!(
+            "SELECT slot_name::text, plugin::text, temporary, \
+             coalesce(confirmed_flush_lsn, restart_lsn)::text, {} \
+             FROM pg_catalog.pg_replication_slots WHERE slot_name = {}",
+            self.two_phase_column(),
+            quote_literal(slot)
+        );
+
+        let Some(row) = self.conn.fetch_optional(&*query).await? else {
+            return Ok(None);
+        };
+
+        let consistent_point: Option<String> = row.try_get(3)?;
+
+        Ok(Some(PgReplicationSlot {
+            slot_name: row.try_get(0)?,
+            consistent_point: consistent_point.map_or(Ok(PgLsn::INVALID), parse_lsn)?,
+            snapshot_name: None,
+            output_plugin: row.try_get(1)?,
+            temporary: row.try_get(2)?,
+            two_phase: row.try_get(4)?,
+        }))
+    }
+
+    /// Drop a replication slot.
+    ///
+    /// If `wait` is `true` and the slot is active, wait until it becomes inactive instead of
+    /// returning an error.
+    pub async fn drop_replication_slot(&mut self, slot: &str, wait: bool) -> Result<(), Error> {
+        self.drop_slot(slot, wait, "dropped").await
+    }
+
+    /// Drop the replication slot named like `slot` if it exists, and create it again with the
+    /// options of `slot`, e.g. to make a consumer bootstrap again from a fresh snapshot.
+    ///
+    /// The options are validated before the old slot is dropped, so that invalid options don't
+    /// leave the consumer without a slot. The old slot is dropped with
+    /// `WAIT`, i.e. after the connection streaming from it (if any) stopped; if
+    /// `terminate_active` is `true`, that connection is terminated with
+    /// `pg_terminate_backend()` first, which requires the privileges to signal it. This is
+    /// opt-in, as the consumer is disconnected without warning.
+    ///
+    /// Replication commands cannot run in a transaction, so the slot is dropped and created by
+    /// two commands sent back to back. If another connection creates a slot with the same name
+    /// in between, the server's error is returned and the slot is not recreated.
+    pub async fn reset_replication_slot(
+        &mut self,
+        slot: &CreateReplicationSlot,
+        terminate_active: bool,
+    ) -> Result<PgReplicationSlot, ReplicationError> {
+        // fail on invalid options before the old slot is gone
+        slot.to_command(self.server_version_num())?;
+
+        let query = format!(
+            "SELECT active_pid FROM pg_catalog.pg_replication_slots WHERE slot_name = {}",
+            quote_literal(&slot.name)
+        );
+
+        if let Some(row) = self.conn.fetch_optional(&*query).await? {
+            let active_pid: Option<i32> = row.try_get(0)?;
+
+            if let (Some(pid), true) = (active_pid, terminate_active) {
+                self.conn
+                    .execute(&*format!(
+                        "SELECT pg_catalog.pg_terminate_backend({pid}) \
+                         WHERE {pid} <> pg_catalog.pg_backend_pid()"
+                    ))
+                    .await?;
+            }
+
+            self.drop_slot(&slot.name, true, "reset")
+                .await
+                .map_err(|error| ReplicationError::from_server(error, &slot.name))?;
+        }
+
+        self.create_replication_slot(slot).await
+    }
+
+    /// Check that logical replication could be started from `slot` at `start` with `options`,
+    /// without starting it, so that the connection stays usable whatever the outcome.
+    ///
+    /// [`start_logical_replication()`][Self::start_logical_replication] takes the connection
+    /// over, and some problems are only reported by the server after it entered the `CopyBoth`
+    /// sub-protocol, or once the first change is decoded. This runs the checks of
+    /// `start_logical_replication()` and the ones the server would run itself up front:
+    ///
+    /// * the options are valid, and the server supports the protocol version and the options
+    ///   ([`ReplicationError::InvalidOptions`]);
+    /// * all publications exist ([`ReplicationError::InvalidOptions`]);
+    /// * the slot exists, is a logical slot using `pgoutput`, was not invalidated, and agrees
+    ///   with the options on two-phase decoding (see
+    ///   [`start_logical_replication()`][Self::start_logical_replication]);
+    /// * the server has the [expected role][Self::set_expected_role], if any
+    ///   ([`ReplicationError::UnexpectedRole`]);
+    /// * a start position is not past the WAL the server wrote, which would mean that it was
+    ///   taken from another server ([`ReplicationError::InvalidOptions`]).
+    ///
+    /// The returned report has the position that streaming would start at. The slot can still
+    /// change before replication is started, e.g. be dropped or become active on another
+    /// connection.
+    pub async fn validate_start(
+        &mut self,
+        slot: &str,
+        options: &PgOutputOptions,
+        start: impl Into<StartPosition>,
+    ) -> Result<StartValidation, ReplicationError> {
+        options.to_option_list()?;
+
+        if let Some(version) = self.server_version_num() {
+            options.check_server_version(version)?;
+        }
+
+        let names = (options.publication_names.iter())
+            .map(|name| quote_literal(name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!(
+            "SELECT name FROM unnest(ARRAY[{names}]::text[]) AS name \
+             WHERE name NOT IN (SELECT pubname FROM pg_catalog.pg_publication)"
+        );
+
+        if let Some(row) = self.conn.fetch_optional(&*query).await? {
+            let name: String = row.try_get(0)?;
+
+            return Err(ReplicationError::InvalidOptions {
+                reason: format!("publication {name:?} does not exist"),
+            });
+        }
+
+        self.check_slot(slot, PGOUTPUT, options.two_phase).await?;
+
+        if let Some(role) = self.expected_role {
+            self.check_role(role).await?;
+        }
+
+        let slot =
+            self.replication_slot(slot)
+                .await?
+                .ok_or_else(|| ReplicationError::SlotNotFound {
+                    slot: slot.to_owned(),
+                })?;
+
+        // the position up to which WAL can be sent, on a primary or a standby
+        let server_lsn = parse_lsn(
+            self.fetch_one(
+                "SELECT (CASE WHEN pg_catalog.pg_is_in_recovery() \
+                 THEN pg_catalog.pg_last_wal_replay_lsn() \
+                 ELSE pg_catalog.pg_current_wal_flush_lsn() END)::text",
+            )
+            .await?
+            .try_get(0)?,
+        )?;
+
+        let start_lsn = match start.into() {
+            StartPosition::Slot => slot.consistent_point,
+            StartPosition::Latest => server_lsn,
+            StartPosition::Lsn(lsn) if lsn > server_lsn => {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: format!(
+                        "start position {lsn} is past the WAL written by the server, \
+                         which ends at {server_lsn}"
+                    ),
+                })
+            }
+            // earlier positions than the confirmed one are skipped by the server
+            StartPosition::Lsn(lsn) => cmp::max(lsn, slot.consistent_point),
+        };
+
+        Ok(StartValidation {
+            slot,
+            start_lsn,
+            server_lsn,
+        })
+    }
+
+    /// Start streaming changes from a logical replication slot using the `pgoutput` plugin.
+    ///
+    /// Streaming starts at `start`, or at the slot's confirmed position if that is later;
+    /// pass [`PgLsn::INVALID`] or [`StartPosition::Slot`] to always start at the slot's
+    /// confirmed position, or [`StartPosition::Latest`] to only receive changes committed from
+    /// now on.
+    ///
+    /// The slot doesn't need to be created on this connection; it can also be a slot that was
+    /// created in advance, e.g. by another tool. It is checked before streaming is started, so
+    /// that a slot that does not exist, that uses a different output plugin or that was
+    /// invalidated is reported with [`ReplicationError::SlotNotFound`],
+    /// [`ReplicationError::PluginMismatch`] (or [`ReplicationError::UnsupportedPlugin`] for a
+    /// plugin that cannot be decoded at all) or [`ReplicationError::SlotInvalidated`].
+    ///
+    /// Prepared transactions are only decoded as such if both the slot was created with
+    /// [`two_phase`][CreateReplicationSlot::two_phase] and the options enable
+    /// [`two_phase`][PgOutputOptions::two_phase]; otherwise the server either decodes them as
+    /// regular transactions once they are committed, or sends `Prepare` messages the consumer
+    /// doesn't expect. [`ReplicationError::TwoPhaseMismatch`] is returned if the two don't
+    /// agree.
+    pub async fn start_logical_replication(
+        mut self,
+        slot: &str,
+        start: impl Into<StartPosition>,
+        options: PgOutputOptions,
+    ) -> Result<LogicalReplicationStream, ReplicationError> {
+        let option_list = options.to_option_list()?;
+
+        self.check_slot(slot, PGOUTPUT, options.two_phase).await?;
+
+        let start_lsn = match start.into() {
+            StartPosition::Slot => PgLsn::INVALID,
+            StartPosition::Lsn(lsn) => lsn,
+            StartPosition::Latest => self.identify_system().await?.xlogpos,
+        };
+
+        let command = format!(
+            "START_REPLICATION SLOT {} LOGICAL {} ({})",
+            quote_ident(slot),
+            start_lsn,
+            option_list
+        );
+
+        self.start_replication(&command, slot)
+            .await
+            .map_err(|error| match error {
+                ReplicationError::Sqlx(error @ Error::Database(_)) => {
+                    ReplicationError::StartReplication {
+                        slot: slot.to_owned(),
+                        plugin: PGOUTPUT.to_owned(),
+                        source: error,
+                    }
+                }
+                error => error,
+            })?;
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = slot,
+            lsn = %start_lsn,
+            "logical replication started"
+        );
+
+        Ok(LogicalReplicationStream::new(
+            self,
+            slot.to_owned(),
+            start_lsn,
+            options.publication_names,
+            options.proto_version,
+        ))
+    }
+
+    /// Start streaming changes from a logical replication slot at the position stored in
+    /// `store`, and persist the confirmed position of the stream in it; see [`OffsetStore`].
+    ///
+    /// Streaming starts at the [loaded][OffsetStore::load] position, or at the slot's confirmed
+    /// position if that is later or nothing was stored yet, as with
+    /// [`start_logical_replication()`][Self::start_logical_replication]. The stream then
+    /// [stores][LogicalReplicationStream::set_offset_store] the positions it confirms.
+    pub async fn start_logical_replication_with_store(
+        self,
+        slot: &str,
+        store: impl OffsetStore,
+        options: PgOutputOptions,
+    ) -> Result<LogicalReplicationStream, ReplicationError> {
+        let start_lsn = store
+            .load(slot)
+            .await
+            .map_err(|source| ReplicationError::OffsetStore {
+                slot: slot.to_owned(),
+                source,
+            })?
+            .unwrap_or(PgLsn::INVALID);
+
+        let mut stream = self
+            .start_logical_replication(slot, start_lsn, options)
+            .await?;
+        stream.set_offset_store(store);
+
+        Ok(stream)
+    }
+
+    /// Start streaming WAL with physical replication, e.g. to archive it.
+    ///
+    /// Streaming starts at `start_lsn` on `timeline`, or on the server's current timeline if
+    /// `timeline` is `None`. If `slot` is given, the physical replication slot is used and
+    /// advanced with the position confirmed by the stream.
+    ///
+    /// When streaming from a timeline that is not the server's latest one, e.g. after a
+    /// standby was promoted, the stream ends with a
+    /// [`TimelineSwitch`][super::PhysicalReplication::TimelineSwitch] at the end of the timeline;
+    /// replication can then be restarted on the next timeline.
+    ///
+    /// Returns [`ReplicationError::UnexpectedRole`] if the server doesn't have the role set
+    /// with [`set_expected_role()`][Self::set_expected_role], e.g. to make sure that WAL is
+    /// archived from the primary.
+    pub async fn start_physical_replication(
+        mut self,
+        slot: Option<&str>,
+        start_lsn: PgLsn,
+        timeline: Option<u32>,
+    ) -> Result<PhysicalReplicationStream, ReplicationError> {
+        let mut command = String::from("START_REPLICATION ");
+
+        if let Some(slot) = slot {
+            command.push_str(&format!("SLOT {} ", quote_ident(slot)));
+        }
+
+        command.push_str(&format!("PHYSICAL {start_lsn}"));
+
+        if let Some(timeline) = timeline {
+            command.push_str(&format!(" TIMELINE {timeline}"));
+        }
+
+        let slot = slot.unwrap_or_default();
+
+        self.start_replication(&command, slot).await?;
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = slot,
+            lsn = %start_lsn,
+            timeline,
+            "physical replication started"
+        );
+
+        Ok(PhysicalReplicationStream::new(
+            self,
+            slot.to_owned(),
+            start_lsn,
+        ))
+    }
+
+    /// Explicitly close this replication connection.
+    pub async fn close(self) -> Result<(), Error> {
+        self.conn.close().await
+    }
+
+    /// Drop the slot `slot`, logging why it was dropped.
+    async fn drop_slot(&mut self, slot: &str, wait: bool, reason: &str) -> Result<(), Error> {
+        let mut command = format!("DROP_REPLICATION_SLOT {}", quote_ident(slot));
+
+        if wait {
+            command.push_str(" WAIT");
+        }
+
+        self.conn.execute(&*command).await?;
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = slot,
+            reason,
+            "replication slot dropped"
+        );
+
+        Ok(())
+    }
+
+    /// Check that the logical replication slot `slot` exists, uses `plugin`, was not
+    /// invalidated, and decodes prepared transactions if and only if `two_phase` is set.
+    async fn check_slot(
+        &mut self,
+        slot: &str,
+        plugin: &str,
+        two_phase: bool,
+    ) -> Result<(), ReplicationError> {
+        // `wal_status` was added in Postgres 13
+        let wal_status = match self.server_version_num() {
+            Some(version) if version < 130000 => "NULL",
+            _ => "wal_status",
+        };
+
+        let query = format!(
+            "SELECT plugin, slot_type, {wal_status}, {} FROM pg_catalog.pg_replication_slots \
+             WHERE slot_name = {}",
+            self.two_phase_column(),
+            quote_literal(slot)
+        );
+
+        let row = self.conn.fetch_optional(&*query).await?.ok_or_else(|| {
+            ReplicationError::SlotNotFound {
+                slot: slot.to_owned(),
+            }
+        })?;
+
+        let slot_plugin: Option<String> = row.try_get(0)?;
+        let slot_type: String = row.try_get(1)?;
+        let wal_status: Option<String> = row.try_get(2)?;
+        let slot_two_phase: bool = row.try_get(3)?;
+
+        check_wal_status(slot, wal_status.as_deref())?;
+
+        if slot_type != "logical" {
+            return Err(ReplicationError::NotLogicalSlot {
+                slot: slot.to_owned(),
+            });
+        }
+
+        check_plugin(slot, slot_plugin.as_deref(), plugin)?;
+
+        if slot_two_phase != two_phase {
+            return Err(ReplicationError::TwoPhaseMismatch {
+                slot: slot.to_owned(),
+                slot_two_phase,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The `two_phase` column of `pg_replication_slots`, which was added in Postgres 14.
+    fn two_phase_column(&self) -> &'static str {
+        match self.server_version_num() {
+            Some(version) if version < 140000 => "false",
+            _ => "two_phase",
+        }
+    }
+
+    /// Send a `START_REPLICATION` command and wait for the server to enter the `CopyBoth`
+    /// sub-protocol.
+    async fn start_replication(
+        &mut self,
+        command: &str,
+        slot: &str,
+    ) -> Result<(), ReplicationError> {
+        if let Some(role) = self.expected_role {
+            self.check_role(role).await?;
+        }
+
+        self.conn.wait_until_ready().await?;
+        self.conn.inner.stream.send(Query(command)).await?;
+
+        if let Err(error) = self
+            .conn
+            .inner
+            .stream
+            .recv_expect::<CopyBothResponse>()
+            .await
+        {
+            // the error is followed by `ReadyForQuery`
+            self.conn
+                .inner
+                .stream
+                .recv_expect::<ReadyForQuery>()
+                .await?;
+
+            return Err(ReplicationError::from_server(error, slot));
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_one(&mut self, command: &str) -> Result<PgRow, Error> {
+        // replication commands are only supported by the simple query protocol,
+        // which is what `Executor` uses for a query string without arguments
+        self.conn.fetch_one(command).await
+    }
+
+    /// Send a `CopyData` message in the `CopyBoth` sub-protocol.
+    pub(crate) async fn send_copy_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.conn.inner.stream.send(CopyData(data)).await
+    }
+
+    /// Read the frames of the `CopyBoth` sub-protocol after replication was started.
+    pub(crate) fn copy_both(&mut self) -> CopyBothReader<'_> {
+        CopyBothReader::new(&mut self.conn.inner.stream)
+    }
+}
+
+impl Debug for PgReplicationConnection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PgReplicationConnection").finish()
+    }
+}
+
+/// The timestamps of the replication protocol are microseconds since the Postgres epoch, which
+/// is only true for servers built with integer datetimes (the only option since Postgres 10).
+fn check_integer_datetimes(value: Option<&str>) -> Result<(), ReplicationError> {
+    match value {
+        Some("off") => Err(ReplicationError::UnsupportedServerParameter {
+            name: "integer_datetimes".to_owned(),
+            value: "off".to_owned(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// A slot whose WAL was removed is invalidated and fails to start replication with a less
+/// helpful error.
+fn check_wal_status(slot: &str, wal_status: Option<&str>) -> Result<(), ReplicationError> {
+    match wal_status {
+        Some("lost") => Err(ReplicationError::SlotInvalidated {
+            slot: slot.to_owned(),
+        }),
+        // `reserved`, `extended` and `unreserved` slots can still be streamed from
+        _ => Ok(()),
+    }
+}
+
+fn check_plugin(
+    slot: &str,
+    slot_plugin: Option<&str>,
+    requested: &str,
+) -> Result<(), ReplicationError> {
+    match slot_plugin {
+        Some(slot_plugin) if slot_plugin == requested => Ok(()),
+        Some(slot_plugin) if KNOWN_PLUGINS.contains(&slot_plugin) => {
+            Err(ReplicationError::PluginMismatch {
+                slot: slot.to_owned(),
+                expected: slot_plugin.to_owned(),
+                requested: requested.to_owned(),
+            })
+        }
+        Some(slot_plugin) => Err(ReplicationError::UnsupportedPlugin {
+            slot: slot.to_owned(),
+            plugin: slot_plugin.to_owned(),
+            known: KNOWN_PLUGINS
+                .iter()
+                .map(|&plugin| plugin.to_owned())
+                .collect(),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn parse_lsn(lsn: String) -> Result<PgLsn, Error> {
+    lsn.parse().map_err(Error::Decode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_rejects_unknown_plugins() {
+        assert!(check_plugin("slot", Some("pgoutput"), PGOUTPUT).is_ok());
+        assert!(check_plugin("slot", None, PGOUTPUT).is_ok());
+
+        let error = check_plugin("slot", Some("wal2json"), PGOUTPUT).unwrap_err();
+
+        assert!(matches!(
+            &error,
+            ReplicationError::UnsupportedPlugin { plugin, known, .. }
+                if plugin == "wal2json" && known.iter().any(|known| known == "pgoutput")
+        ));
+        assert!(error.to_string().contains("known plugins: pgoutput"));
+    }
+
+    #[test]
+    fn it_rejects_lost_slots() {
+        assert!(check_wal_status("slot", Some("reserved")).is_ok());
+        assert!(check_wal_status("slot", Some("unreserved")).is_ok());
+        assert!(check_wal_status("slot", None).is_ok());
+
+        assert!(matches!(
+            check_wal_status("slot", Some("lost")),
+            Err(ReplicationError::SlotInvalidated { slot }) if slot == "slot"
+        ));
+    }
+
+    #[test]
+    fn it_rejects_float_datetimes() {
+        assert!(check_integer_datetimes(Some("on")).is_ok());
+        assert!(check_integer_datetimes(None).is_ok());
+
+        assert!(matches!(
+            check_integer_datetimes(Some("off")),
+            Err(ReplicationError::UnsupportedServerParameter { name, .. }) if name == "integer_datetimes"
+        ));
+    }
+}
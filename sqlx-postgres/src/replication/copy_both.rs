@@ -0,0 +1,231 @@
+use sqlx_core::bytes::Bytes;
+
+use crate::connection::PgStream;
+use crate::error::Error;
+use crate::message::{BackendMessageFormat, CopyData, DataRow};
+
+use super::ReplicationError;
+
+/// Reads the frames of the `CopyBoth` sub-protocol that carries a replication stream.
+///
+/// The payload of each `CopyData` frame is a [`Replication`][super::Replication] message, for
+/// logical and physical replication alike. An `ErrorResponse` received while streaming is
+/// returned as an error, and a `NoticeResponse` is logged like the notices of any other query.
+pub(crate) struct CopyBothReader<'c> {
+    stream: &'c mut PgStream,
+}
+
+impl<'c> CopyBothReader<'c> {
+    pub(crate) fn new(stream: &'c mut PgStream) -> Self {
+        Self { stream }
+    }
+
+    /// Receive the payload of the next `CopyData` frame, or `None` once the server sent
+    /// `CopyDone`.
+    ///
+    /// A frame is returned only once it was read completely, however many reads of the socket
+    /// that takes: the read buffer grows to the length announced in the header of the frame, so
+    /// a frame with a large value, e.g. a `bytea` of hundreds of megabytes, is never decoded
+    /// from a part of it. The capacity of the read buffer is only the minimum size of a read.
+    ///
+    /// This method is cancel-safe.
+    pub(crate) async fn recv_copy_data(&mut self) -> Result<Option<Bytes>, Error> {
+        // `recv()` returns `ErrorResponse` as an error and handles `NoticeResponse`
+        let message = self.stream.recv().await?;
+
+        match message.format {
+            BackendMessageFormat::CopyData => Ok(Some(message.decode::<CopyData<Bytes>>()?.0)),
+            BackendMessageFormat::CopyDone => Ok(None),
+            format => Err(err_protocol!(
+                "unexpected message format during replication: {:?}",
+                format
+            )),
+        }
+    }
+
+    /// Receive the payload of the next `CopyData` frame like
+    /// [`recv_copy_data()`][Self::recv_copy_data], unless its payload is larger than `max_len`
+    /// bytes, which is checked from the header of the frame before the frame is buffered.
+    ///
+    /// This method is cancel-safe.
+    pub(crate) async fn recv_copy_data_within(
+        &mut self,
+        max_len: usize,
+    ) -> Result<Option<Bytes>, ReplicationError> {
+        let (format, len) = self.stream.peek_header().await?;
+        // the length includes the length prefix
+        let len = len.saturating_sub(4);
+
+        if format == b'd' && len > max_len {
+            return Err(ReplicationError::FrameTooLarge { len, max_len });
+        }
+
+        Ok(self.recv_copy_data().await?)
+    }
+
+    /// Discard the remaining frames until the server sent `CopyDone`.
+    pub(crate) async fn skip_to_done(&mut self) -> Result<(), Error> {
+        while self.recv_copy_data().await?.is_some() {}
+
+        Ok(())
+    }
+
+    /// Wait for the server to finish the command after the `CopyBoth` sub-protocol ended.
+    ///
+    /// `row` is set to the row of the result set the server sends when physical replication
+    /// reached the end of a timeline that is not the server's latest one.
+    ///
+    /// This method is cancel-safe: a row received before it is cancelled is kept in `row`, and
+    /// the next call continues with the messages after it.
+    pub(crate) async fn recv_end(&mut self, row: &mut Option<DataRow>) -> Result<(), Error> {
+        loop {
+            let message = self.stream.recv().await?;
+
+            match message.format {
+                BackendMessageFormat::DataRow => *row = Some(message.decode::<DataRow>()?),
+                BackendMessageFormat::ReadyForQuery => return Ok(()),
+                // `RowDescription` and `CommandComplete`
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context, Poll};
+    use std::{cmp, io};
+
+    use futures_util::FutureExt;
+    use sqlx_core::bytes::BufMut;
+
+    use super::*;
+    use crate::io::ReadBuf;
+    use crate::net::Socket;
+
+    /// A socket that returns at most a few bytes per read, and is not ready to be read after
+    /// every read, so that each frame takes many polls to read.
+    struct TrickleSocket {
+        data: Vec<u8>,
+        pos: usize,
+        ready: bool,
+    }
+
+    impl Socket for TrickleSocket {
+        fn try_read(&mut self, buf: &mut dyn ReadBuf) -> io::Result<usize> {
+            if !self.ready {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            self.ready = false;
+
+            let end = cmp::min(self.pos + 1 + self.pos % 3, self.data.len());
+            buf.put_slice(&self.data[self.pos..end]);
+
+            let read = end - self.pos;
+            self.pos = end;
+
+            Ok(read)
+        }
+
+        fn try_write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn poll_read_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            // ready again for the next poll, which is a new read after a cancelled one
+            self.ready = true;
+
+            Poll::Pending
+        }
+
+        fn poll_write_ready(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(&mut self, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn it_resumes_cancelled_reads() {
+        let payloads: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i; 5 + i as usize * 7]).collect();
+
+        let mut data = Vec::new();
+        for payload in &payloads {
+            data.put_u8(b'd');
+            data.put_u32((4 + payload.len()).try_into().unwrap());
+            data.put_slice(payload);
+        }
+        data.extend_from_slice(b"c\0\0\0\x04");
+
+        let mut stream = PgStream::from_socket(TrickleSocket {
+            data,
+            pos: 0,
+            ready: true,
+        });
+
+        let mut received = Vec::new();
+        let mut cancelled = 0;
+
+        // each read is cancelled as soon as it would wait, mostly in the middle of a frame
+        loop {
+            match CopyBothReader::new(&mut stream)
+                .recv_copy_data()
+                .now_or_never()
+            {
+                None => cancelled += 1,
+                Some(Ok(Some(data))) => received.push(data.to_vec()),
+                Some(Ok(None)) => break,
+                Some(Err(error)) => panic!("read failed: {error}"),
+            }
+        }
+
+        assert_eq!(received, payloads);
+        assert!(cancelled > 100, "only {cancelled} reads were cancelled");
+    }
+
+    #[test]
+    fn it_rejects_frames_above_the_maximum_size() {
+        let mut data = Vec::new();
+        data.put_u8(b'd');
+        data.put_u32(4 + 100);
+        data.put_slice(&[7; 100]);
+
+        let mut stream = PgStream::from_socket(TrickleSocket {
+            data,
+            pos: 0,
+            ready: true,
+        });
+
+        // rejected from the header, without buffering the frame
+        let error = loop {
+            match CopyBothReader::new(&mut stream)
+                .recv_copy_data_within(99)
+                .now_or_never()
+            {
+                None => continue,
+                Some(result) => break result.expect_err("frame above the maximum received"),
+            }
+        };
+        assert!(matches!(
+            error,
+            ReplicationError::FrameTooLarge {
+                len: 100,
+                max_len: 99
+            }
+        ));
+
+        // a frame of exactly the maximum size is received
+        let data = loop {
+            if let Some(result) = CopyBothReader::new(&mut stream)
+                .recv_copy_data_within(100)
+                .now_or_never()
+            {
+                break result.unwrap().unwrap();
+            }
+        };
+        assert_eq!(data.len(), 100);
+    }
+}
@@ -0,0 +1,341 @@
+//! Decoding of the [`decoderbufs`] output plugin, which sends every change as a protobuf
+//! `RowMessage`.
+//!
+//! Streams from `decoderbufs` slots are not started by
+//! [`PgReplicationConnection::start_logical_replication()`][super::PgReplicationConnection::start_logical_replication],
+//! which is specific to `pgoutput`; the payloads of messages received by other means (e.g. from
+//! `pg_logical_slot_get_binary_changes()`) can be decoded with [`decode_decoderbufs()`].
+//!
+//! [`decoderbufs`]: https://github.com/debezium/postgres-decoderbufs
+
+use sqlx_core::bytes::{Buf, Bytes};
+
+use crate::error::Error;
+
+/// A change decoded by `decoderbufs` (`decoderbufs.RowMessage`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RowMessage {
+    /// The xid of the transaction.
+    pub transaction_id: Option<u32>,
+    /// The commit time of the transaction, in microseconds since the Unix epoch.
+    pub commit_time: Option<u64>,
+    /// The qualified name of the table, e.g. `"public"."users"`.
+    pub table: Option<String>,
+    /// The kind of change.
+    pub op: Option<Op>,
+    /// The columns of the new row of an insert or update.
+    pub new_tuple: Vec<DatumMessage>,
+    /// The columns of the old row of an update or delete, as far as sent.
+    pub old_tuple: Vec<DatumMessage>,
+    /// Type information for the columns of `new_tuple`.
+    pub new_typeinfo: Vec<TypeInfo>,
+}
+
+/// The kind of change of a [`RowMessage`] (`decoderbufs.Op`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Unknown,
+    Insert,
+    Update,
+    Delete,
+    Begin,
+    Commit,
+}
+
+/// The value of a column (`decoderbufs.DatumMessage`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DatumMessage {
+    /// The name of the column.
+    pub column_name: Option<String>,
+    /// The OID of the data type of the column.
+    pub column_type: Option<i64>,
+    /// The value; `None` for `NULL`.
+    pub datum: Option<Datum>,
+}
+
+/// A column value of a [`DatumMessage`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+    Int32(i32),
+    Int64(i64),
+    Float(f32),
+    Double(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Bytes),
+    Point {
+        x: f64,
+        y: f64,
+    },
+    /// The value is an unchanged TOASTed value that was not sent.
+    Missing,
+}
+
+/// Type information of a column (`decoderbufs.TypeInfo`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TypeInfo {
+    /// The type as formatted with its modifier, e.g. `character varying(255)`.
+    pub modifier: String,
+    /// Whether the column is nullable.
+    pub value_optional: bool,
+}
+
+/// Decode the payload of a message sent by the `decoderbufs` output plugin.
+pub fn decode_decoderbufs(payload: &[u8]) -> Result<RowMessage, Error> {
+    let mut buf = Bytes::copy_from_slice(payload);
+    let mut message = RowMessage::default();
+
+    while let Some((field, wire_type)) = read_key(&mut buf)? {
+        match (field, wire_type) {
+            (1, VARINT) => message.transaction_id = Some(low_u32(read_varint(&mut buf)?)),
+            (2, VARINT) => message.commit_time = Some(read_varint(&mut buf)?),
+            (3, LEN) => message.table = Some(read_string(&mut buf)?),
+            (4, VARINT) => message.op = Some(Op::from_i32(read_int32(&mut buf)?)),
+            (5, LEN) => message.new_tuple.push(decode_datum(read_len(&mut buf)?)?),
+            (6, LEN) => message.old_tuple.push(decode_datum(read_len(&mut buf)?)?),
+            (7, LEN) => message
+                .new_typeinfo
+                .push(decode_type_info(read_len(&mut buf)?)?),
+            (_, wire_type) => skip(&mut buf, wire_type)?,
+        }
+    }
+
+    Ok(message)
+}
+
+impl Op {
+    fn from_i32(op: i32) -> Self {
+        match op {
+            0 => Op::Insert,
+            1 => Op::Update,
+            2 => Op::Delete,
+            3 => Op::Begin,
+            4 => Op::Commit,
+            _ => Op::Unknown,
+        }
+    }
+}
+
+fn decode_datum(mut buf: Bytes) -> Result<DatumMessage, Error> {
+    let mut datum = DatumMessage::default();
+
+    while let Some((field, wire_type)) = read_key(&mut buf)? {
+        let value = match (field, wire_type) {
+            (1, LEN) => {
+                datum.column_name = Some(read_string(&mut buf)?);
+                continue;
+            }
+            (2, VARINT) => {
+                datum.column_type = Some(reinterpret_i64(read_varint(&mut buf)?));
+                continue;
+            }
+            (3, VARINT) => Datum::Int32(read_int32(&mut buf)?),
+            (4, VARINT) => Datum::Int64(reinterpret_i64(read_varint(&mut buf)?)),
+            (5, FIXED32) => Datum::Float(f32::from_bits(read_fixed32(&mut buf)?)),
+            (6, FIXED64) => Datum::Double(f64::from_bits(read_fixed64(&mut buf)?)),
+            (7, VARINT) => Datum::Bool(read_varint(&mut buf)? != 0),
+            (8, LEN) => Datum::String(read_string(&mut buf)?),
+            (9, LEN) => Datum::Bytes(read_len(&mut buf)?),
+            (10, LEN) => decode_point(read_len(&mut buf)?)?,
+            (11, VARINT) => {
+                read_varint(&mut buf)?;
+                Datum::Missing
+            }
+            (_, wire_type) => {
+                skip(&mut buf, wire_type)?;
+                continue;
+            }
+        };
+
+        datum.datum = Some(value);
+    }
+
+    Ok(datum)
+}
+
+fn decode_point(mut buf: Bytes) -> Result<Datum, Error> {
+    let (mut x, mut y) = (0.0, 0.0);
+
+    while let Some((field, wire_type)) = read_key(&mut buf)? {
+        match (field, wire_type) {
+            (1, FIXED64) => x = f64::from_bits(read_fixed64(&mut buf)?),
+            (2, FIXED64) => y = f64::from_bits(read_fixed64(&mut buf)?),
+            (_, wire_type) => skip(&mut buf, wire_type)?,
+        }
+    }
+
+    Ok(Datum::Point { x, y })
+}
+
+fn decode_type_info(mut buf: Bytes) -> Result<TypeInfo, Error> {
+    let mut type_info = TypeInfo::default();
+
+    while let Some((field, wire_type)) = read_key(&mut buf)? {
+        match (field, wire_type) {
+            (1, LEN) => type_info.modifier = read_string(&mut buf)?,
+            (2, VARINT) => type_info.value_optional = read_varint(&mut buf)? != 0,
+            (_, wire_type) => skip(&mut buf, wire_type)?,
+        }
+    }
+
+    Ok(type_info)
+}
+
+// protobuf wire types
+const VARINT: u8 = 0;
+const FIXED64: u8 = 1;
+const LEN: u8 = 2;
+const FIXED32: u8 = 5;
+
+fn read_key(buf: &mut Bytes) -> Result<Option<(u64, u8)>, Error> {
+    if !buf.has_remaining() {
+        return Ok(None);
+    }
+
+    let key = read_varint(buf)?;
+    let [wire_type, ..] = key.to_le_bytes();
+
+    Ok(Some((key >> 3, wire_type & 0x07)))
+}
+
+fn read_varint(buf: &mut Bytes) -> Result<u64, Error> {
+    let mut value = 0_u64;
+
+    for shift in (0..64).step_by(7) {
+        if !buf.has_remaining() {
+            return Err(err_protocol!("decoderbufs: unexpected end of varint"));
+        }
+
+        let byte = buf.get_u8();
+        value |= u64::from(byte & 0x7F) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(err_protocol!("decoderbufs: varint is too long"))
+}
+
+fn read_len(buf: &mut Bytes) -> Result<Bytes, Error> {
+    let len = usize::try_from(read_varint(buf)?).unwrap_or(usize::MAX);
+
+    if buf.remaining() < len {
+        return Err(err_protocol!(
+            "decoderbufs: field length {} exceeds remaining {} bytes",
+            len,
+            buf.remaining()
+        ));
+    }
+
+    Ok(buf.split_to(len))
+}
+
+fn read_string(buf: &mut Bytes) -> Result<String, Error> {
+    String::from_utf8(read_len(buf)?.to_vec())
+        .map_err(|_| err_protocol!("decoderbufs: invalid UTF-8 in string field"))
+}
+
+fn read_fixed32(buf: &mut Bytes) -> Result<u32, Error> {
+    if buf.remaining() < 4 {
+        return Err(err_protocol!("decoderbufs: unexpected end of fixed32"));
+    }
+
+    Ok(buf.get_u32_le())
+}
+
+fn read_fixed64(buf: &mut Bytes) -> Result<u64, Error> {
+    if buf.remaining() < 8 {
+        return Err(err_protocol!("decoderbufs: unexpected end of fixed64"));
+    }
+
+    Ok(buf.get_u64_le())
+}
+
+/// An `int32` is encoded as the varint of its sign extension to 64 bits.
+fn read_int32(buf: &mut Bytes) -> Result<i32, Error> {
+    Ok(i32::from_le_bytes(low_u32(read_varint(buf)?).to_le_bytes()))
+}
+
+fn low_u32(value: u64) -> u32 {
+    let [a, b, c, d, ..] = value.to_le_bytes();
+    u32::from_le_bytes([a, b, c, d])
+}
+
+fn reinterpret_i64(value: u64) -> i64 {
+    i64::from_le_bytes(value.to_le_bytes())
+}
+
+fn skip(buf: &mut Bytes, wire_type: u8) -> Result<(), Error> {
+    match wire_type {
+        VARINT => read_varint(buf).map(drop),
+        FIXED64 => read_fixed64(buf).map(drop),
+        LEN => read_len(buf).map(drop),
+        FIXED32 => read_fixed32(buf).map(drop),
+        wire_type => Err(err_protocol!(
+            "decoderbufs: unsupported wire type {}",
+            wire_type
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_row_messages() {
+        // RowMessage {
+        //   transaction_id: 742, commit_time: 1, table: "public.t", op: UPDATE,
+        //   new_tuple: [{ column_name: "id", column_type: 23, datum_int32: -1 },
+        //               { column_name: "p", column_type: 600, datum_point: { x: 1.5, y: -2 } }],
+        //   old_tuple: [{ column_name: "b", column_type: 25, datum_missing: true }],
+        //   new_typeinfo: [{ modifier: "integer", value_optional: false }],
+        // }
+        const DATA: &[u8] = b"\x08\xe6\x05\x10\x01\x1a\x08public.t\x20\x01\
+            \x2a\x11\x0a\x02id\x10\x17\x18\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01\
+            \x2a\x1a\x0a\x01p\x10\xd8\x04\x52\x12\x09\0\0\0\0\0\0\xf8\x3f\x11\0\0\0\0\0\0\0\xc0\
+            \x32\x07\x0a\x01b\x10\x19\x58\x01\
+            \x3a\x0b\x0a\x07integer\x10\x00";
+
+        let message = decode_decoderbufs(DATA).unwrap();
+
+        assert_eq!(message.transaction_id, Some(742));
+        assert_eq!(message.commit_time, Some(1));
+        assert_eq!(message.table.as_deref(), Some("public.t"));
+        assert_eq!(message.op, Some(Op::Update));
+
+        assert_eq!(
+            message.new_tuple,
+            [
+                DatumMessage {
+                    column_name: Some("id".into()),
+                    column_type: Some(23),
+                    datum: Some(Datum::Int32(-1)),
+                },
+                DatumMessage {
+                    column_name: Some("p".into()),
+                    column_type: Some(600),
+                    datum: Some(Datum::Point { x: 1.5, y: -2.0 }),
+                },
+            ]
+        );
+
+        assert_eq!(message.old_tuple[0].datum, Some(Datum::Missing));
+        assert_eq!(
+            message.new_typeinfo,
+            [TypeInfo {
+                modifier: "integer".into(),
+                value_optional: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_row_messages() {
+        assert!(decode_decoderbufs(b"\x08").is_err());
+        assert!(decode_decoderbufs(b"\x1a\x05abc").is_err());
+        assert!(decode_decoderbufs(b"\x1a\x01\xff").is_err());
+        assert!(decode_decoderbufs(b"\x0b").is_err());
+    }
+}
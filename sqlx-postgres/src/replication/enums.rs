@@ -0,0 +1,128 @@
+use sqlx_core::row::Row;
+
+use crate::error::Error;
+use crate::types::Oid;
+use crate::PgConnection;
+
+use super::{quote_ident, TupleData};
+
+/// A user-defined enum type, as returned by [`enum_types()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EnumType {
+    /// The OID of the type, matching [`Column::type_id`][super::Column::type_id].
+    pub type_id: Oid,
+    /// The schema of the type.
+    pub schema: String,
+    /// The name of the type.
+    pub name: String,
+    /// The labels of the type, in their sort order.
+    pub labels: Vec<String>,
+}
+
+impl EnumType {
+    /// The quoted, schema-qualified name of the type.
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", quote_ident(&self.schema), quote_ident(&self.name))
+    }
+
+    /// Decode a value of a column of this type to its label; `None` for `NULL`.
+    ///
+    /// Enum values are sent as their label in both the text and the binary format, never as
+    /// the OID of the label. Returns an error for a label that is not one of
+    /// [`labels`][Self::labels], e.g. because it was added with `ALTER TYPE ... ADD VALUE`
+    /// after the type was queried, in which case the type should be queried again, and for
+    /// [`TupleData::UnchangedToast`], whose value is not known.
+    pub fn decode_label<'a>(&self, data: &'a TupleData) -> Result<Option<&'a str>, Error> {
+        let bytes = match data {
+            TupleData::Null => return Ok(None),
+            TupleData::UnchangedToast => {
+                return Err(Error::Decode("unchanged TOAST value was not sent".into()));
+            }
+            TupleData::Text(bytes) | TupleData::Binary(bytes) => bytes,
+        };
+
+        let label = std::str::from_utf8(bytes).map_err(|error| Error::Decode(error.into()))?;
+
+        if !self.labels.iter().any(|known| known == label) {
+            return Err(Error::Decode(
+                format!("unknown label {label:?} of enum {}", self.qualified_name()).into(),
+            ));
+        }
+
+        Ok(Some(label))
+    }
+}
+
+/// Query the enum types among the types `type_ids`, e.g. the
+/// [`type_id`][super::Column::type_id]s of the columns of a [`Relation`][super::Relation], to
+/// decode their values with [`EnumType::decode_label()`].
+///
+/// The OIDs of user-defined types are not known up front and differ between databases, so
+/// they are resolved from `pg_type` and `pg_enum`. OIDs of types that are not enums are left
+/// out of the result, which is ordered by OID.
+///
+/// This runs on a normal (non-replication) connection.
+pub async fn enum_types<C: AsMut<PgConnection>>(
+    mut conn: C,
+    type_ids: &[Oid],
+) -> Result<Vec<EnumType>, Error> {
+    let rows = crate::query::query(
+        "SELECT t.oid, n.nspname::text, t.typname::text, \
+         COALESCE(array_agg(e.enumlabel::text ORDER BY e.enumsortorder) \
+         FILTER (WHERE e.enumlabel IS NOT NULL), '{}') \
+         FROM pg_catalog.pg_type t \
+         JOIN pg_catalog.pg_namespace n ON n.oid = t.typnamespace \
+         LEFT JOIN pg_catalog.pg_enum e ON e.enumtypid = t.oid \
+         WHERE t.oid = ANY($1) AND t.typtype = 'e' \
+         GROUP BY t.oid, n.nspname, t.typname \
+         ORDER BY t.oid",
+    )
+    .bind(type_ids)
+    .fetch_all(conn.as_mut())
+    .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(EnumType {
+                type_id: row.try_get(0)?,
+                schema: row.try_get(1)?,
+                name: row.try_get(2)?,
+                labels: row.try_get(3)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn it_decodes_enum_labels() {
+        let mood = EnumType {
+            type_id: Oid(16400),
+            schema: "public".to_owned(),
+            name: "mood".to_owned(),
+            labels: vec!["sad".to_owned(), "ok".to_owned(), "happy".to_owned()],
+        };
+
+        let text = TupleData::Text(Bytes::from_static(b"happy"));
+        assert_eq!(mood.decode_label(&text).unwrap(), Some("happy"));
+
+        // the binary format is the label as well
+        let binary = TupleData::Binary(Bytes::from_static(b"sad"));
+        assert_eq!(mood.decode_label(&binary).unwrap(), Some("sad"));
+
+        assert_eq!(mood.decode_label(&TupleData::Null).unwrap(), None);
+        assert!(mood.decode_label(&TupleData::UnchangedToast).is_err());
+
+        let unknown = TupleData::Text(Bytes::from_static(b"angry"));
+        assert!(matches!(
+            mood.decode_label(&unknown),
+            Err(Error::Decode(error)) if error.to_string().contains(r#""public"."mood""#)
+        ));
+    }
+}
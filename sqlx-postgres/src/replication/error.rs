@@ -0,0 +1,479 @@
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::types::PgLsn;
+
+#[cfg(feature = "replication-stream")]
+use super::{ServerRole, TransactionViolation};
+
+/// An error returned while setting up or consuming a replication stream.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ReplicationError {
+    /// The replication slot does not exist.
+    #[error("replication slot {slot:?} does not exist")]
+    SlotNotFound { slot: String },
+
+    /// Logical replication was requested from a physical replication slot.
+    #[error(
+        "replication slot {slot:?} is a physical slot and cannot be used for logical replication"
+    )]
+    NotLogicalSlot { slot: String },
+
+    /// The replication slot was created with a different output plugin than the one the
+    /// stream was started with.
+    #[error(
+        "replication slot {slot:?} uses output plugin {expected:?}, \
+         but the stream was started with options for {requested:?}"
+    )]
+    PluginMismatch {
+        slot: String,
+        /// The output plugin of the slot.
+        expected: String,
+        /// The output plugin the stream requires.
+        requested: String,
+    },
+
+    /// The replication slot uses an output plugin whose messages cannot be decoded.
+    #[error(
+        "replication slot {slot:?} uses output plugin {plugin:?}, which is not supported; \
+         known plugins: {}",
+        known.join(", ")
+    )]
+    UnsupportedPlugin {
+        slot: String,
+        /// The output plugin of the slot.
+        plugin: String,
+        /// The output plugins that can be decoded.
+        known: Vec<String>,
+    },
+
+    /// The options for the output plugin are invalid.
+    #[error("invalid replication options: {reason}")]
+    InvalidOptions { reason: String },
+
+    /// The server rejected `START_REPLICATION`, typically because of an option the output
+    /// plugin of the slot does not support.
+    #[error("failed to start replication from slot {slot:?} (output plugin {plugin:?}): {source}")]
+    StartReplication {
+        slot: String,
+        plugin: String,
+        #[source]
+        source: Error,
+    },
+
+    /// A replication slot could not be advanced because the requested position is lower than
+    /// its current position.
+    #[error(
+        "cannot advance replication slot {slot:?} backwards to {upto}{}",
+        minimum.map(|lsn| format!(", minimum is {lsn}")).unwrap_or_default()
+    )]
+    AdvanceBackwards {
+        slot: String,
+        /// The requested position.
+        upto: PgLsn,
+        /// The current position of the slot, if reported by the server.
+        minimum: Option<PgLsn>,
+    },
+
+    /// Logical replication was started with options that don't match the `two_phase` setting
+    /// of the slot: a slot created with
+    /// [`two_phase`][super::CreateReplicationSlot::two_phase] must be streamed with
+    /// [`PgOutputOptions::two_phase(true)`][super::PgOutputOptions::two_phase], and one created
+    /// without it must be streamed without it.
+    #[error(
+        "replication slot {slot:?} was created {}, but the stream was started {}",
+        if *slot_two_phase { "with two_phase" } else { "without two_phase" },
+        if *slot_two_phase { "without it" } else { "with it" }
+    )]
+    TwoPhaseMismatch {
+        slot: String,
+        /// Whether the slot was created with `two_phase`.
+        slot_two_phase: bool,
+    },
+
+    /// The server reported a parameter value that replication does not support, like
+    /// `integer_datetimes = off`.
+    #[error("server parameter {name} = {value:?} is not supported for replication")]
+    UnsupportedServerParameter { name: String, value: String },
+
+    /// No message was received from the server within the read timeout of the stream.
+    #[error("no message received from the server for {timeout:?}")]
+    Timeout { timeout: Duration },
+
+    /// The replication slot is in use by another connection.
+    ///
+    /// [`PgReplicationConnection::slot_active_pid()`][super::PgReplicationConnection::slot_active_pid]
+    /// tells which backend holds the slot.
+    #[error("replication slot {slot:?} is in use: {source}")]
+    SlotInUse {
+        slot: String,
+        #[source]
+        source: Error,
+    },
+
+    /// The server terminated replication because of a conflict, e.g. with recovery on a standby.
+    #[error("replication terminated due to a conflict: {source}")]
+    Conflict {
+        #[source]
+        source: Error,
+    },
+
+    /// The server is shutting down or restarting, or terminated the connection on request of an
+    /// administrator.
+    #[error("replication terminated by the server: {source}")]
+    ServerShutdown {
+        #[source]
+        source: Error,
+    },
+
+    /// The WAL needed to continue replication has already been removed from the server.
+    #[error("required WAL has been removed: {source}")]
+    WalRemoved {
+        #[source]
+        source: Error,
+    },
+
+    /// The replication slot was invalidated because WAL it needed was removed
+    /// (`wal_status = 'lost'`), e.g. after the consumer was down for longer than
+    /// `max_slot_wal_keep_size` allows.
+    ///
+    /// The slot can never be streamed from again. Drop it, and bootstrap the consumer again
+    /// from a fresh snapshot with a new slot.
+    #[error(
+        "replication slot {slot:?} was invalidated because required WAL was removed; \
+         drop it and bootstrap from a new slot"
+    )]
+    SlotInvalidated { slot: String },
+
+    /// Logical decoding is not available because the server is a standby: standbys support
+    /// logical decoding from Postgres 16, and only if the primary runs with
+    /// `wal_level = logical`.
+    #[error("logical decoding is not available on this standby: {source}")]
+    StandbyNotSupported {
+        #[source]
+        source: Error,
+    },
+
+    /// A logical slot was not created because the server doesn't run with
+    /// `wal_level = logical`, which logical decoding requires.
+    ///
+    /// Set `wal_level = logical` in `postgresql.conf` (or with `ALTER SYSTEM`) and restart the
+    /// server; the setting can't be changed with a reload.
+    #[error(
+        "logical replication requires wal_level = logical, but the server runs with \
+         wal_level = {found}; set it in postgresql.conf and restart the server"
+    )]
+    WalLevelInsufficient { found: String },
+
+    /// A standby status update could not be sent because the server ended the stream, so the
+    /// connection is no longer in the `CopyBoth` mode of streaming replication, or the stream
+    /// ended while [waiting for an LSN][super::LogicalReplicationStream::wait_for_lsn].
+    #[error("the replication stream has ended")]
+    StreamEnded,
+
+    /// An exported snapshot could not be imported with `SET TRANSACTION SNAPSHOT`, typically
+    /// because its identifier is wrong, or because the replication connection that exported it
+    /// ran another command or was closed since, which ends the snapshot.
+    #[error(
+        "cannot import snapshot {snapshot:?}; exported snapshots are only valid until the next \
+         command on the replication connection that created the slot: {source}"
+    )]
+    SnapshotImport {
+        snapshot: String,
+        #[source]
+        source: Error,
+    },
+
+    /// The definition of the table of a [`TableStream`][super::TableStream] changed while
+    /// streaming, e.g. because a column was added, or the table was dropped and created again.
+    #[error("the definition of relation {relation} changed while streaming its changes")]
+    SchemaChanged {
+        /// The qualified name of the relation.
+        relation: String,
+    },
+
+    /// The WAL received while [archiving][super::PhysicalReplicationStream::archive], or with a
+    /// [contiguity check][super::ContiguityCheck], doesn't continue where the WAL before ended,
+    /// so a copy would have a gap, or an overlap.
+    #[error(
+        "gap in WAL: expected WAL starting at {expected}, received WAL starting at {received}"
+    )]
+    WalGap {
+        /// The end of the WAL written before.
+        expected: PgLsn,
+        /// The start of the WAL received.
+        received: PgLsn,
+    },
+
+    /// A transaction received with a [contiguity check][super::ContiguityCheck] ends before
+    /// the transaction received before it, although transactions are sent in commit order.
+    #[error("transaction ending at {received} received after a transaction ending at {previous}")]
+    CommitOutOfOrder {
+        /// The end position of the transaction received before.
+        previous: PgLsn,
+        /// The end position of the transaction received.
+        received: PgLsn,
+    },
+
+    /// The confirmed position could not be loaded from or stored in the [`OffsetStore`] of
+    /// the stream.
+    ///
+    /// [`OffsetStore`]: super::OffsetStore
+    #[error("failed to persist the position of replication slot {slot:?}: {source}")]
+    OffsetStore {
+        slot: String,
+        #[source]
+        source: Error,
+    },
+
+    /// A deleted row has no key, because its relation has `REPLICA IDENTITY NOTHING` (or
+    /// `DEFAULT` without a primary key), so the delete cannot be applied by key downstream.
+    #[error(
+        "a row of relation {relation} was deleted without a key; it has no replica identity \
+         (REPLICA IDENTITY NOTHING, or DEFAULT without a primary key)"
+    )]
+    MissingKey {
+        /// The qualified name of the relation.
+        relation: String,
+    },
+
+    /// The server announced a frame larger than the
+    /// [maximum frame size][super::LogicalReplicationStream::set_max_frame_size] of the stream,
+    /// which was rejected before it was buffered.
+    #[error("received a frame of {len} bytes, more than the maximum of {max_len} bytes")]
+    FrameTooLarge {
+        /// The size of the payload of the frame.
+        len: usize,
+        /// The maximum size of the payload of a frame.
+        max_len: usize,
+    },
+
+    /// The server doesn't have the role replication was expected to be started on, e.g. it is a
+    /// standby and a primary was expected; see
+    /// [`PgReplicationConnection::set_expected_role()`][super::PgReplicationConnection::set_expected_role].
+    #[cfg(feature = "replication-stream")]
+    #[error("connected to a {actual}, expected a {expected}")]
+    UnexpectedRole {
+        /// The expected role.
+        expected: ServerRole,
+        /// The role of the server.
+        actual: ServerRole,
+    },
+
+    /// A publication could not be created for lack of privileges, e.g. by
+    /// [`create_publication_if_missing()`][super::create_publication_if_missing].
+    #[error("permission denied to create publication {publication:?}: {source}")]
+    PublicationPermissionDenied {
+        publication: String,
+        #[source]
+        source: Error,
+    },
+
+    /// A message could not be applied to the [`ChangeSink`][super::ChangeSink] of
+    /// [`LogicalReplicationStream::pump_into()`][super::LogicalReplicationStream::pump_into], or
+    /// the sink could not be flushed.
+    #[error("failed to apply changes to the sink: {source}")]
+    Sink {
+        #[source]
+        source: Error,
+    },
+
+    /// A transaction received from a [`TransactionStream`][super::TransactionStream] with
+    /// [validation][super::TransactionStream::set_validate] on is inconsistent, which points to
+    /// a bug in decoding or reassembling it.
+    #[cfg(feature = "replication-stream")]
+    #[error("transaction {xid} is inconsistent: {violation}")]
+    InvalidTransaction {
+        xid: u32,
+        #[source]
+        violation: TransactionViolation,
+    },
+
+    #[error(transparent)]
+    Sqlx(#[from] Error),
+}
+
+impl ReplicationError {
+    /// Returns `true` if replication can be resumed by reconnecting (possibly after a delay) and
+    /// restarting from the last confirmed position.
+    ///
+    /// Errors caused by the configuration of the slot or the stream, or by WAL that is no longer
+    /// available, are not retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ReplicationError::Timeout { .. }
+            | ReplicationError::SlotInUse { .. }
+            | ReplicationError::Conflict { .. }
+            | ReplicationError::ServerShutdown { .. } => true,
+            ReplicationError::Sqlx(error)
+            | ReplicationError::OffsetStore { source: error, .. }
+            | ReplicationError::Sink { source: error } => {
+                matches!(error, Error::Io(_) | Error::PoolTimedOut)
+            }
+            _ => false,
+        }
+    }
+
+    /// Map an error the server returned for the replication slot `slot` to a typed variant.
+    #[cfg(feature = "replication-stream")]
+    pub(crate) fn from_server(error: Error, slot: &str) -> Self {
+        let code = error
+            .as_database_error()
+            .and_then(|db_error| db_error.code().map(|code| code.into_owned()));
+
+        match code.as_deref() {
+            // object_in_use
+            Some("55006") => ReplicationError::SlotInUse {
+                slot: slot.to_owned(),
+                source: error,
+            },
+
+            // serialization_failure ("terminating connection due to conflict with recovery"),
+            // deadlock_detected; database_dropped is left as is, as retrying can't succeed
+            Some("40001" | "40P01") => ReplicationError::Conflict { source: error },
+
+            // admin_shutdown, crash_shutdown, cannot_connect_now
+            Some("57P01" | "57P02" | "57P03") => ReplicationError::ServerShutdown { source: error },
+
+            // object_not_in_prerequisite_state, also used for unrelated errors
+            Some("55000")
+                if message_starts_with(
+                    &error,
+                    "can no longer get changes from replication slot",
+                ) =>
+            {
+                ReplicationError::SlotInvalidated {
+                    slot: slot.to_owned(),
+                }
+            }
+
+            // feature_not_supported, before Postgres 16
+            Some("0A000")
+                if message_starts_with(
+                    &error,
+                    "logical decoding cannot be used while in recovery",
+                ) =>
+            {
+                ReplicationError::StandbyNotSupported { source: error }
+            }
+
+            Some("55000")
+                if message_starts_with(&error, "logical decoding on standby requires") =>
+            {
+                ReplicationError::StandbyNotSupported { source: error }
+            }
+
+            // undefined_file ("requested WAL segment ... has already been removed")
+            Some("58P01") => ReplicationError::WalRemoved { source: error },
+
+            _ => ReplicationError::Sqlx(error),
+        }
+    }
+}
+
+#[cfg(feature = "replication-stream")]
+fn message_starts_with(error: &Error, prefix: &str) -> bool {
+    error
+        .as_database_error()
+        .is_some_and(|db_error| db_error.message().starts_with(prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::message::{BackendMessage, Notice};
+    use crate::PgDatabaseError;
+
+    fn database_error(code: &str, message: &str) -> Error {
+        let data = format!("SFATAL\0VFATAL\0C{code}\0M{message}\0\0");
+        let notice = Notice::decode_body(Bytes::from(data)).unwrap();
+
+        PgDatabaseError(notice).into()
+    }
+
+    #[test]
+    fn it_maps_server_errors() {
+        let error = ReplicationError::from_server(
+            database_error(
+                "40001",
+                "terminating connection due to conflict with recovery",
+            ),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::Conflict { .. }));
+        assert!(error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error(
+                "57P01",
+                "terminating connection due to administrator command",
+            ),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::ServerShutdown { .. }));
+        assert!(error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error("55006", "replication slot \"slot\" is active for PID 42"),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::SlotInUse { ref slot, .. } if slot == "slot"));
+        assert!(error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error("58P01", "requested WAL segment has already been removed"),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::WalRemoved { .. }));
+        assert!(!error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error("0A000", "logical decoding cannot be used while in recovery"),
+            "slot",
+        );
+        assert!(matches!(
+            error,
+            ReplicationError::StandbyNotSupported { .. }
+        ));
+        assert!(!error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error(
+                "55000",
+                "logical decoding on standby requires wal_level >= logical on the primary",
+            ),
+            "slot",
+        );
+        assert!(matches!(
+            error,
+            ReplicationError::StandbyNotSupported { .. }
+        ));
+
+        let error = ReplicationError::from_server(
+            database_error(
+                "55000",
+                "can no longer get changes from replication slot \"slot\"",
+            ),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::SlotInvalidated { .. }));
+        assert!(!error.is_retryable());
+
+        let error = ReplicationError::from_server(
+            database_error(
+                "57P04",
+                "terminating connection because the database was dropped",
+            ),
+            "slot",
+        );
+        assert!(matches!(error, ReplicationError::Sqlx(Error::Database(_))));
+        assert!(!error.is_retryable());
+
+        let error = ReplicationError::from_server(database_error("42601", "syntax error"), "slot");
+        assert!(matches!(error, ReplicationError::Sqlx(Error::Database(_))));
+        assert!(!error.is_retryable());
+    }
+}
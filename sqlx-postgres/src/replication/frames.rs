@@ -0,0 +1,321 @@
+//! A builder of `pgoutput` messages, to test the decoders with messages that are tedious to
+//! capture from a server, e.g. with thousands of columns or huge values.
+
+use crate::types::{Oid, PgLsn};
+
+use super::{LogicalDecodeContext, LogicalReplication, TupleData};
+
+/// Builds a sequence of `pgoutput` messages, the way the server encodes them.
+///
+/// Data messages between [`stream_start()`][Self::stream_start] and
+/// [`stream_stop()`][Self::stream_stop] are prefixed with the xid of the streamed transaction.
+#[derive(Debug, Default)]
+pub(crate) struct PgOutputFrameBuilder {
+    frames: Vec<Vec<u8>>,
+    streamed_xid: Option<u32>,
+}
+
+impl PgOutputFrameBuilder {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn begin(self, final_lsn: PgLsn, commit_timestamp: i64, xid: u32) -> Self {
+        self.frame(b'B', false, |buf| {
+            buf.extend_from_slice(&final_lsn.0.to_be_bytes());
+            buf.extend_from_slice(&commit_timestamp.to_be_bytes());
+            buf.extend_from_slice(&xid.to_be_bytes());
+        })
+    }
+
+    pub(crate) fn commit(self, commit_lsn: PgLsn, end_lsn: PgLsn, commit_timestamp: i64) -> Self {
+        self.frame(b'C', false, |buf| {
+            buf.push(0);
+            buf.extend_from_slice(&commit_lsn.0.to_be_bytes());
+            buf.extend_from_slice(&end_lsn.0.to_be_bytes());
+            buf.extend_from_slice(&commit_timestamp.to_be_bytes());
+        })
+    }
+
+    /// A `Relation` message; `columns` are the flags, name, type OID and type modifier of each
+    /// column.
+    pub(crate) fn relation(
+        self,
+        relation_id: Oid,
+        namespace: &str,
+        name: &str,
+        replica_identity: u8,
+        columns: &[(u8, &str, Oid, i32)],
+    ) -> Self {
+        self.frame(b'R', true, |buf| {
+            buf.extend_from_slice(&relation_id.0.to_be_bytes());
+            put_str(buf, namespace);
+            put_str(buf, name);
+            buf.push(replica_identity);
+            put_len(buf, columns.len());
+
+            for &(flags, name, type_id, type_modifier) in columns {
+                buf.push(flags);
+                put_str(buf, name);
+                buf.extend_from_slice(&type_id.0.to_be_bytes());
+                buf.extend_from_slice(&type_modifier.to_be_bytes());
+            }
+        })
+    }
+
+    pub(crate) fn insert(self, relation_id: Oid, new: &[TupleData]) -> Self {
+        self.frame(b'I', true, |buf| {
+            buf.extend_from_slice(&relation_id.0.to_be_bytes());
+            put_tuple(buf, b'N', new);
+        })
+    }
+
+    /// An `Update` message, with the key (`K`) or the old row (`O`) if `old` is given.
+    pub(crate) fn update(
+        self,
+        relation_id: Oid,
+        old: Option<(u8, &[TupleData])>,
+        new: &[TupleData],
+    ) -> Self {
+        self.frame(b'U', true, |buf| {
+            buf.extend_from_slice(&relation_id.0.to_be_bytes());
+
+            if let Some((kind, old)) = old {
+                put_tuple(buf, kind, old);
+            }
+
+            put_tuple(buf, b'N', new);
+        })
+    }
+
+    /// A `Delete` message with the key (`K`) or the old row (`O`).
+    pub(crate) fn delete(self, relation_id: Oid, kind: u8, old: &[TupleData]) -> Self {
+        self.frame(b'D', true, |buf| {
+            buf.extend_from_slice(&relation_id.0.to_be_bytes());
+            put_tuple(buf, kind, old);
+        })
+    }
+
+    pub(crate) fn truncate(self, options: u8, relation_ids: &[Oid]) -> Self {
+        self.frame(b'T', true, |buf| {
+            buf.extend_from_slice(&u32::try_from(relation_ids.len()).unwrap().to_be_bytes());
+            buf.push(options);
+
+            for relation_id in relation_ids {
+                buf.extend_from_slice(&relation_id.0.to_be_bytes());
+            }
+        })
+    }
+
+    pub(crate) fn message(
+        self,
+        transactional: bool,
+        lsn: PgLsn,
+        prefix: &str,
+        content: &[u8],
+    ) -> Self {
+        self.frame(b'M', true, |buf| {
+            buf.push(u8::from(transactional));
+            buf.extend_from_slice(&lsn.0.to_be_bytes());
+            put_str(buf, prefix);
+            buf.extend_from_slice(&u32::try_from(content.len()).unwrap().to_be_bytes());
+            buf.extend_from_slice(content);
+        })
+    }
+
+    pub(crate) fn stream_start(mut self, xid: u32, first_segment: bool) -> Self {
+        self = self.frame(b'S', false, |buf| {
+            buf.extend_from_slice(&xid.to_be_bytes());
+            buf.push(u8::from(first_segment));
+        });
+        self.streamed_xid = Some(xid);
+        self
+    }
+
+    pub(crate) fn stream_stop(mut self) -> Self {
+        self.streamed_xid = None;
+        self.frame(b'E', false, |_| {})
+    }
+
+    pub(crate) fn stream_commit(
+        self,
+        xid: u32,
+        commit_lsn: PgLsn,
+        end_lsn: PgLsn,
+        commit_timestamp: i64,
+    ) -> Self {
+        self.frame(b'c', false, |buf| {
+            buf.extend_from_slice(&xid.to_be_bytes());
+            buf.push(0);
+            buf.extend_from_slice(&commit_lsn.0.to_be_bytes());
+            buf.extend_from_slice(&end_lsn.0.to_be_bytes());
+            buf.extend_from_slice(&commit_timestamp.to_be_bytes());
+        })
+    }
+
+    pub(crate) fn stream_abort(self, xid: u32, subxid: u32) -> Self {
+        self.frame(b'A', false, |buf| {
+            buf.extend_from_slice(&xid.to_be_bytes());
+            buf.extend_from_slice(&subxid.to_be_bytes());
+        })
+    }
+
+    /// The encoded messages.
+    pub(crate) fn build(self) -> Vec<Vec<u8>> {
+        self.frames
+    }
+
+    /// Decode the messages in order, as a stream of protocol version 2 would.
+    pub(crate) fn decode(self) -> Vec<LogicalReplication> {
+        let mut ctx = LogicalDecodeContext::new(2);
+
+        self.frames
+            .iter()
+            .map(|frame| {
+                let message = super::decode_logical(frame, ctx).unwrap();
+                ctx.observe(&message);
+                message
+            })
+            .collect()
+    }
+
+    /// Add a message with `tag`, prefixed with the streamed xid if `data` is a data message.
+    fn frame(mut self, tag: u8, data: bool, body: impl FnOnce(&mut Vec<u8>)) -> Self {
+        let mut buf = vec![tag];
+
+        if let Some(xid) = self.streamed_xid.filter(|_| data) {
+            buf.extend_from_slice(&xid.to_be_bytes());
+        }
+
+        body(&mut buf);
+        self.frames.push(buf);
+        self
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+fn put_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend_from_slice(&i16::try_from(len).unwrap().to_be_bytes());
+}
+
+fn put_tuple(buf: &mut Vec<u8>, kind: u8, values: &[TupleData]) {
+    buf.push(kind);
+    put_len(buf, values.len());
+
+    for value in values {
+        let (marker, bytes) = match value {
+            TupleData::Null => (b'n', None),
+            TupleData::UnchangedToast => (b'u', None),
+            TupleData::Text(bytes) => (b't', Some(bytes)),
+            TupleData::Binary(bytes) => (b'b', Some(bytes)),
+        };
+
+        buf.push(marker);
+
+        if let Some(bytes) = bytes {
+            buf.extend_from_slice(&u32::try_from(bytes.len()).unwrap().to_be_bytes());
+            buf.extend_from_slice(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+
+    const USERS: Oid = Oid(16385);
+
+    fn text(value: &str) -> TupleData {
+        TupleData::Text(Bytes::copy_from_slice(value.as_bytes()))
+    }
+
+    #[test]
+    fn it_encodes_like_the_server() {
+        // the captured `Begin` and `Insert` of `logical.rs`
+        let frames = PgOutputFrameBuilder::new()
+            .begin(PgLsn(0x015B_9A90), 0x0002_B54A_7119_7E22, 742)
+            .insert(USERS, &[text("1"), text("foo")])
+            .build();
+
+        assert_eq!(
+            frames[0],
+            b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6"
+        );
+        assert_eq!(frames[1], b"I\0\0\x40\x01N\0\x02t\0\0\0\x011t\0\0\0\x03foo");
+    }
+
+    #[test]
+    fn it_decodes_generated_edge_cases() {
+        let huge = "x".repeat(1 << 20);
+
+        let messages = PgOutputFrameBuilder::new()
+            .begin(PgLsn(0x100), 0, 742)
+            .relation(USERS, "public", "users", b'd', &[])
+            // a table without columns
+            .insert(USERS, &[])
+            .insert(USERS, &[TupleData::Null, TupleData::Null, TupleData::Null])
+            .update(
+                USERS,
+                Some((b'O', &[text("1"), text(&huge)])),
+                &[text("1"), TupleData::UnchangedToast],
+            )
+            .delete(USERS, b'K', &[text("1"), TupleData::Null])
+            .truncate(1, &[USERS, Oid(16386)])
+            .message(true, PgLsn(0x180), "", b"")
+            .commit(PgLsn(0x200), PgLsn(0x210), 0)
+            .decode();
+
+        let [LogicalReplication::Begin(_), LogicalReplication::Relation(relation), LogicalReplication::Insert(empty), LogicalReplication::Insert(nulls), LogicalReplication::Update(update), LogicalReplication::Delete(delete), LogicalReplication::Truncate(truncate), LogicalReplication::Message(message), LogicalReplication::Commit(commit)] =
+            &messages[..]
+        else {
+            panic!("unexpected messages: {messages:?}");
+        };
+
+        assert!(relation.columns.is_empty());
+        assert!(empty.new_data.is_empty());
+        assert!(nulls.new_data.iter().all(TupleData::is_null));
+
+        assert_eq!(
+            update.old_data.as_ref().unwrap()[1].as_str(),
+            Some(&huge[..])
+        );
+        assert_eq!(update.new_data[1], TupleData::UnchangedToast);
+
+        assert_eq!(delete.key_data.as_ref().unwrap()[1], TupleData::Null);
+        assert!(truncate.cascade());
+        assert_eq!(truncate.relation_ids, [USERS, Oid(16386)]);
+        assert!(message.prefix.is_empty() && message.content.is_empty());
+        assert_eq!(commit.end_lsn, PgLsn(0x210));
+    }
+
+    #[test]
+    fn it_prefixes_streamed_changes_with_the_xid() {
+        let messages = PgOutputFrameBuilder::new()
+            .stream_start(742, true)
+            .insert(USERS, &[text("1")])
+            .stream_stop()
+            .stream_abort(742, 743)
+            .stream_commit(742, PgLsn(0x200), PgLsn(0x210), 0)
+            .decode();
+
+        let LogicalReplication::Insert(insert) = &messages[1] else {
+            panic!("expected Insert, got {:?}", messages[1]);
+        };
+        assert_eq!(insert.xid, Some(742));
+
+        let LogicalReplication::StreamAbort(abort) = &messages[3] else {
+            panic!("expected StreamAbort, got {:?}", messages[3]);
+        };
+        assert!(!abort.is_top_level());
+
+        assert!(
+            matches!(&messages[4], LogicalReplication::StreamCommit(commit) if commit.xid == 742)
+        );
+    }
+}
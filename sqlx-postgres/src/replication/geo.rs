@@ -0,0 +1,120 @@
+//! Decoding of PostGIS `geometry` and `geography` values as [EWKB], for consumers that hand
+//! them to a geometry library.
+//!
+//! PostGIS types are created by an extension, so their OIDs differ between databases and are
+//! resolved from the [`Type`][super::Type] message the server sends before the first
+//! [`Relation`][super::Relation] with a column of the type. Decode the values of these columns
+//! with [`TupleData::try_decode_custom()`][super::TupleData::try_decode_custom]:
+//!
+//! ```rust,no_run
+//! # fn example(
+//! #     data: &sqlx::postgres::replication::TupleData,
+//! #     ty: &sqlx::postgres::replication::Type,
+//! # ) -> Result<(), sqlx::Error> {
+//! use sqlx::postgres::replication::geo::Ewkb;
+//!
+//! let geometry: Option<Ewkb> = data.try_decode_custom(ty)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Parsing the geometry is out of scope.
+//!
+//! [EWKB]: https://postgis.net/docs/using_postgis_dbmanagement.html#EWKB_EWKT
+
+use sqlx_core::decode::Decode;
+use sqlx_core::error::BoxDynError;
+use sqlx_core::type_info::TypeInfo;
+use sqlx_core::types::Type;
+
+use crate::{PgTypeInfo, PgValueFormat, PgValueRef, Postgres};
+
+/// The extended well-known binary (EWKB) representation of a PostGIS `geometry` or
+/// `geography` value, including the SRID if the value has one.
+///
+/// Both formats decode into the same bytes: the binary format is the EWKB itself, and the text
+/// format is the EWKB in hex, e.g. `0101000020E6100000000000000000F03F0000000000000040` for
+/// `SRID=4326;POINT(1 2)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ewkb(pub Vec<u8>);
+
+impl Ewkb {
+    /// The EWKB bytes of the value.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Type<Postgres> for Ewkb {
+    fn type_info() -> PgTypeInfo {
+        // created by the PostGIS extension, so it does not have a stable OID
+        PgTypeInfo::with_name("geometry")
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        matches!(ty.name(), "geometry" | "geography")
+    }
+}
+
+impl Decode<'_, Postgres> for Ewkb {
+    fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
+        match value.format() {
+            PgValueFormat::Binary => Ok(Self(value.as_bytes()?.to_vec())),
+            PgValueFormat::Text => Ok(Self(hex::decode(value.as_bytes()?)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::replication::{TupleData, Type as PgType};
+    use crate::types::Oid;
+
+    // SRID=4326;POINT(1 2)
+    const POINT: &str = "0101000020E6100000000000000000F03F0000000000000040";
+
+    fn postgis_type(name: &str) -> PgType {
+        PgType {
+            xid: None,
+            type_id: Oid(90123),
+            namespace: "public".into(),
+            name: name.into(),
+        }
+    }
+
+    #[test]
+    fn it_decodes_ewkb_in_both_formats() {
+        let ewkb = hex::decode(POINT).unwrap();
+
+        for name in ["geometry", "geography"] {
+            let ty = postgis_type(name);
+
+            let text = TupleData::Text(Bytes::from_static(POINT.as_bytes()));
+            assert_eq!(text.try_decode_custom::<Ewkb>(&ty).unwrap().0, ewkb);
+
+            let binary = TupleData::Binary(Bytes::from(ewkb.clone()));
+            assert_eq!(binary.try_decode_custom::<Ewkb>(&ty).unwrap().0, ewkb);
+
+            assert_eq!(
+                TupleData::Null
+                    .try_decode_custom::<Option<Ewkb>>(&ty)
+                    .unwrap(),
+                None
+            );
+        }
+
+        // other types are rejected, as is malformed hex
+        let text = TupleData::Text(Bytes::from_static(POINT.as_bytes()));
+        assert!(text
+            .try_decode_custom::<Ewkb>(&postgis_type("box2d"))
+            .is_err());
+
+        let text = TupleData::Text(Bytes::from_static(b"POINT(1 2)"));
+        assert!(text
+            .try_decode_custom::<Ewkb>(&postgis_type("geometry"))
+            .is_err());
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+
+use super::PrimaryKeepalive;
+
+/// The number of keepalives a [`KeepaliveHistory`] keeps by default.
+const DEFAULT_LEN: usize = 32;
+
+/// The most recent keepalives a replication stream received, as returned by
+/// [`LogicalReplicationStream::keepalive_history()`][super::LogicalReplicationStream::keepalive_history],
+/// e.g. to estimate how fast the server generates WAL.
+///
+/// Each keepalive has the end of WAL on the server and the server's clock when it was sent,
+/// so the history shows how the end of WAL advanced over time, independently of how fast the
+/// stream consumes it.
+#[derive(Debug, Clone)]
+pub struct KeepaliveHistory {
+    samples: VecDeque<PrimaryKeepalive>,
+    len: usize,
+}
+
+impl Default for KeepaliveHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_LEN)
+    }
+}
+
+impl KeepaliveHistory {
+    pub(super) fn new(len: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(len),
+            len,
+        }
+    }
+
+    /// The keepalives, from the oldest to the most recent.
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = &PrimaryKeepalive> {
+        self.samples.iter()
+    }
+
+    /// The rate at which the server generated WAL between the oldest and the most recent
+    /// keepalive, in bytes per second by the server's clock.
+    ///
+    /// Compared to the rate at which the stream [confirms][super::LogicalReplicationStream::confirmed_lsn]
+    /// WAL, this tells whether the consumer keeps up: if the server generates WAL faster, the
+    /// [lag][super::LogicalReplicationStream::lag] grows. The rate covers all WAL, including
+    /// the changes of tables that are not published.
+    ///
+    /// Returns `None` until two keepalives were received at different times.
+    pub fn wal_generation_rate(&self) -> Option<f64> {
+        let (first, last) = (self.samples.front()?, self.samples.back()?);
+
+        let micros = last.timestamp.checked_sub(first.timestamp)?;
+        if micros <= 0 {
+            return None;
+        }
+
+        let bytes = u64::from(last.wal_end).saturating_sub(u64::from(first.wal_end));
+
+        Some(bytes as f64 * 1_000_000.0 / micros as f64)
+    }
+
+    pub(super) fn record(&mut self, keepalive: PrimaryKeepalive) {
+        if self.len == 0 {
+            return;
+        }
+
+        if self.samples.len() == self.len {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(keepalive);
+    }
+
+    /// Keep at most `len` keepalives, dropping the oldest ones.
+    pub(super) fn set_len(&mut self, len: usize) {
+        self.len = len;
+
+        while self.samples.len() > len {
+            self.samples.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PgLsn;
+
+    fn keepalive(wal_end: u64, timestamp: i64) -> PrimaryKeepalive {
+        PrimaryKeepalive {
+            wal_end: PgLsn::from(wal_end),
+            timestamp,
+            reply_requested: false,
+        }
+    }
+
+    #[test]
+    fn it_computes_the_wal_generation_rate() {
+        let mut history = KeepaliveHistory::new(3);
+        assert_eq!(history.wal_generation_rate(), None);
+
+        history.record(keepalive(0x1000, 1_000_000));
+        assert_eq!(history.wal_generation_rate(), None);
+
+        // 8 KiB in half a second
+        history.record(keepalive(0x2000, 1_250_000));
+        history.record(keepalive(0x3000, 1_500_000));
+        assert_eq!(history.wal_generation_rate(), Some(16384.0));
+
+        // the oldest keepalive is dropped: 12 KiB in half a second
+        history.record(keepalive(0x6000, 1_750_000));
+        assert_eq!(history.samples().len(), 3);
+        assert_eq!(
+            history.samples().next().unwrap().wal_end,
+            PgLsn::from(0x2000)
+        );
+        assert_eq!(history.wal_generation_rate(), Some(32768.0));
+
+        history.set_len(1);
+        assert_eq!(history.samples().len(), 1);
+        assert_eq!(history.wal_generation_rate(), None);
+
+        history.set_len(0);
+        history.record(keepalive(0x7000, 2_000_000));
+        assert_eq!(history.samples().len(), 0);
+    }
+}
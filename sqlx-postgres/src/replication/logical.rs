@@ -0,0 +1,2043 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::SystemTime;
+
+use sqlx_core::bytes::{Buf, Bytes};
+
+use crate::error::Error;
+use crate::io::{BufExt, ProtocolDecode};
+use crate::types::{Oid, PgLsn};
+
+use super::message::timestamp_to_system_time;
+use super::quote_ident;
+use super::tuple::Tuples;
+
+/// A message of the `pgoutput` logical replication protocol, carried in the `data` of an
+/// [`XLogData`][super::XLogData] message.
+///
+/// <https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html>
+///
+/// Changes of sequences are not part of the protocol: the `pgoutput` of no released Postgres
+/// version sends them, as the `Sequence` message added during the development of Postgres 15
+/// was removed before the release, so there is no protocol version to decode it for. A message
+/// of an unknown type is a decode error, which a stream can skip or hand to a dead letter
+/// handler with its [`DecodeErrorPolicy`][super::DecodeErrorPolicy]. To keep sequences in sync
+/// downstream, e.g. before a cutover, copy their state from `pg_sequences` instead.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum LogicalReplication {
+    Begin(Begin),
+    Message(Message),
+    Commit(Commit),
+    Origin(Origin),
+    Relation(Relation),
+    Type(Type),
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    Truncate(Truncate),
+    StreamStart(StreamStart),
+    StreamStop,
+    StreamCommit(StreamCommit),
+    StreamAbort(StreamAbort),
+    BeginPrepare(BeginPrepare),
+    Prepare(Prepare),
+    CommitPrepared(CommitPrepared),
+    RollbackPrepared(RollbackPrepared),
+    StreamPrepare(Prepare),
+}
+
+impl LogicalReplication {
+    /// The name of the message type, e.g. for logs and metrics.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            LogicalReplication::Begin(_) => "Begin",
+            LogicalReplication::Message(_) => "Message",
+            LogicalReplication::Commit(_) => "Commit",
+            LogicalReplication::Origin(_) => "Origin",
+            LogicalReplication::Relation(_) => "Relation",
+            LogicalReplication::Type(_) => "Type",
+            LogicalReplication::Insert(_) => "Insert",
+            LogicalReplication::Update(_) => "Update",
+            LogicalReplication::Delete(_) => "Delete",
+            LogicalReplication::Truncate(_) => "Truncate",
+            LogicalReplication::StreamStart(_) => "StreamStart",
+            LogicalReplication::StreamStop => "StreamStop",
+            LogicalReplication::StreamCommit(_) => "StreamCommit",
+            LogicalReplication::StreamAbort(_) => "StreamAbort",
+            LogicalReplication::BeginPrepare(_) => "BeginPrepare",
+            LogicalReplication::Prepare(_) => "Prepare",
+            LogicalReplication::CommitPrepared(_) => "CommitPrepared",
+            LogicalReplication::RollbackPrepared(_) => "RollbackPrepared",
+            LogicalReplication::StreamPrepare(_) => "StreamPrepare",
+        }
+    }
+}
+
+/// A concise, single-line summary of the message, e.g. `INSERT rel=16385 cols=4` or
+/// `COMMIT @0/16B3748`.
+///
+/// Column values and message contents are left out, as they can be large or sensitive; use
+/// `Debug` to show them.
+impl Display for LogicalReplication {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LogicalReplication::Begin(begin) => {
+                write!(f, "BEGIN xid={} @{}", begin.xid, begin.final_lsn)
+            }
+            LogicalReplication::Message(message) => {
+                write!(
+                    f,
+                    "MESSAGE prefix={:?} len={} @{}",
+                    message.prefix,
+                    message.content.len(),
+                    message.lsn
+                )?;
+                if !message.transactional {
+                    f.write_str(" non-transactional")?;
+                }
+                fmt_xid(f, message.xid)
+            }
+            LogicalReplication::Commit(commit) => write!(f, "COMMIT @{}", commit.commit_lsn),
+            LogicalReplication::Origin(origin) => {
+                write!(f, "ORIGIN name={:?} @{}", origin.name, origin.commit_lsn)
+            }
+            LogicalReplication::Relation(relation) => {
+                write!(
+                    f,
+                    "RELATION rel={} name={}.{} cols={}",
+                    relation.relation_id.0,
+                    relation.namespace,
+                    relation.name,
+                    relation.columns.len()
+                )?;
+                fmt_xid(f, relation.xid)
+            }
+            LogicalReplication::Type(ty) => {
+                write!(
+                    f,
+                    "TYPE oid={} name={}.{}",
+                    ty.type_id.0, ty.namespace, ty.name
+                )?;
+                fmt_xid(f, ty.xid)
+            }
+            LogicalReplication::Insert(insert) => {
+                write!(
+                    f,
+                    "INSERT rel={} cols={}",
+                    insert.relation_id.0,
+                    insert.new_data.len()
+                )?;
+                fmt_xid(f, insert.xid)
+            }
+            LogicalReplication::Update(update) => {
+                write!(
+                    f,
+                    "UPDATE rel={} cols={}",
+                    update.relation_id.0,
+                    update.new_data.len()
+                )?;
+                fmt_old_row(f, update.key_data.is_some(), update.old_data.is_some())?;
+                fmt_xid(f, update.xid)
+            }
+            LogicalReplication::Delete(delete) => {
+                write!(f, "DELETE rel={}", delete.relation_id.0)?;
+                fmt_old_row(f, delete.key_data.is_some(), delete.old_data.is_some())?;
+                fmt_xid(f, delete.xid)
+            }
+            LogicalReplication::Truncate(truncate) => {
+                f.write_str("TRUNCATE rels=")?;
+                for (i, relation_id) in truncate.relation_ids.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", relation_id.0)?;
+                }
+                if truncate.cascade() {
+                    f.write_str(" cascade")?;
+                }
+                if truncate.restart_identity() {
+                    f.write_str(" restart_identity")?;
+                }
+                fmt_xid(f, truncate.xid)
+            }
+            LogicalReplication::StreamStart(start) => {
+                write!(f, "STREAM START xid={}", start.xid)?;
+                if start.first_segment {
+                    f.write_str(" first")?;
+                }
+                Ok(())
+            }
+            LogicalReplication::StreamStop => f.write_str("STREAM STOP"),
+            LogicalReplication::StreamCommit(commit) => {
+                write!(f, "STREAM COMMIT xid={} @{}", commit.xid, commit.commit_lsn)
+            }
+            LogicalReplication::StreamAbort(abort) => {
+                write!(f, "STREAM ABORT xid={} subxid={}", abort.xid, abort.subxid)?;
+                if let Some(lsn) = abort.abort_lsn {
+                    write!(f, " @{lsn}")?;
+                }
+                Ok(())
+            }
+            LogicalReplication::BeginPrepare(begin) => write!(
+                f,
+                "BEGIN PREPARE xid={} gid={:?} @{}",
+                begin.xid, begin.gid, begin.prepare_lsn
+            ),
+            LogicalReplication::Prepare(prepare) => write!(
+                f,
+                "PREPARE xid={} gid={:?} @{}",
+                prepare.xid, prepare.gid, prepare.prepare_lsn
+            ),
+            LogicalReplication::CommitPrepared(commit) => write!(
+                f,
+                "COMMIT PREPARED xid={} gid={:?} @{}",
+                commit.xid, commit.gid, commit.commit_lsn
+            ),
+            LogicalReplication::RollbackPrepared(rollback) => write!(
+                f,
+                "ROLLBACK PREPARED xid={} gid={:?} @{}",
+                rollback.xid, rollback.gid, rollback.rollback_end_lsn
+            ),
+            LogicalReplication::StreamPrepare(prepare) => write!(
+                f,
+                "STREAM PREPARE xid={} gid={:?} @{}",
+                prepare.xid, prepare.gid, prepare.prepare_lsn
+            ),
+        }
+    }
+}
+
+fn fmt_xid(f: &mut Formatter<'_>, xid: Option<u32>) -> fmt::Result {
+    match xid {
+        Some(xid) => write!(f, " xid={xid}"),
+        None => Ok(()),
+    }
+}
+
+fn fmt_old_row(f: &mut Formatter<'_>, key: bool, full: bool) -> fmt::Result {
+    match (key, full) {
+        (_, true) => f.write_str(" old=full"),
+        (true, false) => f.write_str(" old=key"),
+        (false, false) => Ok(()),
+    }
+}
+
+impl LogicalReplication {
+    /// The row as it exists after this change: the new row of an [`Insert`] or [`Update`].
+    ///
+    /// Returns `None` for a [`Delete`], which has no row after the change, and for all
+    /// messages that are not data changes.
+    pub fn row_after(&self) -> Option<&Tuples> {
+        match self {
+            LogicalReplication::Insert(insert) => Some(&insert.new_data),
+            LogicalReplication::Update(update) => Some(&update.new_data),
+            _ => None,
+        }
+    }
+
+    /// The identity of the row before this change: the key (or, with `REPLICA IDENTITY FULL`,
+    /// the whole old row) of an [`Update`] or [`Delete`].
+    ///
+    /// What is sent depends on the replica identity of the relation. For an [`Update`] that did
+    /// not change the key, no key is sent and this returns `None`; the key columns of
+    /// [`row_after()`][Self::row_after] then identify the row. With `REPLICA IDENTITY NOTHING`
+    /// (or `DEFAULT` without a primary key), this is `None` for a [`Delete`] as well.
+    pub fn row_key(&self) -> Option<&Tuples> {
+        match self {
+            LogicalReplication::Update(Update {
+                key_data, old_data, ..
+            })
+            | LogicalReplication::Delete(Delete {
+                key_data, old_data, ..
+            }) => key_data.as_ref().or(old_data.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The state needed to decode a `pgoutput` message that is not contained in the message itself.
+///
+/// When decoding a sequence of messages with [`decode_logical()`], pass each decoded message
+/// to [`observe()`][Self::observe] to keep track of streamed transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogicalDecodeContext {
+    pub(crate) proto_version: u32,
+    pub(crate) in_streamed_transaction: bool,
+    pub(crate) check_trailing_bytes: bool,
+    pub(crate) copy_threshold: usize,
+}
+
+impl LogicalDecodeContext {
+    /// Create a context for messages of the given `proto_version`, outside of a
+    /// streamed transaction.
+    pub fn new(proto_version: u32) -> Self {
+        Self {
+            proto_version,
+            in_streamed_transaction: false,
+            check_trailing_bytes: cfg!(debug_assertions),
+            copy_threshold: 0,
+        }
+    }
+
+    /// The `proto_version` requested from `pgoutput`.
+    pub fn proto_version(&self) -> u32 {
+        self.proto_version
+    }
+
+    /// `true` between a `StreamStart` and a `StreamStop` message; data messages are then
+    /// prefixed with the xid of the streamed transaction.
+    pub fn in_streamed_transaction(&self) -> bool {
+        self.in_streamed_transaction
+    }
+
+    /// Sets whether the next message is part of a block of changes of a streamed transaction.
+    pub fn set_in_streamed_transaction(&mut self, in_streamed_transaction: bool) {
+        self.in_streamed_transaction = in_streamed_transaction;
+    }
+
+    /// Sets whether to log a warning with the message type and the number of remaining bytes
+    /// if a message is not fully consumed by decoding it, which points to a decoding bug.
+    /// Enabled by default in debug builds.
+    pub fn set_check_trailing_bytes(&mut self, check_trailing_bytes: bool) {
+        self.check_trailing_bytes = check_trailing_bytes;
+    }
+
+    /// Sets the size up to which tuple values are copied out of the decoded message, so that
+    /// they don't keep the buffer of the message alive; see
+    /// [`LogicalReplicationStream::set_copy_threshold()`][super::LogicalReplicationStream::set_copy_threshold].
+    /// Defaults to `0`, i.e. values are never copied.
+    pub fn set_copy_threshold(&mut self, copy_threshold: usize) {
+        self.copy_threshold = copy_threshold;
+    }
+
+    /// Update the context after `message` has been decoded.
+    pub fn observe(&mut self, message: &LogicalReplication) {
+        match message {
+            LogicalReplication::StreamStart(_) => self.in_streamed_transaction = true,
+            LogicalReplication::StreamStop => self.in_streamed_transaction = false,
+            _ => {}
+        }
+    }
+}
+
+/// Decode a `pgoutput` message, e.g. the `data` of a captured [`XLogData`][super::XLogData]
+/// message, without a connection.
+///
+/// ```rust
+/// use sqlx::postgres::replication::{decode_logical, LogicalDecodeContext, LogicalReplication};
+///
+/// let mut ctx = LogicalDecodeContext::new(1);
+///
+/// let message = decode_logical(
+///     b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6",
+///     ctx,
+/// )?;
+/// ctx.observe(&message);
+///
+/// assert!(matches!(message, LogicalReplication::Begin(begin) if begin.xid == 742));
+/// # Ok::<(), sqlx::Error>(())
+/// ```
+pub fn decode_logical(
+    payload: &[u8],
+    ctx: LogicalDecodeContext,
+) -> Result<LogicalReplication, Error> {
+    LogicalReplication::decode_with(Bytes::copy_from_slice(payload), ctx)
+}
+
+/// The start of a transaction.
+///
+/// The layout is the same in all protocol versions; a streamed transaction (protocol version
+/// 2+) starts with a [`StreamStart`] instead, and is never prefixed by a `Begin`.
+#[derive(Debug, Clone, Copy)]
+pub struct Begin {
+    /// The final LSN of the transaction, i.e. the LSN of its commit record, the same as
+    /// [`Commit::commit_lsn`].
+    pub final_lsn: PgLsn,
+    /// The commit timestamp of the transaction, as microseconds since the Postgres epoch
+    /// (`2000-01-01`).
+    pub commit_timestamp: i64,
+    /// The xid of the transaction.
+    pub xid: u32,
+}
+
+impl Begin {
+    /// The commit time of the transaction.
+    pub fn commit_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.commit_timestamp)
+    }
+}
+
+/// A logical decoding message, written with `pg_logical_emit_message()`.
+///
+/// Only sent if the `messages` option is enabled.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// `true` if the message is transactional.
+    pub transactional: bool,
+    /// The LSN of the message.
+    pub lsn: PgLsn,
+    /// The prefix of the message.
+    pub prefix: String,
+    /// The content of the message.
+    pub content: Bytes,
+}
+
+/// The end of a transaction.
+///
+/// The layout is the same in all protocol versions; a streamed transaction ends with a
+/// [`StreamCommit`], which adds the xid, instead.
+///
+/// The message carries two positions: the LSN of the commit record, and the end of the
+/// transaction just past it. Only [`end_lsn`][Self::end_lsn] is the position to
+/// [confirm][super::LogicalReplicationStream::set_confirmed_lsn] once the transaction was
+/// processed: confirming the commit LSN instead leaves the commit record unconfirmed, so the
+/// transaction is streamed again after a restart.
+#[derive(Debug, Clone, Copy)]
+pub struct Commit {
+    /// The flags of the message; currently unused.
+    pub flags: CommitFlags,
+    /// The LSN of the commit record of the transaction, the same as [`Begin::final_lsn`].
+    ///
+    /// This is the position the transaction is ordered by, e.g. to compare it to
+    /// `pg_current_wal_lsn()`, but not the position to confirm.
+    pub commit_lsn: PgLsn,
+    /// The end LSN of the transaction, just past its commit record.
+    ///
+    /// This is the position to confirm, or to report as flushed, to acknowledge the
+    /// transaction, and to resume streaming after it.
+    pub end_lsn: PgLsn,
+    /// The commit timestamp of the transaction, as microseconds since the Postgres epoch
+    /// (`2000-01-01`).
+    pub commit_timestamp: i64,
+}
+
+impl Commit {
+    /// The commit time of the transaction.
+    pub fn commit_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.commit_timestamp)
+    }
+}
+
+/// The flags of a [`Commit`] or [`StreamCommit`] message.
+///
+/// No flags are currently defined by Postgres; the raw value is kept so that flags defined
+/// by future versions can be inspected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct CommitFlags(pub u8);
+
+impl CommitFlags {
+    /// The raw value of the flags.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// `true` if no flag is set.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// `true` if all bits of `bits` are set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// The origin of a transaction that was replicated from another node.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    /// The LSN of the commit on the origin server.
+    pub commit_lsn: PgLsn,
+    /// The name of the origin.
+    pub name: String,
+}
+
+/// The description of a table, sent before the first data message for that table, and
+/// again whenever the table definition changed.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The OID of the relation.
+    pub relation_id: Oid,
+    /// The namespace (schema) of the relation; empty for `pg_catalog`.
+    pub namespace: String,
+    /// The name of the relation.
+    pub name: String,
+    /// The replica identity setting of the relation.
+    pub replica_identity: ReplicaIdentity,
+    /// The columns of the relation.
+    pub columns: Vec<Column>,
+}
+
+impl Relation {
+    /// The quoted, schema-qualified name of the relation, e.g. for a statement that applies
+    /// a change to a copy of the table.
+    pub fn qualified_name(&self) -> String {
+        // an empty namespace stands for `pg_catalog`
+        let namespace = if self.namespace.is_empty() {
+            "pg_catalog"
+        } else {
+            &self.namespace
+        };
+
+        format!("{}.{}", quote_ident(namespace), quote_ident(&self.name))
+    }
+
+    /// Returns `true` if `other` describes the same relation with the same definition, i.e.
+    /// the same OID, name, replica identity and columns; the `xid` is not compared.
+    pub fn same_definition(&self, other: &Relation) -> bool {
+        self.relation_id == other.relation_id
+            && self.namespace == other.namespace
+            && self.name == other.name
+            && self.replica_identity == other.replica_identity
+            && self.columns == other.columns
+    }
+}
+
+/// The `REPLICA IDENTITY` setting of a relation, which determines what is sent as the "old"
+/// row of `Update` and `Delete` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicaIdentity {
+    /// The primary key, if any.
+    Default,
+    /// Nothing.
+    Nothing,
+    /// All columns.
+    Full,
+    /// The columns of a specific index.
+    Index,
+}
+
+/// A column of a [`Relation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    /// `1` if the column is part of the key, `0` otherwise.
+    ///
+    /// With `REPLICA IDENTITY INDEX`, these are the columns of the index, but the columns of
+    /// the relation, and thus of the key sent for updates and deletes, stay in the order of
+    /// the table.
+    pub flags: u8,
+    /// The name of the column.
+    pub name: String,
+    /// The OID of the data type of the column.
+    pub type_id: Oid,
+    /// The type modifier of the column (`atttypmod`); see [`modifier()`][Self::modifier].
+    pub type_modifier: i32,
+}
+
+/// The description of a data type, sent before the first data message with a column of a
+/// non-builtin type.
+#[derive(Debug, Clone)]
+pub struct Type {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The OID of the data type.
+    pub type_id: Oid,
+    /// The namespace (schema) of the data type; empty for `pg_catalog`.
+    pub namespace: String,
+    /// The name of the data type.
+    pub name: String,
+}
+
+/// An inserted row.
+#[derive(Debug, Clone)]
+pub struct Insert {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The OID of the relation, matching [`Relation::relation_id`].
+    pub relation_id: Oid,
+    /// The inserted row.
+    pub new_data: Tuples,
+}
+
+/// An updated row.
+#[derive(Debug, Clone)]
+pub struct Update {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The OID of the relation, matching [`Relation::relation_id`].
+    pub relation_id: Oid,
+    /// The key of the old row; only sent if the relation has `REPLICA IDENTITY INDEX`
+    /// (or `DEFAULT` with a primary key) and the key was changed.
+    ///
+    /// Like the other rows, it has a value for every column of the relation, in the order of
+    /// the table rather than of the index, with the columns that are not part of the key
+    /// `NULL`; the key columns are [flagged][Column::flags] in the [`Relation`].
+    pub key_data: Option<Tuples>,
+    /// The old row; only sent if the relation has `REPLICA IDENTITY FULL`.
+    ///
+    /// At most one of `key_data` and `old_data` is sent; see
+    /// [`identity_kind()`][Self::identity_kind].
+    pub old_data: Option<Tuples>,
+    /// The new row.
+    pub new_data: Tuples,
+}
+
+/// A deleted row.
+#[derive(Debug, Clone)]
+pub struct Delete {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The OID of the relation, matching [`Relation::relation_id`].
+    pub relation_id: Oid,
+    /// The key of the deleted row; sent if the relation has `REPLICA IDENTITY INDEX`
+    /// (or `DEFAULT` with a primary key).
+    ///
+    /// It has a value for every column of the relation, in the order of the table rather than
+    /// of the index, like [`Update::key_data`]; see [`key()`][Self::key].
+    pub key_data: Option<Tuples>,
+    /// The deleted row; sent if the relation has `REPLICA IDENTITY FULL`.
+    pub old_data: Option<Tuples>,
+}
+
+/// One or more truncated relations.
+#[derive(Debug, Clone)]
+pub struct Truncate {
+    /// The xid of the transaction; only set for messages in streamed transactions.
+    pub xid: Option<u32>,
+    /// The option bits of the `TRUNCATE` command.
+    pub options: u8,
+    /// The OIDs of the truncated relations.
+    pub relation_ids: Vec<Oid>,
+}
+
+impl Truncate {
+    /// `true` if `TRUNCATE ... CASCADE` was used.
+    pub fn cascade(&self) -> bool {
+        self.options & 1 != 0
+    }
+
+    /// `true` if `TRUNCATE ... RESTART IDENTITY` was used.
+    pub fn restart_identity(&self) -> bool {
+        self.options & 2 != 0
+    }
+
+    /// Resolve the truncated relations with `lookup`, usually
+    /// [`LogicalReplicationStream::relation()`][super::LogicalReplicationStream::relation], in
+    /// the order of [`relation_ids`][Self::relation_ids].
+    ///
+    /// Each OID is yielded along with its relation, or with `None` if `lookup` doesn't know
+    /// it, so that no truncated relation is silently skipped.
+    ///
+    /// ```rust
+    /// # fn example(
+    /// #     stream: &sqlx::postgres::replication::LogicalReplicationStream,
+    /// #     truncate: &sqlx::postgres::replication::Truncate,
+    /// # ) {
+    /// for (relation_id, relation) in truncate.relations(|id| stream.relation(id)) {
+    ///     match relation {
+    ///         Some(relation) => println!("truncated {}", relation.qualified_name()),
+    ///         None => println!("truncated unknown relation {relation_id:?}"),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn relations<'a, 'r: 'a, F>(
+        &'a self,
+        mut lookup: F,
+    ) -> impl Iterator<Item = (Oid, Option<&'r Relation>)> + 'a
+    where
+        F: FnMut(Oid) -> Option<&'r Relation> + 'a,
+    {
+        (self.relation_ids.iter()).map(move |&relation_id| (relation_id, lookup(relation_id)))
+    }
+
+    /// Resolve the truncated relations with `lookup` like with [`relations()`][Self::relations],
+    /// along with the options of the original statement, e.g. to generate the statement that
+    /// applies the change to a copy of the tables.
+    ///
+    /// Returns an error if a relation is unknown to `lookup`, instead of truncating fewer
+    /// tables than the original statement.
+    pub fn resolve<'r, F>(&self, lookup: F) -> Result<ResolvedTruncate<'r>, Error>
+    where
+        F: FnMut(Oid) -> Option<&'r Relation>,
+    {
+        let relations = self
+            .relations(lookup)
+            .map(|(relation_id, relation)| {
+                relation.ok_or_else(|| {
+                    err_protocol!(
+                        "truncated relation {} was not described by a Relation message",
+                        relation_id.0
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(ResolvedTruncate {
+            relations,
+            cascade: self.cascade(),
+            restart_identity: self.restart_identity(),
+        })
+    }
+
+    /// A `TRUNCATE` statement for the truncated relations, resolved with `lookup`; see
+    /// [`resolve()`][Self::resolve] and [`ResolvedTruncate::to_sql()`].
+    pub fn to_sql<'r, F>(&self, lookup: F) -> Result<String, Error>
+    where
+        F: FnMut(Oid) -> Option<&'r Relation>,
+    {
+        Ok(self.resolve(lookup)?.to_sql())
+    }
+}
+
+/// A [`Truncate`] whose relations were resolved, as returned by [`Truncate::resolve()`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ResolvedTruncate<'r> {
+    /// The truncated relations, in the order of [`Truncate::relation_ids`].
+    pub relations: Vec<&'r Relation>,
+    /// `true` if `TRUNCATE ... CASCADE` was used.
+    pub cascade: bool,
+    /// `true` if `TRUNCATE ... RESTART IDENTITY` was used.
+    pub restart_identity: bool,
+}
+
+impl ResolvedTruncate<'_> {
+    /// The [qualified names][Relation::qualified_name] of the truncated relations.
+    pub fn table_names(&self) -> Vec<String> {
+        (self.relations.iter())
+            .map(|relation| relation.qualified_name())
+            .collect()
+    }
+
+    /// A `TRUNCATE` statement for the relations, with `RESTART IDENTITY` and `CASCADE` as in
+    /// the original statement, e.g. `TRUNCATE "public"."users" RESTART IDENTITY CASCADE`.
+    ///
+    /// The relations are named by their [qualified name][Relation::qualified_name]. Like the
+    /// original statement, the generated one also truncates the partitions and inheritance
+    /// children of the tables on the database it is run on.
+    pub fn to_sql(&self) -> String {
+        let mut sql = format!("TRUNCATE {}", self.table_names().join(", "));
+
+        if self.restart_identity {
+            sql.push_str(" RESTART IDENTITY");
+        }
+
+        if self.cascade {
+            sql.push_str(" CASCADE");
+        }
+
+        sql
+    }
+}
+
+/// The start of a block of changes of a streamed (in-progress) transaction.
+///
+/// Only sent if the `streaming` option is enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamStart {
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// `true` if this is the first block of changes for this transaction.
+    pub first_segment: bool,
+}
+
+/// The commit of a streamed transaction.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamCommit {
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The flags of the message; currently unused.
+    pub flags: CommitFlags,
+    /// The LSN of the commit record of the transaction; see [`Commit::commit_lsn`].
+    pub commit_lsn: PgLsn,
+    /// The end LSN of the transaction, just past its commit record, which is the position to
+    /// confirm; see [`Commit::end_lsn`].
+    pub end_lsn: PgLsn,
+    /// The commit timestamp of the transaction, as microseconds since the Postgres epoch
+    /// (`2000-01-01`).
+    pub commit_timestamp: i64,
+}
+
+impl StreamCommit {
+    /// The commit time of the transaction.
+    pub fn commit_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.commit_timestamp)
+    }
+}
+
+/// The abort of a streamed transaction or one of its subtransactions.
+#[derive(Debug, Clone, Copy)]
+pub struct StreamAbort {
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The xid of the subtransaction; equal to `xid` for top-level aborts.
+    pub subxid: u32,
+    /// The LSN of the abort; only sent with `streaming = parallel` (protocol version 4+).
+    pub abort_lsn: Option<PgLsn>,
+    /// The abort timestamp, as microseconds since the Postgres epoch (`2000-01-01`); only
+    /// sent with `streaming = parallel` (protocol version 4+).
+    pub abort_timestamp: Option<i64>,
+}
+
+impl StreamAbort {
+    /// Returns `true` if the whole streamed transaction aborted, and `false` if only one of its
+    /// subtransactions did, e.g. on `ROLLBACK TO SAVEPOINT`.
+    ///
+    /// After a subtransaction abort, only the changes whose xid is [`subxid`][Self::subxid]
+    /// are to be discarded; the rest of the transaction is still streamed and may commit.
+    pub fn is_top_level(&self) -> bool {
+        self.xid == self.subxid
+    }
+}
+
+/// The start of a prepared transaction.
+///
+/// Only sent if the `two_phase` option is enabled.
+#[derive(Debug, Clone)]
+pub struct BeginPrepare {
+    /// The LSN of the prepare.
+    pub prepare_lsn: PgLsn,
+    /// The end LSN of the prepared transaction.
+    pub end_lsn: PgLsn,
+    /// The prepare timestamp, as microseconds since the Postgres epoch (`2000-01-01`).
+    pub prepare_timestamp: i64,
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The user-defined GID of the prepared transaction.
+    pub gid: String,
+}
+
+/// The flags of a [`Prepare`], `StreamPrepare`, [`CommitPrepared`] or [`RollbackPrepared`]
+/// message.
+///
+/// No flags are currently defined by Postgres; the raw value is kept so that flags defined
+/// by future versions can be inspected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PrepareFlags(pub u8);
+
+impl PrepareFlags {
+    /// The raw value of the flags.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// `true` if no flag is set.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// `true` if all bits of `bits` are set.
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+}
+
+/// A `PREPARE TRANSACTION`; also used for `StreamPrepare`.
+///
+/// Only sent if the `two_phase` option is enabled.
+#[derive(Debug, Clone)]
+pub struct Prepare {
+    /// The flags of the message; currently unused.
+    pub flags: PrepareFlags,
+    /// The LSN of the prepare.
+    pub prepare_lsn: PgLsn,
+    /// The end LSN of the prepared transaction.
+    pub end_lsn: PgLsn,
+    /// The prepare timestamp, as microseconds since the Postgres epoch (`2000-01-01`).
+    pub prepare_timestamp: i64,
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The user-defined GID of the prepared transaction.
+    pub gid: String,
+}
+
+/// A `COMMIT PREPARED`.
+///
+/// Only sent if the `two_phase` option is enabled.
+#[derive(Debug, Clone)]
+pub struct CommitPrepared {
+    /// The flags of the message; currently unused.
+    pub flags: PrepareFlags,
+    /// The LSN of the commit record; see [`Commit::commit_lsn`].
+    pub commit_lsn: PgLsn,
+    /// The end LSN of the commit of the prepared transaction, which is the position to
+    /// confirm; see [`Commit::end_lsn`].
+    pub end_lsn: PgLsn,
+    /// The commit timestamp, as microseconds since the Postgres epoch (`2000-01-01`).
+    pub commit_timestamp: i64,
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The user-defined GID of the prepared transaction.
+    pub gid: String,
+}
+
+/// A `ROLLBACK PREPARED`.
+///
+/// Only sent if the `two_phase` option is enabled.
+#[derive(Debug, Clone)]
+pub struct RollbackPrepared {
+    /// The flags of the message; currently unused.
+    pub flags: PrepareFlags,
+    /// The end LSN of the prepared transaction.
+    pub prepare_end_lsn: PgLsn,
+    /// The end LSN of the rollback of the prepared transaction.
+    pub rollback_end_lsn: PgLsn,
+    /// The prepare timestamp, as microseconds since the Postgres epoch (`2000-01-01`).
+    pub prepare_timestamp: i64,
+    /// The rollback timestamp, as microseconds since the Postgres epoch (`2000-01-01`).
+    pub rollback_timestamp: i64,
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The user-defined GID of the prepared transaction.
+    pub gid: String,
+}
+
+impl ProtocolDecode<'_, LogicalDecodeContext> for LogicalReplication {
+    fn decode_with(mut buf: Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let message = Self::decode_from(&mut buf, ctx)?;
+
+        // a decoder that reads too few bytes, e.g. because of a field order mistake, would
+        // otherwise go unnoticed
+        if ctx.check_trailing_bytes && buf.has_remaining() {
+            tracing::warn!(
+                message_type = message.kind(),
+                remaining = buf.remaining(),
+                "trailing bytes after logical replication message"
+            );
+        }
+
+        Ok(message)
+    }
+}
+
+impl LogicalReplication {
+    /// Decode the message at the front of `buf`, advancing it past the end of the message.
+    fn decode_from(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        if buf.is_empty() {
+            return Err(err_protocol!("empty logical replication message"));
+        }
+
+        let tag = buf.get_u8();
+
+        Ok(match tag {
+            b'B' => LogicalReplication::Begin(Begin::decode_body(buf, ctx)?),
+            b'M' => LogicalReplication::Message(Message::decode_body(buf, ctx)?),
+            b'C' => LogicalReplication::Commit(Commit::decode_body(buf, ctx)?),
+            b'O' => LogicalReplication::Origin(Origin::decode_body(buf, ctx)?),
+            b'R' => LogicalReplication::Relation(Relation::decode_body(buf, ctx)?),
+            b'Y' => LogicalReplication::Type(Type::decode_body(buf, ctx)?),
+            b'I' => LogicalReplication::Insert(Insert::decode_body(buf, ctx)?),
+            b'U' => LogicalReplication::Update(Update::decode_body(buf, ctx)?),
+            b'D' => LogicalReplication::Delete(Delete::decode_body(buf, ctx)?),
+            b'T' => LogicalReplication::Truncate(Truncate::decode_body(buf, ctx)?),
+            b'S' => LogicalReplication::StreamStart(StreamStart::decode_body(buf, ctx)?),
+            b'E' => LogicalReplication::StreamStop,
+            b'c' => LogicalReplication::StreamCommit(StreamCommit::decode_body(buf, ctx)?),
+            b'A' => LogicalReplication::StreamAbort(StreamAbort::decode_body(buf, ctx)?),
+            b'b' => LogicalReplication::BeginPrepare(BeginPrepare::decode_body(buf, ctx)?),
+            b'P' => LogicalReplication::Prepare(Prepare::decode_body(buf, ctx)?),
+            b'K' => LogicalReplication::CommitPrepared(CommitPrepared::decode_body(buf, ctx)?),
+            b'r' => LogicalReplication::RollbackPrepared(RollbackPrepared::decode_body(buf, ctx)?),
+            b'p' => LogicalReplication::StreamPrepare(Prepare::decode_body(buf, ctx)?),
+
+            _ => {
+                return Err(err_protocol!(
+                    "unknown logical replication message type: {:?}",
+                    tag as char
+                ));
+            }
+        })
+    }
+}
+
+/// Decodes the body of a message after its tag, advancing `buf` past the end of the body.
+trait DecodeBody: Sized {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error>;
+}
+
+/// Return an error if fewer than `len` bytes remain in `buf`.
+fn ensure_remaining(buf: &Bytes, len: usize, message: &str) -> Result<(), Error> {
+    if buf.remaining() < len {
+        return Err(err_protocol!(
+            "{}: expected at least {} more bytes, got {}",
+            message,
+            len,
+            buf.remaining()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decode the tuple data of a data message, adding the message and relation to the error.
+fn decode_tuples(
+    buf: &mut Bytes,
+    ctx: LogicalDecodeContext,
+    message: &str,
+    relation_id: Oid,
+) -> Result<Tuples, Error> {
+    Tuples::decode_from(buf, ctx.copy_threshold).map_err(|error| match error {
+        Error::Protocol(reason) => Error::Protocol(format!(
+            "failed decoding {message} for relation {}: {reason}",
+            relation_id.0
+        )),
+        error => error,
+    })
+}
+
+/// Describe the relation and, inside a streamed transaction, the xid of a data message for
+/// errors.
+fn describe_change(relation_id: Oid, xid: Option<u32>) -> String {
+    match xid {
+        Some(xid) => format!("relation {} in transaction {xid}", relation_id.0),
+        None => format!("relation {}", relation_id.0),
+    }
+}
+
+/// Read the xid that prefixes data messages inside a streamed transaction.
+fn get_stream_xid(
+    buf: &mut Bytes,
+    ctx: LogicalDecodeContext,
+    message: &str,
+) -> Result<Option<u32>, Error> {
+    // streaming needs protocol version 2, so a version 1 stream never has the prefix, even
+    // if the context was marked as inside a streamed transaction
+    if ctx.proto_version < 2 || !ctx.in_streamed_transaction {
+        return Ok(None);
+    }
+
+    ensure_remaining(buf, 4, message)?;
+
+    Ok(Some(buf.get_u32()))
+}
+
+fn get_oid(buf: &mut Bytes) -> Oid {
+    Oid(buf.get_u32())
+}
+
+impl DecodeBody for Begin {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 20, "Begin")?;
+
+        Ok(Begin {
+            final_lsn: PgLsn(buf.get_u64()),
+            commit_timestamp: buf.get_i64(),
+            xid: buf.get_u32(),
+        })
+    }
+}
+
+impl DecodeBody for Message {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Message")?;
+
+        ensure_remaining(buf, 9, "Message")?;
+
+        let transactional = buf.get_u8() & 1 != 0;
+        let lsn = PgLsn(buf.get_u64());
+        let prefix = buf.get_str_nul()?;
+
+        ensure_remaining(buf, 4, "Message")?;
+
+        let len = buf.get_u32() as usize;
+
+        ensure_remaining(buf, len, "Message")?;
+
+        Ok(Message {
+            xid,
+            transactional,
+            lsn,
+            prefix,
+            content: buf.split_to(len),
+        })
+    }
+}
+
+impl DecodeBody for Commit {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 25, "Commit")?;
+
+        Ok(Commit {
+            flags: CommitFlags(buf.get_u8()),
+            commit_lsn: PgLsn(buf.get_u64()),
+            end_lsn: PgLsn(buf.get_u64()),
+            commit_timestamp: buf.get_i64(),
+        })
+    }
+}
+
+impl DecodeBody for Origin {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 8, "Origin")?;
+
+        Ok(Origin {
+            commit_lsn: PgLsn(buf.get_u64()),
+            name: buf.get_str_nul()?,
+        })
+    }
+}
+
+impl DecodeBody for Relation {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Relation")?;
+
+        ensure_remaining(buf, 4, "Relation")?;
+
+        let relation_id = get_oid(buf);
+        let namespace = buf.get_str_nul()?;
+        let name = buf.get_str_nul()?;
+
+        ensure_remaining(buf, 3, "Relation")?;
+
+        let replica_identity = match buf.get_u8() {
+            b'd' => ReplicaIdentity::Default,
+            b'n' => ReplicaIdentity::Nothing,
+            b'f' => ReplicaIdentity::Full,
+            b'i' => ReplicaIdentity::Index,
+            other => {
+                return Err(err_protocol!(
+                    "unknown replica identity setting: {:?}",
+                    other as char
+                ));
+            }
+        };
+
+        let num_columns = buf.get_i16();
+        let mut columns = Vec::with_capacity(usize::try_from(num_columns).unwrap_or(0));
+
+        for _ in 0..num_columns {
+            ensure_remaining(buf, 1, "Relation")?;
+
+            let flags = buf.get_u8();
+            let name = buf.get_str_nul()?;
+
+            ensure_remaining(buf, 8, "Relation")?;
+
+            columns.push(Column {
+                flags,
+                name,
+                type_id: get_oid(buf),
+                type_modifier: buf.get_i32(),
+            });
+        }
+
+        Ok(Relation {
+            xid,
+            relation_id,
+            namespace,
+            name,
+            replica_identity,
+            columns,
+        })
+    }
+}
+
+impl DecodeBody for Type {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Type")?;
+
+        ensure_remaining(buf, 4, "Type")?;
+
+        Ok(Type {
+            xid,
+            type_id: get_oid(buf),
+            namespace: buf.get_str_nul()?,
+            name: buf.get_str_nul()?,
+        })
+    }
+}
+
+impl DecodeBody for Insert {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Insert")?;
+
+        ensure_remaining(buf, 5, "Insert")?;
+
+        let relation_id = get_oid(buf);
+
+        match buf.get_u8() {
+            b'N' => {}
+            other => {
+                return Err(err_protocol!(
+                    "Insert for {}: expected new tuple data ('N'), got {:?} (0x{:02X})",
+                    describe_change(relation_id, xid),
+                    other as char,
+                    other
+                ));
+            }
+        }
+
+        Ok(Insert {
+            xid,
+            relation_id,
+            new_data: decode_tuples(buf, ctx, "Insert", relation_id)?,
+        })
+    }
+}
+
+impl DecodeBody for Update {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Update")?;
+
+        ensure_remaining(buf, 5, "Update")?;
+
+        let relation_id = get_oid(buf);
+
+        let mut key_data = None;
+        let mut old_data = None;
+
+        // the new tuple is preceded by the key columns ('K') if the update changed the replica
+        // identity index, by the whole old row ('O') with `REPLICA IDENTITY FULL`, or by neither
+        let mut marker = buf.get_u8();
+
+        if let b'K' | b'O' = marker {
+            let tuples = decode_tuples(buf, ctx, "Update", relation_id)?;
+
+            if marker == b'K' {
+                key_data = Some(tuples);
+            } else {
+                old_data = Some(tuples);
+            }
+
+            ensure_remaining(buf, 1, "Update")?;
+            marker = buf.get_u8();
+        }
+
+        if marker != b'N' {
+            return Err(err_protocol!(
+                "Update for {}: expected new tuple data ('N'), got {:?} (0x{:02X})",
+                describe_change(relation_id, xid),
+                marker as char,
+                marker
+            ));
+        }
+
+        Ok(Update {
+            xid,
+            relation_id,
+            key_data,
+            old_data,
+            new_data: decode_tuples(buf, ctx, "Update", relation_id)?,
+        })
+    }
+}
+
+impl DecodeBody for Delete {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Delete")?;
+
+        ensure_remaining(buf, 5, "Delete")?;
+
+        let relation_id = get_oid(buf);
+
+        let mut key_data = None;
+        let mut old_data = None;
+
+        match buf.get_u8() {
+            b'K' => key_data = Some(decode_tuples(buf, ctx, "Delete", relation_id)?),
+            b'O' => old_data = Some(decode_tuples(buf, ctx, "Delete", relation_id)?),
+            other => {
+                return Err(err_protocol!(
+                    "Delete for {}: expected key ('K') or old ('O') tuple data, got {:?} (0x{:02X})",
+                    describe_change(relation_id, xid),
+                    other as char,
+                    other
+                ));
+            }
+        }
+
+        Ok(Delete {
+            xid,
+            relation_id,
+            key_data,
+            old_data,
+        })
+    }
+}
+
+impl DecodeBody for Truncate {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        let xid = get_stream_xid(buf, ctx, "Truncate")?;
+
+        ensure_remaining(buf, 5, "Truncate")?;
+
+        let num_relations = buf.get_u32() as usize;
+        let options = buf.get_u8();
+
+        ensure_remaining(buf, num_relations.saturating_mul(4), "Truncate")?;
+
+        let relation_ids = (0..num_relations).map(|_| get_oid(buf)).collect();
+
+        Ok(Truncate {
+            xid,
+            options,
+            relation_ids,
+        })
+    }
+}
+
+impl DecodeBody for StreamStart {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 5, "StreamStart")?;
+
+        Ok(StreamStart {
+            xid: buf.get_u32(),
+            first_segment: buf.get_u8() == 1,
+        })
+    }
+}
+
+impl DecodeBody for StreamCommit {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 29, "StreamCommit")?;
+
+        Ok(StreamCommit {
+            xid: buf.get_u32(),
+            flags: CommitFlags(buf.get_u8()),
+            commit_lsn: PgLsn(buf.get_u64()),
+            end_lsn: PgLsn(buf.get_u64()),
+            commit_timestamp: buf.get_i64(),
+        })
+    }
+}
+
+impl DecodeBody for StreamAbort {
+    fn decode_body(buf: &mut Bytes, ctx: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 8, "StreamAbort")?;
+
+        let xid = buf.get_u32();
+        let subxid = buf.get_u32();
+
+        // the abort LSN and timestamp are only sent with `streaming = parallel`
+        let (abort_lsn, abort_timestamp) = if ctx.proto_version >= 4 && buf.remaining() >= 16 {
+            (Some(PgLsn(buf.get_u64())), Some(buf.get_i64()))
+        } else {
+            (None, None)
+        };
+
+        Ok(StreamAbort {
+            xid,
+            subxid,
+            abort_lsn,
+            abort_timestamp,
+        })
+    }
+}
+
+impl DecodeBody for BeginPrepare {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 28, "BeginPrepare")?;
+
+        Ok(BeginPrepare {
+            prepare_lsn: PgLsn(buf.get_u64()),
+            end_lsn: PgLsn(buf.get_u64()),
+            prepare_timestamp: buf.get_i64(),
+            xid: buf.get_u32(),
+            gid: buf.get_str_nul()?,
+        })
+    }
+}
+
+impl DecodeBody for Prepare {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 29, "Prepare")?;
+
+        Ok(Prepare {
+            flags: PrepareFlags(buf.get_u8()),
+            prepare_lsn: PgLsn(buf.get_u64()),
+            end_lsn: PgLsn(buf.get_u64()),
+            prepare_timestamp: buf.get_i64(),
+            xid: buf.get_u32(),
+            gid: buf.get_str_nul()?,
+        })
+    }
+}
+
+impl DecodeBody for CommitPrepared {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 29, "CommitPrepared")?;
+
+        Ok(CommitPrepared {
+            flags: PrepareFlags(buf.get_u8()),
+            commit_lsn: PgLsn(buf.get_u64()),
+            end_lsn: PgLsn(buf.get_u64()),
+            commit_timestamp: buf.get_i64(),
+            xid: buf.get_u32(),
+            gid: buf.get_str_nul()?,
+        })
+    }
+}
+
+impl DecodeBody for RollbackPrepared {
+    fn decode_body(buf: &mut Bytes, _: LogicalDecodeContext) -> Result<Self, Error> {
+        ensure_remaining(buf, 37, "RollbackPrepared")?;
+
+        Ok(RollbackPrepared {
+            flags: PrepareFlags(buf.get_u8()),
+            prepare_end_lsn: PgLsn(buf.get_u64()),
+            rollback_end_lsn: PgLsn(buf.get_u64()),
+            prepare_timestamp: buf.get_i64(),
+            rollback_timestamp: buf.get_i64(),
+            xid: buf.get_u32(),
+            gid: buf.get_str_nul()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replication::TupleData;
+
+    const CTX: LogicalDecodeContext = LogicalDecodeContext {
+        proto_version: 1,
+        in_streamed_transaction: false,
+        check_trailing_bytes: true,
+        copy_threshold: 0,
+    };
+
+    const STREAM_CTX: LogicalDecodeContext = LogicalDecodeContext {
+        proto_version: 2,
+        in_streamed_transaction: true,
+        check_trailing_bytes: true,
+        copy_threshold: 0,
+    };
+
+    fn decode(data: &'static [u8], ctx: LogicalDecodeContext) -> LogicalReplication {
+        let mut buf = Bytes::from_static(data);
+        let message = LogicalReplication::decode_from(&mut buf, ctx).unwrap();
+
+        // every fixture must be consumed completely
+        assert!(
+            !buf.has_remaining(),
+            "{} trailing bytes after {}",
+            buf.remaining(),
+            message.kind()
+        );
+
+        message
+    }
+
+    #[test]
+    fn it_decodes_begin() {
+        const DATA: &[u8] = b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6";
+
+        let LogicalReplication::Begin(begin) = decode(DATA, CTX) else {
+            panic!("expected Begin");
+        };
+
+        assert_eq!(begin.final_lsn, PgLsn(0x015B_9A90));
+        assert_eq!(begin.commit_timestamp, 0x0002_B54A_7119_7E22);
+        assert_eq!(begin.xid, 742);
+    }
+
+    #[test]
+    fn it_tolerates_trailing_bytes() {
+        // a Begin message with an extra byte, which is only logged
+        const DATA: &[u8] =
+            b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6\0";
+
+        let mut buf = Bytes::from_static(DATA);
+        let message = LogicalReplication::decode_from(&mut buf, CTX).unwrap();
+
+        assert!(matches!(message, LogicalReplication::Begin(begin) if begin.xid == 742));
+        assert_eq!(buf.remaining(), 1);
+
+        assert!(LogicalReplication::decode_with(Bytes::from_static(DATA), CTX).is_ok());
+    }
+
+    #[test]
+    fn it_decodes_commit() {
+        const DATA: &[u8] =
+            b"C\0\0\0\0\0\x01\x5B\x9A\x90\0\0\0\0\x01\x5B\x9A\xC0\0\x02\xB5\x4A\x71\x19\x7E\x22";
+
+        let LogicalReplication::Commit(commit) = decode(DATA, CTX) else {
+            panic!("expected Commit");
+        };
+
+        assert!(commit.flags.is_empty());
+        assert_eq!(commit.commit_lsn, PgLsn(0x015B_9A90));
+        assert_eq!(commit.end_lsn, PgLsn(0x015B_9AC0));
+        assert_eq!(commit.commit_timestamp, 0x0002_B54A_7119_7E22);
+    }
+
+    #[test]
+    fn it_decodes_prepare() {
+        const DATA: &[u8] = b"P\x02\0\0\0\0\x01\x5B\x9A\x90\0\0\0\0\x01\x5B\x9A\xC0\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6gid\0";
+
+        let LogicalReplication::Prepare(prepare) = decode(DATA, CTX) else {
+            panic!("expected Prepare");
+        };
+
+        assert_eq!(prepare.flags, PrepareFlags(2));
+        assert_eq!(prepare.flags.bits(), 2);
+        assert!(!prepare.flags.is_empty());
+        assert!(prepare.flags.contains(2));
+        assert!(!prepare.flags.contains(1));
+        assert_eq!(prepare.prepare_lsn, PgLsn(0x015B_9A90));
+        assert_eq!(prepare.end_lsn, PgLsn(0x015B_9AC0));
+        assert_eq!(prepare.xid, 742);
+        assert_eq!(prepare.gid, "gid");
+    }
+
+    #[test]
+    fn it_decodes_relation() {
+        const DATA: &[u8] = b"R\0\0\x40\x01public\0users\0d\0\x02\x01id\0\0\0\0\x17\xFF\xFF\xFF\xFF\0name\0\0\0\x04\x13\0\0\0\x44";
+
+        let LogicalReplication::Relation(relation) = decode(DATA, CTX) else {
+            panic!("expected Relation");
+        };
+
+        assert_eq!(relation.xid, None);
+        assert_eq!(relation.relation_id, Oid(16385));
+        assert_eq!(relation.namespace, "public");
+        assert_eq!(relation.name, "users");
+        assert_eq!(relation.replica_identity, ReplicaIdentity::Default);
+        assert_eq!(relation.columns.len(), 2);
+        assert_eq!(relation.columns[0].flags, 1);
+        assert_eq!(relation.columns[0].name, "id");
+        assert_eq!(relation.columns[0].type_id, Oid(23));
+        assert_eq!(relation.columns[0].type_modifier, -1);
+        assert_eq!(relation.columns[1].name, "name");
+        assert_eq!(relation.columns[1].type_id, Oid(1043));
+        assert_eq!(relation.columns[1].type_modifier, 68);
+    }
+
+    #[test]
+    fn it_decodes_insert() {
+        const DATA: &[u8] = b"I\0\0\x40\x01N\0\x02t\0\0\0\x011t\0\0\0\x03foo";
+
+        let LogicalReplication::Insert(insert) = decode(DATA, CTX) else {
+            panic!("expected Insert");
+        };
+
+        assert_eq!(insert.xid, None);
+        assert_eq!(insert.relation_id, Oid(16385));
+        assert_eq!(insert.new_data[0].as_str(), Some("1"));
+        assert_eq!(insert.new_data[1].as_str(), Some("foo"));
+    }
+
+    #[test]
+    fn it_reports_the_message_and_relation_of_invalid_tuple_data() {
+        const DATA: &[u8] = b"I\0\0\x40\x01N\0\x04nnnz";
+
+        let error = LogicalReplication::decode_with(Bytes::from_static(DATA), CTX).unwrap_err();
+
+        assert!(
+            error.to_string().contains(
+                "failed decoding Insert for relation 16385: \
+                 unknown tuple data marker 0x7A at column 3"
+            ),
+            "unexpected error: {error}"
+        );
+    }
+
+    #[test]
+    fn it_reports_unexpected_tuple_markers() {
+        // an Insert of a streamed transaction, decoded without the xid
+        const INSERT: &[u8] = b"I\0\0\x02\xE6\0\0\x40\x01N\0\x01n";
+        // an Update whose new tuple is marked with 'X'
+        const UPDATE: &[u8] = b"U\0\0\x02\xE6\0\0\x40\x01X\0\x01n";
+
+        let error = LogicalReplication::decode_with(Bytes::from_static(INSERT), CTX).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Insert for relation 742: expected new tuple data ('N'), got '\\0' (0x00)"
+            ),
+            "unexpected error: {error}"
+        );
+
+        let error =
+            LogicalReplication::decode_with(Bytes::from_static(UPDATE), STREAM_CTX).unwrap_err();
+        assert!(
+            error.to_string().contains(
+                "Update for relation 16385 in transaction 742: \
+                 expected new tuple data ('N'), got 'X' (0x58)"
+            ),
+            "unexpected error: {error}"
+        );
+    }
+
+    #[test]
+    fn it_decodes_streamed_insert() {
+        const DATA: &[u8] = b"I\0\0\x02\xE6\0\0\x40\x01N\0\x01n";
+
+        let LogicalReplication::Insert(insert) = decode(DATA, STREAM_CTX) else {
+            panic!("expected Insert");
+        };
+
+        assert_eq!(insert.xid, Some(742));
+        assert_eq!(insert.relation_id, Oid(16385));
+        assert_eq!(insert.new_data[0], TupleData::Null);
+    }
+
+    #[test]
+    fn it_decodes_streamed_update_and_delete() {
+        const UPDATE: &[u8] = b"U\0\0\x02\xE6\0\0\x40\x01N\0\x01t\0\0\0\x01b";
+        const DELETE: &[u8] = b"D\0\0\x02\xE6\0\0\x40\x01K\0\x01t\0\0\0\x011";
+
+        let LogicalReplication::Update(update) = decode(UPDATE, STREAM_CTX) else {
+            panic!("expected Update");
+        };
+
+        assert_eq!(update.xid, Some(742));
+        assert_eq!(update.relation_id, Oid(16385));
+        assert_eq!(update.new_data[0].as_str(), Some("b"));
+
+        let LogicalReplication::Delete(delete) = decode(DELETE, STREAM_CTX) else {
+            panic!("expected Delete");
+        };
+
+        assert_eq!(delete.xid, Some(742));
+        assert_eq!(delete.relation_id, Oid(16385));
+        assert_eq!(delete.key_data.unwrap()[0].as_str(), Some("1"));
+
+        // outside of a streamed transaction, the xid is not sent even with protocol version 2
+        let ctx = LogicalDecodeContext {
+            in_streamed_transaction: false,
+            ..STREAM_CTX
+        };
+
+        let LogicalReplication::Insert(insert) = decode(b"I\0\0\x40\x01N\0\x01n", ctx) else {
+            panic!("expected Insert");
+        };
+
+        assert_eq!(insert.xid, None);
+        assert_eq!(insert.relation_id, Oid(16385));
+    }
+
+    #[test]
+    fn it_decodes_update() {
+        const DATA: &[u8] = b"U\0\0\x40\x01O\0\x01t\0\0\0\x01aN\0\x01t\0\0\0\x01b";
+
+        let LogicalReplication::Update(update) = decode(DATA, CTX) else {
+            panic!("expected Update");
+        };
+
+        assert!(update.key_data.is_none());
+        assert_eq!(update.old_data.unwrap()[0].as_str(), Some("a"));
+        assert_eq!(update.new_data[0].as_str(), Some("b"));
+    }
+
+    // captured from PostgreSQL 15 for a table `(id INT, name TEXT)` and `proto_version` 1
+
+    #[test]
+    fn it_decodes_update_with_default_identity() {
+        // `UPDATE ... SET name = 'b'` with a primary key
+        const DATA: &[u8] = b"U\0\0G\x22N\0\x02t\0\0\0\x011t\0\0\0\x01b";
+
+        let LogicalReplication::Update(update) = decode(DATA, CTX) else {
+            panic!("expected Update");
+        };
+
+        assert_eq!(update.relation_id, Oid(0x4722));
+        assert!(update.key_data.is_none());
+        assert!(update.old_data.is_none());
+        assert_eq!(update.new_data[0].as_str(), Some("1"));
+        assert_eq!(update.new_data[1].as_str(), Some("b"));
+    }
+
+    #[test]
+    fn it_decodes_update_with_index_identity() {
+        // `UPDATE ... SET id = 2, name = 'b'` with `REPLICA IDENTITY USING INDEX` on `id`
+        const DATA: &[u8] = b"U\0\0G)K\0\x02t\0\0\0\x011nN\0\x02t\0\0\0\x012t\0\0\0\x01b";
+
+        let LogicalReplication::Update(update) = decode(DATA, CTX) else {
+            panic!("expected Update");
+        };
+
+        let key_data = update.key_data.unwrap();
+        assert_eq!(key_data[0].as_str(), Some("1"));
+        assert!(key_data[1].is_null());
+        assert!(update.old_data.is_none());
+        assert_eq!(update.new_data[0].as_str(), Some("2"));
+        assert_eq!(update.new_data[1].as_str(), Some("b"));
+
+        // the key is only sent if it changed
+        const UNCHANGED_KEY: &[u8] = b"U\0\0G)N\0\x02t\0\0\0\x012t\0\0\0\x01c";
+
+        let LogicalReplication::Update(update) = decode(UNCHANGED_KEY, CTX) else {
+            panic!("expected Update");
+        };
+
+        assert!(update.key_data.is_none());
+        assert!(update.old_data.is_none());
+        assert_eq!(update.new_data[1].as_str(), Some("c"));
+    }
+
+    #[test]
+    fn it_decodes_update_with_full_identity() {
+        // `UPDATE ... SET name = 'b'` with `REPLICA IDENTITY FULL`
+        const DATA: &[u8] =
+            b"U\0\0G/O\0\x02t\0\0\0\x011t\0\0\0\x01aN\0\x02t\0\0\0\x011t\0\0\0\x01b";
+
+        let LogicalReplication::Update(update) = decode(DATA, CTX) else {
+            panic!("expected Update");
+        };
+
+        assert!(update.key_data.is_none());
+        let old_data = update.old_data.unwrap();
+        assert_eq!(old_data[0].as_str(), Some("1"));
+        assert_eq!(old_data[1].as_str(), Some("a"));
+        assert_eq!(update.new_data[1].as_str(), Some("b"));
+    }
+
+    #[test]
+    fn it_rejects_update_with_key_and_old_data() {
+        const DATA: &[u8] = b"U\0\0G/K\0\x01t\0\0\0\x011O\0\x01t\0\0\0\x011N\0\x01t\0\0\0\x011";
+
+        assert!(LogicalReplication::decode_with(Bytes::from_static(DATA), CTX).is_err());
+
+        // no new tuple after the old one
+        const TRUNCATED: &[u8] = b"U\0\0G/O\0\x01t\0\0\0\x011";
+
+        assert!(LogicalReplication::decode_with(Bytes::from_static(TRUNCATED), CTX).is_err());
+    }
+
+    #[test]
+    fn it_decodes_delete() {
+        const DATA: &[u8] = b"D\0\0\x40\x01K\0\x02t\0\0\0\x011n";
+
+        let LogicalReplication::Delete(delete) = decode(DATA, CTX) else {
+            panic!("expected Delete");
+        };
+
+        assert!(delete.old_data.is_none());
+        assert_eq!(delete.key_data.unwrap()[0].as_str(), Some("1"));
+    }
+
+    #[test]
+    fn it_returns_rows_after_and_keys() {
+        let insert = decode(b"I\0\0\x40\x01N\0\x01t\0\0\0\x011", CTX);
+        assert_eq!(insert.row_after().unwrap()[0].as_str(), Some("1"));
+        assert!(insert.row_key().is_none());
+
+        // the key of the row was not changed
+        let update = decode(b"U\0\0\x40\x01N\0\x01t\0\0\0\x012", CTX);
+        assert_eq!(update.row_after().unwrap()[0].as_str(), Some("2"));
+        assert!(update.row_key().is_none());
+
+        let update = decode(b"U\0\0\x40\x01K\0\x01t\0\0\0\x011N\0\x01t\0\0\0\x012", CTX);
+        assert_eq!(update.row_after().unwrap()[0].as_str(), Some("2"));
+        assert_eq!(update.row_key().unwrap()[0].as_str(), Some("1"));
+
+        let delete = decode(b"D\0\0\x40\x01O\0\x01t\0\0\0\x011", CTX);
+        assert!(delete.row_after().is_none());
+        assert_eq!(delete.row_key().unwrap()[0].as_str(), Some("1"));
+
+        let begin = decode(
+            b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6",
+            CTX,
+        );
+        assert!(begin.row_after().is_none());
+        assert!(begin.row_key().is_none());
+    }
+
+    #[test]
+    fn it_displays_messages() {
+        let cases: [(&[u8], LogicalDecodeContext, &str); 5] = [
+            (
+                b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6",
+                CTX,
+                "BEGIN xid=742 @0/15B9A90",
+            ),
+            (
+                b"I\0\0\x40\x01N\0\x02t\0\0\0\x011t\0\0\0\x03foo",
+                CTX,
+                "INSERT rel=16385 cols=2",
+            ),
+            (
+                b"I\0\0\x02\xE6\0\0\x40\x01N\0\x01n",
+                STREAM_CTX,
+                "INSERT rel=16385 cols=1 xid=742",
+            ),
+            (
+                b"D\0\0\x40\x01K\0\x02t\0\0\0\x011n",
+                CTX,
+                "DELETE rel=16385 old=key",
+            ),
+            (
+                b"T\0\0\0\x02\x03\0\0\x40\x01\0\0\x40\x02",
+                CTX,
+                "TRUNCATE rels=16385,16386 cascade restart_identity",
+            ),
+        ];
+
+        for (data, ctx, expected) in cases {
+            assert_eq!(decode(data, ctx).to_string(), expected);
+        }
+
+        // tuple data is not shown
+        let insert = decode(b"I\0\0\x40\x01N\0\x01t\0\0\0\x06secret", CTX);
+        assert!(!insert.to_string().contains("secret"));
+    }
+
+    #[test]
+    fn it_decodes_truncate() {
+        const DATA: &[u8] = b"T\0\0\0\x02\x03\0\0\x40\x01\0\0\x40\x02";
+
+        let LogicalReplication::Truncate(truncate) = decode(DATA, CTX) else {
+            panic!("expected Truncate");
+        };
+
+        assert!(truncate.cascade());
+        assert!(truncate.restart_identity());
+        assert_eq!(truncate.relation_ids, [Oid(16385), Oid(16386)]);
+    }
+
+    #[test]
+    fn it_resolves_truncated_relations() {
+        let relation = |relation_id, namespace: &str, name: &str| Relation {
+            xid: None,
+            relation_id,
+            namespace: namespace.into(),
+            name: name.into(),
+            replica_identity: ReplicaIdentity::Default,
+            columns: Vec::new(),
+        };
+
+        let relations = [
+            relation(Oid(16385), "public", "users"),
+            relation(Oid(16386), "my \"schema\"", "Orders"),
+        ];
+        let lookup = |id| relations.iter().find(|relation| relation.relation_id == id);
+
+        let mut truncate = Truncate {
+            xid: None,
+            options: 0,
+            relation_ids: vec![Oid(16385), Oid(16386)],
+        };
+
+        let resolved: Vec<_> = truncate
+            .relations(lookup)
+            .map(|(id, relation)| (id, relation.map(|relation| relation.name.as_str())))
+            .collect();
+        assert_eq!(
+            resolved,
+            [(Oid(16385), Some("users")), (Oid(16386), Some("Orders"))]
+        );
+
+        assert_eq!(
+            truncate.to_sql(lookup).unwrap(),
+            r#"TRUNCATE "public"."users", "my ""schema"""."Orders""#
+        );
+
+        truncate.options = 3;
+        assert_eq!(
+            truncate.to_sql(lookup).unwrap(),
+            r#"TRUNCATE "public"."users", "my ""schema"""."Orders" RESTART IDENTITY CASCADE"#
+        );
+
+        // an unknown relation is reported, not skipped
+        truncate.relation_ids.push(Oid(16387));
+
+        let resolved: Vec<_> = truncate.relations(lookup).collect();
+        assert_eq!(resolved.len(), 3);
+        assert_eq!(resolved[2].0, Oid(16387));
+        assert!(resolved[2].1.is_none());
+
+        let error = truncate.to_sql(lookup).unwrap_err().to_string();
+        assert!(error.contains("16387"), "{error}");
+    }
+
+    #[test]
+    fn it_renders_resolved_truncates() {
+        // `TRUNCATE users, orders CASCADE`
+        const DATA: &[u8] = b"T\0\0\0\x02\x01\0\0\x40\x01\0\0\x40\x02";
+
+        let LogicalReplication::Truncate(truncate) = decode(DATA, CTX) else {
+            panic!("expected Truncate");
+        };
+
+        let relations = [Oid(16385), Oid(16386)].map(|relation_id| Relation {
+            xid: None,
+            relation_id,
+            namespace: "public".into(),
+            name: format!("table_{}", relation_id.0),
+            replica_identity: ReplicaIdentity::Default,
+            columns: Vec::new(),
+        });
+        let lookup = |id| relations.iter().find(|relation| relation.relation_id == id);
+
+        let resolved = truncate.resolve(lookup).unwrap();
+        assert!(resolved.cascade);
+        assert!(!resolved.restart_identity);
+        assert_eq!(
+            resolved.table_names(),
+            [r#""public"."table_16385""#, r#""public"."table_16386""#]
+        );
+        assert_eq!(
+            resolved.to_sql(),
+            r#"TRUNCATE "public"."table_16385", "public"."table_16386" CASCADE"#
+        );
+        assert_eq!(truncate.to_sql(lookup).unwrap(), resolved.to_sql());
+
+        // an unknown relation is reported, not skipped
+        assert!(truncate.resolve(|_| None).is_err());
+    }
+
+    #[test]
+    fn it_decodes_message() {
+        const DATA: &[u8] = b"M\x01\0\0\0\0\x01\x5B\x9A\x90app\0\0\0\0\x05hello";
+
+        let LogicalReplication::Message(message) = decode(DATA, CTX) else {
+            panic!("expected Message");
+        };
+
+        assert!(message.transactional);
+        assert_eq!(message.lsn, PgLsn(0x015B_9A90));
+        assert_eq!(message.prefix, "app");
+        assert_eq!(&message.content[..], b"hello");
+    }
+
+    #[test]
+    fn it_tracks_streamed_transactions() {
+        let mut ctx = LogicalDecodeContext::new(2);
+
+        let start = decode(b"S\0\0\x02\xE6\x01", ctx);
+        ctx.observe(&start);
+        assert!(ctx.in_streamed_transaction);
+
+        let LogicalReplication::StreamStart(start) = start else {
+            panic!("expected StreamStart");
+        };
+        assert_eq!(start.xid, 742);
+        assert!(start.first_segment);
+
+        let stop = decode(b"E", ctx);
+        ctx.observe(&stop);
+        assert!(!ctx.in_streamed_transaction);
+    }
+
+    #[test]
+    fn it_tells_subtransaction_aborts_apart() {
+        let ctx = LogicalDecodeContext::new(2);
+
+        let LogicalReplication::StreamAbort(top_level) = decode(b"A\0\0\x02\xE6\0\0\x02\xE6", ctx)
+        else {
+            panic!("expected StreamAbort");
+        };
+        assert!(top_level.is_top_level());
+
+        let LogicalReplication::StreamAbort(savepoint) = decode(b"A\0\0\x02\xE6\0\0\x02\xE7", ctx)
+        else {
+            panic!("expected StreamAbort");
+        };
+        assert_eq!((savepoint.xid, savepoint.subxid), (742, 743));
+        assert!(!savepoint.is_top_level());
+    }
+
+    #[test]
+    fn it_decodes_captured_payloads() {
+        let mut ctx = LogicalDecodeContext::new(2);
+
+        for payload in [
+            &b"S\0\0\x02\xE6\x01"[..],
+            b"I\0\0\x02\xE6\0\0\x40\x01N\0\x01n",
+            b"E",
+        ] {
+            let message = decode_logical(payload, ctx).unwrap();
+            ctx.observe(&message);
+
+            if let LogicalReplication::Insert(insert) = message {
+                assert_eq!(insert.xid, Some(742));
+            }
+        }
+
+        assert!(!ctx.in_streamed_transaction());
+    }
+
+    #[test]
+    fn it_decodes_boundary_messages_of_every_protocol_version() {
+        const BEGIN: &[u8] = b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6";
+        const COMMIT: &[u8] =
+            b"C\0\0\0\0\0\x01\x5B\x9A\x90\0\0\0\0\x01\x5B\x9A\xC0\0\x02\xB5\x4A\x71\x19\x7E\x22";
+        const INSERT: &[u8] = b"I\0\0\x40\x01N\0\x01n";
+        const STREAM_START: &[u8] = b"S\0\0\x02\xE6\x01";
+        const STREAMED_INSERT: &[u8] = b"I\0\0\x02\xE6\0\0\x40\x01N\0\x01n";
+        const STREAM_COMMIT: &[u8] = b"c\0\0\x02\xE6\0\0\0\0\0\x01\x5B\x9A\x90\0\0\0\0\x01\x5B\x9A\xC0\0\x02\xB5\x4A\x71\x19\x7E\x22";
+        const STREAM_ABORT: &[u8] = b"A\0\0\x02\xE6\0\0\x02\xE7";
+        // with `streaming = parallel`
+        const PARALLEL_STREAM_ABORT: &[u8] =
+            b"A\0\0\x02\xE6\0\0\x02\xE7\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22";
+
+        for proto_version in 1..=4 {
+            let mut ctx = LogicalDecodeContext::new(proto_version);
+
+            let LogicalReplication::Begin(begin) = decode(BEGIN, ctx) else {
+                panic!("expected Begin for version {proto_version}");
+            };
+            assert_eq!(begin.final_lsn, PgLsn(0x015B_9A90));
+            assert_eq!(begin.commit_timestamp, 0x0002_B54A_7119_7E22);
+            assert_eq!(begin.xid, 742);
+
+            let LogicalReplication::Insert(insert) = decode(INSERT, ctx) else {
+                panic!("expected Insert for version {proto_version}");
+            };
+            assert_eq!((insert.xid, insert.relation_id), (None, Oid(16385)));
+
+            let LogicalReplication::Commit(commit) = decode(COMMIT, ctx) else {
+                panic!("expected Commit for version {proto_version}");
+            };
+            assert!(commit.flags.is_empty());
+            assert_eq!(commit.commit_lsn, PgLsn(0x015B_9A90));
+            assert_eq!(commit.end_lsn, PgLsn(0x015B_9AC0));
+            assert_eq!(commit.commit_timestamp, 0x0002_B54A_7119_7E22);
+
+            if proto_version == 1 {
+                // a version 1 stream has no xid prefixes to shift the fields
+                ctx.set_in_streamed_transaction(true);
+
+                let LogicalReplication::Insert(insert) = decode(INSERT, ctx) else {
+                    panic!("expected Insert for version {proto_version}");
+                };
+                assert_eq!((insert.xid, insert.relation_id), (None, Oid(16385)));
+
+                continue;
+            }
+
+            let start = decode(STREAM_START, ctx);
+            ctx.observe(&start);
+
+            let LogicalReplication::Insert(insert) = decode(STREAMED_INSERT, ctx) else {
+                panic!("expected Insert for version {proto_version}");
+            };
+            assert_eq!((insert.xid, insert.relation_id), (Some(742), Oid(16385)));
+
+            ctx.observe(&decode(b"E", ctx));
+
+            let LogicalReplication::StreamCommit(commit) = decode(STREAM_COMMIT, ctx) else {
+                panic!("expected StreamCommit for version {proto_version}");
+            };
+            assert_eq!(commit.xid, 742);
+            assert_eq!(commit.commit_lsn, PgLsn(0x015B_9A90));
+            assert_eq!(commit.end_lsn, PgLsn(0x015B_9AC0));
+            assert_eq!(commit.commit_timestamp, 0x0002_B54A_7119_7E22);
+
+            let LogicalReplication::StreamAbort(abort) = decode(STREAM_ABORT, ctx) else {
+                panic!("expected StreamAbort for version {proto_version}");
+            };
+            assert_eq!((abort.xid, abort.subxid), (742, 743));
+            assert_eq!((abort.abort_lsn, abort.abort_timestamp), (None, None));
+
+            if proto_version >= 4 {
+                let LogicalReplication::StreamAbort(abort) = decode(PARALLEL_STREAM_ABORT, ctx)
+                else {
+                    panic!("expected StreamAbort for version {proto_version}");
+                };
+                assert_eq!((abort.xid, abort.subxid), (742, 743));
+                assert_eq!(abort.abort_lsn, Some(PgLsn(0x015B_9A90)));
+                assert_eq!(abort.abort_timestamp, Some(0x0002_B54A_7119_7E22));
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_malformed_messages() {
+        for data in [
+            &b""[..],
+            b"B\0\0",
+            b"C\0",
+            b"I\0\0\x40\x01X\0\0",
+            b"D\0\0\x40\x01N\0\0",
+            b"R\0\0\x40\x01public\0users\0x\0\0",
+            b"Z",
+        ] {
+            assert!(LogicalReplication::decode_with(Bytes::from_static(data), CTX).is_err());
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+
+use futures_core::stream::Stream;
+use futures_util::{future, stream, FutureExt};
+
+use super::transaction::TransactionBuffer;
+use super::{
+    LogicalReplication, LogicalReplicationStream, PgReplicationConnection, ReplicatedTransaction,
+    ReplicationError,
+};
+
+/// A message received by a [`ReplicationManager`], with the name of the slot it came from.
+pub type SlotMessage = (String, Result<LogicalReplication, ReplicationError>);
+
+/// A message or transaction received by a [`ReplicationManager`], with the slot and the
+/// publications of the stream it came from, e.g. to route the changes of several publications
+/// consumed into one sink.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct SourcedChange<T> {
+    /// The name of the slot of the stream.
+    pub slot: String,
+    /// The names of the publications the stream was started with; see
+    /// [`LogicalReplicationStream::publications()`].
+    pub publications: Vec<String>,
+    /// The message, the transaction, or the error that ended the stream.
+    pub change: Result<T, ReplicationError>,
+}
+
+impl<T> SourcedChange<T> {
+    fn new(stream: &LogicalReplicationStream, change: Result<T, ReplicationError>) -> Self {
+        Self {
+            slot: stream.slot().to_owned(),
+            publications: stream.publications().to_vec(),
+            change,
+        }
+    }
+}
+
+/// Consumes several [`LogicalReplicationStream`]s, e.g. from different slots and
+/// publications, in one task.
+///
+/// [`recv()`][Self::recv] waits on all streams at once, so each stream keeps answering
+/// keepalives and sending its periodic status updates while the others are busy. The streams
+/// are polled in turns, so that a slot with a lot of changes does not starve the others.
+///
+/// Each stream keeps its own confirmed position; use [`stream_mut()`][Self::stream_mut] to
+/// [confirm][LogicalReplicationStream::set_confirmed_lsn] the changes of a slot.
+///
+/// A stream is removed from the manager once it ends or returns an error, as it cannot be
+/// used afterwards.
+#[derive(Default)]
+pub struct ReplicationManager {
+    streams: Vec<LogicalReplicationStream>,
+    next: usize,
+    /// The messages of the transactions in progress, by slot, for `recv_transaction()`.
+    transactions: HashMap<String, TransactionBuffer>,
+}
+
+impl ReplicationManager {
+    /// Create a manager without any streams.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stream, returning the stream that was previously added for the same slot, if any.
+    pub fn add(&mut self, stream: LogicalReplicationStream) -> Option<LogicalReplicationStream> {
+        let previous = self.remove(stream.slot());
+        self.streams.push(stream);
+
+        previous
+    }
+
+    /// Remove the stream of `slot`, e.g. to [finish][LogicalReplicationStream::finish] it.
+    pub fn remove(&mut self, slot: &str) -> Option<LogicalReplicationStream> {
+        let index = self
+            .streams
+            .iter()
+            .position(|stream| stream.slot() == slot)?;
+
+        self.transactions.remove(slot);
+
+        Some(self.streams.remove(index))
+    }
+
+    /// The stream of `slot`.
+    pub fn stream(&self, slot: &str) -> Option<&LogicalReplicationStream> {
+        self.streams.iter().find(|stream| stream.slot() == slot)
+    }
+
+    /// The stream of `slot`, e.g. to confirm the changes that have been processed.
+    pub fn stream_mut(&mut self, slot: &str) -> Option<&mut LogicalReplicationStream> {
+        self.streams.iter_mut().find(|stream| stream.slot() == slot)
+    }
+
+    /// The names of the slots of the streams in this manager.
+    pub fn slots(&self) -> impl Iterator<Item = &str> {
+        self.streams.iter().map(|stream| stream.slot())
+    }
+
+    /// The number of streams in this manager.
+    pub fn len(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Returns `true` if this manager has no streams.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Receive the next message from any of the streams.
+    ///
+    /// Returns `None` once no streams are left. A stream that ends is removed without
+    /// returning a message; a stream that returns an error is removed after the error was
+    /// returned.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe, like [`LogicalReplicationStream::recv()`].
+    pub async fn recv(&mut self) -> Option<SlotMessage> {
+        let message = self.recv_sourced().await?;
+
+        Some((message.slot, message.change))
+    }
+
+    /// Receive the next message from any of the streams like [`recv()`][Self::recv], with the
+    /// slot and the publications of its stream.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe, like [`LogicalReplicationStream::recv()`].
+    pub async fn recv_sourced(&mut self) -> Option<SourcedChange<LogicalReplication>> {
+        loop {
+            if self.streams.is_empty() {
+                return None;
+            }
+
+            // start with a different stream every time, as `select_all` prefers the first
+            // ready future
+            let start = self.next % self.streams.len();
+            self.next = start + 1;
+
+            let (index, result) = {
+                let (before, after) = self.streams.split_at_mut(start);
+
+                let futures = after
+                    .iter_mut()
+                    .chain(before)
+                    .map(|stream| stream.recv().boxed());
+
+                let (result, index, _) = future::select_all(futures).await;
+
+                ((start + index) % self.streams.len(), result)
+            };
+
+            match result {
+                Ok(Some(message)) => {
+                    return Some(SourcedChange::new(&self.streams[index], Ok(message)))
+                }
+                Ok(None) => {
+                    let stream = self.streams.remove(index);
+                    self.transactions.remove(stream.slot());
+                }
+                Err(error) => {
+                    let stream = self.streams.remove(index);
+                    self.transactions.remove(stream.slot());
+
+                    return Some(SourcedChange::new(&stream, Err(error)));
+                }
+            }
+        }
+    }
+
+    /// Receive the next committed transaction from any of the streams, with the slot and the
+    /// publications of its stream.
+    ///
+    /// The messages of each stream are collected until their transaction commits, like with
+    /// a [`TransactionStream`][super::TransactionStream], but transactions are not
+    /// acknowledged automatically: confirm [`ReplicatedTransaction::end_lsn`] on the
+    /// [stream of the slot][Self::stream_mut] once the transaction was processed. Don't mix
+    /// this with [`recv()`][Self::recv], which would take messages out of the transactions.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe, like [`LogicalReplicationStream::recv()`].
+    pub async fn recv_transaction(&mut self) -> Option<SourcedChange<ReplicatedTransaction>> {
+        loop {
+            let message = self.recv_sourced().await?;
+
+            let replication = match message.change {
+                Ok(replication) => replication,
+                Err(error) => {
+                    return Some(SourcedChange {
+                        slot: message.slot,
+                        publications: message.publications,
+                        change: Err(error),
+                    })
+                }
+            };
+
+            let now = match self.stream(&message.slot) {
+                Some(stream) => stream.now(),
+                None => continue,
+            };
+
+            let transaction = self
+                .transactions
+                .entry(message.slot.clone())
+                .or_default()
+                .push(replication, now);
+
+            if let Some(transaction) = transaction {
+                return Some(SourcedChange {
+                    slot: message.slot,
+                    publications: message.publications,
+                    change: Ok(transaction),
+                });
+            }
+        }
+    }
+
+    /// Finish all streams, returning their replication connections.
+    pub async fn finish(self) -> Result<Vec<PgReplicationConnection>, ReplicationError> {
+        let mut conns = Vec::with_capacity(self.streams.len());
+
+        for stream in self.streams {
+            conns.push(stream.finish().await?);
+        }
+
+        Ok(conns)
+    }
+
+    /// Consume this manager, returning a `Stream` of the messages of all streams.
+    ///
+    /// The stream ends once no streams are left.
+    pub fn into_stream(self) -> impl Stream<Item = SlotMessage> + Unpin {
+        Box::pin(stream::unfold(self, |mut this| async move {
+            let message = this.recv().await?;
+
+            Some((message, this))
+        }))
+    }
+}
+
+impl Debug for ReplicationManager {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicationManager")
+            .field("streams", &self.streams)
+            .finish()
+    }
+}
@@ -0,0 +1,1443 @@
+#[cfg(feature = "json")]
+use serde_json::{Map, Value as JsonValue};
+
+use sqlx_core::decode::Decode;
+use sqlx_core::types::Type;
+
+use crate::error::Error;
+#[cfg(feature = "json")]
+use crate::type_info::PgType;
+#[cfg(feature = "json")]
+use crate::types::Oid;
+use crate::Postgres;
+
+#[cfg(feature = "json")]
+use super::TIMING_TARGET;
+use super::{
+    Column, Delete, MaybeDecoded, Relation, ReplicationError, TupleData, Tuples, UnknownTypePolicy,
+    Update,
+};
+
+/// A row mapped to a JSON object keyed by column name.
+#[cfg(feature = "json")]
+type JsonRow = Map<String, JsonValue>;
+
+/// How [`Tuples::to_json_with()`] maps the values of the text format.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValueMapping {
+    /// Map booleans, integers, floating point numbers and `json`/`jsonb` values to the
+    /// matching JSON values, like [`Tuples::to_json()`]. This is the default.
+    ///
+    /// Decoding a value and encoding it again can change its text: a `real` like `0.1` maps to
+    /// the `f64` `0.10000000149011612`, a `double precision` like `1e+30` to `1e30`, and `NaN`
+    /// and `Infinity` to `null`.
+    #[default]
+    Typed,
+    /// Map every value in the text format to a JSON string of the text as the server sent it,
+    /// byte for byte, e.g. for a sink that must reproduce `numeric` and floating point values
+    /// exactly. Values in the binary format have no text and are mapped like with
+    /// [`Typed`][Self::Typed].
+    ///
+    /// To get the text alongside a decoded value instead, decode the value with
+    /// [`LazyColumn::get()`] and take the text from [`LazyColumn::data()`] with
+    /// [`TupleData::as_str()`].
+    OriginalText,
+}
+
+/// A subset of the columns of a [`Relation`] to map, resolved once and reused for every row.
+///
+/// ```rust
+/// # use sqlx::postgres::replication::{Projection, Relation};
+/// # fn example(relation: &Relation) -> Result<(), sqlx::Error> {
+/// // only `id` and `name` are decoded; `payload` (a large `jsonb` column) is skipped
+/// let projection = Projection::new(relation, ["id", "name"])?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Projection {
+    indices: Vec<usize>,
+}
+
+impl Projection {
+    /// Resolve the columns named `columns` of `relation`, in the given order.
+    ///
+    /// Returns [`Error::ColumnNotFound`] if the relation has no column with one of the names.
+    pub fn new<I>(relation: &Relation, columns: I) -> Result<Self, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let indices = columns
+            .into_iter()
+            .map(|name| {
+                let name = name.as_ref();
+
+                relation
+                    .columns
+                    .iter()
+                    .position(|column| column.name == name)
+                    .ok_or_else(|| Error::ColumnNotFound(name.to_owned()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { indices })
+    }
+
+    /// All columns of `relation`.
+    pub fn all(relation: &Relation) -> Self {
+        Self {
+            indices: (0..relation.columns.len()).collect(),
+        }
+    }
+
+    /// The key columns of `relation`, which identify a row in the key sent for `Update` and
+    /// `Delete` messages.
+    ///
+    /// The columns are in the order of the table, which is the order of the values of the key,
+    /// also for `REPLICA IDENTITY INDEX` with an index on the columns in another order.
+    pub fn key(relation: &Relation) -> Self {
+        Self {
+            indices: (relation.columns.iter().enumerate())
+                .filter(|(_, column)| column.flags & 1 != 0)
+                .map(|(index, _)| index)
+                .collect(),
+        }
+    }
+
+    /// The indices of the selected columns in the relation.
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl Tuples {
+    /// Iterate over the values of a row together with the names of their columns in
+    /// `relation`.
+    pub fn named<'a>(
+        &'a self,
+        relation: &'a Relation,
+    ) -> impl Iterator<Item = (&'a str, &'a TupleData)> + 'a {
+        relation
+            .columns
+            .iter()
+            .map(|column| column.name.as_str())
+            .zip(self.iter())
+    }
+
+    /// Returns the value of the column named `name` in `relation`, e.g. to extract the key of
+    /// a row:
+    ///
+    /// ```rust
+    /// # use sqlx::postgres::replication::{Relation, Tuples};
+    /// # use sqlx::postgres::types::Oid;
+    /// # fn example(tuples: &Tuples, relation: &Relation) -> Result<(), sqlx::Error> {
+    /// let id: Option<i64> = tuples
+    ///     .get_by_name(relation, "id")
+    ///     .map(|data| data.try_decode(Oid(20))) // the OID of `int8`
+    ///     .transpose()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Names are compared exactly, as they are sent by the server (unquoted names are folded to
+    /// lower case by Postgres). Returns `None`, rather than an error, both if the relation has
+    /// no such column and if the row has no value for it, just like [`get()`][Self::get] for
+    /// an index out of range; use a [`Projection`] to resolve names that must exist up front.
+    pub fn get_by_name(&self, relation: &Relation, name: &str) -> Option<&TupleData> {
+        let index = relation
+            .columns
+            .iter()
+            .position(|column| column.name == name)?;
+
+        self.get(index)
+    }
+
+    /// Iterate over the values of the columns selected by `projection`, together with their
+    /// names.
+    ///
+    /// Returns an error if the number of values does not match the columns of `relation`,
+    /// which means the row does not belong to the relation.
+    pub fn named_projected<'a>(
+        &'a self,
+        relation: &'a Relation,
+        projection: &'a Projection,
+    ) -> Result<impl Iterator<Item = (&'a str, &'a TupleData)> + 'a, Error> {
+        self.check_projection(relation, projection)?;
+
+        Ok(projection
+            .indices
+            .iter()
+            .map(|&index| (relation.columns[index].name.as_str(), &self[index])))
+    }
+
+    /// A view of a row by column of `relation` that decodes the value of a column only when
+    /// it is asked for, e.g. to inspect a few columns of a wide row and forward the others
+    /// untouched, without decoding or copying them.
+    ///
+    /// ```rust
+    /// # use sqlx::postgres::replication::{Relation, Tuples};
+    /// # fn example(tuples: &Tuples, relation: &Relation) -> Result<(), sqlx::Error> {
+    /// let row = tuples.lazy(relation)?;
+    ///
+    /// // only `id` is decoded
+    /// let id: i64 = row.get("id")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns an error if the number of values does not match the columns of `relation`,
+    /// which means the row does not belong to the relation.
+    pub fn lazy<'a>(&'a self, relation: &'a Relation) -> Result<LazyRow<'a>, Error> {
+        self.check_relation(relation)?;
+
+        Ok(LazyRow {
+            relation,
+            row: self,
+        })
+    }
+
+    /// Map a row to a JSON object keyed by column name.
+    ///
+    /// Booleans, integers, floating point numbers and `json`/`jsonb` values are mapped to
+    /// the matching JSON values, other values in the text format to strings. Columns with an
+    /// unchanged TOAST value are left out, as their value is not known.
+    ///
+    /// Returns an error for a value in the binary format of any other type.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self, relation: &Relation) -> Result<Map<String, JsonValue>, Error> {
+        self.to_json_projected(relation, &Projection::all(relation))
+    }
+
+    /// Map the columns selected by `projection` to a JSON object keyed by column name, like
+    /// [`to_json()`][Self::to_json]. The values of the other columns are not decoded.
+    #[cfg(feature = "json")]
+    pub fn to_json_projected(
+        &self,
+        relation: &Relation,
+        projection: &Projection,
+    ) -> Result<Map<String, JsonValue>, Error> {
+        self.to_json_with(relation, projection, ValueMapping::Typed)
+    }
+
+    /// Map the columns selected by `projection` to a JSON object keyed by column name, like
+    /// [`to_json_projected()`][Self::to_json_projected], mapping the values according to
+    /// `mapping`, e.g. [`ValueMapping::OriginalText`] to keep the text of every value as it was
+    /// sent.
+    #[cfg(feature = "json")]
+    pub fn to_json_with(
+        &self,
+        relation: &Relation,
+        projection: &Projection,
+        mapping: ValueMapping,
+    ) -> Result<Map<String, JsonValue>, Error> {
+        self.check_projection(relation, projection)?;
+
+        let started = tracing::enabled!(target: TIMING_TARGET, tracing::Level::TRACE)
+            .then(std::time::Instant::now);
+
+        let mut object = Map::with_capacity(projection.indices.len());
+
+        for &index in &projection.indices {
+            let column = &relation.columns[index];
+            let data = &self[index];
+
+            if matches!(data, TupleData::UnchangedToast) {
+                continue;
+            }
+
+            let value =
+                to_json_value(data, column.type_id, mapping).map_err(|error| match error {
+                    Error::Decode(source) => Error::ColumnDecode {
+                        index: format!("{:?}", column.name),
+                        source,
+                    },
+                    error => error,
+                })?;
+
+            object.insert(column.name.clone(), value);
+        }
+
+        if let Some(started) = started {
+            tracing::trace!(
+                target: TIMING_TARGET,
+                relation = %relation.name,
+                columns = object.len(),
+                elapsed = ?started.elapsed(),
+                "mapped row to JSON"
+            );
+        }
+
+        Ok(object)
+    }
+
+    fn check_projection(&self, relation: &Relation, projection: &Projection) -> Result<(), Error> {
+        self.check_relation(relation)?;
+
+        match projection
+            .indices
+            .iter()
+            .find(|&&index| index >= self.len())
+        {
+            Some(&index) => Err(Error::ColumnIndexOutOfBounds {
+                index,
+                len: self.len(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn check_relation(&self, relation: &Relation) -> Result<(), Error> {
+        if self.len() != relation.columns.len() {
+            return Err(Error::Decode(
+                format!(
+                    "row has {} values, but relation {:?} has {} columns",
+                    self.len(),
+                    relation.name,
+                    relation.columns.len()
+                )
+                .into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The row before an [`Update`], as returned by [`Update::images()`].
+///
+/// Which of the two is sent depends on the replica identity of the relation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BeforeImage<T> {
+    /// The complete old row, sent for relations with `REPLICA IDENTITY FULL`.
+    Full(T),
+    /// Only the key of the old row, sent for relations with `REPLICA IDENTITY DEFAULT` or
+    /// `INDEX` if the key was changed. The other columns are `NULL` in [`Tuples`] and left out
+    /// when mapped to JSON.
+    Key(T),
+}
+
+impl<T> BeforeImage<T> {
+    /// Returns `true` if this is the complete old row.
+    pub fn is_full(&self) -> bool {
+        matches!(self, BeforeImage::Full(_))
+    }
+
+    /// The row, regardless of whether it is complete.
+    pub fn into_inner(self) -> T {
+        match self {
+            BeforeImage::Full(row) | BeforeImage::Key(row) => row,
+        }
+    }
+
+    /// Map the row, keeping whether it is complete.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> BeforeImage<U> {
+        match self {
+            BeforeImage::Full(row) => BeforeImage::Full(f(row)),
+            BeforeImage::Key(row) => BeforeImage::Key(f(row)),
+        }
+    }
+}
+
+/// What identifies the old row of an [`Update`], as returned by [`Update::identity_kind()`].
+///
+/// The server sends at most one image of the old row, so these are the only valid states of
+/// [`Update::key_data`] and [`Update::old_data`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpdateIdentity {
+    /// Neither was sent: the relation has `REPLICA IDENTITY NOTHING`, or `DEFAULT` or `INDEX`
+    /// and the key was not changed, in which case the key columns of the new row identify it.
+    None,
+    /// [`key_data`][Update::key_data] was sent, for `REPLICA IDENTITY DEFAULT` or `INDEX`
+    /// because the key was changed.
+    Key,
+    /// [`old_data`][Update::old_data] was sent, for `REPLICA IDENTITY FULL`.
+    Full,
+}
+
+impl Update {
+    /// Which image of the old row the server sent, e.g. to branch on it without inspecting
+    /// [`key_data`][Self::key_data] and [`old_data`][Self::old_data]; see [`UpdateIdentity`].
+    ///
+    /// The complete old row takes precedence, like in [`images()`][Self::images], for an
+    /// `Update` constructed with both.
+    pub fn identity_kind(&self) -> UpdateIdentity {
+        match (&self.old_data, &self.key_data) {
+            (Some(_), _) => UpdateIdentity::Full,
+            (None, Some(_)) => UpdateIdentity::Key,
+            (None, None) => UpdateIdentity::None,
+        }
+    }
+
+    /// The rows before and after the update.
+    ///
+    /// The row before is `None` if the server did not send it: with `REPLICA IDENTITY NOTHING`,
+    /// and with `DEFAULT` or `INDEX` if the key was not changed, in which case the key columns
+    /// of the row after identify the row.
+    pub fn images(&self) -> (Option<BeforeImage<&Tuples>>, &Tuples) {
+        let before = match (&self.old_data, &self.key_data) {
+            (Some(old), _) => Some(BeforeImage::Full(old)),
+            (None, Some(key)) => Some(BeforeImage::Key(key)),
+            (None, None) => None,
+        };
+
+        (before, &self.new_data)
+    }
+
+    /// The key of the row before the update, e.g. to issue an `UPDATE ... WHERE` by key
+    /// downstream: the key columns sent if the key changed, those of the complete old row sent
+    /// for `REPLICA IDENTITY FULL`, or otherwise those of the row after the update, as the key
+    /// didn't change.
+    ///
+    /// ```rust
+    /// # use sqlx::postgres::replication::{Relation, ReplicationError, Update};
+    /// # fn example(update: &Update, relation: &Relation) -> Result<(), ReplicationError> {
+    /// // e.g. `PRIMARY KEY (tenant_id, id)`
+    /// let (tenant_id, id): (i32, i64) = update.key(relation)?.decode()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The key columns are the columns [flagged][super::Column::flags] in `relation`, in the
+    /// order of the relation, like for [`Delete::key()`]. Returns
+    /// [`ReplicationError::MissingKey`] if the relation has no key columns, which happens for
+    /// relations with `REPLICA IDENTITY NOTHING`, and an error if the row does not belong to
+    /// `relation`.
+    pub fn key<'a>(&'a self, relation: &'a Relation) -> Result<RowKey<'a>, ReplicationError> {
+        let key = match (&self.old_data, &self.key_data) {
+            (Some(old), _) => RowKey::new(relation, old, KeySource::Full)?,
+            (None, Some(key)) => RowKey::new(relation, key, KeySource::Key)?,
+            (None, None) => RowKey::new(relation, &self.new_data, KeySource::New)?,
+        };
+
+        if key.is_empty() {
+            return Err(ReplicationError::MissingKey {
+                relation: relation.qualified_name(),
+            });
+        }
+
+        Ok(key)
+    }
+
+    /// Map the rows before and after the update to JSON objects keyed by column name, like
+    /// [`Tuples::to_json()`]; see [`images()`][Self::images].
+    ///
+    /// Only the key columns are mapped for a [`BeforeImage::Key`]. Columns with an unchanged
+    /// TOAST value are left out of the row after, as their value is not known.
+    #[cfg(feature = "json")]
+    pub fn to_json_images(
+        &self,
+        relation: &Relation,
+    ) -> Result<(Option<BeforeImage<JsonRow>>, JsonRow), Error> {
+        let (before, after) = self.images();
+
+        let before = before
+            .map(|before| match before {
+                BeforeImage::Full(row) => row.to_json(relation).map(BeforeImage::Full),
+                BeforeImage::Key(row) => row
+                    .to_json_projected(relation, &Projection::key(relation))
+                    .map(BeforeImage::Key),
+            })
+            .transpose()?;
+
+        Ok((before, after.to_json(relation)?))
+    }
+}
+
+impl Delete {
+    /// The key of the deleted row: the key columns sent for `REPLICA IDENTITY DEFAULT` or
+    /// `INDEX`, or those of the complete old row sent for `FULL`, e.g. to issue a
+    /// `DELETE ... WHERE` by key downstream.
+    ///
+    /// ```rust
+    /// # use sqlx::postgres::replication::{Delete, Relation, ReplicationError};
+    /// # fn example(delete: &Delete, relation: &Relation) -> Result<(), ReplicationError> {
+    /// let key = delete.key(relation)?;
+    /// let id: i64 = key.get("id")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The key columns are the columns [flagged][super::Column::flags] in `relation`, e.g. a
+    /// subset of the columns for `REPLICA IDENTITY USING INDEX`. The key sent by the server
+    /// has a value for every column of the relation, with the columns that are not part of the
+    /// key `NULL`; these placeholders are not part of the returned key, so they are never
+    /// mistaken for `NULL` key values.
+    ///
+    /// Returns [`ReplicationError::MissingKey`] if the server sent neither, which happens
+    /// for relations with `REPLICA IDENTITY NOTHING`, rather than letting the delete be
+    /// dropped silently. Returns an error as well if the row does not belong to `relation`,
+    /// including if a column that is not flagged as a key column has a value in the key,
+    /// which means that `relation` is not the one the key was sent with.
+    pub fn key<'a>(&'a self, relation: &'a Relation) -> Result<RowKey<'a>, ReplicationError> {
+        match (&self.key_data, &self.old_data) {
+            (Some(key), _) => RowKey::new(relation, key, KeySource::Key),
+            (None, Some(old)) => RowKey::new(relation, old, KeySource::Full),
+            (None, None) => Err(ReplicationError::MissingKey {
+                relation: relation.qualified_name(),
+            }),
+        }
+    }
+}
+
+/// Which row the columns of a [`RowKey`] are taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeySource {
+    /// The key sent by the server, with placeholders for the other columns.
+    Key,
+    /// The complete old row, sent for `REPLICA IDENTITY FULL`.
+    Full,
+    /// The row after an update that didn't change the key.
+    New,
+}
+
+/// The key columns of an updated or deleted row, by name or in order, as returned by
+/// [`Update::key()`] and [`Delete::key()`].
+///
+/// A key of several columns, e.g. a composite primary key, is decoded by column with
+/// [`get()`][Self::get], or as a whole into a tuple or a type implementing [`FromRowKey`] with
+/// [`decode()`][Self::decode].
+#[derive(Debug, Clone)]
+pub struct RowKey<'a> {
+    relation: &'a Relation,
+    row: &'a Tuples,
+    projection: Projection,
+    source: KeySource,
+}
+
+impl<'a> RowKey<'a> {
+    /// The key columns of `row`, checking that it belongs to `relation`.
+    fn new(
+        relation: &'a Relation,
+        row: &'a Tuples,
+        source: KeySource,
+    ) -> Result<Self, ReplicationError> {
+        let projection = Projection::key(relation);
+        row.check_projection(relation, &projection)?;
+
+        if source == KeySource::Key {
+            let placeholder = (relation.columns.iter().zip(row.iter()))
+                .find(|(column, data)| column.flags & 1 == 0 && !data.is_null());
+
+            if let Some((column, _)) = placeholder {
+                return Err(Error::Decode(
+                    format!(
+                        "the key of a row of {} has a value for column {:?}, \
+                         which is not a key column of the relation",
+                        relation.qualified_name(),
+                        column.name
+                    )
+                    .into(),
+                )
+                .into());
+            }
+        }
+
+        Ok(RowKey {
+            relation,
+            row,
+            projection,
+            source,
+        })
+    }
+
+    /// Decode the value of the key column named `name` into `T`, checking it against the
+    /// type of the column.
+    ///
+    /// Returns [`Error::ColumnNotFound`] if the relation has no key column with that name.
+    pub fn get<T>(&self, name: &str) -> Result<T, Error>
+    where
+        T: Decode<'a, Postgres> + Type<Postgres>,
+    {
+        let (column, data) = self
+            .columns()
+            .find(|(column, _)| column.name == name)
+            .ok_or_else(|| Error::ColumnNotFound(name.to_owned()))?;
+
+        decode_column(column, data)
+    }
+
+    /// Decode the value of the key column at `index`, counting only the key columns, into `T`,
+    /// checking it against the type of the column.
+    ///
+    /// Returns [`Error::ColumnIndexOutOfBounds`] if the key has no column at `index`.
+    pub fn get_at<T>(&self, index: usize) -> Result<T, Error>
+    where
+        T: Decode<'a, Postgres> + Type<Postgres>,
+    {
+        let (column, data) =
+            self.columns()
+                .nth(index)
+                .ok_or_else(|| Error::ColumnIndexOutOfBounds {
+                    index,
+                    len: self.len(),
+                })?;
+
+        decode_column(column, data)
+    }
+
+    /// Decode all key columns at once into `T`, e.g. a tuple with a value for each column of a
+    /// composite key, in the order of the columns in the relation; see [`FromRowKey`].
+    pub fn decode<T: FromRowKey<'a>>(&self) -> Result<T, Error> {
+        T::from_key(self)
+    }
+
+    /// Iterate over the key columns together with their values.
+    pub fn columns(&self) -> impl Iterator<Item = (&'a Column, &'a TupleData)> + '_ {
+        let (relation, row) = (self.relation, self.row);
+
+        (self.projection.indices.iter()).map(move |&index| (&relation.columns[index], &row[index]))
+    }
+
+    /// The names of the key columns.
+    pub fn names(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.columns().map(|(column, _)| column.name.as_str())
+    }
+
+    /// The number of key columns.
+    pub fn len(&self) -> usize {
+        self.projection.indices.len()
+    }
+
+    /// Returns `true` if the relation has no key columns.
+    pub fn is_empty(&self) -> bool {
+        self.projection.indices.is_empty()
+    }
+
+    /// Returns `true` if the key was taken from the complete old row, sent for relations with
+    /// `REPLICA IDENTITY FULL`, in which all columns are key columns.
+    pub fn is_full(&self) -> bool {
+        self.source == KeySource::Full
+    }
+
+    /// The row the key was taken from, in which the columns that are not part of the key are
+    /// `NULL` if it is the key sent by the server, rather than the [complete old
+    /// row][Self::is_full] or the row after an update that didn't change the key.
+    pub fn row(&self) -> &'a Tuples {
+        self.row
+    }
+
+    /// Map the key columns to a JSON object keyed by column name, like
+    /// [`Tuples::to_json()`].
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<Map<String, JsonValue>, Error> {
+        self.row.to_json_projected(self.relation, &self.projection)
+    }
+}
+
+/// A type that can be decoded from all columns of a [`RowKey`] at once, with
+/// [`RowKey::decode()`].
+///
+/// This is implemented for tuples of up to 8 values, which take the key columns in the order of
+/// the relation and fail if the key has a different number of columns. A struct naming the
+/// columns of a composite key implements it by column name:
+///
+/// ```rust
+/// # use sqlx::postgres::replication::{FromRowKey, RowKey};
+/// struct OrderKey {
+///     tenant_id: i32,
+///     id: i64,
+/// }
+///
+/// impl FromRowKey<'_> for OrderKey {
+///     fn from_key(key: &RowKey<'_>) -> Result<Self, sqlx::Error> {
+///         Ok(OrderKey {
+///             tenant_id: key.get("tenant_id")?,
+///             id: key.get("id")?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromRowKey<'a>: Sized {
+    /// Decode the key columns of `key`.
+    fn from_key(key: &RowKey<'a>) -> Result<Self, Error>;
+}
+
+macro_rules! impl_from_row_key_for_tuple {
+    ($( ($idx:tt) -> $T:ident );+;) => {
+        impl<'a, $($T,)+> FromRowKey<'a> for ($($T,)+)
+        where
+            $($T: Decode<'a, Postgres> + Type<Postgres>,)+
+        {
+            fn from_key(key: &RowKey<'a>) -> Result<Self, Error> {
+                let len = [$($idx),+].len();
+
+                if key.len() != len {
+                    return Err(Error::Decode(
+                        format!(
+                            "the key of a row of {} has {} columns, but {len} were expected",
+                            key.relation.qualified_name(),
+                            key.len(),
+                        )
+                        .into(),
+                    ));
+                }
+
+                Ok(($(key.get_at($idx)?,)+))
+            }
+        }
+    };
+}
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+    (3) -> T4;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+    (3) -> T4;
+    (4) -> T5;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+    (3) -> T4;
+    (4) -> T5;
+    (5) -> T6;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+    (3) -> T4;
+    (4) -> T5;
+    (5) -> T6;
+    (6) -> T7;
+);
+
+impl_from_row_key_for_tuple!(
+    (0) -> T1;
+    (1) -> T2;
+    (2) -> T3;
+    (3) -> T4;
+    (4) -> T5;
+    (5) -> T6;
+    (6) -> T7;
+    (7) -> T8;
+);
+
+/// A row whose values are decoded by column on demand, as returned by [`Tuples::lazy()`].
+///
+/// The row borrows the values as they were received, so nothing is decoded or copied until a
+/// column is [decoded][LazyColumn::get], and the values of the other columns are never parsed.
+#[derive(Debug, Clone, Copy)]
+pub struct LazyRow<'a> {
+    relation: &'a Relation,
+    row: &'a Tuples,
+}
+
+impl<'a> LazyRow<'a> {
+    /// The column named `name`, or `None` if the relation has no such column.
+    pub fn column(&self, name: &str) -> Option<LazyColumn<'a>> {
+        let index = (self.relation.columns.iter()).position(|column| column.name == name)?;
+
+        self.column_at(index)
+    }
+
+    /// The column at `index` in the relation, or `None` if it is out of range.
+    pub fn column_at(&self, index: usize) -> Option<LazyColumn<'a>> {
+        Some(LazyColumn {
+            column: self.relation.columns.get(index)?,
+            data: self.row.get(index)?,
+        })
+    }
+
+    /// Decode the value of the column named `name` into `T`, checking it against the type of
+    /// the column.
+    ///
+    /// Returns [`Error::ColumnNotFound`] if the relation has no column with that name.
+    pub fn get<T>(&self, name: &str) -> Result<T, Error>
+    where
+        T: Decode<'a, Postgres> + Type<Postgres>,
+    {
+        self.column(name)
+            .ok_or_else(|| Error::ColumnNotFound(name.to_owned()))?
+            .get()
+    }
+
+    /// Iterate over the columns, in the order of the relation.
+    pub fn columns(&self) -> impl Iterator<Item = LazyColumn<'a>> + 'a {
+        (self.relation.columns.iter())
+            .zip(self.row.iter())
+            .map(|(column, data)| LazyColumn { column, data })
+    }
+
+    /// The number of columns.
+    pub fn len(&self) -> usize {
+        self.row.len()
+    }
+
+    /// Returns `true` if the row has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.row.is_empty()
+    }
+
+    /// The relation of the row.
+    pub fn relation(&self) -> &'a Relation {
+        self.relation
+    }
+
+    /// The values of the row, as received.
+    pub fn row(&self) -> &'a Tuples {
+        self.row
+    }
+}
+
+/// A column of a [`LazyRow`], whose value is only decoded by [`get()`][Self::get].
+#[derive(Debug, Clone, Copy)]
+pub struct LazyColumn<'a> {
+    column: &'a Column,
+    data: &'a TupleData,
+}
+
+impl<'a> LazyColumn<'a> {
+    /// The name of the column.
+    pub fn name(&self) -> &'a str {
+        &self.column.name
+    }
+
+    /// The definition of the column in the relation.
+    pub fn column(&self) -> &'a Column {
+        self.column
+    }
+
+    /// The value of the column, as received, e.g. to forward it without decoding it.
+    pub fn data(&self) -> &'a TupleData {
+        self.data
+    }
+
+    /// Returns `true` if the value is `NULL`.
+    pub fn is_null(&self) -> bool {
+        self.data.is_null()
+    }
+
+    /// Decode the value into `T`, checking it against the type of the column.
+    ///
+    /// Each call decodes the value again; decoding into a borrowing type like `&str` or
+    /// `&[u8]` doesn't copy it.
+    pub fn get<T>(&self) -> Result<T, Error>
+    where
+        T: Decode<'a, Postgres> + Type<Postgres>,
+    {
+        decode_column(self.column, self.data)
+    }
+
+    /// Decode the value into `T` like [`get()`][Self::get], handling a column of a type that
+    /// is not built in according to `policy`; see [`TupleData::try_decode_with()`].
+    pub fn get_with<T>(&self, policy: UnknownTypePolicy) -> Result<MaybeDecoded<'a, T>, Error>
+    where
+        T: Decode<'a, Postgres> + Type<Postgres>,
+    {
+        self.data
+            .try_decode_with(self.column.type_id, policy)
+            .map_err(|error| column_error(self.column, error))
+    }
+}
+
+/// Decode the value `data` of `column`, reporting a failure with the name of the column.
+fn decode_column<'a, T>(column: &Column, data: &'a TupleData) -> Result<T, Error>
+where
+    T: Decode<'a, Postgres> + Type<Postgres>,
+{
+    data.try_decode(column.type_id)
+        .map_err(|error| column_error(column, error))
+}
+
+/// Report a failure to decode a value with the name of its column.
+fn column_error(column: &Column, error: Error) -> Error {
+    match error {
+        Error::Decode(source) => Error::ColumnDecode {
+            index: format!("{:?}", column.name),
+            source,
+        },
+        error => error,
+    }
+}
+
+#[cfg(feature = "json")]
+fn to_json_value(
+    data: &TupleData,
+    type_id: Oid,
+    mapping: ValueMapping,
+) -> Result<JsonValue, Error> {
+    if data.is_null() {
+        return Ok(JsonValue::Null);
+    }
+
+    if let (ValueMapping::OriginalText, TupleData::Text(_)) = (mapping, data) {
+        return Ok(data
+            .as_str()
+            .ok_or_else(|| Error::Decode("invalid UTF-8 in text value".into()))?
+            .into());
+    }
+
+    let ty = PgType::try_from_oid(type_id);
+
+    // the text format of a bit string, like it is sent without `binary`
+    if let (Some(PgType::Bit | PgType::Varbit), TupleData::Binary(value)) = (&ty, data) {
+        return Ok(super::tuple::bit_string(value)?.into());
+    }
+
+    Ok(match ty {
+        Some(PgType::Bool) => data.try_decode::<bool>(type_id)?.into(),
+        Some(PgType::Int2) => data.try_decode::<i16>(type_id)?.into(),
+        Some(PgType::Int4) => data.try_decode::<i32>(type_id)?.into(),
+        Some(PgType::Int8) => data.try_decode::<i64>(type_id)?.into(),
+        Some(PgType::Float4) => data.try_decode::<f32>(type_id)?.into(),
+        Some(PgType::Float8) => data.try_decode::<f64>(type_id)?.into(),
+        Some(PgType::Json | PgType::Jsonb) => data.try_decode::<JsonValue>(type_id)?,
+        Some(PgType::Text | PgType::Varchar | PgType::Bpchar | PgType::Name) => {
+            data.try_decode::<String>(type_id)?.into()
+        }
+
+        _ => match data {
+            TupleData::Text(_) => data
+                .as_str()
+                .ok_or_else(|| Error::Decode("invalid UTF-8 in text value".into()))?
+                .into(),
+            _ => {
+                return Err(Error::Decode(
+                    format!(
+                        "cannot map binary value of type {} to JSON",
+                        ty.map_or_else(|| type_id.0.to_string(), |ty| ty.display_name().to_owned())
+                    )
+                    .into(),
+                ))
+            }
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::replication::{Column, ReplicaIdentity};
+    use crate::types::Oid;
+
+    fn relation() -> Relation {
+        let column = |name: &str, type_id: u32| Column {
+            flags: 0,
+            name: name.to_owned(),
+            type_id: Oid(type_id),
+            type_modifier: -1,
+        };
+
+        Relation {
+            xid: None,
+            relation_id: Oid(16384),
+            namespace: "public".to_owned(),
+            name: "users".to_owned(),
+            replica_identity: ReplicaIdentity::Default,
+            columns: vec![
+                column("id", 23),
+                column("name", 25),
+                column("payload", 3802),
+            ],
+        }
+    }
+
+    fn tuples() -> Tuples {
+        Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Text(Bytes::from_static(b"foo")),
+            // not valid `jsonb`, so decoding it would fail
+            TupleData::Text(Bytes::from_static(b"{")),
+        ])
+    }
+
+    #[test]
+    fn it_decodes_columns_lazily() {
+        let relation = relation();
+        let tuples = tuples();
+        let row = tuples.lazy(&relation).unwrap();
+
+        assert_eq!(row.len(), 3);
+        assert_eq!(row.get::<i32>("id").unwrap(), 1);
+        assert_eq!(row.get::<&str>("name").unwrap(), "foo");
+        assert!(matches!(
+            row.get::<i32>("email"),
+            Err(Error::ColumnNotFound(name)) if name == "email"
+        ));
+
+        // the invalid `payload` is only an error once it is decoded
+        let payload = row.column("payload").unwrap();
+        assert_eq!(payload.data().as_bytes(), Some(&b"{"[..]));
+        #[cfg(feature = "json")]
+        assert!(matches!(
+            payload.get::<serde_json::Value>(),
+            Err(Error::ColumnDecode { index, .. }) if index == r#""payload""#
+        ));
+
+        let names: Vec<_> = row.columns().map(|column| column.name()).collect();
+        assert_eq!(names, ["id", "name", "payload"]);
+        assert_eq!(row.column_at(1).unwrap().name(), "name");
+        assert!(row.column_at(3).is_none());
+
+        // a row of another relation
+        let short = Tuples(vec![TupleData::Null]);
+        assert!(short.lazy(&relation).is_err());
+    }
+
+    #[test]
+    fn it_resolves_projections() {
+        let relation = relation();
+
+        assert_eq!(
+            Projection::new(&relation, ["payload", "id"])
+                .unwrap()
+                .indices(),
+            &[2, 0]
+        );
+        assert_eq!(Projection::all(&relation).indices(), &[0, 1, 2]);
+
+        assert!(matches!(
+            Projection::new(&relation, ["id", "email"]),
+            Err(Error::ColumnNotFound(name)) if name == "email"
+        ));
+    }
+
+    #[test]
+    fn it_maps_named_columns() {
+        let relation = relation();
+        let tuples = tuples();
+        let projection = Projection::new(&relation, ["name"]).unwrap();
+
+        let names: Vec<_> = tuples.named(&relation).map(|(name, _)| name).collect();
+        assert_eq!(names, ["id", "name", "payload"]);
+
+        let projected: Vec<_> = tuples
+            .named_projected(&relation, &projection)
+            .unwrap()
+            .map(|(name, data)| (name, data.as_str()))
+            .collect();
+        assert_eq!(projected, [("name", Some("foo"))]);
+
+        assert!(Tuples(vec![TupleData::Null])
+            .named_projected(&relation, &projection)
+            .is_err());
+    }
+
+    #[test]
+    fn it_gets_columns_by_name() {
+        let relation = relation();
+        let tuples = tuples();
+
+        assert_eq!(
+            tuples
+                .get_by_name(&relation, "name")
+                .and_then(TupleData::as_str),
+            Some("foo")
+        );
+        assert_eq!(
+            tuples
+                .get_by_name(&relation, "id")
+                .map(|data| data.try_decode::<i32>(relation.columns[0].type_id))
+                .transpose()
+                .unwrap(),
+            Some(1)
+        );
+
+        assert_eq!(tuples.get_by_name(&relation, "email"), None);
+        assert_eq!(tuples.get_by_name(&relation, "ID"), None);
+        assert_eq!(Tuples::default().get_by_name(&relation, "id"), None);
+    }
+
+    #[test]
+    fn it_returns_update_images() {
+        let update = |key_data, old_data| Update {
+            xid: None,
+            relation_id: Oid(16384),
+            key_data,
+            old_data,
+            new_data: tuples(),
+        };
+
+        let full = update(None, Some(tuples()));
+        assert_eq!(full.identity_kind(), UpdateIdentity::Full);
+        let (before, after) = full.images();
+        assert_eq!(before, Some(BeforeImage::Full(&tuples())));
+        assert_eq!(after, &tuples());
+
+        let key = Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Null,
+            TupleData::Null,
+        ]);
+        let changed_key = update(Some(key.clone()), None);
+        assert_eq!(changed_key.identity_kind(), UpdateIdentity::Key);
+        let (before, _) = changed_key.images();
+        assert_eq!(before, Some(BeforeImage::Key(&key)));
+        assert!(!before.unwrap().is_full());
+
+        assert_eq!(update(None, None).images().0, None);
+        assert_eq!(update(None, None).identity_kind(), UpdateIdentity::None);
+    }
+
+    #[test]
+    fn it_extracts_delete_keys() {
+        let mut relation = relation();
+        relation.columns[0].flags = 1;
+
+        let delete = |key_data, old_data| Delete {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data,
+            old_data,
+        };
+
+        let key = Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Null,
+            TupleData::Null,
+        ]);
+        let by_key = delete(Some(key), None);
+        let row_key = by_key.key(&relation).unwrap();
+        assert!(!row_key.is_full());
+        assert_eq!(row_key.len(), 1);
+        assert_eq!(row_key.names().collect::<Vec<_>>(), ["id"]);
+        assert_eq!(row_key.get::<i32>("id").unwrap(), 1);
+
+        // only key columns can be read, and only as their type
+        assert!(matches!(
+            row_key.get::<String>("name"),
+            Err(Error::ColumnNotFound(name)) if name == "name"
+        ));
+        assert!(matches!(
+            row_key.get::<String>("id"),
+            Err(Error::ColumnDecode { index, .. }) if index == "\"id\""
+        ));
+
+        // with `REPLICA IDENTITY FULL`, all columns are key columns
+        let mut full = relation.clone();
+        full.replica_identity = ReplicaIdentity::Full;
+        full.columns.iter_mut().for_each(|column| column.flags = 1);
+
+        let by_row = delete(None, Some(tuples()));
+        let row_key = by_row.key(&full).unwrap();
+        assert!(row_key.is_full());
+        assert_eq!(row_key.len(), 3);
+        assert_eq!(row_key.get::<String>("name").unwrap(), "foo");
+
+        assert!(matches!(
+            delete(None, None).key(&relation),
+            Err(ReplicationError::MissingKey { relation }) if relation == r#""public"."users""#
+        ));
+        assert!(delete(Some(Tuples::default()), None)
+            .key(&relation)
+            .is_err());
+    }
+
+    #[test]
+    fn it_extracts_delete_keys_of_an_index_identity() {
+        // `REPLICA IDENTITY USING INDEX` on (code, tenant): the key columns are flagged in the
+        // order of the table, and the other columns are `NULL` placeholders in the key
+        let mut relation = relation();
+        relation.replica_identity = ReplicaIdentity::Index;
+        relation.columns[1].flags = 1;
+        relation.columns.push(Column {
+            flags: 1,
+            name: "code".to_owned(),
+            type_id: Oid(23),
+            type_modifier: -1,
+        });
+
+        let delete = |key_data| Delete {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data: Some(key_data),
+            old_data: None,
+        };
+
+        let by_key = delete(Tuples(vec![
+            TupleData::Null,
+            TupleData::Text(Bytes::from_static(b"acme")),
+            TupleData::Null,
+            TupleData::Text(Bytes::from_static(b"42")),
+        ]));
+        let key = by_key.key(&relation).unwrap();
+        assert_eq!(key.names().collect::<Vec<_>>(), ["name", "code"]);
+        assert!(key.columns().all(|(_, data)| !data.is_null()));
+        assert_eq!(key.get::<String>("name").unwrap(), "acme");
+        assert_eq!(key.get::<i32>("code").unwrap(), 42);
+
+        // the placeholders are not key values
+        assert!(matches!(
+            key.get::<Option<i32>>("id"),
+            Err(Error::ColumnNotFound(name)) if name == "id"
+        ));
+
+        // a value in a column that is not flagged means the relation doesn't match the key
+        let mismatched = delete(Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Text(Bytes::from_static(b"acme")),
+            TupleData::Null,
+            TupleData::Text(Bytes::from_static(b"42")),
+        ]));
+        assert!(matches!(
+            mismatched.key(&relation),
+            Err(ReplicationError::Sqlx(Error::Decode(_)))
+        ));
+    }
+
+    #[test]
+    fn it_extracts_composite_keys() {
+        // `PRIMARY KEY (id, name)`
+        let mut relation = relation();
+        relation.columns[0].flags = 1;
+        relation.columns[1].flags = 1;
+
+        let text = |value: &'static str| TupleData::Text(Bytes::from_static(value.as_bytes()));
+
+        let update = |key_data| Update {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data,
+            old_data: None,
+            new_data: Tuples(vec![text("2"), text("bar"), text("{}")]),
+        };
+
+        // the key didn't change, so it is taken from the row after the update
+        let unchanged = update(None);
+        let key = unchanged.key(&relation).unwrap();
+        assert!(!key.is_full());
+        assert_eq!(key.names().collect::<Vec<_>>(), ["id", "name"]);
+        assert_eq!(
+            key.decode::<(i32, String)>().unwrap(),
+            (2, "bar".to_owned())
+        );
+
+        // the key before the update, in the order of the columns
+        let changed = update(Some(Tuples(vec![text("1"), text("foo"), TupleData::Null])));
+        let key = changed.key(&relation).unwrap();
+        assert_eq!(key.decode::<(i32, &str)>().unwrap(), (1, "foo"));
+        assert_eq!(key.get_at::<&str>(1).unwrap(), "foo");
+        assert!(matches!(
+            key.get_at::<i32>(2),
+            Err(Error::ColumnIndexOutOfBounds { index: 2, len: 2 })
+        ));
+
+        // a tuple of another size is an error, rather than a partial key
+        assert!(matches!(key.decode::<(i32,)>(), Err(Error::Decode(_))));
+        assert!(matches!(
+            key.decode::<(i32, String, String)>(),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(
+            key.decode::<(String, String)>(),
+            Err(Error::ColumnDecode { index, .. }) if index == "\"id\""
+        ));
+
+        let delete = Delete {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data: Some(Tuples(vec![text("1"), text("foo"), TupleData::Null])),
+            old_data: None,
+        };
+        let key = delete.key(&relation).unwrap();
+        assert_eq!(
+            key.decode::<(i32, String)>().unwrap(),
+            (1, "foo".to_owned())
+        );
+
+        // without key columns, as for `REPLICA IDENTITY NOTHING`, an update has no key
+        let mut nothing = relation.clone();
+        nothing.replica_identity = ReplicaIdentity::Nothing;
+        nothing
+            .columns
+            .iter_mut()
+            .for_each(|column| column.flags = 0);
+
+        assert!(matches!(
+            unchanged.key(&nothing),
+            Err(ReplicationError::MissingKey { .. })
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn it_maps_update_images_to_json() {
+        let mut relation = relation();
+        relation.columns[0].flags = 1;
+        // the text columns only, as the payload is not valid `jsonb`
+        relation.columns[2].type_id = Oid(25);
+
+        let key = Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"1")),
+            TupleData::Null,
+            TupleData::Null,
+        ]);
+        let new_data = Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"2")),
+            TupleData::Text(Bytes::from_static(b"bar")),
+            TupleData::UnchangedToast,
+        ]);
+
+        let update = Update {
+            xid: None,
+            relation_id: relation.relation_id,
+            key_data: Some(key),
+            old_data: None,
+            new_data,
+        };
+
+        let (before, after) = update.to_json_images(&relation).unwrap();
+        assert_eq!(
+            before.map(|before| before.map(JsonValue::Object)),
+            Some(BeforeImage::Key(serde_json::json!({ "id": 1 })))
+        );
+        assert_eq!(
+            JsonValue::Object(after),
+            serde_json::json!({ "id": 2, "name": "bar" })
+        );
+
+        let update = Update {
+            key_data: None,
+            old_data: Some(tuples()),
+            ..update
+        };
+
+        let (before, _) = update.to_json_images(&relation).unwrap();
+        assert_eq!(
+            before.map(|before| before.map(JsonValue::Object)),
+            Some(BeforeImage::Full(
+                serde_json::json!({ "id": 1, "name": "foo", "payload": "{" })
+            ))
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn it_maps_original_text_to_json() {
+        let column = |name: &str, type_id: u32| Column {
+            flags: 0,
+            name: name.to_owned(),
+            type_id: Oid(type_id),
+            type_modifier: -1,
+        };
+        let relation = Relation {
+            columns: vec![
+                column("real", 700),
+                column("double", 701),
+                column("numeric", 1700),
+                column("flag", 16),
+                column("count", 23),
+            ],
+            ..relation()
+        };
+        let tuples = Tuples(vec![
+            TupleData::Text(Bytes::from_static(b"0.1")),
+            TupleData::Text(Bytes::from_static(b"NaN")),
+            TupleData::Text(Bytes::from_static(b"1.50")),
+            TupleData::Text(Bytes::from_static(b"t")),
+            TupleData::Binary(Bytes::from_static(b"\0\0\0\x07")),
+        ]);
+        let projection = Projection::all(&relation);
+
+        let typed = tuples.to_json_projected(&relation, &projection).unwrap();
+        assert_eq!(typed["real"], serde_json::json!(0.1f32 as f64));
+        assert_eq!(typed["double"], JsonValue::Null);
+
+        let original = tuples
+            .to_json_with(&relation, &projection, ValueMapping::OriginalText)
+            .unwrap();
+        assert_eq!(
+            JsonValue::Object(original),
+            serde_json::json!({
+                "real": "0.1",
+                "double": "NaN",
+                "numeric": "1.50",
+                "flag": "t",
+                // no text to keep
+                "count": 7,
+            })
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn it_maps_bit_strings_to_json() {
+        let column = |name: &str, type_id: u32, type_modifier: i32| Column {
+            flags: 0,
+            name: name.to_owned(),
+            type_id: Oid(type_id),
+            type_modifier,
+        };
+        let relation = Relation {
+            columns: vec![
+                column("mask", 1560, 8),
+                column("flags", 1562, -1),
+                column("text", 1562, -1),
+            ],
+            ..relation()
+        };
+
+        // the bits of the binary format, with the leading zeros of the `bit(8)`
+        let tuples = Tuples(vec![
+            TupleData::Binary(Bytes::from_static(b"\0\0\0\x08\x05")),
+            TupleData::Binary(Bytes::from_static(b"\0\0\0\x0a\xb0\xc0")),
+            TupleData::Text(Bytes::from_static(b"101")),
+        ]);
+        assert_eq!(
+            JsonValue::Object(tuples.to_json(&relation).unwrap()),
+            serde_json::json!({
+                "mask": "00000101",
+                "flags": "1011000011",
+                "text": "101",
+            })
+        );
+
+        let truncated = Tuples(vec![
+            TupleData::Binary(Bytes::from_static(b"\0\0\0\x10\x05")),
+            TupleData::Null,
+            TupleData::Null,
+        ]);
+        assert!(truncated.to_json(&relation).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn it_maps_projected_columns_to_json() {
+        let relation = relation();
+        let tuples = tuples();
+
+        let projection = Projection::new(&relation, ["id", "name"]).unwrap();
+        let object = tuples.to_json_projected(&relation, &projection).unwrap();
+
+        assert_eq!(
+            JsonValue::Object(object),
+            serde_json::json!({ "id": 1, "name": "foo" })
+        );
+
+        // the `payload` column is decoded without a projection
+        assert!(matches!(
+            tuples.to_json(&relation),
+            Err(Error::ColumnDecode { index, .. }) if index == "\"payload\""
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "replication-stream")]
+use sqlx_core::bytes::BufMut;
+use sqlx_core::bytes::{Buf, Bytes};
+
+use crate::error::Error;
+use crate::io::ProtocolDecode;
+use crate::types::PgLsn;
+
+/// Seconds between the Unix epoch and the Postgres epoch, `2000-01-01 00:00:00 UTC`.
+const POSTGRES_EPOCH_SECS: u64 = 946_684_800;
+
+/// A message sent by the server inside the `CopyBoth` sub-protocol started by
+/// `START_REPLICATION`.
+///
+/// <https://www.postgresql.org/docs/current/protocol-replication.html#PROTOCOL-REPLICATION-START-REPLICATION>
+#[derive(Debug, Clone)]
+pub enum Replication {
+    XLogData(XLogData),
+    PrimaryKeepalive(PrimaryKeepalive),
+}
+
+/// A section of the WAL data stream (`w`).
+///
+/// In logical replication, `data` holds exactly one message of the output plugin.
+#[derive(Debug, Clone)]
+pub struct XLogData {
+    /// The starting point of the WAL data in this message.
+    pub wal_start: PgLsn,
+    /// The current end of WAL on the server.
+    pub wal_end: PgLsn,
+    /// The server's system clock at the time of transmission, as microseconds since
+    /// the Postgres epoch (`2000-01-01`).
+    pub timestamp: i64,
+    /// The WAL data (physical replication) or output plugin message (logical replication).
+    ///
+    /// This references the buffer the message was read into; copy it if only a small part
+    /// of it is kept for long.
+    pub data: Bytes,
+}
+
+/// A keepalive message from the server (`k`).
+#[derive(Debug, Clone, Copy)]
+pub struct PrimaryKeepalive {
+    /// The current end of WAL on the server.
+    pub wal_end: PgLsn,
+    /// The server's system clock at the time of transmission, as microseconds since
+    /// the Postgres epoch (`2000-01-01`).
+    pub timestamp: i64,
+    /// `true` if the server wants a status update as soon as possible, to avoid a timeout
+    /// disconnect.
+    pub reply_requested: bool,
+}
+
+/// The standby status update sent by the client (`r`).
+#[cfg(feature = "replication-stream")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StandbyStatusUpdate {
+    /// The location of the last WAL byte + 1 received and written to disk in the standby.
+    pub write: PgLsn,
+    /// The location of the last WAL byte + 1 flushed to disk in the standby.
+    pub flush: PgLsn,
+    /// The location of the last WAL byte + 1 applied in the standby.
+    pub apply: PgLsn,
+    /// The client's system clock at the time of transmission, as microseconds since
+    /// the Postgres epoch (`2000-01-01`).
+    pub timestamp: i64,
+    /// If `true`, the client requests the server to reply to this message immediately.
+    pub reply_requested: bool,
+}
+
+impl XLogData {
+    /// The server's system clock at the time of transmission.
+    pub fn system_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.timestamp)
+    }
+}
+
+impl PrimaryKeepalive {
+    /// The server's system clock at the time of transmission.
+    pub fn system_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.timestamp)
+    }
+}
+
+#[cfg(feature = "replication-stream")]
+impl StandbyStatusUpdate {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(34);
+
+        buf.put_u8(b'r');
+        buf.put_u64(self.write.0);
+        buf.put_u64(self.flush.0);
+        buf.put_u64(self.apply.0);
+        buf.put_i64(self.timestamp);
+        buf.put_u8(self.reply_requested.into());
+
+        buf
+    }
+}
+
+impl ProtocolDecode<'_> for Replication {
+    fn decode_with(mut buf: Bytes, _: ()) -> Result<Self, Error> {
+        if buf.is_empty() {
+            return Err(err_protocol!("empty replication message"));
+        }
+
+        match buf.get_u8() {
+            b'w' => {
+                if buf.remaining() < 24 {
+                    return Err(err_protocol!(
+                        "XLogData: expected at least 24 bytes, got {}",
+                        buf.remaining()
+                    ));
+                }
+
+                let wal_start = PgLsn(buf.get_u64());
+                let wal_end = PgLsn(buf.get_u64());
+                let timestamp = buf.get_i64();
+
+                Ok(Replication::XLogData(XLogData {
+                    wal_start,
+                    wal_end,
+                    timestamp,
+                    data: buf,
+                }))
+            }
+
+            b'k' => {
+                if buf.remaining() < 17 {
+                    return Err(err_protocol!(
+                        "PrimaryKeepalive: expected 17 bytes, got {}",
+                        buf.remaining()
+                    ));
+                }
+
+                Ok(Replication::PrimaryKeepalive(PrimaryKeepalive {
+                    wal_end: PgLsn(buf.get_u64()),
+                    timestamp: buf.get_i64(),
+                    reply_requested: buf.get_u8() == 1,
+                }))
+            }
+
+            tag => Err(err_protocol!(
+                "unknown replication message type: {:?}",
+                tag as char
+            )),
+        }
+    }
+}
+
+/// Convert a timestamp in microseconds since the Postgres epoch (`2000-01-01`) to a [`SystemTime`].
+pub(crate) fn timestamp_to_system_time(micros: i64) -> SystemTime {
+    let epoch = UNIX_EPOCH + Duration::from_secs(POSTGRES_EPOCH_SECS);
+    let offset = Duration::from_micros(micros.unsigned_abs());
+
+    if micros >= 0 {
+        epoch + offset
+    } else {
+        epoch - offset
+    }
+}
+
+/// Convert a [`SystemTime`] to microseconds since the Postgres epoch (`2000-01-01`).
+#[cfg(feature = "replication-stream")]
+pub(crate) fn system_time_to_timestamp(time: SystemTime) -> i64 {
+    let epoch = UNIX_EPOCH + Duration::from_secs(POSTGRES_EPOCH_SECS);
+
+    match time.duration_since(epoch) {
+        Ok(after) => i64::try_from(after.as_micros()).unwrap_or(i64::MAX),
+        Err(before) => i64::try_from(before.duration().as_micros())
+            .map(|micros| -micros)
+            .unwrap_or(i64::MIN),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_xlog_data() {
+        const DATA: &[u8] = b"w\0\0\0\0\x01\x5B\x9A\x28\0\0\0\0\x01\x5B\x9A\x60\0\x02\xB5\x4A\x71\x19\x7E\x22B\0\0\x02\x82\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0\0\x07\x3C";
+
+        let Replication::XLogData(data) = Replication::decode(Bytes::from_static(DATA)).unwrap()
+        else {
+            panic!("expected XLogData");
+        };
+
+        assert_eq!(data.wal_start, PgLsn(0x0000_0000_015B_9A28));
+        assert_eq!(data.wal_end, PgLsn(0x0000_0000_015B_9A60));
+        assert_eq!(data.timestamp, 0x0002_B54A_7119_7E22);
+        assert_eq!(data.data.first(), Some(&b'B'));
+    }
+
+    #[test]
+    fn it_decodes_primary_keepalive() {
+        const DATA: &[u8] = b"k\0\0\0\0\x01\x5B\x9A\x60\0\x02\xB5\x4A\x71\x19\x7E\x22\x01";
+
+        let Replication::PrimaryKeepalive(keepalive) =
+            Replication::decode(Bytes::from_static(DATA)).unwrap()
+        else {
+            panic!("expected PrimaryKeepalive");
+        };
+
+        assert_eq!(keepalive.wal_end, PgLsn(0x0000_0000_015B_9A60));
+        assert_eq!(keepalive.timestamp, 0x0002_B54A_7119_7E22);
+        assert!(keepalive.reply_requested);
+    }
+
+    #[test]
+    fn it_rejects_truncated_messages() {
+        assert!(Replication::decode(Bytes::from_static(b"")).is_err());
+        assert!(Replication::decode(Bytes::from_static(b"k\0\0")).is_err());
+        assert!(Replication::decode(Bytes::from_static(b"w\0\0\0\0")).is_err());
+        assert!(Replication::decode(Bytes::from_static(b"x")).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "replication-stream")]
+    fn it_encodes_standby_status_update() {
+        let update = StandbyStatusUpdate {
+            write: PgLsn(0x0000_0000_015B_9A60),
+            flush: PgLsn(0x0000_0000_015B_9A28),
+            apply: PgLsn(0x0000_0000_015B_9A28),
+            timestamp: 0x0002_B54A_7119_7E22,
+            reply_requested: false,
+        };
+
+        assert_eq!(
+            update.encode(),
+            b"r\0\0\0\0\x01\x5B\x9A\x60\0\0\0\0\x01\x5B\x9A\x28\0\0\0\0\x01\x5B\x9A\x28\0\x02\xB5\x4A\x71\x19\x7E\x22\0"
+        );
+    }
+
+    #[test]
+    fn it_converts_timestamps() {
+        let epoch = UNIX_EPOCH + Duration::from_secs(POSTGRES_EPOCH_SECS);
+
+        assert_eq!(timestamp_to_system_time(0), epoch);
+        assert_eq!(
+            timestamp_to_system_time(-1_000_000),
+            epoch - Duration::from_secs(1)
+        );
+        assert_eq!(system_time_to_timestamp(UNIX_EPOCH), -946_684_800_000_000);
+        assert_eq!(
+            system_time_to_timestamp(timestamp_to_system_time(0x0002_B54A_7119_7E22)),
+            0x0002_B54A_7119_7E22
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+
+use futures_core::stream::Stream;
+use futures_util::stream;
+
+use crate::types::PgLsn;
+
+use super::{
+    LogicalReplication, LogicalReplicationStream, Message, PgReplicationConnection,
+    ReplicationError,
+};
+
+/// A stream of only the [`Message`]s emitted with `pg_logical_emit_message()`, created with
+/// [`LogicalReplicationStream::only_messages()`].
+///
+/// This uses the WAL as a durable message bus: the changes of the published tables are
+/// skipped, and the slot advances on its own. Calling [`recv()`][Self::recv] again
+/// acknowledges the messages returned before, so each message is received at least once,
+/// also after a restart.
+///
+/// Messages are returned in the order the server sends them: non-transactional messages as
+/// soon as they are written, transactional ones once their transaction committed. The
+/// transactional messages of a streamed transaction are held back until the transaction
+/// commits, and dropped if it aborts.
+///
+/// Streaming must be started with [`PgOutputOptions::messages(true)`][super::PgOutputOptions::messages],
+/// otherwise the server doesn't send any messages.
+pub struct LogicalMessageStream {
+    stream: LogicalReplicationStream,
+    prefixes: Vec<String>,
+    /// The top-level xid of the streamed transaction currently being received.
+    streamed_xid: Option<u32>,
+    /// The transactional messages of streamed transactions, by top-level xid.
+    streamed: HashMap<u32, Vec<Message>>,
+    /// Committed messages of a streamed transaction that were not returned yet.
+    ready: VecDeque<Message>,
+    /// The position to confirm once all messages in `ready` were returned.
+    ready_lsn: Option<PgLsn>,
+    /// The position to confirm with the next call to `recv()`.
+    ack_lsn: Option<PgLsn>,
+}
+
+impl LogicalMessageStream {
+    pub(crate) fn new(stream: LogicalReplicationStream) -> Self {
+        Self {
+            stream,
+            prefixes: Vec::new(),
+            streamed_xid: None,
+            streamed: HashMap::new(),
+            ready: VecDeque::new(),
+            ready_lsn: None,
+            ack_lsn: None,
+        }
+    }
+
+    /// Only return messages with the prefix `prefix`.
+    ///
+    /// Can be called multiple times to receive the messages of several prefixes. By default,
+    /// messages with any prefix are returned.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// The underlying stream, e.g. to configure it.
+    pub fn stream_mut(&mut self) -> &mut LogicalReplicationStream {
+        &mut self.stream
+    }
+
+    /// Receive the next message, acknowledging the messages returned before.
+    ///
+    /// Returns `Ok(None)` if the server ended the stream.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv(&mut self) -> Result<Option<Message>, ReplicationError> {
+        if let Some(lsn) = self.ack_lsn.take() {
+            self.stream.set_confirmed_lsn(lsn);
+        }
+
+        loop {
+            if let Some(message) = self.ready.pop_front() {
+                if self.ready.is_empty() {
+                    self.ack_lsn = self.ready_lsn.take();
+                }
+
+                return Ok(Some(message));
+            }
+
+            let Some(replication) = self.stream.recv().await? else {
+                return Ok(None);
+            };
+
+            match replication {
+                LogicalReplication::Message(message) if self.matches(&message) => {
+                    match (message.transactional, self.streamed_xid) {
+                        (true, Some(xid)) => self.streamed.entry(xid).or_default().push(message),
+                        (false, _) => {
+                            self.ack_lsn = Some(message.lsn);
+
+                            return Ok(Some(message));
+                        }
+                        // confirmed with the commit of the transaction
+                        (true, None) => return Ok(Some(message)),
+                    }
+                }
+
+                // everything returned before was acknowledged by calling `recv()` again
+                LogicalReplication::Commit(commit) => self.stream.set_confirmed_lsn(commit.end_lsn),
+
+                LogicalReplication::StreamStart(start) => {
+                    // a first segment starts the transaction over
+                    if start.first_segment {
+                        self.streamed.remove(&start.xid);
+                    }
+
+                    self.streamed_xid = Some(start.xid);
+                }
+                LogicalReplication::StreamStop => self.streamed_xid = None,
+
+                LogicalReplication::StreamCommit(commit) => {
+                    self.ready
+                        .extend(self.streamed.remove(&commit.xid).unwrap_or_default());
+
+                    if self.ready.is_empty() {
+                        self.stream.set_confirmed_lsn(commit.end_lsn);
+                    } else {
+                        self.ready_lsn = Some(commit.end_lsn);
+                    }
+                }
+
+                LogicalReplication::StreamAbort(abort) if abort.is_top_level() => {
+                    self.streamed.remove(&abort.xid);
+                }
+
+                // the messages of an aborted subtransaction carry its xid
+                LogicalReplication::StreamAbort(abort) => {
+                    if let Some(messages) = self.streamed.get_mut(&abort.xid) {
+                        messages.retain(|message| message.xid != Some(abort.subxid));
+                    }
+                }
+
+                _ => {}
+            }
+        }
+    }
+
+    /// Stop streaming and return the replication connection, acknowledging the messages
+    /// returned before.
+    pub async fn finish(mut self) -> Result<PgReplicationConnection, ReplicationError> {
+        if let Some(lsn) = self.ack_lsn.take() {
+            self.stream.set_confirmed_lsn(lsn);
+        }
+
+        self.stream.finish().await
+    }
+
+    /// Consume this stream, returning a `Stream` of messages.
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Message, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv().await {
+                Ok(Some(message)) => Some((Ok(message), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+
+    fn matches(&self, message: &Message) -> bool {
+        self.prefixes.is_empty() || self.prefixes.contains(&message.prefix)
+    }
+}
+
+impl Debug for LogicalMessageStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogicalMessageStream")
+            .field("stream", &self.stream)
+            .field("prefixes", &self.prefixes)
+            .finish()
+    }
+}
@@ -0,0 +1,246 @@
+//! Logical replication using the streaming replication protocol and the `pgoutput` plugin,
+//! and physical replication of raw WAL.
+//!
+//! A [`PgReplicationConnection`] is a connection opened with `replication=database`, which
+//! can create and drop replication slots and start streaming changes from a slot. The
+//! changes are received from a [`LogicalReplicationStream`] as [`LogicalReplication`]
+//! messages, decoded from the [`pgoutput` message formats].
+//!
+//! The server needs to run with `wal_level = logical`, which can be checked with
+//! [`replication_settings()`] and is checked before creating a logical slot, and the user needs
+//! the `REPLICATION` attribute. The tables to replicate are selected with a publication:
+//!
+//! ```sql
+//! CREATE PUBLICATION my_pub FOR TABLE users, orders;
+//! ```
+//!
+//! # Delivery guarantees
+//!
+//! The slot only advances to the positions the consumer confirms with
+//! [`LogicalReplicationStream::set_confirmed_lsn()`], so by default every change is received
+//! at least once: the changes received but not confirmed before a restart are streamed again.
+//! Confirm a transaction only once its changes were durably processed, switch the stream to
+//! [`DeliveryMode::ConfirmOnCommit`] to have it confirm each transaction once the message after
+//! its commit is asked for, or to [`DeliveryMode::AtMostOnce`] to have it confirm transactions
+//! as they are received instead. [`LogicalReplicationStream::pump_into()`] applies the changes
+//! to a [`ChangeSink`] and confirms the transactions the sink flushed.
+//!
+//! # Replication from a standby
+//!
+//! Since Postgres 16, logical slots can also be created and streamed from on a physical
+//! standby, e.g. to offload change data capture from the primary. This requires:
+//!
+//! * `wal_level = logical` on the primary, where publications are created as well;
+//! * `hot_standby_feedback = on` on the standby, and preferably a physical slot
+//!   (`primary_slot_name`) for the standby, so that the primary doesn't remove rows the slot
+//!   still needs to decode.
+//!
+//! Creating a slot on a standby waits for the primary to log a snapshot of its running
+//! transactions, which can take a while on an idle primary; running
+//! `SELECT pg_log_standby_snapshot()` on the primary speeds it up. On older versions, or if
+//! the primary doesn't run with `wal_level = logical`,
+//! [`ReplicationError::StandbyNotSupported`] is returned.
+//! [`PgReplicationConnection::is_in_recovery()`] tells whether a server is a standby.
+//!
+//! # Tracing
+//!
+//! The time spent decoding each message, and mapping rows to JSON, is reported with `TRACE`
+//! events of the `sqlx::postgres::replication::timing` `tracing` target. Nothing is measured
+//! unless the target is enabled.
+//!
+//! The lifecycle of slots and streams is reported with `INFO` events of the
+//! `sqlx::postgres::replication::lifecycle` target: a slot being created or dropped, streaming
+//! being started and finished, a [`ReconnectingStream`] reconnecting after an error, and the
+//! confirmed position advancing, which is reported at most once a minute per stream. The events
+//! have the fields `slot_name`, `lsn` and, for reconnects and drops, `reason`, which gives a
+//! timeline of a consumer, e.g. to find out why changes were received again.
+//!
+//! # Decoding without a connection
+//!
+//! The `pgoutput` messages received by other means, e.g. forwarded through a queue, are decoded
+//! with [`decode_logical()`] and a [`LogicalDecodeContext`], or read from a capture with a
+//! [`ReplayReader`]; neither needs a connection or an async runtime. The replication
+//! connections and streams are behind the `replication-stream` feature of `sqlx-postgres`,
+//! which is enabled by default and by the `postgres` feature of `sqlx`; depending on
+//! `sqlx-postgres` with `default-features = false` builds only the decoders of replication
+//! messages and the mapping of their rows. The decoders still share the error type of the
+//! driver, [`PgLsn`] and the [`Decode`] implementations that [`TupleData::try_decode()`] uses,
+//! so the rest of the driver is compiled as well, though it needs no runtime unless a
+//! connection is opened.
+//!
+//! [`pgoutput` message formats]: https://www.postgresql.org/docs/current/protocol-logicalrep-message-formats.html
+//! [`PgLsn`]: crate::types::PgLsn
+//! [`Decode`]: sqlx_core::decode::Decode
+
+#[cfg(feature = "replication-stream")]
+mod apply;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "replication-stream")]
+mod cancel;
+#[cfg(feature = "replication-stream")]
+mod channel;
+#[cfg(feature = "replication-stream")]
+mod clock;
+#[cfg(feature = "replication-stream")]
+mod connection;
+#[cfg(feature = "replication-stream")]
+mod copy_both;
+#[cfg(feature = "decoderbufs")]
+pub mod decoderbufs;
+#[cfg(feature = "replication-stream")]
+mod enums;
+mod error;
+#[cfg(test)]
+mod frames;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(feature = "replication-stream")]
+mod keepalives;
+mod logical;
+#[cfg(feature = "replication-stream")]
+mod manager;
+mod mapping;
+mod message;
+#[cfg(feature = "replication-stream")]
+mod message_stream;
+mod modifier;
+#[cfg(feature = "replication-stream")]
+mod notice;
+#[cfg(feature = "replication-stream")]
+mod observer;
+#[cfg(feature = "replication-stream")]
+mod offset;
+#[cfg(feature = "replication-stream")]
+mod options;
+#[cfg(feature = "replication-stream")]
+mod physical;
+#[cfg(feature = "replication-stream")]
+mod publication;
+#[cfg(feature = "replication-stream")]
+mod reconnect;
+mod replay;
+#[cfg(feature = "replication-stream")]
+mod retry;
+#[cfg(feature = "replication-stream")]
+mod settings;
+#[cfg(feature = "replication-stream")]
+mod sink;
+#[cfg(feature = "replication-stream")]
+mod sizes;
+#[cfg(feature = "replication-stream")]
+mod slot;
+#[cfg(feature = "replication-stream")]
+mod stream;
+#[cfg(feature = "replication-stream")]
+mod system;
+#[cfg(feature = "replication-stream")]
+mod table;
+#[cfg(feature = "replication-stream")]
+mod transaction;
+mod tuple;
+#[cfg(feature = "wal")]
+pub mod wal;
+
+#[cfg(feature = "replication-stream")]
+pub use apply::ApplyStatement;
+#[cfg(feature = "replication-stream")]
+pub use cancel::CancelHandle;
+#[cfg(feature = "replication-stream")]
+pub use channel::ReplicationChannel;
+#[cfg(feature = "replication-stream")]
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "replication-stream")]
+pub use connection::{PgReplicationConnection, ServerRole, StartValidation};
+#[cfg(feature = "replication-stream")]
+pub use enums::{enum_types, EnumType};
+pub use error::ReplicationError;
+#[cfg(feature = "replication-stream")]
+pub use keepalives::KeepaliveHistory;
+pub use logical::{
+    decode_logical, Begin, BeginPrepare, Column, Commit, CommitFlags, CommitPrepared, Delete,
+    Insert, LogicalDecodeContext, LogicalReplication, Message, Origin, Prepare, PrepareFlags,
+    Relation, ReplicaIdentity, ResolvedTruncate, RollbackPrepared, StreamAbort, StreamCommit,
+    StreamStart, Truncate, Type, Update,
+};
+#[cfg(feature = "replication-stream")]
+pub use manager::{ReplicationManager, SlotMessage, SourcedChange};
+#[cfg(feature = "json")]
+pub use mapping::ValueMapping;
+pub use mapping::{
+    BeforeImage, FromRowKey, LazyColumn, LazyRow, Projection, RowKey, UpdateIdentity,
+};
+pub use message::{PrimaryKeepalive, Replication, XLogData};
+#[cfg(feature = "replication-stream")]
+pub use message_stream::LogicalMessageStream;
+pub use modifier::TypeModifier;
+#[cfg(feature = "replication-stream")]
+pub use notice::ReplicationNotice;
+#[cfg(feature = "replication-stream")]
+pub use observer::ReplicationObserver;
+#[cfg(feature = "replication-stream")]
+pub use offset::{OffsetStore, PgTableOffsetStore};
+#[cfg(feature = "replication-stream")]
+pub use options::PgOutputOptions;
+#[cfg(feature = "replication-stream")]
+pub use physical::{ArchivedWal, PhysicalReplication, PhysicalReplicationStream};
+#[cfg(feature = "replication-stream")]
+pub use publication::{
+    copy_publication_snapshot, copy_publication_table, create_publication_if_missing,
+    publication_row_filters, publication_tables, CreatePublication, PublicationChange,
+    PublicationRowFilter, PublicationTable, PublicationWatcher,
+};
+#[cfg(feature = "replication-stream")]
+pub use reconnect::{ReconnectingStream, SlotLost};
+pub use replay::ReplayReader;
+#[cfg(feature = "replication-stream")]
+pub use retry::{is_transient, RetryPolicy};
+#[cfg(feature = "replication-stream")]
+pub use settings::{replication_settings, ReplicationSettings};
+#[cfg(feature = "replication-stream")]
+pub use sink::ChangeSink;
+#[cfg(feature = "json")]
+#[cfg(feature = "replication-stream")]
+pub use sink::JsonLinesSink;
+#[cfg(feature = "replication-stream")]
+pub use sizes::MessageSizes;
+#[cfg(feature = "replication-stream")]
+pub use slot::{
+    advance_replication_slot, import_snapshot, retained_wal_bytes, slot_position_diff,
+    CreateReplicationSlot, IdentifySystem, PgReplicationSlot, SlotPositionDiff, SnapshotAction,
+    StartPosition,
+};
+#[cfg(feature = "replication-stream")]
+pub use stream::{
+    ContiguityCheck, DeadLetterHandler, DecodeErrorPolicy, DeliveryMode, LogicalReplicationStream,
+    ReplicationHealth, ReplicationLag, StreamEvent,
+};
+#[cfg(feature = "replication-stream")]
+pub use system::SystemValue;
+#[cfg(feature = "replication-stream")]
+pub use table::{Change, FromReplicationRow, TableStream};
+#[cfg(feature = "replication-stream")]
+pub use transaction::{
+    PendingTxInfo, ReplicatedTransaction, TransactionStream, TransactionViolation,
+};
+pub use tuple::{MaybeDecoded, TupleData, Tuples, UnknownTypePolicy};
+
+/// The `tracing` target of the `TRACE` events that report how long decoding and mapping
+/// messages took, which are only measured if the target is enabled.
+#[cfg(any(feature = "replication-stream", feature = "json"))]
+const TIMING_TARGET: &str = "sqlx::postgres::replication::timing";
+
+/// The `tracing` target of the `INFO` events that report the lifecycle of slots and streams.
+#[cfg(feature = "replication-stream")]
+const LIFECYCLE_TARGET: &str = "sqlx::postgres::replication::lifecycle";
+
+/// Quote an identifier (e.g. a slot name) for a replication command.
+fn quote_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Quote a string literal for a replication command or query.
+#[cfg(feature = "replication-stream")]
+fn quote_literal(literal: &str) -> String {
+    format!("'{}'", literal.replace('\'', "''"))
+}
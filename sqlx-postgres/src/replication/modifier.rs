@@ -0,0 +1,174 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::type_info::PgType;
+use crate::types::Oid;
+
+use super::Column;
+
+/// The size of the varlena header that is included in the type modifiers of `varchar`, `char`
+/// and `numeric`.
+const VARHDRSZ: i32 = 4;
+
+/// The interpreted type modifier (`atttypmod`) of a [`Column`], as returned by
+/// [`Column::modifier()`].
+///
+/// The [`Display`] implementation writes the modifier like in a column definition, e.g.
+/// `(255)` or `(10,2)`, or nothing at all for [`TypeModifier::None`] and
+/// [`TypeModifier::Unknown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TypeModifier {
+    /// The column has no type modifier, e.g. `varchar` or `numeric` without arguments.
+    None,
+
+    /// The maximum length of a `varchar(n)` or `char(n)`, in characters, or the length of a
+    /// `bit(n)` or `varbit(n)`, in bits.
+    Length(u32),
+
+    /// The precision and scale of a `numeric(p, s)`.
+    Numeric {
+        precision: u16,
+        /// Negative scales are allowed since Postgres 15.
+        scale: i16,
+    },
+
+    /// The fractional seconds precision of a `time(p)`, `timetz(p)`, `timestamp(p)` or
+    /// `timestamptz(p)`.
+    Precision(u32),
+
+    /// The modifier of a type whose encoding is not known, e.g. of a user-defined type.
+    Unknown(i32),
+}
+
+impl TypeModifier {
+    /// Interpret the type modifier `modifier` of a column of the type `type_id`.
+    pub fn new(type_id: Oid, modifier: i32) -> Self {
+        if modifier < 0 {
+            return TypeModifier::None;
+        }
+
+        match PgType::try_from_oid(type_id) {
+            Some(PgType::Varchar | PgType::Bpchar) => length(modifier - VARHDRSZ),
+
+            Some(PgType::Bit | PgType::Varbit) => length(modifier),
+
+            Some(PgType::Numeric) => {
+                let modifier = modifier - VARHDRSZ;
+
+                // the scale is stored as a signed 11-bit integer in the lower bits
+                let scale = ((modifier & 0x7FF) ^ 0x400) - 0x400;
+
+                match (u16::try_from(modifier >> 16), i16::try_from(scale)) {
+                    (Ok(precision), Ok(scale)) => TypeModifier::Numeric { precision, scale },
+                    _ => TypeModifier::Unknown(modifier + VARHDRSZ),
+                }
+            }
+
+            Some(PgType::Time | PgType::Timetz | PgType::Timestamp | PgType::Timestamptz) => {
+                u32::try_from(modifier).map_or(TypeModifier::Unknown(modifier), |precision| {
+                    TypeModifier::Precision(precision)
+                })
+            }
+
+            _ => TypeModifier::Unknown(modifier),
+        }
+    }
+}
+
+fn length(length: i32) -> TypeModifier {
+    u32::try_from(length).map_or(TypeModifier::None, TypeModifier::Length)
+}
+
+impl Display for TypeModifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeModifier::None | TypeModifier::Unknown(_) => Ok(()),
+            TypeModifier::Length(length) => write!(f, "({length})"),
+            TypeModifier::Numeric { precision, scale } => write!(f, "({precision},{scale})"),
+            TypeModifier::Precision(precision) => write!(f, "({precision})"),
+        }
+    }
+}
+
+impl Column {
+    /// Interpret the [`type_modifier`][Self::type_modifier] of the column for its type, e.g.
+    /// the length of a `varchar(n)` or the precision and scale of a `numeric(p, s)`.
+    pub fn modifier(&self) -> TypeModifier {
+        TypeModifier::new(self.type_id, self.type_modifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `atttypmod` of columns created with the given types on PostgreSQL 15
+
+    #[test]
+    fn it_interprets_lengths() {
+        // varchar(255), char(10), bit(8), varbit(64)
+        assert_eq!(TypeModifier::new(Oid(1043), 259), TypeModifier::Length(255));
+        assert_eq!(TypeModifier::new(Oid(1042), 14), TypeModifier::Length(10));
+        assert_eq!(TypeModifier::new(Oid(1560), 8), TypeModifier::Length(8));
+        assert_eq!(TypeModifier::new(Oid(1562), 64), TypeModifier::Length(64));
+
+        // varchar
+        assert_eq!(TypeModifier::new(Oid(1043), -1), TypeModifier::None);
+    }
+
+    #[test]
+    fn it_interprets_numeric_precision_and_scale() {
+        // numeric(10,2), numeric(5), numeric(3,-2)
+        assert_eq!(
+            TypeModifier::new(Oid(1700), 655366),
+            TypeModifier::Numeric {
+                precision: 10,
+                scale: 2
+            }
+        );
+        assert_eq!(
+            TypeModifier::new(Oid(1700), 327684),
+            TypeModifier::Numeric {
+                precision: 5,
+                scale: 0
+            }
+        );
+        assert_eq!(
+            TypeModifier::new(Oid(1700), 198658),
+            TypeModifier::Numeric {
+                precision: 3,
+                scale: -2
+            }
+        );
+
+        assert_eq!(TypeModifier::new(Oid(1700), -1), TypeModifier::None);
+    }
+
+    #[test]
+    fn it_interprets_time_precision() {
+        // timestamp(3), timestamptz(6), time(0)
+        assert_eq!(TypeModifier::new(Oid(1114), 3), TypeModifier::Precision(3));
+        assert_eq!(TypeModifier::new(Oid(1184), 6), TypeModifier::Precision(6));
+        assert_eq!(TypeModifier::new(Oid(1083), 0), TypeModifier::Precision(0));
+    }
+
+    #[test]
+    fn it_returns_unknown_modifiers_raw() {
+        assert_eq!(TypeModifier::new(Oid(16385), 42), TypeModifier::Unknown(42));
+    }
+
+    #[test]
+    fn it_formats_modifiers_for_column_definitions() {
+        assert_eq!(TypeModifier::Length(255).to_string(), "(255)");
+        assert_eq!(
+            TypeModifier::Numeric {
+                precision: 10,
+                scale: 2
+            }
+            .to_string(),
+            "(10,2)"
+        );
+        assert_eq!(TypeModifier::Precision(3).to_string(), "(3)");
+        assert_eq!(TypeModifier::None.to_string(), "");
+    }
+}
@@ -0,0 +1,54 @@
+use crate::message::Notice;
+use crate::PgSeverity;
+
+/// A notice sent by the server while streaming, like "logical decoding found consistent point".
+///
+/// Notices are logged like the notices of any other query, unless a handler is set with
+/// [`LogicalReplicationStream::set_notice_handler()`][super::LogicalReplicationStream::set_notice_handler].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationNotice {
+    /// The severity of the notice, e.g. `NOTICE` or `WARNING`.
+    pub severity: PgSeverity,
+    /// The SQLSTATE code of the notice.
+    pub code: String,
+    /// The primary message of the notice.
+    pub message: String,
+    /// An optional secondary message with more details.
+    pub detail: Option<String>,
+    /// An optional suggestion what to do about the reported condition.
+    pub hint: Option<String>,
+}
+
+impl From<&Notice> for ReplicationNotice {
+    fn from(notice: &Notice) -> Self {
+        Self {
+            severity: notice.severity(),
+            code: notice.code().to_owned(),
+            message: notice.message().to_owned(),
+            detail: notice.get(b'D').map(ToOwned::to_owned),
+            hint: notice.get(b'H').map(ToOwned::to_owned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::message::BackendMessage;
+
+    #[test]
+    fn it_converts_notices() {
+        const DATA: &[u8] = b"SWARNING\0VWARNING\0C01000\0Mslot is lagging\0Dby a lot\0\0";
+
+        let notice =
+            ReplicationNotice::from(&Notice::decode_body(Bytes::from_static(DATA)).unwrap());
+
+        assert_eq!(notice.severity, PgSeverity::Warning);
+        assert_eq!(notice.code, "01000");
+        assert_eq!(notice.message, "slot is lagging");
+        assert_eq!(notice.detail.as_deref(), Some("by a lot"));
+        assert_eq!(notice.hint, None);
+    }
+}
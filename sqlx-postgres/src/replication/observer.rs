@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use crate::error::Error;
+
+use super::{PrimaryKeepalive, XLogData};
+
+/// Receives events of a replication stream, e.g. to record metrics, set with
+/// [`LogicalReplicationStream::set_observer()`][super::LogicalReplicationStream::set_observer]
+/// or [`PhysicalReplicationStream::set_observer()`][super::PhysicalReplicationStream::set_observer].
+///
+/// All methods have empty default implementations, so an observer only implements the events
+/// it is interested in. The methods are called on the task that receives from the stream, so
+/// they should return quickly.
+///
+/// An observer can be shared by several streams by passing it as an `Arc`:
+///
+/// ```rust
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+///
+/// use sqlx::postgres::replication::ReplicationObserver;
+///
+/// #[derive(Default)]
+/// struct FrameBytes(AtomicU64);
+///
+/// impl ReplicationObserver for FrameBytes {
+///     fn on_frame(&self, _kind: u8, len: usize) {
+///         self.0.fetch_add(len as u64, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let observer = Arc::new(FrameBytes::default());
+/// // stream.set_observer(observer.clone());
+/// ```
+pub trait ReplicationObserver: Send + Sync + 'static {
+    /// Called for each `CopyData` frame received from the server, before it is decoded, with
+    /// its leading message byte (`b'w'` for [`XLogData`][super::XLogData], `b'k'` for a
+    /// [`PrimaryKeepalive`][super::PrimaryKeepalive]) and the length of its payload in bytes,
+    /// including that byte.
+    fn on_frame(&self, kind: u8, len: usize) {
+        let _ = (kind, len);
+    }
+
+    /// Called for each [`PrimaryKeepalive`] received from the server, after the stream
+    /// recorded its position and before it answers a requested reply.
+    ///
+    /// Keepalives are not returned by `recv()`, but they show that the server and the
+    /// connection are alive even if there is no data to send, e.g. on an idle database or
+    /// before the first change of a new stream.
+    fn on_keepalive(&self, keepalive: &PrimaryKeepalive) {
+        let _ = keepalive;
+    }
+
+    /// Called for each message of a logical stream that cannot be decoded, with the error and
+    /// whether the [decode error policy][super::DecodeErrorPolicy] skips the message, before
+    /// the policy handles it.
+    ///
+    /// Skipped messages are changes the consumer never sees, so a rising count of them, e.g.
+    /// after an upgrade of the server, is worth an alert; the stream keeps the total as
+    /// [`decode_errors()`][super::LogicalReplicationStream::decode_errors] as well.
+    fn on_decode_error(&self, data: &XLogData, error: &Error, skipped: bool) {
+        let _ = (data, error, skipped);
+    }
+}
+
+impl<T> ReplicationObserver for Arc<T>
+where
+    T: ReplicationObserver + ?Sized,
+{
+    fn on_frame(&self, kind: u8, len: usize) {
+        (**self).on_frame(kind, len);
+    }
+
+    fn on_keepalive(&self, keepalive: &PrimaryKeepalive) {
+        (**self).on_keepalive(keepalive);
+    }
+
+    fn on_decode_error(&self, data: &XLogData, error: &Error, skipped: bool) {
+        (**self).on_decode_error(data, error, skipped);
+    }
+}
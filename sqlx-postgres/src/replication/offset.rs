@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use futures_core::future::BoxFuture;
+use sqlx_core::row::Row;
+
+use crate::error::Error;
+use crate::types::PgLsn;
+use crate::PgPool;
+
+use super::quote_ident;
+
+/// Persists the confirmed position of a replication slot outside of the slot, e.g. in a table,
+/// in Redis or in a file, to restore it on startup.
+///
+/// A stream started with
+/// [`PgReplicationConnection::start_logical_replication_with_store()`][super::PgReplicationConnection::start_logical_replication_with_store]
+/// starts at the position [loaded][Self::load] from the store, and
+/// [stores][Self::store] its [confirmed position][super::LogicalReplicationStream::set_confirmed_lsn]
+/// before it reports it to the server, so that the store is never behind the slot. Streaming
+/// starts at the later of the loaded position and the position of the slot, so a store that is
+/// ahead of the slot, e.g. because the connection was lost after storing, skips the changes
+/// processed before instead of receiving them again.
+///
+/// The methods return boxed futures, so that the trait can be used as a trait object:
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// use futures_util::future::{self, BoxFuture, FutureExt};
+/// use sqlx::postgres::replication::OffsetStore;
+/// use sqlx::postgres::types::PgLsn;
+///
+/// #[derive(Default)]
+/// struct MemoryStore(Mutex<HashMap<String, PgLsn>>);
+///
+/// impl OffsetStore for MemoryStore {
+///     fn load<'a>(&'a self, slot: &'a str) -> BoxFuture<'a, Result<Option<PgLsn>, sqlx::Error>> {
+///         future::ready(Ok(self.0.lock().unwrap().get(slot).copied())).boxed()
+///     }
+///
+///     fn store<'a>(&'a self, slot: &'a str, lsn: PgLsn) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+///         self.0.lock().unwrap().insert(slot.to_owned(), lsn);
+///         future::ready(Ok(())).boxed()
+///     }
+/// }
+/// ```
+pub trait OffsetStore: Send + Sync + 'static {
+    /// Load the stored position of `slot`; `None` if none was stored yet.
+    fn load<'a>(&'a self, slot: &'a str) -> BoxFuture<'a, Result<Option<PgLsn>, Error>>;
+
+    /// Store `lsn` as the position of `slot`, replacing the position stored before.
+    ///
+    /// The positions stored for a slot only increase.
+    fn store<'a>(&'a self, slot: &'a str, lsn: PgLsn) -> BoxFuture<'a, Result<(), Error>>;
+}
+
+impl<T> OffsetStore for Arc<T>
+where
+    T: OffsetStore + ?Sized,
+{
+    fn load<'a>(&'a self, slot: &'a str) -> BoxFuture<'a, Result<Option<PgLsn>, Error>> {
+        (**self).load(slot)
+    }
+
+    fn store<'a>(&'a self, slot: &'a str, lsn: PgLsn) -> BoxFuture<'a, Result<(), Error>> {
+        (**self).store(slot, lsn)
+    }
+}
+
+/// An [`OffsetStore`] that stores the positions of the slots in a Postgres table, with a row
+/// of the slot name and its `pg_lsn` position per slot.
+///
+/// The table is `replication_offsets` by default, and is created with
+/// [`create_table()`][Self::create_table]:
+///
+/// ```sql
+/// CREATE TABLE IF NOT EXISTS replication_offsets (slot_name TEXT PRIMARY KEY, lsn PG_LSN NOT NULL)
+/// ```
+///
+/// The positions are written with a normal (non-replication) connection of the pool, which can
+/// be a connection to another database than the one that is replicated, e.g. the database the
+/// changes are written to.
+#[derive(Debug, Clone)]
+pub struct PgTableOffsetStore {
+    pool: PgPool,
+    table: String,
+}
+
+impl PgTableOffsetStore {
+    /// Store the positions in the table `replication_offsets`, using connections of `pool`.
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            table: quote_ident("replication_offsets"),
+        }
+    }
+
+    /// Store the positions in the table `table`, in the schema `schema` or the first schema of
+    /// the `search_path` if it is `None`.
+    pub fn table(mut self, schema: Option<&str>, table: &str) -> Self {
+        self.table = match schema {
+            Some(schema) => format!("{}.{}", quote_ident(schema), quote_ident(table)),
+            None => quote_ident(table),
+        };
+        self
+    }
+
+    /// Create the table if it does not exist.
+    pub async fn create_table(&self) -> Result<(), Error> {
+        crate::query::query(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (slot_name TEXT PRIMARY KEY, lsn PG_LSN NOT NULL)",
+            self.table
+        ))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl OffsetStore for PgTableOffsetStore {
+    fn load<'a>(&'a self, slot: &'a str) -> BoxFuture<'a, Result<Option<PgLsn>, Error>> {
+        Box::pin(async move {
+            let row = crate::query::query(&format!(
+                "SELECT lsn FROM {} WHERE slot_name = $1",
+                self.table
+            ))
+            .bind(slot)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            row.map(|row| row.try_get(0)).transpose()
+        })
+    }
+
+    fn store<'a>(&'a self, slot: &'a str, lsn: PgLsn) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            // never move a stored position backwards, e.g. if two streams of a slot raced
+            crate::query::query(&format!(
+                "INSERT INTO {table} AS o (slot_name, lsn) VALUES ($1, $2) \
+                 ON CONFLICT (slot_name) DO UPDATE SET lsn = GREATEST(o.lsn, EXCLUDED.lsn)",
+                table = self.table
+            ))
+            .bind(slot)
+            .bind(lsn)
+            .execute(&self.pool)
+            .await?;
+
+            Ok(())
+        })
+    }
+}
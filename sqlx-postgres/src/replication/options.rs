@@ -0,0 +1,261 @@
+use std::fmt::Write;
+
+use super::{quote_ident, quote_literal, ReplicationError};
+
+/// Options for the `pgoutput` logical decoding output plugin, passed to `START_REPLICATION`.
+///
+/// <https://www.postgresql.org/docs/current/protocol-logical-replication.html#PROTOCOL-LOGICAL-REPLICATION-PARAMS>
+///
+/// ```rust
+/// # use sqlx::postgres::replication::PgOutputOptions;
+/// let options = PgOutputOptions::new(["my_publication"])
+///     .proto_version(2)
+///     .streaming(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PgOutputOptions {
+    pub(crate) proto_version: u32,
+    pub(crate) publication_names: Vec<String>,
+    pub(crate) binary: bool,
+    pub(crate) messages: bool,
+    pub(crate) streaming: bool,
+    pub(crate) two_phase: bool,
+    pub(crate) origin: Option<String>,
+}
+
+impl PgOutputOptions {
+    /// Create options replicating changes of the given publications, using protocol version 1.
+    ///
+    /// The names are used as given, so case and special characters are preserved. At least
+    /// one publication is required; an empty list is rejected when streaming is started.
+    pub fn new<I, S>(publication_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            proto_version: 1,
+            publication_names: publication_names.into_iter().map(Into::into).collect(),
+            binary: false,
+            messages: false,
+            streaming: false,
+            two_phase: false,
+            origin: None,
+        }
+    }
+
+    /// Sets the protocol version.
+    ///
+    /// Version 2 (Postgres 14+) is required for `streaming`, version 3 (Postgres 15+) for
+    /// `two_phase`, and version 4 (Postgres 16+) for parallel streaming.
+    pub fn proto_version(mut self, proto_version: u32) -> Self {
+        self.proto_version = proto_version;
+        self
+    }
+
+    /// Sets whether column values are sent in the binary format instead of the text format,
+    /// where the data type supports it.
+    pub fn binary(mut self, binary: bool) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Sets whether messages written with `pg_logical_emit_message()` are sent.
+    pub fn messages(mut self, messages: bool) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Sets whether large in-progress transactions are streamed before they commit.
+    pub fn streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Sets whether prepared transactions are decoded at `PREPARE TRANSACTION` time.
+    pub fn two_phase(mut self, two_phase: bool) -> Self {
+        self.two_phase = two_phase;
+        self
+    }
+
+    /// Only send changes with the given origin; `none` for changes without an origin, `any`
+    /// (the default) for all changes.
+    pub fn origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        self
+    }
+
+    /// Render the options as the parenthesized option list of `START_REPLICATION`.
+    pub(crate) fn to_option_list(&self) -> Result<String, ReplicationError> {
+        if self.publication_names.is_empty() {
+            return Err(ReplicationError::InvalidOptions {
+                reason: "at least one publication name is required".into(),
+            });
+        }
+
+        if self.publication_names.iter().any(String::is_empty) {
+            return Err(ReplicationError::InvalidOptions {
+                reason: "publication names must not be empty".into(),
+            });
+        }
+
+        // `pgoutput` splits the names like a list of SQL identifiers, so names that would be
+        // changed (e.g. lowercased) or split as an unquoted identifier are quoted
+        let publication_names = self
+            .publication_names
+            .iter()
+            .map(|name| {
+                if is_plain_identifier(name) {
+                    name.clone()
+                } else {
+                    quote_ident(name)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut list = format!(
+            "proto_version {}, publication_names {}",
+            quote_literal(&self.proto_version.to_string()),
+            quote_literal(&publication_names)
+        );
+
+        for (name, enabled) in [
+            ("binary", self.binary),
+            ("messages", self.messages),
+            ("streaming", self.streaming),
+            ("two_phase", self.two_phase),
+        ] {
+            if enabled {
+                let _ = write!(list, ", {name} 'true'");
+            }
+        }
+
+        if let Some(origin) = &self.origin {
+            let _ = write!(list, ", origin {}", quote_literal(origin));
+        }
+
+        Ok(list)
+    }
+
+    /// Check that a server with the version number `version` (e.g. `150004`) supports the
+    /// protocol version and the options, which the server otherwise only reports once
+    /// streaming is started, or for some options not at all.
+    pub(crate) fn check_server_version(&self, version: u32) -> Result<(), ReplicationError> {
+        let max_proto_version = match version {
+            ..=139999 => 1,
+            140000..=149999 => 2,
+            150000..=159999 => 3,
+            _ => 4,
+        };
+
+        let reason = if self.proto_version == 0 || self.proto_version > max_proto_version {
+            format!(
+                "protocol version {} is not supported by the server, \
+                 which supports versions 1 to {max_proto_version}",
+                self.proto_version
+            )
+        } else if self.streaming && self.proto_version < 2 {
+            "`streaming` requires protocol version 2".into()
+        } else if self.two_phase && self.proto_version < 3 {
+            "`two_phase` requires protocol version 3".into()
+        } else if (self.binary || self.messages) && version < 140000 {
+            "`binary` and `messages` require Postgres 14".into()
+        } else if self.origin.is_some() && version < 160000 {
+            "`origin` requires Postgres 16".into()
+        } else {
+            return Ok(());
+        };
+
+        Err(ReplicationError::InvalidOptions { reason })
+    }
+}
+
+/// Returns `true` if `name` is read unchanged as an unquoted identifier.
+fn is_plain_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '$')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_options_against_the_server_version() {
+        let options = || PgOutputOptions::new(["pub"]);
+
+        assert!(options().check_server_version(100000).is_ok());
+        assert!(options()
+            .proto_version(4)
+            .streaming(true)
+            .two_phase(true)
+            .origin("none")
+            .check_server_version(160000)
+            .is_ok());
+
+        for (options, version) in [
+            (options().proto_version(0), 160000),
+            (options().proto_version(2), 130000),
+            (options().proto_version(4), 150000),
+            (options().streaming(true), 160000),
+            (options().proto_version(2).two_phase(true), 160000),
+            (options().binary(true), 130000),
+            (options().proto_version(3).origin("none"), 150000),
+        ] {
+            assert!(
+                matches!(
+                    options.check_server_version(version),
+                    Err(ReplicationError::InvalidOptions { .. })
+                ),
+                "{options:?} accepted for {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_renders_option_list() {
+        assert_eq!(
+            PgOutputOptions::new(["a", "b"]).to_option_list().unwrap(),
+            "proto_version '1', publication_names 'a,b'"
+        );
+
+        assert_eq!(
+            PgOutputOptions::new(["pub"])
+                .proto_version(2)
+                .streaming(true)
+                .messages(true)
+                .origin("none")
+                .to_option_list()
+                .unwrap(),
+            "proto_version '2', publication_names 'pub', messages 'true', streaming 'true', origin 'none'"
+        );
+    }
+
+    #[test]
+    fn it_quotes_publication_names() {
+        assert_eq!(
+            PgOutputOptions::new(["My Pub", "orders_2024", "a\"b", "it's"])
+                .to_option_list()
+                .unwrap(),
+            r#"proto_version '1', publication_names '"My Pub",orders_2024,"a""b","it''s"'"#
+        );
+    }
+
+    #[test]
+    fn it_rejects_empty_publication_names() {
+        assert!(matches!(
+            PgOutputOptions::new(Vec::<String>::new()).to_option_list(),
+            Err(ReplicationError::InvalidOptions { .. })
+        ));
+
+        assert!(matches!(
+            PgOutputOptions::new(["pub", ""]).to_option_list(),
+            Err(ReplicationError::InvalidOptions { .. })
+        ));
+    }
+}
@@ -0,0 +1,496 @@
+use std::cmp;
+use std::fmt::{self, Debug, Formatter};
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_core::stream::Stream;
+use futures_util::io::{AsyncWrite, AsyncWriteExt};
+use futures_util::{stream, FutureExt};
+use sqlx_core::bytes::Bytes;
+
+use crate::error::Error;
+use crate::message::DataRow;
+use crate::types::PgLsn;
+
+use super::clock::SharedClock;
+use super::stream::{Received, StreamCore};
+use super::{
+    CancelHandle, Clock, ContiguityCheck, KeepaliveHistory, MessageSizes, PgReplicationConnection,
+    ReplicationError, ReplicationHealth, ReplicationLag, ReplicationNotice, ReplicationObserver,
+    XLogData,
+};
+
+/// A message received from a [`PhysicalReplicationStream`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum PhysicalReplication {
+    /// A chunk of raw WAL.
+    XLogData(XLogData),
+
+    /// The end of the timeline the stream was started on was reached, because the server
+    /// switched to a new timeline (e.g. after a standby was promoted).
+    ///
+    /// This is the last message of the stream. WAL of the next timeline is streamed by
+    /// starting physical replication on `next_tli` at `switch_lsn` on a new stream.
+    TimelineSwitch {
+        /// The next timeline in the server's history.
+        next_tli: u32,
+        /// The position at which the server switched to the next timeline.
+        switch_lsn: PgLsn,
+    },
+}
+
+/// How [`PhysicalReplicationStream::archive()`] ended, i.e. how far the WAL was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ArchivedWal {
+    /// The position the copy started at.
+    pub start_lsn: PgLsn,
+    /// The position up to which WAL was written and flushed, and confirmed to the server.
+    pub end_lsn: PgLsn,
+    /// The next timeline and the position of the switch to it, if the stream ended because the
+    /// end of its timeline was reached; see [`PhysicalReplication::TimelineSwitch`].
+    pub next_timeline: Option<(u32, PgLsn)>,
+}
+
+/// A stream of WAL from physical replication, started with
+/// [`PgReplicationConnection::start_physical_replication()`].
+///
+/// Like [`LogicalReplicationStream`][super::LogicalReplicationStream], the stream answers
+/// keepalive messages and periodically reports its position to the server. The position
+/// reported as flushed is only advanced by [`set_confirmed_lsn()`][Self::set_confirmed_lsn];
+/// call it once the WAL up to an LSN has been durably written.
+pub struct PhysicalReplicationStream {
+    core: StreamCore,
+}
+
+impl PhysicalReplicationStream {
+    pub(crate) fn new(conn: PgReplicationConnection, slot: String, start_lsn: PgLsn) -> Self {
+        Self {
+            core: StreamCore::new(conn, slot, start_lsn),
+        }
+    }
+
+    /// The name of the replication slot this stream uses, or an empty string if it does not
+    /// use a slot.
+    pub fn slot(&self) -> &str {
+        &self.core.slot
+    }
+
+    /// The end of the latest WAL received from the server.
+    pub fn received_lsn(&self) -> PgLsn {
+        self.core.received_lsn
+    }
+
+    /// The position up to which WAL has been written, as reported to the server with the next
+    /// status update.
+    pub fn confirmed_lsn(&self) -> PgLsn {
+        self.core.confirmed_lsn
+    }
+
+    /// Set the position up to which WAL has been written.
+    ///
+    /// The position is reported to the server with the next status update. Positions lower
+    /// than the current one are ignored.
+    pub fn set_confirmed_lsn(&mut self, lsn: PgLsn) {
+        self.core.set_confirmed_lsn(lsn);
+    }
+
+    /// Set the clock for the timestamps of status updates and for [`lag()`][Self::lag]; see
+    /// [`LogicalReplicationStream::set_clock()`][super::LogicalReplicationStream::set_clock].
+    pub fn set_clock(&mut self, clock: impl Clock) {
+        self.core.clock = SharedClock::new(clock);
+    }
+
+    /// Set an observer that is notified of the events of this stream; see
+    /// [`ReplicationObserver`].
+    pub fn set_observer(&mut self, observer: impl ReplicationObserver) {
+        self.core.observer = Some(Arc::new(observer));
+    }
+
+    /// Transform the payload of each `CopyData` frame with `decoder` before it is decoded; see
+    /// [`LogicalReplicationStream::set_frame_decoder()`][super::LogicalReplicationStream::set_frame_decoder].
+    pub fn set_frame_decoder<F>(&mut self, decoder: F)
+    where
+        F: FnMut(Bytes) -> Result<Bytes, Error> + Send + 'static,
+    {
+        self.core.frame_decoder = Some(Box::new(decoder));
+    }
+
+    /// A handle to cancel a pending or later [`recv()`][Self::recv] from another task; see
+    /// [`CancelHandle`].
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.core.cancel.clone()
+    }
+
+    /// How far the confirmed position is behind the end of WAL on the server; see
+    /// [`LogicalReplicationStream::lag()`][super::LogicalReplicationStream::lag].
+    pub fn lag(&self) -> Option<ReplicationLag> {
+        self.core.lag()
+    }
+
+    /// The time since the stream last received a message from the server, including
+    /// keepalives; see
+    /// [`LogicalReplicationStream::since_last_message()`][super::LogicalReplicationStream::since_last_message].
+    pub fn since_last_message(&self) -> Duration {
+        self.core.last_received.elapsed()
+    }
+
+    /// Returns `true` if the confirmed position reached the end of WAL on the server; see
+    /// [`LogicalReplicationStream::is_caught_up()`][super::LogicalReplicationStream::is_caught_up].
+    pub fn is_caught_up(&self) -> bool {
+        self.core.is_caught_up()
+    }
+
+    /// The state of the stream in one snapshot; see
+    /// [`LogicalReplicationStream::health()`][super::LogicalReplicationStream::health].
+    pub fn health(&self) -> ReplicationHealth {
+        self.core.health()
+    }
+
+    /// Set the gap in bytes up to which the stream is [caught up][Self::is_caught_up].
+    ///
+    /// See [`LogicalReplicationStream::set_caught_up_tolerance()`][super::LogicalReplicationStream::set_caught_up_tolerance].
+    pub fn set_caught_up_tolerance(&mut self, bytes: u64) {
+        self.core.caught_up_tolerance = bytes;
+    }
+
+    /// Stop receiving WAL without closing the stream; see
+    /// [`LogicalReplicationStream::pause()`][super::LogicalReplicationStream::pause].
+    pub fn pause(&mut self) {
+        self.core.paused = true;
+    }
+
+    /// Continue receiving WAL after [`pause()`][Self::pause], where the stream left off.
+    pub fn resume(&mut self) {
+        self.core.paused = false;
+    }
+
+    /// Returns `true` if the stream was [paused][Self::pause] and not resumed since.
+    pub fn is_paused(&self) -> bool {
+        self.core.paused
+    }
+
+    /// The distribution of the sizes of the frames received so far; see
+    /// [`LogicalReplicationStream::message_sizes()`][super::LogicalReplicationStream::message_sizes].
+    pub fn message_sizes(&self) -> &MessageSizes {
+        &self.core.message_sizes
+    }
+
+    /// The most recent keepalives; see
+    /// [`LogicalReplicationStream::keepalive_history()`][super::LogicalReplicationStream::keepalive_history].
+    pub fn keepalive_history(&self) -> &KeepaliveHistory {
+        &self.core.keepalive_history
+    }
+
+    /// Set the number of keepalives kept in the history; see
+    /// [`LogicalReplicationStream::set_keepalive_history_len()`][super::LogicalReplicationStream::set_keepalive_history_len].
+    pub fn set_keepalive_history_len(&mut self, len: usize) {
+        self.core.keepalive_history.set_len(len);
+    }
+
+    /// Size the read buffer of the connection by the sizes of the recent frames, up to `max`
+    /// bytes; see
+    /// [`LogicalReplicationStream::set_adaptive_read_buffer()`][super::LogicalReplicationStream::set_adaptive_read_buffer].
+    pub fn set_adaptive_read_buffer(&mut self, max: Option<usize>) {
+        self.core.set_adaptive_read_buffer(max);
+    }
+
+    /// Reject frames whose payload is larger than `max` bytes; see
+    /// [`LogicalReplicationStream::set_max_frame_size()`][super::LogicalReplicationStream::set_max_frame_size].
+    pub fn set_max_frame_size(&mut self, max: usize) {
+        self.core.max_frame_size = max;
+    }
+
+    /// Set the interval between periodic standby status updates.
+    ///
+    /// See [`LogicalReplicationStream::set_status_interval()`][super::LogicalReplicationStream::set_status_interval].
+    pub fn set_status_interval(&mut self, interval: Duration) {
+        self.core.status_interval = interval;
+    }
+
+    /// Sets whether the stream sends standby status updates by itself; see
+    /// [`LogicalReplicationStream::set_automatic_status()`][super::LogicalReplicationStream::set_automatic_status].
+    pub fn set_automatic_status(&mut self, automatic: bool) {
+        self.core.automatic_status = automatic;
+    }
+
+    /// Send a standby status update with the given positions; see
+    /// [`LogicalReplicationStream::send_standby_status()`][super::LogicalReplicationStream::send_standby_status].
+    pub async fn send_standby_status(
+        &mut self,
+        write: PgLsn,
+        flush: PgLsn,
+        apply: PgLsn,
+        reply_requested: bool,
+    ) -> Result<(), ReplicationError> {
+        self.core
+            .send_standby_status(write, flush, apply, reply_requested)
+            .await
+    }
+
+    /// Set whether the stream checks that each chunk of WAL starts where the previous one
+    /// ended; off by default. See [`ContiguityCheck`].
+    ///
+    /// [`archive()`][Self::archive] always checks it.
+    pub fn set_contiguity_check(&mut self, check: ContiguityCheck) {
+        self.core.contiguity_check = check;
+    }
+
+    /// Set how long to wait for any message from the server before [`recv()`][Self::recv]
+    /// returns [`ReplicationError::Timeout`], or `None` to wait forever.
+    ///
+    /// See [`LogicalReplicationStream::set_read_timeout()`][super::LogicalReplicationStream::set_read_timeout].
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.core.read_timeout = timeout;
+    }
+
+    /// Call `handler` with the notices the server sends, instead of logging them.
+    ///
+    /// The handler stays installed on the connection returned by [`finish()`][Self::finish].
+    pub fn set_notice_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(ReplicationNotice) + Send + 'static,
+    {
+        self.core.set_notice_handler(handler);
+    }
+
+    /// Set the position reported by the initial standby status update, or `None` to not send one.
+    ///
+    /// Defaults to the start position passed to
+    /// [`PgReplicationConnection::start_physical_replication()`]. See
+    /// [`LogicalReplicationStream::set_initial_status()`][super::LogicalReplicationStream::set_initial_status].
+    pub fn set_initial_status(&mut self, lsn: Option<PgLsn>) {
+        self.core.initial_status = lsn;
+    }
+
+    /// Receive the next message from the server.
+    ///
+    /// Returns `Ok(None)` if the server ended the stream, if the stream was
+    /// [cancelled][Self::cancel_handle], or after a
+    /// [`TimelineSwitch`][PhysicalReplication::TimelineSwitch] was returned.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv(&mut self) -> Result<Option<PhysicalReplication>, ReplicationError> {
+        match self.core.recv().await? {
+            Received::XLogData(data) => {
+                if self.core.contiguity_check != ContiguityCheck::Off
+                    && data.wal_start != self.core.data_lsn
+                {
+                    self.core.contiguity_violation(ReplicationError::WalGap {
+                        expected: self.core.data_lsn,
+                        received: data.wal_start,
+                    })?;
+                }
+
+                let end = data.wal_start.0 + data.data.len() as u64;
+                self.core.received_lsn = cmp::max(self.core.received_lsn, PgLsn(end));
+                self.core.data_lsn = cmp::max(self.core.data_lsn, PgLsn(end));
+
+                Ok(Some(PhysicalReplication::XLogData(data)))
+            }
+            Received::End(Some(row)) => {
+                let (next_tli, switch_lsn) = parse_timeline_switch(&row)?;
+
+                Ok(Some(PhysicalReplication::TimelineSwitch {
+                    next_tli,
+                    switch_lsn,
+                }))
+            }
+            // `Reached` is only returned while waiting for an LSN, and `Keepalive` if keepalive
+            // events are requested, which physical streams don't
+            Received::End(None)
+            | Received::Cancelled
+            | Received::Reached
+            | Received::Keepalive(_) => Ok(None),
+        }
+    }
+
+    /// Write the WAL of the stream to `writer` until the stream ends, e.g. to ship it to an
+    /// archive, returning how far it was written.
+    ///
+    /// The raw WAL is written byte for byte, starting at the end of the WAL received before,
+    /// i.e. at the start position if nothing was received yet. Each chunk must continue where
+    /// the previous one ended; otherwise [`ReplicationError::WalGap`] is returned, as the copy
+    /// would be corrupt. The writer is flushed whenever no more WAL was received already, and
+    /// the position written up to is then [confirmed][Self::set_confirmed_lsn], so the server
+    /// (and a slot) only advance past WAL that was flushed.
+    ///
+    /// Returns once the server ended the stream, e.g. at the end of a timeline, or the stream
+    /// was [cancelled][Self::cancel_handle]. On an error, the WAL after the last confirmed
+    /// position may have been written only partially.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(
+    /// #     conn: sqlx::postgres::replication::PgReplicationConnection,
+    /// #     start_lsn: sqlx::postgres::types::PgLsn,
+    /// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+    /// let mut stream = conn
+    ///     .start_physical_replication(Some("archiver"), start_lsn, None)
+    ///     .await?;
+    ///
+    /// let mut wal = Vec::new();
+    /// let archived = stream.archive(&mut wal).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancel-safe: WAL received but not yet written is lost, and the
+    /// position written up to is not known. Stop it with the cancel handle instead.
+    pub async fn archive<W>(&mut self, writer: &mut W) -> Result<ArchivedWal, ReplicationError>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        let start_lsn = self.core.data_lsn;
+        let mut position = start_lsn;
+        let mut unflushed = false;
+
+        let next_timeline = loop {
+            let message = if unflushed {
+                // `recv()` is cancel-safe, so no message is lost if it is not ready
+                match self.recv().now_or_never() {
+                    Some(message) => message?,
+                    None => {
+                        writer.flush().await.map_err(Error::Io)?;
+                        self.set_confirmed_lsn(position);
+                        unflushed = false;
+
+                        continue;
+                    }
+                }
+            } else {
+                self.recv().await?
+            };
+
+            match message {
+                Some(PhysicalReplication::XLogData(data)) => {
+                    if data.wal_start != position {
+                        return Err(ReplicationError::WalGap {
+                            expected: position,
+                            received: data.wal_start,
+                        });
+                    }
+
+                    writer.write_all(&data.data).await.map_err(Error::Io)?;
+                    position = PgLsn(position.0 + data.data.len() as u64);
+                    unflushed = true;
+                }
+                Some(PhysicalReplication::TimelineSwitch {
+                    next_tli,
+                    switch_lsn,
+                }) => break Some((next_tli, switch_lsn)),
+                None => break None,
+            }
+        };
+
+        if unflushed {
+            writer.flush().await.map_err(Error::Io)?;
+            self.set_confirmed_lsn(position);
+        }
+
+        Ok(ArchivedWal {
+            start_lsn,
+            end_lsn: position,
+            next_timeline,
+        })
+    }
+
+    /// Stop streaming and return the replication connection.
+    ///
+    /// A final status update with the confirmed position is sent before the stream is ended,
+    /// unless the server already ended it.
+    pub async fn finish(self) -> Result<PgReplicationConnection, ReplicationError> {
+        self.core.finish().await
+    }
+
+    /// Consume this stream, returning a `Stream` of messages.
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<PhysicalReplication, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv().await {
+                Ok(Some(message)) => Some((Ok(message), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+}
+
+impl Debug for PhysicalReplicationStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhysicalReplicationStream")
+            .field("slot", &self.core.slot)
+            .field("received_lsn", &self.core.received_lsn)
+            .field("confirmed_lsn", &self.core.confirmed_lsn)
+            .finish()
+    }
+}
+
+/// Parse the `next_tli` and `next_tli_startpos` columns of the result set that follows the
+/// end of a timeline.
+fn parse_timeline_switch(row: &DataRow) -> Result<(u32, PgLsn), Error> {
+    let column = |index: usize| {
+        row.values
+            .get(index)
+            .and_then(|_| row.get(index))
+            .and_then(|value| str::from_utf8(value).ok())
+            .ok_or_else(|| err_protocol!("invalid end of timeline result set: column {}", index))
+    };
+
+    let next_tli = column(0)?;
+    let next_tli = next_tli
+        .parse()
+        .map_err(|_| err_protocol!("invalid next timeline: {:?}", next_tli))?;
+
+    let switch_lsn = column(1)?.parse().map_err(Error::Decode)?;
+
+    Ok((next_tli, switch_lsn))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::message::BackendMessage;
+
+    use super::*;
+
+    fn data_row(values: &[&str]) -> DataRow {
+        let mut buf = BytesMut::new();
+        buf.put_u16(u16::try_from(values.len()).unwrap());
+
+        for value in values {
+            buf.put_i32(i32::try_from(value.len()).unwrap());
+            buf.put_slice(value.as_bytes());
+        }
+
+        DataRow::decode_body(Bytes::from(buf)).unwrap()
+    }
+
+    #[test]
+    fn it_parses_timeline_switch() {
+        let row = data_row(&["2", "0/3000148"]);
+
+        assert_eq!(
+            parse_timeline_switch(&row).unwrap(),
+            (2, "0/3000148".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_timeline_switch() {
+        assert!(parse_timeline_switch(&data_row(&["x", "0/3000148"])).is_err());
+        assert!(parse_timeline_switch(&data_row(&["2"])).is_err());
+    }
+}
@@ -0,0 +1,707 @@
+use std::time::{Duration, Instant};
+
+use futures_core::stream::BoxStream;
+use futures_util::TryStreamExt;
+use sqlx_core::bytes::Bytes;
+use sqlx_core::executor::Executor;
+use sqlx_core::row::Row;
+use sqlx_core::rt;
+
+use crate::error::Error;
+use crate::PgConnection;
+
+use super::{quote_ident, quote_literal, ReplicationError};
+
+/// A table replicated by a publication, as returned by [`publication_tables()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PublicationTable {
+    /// The schema of the table.
+    pub schema: String,
+    /// The name of the table.
+    pub name: String,
+    /// The replicated columns; all columns unless the publication has a column list.
+    ///
+    /// `None` before Postgres 15, which always replicates all columns.
+    pub columns: Option<Vec<String>>,
+    /// The row filter (`WHERE` clause) of the table in the publication, if any (Postgres 15+).
+    pub row_filter: Option<String>,
+}
+
+impl PublicationTable {
+    /// The quoted, schema-qualified name of the table, e.g. for `COPY`.
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", quote_ident(&self.schema), quote_ident(&self.name))
+    }
+
+    /// The `COPY ... TO STDOUT` statement that copies exactly the rows and columns of the table
+    /// the publication replicates, applying the [row filter][Self::row_filter] and the
+    /// [column list][Self::columns], e.g.
+    /// `COPY (SELECT "id", "total" FROM "public"."orders" WHERE (total > 100)) TO STDOUT`.
+    ///
+    /// See [`copy_publication_table()`] to run it.
+    pub fn copy_statement(&self) -> String {
+        let columns = match &self.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|column| quote_ident(column))
+                .collect::<Vec<_>>()
+                .join(", "),
+            None => "*".into(),
+        };
+
+        let mut select = format!("SELECT {columns} FROM {}", self.qualified_name());
+
+        if let Some(filter) = &self.row_filter {
+            select.push_str(" WHERE ");
+            select.push_str(filter);
+        }
+
+        format!("COPY ({select}) TO STDOUT")
+    }
+}
+
+/// Copy the rows of `table` that its publication replicates, in the text format of `COPY`,
+/// e.g. to bootstrap a consumer from the snapshot exported by its slot before streaming.
+///
+/// This runs [`PublicationTable::copy_statement()`], so the [row filter][PublicationTable::row_filter]
+/// and the [column list][PublicationTable::columns] of the publication are applied to the copy
+/// exactly like the server applies them to the stream. Run it in the transaction returned by
+/// [`import_snapshot()`][super::import_snapshot] for each table returned by
+/// [`publication_tables()`]:
+///
+/// ```rust,no_run
+/// # async fn example(
+/// #     conn: &mut sqlx::PgConnection,
+/// #     slot: &sqlx::postgres::replication::PgReplicationSlot,
+/// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use futures_util::TryStreamExt;
+/// use sqlx::postgres::replication::{copy_publication_table, publication_tables};
+///
+/// let mut tx = slot.import_snapshot(conn).await?;
+///
+/// for table in publication_tables(&mut *tx, "orders_pub").await? {
+///     let mut rows = copy_publication_table(&mut tx, &table).await?;
+///
+///     while let Some(chunk) = rows.try_next().await? {
+///         // load `chunk` into the target of `table`
+///     }
+/// }
+///
+/// tx.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The filters are read from the catalog, so the copy only matches the stream if the
+/// publication is not altered between [`publication_tables()`] and the end of the stream. A
+/// copy that ignores the filter, or that uses a filter that no longer matches the one of the
+/// publication, is not detected: the snapshot then contains rows the stream never updates or
+/// deletes, or misses rows whose later changes are streamed as updates of unknown rows, and
+/// the target drifts silently from the source.
+///
+/// If the slot streams several publications, a row is replicated if it matches the filter of
+/// any of them, and all rows are replicated if one of them has no filter for the table; copy
+/// with the filters of all publications combined with `OR` in that case rather than with this.
+pub async fn copy_publication_table<'c>(
+    conn: &'c mut PgConnection,
+    table: &PublicationTable,
+) -> Result<BoxStream<'c, Result<Bytes, Error>>, Error> {
+    conn.copy_out_raw(&table.copy_statement()).await
+}
+
+/// Copy every table of the publication `publication` with `COPY`, calling `on_row` with each
+/// row, and return the number of rows copied from each table.
+///
+/// This is the initial sync of Postgres' built-in logical replication: the tables are listed
+/// with [`publication_tables()`] and copied with [`copy_publication_table()`] in the same
+/// transaction, which must be the one returned by [`import_snapshot()`][super::import_snapshot]
+/// for the slot, so that the copy ends exactly where the stream of the slot starts. `COPY`
+/// sends each row as a message, in the text format of `COPY` with a trailing newline (e.g.
+/// `2\t150\n`), so large tables are loaded far faster than with `SELECT`, without keeping more
+/// than one row in memory.
+///
+/// ```rust,no_run
+/// # async fn example(
+/// #     conn: &mut sqlx::PgConnection,
+/// #     slot: &sqlx::postgres::replication::PgReplicationSlot,
+/// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use sqlx::postgres::replication::copy_publication_snapshot;
+///
+/// let mut tx = slot.import_snapshot(conn).await?;
+///
+/// let copied = copy_publication_snapshot(&mut tx, "orders_pub", |table, row| {
+///     // load `row` into the target of `table`
+///     Ok(())
+/// })
+/// .await?;
+///
+/// tx.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// An error returned by `on_row` stops the copy and is returned, leaving the transaction in a
+/// state where it can only be rolled back.
+pub async fn copy_publication_snapshot<F>(
+    conn: &mut PgConnection,
+    publication: &str,
+    mut on_row: F,
+) -> Result<Vec<(PublicationTable, u64)>, ReplicationError>
+where
+    F: FnMut(&PublicationTable, Bytes) -> Result<(), ReplicationError>,
+{
+    let tables = publication_tables(&mut *conn, publication).await?;
+    let mut copied = Vec::with_capacity(tables.len());
+
+    for table in tables {
+        let mut rows = copy_publication_table(conn, &table).await?;
+        let mut count = 0;
+
+        while let Some(row) = rows.try_next().await? {
+            on_row(&table, row)?;
+            count += 1;
+        }
+
+        drop(rows);
+        copied.push((table, count));
+    }
+
+    Ok(copied)
+}
+
+/// List the tables replicated by the publication `publication`, e.g. to copy exactly these
+/// tables when bootstrapping a consumer from a snapshot.
+///
+/// The list is taken from `pg_publication_tables`, so publications `FOR ALL TABLES` and
+/// `FOR TABLES IN SCHEMA` are expanded to the tables they currently cover. Copy only the rows
+/// matching the [row filter][PublicationTable::row_filter] and only the
+/// [replicated columns][PublicationTable::columns], otherwise the snapshot contains rows and
+/// columns the stream never updates; [`copy_publication_table()`] does both.
+///
+/// This runs on a normal (non-replication) connection, usually the one that imported the
+/// snapshot of the slot.
+pub async fn publication_tables<C: AsMut<PgConnection>>(
+    mut conn: C,
+    publication: &str,
+) -> Result<Vec<PublicationTable>, Error> {
+    let conn = conn.as_mut();
+
+    // the column lists and row filters were added in Postgres 15
+    let query = if conn
+        .server_version_num()
+        .is_some_and(|version| version >= 150000)
+    {
+        "SELECT schemaname::text, tablename::text, attnames::text[], rowfilter \
+         FROM pg_catalog.pg_publication_tables WHERE pubname = $1 \
+         ORDER BY schemaname, tablename"
+    } else {
+        "SELECT schemaname::text, tablename::text, NULL::text[], NULL::text \
+         FROM pg_catalog.pg_publication_tables WHERE pubname = $1 \
+         ORDER BY schemaname, tablename"
+    };
+
+    let rows = crate::query::query(query)
+        .bind(publication)
+        .fetch_all(conn)
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(PublicationTable {
+                schema: row.try_get(0)?,
+                name: row.try_get(1)?,
+                columns: row.try_get(2)?,
+                row_filter: row.try_get(3)?,
+            })
+        })
+        .collect()
+}
+
+/// The row filter of a table listed in a publication, as returned by
+/// [`publication_row_filters()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PublicationRowFilter {
+    /// The schema of the table.
+    pub schema: String,
+    /// The name of the table.
+    pub name: String,
+    /// The row filter (`WHERE` clause) of the table, as deparsed by the server, e.g.
+    /// `(total > 100)`; `None` if all rows are replicated.
+    pub filter: Option<String>,
+}
+
+impl PublicationRowFilter {
+    /// The quoted, schema-qualified name of the table.
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", quote_ident(&self.schema), quote_ident(&self.name))
+    }
+}
+
+/// List the row filters of the tables listed in the publication `publication`, from
+/// `pg_publication_rel`, e.g. to explain why the changes of some rows are not streamed.
+///
+/// Only the rows matching the filter of a table are replicated: an `UPDATE` that makes a row
+/// match is sent as an `INSERT`, one that makes it stop matching as a `DELETE`, and all other
+/// changes of rows that don't match are never sent.
+///
+/// Only the tables listed in the publication are returned, with or without a filter: the
+/// tables of a publication `FOR ALL TABLES` or `FOR TABLES IN SCHEMA` cannot be filtered.
+/// Row filters were added in Postgres 15; before, the filter of every table is `None`.
+///
+/// This runs on a normal (non-replication) connection.
+pub async fn publication_row_filters<C: AsMut<PgConnection>>(
+    mut conn: C,
+    publication: &str,
+) -> Result<Vec<PublicationRowFilter>, Error> {
+    let conn = conn.as_mut();
+
+    let filter = if conn
+        .server_version_num()
+        .is_some_and(|version| version >= 150000)
+    {
+        "pg_catalog.pg_get_expr(r.prqual, r.prrelid)"
+    } else {
+        "NULL::text"
+    };
+
+    let query = format!(
+        "SELECT n.nspname::text, c.relname::text, {filter} \
+         FROM pg_catalog.pg_publication_rel r \
+         JOIN pg_catalog.pg_publication p ON p.oid = r.prpubid \
+         JOIN pg_catalog.pg_class c ON c.oid = r.prrelid \
+         JOIN pg_catalog.pg_namespace n ON n.oid = c.relnamespace \
+         WHERE p.pubname = $1 \
+         ORDER BY n.nspname, c.relname"
+    );
+
+    let rows = crate::query::query(&query)
+        .bind(publication)
+        .fetch_all(conn)
+        .await?;
+
+    rows.iter()
+        .map(|row| {
+            Ok(PublicationRowFilter {
+                schema: row.try_get(0)?,
+                name: row.try_get(1)?,
+                filter: row.try_get(2)?,
+            })
+        })
+        .collect()
+}
+
+/// The definition of a publication, to create it with [`create_publication_if_missing()`].
+///
+/// <https://www.postgresql.org/docs/current/sql-createpublication.html>
+///
+/// ```rust
+/// # use sqlx::postgres::replication::CreatePublication;
+/// let publication = CreatePublication::new("my_pub")
+///     .table("public", "users")
+///     .table("public", "orders")
+///     .publish_truncate(false);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CreatePublication {
+    pub(crate) name: String,
+    pub(crate) tables: Vec<(String, String)>,
+    pub(crate) all_tables: bool,
+    pub(crate) publish_insert: bool,
+    pub(crate) publish_update: bool,
+    pub(crate) publish_delete: bool,
+    pub(crate) publish_truncate: bool,
+}
+
+impl CreatePublication {
+    /// A publication named `name` of no tables, which publishes all operations.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tables: Vec::new(),
+            all_tables: false,
+            publish_insert: true,
+            publish_update: true,
+            publish_delete: true,
+            publish_truncate: true,
+        }
+    }
+
+    /// Add the table `name` in `schema` to the publication; the names are used as given, so
+    /// case and special characters are preserved.
+    pub fn table(mut self, schema: impl Into<String>, name: impl Into<String>) -> Self {
+        self.tables.push((schema.into(), name.into()));
+        self
+    }
+
+    /// Sets whether the publication is `FOR ALL TABLES`, including tables created later,
+    /// instead of the [tables][Self::table] added to it. Requires superuser privileges.
+    pub fn all_tables(mut self, all_tables: bool) -> Self {
+        self.all_tables = all_tables;
+        self
+    }
+
+    /// Sets whether inserts are published.
+    pub fn publish_insert(mut self, publish: bool) -> Self {
+        self.publish_insert = publish;
+        self
+    }
+
+    /// Sets whether updates are published.
+    pub fn publish_update(mut self, publish: bool) -> Self {
+        self.publish_update = publish;
+        self
+    }
+
+    /// Sets whether deletes are published.
+    pub fn publish_delete(mut self, publish: bool) -> Self {
+        self.publish_delete = publish;
+        self
+    }
+
+    /// Sets whether `TRUNCATE` is published.
+    pub fn publish_truncate(mut self, publish: bool) -> Self {
+        self.publish_truncate = publish;
+        self
+    }
+
+    /// Render the `CREATE PUBLICATION` statement.
+    pub(crate) fn to_sql(&self) -> Result<String, ReplicationError> {
+        if self.name.is_empty() {
+            return Err(ReplicationError::InvalidOptions {
+                reason: "the publication name must not be empty".into(),
+            });
+        }
+
+        if self.all_tables && !self.tables.is_empty() {
+            return Err(ReplicationError::InvalidOptions {
+                reason: "a publication for all tables cannot list tables".into(),
+            });
+        }
+
+        let mut sql = format!("CREATE PUBLICATION {}", quote_ident(&self.name));
+
+        if self.all_tables {
+            sql.push_str(" FOR ALL TABLES");
+        } else if !self.tables.is_empty() {
+            let tables = (self.tables.iter())
+                .map(|(schema, name)| format!("{}.{}", quote_ident(schema), quote_ident(name)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            sql.push_str(" FOR TABLE ");
+            sql.push_str(&tables);
+        }
+
+        let publish = [
+            ("insert", self.publish_insert),
+            ("update", self.publish_update),
+            ("delete", self.publish_delete),
+            ("truncate", self.publish_truncate),
+        ];
+
+        // all operations are published by default
+        if publish.iter().any(|&(_, enabled)| !enabled) {
+            let publish = (publish.iter())
+                .filter(|&&(_, enabled)| enabled)
+                .map(|&(operation, _)| operation)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            sql.push_str(&format!(" WITH (publish = {})", quote_literal(&publish)));
+        }
+
+        Ok(sql)
+    }
+}
+
+/// Create the publication `publication` unless a publication with its name exists, returning
+/// whether it was created, e.g. to let a consumer set up its own publication in development
+/// and test environments.
+///
+/// An existing publication is left as it is, even if it is defined differently. Production
+/// publications are usually managed separately from their consumers, so this is only done if
+/// called explicitly.
+///
+/// Creating a publication requires the `CREATE` privilege on the database and ownership of the
+/// tables, or superuser privileges for a publication [for all tables][CreatePublication::all_tables];
+/// [`ReplicationError::PublicationPermissionDenied`] is returned without them. A publication
+/// created by another connection at the same time is treated as existing.
+///
+/// This runs on a normal (non-replication) connection.
+pub async fn create_publication_if_missing<C: AsMut<PgConnection>>(
+    mut conn: C,
+    publication: &CreatePublication,
+) -> Result<bool, ReplicationError> {
+    let conn = conn.as_mut();
+    let sql = publication.to_sql()?;
+
+    let exists: bool = crate::query_scalar::query_scalar(
+        "SELECT EXISTS (SELECT FROM pg_catalog.pg_publication WHERE pubname = $1)",
+    )
+    .bind(&publication.name)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if exists {
+        return Ok(false);
+    }
+
+    let error = match conn.execute(&*sql).await {
+        Ok(_) => return Ok(true),
+        Err(error) => error,
+    };
+
+    let code = error
+        .as_database_error()
+        .and_then(|db_error| db_error.code().map(|code| code.into_owned()));
+
+    match code.as_deref() {
+        // duplicate_object
+        Some("42710") => Ok(false),
+        // insufficient_privilege
+        Some("42501") => Err(ReplicationError::PublicationPermissionDenied {
+            publication: publication.name.clone(),
+            source: error,
+        }),
+        _ => Err(error.into()),
+    }
+}
+
+/// A change of the table set of a publication, as found by [`PublicationWatcher`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PublicationChange {
+    /// The tables that were added to the publication, e.g. to snapshot before their changes
+    /// are applied.
+    pub added: Vec<PublicationTable>,
+    /// The tables that were removed from the publication, whose changes are no longer streamed.
+    pub removed: Vec<PublicationTable>,
+    /// The tables whose [column list][PublicationTable::columns] or
+    /// [row filter][PublicationTable::row_filter] changed, as they are now.
+    pub altered: Vec<PublicationTable>,
+}
+
+impl PublicationChange {
+    /// Compare two lists of tables of a publication, as returned by [`publication_tables()`].
+    fn diff(old: &[PublicationTable], new: &[PublicationTable]) -> Self {
+        let find = |tables: &[PublicationTable], table: &PublicationTable| {
+            tables
+                .iter()
+                .find(|other| other.schema == table.schema && other.name == table.name)
+                .cloned()
+        };
+
+        let mut change = PublicationChange::default();
+
+        for table in new {
+            match find(old, table) {
+                None => change.added.push(table.clone()),
+                Some(old) if old != *table => change.altered.push(table.clone()),
+                Some(_) => {}
+            }
+        }
+
+        change.removed = old
+            .iter()
+            .filter(|table| find(new, table).is_none())
+            .cloned()
+            .collect();
+
+        change
+    }
+
+    /// Returns `true` if no table was added, removed or altered.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.altered.is_empty()
+    }
+}
+
+/// Polls the table set of a publication to notice tables being added or removed at runtime.
+///
+/// `pgoutput` doesn't tell when the tables of a publication change: the changes of an added
+/// table just start to be streamed, without the snapshot of its existing rows, and the changes
+/// of a removed table stop. The watcher re-queries [`publication_tables()`] on a normal
+/// (non-replication) connection, and reports the difference to the previous query:
+///
+/// ```rust,no_run
+/// # async fn example(conn: &mut sqlx::PgConnection) -> Result<(), sqlx::Error> {
+/// use std::time::Duration;
+/// use sqlx::postgres::replication::PublicationWatcher;
+///
+/// let mut watcher = PublicationWatcher::new("orders_pub");
+/// watcher.set_interval(Duration::from_secs(30));
+///
+/// loop {
+///     let change = watcher.changed(&mut *conn).await?;
+///
+///     for table in &change.added {
+///         // snapshot `table` with `copy_publication_table()`
+///     }
+/// }
+/// # }
+/// ```
+///
+/// The first query only records the tables the publication starts with.
+#[derive(Debug, Clone)]
+pub struct PublicationWatcher {
+    publication: String,
+    interval: Duration,
+    tables: Option<Vec<PublicationTable>>,
+    last_check: Option<Instant>,
+}
+
+impl PublicationWatcher {
+    /// Create a watcher of the publication `publication`, which checks it every minute.
+    pub fn new(publication: impl Into<String>) -> Self {
+        Self {
+            publication: publication.into(),
+            interval: Duration::from_secs(60),
+            tables: None,
+            last_check: None,
+        }
+    }
+
+    /// Set the interval between the queries of [`changed()`][Self::changed]. Defaults to one
+    /// minute.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// The name of the watched publication.
+    pub fn publication(&self) -> &str {
+        &self.publication
+    }
+
+    /// The tables of the publication as of the last query, or `None` before the first one.
+    pub fn tables(&self) -> Option<&[PublicationTable]> {
+        self.tables.as_deref()
+    }
+
+    /// Query the tables of the publication now, and return how they changed since the
+    /// previous query; always `None` for the first query.
+    pub async fn check<C: AsMut<PgConnection>>(
+        &mut self,
+        conn: C,
+    ) -> Result<Option<PublicationChange>, Error> {
+        let tables = publication_tables(conn, &self.publication).await?;
+
+        self.last_check = Some(Instant::now());
+
+        let change = self
+            .tables
+            .as_deref()
+            .map(|old| PublicationChange::diff(old, &tables))
+            .filter(|change| !change.is_empty());
+
+        self.tables = Some(tables);
+
+        Ok(change)
+    }
+
+    /// Wait for the next change of the tables of the publication, querying them every
+    /// [interval][Self::set_interval].
+    ///
+    /// This is cancel-safe, e.g. in a `select!` with the replication stream: the tables are
+    /// only recorded once a query completed, so no change is lost.
+    pub async fn changed<C: AsMut<PgConnection>>(
+        &mut self,
+        mut conn: C,
+    ) -> Result<PublicationChange, Error> {
+        loop {
+            if let Some(last_check) = self.last_check {
+                let elapsed = last_check.elapsed();
+
+                if elapsed < self.interval {
+                    rt::sleep(self.interval - elapsed).await;
+                }
+            }
+
+            if let Some(change) = self.check(conn.as_mut()).await? {
+                return Ok(change);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(columns: Option<&[&str]>, row_filter: Option<&str>) -> PublicationTable {
+        PublicationTable {
+            schema: "public".into(),
+            name: "orders".into(),
+            columns: columns.map(|columns| columns.iter().map(|&c| c.into()).collect()),
+            row_filter: row_filter.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn it_builds_filtered_copy_statements() {
+        assert_eq!(
+            table(None, None).copy_statement(),
+            r#"COPY (SELECT * FROM "public"."orders") TO STDOUT"#
+        );
+        assert_eq!(
+            table(Some(&["id", "total \"eur\""]), Some("(total > 100)")).copy_statement(),
+            r#"COPY (SELECT "id", "total ""eur""" FROM "public"."orders" WHERE (total > 100)) TO STDOUT"#
+        );
+    }
+
+    #[test]
+    fn it_builds_create_publication_statements() {
+        assert_eq!(
+            CreatePublication::new("pub").to_sql().unwrap(),
+            r#"CREATE PUBLICATION "pub""#
+        );
+        assert_eq!(
+            CreatePublication::new("My Pub")
+                .table("public", "users")
+                .table("sales", "Orders")
+                .publish_delete(false)
+                .publish_truncate(false)
+                .to_sql()
+                .unwrap(),
+            r#"CREATE PUBLICATION "My Pub" FOR TABLE "public"."users", "sales"."Orders" WITH (publish = 'insert, update')"#
+        );
+        assert_eq!(
+            CreatePublication::new("pub")
+                .all_tables(true)
+                .to_sql()
+                .unwrap(),
+            r#"CREATE PUBLICATION "pub" FOR ALL TABLES"#
+        );
+
+        assert!(CreatePublication::new("").to_sql().is_err());
+        assert!(CreatePublication::new("pub")
+            .all_tables(true)
+            .table("public", "users")
+            .to_sql()
+            .is_err());
+    }
+
+    #[test]
+    fn it_diffs_publication_tables() {
+        let named = |name: &str, row_filter: Option<&str>| PublicationTable {
+            name: name.into(),
+            ..table(None, row_filter)
+        };
+
+        let old = [named("orders", None), named("users", None)];
+        let new = [
+            named("orders", Some("(total > 100)")),
+            named("invoices", None),
+        ];
+
+        let change = PublicationChange::diff(&old, &new);
+
+        assert_eq!(change.added, [named("invoices", None)]);
+        assert_eq!(change.removed, [named("users", None)]);
+        assert_eq!(change.altered, [named("orders", Some("(total > 100)"))]);
+        assert!(!change.is_empty());
+
+        assert!(PublicationChange::diff(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+use std::cmp;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+
+use futures_core::future::BoxFuture;
+
+use crate::types::PgLsn;
+use crate::PgConnectOptions;
+
+use super::{
+    LogicalReplication, LogicalReplicationStream, PgOutputOptions, PgReplicationConnection,
+    Relation, ReplicationError, LIFECYCLE_TARGET,
+};
+
+/// Why the slot of a [`ReconnectingStream`] cannot be streamed from anymore, as passed to its
+/// [slot lost handler][ReconnectingStream::on_slot_lost].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SlotLost {
+    /// The name of the slot.
+    pub slot: String,
+    /// `true` if the slot still exists but was [invalidated][ReplicationError::SlotInvalidated]
+    /// and must be dropped before it can be created again, `false` if it doesn't exist (any
+    /// more), e.g. because it was dropped by an administrator.
+    pub invalidated: bool,
+    /// The last position confirmed by the consumer; the changes after it were lost with the
+    /// slot, and can only be recovered from a new snapshot.
+    pub confirmed_lsn: PgLsn,
+}
+
+type SlotLostHandler = Box<
+    dyn FnMut(
+            PgReplicationConnection,
+            SlotLost,
+        ) -> BoxFuture<'static, Result<PgReplicationConnection, ReplicationError>>
+        + Send,
+>;
+
+/// A [`LogicalReplicationStream`] that reconnects and resumes from the confirmed position
+/// after an error.
+///
+/// An error returned by [`recv()`][Self::recv] ends the current connection; the next call
+/// connects again and restarts streaming of the slot from the
+/// [confirmed position][Self::set_confirmed_lsn], so the changes after it are received again.
+/// Retrying is up to the caller, e.g. only for [retryable][ReplicationError::is_retryable]
+/// errors and after a delay.
+///
+/// If the slot no longer exists or was invalidated when reconnecting, e.g. after an outage
+/// that was longer than `max_slot_wal_keep_size` allows, the error is returned unless a
+/// [slot lost handler][Self::on_slot_lost] re-bootstraps the consumer.
+pub struct ReconnectingStream {
+    options: PgConnectOptions,
+    slot: String,
+    output_options: PgOutputOptions,
+    stream: Option<LogicalReplicationStream>,
+    confirmed_lsn: PgLsn,
+    on_slot_lost: Option<SlotLostHandler>,
+    warm_start: bool,
+    /// The cached relations of the stream that ended, to seed the next one with.
+    relations: Vec<Relation>,
+}
+
+impl ReconnectingStream {
+    /// Create a stream of the logical replication slot `slot`, connecting with `options` on
+    /// the first call to [`recv()`][Self::recv].
+    pub fn new(
+        options: PgConnectOptions,
+        slot: impl Into<String>,
+        output_options: PgOutputOptions,
+    ) -> Self {
+        Self {
+            options,
+            slot: slot.into(),
+            output_options,
+            stream: None,
+            confirmed_lsn: PgLsn::INVALID,
+            on_slot_lost: None,
+            warm_start: false,
+            relations: Vec::new(),
+        }
+    }
+
+    /// Seed the relation cache of the stream after a reconnect with the relations cached
+    /// before, with [`LogicalReplicationStream::seed_relations()`]. Defaults to `false`.
+    ///
+    /// The server sends the definitions of the relations again after a reconnect, which
+    /// replace the seeded ones.
+    pub fn warm_start(mut self, enabled: bool) -> Self {
+        self.warm_start = enabled;
+        self
+    }
+
+    /// Call `handler` when the slot is lost instead of returning
+    /// [`ReplicationError::SlotNotFound`] or [`ReplicationError::SlotInvalidated`].
+    ///
+    /// The handler is passed a new replication connection and returns it once it
+    /// re-bootstrapped the consumer, e.g. dropped an invalidated slot, created it again with
+    /// an exported snapshot and copied the tables from that snapshot. Streaming then starts at
+    /// the consistent point of the new slot, and the confirmed position is reset.
+    ///
+    /// This is opt-in, as re-bootstrapping is usually expensive, and the changes between the
+    /// [last confirmed position][SlotLost::confirmed_lsn] and the new slot are lost unless
+    /// they are recovered from the new snapshot.
+    pub fn on_slot_lost<F, Fut>(mut self, mut handler: F) -> Self
+    where
+        F: FnMut(PgReplicationConnection, SlotLost) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<PgReplicationConnection, ReplicationError>> + Send + 'static,
+    {
+        self.on_slot_lost = Some(Box::new(move |conn, lost| Box::pin(handler(conn, lost))));
+        self
+    }
+
+    /// The name of the replication slot this stream consumes.
+    pub fn slot(&self) -> &str {
+        &self.slot
+    }
+
+    /// The position up to which changes have been processed, which streaming is restarted
+    /// from after reconnecting.
+    pub fn confirmed_lsn(&self) -> PgLsn {
+        self.confirmed_lsn
+    }
+
+    /// Set the position up to which changes have been processed; see
+    /// [`LogicalReplicationStream::set_confirmed_lsn()`].
+    pub fn set_confirmed_lsn(&mut self, lsn: PgLsn) {
+        self.confirmed_lsn = cmp::max(self.confirmed_lsn, lsn);
+
+        if let Some(stream) = &mut self.stream {
+            stream.set_confirmed_lsn(lsn);
+        }
+    }
+
+    /// The stream of the current connection, if connected, e.g. to configure it.
+    ///
+    /// Settings of the stream are not carried over when reconnecting.
+    pub fn stream_mut(&mut self) -> Option<&mut LogicalReplicationStream> {
+        self.stream.as_mut()
+    }
+
+    /// Receive the next message from the slot, connecting first if the previous connection
+    /// ended with an error; see [`LogicalReplicationStream::recv()`].
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe once connected.
+    pub async fn recv(&mut self) -> Result<Option<LogicalReplication>, ReplicationError> {
+        if self.stream.is_none() {
+            let mut stream = self.connect().await?;
+            stream.seed_relations(self.relations.drain(..));
+            self.stream = Some(stream);
+        }
+
+        let stream = self
+            .stream
+            .as_mut()
+            .expect("BUG: stream was just connected");
+
+        match stream.recv().await {
+            Ok(message) => Ok(message),
+            Err(error) => {
+                // an error ends the stream; reconnect with the next call
+                let stream = self.stream.take().expect("BUG: stream was just connected");
+
+                if self.warm_start {
+                    self.relations = stream.relations().cloned().collect();
+                }
+
+                tracing::info!(
+                    target: LIFECYCLE_TARGET,
+                    slot_name = self.slot,
+                    lsn = %self.confirmed_lsn,
+                    reason = %error,
+                    "replication stream failed; reconnecting with the next call"
+                );
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Stop streaming and return the replication connection, if connected; see
+    /// [`LogicalReplicationStream::finish()`].
+    pub async fn finish(self) -> Result<Option<PgReplicationConnection>, ReplicationError> {
+        match self.stream {
+            Some(stream) => stream.finish().await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    async fn connect(&mut self) -> Result<LogicalReplicationStream, ReplicationError> {
+        let conn = PgReplicationConnection::connect_with(&self.options).await?;
+
+        let result = conn
+            .start_logical_replication(&self.slot, self.confirmed_lsn, self.output_options.clone())
+            .await;
+
+        let invalidated = match &result {
+            Err(ReplicationError::SlotNotFound { .. }) => false,
+            Err(ReplicationError::SlotInvalidated { .. }) => true,
+            _ => return result,
+        };
+
+        let Some(handler) = &mut self.on_slot_lost else {
+            return result;
+        };
+
+        tracing::warn!(
+            slot = self.slot,
+            invalidated,
+            confirmed_lsn = %self.confirmed_lsn,
+            "replication slot was lost; re-bootstrapping"
+        );
+
+        let conn = PgReplicationConnection::connect_with(&self.options).await?;
+        let conn = handler(
+            conn,
+            SlotLost {
+                slot: self.slot.clone(),
+                invalidated,
+                confirmed_lsn: self.confirmed_lsn,
+            },
+        )
+        .await?;
+
+        // the new slot starts at its consistent point
+        self.confirmed_lsn = PgLsn::INVALID;
+
+        conn.start_logical_replication(&self.slot, PgLsn::INVALID, self.output_options.clone())
+            .await
+    }
+}
+
+impl Debug for ReconnectingStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingStream")
+            .field("slot", &self.slot)
+            .field("confirmed_lsn", &self.confirmed_lsn)
+            .field("connected", &self.stream.is_some())
+            .finish()
+    }
+}
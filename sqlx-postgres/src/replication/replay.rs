@@ -0,0 +1,250 @@
+#[cfg(feature = "replication-stream")]
+use std::io::Write;
+use std::io::{self, Read};
+
+use sqlx_core::bytes::{Buf, Bytes};
+
+use crate::error::Error;
+use crate::io::ProtocolDecode;
+
+use super::{LogicalDecodeContext, LogicalReplication, Replication};
+
+/// The type of a `CopyData` frame.
+const COPY_DATA: u8 = b'd';
+
+/// The type of a `CopyDone` frame.
+const COPY_DONE: u8 = b'c';
+
+/// Reads the `CopyData` frames of a captured replication stream, e.g. a file written with
+/// [`LogicalReplicationStream::set_capture()`][super::LogicalReplicationStream::set_capture],
+/// and decodes the `pgoutput` messages in them, without a connection.
+///
+/// The capture is a sequence of `CopyData` frames as they are sent on the wire: the type
+/// `d`, the length of the frame as a big-endian `i32` including itself, and the payload, an
+/// `XLogData` or `PrimaryKeepalive` message. A `CopyDone` frame ends the capture like the end of
+/// the input does.
+///
+/// Iterating over the reader returns the decoded messages, skipping keepalives, and keeps track
+/// of streamed transactions like [`LogicalDecodeContext::observe()`] does.
+///
+/// ```rust,no_run
+/// # fn example() -> Result<(), sqlx::Error> {
+/// use std::fs::File;
+/// use std::io::BufReader;
+///
+/// use sqlx::postgres::replication::{LogicalDecodeContext, ReplayReader};
+///
+/// let file = BufReader::new(File::open("orders.capture")?);
+///
+/// for message in ReplayReader::new(file, LogicalDecodeContext::new(1)) {
+///     println!("{:?}", message?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ReplayReader<R> {
+    reader: R,
+    ctx: LogicalDecodeContext,
+    done: bool,
+}
+
+impl<R: Read> ReplayReader<R> {
+    /// Read the frames of `reader`, decoding them with `ctx`, which must have the
+    /// `proto_version` the stream was captured with.
+    pub fn new(reader: R, ctx: LogicalDecodeContext) -> Self {
+        Self {
+            reader,
+            ctx,
+            done: false,
+        }
+    }
+
+    /// The context the next message is decoded with.
+    pub fn context(&self) -> &LogicalDecodeContext {
+        &self.ctx
+    }
+
+    /// Read the next frame without decoding the `pgoutput` message in it, e.g. to get the WAL
+    /// positions of an `XLogData` message, or `None` at the end of the capture.
+    ///
+    /// Returns an error if the capture ends in the middle of a frame, or if a frame is not a
+    /// `CopyData` frame with a [`Replication`] message.
+    pub fn next_frame(&mut self) -> Result<Option<Replication>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let mut header = [0; 5];
+
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            self.done = true;
+            return Ok(None);
+        }
+
+        let mut header = &header[..];
+        let format = header.get_u8();
+        let len = header.get_i32();
+
+        match format {
+            COPY_DATA => {}
+            COPY_DONE => {
+                self.done = true;
+                return Ok(None);
+            }
+            format => {
+                return Err(err_protocol!(
+                    "expected a CopyData frame in the capture, got type 0x{:02X}",
+                    format
+                ))
+            }
+        }
+
+        let len = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_sub(4))
+            .ok_or_else(|| err_protocol!("invalid CopyData frame length {} in the capture", len))?;
+
+        let mut payload = vec![0; len];
+
+        if !read_exact_or_eof(&mut self.reader, &mut payload)? {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        Replication::decode(Bytes::from(payload)).map(Some)
+    }
+
+    /// Read and decode the next `pgoutput` message, skipping keepalives, or `None` at the end
+    /// of the capture.
+    pub fn next_message(&mut self) -> Result<Option<LogicalReplication>, Error> {
+        loop {
+            match self.next_frame()? {
+                Some(Replication::XLogData(data)) => {
+                    let message = LogicalReplication::decode_with(data.data, self.ctx)?;
+                    self.ctx.observe(&message);
+
+                    return Ok(Some(message));
+                }
+                Some(Replication::PrimaryKeepalive(_)) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ReplayReader<R> {
+    type Item = Result<LogicalReplication, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // stop after an error, which is most likely a truncated or corrupt capture
+        let result = self.next_message();
+        self.done |= result.is_err();
+
+        result.transpose()
+    }
+}
+
+/// Write `payload` to `writer` as a `CopyData` frame, in the format read by [`ReplayReader`].
+#[cfg(feature = "replication-stream")]
+pub(super) fn write_frame(writer: &mut dyn Write, payload: &[u8]) -> io::Result<()> {
+    let len = i32::try_from(payload.len() + 4)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame too large"))?;
+
+    writer.write_all(&[COPY_DATA])?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+/// Fill `buf` from `reader`, returning `false` if the input ended before the first byte.
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(Error::Io(io::ErrorKind::UnexpectedEof.into())),
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(Error::Io(error)),
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BEGIN: &[u8] = b"B\0\0\0\0\x01\x5B\x9A\x90\0\x02\xB5\x4A\x71\x19\x7E\x22\0\0\x02\xE6";
+    const COMMIT: &[u8] =
+        b"C\0\0\0\0\0\x01\x5B\x9A\x90\0\0\0\0\x01\x5B\x9A\xC0\0\x02\xB5\x4A\x71\x19\x7E\x22";
+
+    fn xlog_data(data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![b'w'];
+        payload.extend_from_slice(&0x100u64.to_be_bytes());
+        payload.extend_from_slice(&0x200u64.to_be_bytes());
+        payload.extend_from_slice(&0i64.to_be_bytes());
+        payload.extend_from_slice(data);
+        payload
+    }
+
+    fn keepalive() -> Vec<u8> {
+        let mut payload = vec![b'k'];
+        payload.extend_from_slice(&0x200u64.to_be_bytes());
+        payload.extend_from_slice(&0i64.to_be_bytes());
+        payload.push(0);
+        payload
+    }
+
+    fn capture() -> Vec<u8> {
+        let mut capture = Vec::new();
+
+        for payload in [xlog_data(BEGIN), keepalive(), xlog_data(COMMIT)] {
+            write_frame(&mut capture, &payload).unwrap();
+        }
+
+        capture
+    }
+
+    #[test]
+    fn it_replays_captured_frames() {
+        let messages: Vec<_> = ReplayReader::new(&capture()[..], LogicalDecodeContext::new(1))
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(&messages[0], LogicalReplication::Begin(begin) if begin.xid == 742));
+        assert!(matches!(&messages[1], LogicalReplication::Commit(_)));
+
+        // `CopyDone` ends the capture
+        let mut capture = capture();
+        capture.extend_from_slice(b"c\0\0\0\x04garbage");
+        let mut reader = ReplayReader::new(&capture[..], LogicalDecodeContext::new(1));
+        assert!(matches!(
+            reader.next_frame().unwrap(),
+            Some(Replication::XLogData(data)) if data.wal_start.0 == 0x100
+        ));
+        assert_eq!(reader.by_ref().count(), 1);
+        assert!(reader.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn it_rejects_truncated_captures() {
+        let capture = capture();
+
+        let mut reader =
+            ReplayReader::new(&capture[..capture.len() - 3], LogicalDecodeContext::new(1));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(Error::Io(_)))));
+        assert!(reader.next().is_none());
+
+        let mut reader = ReplayReader::new(&b"x\0\0\0\x04"[..], LogicalDecodeContext::new(1));
+        assert!(matches!(reader.next(), Some(Err(Error::Protocol(_)))));
+    }
+}
@@ -0,0 +1,264 @@
+use std::cmp;
+use std::future::Future;
+use std::time::Duration;
+
+use sqlx_core::rt;
+
+use crate::error::Error;
+use crate::PgConnectOptions;
+
+use super::{
+    CreateReplicationSlot, IdentifySystem, PgReplicationConnection, PgReplicationSlot,
+    ReplicationError,
+};
+
+/// How often, and with which delays, to retry setting up replication after a transient
+/// failure, e.g. while the server fails over or is restarted.
+///
+/// Only [transient][is_transient] errors are retried: lost connections, `too_many_connections`
+/// and the server shutting down or starting up. Errors that would happen again, like a slot
+/// that already exists or a syntax error, are returned right away.
+///
+/// Each attempt uses a new connection, as a failed connection cannot be used any more. The
+/// delay before a retry starts at [`initial_backoff`][Self::initial_backoff] and is multiplied
+/// by [`multiplier`][Self::multiplier] after each attempt, up to
+/// [`max_backoff`][Self::max_backoff].
+///
+/// ```rust,no_run
+/// # async fn example() -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use std::time::Duration;
+/// use sqlx::postgres::PgConnectOptions;
+/// use sqlx::postgres::replication::{CreateReplicationSlot, RetryPolicy};
+///
+/// let options: PgConnectOptions = "postgres://localhost/mydb".parse()?;
+/// let policy = RetryPolicy::new()
+///     .max_attempts(10)
+///     .initial_backoff(Duration::from_millis(500));
+///
+/// let (conn, slot) = policy
+///     .create_replication_slot(&options, &CreateReplicationSlot::logical("my_slot", "pgoutput"))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy of 5 attempts, with delays starting at 100 milliseconds and doubling up to 10
+    /// seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that makes a single attempt, i.e. doesn't retry.
+    pub fn never() -> Self {
+        Self::new().max_attempts(1)
+    }
+
+    /// The number of attempts, including the first one; `0` is treated as `1`.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = cmp::max(max_attempts, 1);
+        self
+    }
+
+    /// The delay before the first retry.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// The longest delay between two attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// The factor the delay is multiplied by after each retry; `1` retries with a constant
+    /// delay.
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// The delay before retry number `retry`, starting at `0`.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = (0..retry).try_fold(self.initial_backoff, |backoff, _| {
+            backoff
+                .checked_mul(self.multiplier)
+                .filter(|backoff| *backoff < self.max_backoff)
+        });
+
+        cmp::min(backoff.unwrap_or(self.max_backoff), self.max_backoff)
+    }
+
+    /// Open a replication connection with `options`, retrying transient failures.
+    pub async fn connect(
+        &self,
+        options: &PgConnectOptions,
+    ) -> Result<PgReplicationConnection, ReplicationError> {
+        self.retry("connect", || PgReplicationConnection::connect_with(options))
+            .await
+    }
+
+    /// Connect with `options` and run `IDENTIFY_SYSTEM`, retrying transient failures.
+    ///
+    /// Returns the connection the command succeeded on.
+    pub async fn identify_system(
+        &self,
+        options: &PgConnectOptions,
+    ) -> Result<(PgReplicationConnection, IdentifySystem), ReplicationError> {
+        self.retry("IDENTIFY_SYSTEM", || async {
+            let mut conn = PgReplicationConnection::connect_with(options).await?;
+            let system = conn.identify_system().await?;
+
+            Ok((conn, system))
+        })
+        .await
+    }
+
+    /// Connect with `options` and create the replication slot `slot`, retrying transient
+    /// failures.
+    ///
+    /// Returns the connection the slot was created on, which a temporary slot and an exported
+    /// snapshot are bound to.
+    ///
+    /// If the connection is lost after the server created a permanent slot but before it
+    /// replied, the retry fails because the slot already exists, which is not transient. Look
+    /// the slot up with [`PgReplicationConnection::replication_slot()`] in that case.
+    pub async fn create_replication_slot(
+        &self,
+        options: &PgConnectOptions,
+        slot: &CreateReplicationSlot,
+    ) -> Result<(PgReplicationConnection, PgReplicationSlot), ReplicationError> {
+        self.retry("CREATE_REPLICATION_SLOT", || async {
+            let mut conn = PgReplicationConnection::connect_with(options).await?;
+            let slot = conn.create_replication_slot(slot).await?;
+
+            Ok((conn, slot))
+        })
+        .await
+    }
+
+    async fn retry<T, F, Fut>(&self, operation: &str, mut f: F) -> Result<T, ReplicationError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ReplicationError>>,
+    {
+        let mut retry = 0;
+
+        loop {
+            match f().await {
+                Err(error) if retry + 1 < self.max_attempts && is_transient_error(&error) => {
+                    let backoff = self.backoff(retry);
+
+                    tracing::warn!(
+                        operation,
+                        attempt = retry + 1,
+                        ?backoff,
+                        %error,
+                        "transient failure while setting up replication; retrying"
+                    );
+
+                    rt::sleep(backoff).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Returns `true` if an error with the SQLSTATE `code` is transient, i.e. may not happen again
+/// if the operation is retried on a new connection (possibly after a delay).
+///
+/// These are the connection exceptions (class `08`), `too_many_connections` (`53300`),
+/// `admin_shutdown`, `crash_shutdown` and `cannot_connect_now` (`57P01` to `57P03`), as sent
+/// while a server shuts down or starts up, and `serialization_failure` and
+/// `deadlock_detected` (`40001`, `40P01`). All other errors, e.g. `duplicate_object` for a
+/// slot that already exists or `syntax_error`, are permanent.
+pub fn is_transient(code: &str) -> bool {
+    code.starts_with("08")
+        || matches!(
+            code,
+            "53300" | "57P01" | "57P02" | "57P03" | "40001" | "40P01"
+        )
+}
+
+fn is_transient_error(error: &ReplicationError) -> bool {
+    match error {
+        ReplicationError::ServerShutdown { .. } | ReplicationError::Conflict { .. } => true,
+        ReplicationError::Sqlx(Error::Io(_)) => true,
+        ReplicationError::Sqlx(Error::Database(error)) => {
+            error.code().is_some_and(|code| is_transient(&code))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn it_backs_off_exponentially() {
+        let policy = RetryPolicy::new()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(500));
+
+        let backoffs: Vec<_> = (0..5)
+            .map(|retry| policy.backoff(retry).as_millis())
+            .collect();
+        assert_eq!(backoffs, [100, 200, 400, 500, 500]);
+
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(500));
+
+        let constant = policy.multiplier(1);
+        assert_eq!(constant.backoff(3), Duration::from_millis(100));
+
+        assert_eq!(RetryPolicy::never(), RetryPolicy::new().max_attempts(0));
+    }
+
+    #[test]
+    fn it_classifies_sqlstates() {
+        for code in [
+            "08000", "08006", "08P01", "53300", "57P01", "57P03", "40001",
+        ] {
+            assert!(is_transient(code), "{code}");
+        }
+
+        // duplicate_object, syntax_error, undefined_object, insufficient_privilege, disk_full
+        for code in ["42710", "42601", "42704", "42501", "53100"] {
+            assert!(!is_transient(code), "{code}");
+        }
+    }
+
+    #[test]
+    fn it_classifies_errors() {
+        let reset = Error::Io(io::Error::from(io::ErrorKind::ConnectionReset));
+        assert!(is_transient_error(&reset.into()));
+
+        assert!(!is_transient_error(&ReplicationError::InvalidOptions {
+            reason: "".into()
+        }));
+        assert!(!is_transient_error(&Error::Protocol("".into()).into()));
+    }
+}
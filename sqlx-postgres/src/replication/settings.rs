@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use sqlx_core::row::Row;
+
+use crate::error::Error;
+use crate::PgConnection;
+
+/// The server settings that replication depends on, as returned by
+/// [`replication_settings()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReplicationSettings {
+    /// `wal_level`; logical replication requires `logical`.
+    pub wal_level: String,
+    /// `max_replication_slots`, the number of slots that can exist at the same time.
+    pub max_replication_slots: u32,
+    /// `max_wal_senders`, the number of replication connections that can stream at the same
+    /// time.
+    pub max_wal_senders: u32,
+    /// `wal_sender_timeout`, after which the server ends a stream that didn't send a status
+    /// update; `None` if the timeout is disabled.
+    pub wal_sender_timeout: Option<Duration>,
+}
+
+impl ReplicationSettings {
+    /// Returns `true` if `wal_level` allows logical replication.
+    pub fn is_logical(&self) -> bool {
+        self.wal_level == "logical"
+    }
+}
+
+/// Read the server settings that replication depends on, e.g. to report a `wal_level` other
+/// than `logical` with a clear message instead of the error of a failing slot creation.
+///
+/// This runs on a normal (non-replication) connection.
+pub async fn replication_settings<C: AsMut<PgConnection>>(
+    mut conn: C,
+) -> Result<ReplicationSettings, Error> {
+    // `pg_settings` reports `wal_sender_timeout` in milliseconds, `current_setting()` with a unit
+    let row = crate::query::query(
+        "SELECT pg_catalog.current_setting('wal_level'), \
+         pg_catalog.current_setting('max_replication_slots')::int4, \
+         pg_catalog.current_setting('max_wal_senders')::int4, \
+         (SELECT setting::int8 FROM pg_catalog.pg_settings WHERE name = 'wal_sender_timeout')",
+    )
+    .fetch_one(conn.as_mut())
+    .await?;
+
+    let max_replication_slots: i32 = row.try_get(1)?;
+    let max_wal_senders: i32 = row.try_get(2)?;
+    let wal_sender_timeout: i64 = row.try_get(3)?;
+
+    Ok(ReplicationSettings {
+        wal_level: row.try_get(0)?,
+        max_replication_slots: u32::try_from(max_replication_slots)
+            .map_err(|error| Error::Decode(error.into()))?,
+        max_wal_senders: u32::try_from(max_wal_senders)
+            .map_err(|error| Error::Decode(error.into()))?,
+        wal_sender_timeout: u64::try_from(wal_sender_timeout)
+            .ok()
+            .filter(|&millis| millis > 0)
+            .map(Duration::from_millis),
+    })
+}
@@ -0,0 +1,311 @@
+use futures_core::future::BoxFuture;
+#[cfg(feature = "json")]
+use futures_util::io::{AsyncWrite, AsyncWriteExt};
+#[cfg(feature = "json")]
+use serde_json::{json, Value as JsonValue};
+
+use crate::error::Error;
+use crate::types::PgLsn;
+
+#[cfg(feature = "json")]
+use super::{BeforeImage, Projection};
+use super::{LogicalReplication, Relation};
+
+/// A destination of the changes of a [`LogicalReplicationStream`], e.g. a message broker,
+/// another database or a file, which the stream is pumped into with
+/// [`LogicalReplicationStream::pump_into()`].
+///
+/// The stream [applies][Self::apply] every message it receives to the sink in order, and
+/// [flushes][Self::flush_checkpoint] it after the commits of transactions, before confirming
+/// them to the server. A sink only needs to make the changes applied before a checkpoint
+/// durable once it is flushed to get at-least-once delivery: the changes after the last
+/// checkpoint are received again after a restart, so they should be applied idempotently,
+/// e.g. by key, or deduplicated by the position of their commit.
+///
+/// The methods return boxed futures, so that the trait can be used as a trait object, like
+/// [`OffsetStore`][super::OffsetStore]:
+///
+/// ```rust
+/// use futures_util::future::{self, BoxFuture, FutureExt};
+/// use sqlx::postgres::replication::{ChangeSink, LogicalReplication, Relation};
+/// use sqlx::postgres::types::PgLsn;
+///
+/// #[derive(Default)]
+/// struct CountingSink {
+///     inserts: u64,
+///     checkpoint: Option<PgLsn>,
+/// }
+///
+/// impl ChangeSink for CountingSink {
+///     fn apply<'a>(
+///         &'a mut self,
+///         change: &'a LogicalReplication,
+///         _relation: Option<&'a Relation>,
+///     ) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+///         if let LogicalReplication::Insert(_) = change {
+///             self.inserts += 1;
+///         }
+///         future::ready(Ok(())).boxed()
+///     }
+///
+///     fn flush_checkpoint(&mut self, lsn: PgLsn) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+///         self.checkpoint = Some(lsn);
+///         future::ready(Ok(())).boxed()
+///     }
+/// }
+/// ```
+///
+/// [`LogicalReplicationStream`]: super::LogicalReplicationStream
+/// [`LogicalReplicationStream::pump_into()`]: super::LogicalReplicationStream::pump_into
+pub trait ChangeSink: Send {
+    /// Apply a message received from the stream, e.g. buffer it to be written.
+    ///
+    /// All messages are passed, including `Begin`, `Commit` and `Relation` messages, and
+    /// the segments of transactions streamed while in progress with their `StreamAbort`s; a
+    /// sink ignores the messages it has no use for. `relation` is the relation of an
+    /// [`Insert`][super::Insert], [`Update`][super::Update], [`Delete`][super::Delete] or
+    /// [`Relation`] message as known to the stream, and `None` for other messages.
+    fn apply<'a>(
+        &'a mut self,
+        change: &'a LogicalReplication,
+        relation: Option<&'a Relation>,
+    ) -> BoxFuture<'a, Result<(), Error>>;
+
+    /// Make the messages applied so far durable; once this returns, the stream confirms `lsn`,
+    /// the end of the transaction applied last, to the server, which then doesn't send them
+    /// again.
+    fn flush_checkpoint(&mut self, lsn: PgLsn) -> BoxFuture<'_, Result<(), Error>>;
+}
+
+impl<T> ChangeSink for Box<T>
+where
+    T: ChangeSink + ?Sized,
+{
+    fn apply<'a>(
+        &'a mut self,
+        change: &'a LogicalReplication,
+        relation: Option<&'a Relation>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        (**self).apply(change, relation)
+    }
+
+    fn flush_checkpoint(&mut self, lsn: PgLsn) -> BoxFuture<'_, Result<(), Error>> {
+        (**self).flush_checkpoint(lsn)
+    }
+}
+
+/// A [`ChangeSink`] that writes the changes as JSON Lines, i.e. one JSON object per line, to
+/// an [`AsyncWrite`], e.g. a file or a socket.
+///
+/// Each insert, update, delete and truncate is written as an object with the operation as
+/// `op` and the schema and name of the relation, unquoted, as `table`, with the rows mapped
+/// like with [`Tuples::to_json()`][super::Tuples::to_json]:
+///
+/// ```json
+/// {"op":"insert","table":"public.users","new":{"id":1,"name":"foo"}}
+/// {"op":"update","table":"public.users","old":null,"new":{"id":1,"name":"bar"}}
+/// {"op":"delete","table":"public.users","old":{"id":1}}
+/// {"op":"truncate","relation_ids":[16385]}
+/// {"op":"commit","commit_lsn":"0/16B3748","end_lsn":"0/16B3778"}
+/// ```
+///
+/// `old` is the row before an update or delete as far as it was sent, i.e. only its key
+/// columns unless the relation has `REPLICA IDENTITY FULL`. The commit of each transaction
+/// is written as well, so that a reader can skip the transactions it has seen before by their
+/// `end_lsn`; the other messages are not written. Lines are written as they are applied, and
+/// the writer is flushed at each checkpoint, so wrap a writer that doesn't buffer itself into
+/// a buffered writer.
+///
+/// The changes of transactions streamed while in progress are written as they are received,
+/// including those of transactions or subtransactions that are aborted later, so leave
+/// [streaming][super::PgOutputOptions::streaming] off.
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub struct JsonLinesSink<W> {
+    writer: W,
+}
+
+#[cfg(feature = "json")]
+impl<W> JsonLinesSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    /// Write the changes to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    async fn write_line(&mut self, value: JsonValue) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(&value).map_err(|error| Error::Encode(error.into()))?;
+        line.push(b'\n');
+
+        self.writer.write_all(&line).await.map_err(Error::Io)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W> ChangeSink for JsonLinesSink<W>
+where
+    W: AsyncWrite + Unpin + Send,
+{
+    fn apply<'a>(
+        &'a mut self,
+        change: &'a LogicalReplication,
+        relation: Option<&'a Relation>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let Some(line) = change_to_json(change, relation)? else {
+                return Ok(());
+            };
+
+            self.write_line(line).await
+        })
+    }
+
+    fn flush_checkpoint(&mut self, _lsn: PgLsn) -> BoxFuture<'_, Result<(), Error>> {
+        Box::pin(async move { self.writer.flush().await.map_err(Error::Io) })
+    }
+}
+
+/// The line a [`JsonLinesSink`] writes for `change`, if any.
+#[cfg(feature = "json")]
+fn change_to_json(
+    change: &LogicalReplication,
+    relation: Option<&Relation>,
+) -> Result<Option<JsonValue>, Error> {
+    let relation = |relation_id: crate::types::Oid| {
+        relation.ok_or_else(|| {
+            Error::Protocol(format!(
+                "change of relation {} received before its Relation message",
+                relation_id.0
+            ))
+        })
+    };
+
+    let line = match change {
+        LogicalReplication::Insert(insert) => {
+            let relation = relation(insert.relation_id)?;
+
+            json!({
+                "op": "insert",
+                "table": format!("{}.{}", relation.namespace, relation.name),
+                "new": insert.new_data.to_json(relation)?,
+            })
+        }
+        LogicalReplication::Update(update) => {
+            let relation = relation(update.relation_id)?;
+            let (old, new) = update.to_json_images(relation)?;
+
+            json!({
+                "op": "update",
+                "table": format!("{}.{}", relation.namespace, relation.name),
+                "old": old.map(BeforeImage::into_inner),
+                "new": new,
+            })
+        }
+        LogicalReplication::Delete(delete) => {
+            let relation = relation(delete.relation_id)?;
+            let old = match (&delete.key_data, &delete.old_data) {
+                (Some(key), _) => {
+                    Some(key.to_json_projected(relation, &Projection::key(relation))?)
+                }
+                (None, Some(old)) => Some(old.to_json(relation)?),
+                (None, None) => None,
+            };
+
+            json!({
+                "op": "delete",
+                "table": format!("{}.{}", relation.namespace, relation.name),
+                "old": old,
+            })
+        }
+        LogicalReplication::Truncate(truncate) => json!({
+            "op": "truncate",
+            "relation_ids": truncate.relation_ids.iter().map(|id| id.0).collect::<Vec<_>>(),
+        }),
+        LogicalReplication::Commit(commit) => json!({
+            "op": "commit",
+            "commit_lsn": commit.commit_lsn.to_string(),
+            "end_lsn": commit.end_lsn.to_string(),
+        }),
+        LogicalReplication::StreamCommit(commit) => json!({
+            "op": "commit",
+            "commit_lsn": commit.commit_lsn.to_string(),
+            "end_lsn": commit.end_lsn.to_string(),
+        }),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(line))
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+    use crate::replication::frames::PgOutputFrameBuilder;
+    use crate::replication::TupleData;
+    use crate::types::Oid;
+
+    const USERS: Oid = Oid(16385);
+
+    fn text(value: &str) -> TupleData {
+        TupleData::Text(Bytes::copy_from_slice(value.as_bytes()))
+    }
+
+    #[test]
+    fn it_maps_changes_to_json_lines() {
+        let messages = PgOutputFrameBuilder::new()
+            .begin(PgLsn(0x200), 0, 742)
+            .relation(
+                USERS,
+                "public",
+                "users",
+                b'd',
+                &[(1, "id", Oid(23), -1), (0, "name", Oid(25), -1)],
+            )
+            .insert(USERS, &[text("1"), text("foo")])
+            .update(USERS, None, &[text("1"), text("bar")])
+            .delete(USERS, b'K', &[text("1"), TupleData::Null])
+            .truncate(0, &[USERS])
+            .commit(PgLsn(0x200), PgLsn(0x230), 0)
+            .decode();
+
+        let LogicalReplication::Relation(relation) = &messages[1] else {
+            panic!("expected Relation, got {:?}", messages[1]);
+        };
+
+        let lines: Vec<_> = (messages.iter())
+            .filter_map(|message| change_to_json(message, Some(relation)).unwrap())
+            .map(|line| line.to_string())
+            .collect();
+
+        assert_eq!(
+            lines,
+            [
+                r#"{"new":{"id":1,"name":"foo"},"op":"insert","table":"public.users"}"#,
+                r#"{"new":{"id":1,"name":"bar"},"old":null,"op":"update","table":"public.users"}"#,
+                r#"{"old":{"id":1},"op":"delete","table":"public.users"}"#,
+                r#"{"op":"truncate","relation_ids":[16385]}"#,
+                r#"{"commit_lsn":"0/200","end_lsn":"0/230","op":"commit"}"#,
+            ]
+        );
+
+        // a change can't be mapped without its relation
+        assert!(matches!(
+            change_to_json(&messages[2], None),
+            Err(Error::Protocol(_))
+        ));
+    }
+}
@@ -0,0 +1,173 @@
+use std::cmp;
+
+/// The smallest read buffer capacity chosen by adaptive sizing, also the default capacity of the
+/// read buffer of a connection.
+const MIN_READ_BUFFER: usize = 8192;
+
+/// The number of frames of the recent mean size an adaptively sized read buffer holds.
+const FRAMES_PER_READ: usize = 16;
+
+/// The inverse of the weight of a new frame in the recent mean size.
+const RECENT_WEIGHT: usize = 16;
+
+/// The number of size classes of [`MessageSizes`]; class `i` holds the sizes below `2^i` not
+/// held by a smaller class, the last one all larger sizes.
+const SIZE_CLASSES: usize = 32;
+
+/// The distribution of the sizes of the frames a replication stream received, as returned by
+/// [`LogicalReplicationStream::message_sizes()`][super::LogicalReplicationStream::message_sizes].
+///
+/// Sizes are the lengths of the payloads of the `CopyData` frames, including keepalives, and
+/// are counted by power-of-two size class, so [percentiles][Self::percentile] are upper bounds
+/// within a factor of two.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageSizes {
+    count: u64,
+    total: u64,
+    max: usize,
+    /// The recent mean size times `RECENT_WEIGHT`.
+    recent: usize,
+    classes: [u64; SIZE_CLASSES],
+}
+
+impl Default for MessageSizes {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            total: 0,
+            max: 0,
+            recent: 0,
+            classes: [0; SIZE_CLASSES],
+        }
+    }
+}
+
+impl MessageSizes {
+    /// The number of frames received.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The total size of all frames received, in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        self.total
+    }
+
+    /// The size of the largest frame received.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// The mean size of all frames received; `0` if none was received.
+    pub fn mean(&self) -> u64 {
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+
+    /// The mean size of the recent frames, in which each frame weighs more than the frames
+    /// received before it, so that it follows changes of the workload.
+    pub fn recent_mean(&self) -> usize {
+        self.recent / RECENT_WEIGHT
+    }
+
+    /// An upper bound of the `percent`th percentile (at most `100`) of the frame sizes, e.g.
+    /// `percentile(99)` for the size that 99% of the frames don't exceed, within a factor of
+    /// two; `0` if no frame was received.
+    pub fn percentile(&self, percent: u8) -> usize {
+        let rank = cmp::max(
+            (self.count * u64::from(cmp::min(percent, 100))).div_ceil(100),
+            1,
+        );
+        let mut seen = 0;
+
+        for (class, count) in (0u32..).zip(self.classes) {
+            seen += count;
+
+            if seen >= rank && count > 0 {
+                let upper = 1usize
+                    .checked_shl(class)
+                    .map_or(usize::MAX, |bound| bound - 1);
+
+                return cmp::min(upper, self.max);
+            }
+        }
+
+        self.max
+    }
+
+    pub(super) fn record(&mut self, size: usize) {
+        self.recent = if self.count == 0 {
+            size.saturating_mul(RECENT_WEIGHT)
+        } else {
+            (self.recent - self.recent / RECENT_WEIGHT).saturating_add(size)
+        };
+
+        self.count += 1;
+        self.total += size as u64;
+        self.max = cmp::max(self.max, size);
+
+        let class = (usize::BITS - size.leading_zeros()) as usize;
+        self.classes[cmp::min(class, SIZE_CLASSES - 1)] += 1;
+    }
+
+    /// The capacity of the read buffer for the recent frames, at most `max`: room for a few
+    /// frames of the recent mean size, rounded up to a power of two.
+    pub(super) fn read_buffer_capacity(&self, max: usize) -> usize {
+        let capacity = self.recent_mean().saturating_mul(FRAMES_PER_READ);
+
+        cmp::min(
+            cmp::max(capacity.next_power_of_two(), MIN_READ_BUFFER),
+            cmp::max(max, MIN_READ_BUFFER),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tracks_message_sizes() {
+        let mut sizes = MessageSizes::default();
+        assert_eq!(sizes.mean(), 0);
+        assert_eq!(sizes.percentile(50), 0);
+
+        for size in [100, 100, 100, 200, 5000] {
+            sizes.record(size);
+        }
+
+        assert_eq!(sizes.count(), 5);
+        assert_eq!(sizes.total_bytes(), 5500);
+        assert_eq!(sizes.max(), 5000);
+        assert_eq!(sizes.mean(), 1100);
+
+        // 100 is in the class of 64..=127, 200 in 128..=255
+        assert_eq!(sizes.percentile(0), 127);
+        assert_eq!(sizes.percentile(60), 127);
+        assert_eq!(sizes.percentile(80), 255);
+        assert_eq!(sizes.percentile(100), 5000);
+        assert_eq!(sizes.percentile(200), 5000);
+
+        // the recent mean follows the latest frames
+        assert!(sizes.recent_mean() > 100 && sizes.recent_mean() < 1100);
+    }
+
+    #[test]
+    fn it_sizes_the_read_buffer() {
+        let mut sizes = MessageSizes::default();
+        sizes.record(100);
+        assert_eq!(sizes.read_buffer_capacity(1 << 20), MIN_READ_BUFFER);
+
+        // grows for large frames, up to the cap
+        for _ in 0..100 {
+            sizes.record(50_000);
+        }
+        assert_eq!(sizes.read_buffer_capacity(1 << 24), 1 << 20);
+        assert_eq!(sizes.read_buffer_capacity(1 << 18), 1 << 18);
+
+        // and shrinks again for small ones
+        for _ in 0..200 {
+            sizes.record(100);
+        }
+        assert_eq!(sizes.read_buffer_capacity(1 << 24), MIN_READ_BUFFER);
+    }
+}
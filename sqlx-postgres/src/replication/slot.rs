@@ -0,0 +1,810 @@
+use sqlx_core::connection::Connection;
+use sqlx_core::executor::Executor;
+use sqlx_core::transaction::Transaction;
+
+use crate::error::Error;
+use crate::types::PgLsn;
+use crate::{PgConnection, Postgres};
+
+use super::{
+    quote_ident, quote_literal, LogicalReplicationStream, PgOutputOptions, PgReplicationConnection,
+    PhysicalReplicationStream, ReplicationError,
+};
+
+/// Builder for the `CREATE_REPLICATION_SLOT` command.
+///
+/// The command is sent in the syntax of the version of the server: with the parenthesized
+/// options of Postgres 15 and later (`(SNAPSHOT 'use', TWO_PHASE 'true')`), or with the
+/// keywords of older versions (`USE_SNAPSHOT TWO_PHASE`).
+///
+/// <https://www.postgresql.org/docs/current/protocol-replication.html#PROTOCOL-REPLICATION-CREATE-REPLICATION-SLOT>
+///
+/// ```rust
+/// # use sqlx::postgres::replication::{CreateReplicationSlot, SnapshotAction};
+/// let slot = CreateReplicationSlot::logical("my_slot", "pgoutput")
+///     .temporary(true)
+///     .snapshot(SnapshotAction::NoExport);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CreateReplicationSlot {
+    pub(crate) name: String,
+    pub(crate) plugin: Option<String>,
+    pub(crate) temporary: bool,
+    pub(crate) snapshot: Option<SnapshotAction>,
+    pub(crate) two_phase: bool,
+    pub(crate) reserve_wal: bool,
+    pub(crate) check_wal_level: bool,
+    pub(crate) options: Vec<(String, String)>,
+}
+
+/// The options that are set with the typed methods of [`CreateReplicationSlot`], which must not
+/// be passed with [`CreateReplicationSlot::option()`].
+const TYPED_OPTIONS: &[&str] = &["snapshot", "two_phase", "reserve_wal"];
+
+/// What to do with the snapshot created while creating a logical replication slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotAction {
+    /// Export the snapshot, so that other sessions can use it with `SET TRANSACTION SNAPSHOT`
+    /// until the next command is run on the replication connection. This is the default.
+    Export,
+    /// Do not export the snapshot.
+    NoExport,
+    /// Use the snapshot for the current transaction of the replication connection, which must
+    /// be the first command in a `REPEATABLE READ` transaction.
+    Use,
+}
+
+/// Where to start streaming from a logical replication slot, as passed to
+/// [`PgReplicationConnection::start_logical_replication()`].
+///
+/// A [`PgLsn`] converts into [`StartPosition::Lsn`], so a position can also be passed
+/// directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StartPosition {
+    /// Start at the slot's confirmed position, i.e. resume where the last consumer stopped.
+    /// The same as [`PgLsn::INVALID`].
+    Slot,
+    /// Start at the given position, or at the slot's confirmed position if that is later.
+    Lsn(PgLsn),
+    /// Don't replay changes, begin near now: start at the current WAL flush location of the
+    /// server, as returned by [`PgReplicationConnection::identify_system()`].
+    ///
+    /// The slot's confirmed position still governs the real start: the server never streams
+    /// from before it, and it only starts later than the current WAL location for a slot that
+    /// was already confirmed past it. The changes committed between the slot's confirmed
+    /// position and the start are skipped, and the slot is advanced past them with the
+    /// [initial status update][LogicalReplicationStream::set_initial_status], so no other
+    /// consumer of the slot can receive them either.
+    Latest,
+}
+
+impl From<PgLsn> for StartPosition {
+    fn from(lsn: PgLsn) -> Self {
+        StartPosition::Lsn(lsn)
+    }
+}
+
+/// A replication slot as returned by
+/// [`PgReplicationConnection::create_replication_slot()`], or by
+/// [`PgReplicationConnection::replication_slot()`] for an existing slot.
+///
+/// Streaming from the slot, advancing it and dropping it are available as methods, so that the
+/// slot name doesn't have to be passed around:
+///
+/// ```rust,no_run
+/// # async fn example() -> Result<(), sqlx::postgres::replication::ReplicationError> {
+/// use sqlx::postgres::replication::{CreateReplicationSlot, PgOutputOptions, PgReplicationConnection};
+/// use sqlx::postgres::types::PgLsn;
+///
+/// let mut conn = PgReplicationConnection::connect("postgres://localhost/mydb").await?;
+///
+/// let slot = conn
+///     .create_replication_slot(&CreateReplicationSlot::logical("my_slot", "pgoutput"))
+///     .await?;
+///
+/// let stream = slot
+///     .start_streaming(conn, PgLsn::INVALID, PgOutputOptions::new(["my_pub"]))
+///     .await?;
+///
+/// let mut conn = stream.finish().await?;
+/// slot.drop(&mut conn, false).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PgReplicationSlot {
+    /// The name of the slot.
+    pub slot_name: String,
+    /// The WAL location at which the slot became consistent. This is the earliest location
+    /// from which streaming can start on this slot.
+    pub consistent_point: PgLsn,
+    /// The identifier of the snapshot exported by the command, if any.
+    pub snapshot_name: Option<String>,
+    /// The output plugin used by the slot; `None` for physical slots.
+    pub output_plugin: Option<String>,
+    /// Whether the slot is temporary, i.e. dropped when the connection that created it is
+    /// closed.
+    pub temporary: bool,
+    /// Whether the slot decodes prepared transactions at `PREPARE TRANSACTION` (Postgres 14+);
+    /// see [`CreateReplicationSlot::two_phase()`].
+    pub two_phase: bool,
+}
+
+/// The result of the `IDENTIFY_SYSTEM` command.
+#[derive(Debug, Clone)]
+pub struct IdentifySystem {
+    /// The unique system identifier of the cluster.
+    pub systemid: String,
+    /// The current timeline ID.
+    pub timeline: i32,
+    /// The current WAL flush location.
+    pub xlogpos: PgLsn,
+    /// The database connected to.
+    pub dbname: Option<String>,
+}
+
+impl CreateReplicationSlot {
+    /// Create a logical replication slot using the given output plugin (e.g. `pgoutput`).
+    pub fn logical(name: impl Into<String>, plugin: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            plugin: Some(plugin.into()),
+            temporary: false,
+            snapshot: None,
+            two_phase: false,
+            reserve_wal: false,
+            check_wal_level: true,
+            options: Vec::new(),
+        }
+    }
+
+    /// Create a physical replication slot.
+    pub fn physical(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            plugin: None,
+            temporary: false,
+            snapshot: None,
+            two_phase: false,
+            reserve_wal: false,
+            check_wal_level: true,
+            options: Vec::new(),
+        }
+    }
+
+    /// Sets whether the slot is temporary.
+    ///
+    /// Temporary slots are not saved to disk and are dropped automatically when the
+    /// connection that created them is closed.
+    pub fn temporary(mut self, temporary: bool) -> Self {
+        self.temporary = temporary;
+        self
+    }
+
+    /// Sets what to do with the snapshot created for a logical slot.
+    pub fn snapshot(mut self, snapshot: SnapshotAction) -> Self {
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Sets whether a logical slot supports decoding of prepared transactions (Postgres 14+).
+    pub fn two_phase(mut self, two_phase: bool) -> Self {
+        self.two_phase = two_phase;
+        self
+    }
+
+    /// Sets whether a physical slot reserves WAL immediately, instead of on the first
+    /// connection of a streaming client.
+    pub fn reserve_wal(mut self, reserve_wal: bool) -> Self {
+        self.reserve_wal = reserve_wal;
+        self
+    }
+
+    /// Sets whether creating a logical slot first checks that the server runs with
+    /// `wal_level = logical`, returning [`ReplicationError::WalLevelInsufficient`] if it
+    /// doesn't. This is the default; the check costs a round trip, which can be saved once the
+    /// configuration is known to be right.
+    pub fn check_wal_level(mut self, check_wal_level: bool) -> Self {
+        self.check_wal_level = check_wal_level;
+        self
+    }
+
+    /// Adds an option that is passed through to the command as is, for options of the output
+    /// plugin or the server that have no typed method.
+    ///
+    /// The name is quoted as an identifier, so it must be given as the server expects it
+    /// (usually lowercase), and the value as a string literal. Options require the
+    /// parenthesized option syntax of Postgres 15 and later.
+    ///
+    /// Creating the slot fails with [`ReplicationError::InvalidOptions`] if an option is added
+    /// twice, if it is one of the typed options (`snapshot`, `two_phase` or `reserve_wal`), or
+    /// if the server runs an older version than Postgres 15.
+    pub fn option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    /// The command for a server with the version `server_version`, as reported in
+    /// `server_version_num`.
+    ///
+    /// Postgres 15 and later get the parenthesized option syntax, older versions the options
+    /// as keywords. If the version is not known, the keywords are used unless options without
+    /// a typed method are set, as later versions still understand them.
+    pub(crate) fn to_command(
+        &self,
+        server_version: Option<u32>,
+    ) -> Result<String, ReplicationError> {
+        let parenthesized = match server_version {
+            Some(version) => version >= 150000,
+            None => !self.options.is_empty(),
+        };
+
+        if let Some(version) = server_version {
+            if !parenthesized && !self.options.is_empty() {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: format!(
+                        "slot options require Postgres 15 or later, \
+                         but the server version is {version}"
+                    ),
+                });
+            }
+
+            if self.two_phase && self.plugin.is_some() && version < 140000 {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: format!(
+                        "two-phase slots require Postgres 14 or later, \
+                         but the server version is {version}"
+                    ),
+                });
+            }
+        }
+
+        if !parenthesized {
+            return Ok(self.to_legacy_command());
+        }
+
+        for (i, (name, _)) in self.options.iter().enumerate() {
+            if name.is_empty() {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: "slot option names must not be empty".into(),
+                });
+            }
+
+            if let Some(typed) = TYPED_OPTIONS
+                .iter()
+                .find(|typed| name.eq_ignore_ascii_case(typed))
+            {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: format!("slot option {name:?} must be set with `{typed}()`"),
+                });
+            }
+
+            if self.options[..i].iter().any(|(other, _)| other == name) {
+                return Err(ReplicationError::InvalidOptions {
+                    reason: format!("slot option {name:?} is given more than once"),
+                });
+            }
+        }
+
+        let mut command = format!("CREATE_REPLICATION_SLOT {}", quote_ident(&self.name));
+
+        if self.temporary {
+            command.push_str(" TEMPORARY");
+        }
+
+        let mut options = Vec::new();
+
+        match &self.plugin {
+            Some(plugin) => {
+                command.push_str(" LOGICAL ");
+                command.push_str(&quote_ident(plugin));
+
+                match self.snapshot {
+                    Some(SnapshotAction::Export) => options.push("snapshot 'export'".to_owned()),
+                    Some(SnapshotAction::NoExport) => options.push("snapshot 'nothing'".to_owned()),
+                    Some(SnapshotAction::Use) => options.push("snapshot 'use'".to_owned()),
+                    None => {}
+                }
+
+                if self.two_phase {
+                    options.push("two_phase 'true'".to_owned());
+                }
+            }
+
+            None => {
+                command.push_str(" PHYSICAL");
+
+                if self.reserve_wal {
+                    options.push("reserve_wal 'true'".to_owned());
+                }
+            }
+        }
+
+        options.extend(
+            (self.options.iter())
+                .map(|(name, value)| format!("{} {}", quote_ident(name), quote_literal(value))),
+        );
+
+        if !options.is_empty() {
+            command.push_str(" (");
+            command.push_str(&options.join(", "));
+            command.push(')');
+        }
+
+        Ok(command)
+    }
+
+    /// The command in the syntax before Postgres 15, which is still understood by later
+    /// versions.
+    fn to_legacy_command(&self) -> String {
+        let mut command = format!("CREATE_REPLICATION_SLOT {}", quote_ident(&self.name));
+
+        if self.temporary {
+            command.push_str(" TEMPORARY");
+        }
+
+        match &self.plugin {
+            Some(plugin) => {
+                command.push_str(" LOGICAL ");
+                command.push_str(&quote_ident(plugin));
+
+                match self.snapshot {
+                    Some(SnapshotAction::Export) => command.push_str(" EXPORT_SNAPSHOT"),
+                    Some(SnapshotAction::NoExport) => command.push_str(" NOEXPORT_SNAPSHOT"),
+                    Some(SnapshotAction::Use) => command.push_str(" USE_SNAPSHOT"),
+                    None => {}
+                }
+
+                if self.two_phase {
+                    command.push_str(" TWO_PHASE");
+                }
+            }
+
+            None => {
+                command.push_str(" PHYSICAL");
+
+                if self.reserve_wal {
+                    command.push_str(" RESERVE_WAL");
+                }
+            }
+        }
+
+        command
+    }
+}
+
+impl PgReplicationSlot {
+    /// The name of the slot.
+    pub fn name(&self) -> &str {
+        &self.slot_name
+    }
+
+    /// Start streaming changes from this logical slot using `pgoutput`; see
+    /// [`PgReplicationConnection::start_logical_replication()`].
+    pub async fn start_streaming(
+        &self,
+        conn: PgReplicationConnection,
+        start: impl Into<StartPosition>,
+        options: PgOutputOptions,
+    ) -> Result<LogicalReplicationStream, ReplicationError> {
+        if self.output_plugin.is_none() {
+            return Err(ReplicationError::NotLogicalSlot {
+                slot: self.slot_name.clone(),
+            });
+        }
+
+        conn.start_logical_replication(&self.slot_name, start, options)
+            .await
+    }
+
+    /// Start streaming WAL with physical replication from this physical slot; see
+    /// [`PgReplicationConnection::start_physical_replication()`].
+    pub async fn start_physical_streaming(
+        &self,
+        conn: PgReplicationConnection,
+        start_lsn: PgLsn,
+        timeline: Option<u32>,
+    ) -> Result<PhysicalReplicationStream, ReplicationError> {
+        conn.start_physical_replication(Some(&self.slot_name), start_lsn, timeline)
+            .await
+    }
+
+    /// Advance this logical slot on a normal connection; see [`advance_replication_slot()`].
+    pub async fn advance<C: AsMut<PgConnection>>(
+        &self,
+        conn: C,
+        upto: PgLsn,
+    ) -> Result<PgLsn, ReplicationError> {
+        advance_replication_slot(conn, &self.slot_name, upto).await
+    }
+
+    /// The bytes of WAL this slot keeps the server from removing; see
+    /// [`retained_wal_bytes()`].
+    pub async fn retained_wal_bytes<C: AsMut<PgConnection>>(
+        &self,
+        conn: C,
+    ) -> Result<u64, ReplicationError> {
+        retained_wal_bytes(conn, &self.slot_name).await
+    }
+
+    /// Start a transaction on a normal connection that sees the snapshot exported when this
+    /// slot was created; see [`import_snapshot()`].
+    ///
+    /// Returns [`ReplicationError::InvalidOptions`] if the slot was created without
+    /// [`SnapshotAction::Export`], or was looked up rather than created.
+    pub async fn import_snapshot<'c>(
+        &self,
+        conn: &'c mut PgConnection,
+    ) -> Result<Transaction<'c, Postgres>, ReplicationError> {
+        let Some(snapshot) = &self.snapshot_name else {
+            return Err(ReplicationError::InvalidOptions {
+                reason: format!(
+                    "replication slot {:?} did not export a snapshot",
+                    self.slot_name
+                ),
+            });
+        };
+
+        import_snapshot(conn, snapshot).await
+    }
+
+    /// Drop this slot; see [`PgReplicationConnection::drop_replication_slot()`].
+    pub async fn drop(self, conn: &mut PgReplicationConnection, wait: bool) -> Result<(), Error> {
+        conn.drop_replication_slot(&self.slot_name, wait).await
+    }
+}
+
+/// Start a `REPEATABLE READ, READ ONLY` transaction on the normal connection `conn` that sees
+/// the snapshot `snapshot` exported by creating a replication slot, e.g. to copy the initial
+/// state of the tables that are then streamed from the consistent point of the slot.
+///
+/// The snapshot must be imported with `SET TRANSACTION SNAPSHOT` before any query of the
+/// transaction, and only a `REPEATABLE READ` or `SERIALIZABLE` transaction keeps seeing it
+/// afterwards; otherwise the copy would not be consistent with the stream. This starts the
+/// transaction and imports the snapshot before returning it, so these constraints always hold.
+///
+/// The snapshot can only be imported until the next command is run on the replication
+/// connection that created the slot, or until it is closed; keep it idle until all
+/// transactions that need the snapshot imported it.
+///
+/// Returns [`ReplicationError::InvalidOptions`] if `conn` is already in a transaction, and
+/// [`ReplicationError::SnapshotImport`] if the server rejected the snapshot, e.g. because the
+/// identifier is wrong or no longer valid.
+pub async fn import_snapshot<'c>(
+    conn: &'c mut PgConnection,
+    snapshot: &str,
+) -> Result<Transaction<'c, Postgres>, ReplicationError> {
+    if conn.inner.transaction_depth > 0 {
+        return Err(ReplicationError::InvalidOptions {
+            reason: "a snapshot can only be imported at the start of a new transaction, \
+                     but the connection is already in a transaction"
+                .into(),
+        });
+    }
+
+    let mut transaction = conn.begin().await?;
+
+    let result = transaction
+        .execute(&*format!(
+            "SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY; \
+             SET TRANSACTION SNAPSHOT {}",
+            quote_literal(snapshot)
+        ))
+        .await;
+
+    if let Err(error) = result {
+        return Err(match error {
+            Error::Database(_) => ReplicationError::SnapshotImport {
+                snapshot: snapshot.to_owned(),
+                source: error,
+            },
+            error => error.into(),
+        });
+    }
+
+    Ok(transaction)
+}
+
+/// Advance a logical replication slot to `upto` without consuming its changes, returning the
+/// position the slot was advanced to.
+///
+/// This runs `pg_replication_slot_advance()` on a normal (non-replication) connection. The slot
+/// is advanced to at most the current WAL flush location, so the returned position may be lower
+/// than `upto`.
+///
+/// Returns [`ReplicationError::AdvanceBackwards`] if `upto` is lower than the current position
+/// of the slot.
+pub async fn advance_replication_slot<C: AsMut<PgConnection>>(
+    mut conn: C,
+    slot: &str,
+    upto: PgLsn,
+) -> Result<PgLsn, ReplicationError> {
+    let result = crate::query_scalar::query_scalar(
+        "SELECT end_lsn FROM pg_catalog.pg_replication_slot_advance($1, $2)",
+    )
+    .bind(slot)
+    .bind(upto)
+    .fetch_one(conn.as_mut())
+    .await;
+
+    result.map_err(|error| map_advance_error(error, slot, upto))
+}
+
+/// Estimate the bytes of WAL the replication slot `slot` keeps the server from removing, e.g.
+/// to alert before a stuck consumer fills the disk.
+///
+/// This is the distance from the `restart_lsn` of the slot to the current WAL insert location
+/// (`pg_current_wal_insert_lsn()`, or the last replayed location on a standby). WAL is retained
+/// from `restart_lsn`, the start of the oldest transaction the slot may still have to decode,
+/// and not from `confirmed_flush_lsn`, the position confirmed by the consumer: a long-running
+/// transaction holds `restart_lsn` back even when the consumer confirms everything it receives,
+/// so the lag to the confirmed position underestimates the retained WAL.
+///
+/// Returns `0` if the slot doesn't reserve WAL yet, and [`ReplicationError::SlotNotFound`] if
+/// the slot doesn't exist. This runs on a normal (non-replication) connection.
+pub async fn retained_wal_bytes<C: AsMut<PgConnection>>(
+    mut conn: C,
+    slot: &str,
+) -> Result<u64, ReplicationError> {
+    let row: Option<(Option<PgLsn>, PgLsn)> = crate::query_as::query_as(
+        "SELECT restart_lsn, CASE WHEN pg_catalog.pg_is_in_recovery() \
+         THEN pg_catalog.pg_last_wal_replay_lsn() \
+         ELSE pg_catalog.pg_current_wal_insert_lsn() END \
+         FROM pg_catalog.pg_replication_slots WHERE slot_name = $1",
+    )
+    .bind(slot)
+    .fetch_optional(conn.as_mut())
+    .await?;
+
+    let Some((restart_lsn, current)) = row else {
+        return Err(ReplicationError::SlotNotFound {
+            slot: slot.to_owned(),
+        });
+    };
+
+    Ok(restart_lsn.map_or(0, |restart_lsn| current.0.saturating_sub(restart_lsn.0)))
+}
+
+/// The confirmed positions of two replication slots, as returned by [`slot_position_diff()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SlotPositionDiff {
+    /// The `confirmed_flush_lsn` of the first slot, or `None` if it has none.
+    pub first: Option<PgLsn>,
+    /// The `confirmed_flush_lsn` of the second slot, or `None` if it has none.
+    pub second: Option<PgLsn>,
+}
+
+impl SlotPositionDiff {
+    /// The signed distance in bytes from the confirmed position of the second slot to that of
+    /// the first slot: positive if the first slot is ahead, negative if it is behind.
+    ///
+    /// Returns `None` if either slot has no confirmed position to compare.
+    pub fn bytes(&self) -> Option<i64> {
+        Some(self.first?.diff(self.second?))
+    }
+}
+
+/// Compare the confirmed positions (`confirmed_flush_lsn`) of the replication slots `first`
+/// and `second`, e.g. to follow the progress of a new slot against an old one during a
+/// migration or a failover rehearsal.
+///
+/// A slot has no confirmed position if it is a physical slot, or a logical slot that is still
+/// being created; such a position is `None` rather than [`PgLsn::INVALID`], so that it is not
+/// mistaken for a slot that is far behind.
+///
+/// Returns [`ReplicationError::SlotNotFound`] if either slot doesn't exist. This runs on a
+/// normal (non-replication) connection.
+pub async fn slot_position_diff<C: AsMut<PgConnection>>(
+    mut conn: C,
+    first: &str,
+    second: &str,
+) -> Result<SlotPositionDiff, ReplicationError> {
+    let rows: Vec<(String, Option<PgLsn>)> = crate::query_as::query_as(
+        "SELECT slot_name::text, confirmed_flush_lsn \
+         FROM pg_catalog.pg_replication_slots WHERE slot_name IN ($1, $2)",
+    )
+    .bind(first)
+    .bind(second)
+    .fetch_all(conn.as_mut())
+    .await?;
+
+    let position = |slot: &str| {
+        rows.iter()
+            .find(|(name, _)| name == slot)
+            .map(|(_, lsn)| *lsn)
+            .ok_or_else(|| ReplicationError::SlotNotFound {
+                slot: slot.to_owned(),
+            })
+    };
+
+    Ok(SlotPositionDiff {
+        first: position(first)?,
+        second: position(second)?,
+    })
+}
+
+fn map_advance_error(error: Error, slot: &str, upto: PgLsn) -> ReplicationError {
+    let Some(db_error) = error.as_database_error() else {
+        return error.into();
+    };
+
+    match db_error.code().as_deref() {
+        // undefined_object
+        Some("42704") => ReplicationError::SlotNotFound {
+            slot: slot.to_owned(),
+        },
+
+        // object_not_in_prerequisite_state: "cannot advance replication slot to X/X, minimum is X/X"
+        Some("55000")
+            if db_error
+                .message()
+                .starts_with("cannot advance replication slot") =>
+        {
+            let minimum = db_error
+                .message()
+                .rsplit_once("minimum is ")
+                .and_then(|(_, lsn)| lsn.parse().ok());
+
+            ReplicationError::AdvanceBackwards {
+                slot: slot.to_owned(),
+                upto,
+                minimum,
+            }
+        }
+
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_diffs_slot_positions() {
+        let diff = SlotPositionDiff {
+            first: Some(PgLsn(0x100)),
+            second: Some(PgLsn(0x180)),
+        };
+        assert_eq!(diff.bytes(), Some(-0x80));
+
+        let never_confirmed = SlotPositionDiff {
+            second: None,
+            ..diff
+        };
+        assert_eq!(never_confirmed.bytes(), None);
+    }
+
+    #[test]
+    fn it_builds_create_replication_slot() {
+        // Postgres 14, and an unknown version
+        for version in [Some(140005), None] {
+            assert_eq!(
+                CreateReplicationSlot::logical("slot", "pgoutput")
+                    .to_command(version)
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" LOGICAL "pgoutput""#
+            );
+
+            assert_eq!(
+                CreateReplicationSlot::logical("slot", "pgoutput")
+                    .temporary(true)
+                    .snapshot(SnapshotAction::NoExport)
+                    .two_phase(true)
+                    .to_command(version)
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" TEMPORARY LOGICAL "pgoutput" NOEXPORT_SNAPSHOT TWO_PHASE"#
+            );
+
+            assert_eq!(
+                CreateReplicationSlot::physical("slot")
+                    .reserve_wal(true)
+                    .to_command(version)
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" PHYSICAL RESERVE_WAL"#
+            );
+        }
+
+        // Postgres 10
+        assert_eq!(
+            CreateReplicationSlot::logical("slot", "pgoutput")
+                .snapshot(SnapshotAction::Use)
+                .to_command(Some(100023))
+                .unwrap(),
+            r#"CREATE_REPLICATION_SLOT "slot" LOGICAL "pgoutput" USE_SNAPSHOT"#
+        );
+    }
+
+    #[test]
+    fn it_builds_create_replication_slot_for_postgres_15() {
+        for version in [150000, 160004] {
+            assert_eq!(
+                CreateReplicationSlot::logical("slot", "pgoutput")
+                    .to_command(Some(version))
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" LOGICAL "pgoutput""#
+            );
+
+            assert_eq!(
+                CreateReplicationSlot::logical("slot", "pgoutput")
+                    .temporary(true)
+                    .snapshot(SnapshotAction::Use)
+                    .two_phase(true)
+                    .to_command(Some(version))
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" TEMPORARY LOGICAL "pgoutput" (snapshot 'use', two_phase 'true')"#
+            );
+
+            assert_eq!(
+                CreateReplicationSlot::physical("slot")
+                    .reserve_wal(true)
+                    .to_command(Some(version))
+                    .unwrap(),
+                r#"CREATE_REPLICATION_SLOT "slot" PHYSICAL (reserve_wal 'true')"#
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_slot_options_of_later_versions() {
+        let slot = CreateReplicationSlot::logical("slot", "pgoutput").option("failover", "true");
+        assert!(matches!(
+            slot.to_command(Some(140005)),
+            Err(ReplicationError::InvalidOptions { .. })
+        ));
+
+        let slot = CreateReplicationSlot::logical("slot", "pgoutput").two_phase(true);
+        assert!(matches!(
+            slot.to_command(Some(130010)),
+            Err(ReplicationError::InvalidOptions { .. })
+        ));
+        assert!(slot.to_command(Some(140000)).is_ok());
+    }
+
+    #[test]
+    fn it_passes_slot_options_through() {
+        assert_eq!(
+            CreateReplicationSlot::logical("slot", "my_plugin")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport)
+                .two_phase(true)
+                .option("failover", "true")
+                .option("Odd Name", "it's")
+                .to_command(None)
+                .unwrap(),
+            r#"CREATE_REPLICATION_SLOT "slot" TEMPORARY LOGICAL "my_plugin" (snapshot 'nothing', two_phase 'true', "failover" 'true', "Odd Name" 'it''s')"#
+        );
+
+        assert_eq!(
+            CreateReplicationSlot::physical("slot")
+                .reserve_wal(true)
+                .option("failover", "false")
+                .to_command(Some(170000))
+                .unwrap(),
+            r#"CREATE_REPLICATION_SLOT "slot" PHYSICAL (reserve_wal 'true', "failover" 'false')"#
+        );
+    }
+
+    #[test]
+    fn it_rejects_colliding_slot_options() {
+        for slot in [
+            CreateReplicationSlot::logical("slot", "pgoutput").option("two_phase", "true"),
+            CreateReplicationSlot::logical("slot", "pgoutput").option("SNAPSHOT", "use"),
+            CreateReplicationSlot::physical("slot").option("reserve_wal", "true"),
+            CreateReplicationSlot::physical("slot")
+                .option("failover", "true")
+                .option("failover", "false"),
+            CreateReplicationSlot::physical("slot").option("", "true"),
+        ] {
+            assert!(
+                matches!(
+                    slot.to_command(None),
+                    Err(ReplicationError::InvalidOptions { .. })
+                ),
+                "{slot:?}"
+            );
+        }
+    }
+}
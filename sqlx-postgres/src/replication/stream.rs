@@ -0,0 +1,2269 @@
+use std::cmp;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::io::Write;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_channel::mpsc;
+use futures_core::stream::Stream;
+use futures_util::future::{self, Either, FutureExt};
+use futures_util::stream;
+use sqlx_core::bytes::Bytes;
+use sqlx_core::rt;
+
+use crate::error::Error;
+use crate::io::ProtocolDecode;
+use crate::message::{CopyDone, DataRow};
+use crate::types::{Oid, PgLsn};
+
+use super::clock::SharedClock;
+use super::copy_both::CopyBothReader;
+use super::logical::LogicalDecodeContext;
+use super::message::{
+    system_time_to_timestamp, PrimaryKeepalive, Replication, StandbyStatusUpdate,
+};
+use super::replay;
+use super::{
+    CancelHandle, ChangeSink, Clock, FromReplicationRow, KeepaliveHistory, LogicalMessageStream,
+    LogicalReplication, MessageSizes, OffsetStore, PgReplicationConnection, Relation,
+    ReplicationChannel, ReplicationError, ReplicationNotice, ReplicationObserver, TableStream,
+    TransactionStream, Tuples, Type, XLogData,
+};
+use super::{LIFECYCLE_TARGET, TIMING_TARGET};
+
+/// The minimum time between the lifecycle events reporting that the confirmed position of a
+/// stream advanced, so that they don't flood the log at a high throughput.
+const CONFIRMED_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The default gap tolerated by [`LogicalReplicationStream::is_caught_up()`], which covers a
+/// few records not sent to logical replication.
+const CAUGHT_UP_TOLERANCE: u64 = 1024;
+
+/// The default of [`LogicalReplicationStream::set_max_frame_size()`], the size the server
+/// limits each message to.
+const DEFAULT_MAX_FRAME_SIZE: usize = 1 << 30;
+
+/// When a [`LogicalReplicationStream`] confirms a transaction to the server, which decides
+/// whether its changes can be received again after a restart; set with
+/// [`LogicalReplicationStream::set_delivery_mode()`].
+///
+/// The server continues a slot after the position confirmed last, so whether a consumer can
+/// lose or repeat changes depends on whether it is confirmed before or after the changes were
+/// processed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeliveryMode {
+    /// The slot is only advanced to the positions confirmed by the consumer, with
+    /// [`set_confirmed_lsn()`][LogicalReplicationStream::set_confirmed_lsn] or by calling
+    /// `recv()` again on a [`TransactionStream`] or [`LogicalMessageStream`], so every change
+    /// is received at least once: the changes that were received but not confirmed before a
+    /// restart or crash are streamed again. This is the default.
+    #[default]
+    AtLeastOnce,
+    /// The stream confirms each transaction as soon as it receives its commit, and sends a
+    /// status update before returning the commit, so a transaction is received at most once:
+    /// if the consumer fails while processing it, it is not streamed again. The update is not
+    /// sent with [automatic status updates][LogicalReplicationStream::set_automatic_status]
+    /// disabled.
+    ///
+    /// The changes of a transaction that were returned before its commit are streamed again
+    /// after a restart, as transactions are only skipped once their commit was confirmed.
+    /// Non-transactional [messages][super::Message] are confirmed as they are received.
+    AtMostOnce,
+    /// The stream confirms each transaction once the consumer asks for the message after its
+    /// commit, i.e. once it consumed the whole transaction, so every change is received at least
+    /// once like with [`AtLeastOnce`][Self::AtLeastOnce], without calling
+    /// [`set_confirmed_lsn()`][LogicalReplicationStream::set_confirmed_lsn]. A transaction is
+    /// never confirmed in the middle, and the commit returned last is also confirmed by
+    /// [`finish()`][LogicalReplicationStream::finish].
+    ///
+    /// A [`TransactionStream`] asks for the next message only after the consumer asked for the
+    /// next transaction, so the transactions it returns are confirmed the same way. The
+    /// position is reported with the next status update. Non-transactional
+    /// [messages][super::Message] are confirmed like commits.
+    ConfirmOnCommit,
+}
+
+/// Whether a stream checks that the positions of the messages it receives advance as
+/// expected, which would otherwise indicate a bug, e.g. a position that wasn't reset after a
+/// reconnect; set with [`LogicalReplicationStream::set_contiguity_check()`] or
+/// [`PhysicalReplicationStream::set_contiguity_check()`][super::PhysicalReplicationStream::set_contiguity_check].
+///
+/// The positions are checked differently for the two kinds of streams:
+///
+/// * the WAL of a physical stream is contiguous, so each chunk must start exactly where the
+///   previous one ended, at the start position for the first chunk; a gap would be silent data
+///   loss for an archive, and an overlap a duplicate. Violations are reported as
+///   [`ReplicationError::WalGap`].
+/// * a logical stream sends transactions in the order they committed, but the changes of a
+///   transaction have the positions of the WAL records they were decoded from, which can be
+///   lower than the commit of the transaction before, and large parts of the WAL aren't sent
+///   at all. So only the end positions of commits, prepares and rollbacks of prepared
+///   transactions are checked, which must increase; violations are reported as
+///   [`ReplicationError::CommitOutOfOrder`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContiguityCheck {
+    /// Positions are not checked. This is the default.
+    #[default]
+    Off,
+    /// A violation is logged with a `WARN` event, and the message is returned anyway.
+    Warn,
+    /// A violation is returned as an error, which ends the stream.
+    Error,
+}
+
+/// What a [`LogicalReplicationStream`] does with a message it cannot decode, e.g. because of
+/// a bug of the output plugin or a construct the decoder doesn't support; set with
+/// [`LogicalReplicationStream::set_decode_error_policy()`].
+///
+/// Only the output plugin message in an `XLogData` frame is covered: a frame of the
+/// replication protocol that cannot be decoded still ends the stream, as the connection can't
+/// be trusted after it.
+///
+/// A skipped message is lost to the consumer, and the stream continues after it, so the slot
+/// advances past it once a later position is confirmed. Skipping a `Relation` or `Type`
+/// message leaves the relation or type unknown to the stream, and skipping the start or end of
+/// a transaction breaks the grouping of its changes, e.g. by a [`TransactionStream`]. The
+/// stream counts the skipped messages, see [`LogicalReplicationStream::decode_errors()`], so
+/// they can be alerted on before much is lost.
+#[derive(Default)]
+#[non_exhaustive]
+pub enum DecodeErrorPolicy {
+    /// Return the error from `recv()`; the message is decoded again, and fails again, after a
+    /// restart. This is the default.
+    #[default]
+    Fail,
+    /// Log the error as a `WARN` event, with the position of the message, and continue with
+    /// the next message.
+    Skip,
+    /// Pass the undecodable frame and the error to a callback, e.g. to keep the raw message in
+    /// a dead letter queue, and continue with the next message.
+    DeadLetter(DeadLetterHandler),
+}
+
+/// The callback of [`DecodeErrorPolicy::DeadLetter`].
+pub type DeadLetterHandler = Box<dyn FnMut(&XLogData, &Error) + Send>;
+
+impl DecodeErrorPolicy {
+    /// Hand undecodable messages to `handler`; see [`DecodeErrorPolicy::DeadLetter`].
+    pub fn dead_letter<F>(handler: F) -> Self
+    where
+        F: FnMut(&XLogData, &Error) + Send + 'static,
+    {
+        Self::DeadLetter(Box::new(handler))
+    }
+
+    /// Handle the error of decoding `data`, returning it if the stream should fail.
+    fn handle(&mut self, slot: &str, data: &XLogData, error: Error) -> Result<(), Error> {
+        match self {
+            Self::Fail => return Err(error),
+            Self::Skip => tracing::warn!(
+                slot,
+                wal_start = %data.wal_start,
+                len = data.data.len(),
+                %error,
+                "skipping replication message that cannot be decoded"
+            ),
+            Self::DeadLetter(handler) => handler(data, &error),
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for DecodeErrorPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fail => f.write_str("Fail"),
+            Self::Skip => f.write_str("Skip"),
+            Self::DeadLetter(_) => f.write_str("DeadLetter(..)"),
+        }
+    }
+}
+
+/// A message or a keepalive, as returned by [`LogicalReplicationStream::recv_event()`].
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// A `pgoutput` message, as returned by [`LogicalReplicationStream::recv()`].
+    Data(LogicalReplication),
+    /// A keepalive of the server, which the stream already answered if the server asked for a
+    /// reply.
+    Keepalive(PrimaryKeepalive),
+}
+
+impl StreamEvent {
+    /// Returns the message, or `None` for a keepalive.
+    pub fn into_data(self) -> Option<LogicalReplication> {
+        match self {
+            StreamEvent::Data(message) => Some(message),
+            StreamEvent::Keepalive(_) => None,
+        }
+    }
+}
+
+/// How far a stream is behind the server, as returned by
+/// [`LogicalReplicationStream::lag()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplicationLag {
+    /// The number of bytes between the end of WAL on the server and the confirmed position.
+    pub bytes: u64,
+    /// The time since the server sent its latest keepalive.
+    pub time: Duration,
+}
+
+/// A snapshot of the state of a stream, e.g. for a health check endpoint, as returned by
+/// [`LogicalReplicationStream::health()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReplicationHealth {
+    /// `false` once the server ended the stream or it was
+    /// [cancelled][LogicalReplicationStream::cancel_handle].
+    pub connected: bool,
+    /// Whether the server started decoding changes; see
+    /// [`is_ready()`][LogicalReplicationStream::is_ready].
+    pub ready: bool,
+    /// Whether the stream is [paused][LogicalReplicationStream::pause].
+    pub paused: bool,
+    /// The time since the stream last received a message, including keepalives; see
+    /// [`since_last_message()`][LogicalReplicationStream::since_last_message].
+    pub since_last_message: Duration,
+    /// The time since the stream received the latest keepalive; `None` until the first one.
+    pub since_last_keepalive: Option<Duration>,
+    /// The latest WAL position received from the server.
+    pub received_lsn: PgLsn,
+    /// The position confirmed to the server.
+    pub confirmed_lsn: PgLsn,
+    /// The end of WAL on the server, from the latest keepalive; `None` until the first one.
+    pub server_wal_end: Option<PgLsn>,
+    /// How far the stream is behind the server; see [`lag()`][LogicalReplicationStream::lag].
+    pub lag: Option<ReplicationLag>,
+    /// Whether the stream is [caught up][LogicalReplicationStream::is_caught_up].
+    pub caught_up: bool,
+}
+
+/// A stream of `pgoutput` messages from a logical replication slot, started with
+/// [`PgReplicationConnection::start_logical_replication()`].
+///
+/// The stream answers keepalive messages of the server and periodically reports its
+/// position with a standby status update. The position reported as flushed, which allows
+/// the server to advance the slot and recycle WAL, is only advanced by
+/// [`set_confirmed_lsn()`][Self::set_confirmed_lsn]; call it once the changes up to an LSN
+/// (usually the `end_lsn` of a [`Commit`][super::Commit]) have been durably processed.
+pub struct LogicalReplicationStream {
+    core: StreamCore,
+    publications: Vec<String>,
+    context: LogicalDecodeContext,
+    relations: HashMap<Oid, Relation>,
+    /// The relations whose cached definition was seeded and not sent by the server yet.
+    seeded_relations: HashSet<Oid>,
+    types: HashMap<Oid, Type>,
+    schema_messages: bool,
+    boundaries_only: bool,
+    whole_transaction_batches: bool,
+    /// Set between the start and the end of a transaction, or of a block of a streamed one.
+    in_transaction: bool,
+    tuple_transform: Option<TupleTransform>,
+    delivery_mode: DeliveryMode,
+    /// The position of the commit returned last with [`DeliveryMode::ConfirmOnCommit`], which is
+    /// confirmed once the next message is asked for.
+    consumed_lsn: Option<PgLsn>,
+    decode_error_policy: DecodeErrorPolicy,
+    /// The number of messages that could not be decoded.
+    decode_errors: u64,
+    /// The end position of the transaction received last, for the contiguity check.
+    last_commit_lsn: Option<PgLsn>,
+    stop_lsn: Option<PgLsn>,
+    /// Set once a transaction ended at or past `stop_lsn`, to stop before receiving the next
+    /// message.
+    stopping: bool,
+    /// A message that is returned once the status update confirming it was sent, so that it is
+    /// not lost if `recv()` is cancelled meanwhile.
+    pending: Option<LogicalReplication>,
+}
+
+type TupleTransform = Box<dyn FnMut(&Relation, &mut Tuples) + Send>;
+
+type FrameDecoder = Box<dyn FnMut(Bytes) -> Result<Bytes, Error> + Send>;
+
+/// Returns `true` if the `pgoutput` message `data` starts or ends a transaction, or a block of
+/// a streamed one.
+fn is_boundary(data: &[u8]) -> bool {
+    matches!(
+        data.first(),
+        Some(b'B' | b'C' | b'S' | b'E' | b'c' | b'A' | b'b' | b'P' | b'K' | b'r' | b'p')
+    )
+}
+
+impl LogicalReplicationStream {
+    pub(crate) fn new(
+        conn: PgReplicationConnection,
+        slot: String,
+        start_lsn: PgLsn,
+        publications: Vec<String>,
+        proto_version: u32,
+    ) -> Self {
+        Self {
+            core: StreamCore::new(conn, slot, start_lsn),
+            publications,
+            context: LogicalDecodeContext::new(proto_version),
+            relations: HashMap::new(),
+            seeded_relations: HashSet::new(),
+            types: HashMap::new(),
+            schema_messages: true,
+            boundaries_only: false,
+            whole_transaction_batches: false,
+            in_transaction: false,
+            tuple_transform: None,
+            delivery_mode: DeliveryMode::default(),
+            consumed_lsn: None,
+            decode_error_policy: DecodeErrorPolicy::default(),
+            decode_errors: 0,
+            last_commit_lsn: None,
+            stop_lsn: None,
+            stopping: false,
+            pending: None,
+        }
+    }
+
+    /// The name of the replication slot this stream consumes.
+    pub fn slot(&self) -> &str {
+        &self.core.slot
+    }
+
+    /// The names of the publications whose changes this stream receives.
+    pub fn publications(&self) -> &[String] {
+        &self.publications
+    }
+
+    /// The latest WAL position received from the server.
+    pub fn received_lsn(&self) -> PgLsn {
+        self.core.received_lsn
+    }
+
+    /// Returns `true` once the server started decoding changes for this stream.
+    ///
+    /// Before sending the first change, the server may spend a while reading WAL up to the
+    /// start position or, for a new slot, until it reaches a consistent point, and only sends
+    /// keepalives meanwhile. The stream is ready once the first message was received from the
+    /// slot, or once the server sent its "logical decoding found consistent point" notice to the
+    /// [notice handler][Self::set_notice_handler] (only sent with `client_min_messages` set
+    /// to `debug1` or lower).
+    ///
+    /// This allows a health check to tell a stream that is idle because there are no changes
+    /// from one that is still initializing.
+    pub fn is_ready(&self) -> bool {
+        self.core.ready.load(Ordering::Relaxed)
+    }
+
+    /// The time since the stream last received a message from the server, or since it was
+    /// started if it received none yet.
+    ///
+    /// Keepalives count as messages: on an idle database, or while the server prepares the
+    /// first change, they may be the only messages for a long time. They are answered by
+    /// [`recv()`][Self::recv], which keeps waiting for data instead of returning, and reset the
+    /// [read timeout][Self::set_read_timeout]. A liveness probe can treat the stream as alive
+    /// as long as this stays below the read timeout, whether or not it is
+    /// [ready][Self::is_ready]; keepalives can be observed with
+    /// [`ReplicationObserver::on_keepalive()`].
+    pub fn since_last_message(&self) -> Duration {
+        self.core.last_received.elapsed()
+    }
+
+    /// The position up to which changes have been processed, as reported to the server with
+    /// the next status update.
+    pub fn confirmed_lsn(&self) -> PgLsn {
+        self.core.confirmed_lsn
+    }
+
+    /// Set the position up to which changes have been processed.
+    ///
+    /// The position is reported to the server with the next status update. Positions lower
+    /// than the current one are ignored.
+    pub fn set_confirmed_lsn(&mut self, lsn: PgLsn) {
+        self.core.set_confirmed_lsn(lsn);
+    }
+
+    /// Set when transactions are confirmed to the server; see [`DeliveryMode`].
+    ///
+    /// With the default [`DeliveryMode::AtLeastOnce`], the stream never advances the slot
+    /// beyond the position confirmed by the consumer. Switching away from
+    /// [`DeliveryMode::ConfirmOnCommit`] still confirms the commit returned last once the next
+    /// message is asked for. [`recv_raw()`][Self::recv_raw] doesn't
+    /// decode messages, so it doesn't confirm anything in either mode.
+    pub fn set_delivery_mode(&mut self, mode: DeliveryMode) {
+        self.delivery_mode = mode;
+    }
+
+    /// The [delivery mode][Self::set_delivery_mode] of the stream.
+    pub fn delivery_mode(&self) -> DeliveryMode {
+        self.delivery_mode
+    }
+
+    /// How far the [confirmed position][Self::confirmed_lsn] is behind the end of WAL on the
+    /// server, and how long ago the server reported its position, from the latest keepalive.
+    ///
+    /// Returns `None` until the first keepalive was received. The byte lag shrinks as soon as
+    /// the confirmed position advances, while the end of WAL on the server is only updated
+    /// with each keepalive.
+    pub fn lag(&self) -> Option<ReplicationLag> {
+        self.core.lag()
+    }
+
+    /// Returns `true` if the [confirmed position][Self::confirmed_lsn] reached the end of WAL
+    /// reported by the server with the latest keepalive, i.e. all changes written so far have
+    /// been processed.
+    ///
+    /// This allows switching from a backfill to live processing once the stream caught up.
+    /// Returns `false` until the first keepalive was received; like [`lag()`][Self::lag],
+    /// this only changes with the confirmed position and with each keepalive.
+    ///
+    /// The end of WAL usually stays a little ahead of the `end_lsn` of the last commit, as
+    /// the server writes records that are not sent to logical replication, e.g. of running
+    /// transactions, so a gap of up to [`set_caught_up_tolerance()`][Self::set_caught_up_tolerance]
+    /// bytes is tolerated. The stream is also caught up with any gap if the server sent a
+    /// keepalive after the latest message and the position of that message was confirmed, as
+    /// the server had nothing more to send; the gap then consists of transactions without
+    /// changes for the publications.
+    pub fn is_caught_up(&self) -> bool {
+        self.core.is_caught_up()
+    }
+
+    /// The state of the stream in one snapshot, e.g. for a health check endpoint: whether it
+    /// is connected, the age of the latest message and keepalive, the positions, the
+    /// [lag][Self::lag] and whether it is [caught up][Self::is_caught_up].
+    ///
+    /// Like the getters it combines, this doesn't communicate with the server, so the values
+    /// are only as recent as the latest message; the stream must be polled with
+    /// [`recv()`][Self::recv] meanwhile for them to advance.
+    pub fn health(&self) -> ReplicationHealth {
+        self.core.health()
+    }
+
+    /// Set the gap in bytes between the confirmed position and the end of WAL up to which the
+    /// stream is [caught up][Self::is_caught_up]; 1 KiB by default.
+    pub fn set_caught_up_tolerance(&mut self, bytes: u64) {
+        self.core.caught_up_tolerance = bytes;
+    }
+
+    /// Stop receiving messages, e.g. during an outage of the system the changes are written to,
+    /// without closing the stream.
+    ///
+    /// While paused, [`recv()`][Self::recv] doesn't read from the connection and never
+    /// returns, but keeps sending status updates with the
+    /// [confirmed position][Self::confirmed_lsn] at the
+    /// [status interval][Self::set_status_interval], so the server neither times out the
+    /// connection nor advances the slot; it must thus be polled like when not paused, e.g. in
+    /// a `select!` with the signal to [`resume()`][Self::resume], which is cancel-safe. The
+    /// status interval must be shorter than `wal_sender_timeout`.
+    ///
+    /// The server holds WAL from the confirmed position on, so WAL accumulates on the server
+    /// for as long as the stream is paused. Messages the server sent before are kept in the
+    /// connection buffers and returned after resuming; an error of the server is only noticed
+    /// then as well.
+    pub fn pause(&mut self) {
+        self.core.paused = true;
+    }
+
+    /// Continue receiving messages after [`pause()`][Self::pause], where the stream left off.
+    pub fn resume(&mut self) {
+        self.core.paused = false;
+    }
+
+    /// Returns `true` if the stream was [paused][Self::pause] and not resumed since.
+    pub fn is_paused(&self) -> bool {
+        self.core.paused
+    }
+
+    /// Set the interval between periodic standby status updates.
+    ///
+    /// This should be lower than the server's `wal_sender_timeout` (60 seconds by default).
+    /// Defaults to 10 seconds.
+    pub fn set_status_interval(&mut self, interval: Duration) {
+        self.core.status_interval = interval;
+    }
+
+    /// Sets whether the stream sends standby status updates by itself; `true` by default.
+    ///
+    /// With automatic status updates disabled, the stream sends no status updates at all,
+    /// neither the [initial one][Self::set_initial_status], nor periodic ones, nor the replies
+    /// the server requests with a keepalive, nor the final one of [`finish()`][Self::finish].
+    /// Send them with [`send_standby_status()`][Self::send_standby_status] instead, often
+    /// enough to keep the server from closing the connection after its `wal_sender_timeout`.
+    pub fn set_automatic_status(&mut self, automatic: bool) {
+        self.core.automatic_status = automatic;
+    }
+
+    /// Send a standby status update with the given positions, e.g. to report positions at a
+    /// custom cadence with [automatic status updates][Self::set_automatic_status] disabled.
+    ///
+    /// The positions are sent as given, regardless of [`confirmed_lsn()`][Self::confirmed_lsn]
+    /// and of [read-only mode][Self::set_read_only]: the server advances the slot to `flush`,
+    /// which must only include changes that were durably processed. `reply_requested` asks
+    /// the server to answer with a keepalive right away.
+    ///
+    /// Returns [`ReplicationError::StreamEnded`] if the server already ended the stream.
+    pub async fn send_standby_status(
+        &mut self,
+        write: PgLsn,
+        flush: PgLsn,
+        apply: PgLsn,
+        reply_requested: bool,
+    ) -> Result<(), ReplicationError> {
+        self.core
+            .send_standby_status(write, flush, apply, reply_requested)
+            .await
+    }
+
+    /// Also send a status update once this many bytes of messages were received since the
+    /// last one, or `None` to only send them periodically.
+    ///
+    /// Together with [`set_status_transaction_threshold()`][Self::set_status_transaction_threshold],
+    /// this keeps the slot advancing promptly while a large backlog is drained, instead of
+    /// only every [status interval][Self::set_status_interval]. Whichever trigger fires first
+    /// sends the update. The update is sent the next time the stream is polled, so that it
+    /// includes the position confirmed after processing the latest messages.
+    pub fn set_status_byte_threshold(&mut self, bytes: Option<u64>) {
+        self.core.status_bytes = bytes;
+    }
+
+    /// Also send a status update once this many transactions were received since the last
+    /// one, or `None` to only send them periodically; see
+    /// [`set_status_byte_threshold()`][Self::set_status_byte_threshold].
+    pub fn set_status_transaction_threshold(&mut self, transactions: Option<u64>) {
+        self.core.status_transactions = transactions;
+    }
+
+    /// Sets whether the stream is read-only, i.e. never reports a flushed position to the
+    /// server, so that the slot is not advanced.
+    ///
+    /// This is meant for testing, e.g. to run a new version of a consumer against the
+    /// changes of a production slot: after reconnecting, the same changes are streamed again.
+    /// Status updates are still sent to keep the connection alive.
+    ///
+    /// **Warning:** the server retains all WAL needed by the slot while it doesn't advance,
+    /// which can fill up its disk, so a warning is logged when read-only mode is enabled.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        if read_only {
+            tracing::warn!(
+                slot = self.core.slot,
+                "replication stream is read-only; the slot will not advance and the server \
+                 retains WAL until another consumer confirms it"
+            );
+        }
+
+        self.core.read_only = read_only;
+    }
+
+    /// Keep reporting the current [confirmed position][Self::confirmed_lsn] to the server,
+    /// while messages are still received and confirmed as usual, e.g. to measure how the server
+    /// behaves when a consumer stops confirming, like the WAL it retains for the slot.
+    ///
+    /// Unlike [`pause()`][Self::pause], which stops reading, the stream keeps draining the
+    /// connection, and the reported write position keeps following the received messages. Unlike
+    /// [read-only mode][Self::set_read_only], which reports no flushed position at all and is
+    /// meant to receive the same changes again after a restart, the server sees a valid position
+    /// that doesn't advance, e.g. in the `flush_lsn` of `pg_stat_replication`, and the
+    /// [offset store][Self::set_offset_store] isn't written past it either. The position
+    /// confirmed meanwhile is reported with the first status update after
+    /// [`release_feedback()`][Self::release_feedback], or by [`finish()`][Self::finish].
+    ///
+    /// **Warning:** like in read-only mode, the server retains all WAL the slot needs from the
+    /// held position on.
+    pub fn hold_feedback(&mut self) {
+        self.core.held_lsn.get_or_insert(self.core.confirmed_lsn);
+    }
+
+    /// Report the confirmed position to the server again after
+    /// [`hold_feedback()`][Self::hold_feedback].
+    pub fn release_feedback(&mut self) {
+        self.core.held_lsn = None;
+    }
+
+    /// The position reported to the server while feedback is [held][Self::hold_feedback], or
+    /// `None` if it isn't.
+    pub fn held_feedback_lsn(&self) -> Option<PgLsn> {
+        self.core.held_lsn
+    }
+
+    /// Set the clock for the timestamps of status updates and for [`lag()`][Self::lag];
+    /// defaults to the [system clock][super::SystemClock].
+    pub fn set_clock(&mut self, clock: impl Clock) {
+        self.core.clock = SharedClock::new(clock);
+    }
+
+    /// Set an observer that is notified of the events of this stream, e.g. to record metrics;
+    /// see [`ReplicationObserver`].
+    pub fn set_observer(&mut self, observer: impl ReplicationObserver) {
+        self.core.observer = Some(Arc::new(observer));
+    }
+
+    /// Transform the payload of each `CopyData` frame with `decoder` before it is decoded,
+    /// e.g. to decompress the frames of a proxy that compresses the replication protocol.
+    ///
+    /// Postgres itself doesn't compress replication frames, so this is only needed for a
+    /// transport that wraps them; without a decoder, frames are decoded as they are received.
+    /// The decoder must return a [`Replication`] message, i.e. an `XLogData` or
+    /// `PrimaryKeepalive` frame; an error it returns ends the stream. The
+    /// [sizes][Self::message_sizes] of frames are those received, before decoding them.
+    pub fn set_frame_decoder<F>(&mut self, decoder: F)
+    where
+        F: FnMut(Bytes) -> Result<Bytes, Error> + Send + 'static,
+    {
+        self.core.frame_decoder = Some(Box::new(decoder));
+    }
+
+    /// Write each `CopyData` frame received to `writer`, e.g. a file, to replay the stream
+    /// later without a server with a [`ReplayReader`][super::ReplayReader].
+    ///
+    /// Frames are written after the [frame decoder][Self::set_frame_decoder], if any, so the
+    /// capture holds the frames as Postgres sends them, keepalives included. Writes are
+    /// blocking, so `writer` should be buffered; a failed write ends the stream with
+    /// [`Error::Io`].
+    pub fn set_capture(&mut self, writer: impl Write + Send + 'static) {
+        self.core.capture = Some(Box::new(writer));
+    }
+
+    /// Persist the confirmed position in `store`; see [`OffsetStore`].
+    ///
+    /// The position is stored with each automatic status update that reports a later one, and
+    /// by [`finish()`][Self::finish], always before the server is told, so a failure to store
+    /// it returns [`ReplicationError::OffsetStore`] without advancing the slot. Nothing is
+    /// stored for a [read-only][Self::set_read_only] stream, or without
+    /// [automatic status updates][Self::set_automatic_status].
+    ///
+    /// To also start at the stored position, start the stream with
+    /// [`PgReplicationConnection::start_logical_replication_with_store()`].
+    pub fn set_offset_store(&mut self, store: impl OffsetStore) {
+        self.core.offset_store = Some(Arc::new(store));
+        self.core.stored_lsn = PgLsn::INVALID;
+    }
+
+    /// Set what the stream does with a message it cannot decode; fails by default. See
+    /// [`DecodeErrorPolicy`].
+    pub fn set_decode_error_policy(&mut self, policy: DecodeErrorPolicy) {
+        self.decode_error_policy = policy;
+    }
+
+    /// The number of messages received by this stream that could not be decoded, all of which
+    /// were skipped unless the [decode error policy][Self::set_decode_error_policy] is
+    /// [`DecodeErrorPolicy::Fail`].
+    ///
+    /// Each of them is also reported to the [observer][Self::set_observer] with
+    /// [`ReplicationObserver::on_decode_error()`].
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
+
+    /// Set whether the stream checks that the end positions of transactions increase; off by
+    /// default. See [`ContiguityCheck`].
+    pub fn set_contiguity_check(&mut self, check: ContiguityCheck) {
+        self.core.contiguity_check = check;
+    }
+
+    /// A handle to cancel a pending or later [`recv()`][Self::recv] from another task, e.g. on
+    /// shutdown; see [`CancelHandle`].
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.core.cancel.clone()
+    }
+
+    /// Set whether [`recv()`][Self::recv] returns [`Relation`] and [`Type`] messages, or only
+    /// adds them to the [cache][Self::relation] and skips them, e.g. for consumers that are
+    /// only interested in DML. Defaults to `true`.
+    pub fn set_schema_messages(&mut self, schema_messages: bool) {
+        self.schema_messages = schema_messages;
+    }
+
+    /// The distribution of the sizes of the frames received so far, e.g. to tune the
+    /// [read buffer][Self::set_adaptive_read_buffer] or the
+    /// [copy threshold][Self::set_copy_threshold].
+    pub fn message_sizes(&self) -> &MessageSizes {
+        &self.core.message_sizes
+    }
+
+    /// The most recent keepalives, e.g. to compare the rate at which the server generates WAL
+    /// ([`KeepaliveHistory::wal_generation_rate()`]) with the throughput of the consumer.
+    pub fn keepalive_history(&self) -> &KeepaliveHistory {
+        &self.core.keepalive_history
+    }
+
+    /// Set the number of keepalives kept in the [history][Self::keepalive_history]; 32 by
+    /// default. The server sends a keepalive at least every half `wal_sender_timeout` and with
+    /// each reply requested by a status update, so the history covers a few minutes with the
+    /// default settings; keep more to average the rate over a longer time.
+    pub fn set_keepalive_history_len(&mut self, len: usize) {
+        self.core.keepalive_history.set_len(len);
+    }
+
+    /// Size the read buffer of the connection by the sizes of the recent frames, up to `max`
+    /// bytes, or use the fixed default buffer of the connection with `None` (the default).
+    ///
+    /// The buffer holds a few frames of the [recent mean size][MessageSizes::recent_mean],
+    /// but at least 8 KiB. With many small rows, a larger buffer receives more of them per
+    /// read from the socket. A frame larger than the buffer is still received in full, and when
+    /// the frames get smaller again, e.g. after a few multi-megabyte rows, the buffer is
+    /// shrunk again instead of keeping the memory.
+    pub fn set_adaptive_read_buffer(&mut self, max: Option<usize>) {
+        self.core.set_adaptive_read_buffer(max);
+    }
+
+    /// Reject frames whose payload is larger than `max` bytes with
+    /// [`ReplicationError::FrameTooLarge`], before they are buffered, e.g. to protect a
+    /// consumer of a less trusted server from running out of memory. Defaults to 1 GiB.
+    ///
+    /// A frame carries a single message, so the largest frames are those of the largest rows:
+    /// an `Insert` or `Update` has all values of the row, and an `Update` of a table with
+    /// `REPLICA IDENTITY FULL` the old row as well. The server builds each message in memory,
+    /// which limits it to about 1 GiB; set the maximum well above the largest rows expected,
+    /// as a legitimate frame above it ends the stream, and the next stream fails on the same
+    /// frame again.
+    pub fn set_max_frame_size(&mut self, max: usize) {
+        self.core.max_frame_size = max;
+    }
+
+    /// Set whether [`recv()`][Self::recv] only decodes and returns the messages that start and
+    /// end transactions, like [`Begin`][super::Begin] and [`Commit`][super::Commit], e.g. for
+    /// a monitor that measures throughput and lag from the commit positions and timestamps.
+    /// Defaults to `false`.
+    ///
+    /// All other messages are skipped by their type without being decoded, so the rows of
+    /// changes cost nothing, and the [relation cache][Self::relation] is not filled. The slot
+    /// still only advances to the [confirmed position][Self::set_confirmed_lsn]; with
+    /// [`DeliveryMode::AtMostOnce`], each commit is confirmed as it is received.
+    pub fn set_boundaries_only(&mut self, boundaries_only: bool) {
+        self.boundaries_only = boundaries_only;
+    }
+
+    /// Copy tuple values of at most `copy_threshold` bytes out of the received message.
+    ///
+    /// Decoded values reference the buffer the message was read into, which can be much larger
+    /// than the message itself, e.g. after a large row was received; keeping a few small
+    /// values of a message, e.g. the keys of a large transaction, then keeps the whole buffer
+    /// alive. Copying small values avoids that at the cost of an allocation per value.
+    /// Defaults to `0`, i.e. values are never copied.
+    pub fn set_copy_threshold(&mut self, copy_threshold: usize) {
+        self.context.set_copy_threshold(copy_threshold);
+    }
+
+    /// Call `transform` with each row of an [`Insert`][super::Insert],
+    /// [`Update`][super::Update] or [`Delete`][super::Delete] and the [`Relation`] of its
+    /// table before the change is returned, e.g. to null out or mask columns with personal
+    /// data so that they never reach the consumer.
+    ///
+    /// The rows of a change are the new row, the old row and the key, as far as they were
+    /// sent; the columns of each are in the order of [`Relation::columns`]. Changes of a
+    /// relation whose definition was not received are returned unchanged, which the server
+    /// doesn't do. Rows received with [`recv_raw()`][Self::recv_raw] are not decoded, so they
+    /// are not transformed either.
+    ///
+    /// ```rust,no_run
+    /// # fn example(stream: &mut sqlx::postgres::replication::LogicalReplicationStream) {
+    /// use sqlx::postgres::replication::TupleData;
+    ///
+    /// stream.set_tuple_transform(|relation, tuples| {
+    ///     for (column, value) in relation.columns.iter().zip(tuples.0.iter_mut()) {
+    ///         if column.name == "email" && !value.is_null() {
+    ///             *value = TupleData::Null;
+    ///         }
+    ///     }
+    /// });
+    /// # }
+    /// ```
+    pub fn set_tuple_transform<F>(&mut self, transform: F)
+    where
+        F: FnMut(&Relation, &mut Tuples) + Send + 'static,
+    {
+        self.tuple_transform = Some(Box::new(transform));
+    }
+
+    /// Set whether [`next_batch()`][Self::next_batch] only ends batches at transaction
+    /// boundaries, i.e. never splits a transaction, or a block of a streamed transaction,
+    /// across batches. Defaults to `false`.
+    pub fn set_whole_transaction_batches(&mut self, whole_transactions: bool) {
+        self.whole_transaction_batches = whole_transactions;
+    }
+
+    /// The current time, by the clock of the stream.
+    pub(super) fn now(&self) -> SystemTime {
+        self.core.clock.now()
+    }
+
+    /// The latest definition of the relation `relation_id` sent by the server, for mapping
+    /// the tuples of its changes.
+    ///
+    /// The server sends a [`Relation`] message before the first change of a relation in each
+    /// session, and again after its definition changed. With streaming of in-progress
+    /// transactions, it is also sent again inside the first segment of each streamed
+    /// transaction that changes the relation, i.e. between any two changes of other
+    /// transactions; the cache is updated whenever one arrives.
+    pub fn relation(&self, relation_id: Oid) -> Option<&Relation> {
+        self.relations.get(&relation_id)
+    }
+
+    /// The cached definitions of all relations, e.g. to [seed][Self::seed_relations] the
+    /// cache of the stream that resumes after a reconnect.
+    pub fn relations(&self) -> impl Iterator<Item = &Relation> + '_ {
+        self.relations.values()
+    }
+
+    /// Seed the cache of [`relation()`][Self::relation] with definitions captured from a
+    /// previous stream, e.g. to map the tuples of changes right away after a reconnect.
+    ///
+    /// The seeded definitions are only a cache, not a source of truth: the server still sends
+    /// a [`Relation`] message before the first change of each relation in the session, which
+    /// replaces the seeded definition. Compare it to the seeded one with
+    /// [`Relation::same_definition()`] to detect schema drift while the consumer was
+    /// disconnected. Definitions already sent by the server are not replaced.
+    pub fn seed_relations(&mut self, relations: impl IntoIterator<Item = Relation>) {
+        for relation in relations {
+            if let Entry::Vacant(entry) = self.relations.entry(relation.relation_id) {
+                self.seeded_relations.insert(relation.relation_id);
+                entry.insert(relation);
+            }
+        }
+    }
+
+    /// Returns `true` if the cached definition of the relation `relation_id` was
+    /// [seeded][Self::seed_relations], and not sent by the server (yet).
+    pub fn is_seeded_relation(&self, relation_id: Oid) -> bool {
+        self.seeded_relations.contains(&relation_id)
+    }
+
+    /// The latest definition of the data type `type_id` sent by the server with a [`Type`]
+    /// message; only sent for types that are not built in.
+    pub fn custom_type(&self, type_id: Oid) -> Option<&Type> {
+        self.types.get(&type_id)
+    }
+
+    /// Set how long to wait for any message from the server, including keepalives, before
+    /// [`recv()`][Self::recv] returns [`ReplicationError::Timeout`], or `None` to wait forever.
+    ///
+    /// The server sends a keepalive whenever half of its `wal_sender_timeout` (60 seconds by
+    /// default) passes without a message, and the stream requests one with its status update
+    /// if nothing was received since the previous update, so a longer silence means the
+    /// connection is broken even if it was not closed. Defaults to 60 seconds, twice the
+    /// default keepalive interval.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.core.read_timeout = timeout;
+    }
+
+    /// Call `handler` with the notices the server sends, instead of logging them.
+    ///
+    /// The handler stays installed on the connection returned by [`finish()`][Self::finish].
+    pub fn set_notice_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(ReplicationNotice) + Send + 'static,
+    {
+        self.core.set_notice_handler(handler);
+    }
+
+    /// Set the position reported by the initial standby status update, or `None` to not send one.
+    ///
+    /// The initial status update is sent as soon as the stream is first polled, so that servers
+    /// with an aggressive `wal_sender_timeout` don't disconnect the stream before the first
+    /// periodic update. Defaults to the start position passed to
+    /// [`PgReplicationConnection::start_logical_replication()`].
+    ///
+    /// The position is also reported as processed, like with
+    /// [`set_confirmed_lsn()`][Self::set_confirmed_lsn]. Has no effect once the stream was
+    /// polled.
+    pub fn set_initial_status(&mut self, lsn: Option<PgLsn>) {
+        self.core.initial_status = lsn;
+    }
+
+    /// Stop streaming at the WAL position `lsn`, like `pg_recvlogical --endpos`, e.g. for a job
+    /// that catches up to a known position and then exits instead of streaming indefinitely; or
+    /// `None` to stream until the server ends the stream, which is the default.
+    ///
+    /// The stream stops at a transaction boundary: a transaction is delivered completely if it
+    /// commits (or is prepared) at or before `lsn`, i.e. its [`Begin::final_lsn`] is at or
+    /// before `lsn`, and not at all otherwise. Messages outside of transactions, like
+    /// non-transactional [`Message`]s and [`CommitPrepared`], are delivered if their position
+    /// is at or before `lsn`. Once the last of them was delivered, or a keepalive reported that
+    /// the server sent everything up to `lsn` while no transaction was in progress, a final
+    /// status update is sent and the stream is ended on our side: [`recv()`][Self::recv]
+    /// returns `Ok(None)`, and [`finish()`][Self::finish] returns the connection for other
+    /// commands.
+    ///
+    /// Transactions [streamed while in progress][super::PgOutputOptions::streaming] are sent
+    /// in blocks before their commit position is known, so the blocks of a transaction that
+    /// commits after `lsn` may be delivered before the stream stops; its
+    /// [`StreamCommit`][super::StreamCommit] is not. [`recv_raw()`][Self::recv_raw] doesn't
+    /// decode the messages and ignores the stop position.
+    ///
+    /// [`Begin::final_lsn`]: super::Begin::final_lsn
+    /// [`Message`]: super::Message
+    /// [`CommitPrepared`]: super::CommitPrepared
+    pub fn set_stop_lsn(&mut self, lsn: Option<PgLsn>) {
+        self.stop_lsn = lsn;
+    }
+
+    /// Receive the next message from the slot.
+    ///
+    /// Returns `Ok(None)` if the server ended the stream or the stream was
+    /// [cancelled][Self::cancel_handle], and [`ReplicationError::Timeout`] if no message was
+    /// received within the [read timeout][Self::set_read_timeout]; the connection should then
+    /// be dropped and replication restarted on a new one.
+    ///
+    /// An error sent by the server ends the stream. Common causes, like a conflict with
+    /// recovery or a server shutdown, are returned as typed variants; see
+    /// [`ReplicationError::is_retryable()`].
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe. If it is used as the event in a `select!` and another
+    /// branch completes first, no message is lost.
+    pub async fn recv(&mut self) -> Result<Option<LogicalReplication>, ReplicationError> {
+        // left set if `wait_for_lsn()` was cancelled
+        self.core.wait_lsn = None;
+        self.confirm_consumed();
+
+        // without keepalive events, only messages are received
+        Ok(self
+            .recv_message(false)
+            .await?
+            .and_then(StreamEvent::into_data))
+    }
+
+    /// Receive the next message like [`recv()`][Self::recv], or the next keepalive of the
+    /// server, e.g. to update a lag gauge with its [`wal_end`][PrimaryKeepalive::wal_end] while
+    /// the stream is idle.
+    ///
+    /// Keepalives are still answered by the stream as with `recv()`, so the consumer can
+    /// ignore them.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv_event(&mut self) -> Result<Option<StreamEvent>, ReplicationError> {
+        self.core.wait_lsn = None;
+        self.confirm_consumed();
+
+        self.recv_message(true).await
+    }
+
+    /// Receive messages until the stream reached the WAL position `target`, e.g. the result of
+    /// `pg_current_wal_lsn()` after a write, to wait until the write was replicated.
+    ///
+    /// Returns the messages received meanwhile, which are delivered like by
+    /// [`recv()`][Self::recv] (and confirmed if the [delivery mode][Self::set_delivery_mode]
+    /// is [`AtMostOnce`][DeliveryMode::AtMostOnce], or with the next call for
+    /// [`ConfirmOnCommit`][DeliveryMode::ConfirmOnCommit]), so none of them is lost; they are not
+    /// returned by `recv()` again. The wait ends after a message at or past `target`, like the
+    /// [`Commit`][super::Commit] of the write, or once a keepalive reports that the server sent
+    /// everything up to `target`, which is asked for right away unless [automatic status
+    /// updates][Self::set_automatic_status] are disabled. Returns an empty batch if the stream
+    /// already [received][Self::received_lsn] `target`.
+    ///
+    /// Returns [`ReplicationError::StreamEnded`] if the server ended the stream or the stream
+    /// was [cancelled][Self::cancel_handle] before reaching `target`. The [read
+    /// timeout][Self::set_read_timeout] applies while waiting; wrap the call in a timeout to
+    /// limit the wait itself.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(
+    /// #     stream: &mut sqlx::postgres::replication::LogicalReplicationStream,
+    /// #     conn: &mut sqlx::PgConnection,
+    /// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+    /// use sqlx::postgres::types::PgLsn;
+    ///
+    /// sqlx::query("INSERT INTO users (name) VALUES ('foo')")
+    ///     .execute(&mut *conn)
+    ///     .await?;
+    ///
+    /// let lsn: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+    ///     .fetch_one(&mut *conn)
+    ///     .await?;
+    ///
+    /// // contains the `Insert` of the new row
+    /// let messages = stream.wait_for_lsn(lsn).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancel-safe: the messages received before it is cancelled are lost.
+    pub async fn wait_for_lsn(
+        &mut self,
+        target: PgLsn,
+    ) -> Result<Vec<LogicalReplication>, ReplicationError> {
+        let mut messages = Vec::new();
+        self.confirm_consumed();
+
+        if self.received_lsn() >= target && self.pending.is_none() {
+            return Ok(messages);
+        }
+
+        // ask for a keepalive with the position the server sent up to
+        if self.core.automatic_status && !self.core.finished {
+            self.core.send_status_update(true).await?;
+        }
+
+        self.core.wait_lsn = Some(target);
+
+        loop {
+            let Some(message) = self
+                .recv_message(false)
+                .await?
+                .and_then(StreamEvent::into_data)
+            else {
+                self.core.wait_lsn = None;
+
+                // the core stops receiving once a keepalive reached the target
+                if self.received_lsn() >= target {
+                    return Ok(messages);
+                }
+
+                return Err(ReplicationError::StreamEnded);
+            };
+
+            let reached = delivered_lsn(&message).is_some_and(|lsn| lsn >= target)
+                || self.received_lsn() >= target;
+            messages.push(message);
+
+            if reached && self.pending.is_none() {
+                self.core.wait_lsn = None;
+
+                return Ok(messages);
+            }
+        }
+    }
+
+    /// Receive the next message, or the next keepalive if `keepalives` is set.
+    async fn recv_message(
+        &mut self,
+        keepalives: bool,
+    ) -> Result<Option<StreamEvent>, ReplicationError> {
+        // keepalives also tell when the stop position was reached while idle
+        self.core.keepalive_events = keepalives || self.stop_lsn.is_some();
+
+        loop {
+            if self.pending.is_some() {
+                if self.core.automatic_status {
+                    self.core.send_status_update(false).await?;
+                }
+
+                return Ok(self.pending.take().map(StreamEvent::Data));
+            }
+
+            if self.stopping {
+                self.stop().await?;
+
+                return Ok(None);
+            }
+
+            let data = match self.core.recv().await? {
+                Received::XLogData(data) => self.received(data),
+                Received::Keepalive(keepalive) => {
+                    let reached = self.stop_lsn.is_some_and(|lsn| self.received_lsn() >= lsn);
+
+                    if reached && !self.in_transaction {
+                        self.stop().await?;
+
+                        return Ok(None);
+                    }
+
+                    if keepalives {
+                        return Ok(Some(StreamEvent::Keepalive(keepalive)));
+                    }
+
+                    continue;
+                }
+                Received::End(_) | Received::Cancelled | Received::Reached => return Ok(None),
+            };
+
+            if self.boundaries_only && !is_boundary(&data.data) {
+                continue;
+            }
+
+            // only measure the decoding time if it is going to be logged
+            let started =
+                tracing::enabled!(target: TIMING_TARGET, tracing::Level::TRACE).then(Instant::now);
+            let len = data.data.len();
+
+            let message = match LogicalReplication::decode_with(data.data.clone(), self.context) {
+                Ok(message) => message,
+                Err(error) => {
+                    self.decode_errors += 1;
+
+                    if let Some(observer) = &self.core.observer {
+                        let skipped = !matches!(self.decode_error_policy, DecodeErrorPolicy::Fail);
+                        observer.on_decode_error(&data, &error, skipped);
+                    }
+
+                    self.decode_error_policy
+                        .handle(&self.core.slot, &data, error)?;
+
+                    continue;
+                }
+            };
+
+            if let Some(stop_lsn) = self.stop_lsn {
+                let (starts, ends) = stop_positions(&message, data.wal_start);
+
+                if starts.is_some_and(|lsn| lsn > stop_lsn) {
+                    self.stop().await?;
+
+                    return Ok(None);
+                }
+
+                self.stopping = ends.is_some_and(|lsn| lsn >= stop_lsn);
+            }
+
+            self.context.observe(&message);
+
+            if let LogicalReplication::Commit(_)
+            | LogicalReplication::StreamCommit(_)
+            | LogicalReplication::CommitPrepared(_) = message
+            {
+                self.core.transactions_since_status += 1;
+            }
+
+            match message {
+                LogicalReplication::Begin(_)
+                | LogicalReplication::BeginPrepare(_)
+                | LogicalReplication::StreamStart(_) => self.in_transaction = true,
+                LogicalReplication::Commit(_)
+                | LogicalReplication::Prepare(_)
+                | LogicalReplication::StreamStop => self.in_transaction = false,
+                _ => {}
+            }
+
+            if let Some(started) = started {
+                tracing::trace!(
+                    target: TIMING_TARGET,
+                    message_type = message.kind(),
+                    len,
+                    elapsed = ?started.elapsed(),
+                    "decoded replication message"
+                );
+            }
+
+            if let LogicalReplication::Relation(relation) = &message {
+                self.replace_seeded_relation(relation);
+            }
+
+            match message {
+                LogicalReplication::Relation(relation) if !self.schema_messages => {
+                    self.relations.insert(relation.relation_id, relation);
+                }
+                LogicalReplication::Relation(ref relation) => {
+                    self.relations
+                        .insert(relation.relation_id, relation.clone());
+
+                    return Ok(Some(StreamEvent::Data(message)));
+                }
+                LogicalReplication::Type(ty) if !self.schema_messages => {
+                    self.types.insert(ty.type_id, ty);
+                }
+                LogicalReplication::Type(ref ty) => {
+                    self.types.insert(ty.type_id, ty.clone());
+
+                    return Ok(Some(StreamEvent::Data(message)));
+                }
+                mut message => {
+                    self.check_commit_order(&message)?;
+
+                    if let Some(transform) = &mut self.tuple_transform {
+                        transform_tuples(&mut message, &self.relations, transform);
+                    }
+
+                    match (self.delivery_mode, delivered_lsn(&message)) {
+                        (DeliveryMode::AtMostOnce, Some(lsn)) => {
+                            self.core.set_confirmed_lsn(lsn);
+                            self.pending = Some(message);
+
+                            // sent at the start of the loop
+                            continue;
+                        }
+                        (DeliveryMode::ConfirmOnCommit, Some(lsn)) => {
+                            self.consumed_lsn = Some(lsn);
+                        }
+                        _ => {}
+                    }
+
+                    return Ok(Some(StreamEvent::Data(message)));
+                }
+            }
+        }
+    }
+
+    /// Confirm the commit returned last with [`DeliveryMode::ConfirmOnCommit`], as the consumer
+    /// asked for what comes after it.
+    fn confirm_consumed(&mut self) {
+        if let Some(lsn) = self.consumed_lsn.take() {
+            self.core.set_confirmed_lsn(lsn);
+        }
+    }
+
+    /// End the stream on our side once the stop position was reached.
+    async fn stop(&mut self) -> Result<(), ReplicationError> {
+        self.stopping = false;
+        self.core.stop().await?;
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = self.core.slot,
+            lsn = %self.core.confirmed_lsn,
+            "replication stopped at the stop position"
+        );
+
+        Ok(())
+    }
+
+    /// Check that the end position of a transaction is after the one received before.
+    fn check_commit_order(&mut self, message: &LogicalReplication) -> Result<(), ReplicationError> {
+        let end_lsn = match message {
+            LogicalReplication::Commit(commit) => commit.end_lsn,
+            LogicalReplication::StreamCommit(commit) => commit.end_lsn,
+            LogicalReplication::Prepare(prepare) => prepare.end_lsn,
+            LogicalReplication::CommitPrepared(commit) => commit.end_lsn,
+            LogicalReplication::RollbackPrepared(rollback) => rollback.rollback_end_lsn,
+            _ => return Ok(()),
+        };
+
+        let previous = self.last_commit_lsn.replace(end_lsn);
+
+        match previous {
+            Some(previous) if end_lsn <= previous => {
+                self.core
+                    .contiguity_violation(ReplicationError::CommitOutOfOrder {
+                        previous,
+                        received: end_lsn,
+                    })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Forget that the cached definition of a relation was seeded, once the server sent it.
+    fn replace_seeded_relation(&mut self, relation: &Relation) {
+        if !self.seeded_relations.remove(&relation.relation_id) {
+            return;
+        }
+
+        let seeded = &self.relations[&relation.relation_id];
+
+        if !seeded.same_definition(relation) {
+            tracing::debug!(
+                slot = self.core.slot,
+                relation = relation.qualified_name(),
+                "definition of relation differs from the seeded one"
+            );
+        }
+    }
+
+    /// Receive up to `max` messages at once, e.g. for a sink that writes in batches.
+    ///
+    /// Waits for the first message like [`recv()`][Self::recv], then adds the messages that
+    /// were already received from the server without waiting for more. Returns an empty batch
+    /// if the server ended the stream or the stream was [cancelled][Self::cancel_handle].
+    ///
+    /// With [`set_whole_transaction_batches(true)`][Self::set_whole_transaction_batches], a
+    /// batch that ends within a transaction is continued, waiting if necessary, until the
+    /// transaction commits, so it can hold more than `max` messages.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancel-safe: the messages received before it is cancelled are lost.
+    pub async fn next_batch(
+        &mut self,
+        max: usize,
+    ) -> Result<Vec<LogicalReplication>, ReplicationError> {
+        let mut batch = Vec::new();
+
+        loop {
+            let complete = !(self.whole_transaction_batches && self.in_transaction);
+
+            if batch.len() >= max && complete {
+                break;
+            }
+
+            let message = if batch.is_empty() || !complete {
+                self.recv().await?
+            } else {
+                // `recv()` is cancel-safe, so no message is lost if it is not ready
+                match self.recv().now_or_never() {
+                    Some(message) => message?,
+                    None => break,
+                }
+            };
+
+            let Some(message) = message else {
+                break;
+            };
+
+            batch.push(message);
+        }
+
+        Ok(batch)
+    }
+
+    /// Apply the messages of the stream to `sink` until the stream ends, confirming the
+    /// transactions the sink flushed, e.g. to write the changes to a message broker with
+    /// at-least-once delivery; returns the position confirmed last.
+    ///
+    /// Each message is [applied][ChangeSink::apply] in order. After the commit of a
+    /// transaction, the sink is [flushed][ChangeSink::flush_checkpoint] with its end position
+    /// whenever no more messages were received already, or once the
+    /// [status interval][Self::set_status_interval] passed since the last checkpoint, and the
+    /// position is then [confirmed][Self::set_confirmed_lsn], so the slot only advances past
+    /// transactions the sink made durable. The stream is switched to
+    /// [`DeliveryMode::AtLeastOnce`] for this, as the other modes confirm transactions before
+    /// they are flushed.
+    ///
+    /// With a [stop position][Self::set_stop_lsn], the sink is flushed after every transaction,
+    /// as the stream may stop with the next message, so that its final status update confirms
+    /// the last transaction. Otherwise, the method returns once the server ended the stream or
+    /// the stream was [cancelled][Self::cancel_handle], after a final checkpoint; after a
+    /// cancel, it is sent to the server by [`finish()`][Self::finish], while the transactions
+    /// after the last status update are received again if the server ended the stream.
+    ///
+    /// An error of the sink is returned as [`ReplicationError::Sink`]; the transactions after
+    /// the position confirmed last are then received again on the next start.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(
+    /// #     stream: &mut sqlx::postgres::replication::LogicalReplicationStream,
+    /// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+    /// use sqlx::postgres::replication::JsonLinesSink;
+    ///
+    /// let mut sink = JsonLinesSink::new(Vec::new());
+    /// let confirmed_lsn = stream.pump_into(&mut sink).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is not cancel-safe: the sink may have been given messages of a transaction
+    /// that was not flushed. Stop it with the cancel handle instead.
+    pub async fn pump_into<S>(&mut self, sink: &mut S) -> Result<PgLsn, ReplicationError>
+    where
+        S: ChangeSink + ?Sized,
+    {
+        self.delivery_mode = DeliveryMode::AtLeastOnce;
+
+        let mut checkpoint = None;
+        let mut checkpointed_at = self.now();
+
+        loop {
+            let message = match checkpoint {
+                Some(lsn) => {
+                    // checked before polling, as a message received by `recv()` can't be put back
+                    let due = self.stop_lsn.is_some()
+                        || (self.now().duration_since(checkpointed_at))
+                            .is_ok_and(|elapsed| elapsed >= self.core.status_interval);
+
+                    // `recv()` is cancel-safe, so no message is lost if it is not ready
+                    match (!due).then(|| self.recv().now_or_never()).flatten() {
+                        Some(message) => message?,
+                        None => {
+                            self.checkpoint(sink, lsn).await?;
+
+                            checkpoint = None;
+                            checkpointed_at = self.now();
+
+                            continue;
+                        }
+                    }
+                }
+                None => self.recv().await?,
+            };
+
+            let Some(message) = message else {
+                break;
+            };
+
+            let relation = match &message {
+                LogicalReplication::Insert(insert) => self.relation(insert.relation_id),
+                LogicalReplication::Update(update) => self.relation(update.relation_id),
+                LogicalReplication::Delete(delete) => self.relation(delete.relation_id),
+                LogicalReplication::Relation(relation) => Some(relation),
+                _ => None,
+            };
+
+            sink.apply(&message, relation)
+                .await
+                .map_err(|source| ReplicationError::Sink { source })?;
+
+            if let Some(lsn) = delivered_lsn(&message) {
+                checkpoint = Some(lsn);
+            }
+        }
+
+        if let Some(lsn) = checkpoint {
+            self.checkpoint(sink, lsn).await?;
+        }
+
+        Ok(self.confirmed_lsn())
+    }
+
+    /// Flush `sink` and confirm `lsn` once it was flushed.
+    async fn checkpoint<S>(&mut self, sink: &mut S, lsn: PgLsn) -> Result<(), ReplicationError>
+    where
+        S: ChangeSink + ?Sized,
+    {
+        sink.flush_checkpoint(lsn)
+            .await
+            .map_err(|source| ReplicationError::Sink { source })?;
+        self.set_confirmed_lsn(lsn);
+
+        Ok(())
+    }
+
+    /// Receive the next message from the slot without decoding it, e.g. to forward the
+    /// `pgoutput` messages elsewhere.
+    ///
+    /// Keepalives and status updates are handled like with [`recv()`][Self::recv], which this
+    /// method otherwise behaves like. As the messages are not decoded, the stream cannot keep
+    /// track of streamed transactions; don't mix this method with `recv()` if streaming of
+    /// in-progress transactions is enabled.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv_raw(&mut self) -> Result<Option<XLogData>, ReplicationError> {
+        self.core.wait_lsn = None;
+        self.core.keepalive_events = false;
+
+        match self.core.recv().await? {
+            Received::XLogData(data) => Ok(Some(self.received(data))),
+            // `Keepalive` is only returned for `recv_event()`
+            Received::End(_) | Received::Cancelled | Received::Reached | Received::Keepalive(_) => {
+                Ok(None)
+            }
+        }
+    }
+
+    /// Advance the received position to the start of `data`.
+    fn received(&mut self, data: XLogData) -> XLogData {
+        self.core.received_lsn = cmp::max(self.core.received_lsn, data.wal_start);
+        self.core.data_lsn = cmp::max(self.core.data_lsn, data.wal_start);
+
+        data
+    }
+
+    /// Stop streaming and return the replication connection.
+    ///
+    /// A final status update with the confirmed position is sent before the stream is ended,
+    /// which includes the commit returned last with [`DeliveryMode::ConfirmOnCommit`].
+    pub async fn finish(mut self) -> Result<PgReplicationConnection, ReplicationError> {
+        self.confirm_consumed();
+        self.core.finish().await
+    }
+
+    /// Only receive the messages emitted with `pg_logical_emit_message()`, skipping the changes
+    /// of the published tables; see [`LogicalMessageStream`].
+    pub fn only_messages(self) -> LogicalMessageStream {
+        LogicalMessageStream::new(self)
+    }
+
+    /// Receive whole committed transactions instead of single messages; see
+    /// [`TransactionStream`].
+    pub fn transactions(self) -> TransactionStream {
+        TransactionStream::new(self)
+    }
+
+    /// Receive the changes of the table `namespace.name` as rows decoded into `T`; see
+    /// [`TableStream`].
+    pub fn table<T: FromReplicationRow>(
+        self,
+        namespace: impl Into<String>,
+        name: impl Into<String>,
+    ) -> TableStream<T> {
+        TableStream::new(self, namespace, name)
+    }
+
+    /// Move this stream into a background task that sends the messages it receives to a
+    /// channel with room for `capacity` messages, returning the receiver of the channel and a
+    /// handle to confirm the messages and stop the task; see [`ReplicationChannel`].
+    ///
+    /// The task keeps the connection alive, so the consumer only needs to read the channel and
+    /// [confirm][ReplicationChannel::confirm] what it processed.
+    ///
+    /// ```rust,no_run
+    /// # async fn example(
+    /// #     stream: sqlx::postgres::replication::LogicalReplicationStream,
+    /// # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+    /// use futures_util::StreamExt;
+    /// use sqlx::postgres::replication::LogicalReplication;
+    ///
+    /// let (mut messages, channel) = stream.into_channel(1024);
+    ///
+    /// while let Some(message) = messages.next().await {
+    ///     if let LogicalReplication::Commit(commit) = message {
+    ///         channel.confirm(commit.end_lsn);
+    ///     }
+    /// }
+    ///
+    /// let conn = channel.join().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a Tokio or async-std runtime.
+    pub fn into_channel(
+        self,
+        capacity: usize,
+    ) -> (mpsc::Receiver<LogicalReplication>, ReplicationChannel) {
+        ReplicationChannel::spawn(self, capacity)
+    }
+
+    /// Consume this stream, returning a `Stream` of messages and keepalives, as received with
+    /// [`recv_event()`][Self::recv_event].
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_event_stream(
+        self,
+    ) -> impl Stream<Item = Result<StreamEvent, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv_event().await {
+                Ok(Some(event)) => Some((Ok(event), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+
+    /// Consume this stream, returning a `Stream` of messages.
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<LogicalReplication, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv().await {
+                Ok(Some(message)) => Some((Ok(message), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+}
+
+/// The position to confirm once `message` was received with [`DeliveryMode::AtMostOnce`], or
+/// consumed with [`DeliveryMode::ConfirmOnCommit`].
+fn delivered_lsn(message: &LogicalReplication) -> Option<PgLsn> {
+    match message {
+        LogicalReplication::Commit(commit) => Some(commit.end_lsn),
+        LogicalReplication::StreamCommit(commit) => Some(commit.end_lsn),
+        LogicalReplication::Prepare(prepare) => Some(prepare.end_lsn),
+        LogicalReplication::CommitPrepared(commit) => Some(commit.end_lsn),
+        LogicalReplication::RollbackPrepared(rollback) => Some(rollback.rollback_end_lsn),
+        LogicalReplication::Message(message) if !message.transactional => Some(message.lsn),
+        _ => None,
+    }
+}
+
+/// Returns the positions a message is checked against the stop position with: the commit
+/// position of the transaction it starts, which must not be past the stop position for the
+/// transaction to be delivered, and the position of the transaction it ends, at or past which
+/// the stream stops after the message. Both are set for messages outside of transactions.
+///
+/// The commit position of a transaction streamed while in progress is not known at its start,
+/// so the position of the `XLogData` of its first block, `wal_start`, is used instead, and its
+/// `StreamCommit` is checked against both, as its blocks may have been delivered although it
+/// commits past the stop position.
+fn stop_positions(
+    message: &LogicalReplication,
+    wal_start: PgLsn,
+) -> (Option<PgLsn>, Option<PgLsn>) {
+    match message {
+        LogicalReplication::Begin(begin) => (Some(begin.final_lsn), None),
+        LogicalReplication::BeginPrepare(begin) => (Some(begin.prepare_lsn), None),
+        LogicalReplication::StreamStart(start) if start.first_segment => (Some(wal_start), None),
+        LogicalReplication::Commit(commit) => (None, Some(commit.commit_lsn)),
+        LogicalReplication::StreamCommit(commit) => {
+            (Some(commit.commit_lsn), Some(commit.commit_lsn))
+        }
+        LogicalReplication::Prepare(prepare) => (None, Some(prepare.prepare_lsn)),
+        LogicalReplication::CommitPrepared(commit) => {
+            (Some(commit.commit_lsn), Some(commit.commit_lsn))
+        }
+        LogicalReplication::RollbackPrepared(rollback) => (
+            Some(rollback.rollback_end_lsn),
+            Some(rollback.rollback_end_lsn),
+        ),
+        LogicalReplication::Message(message) if !message.transactional => {
+            (Some(message.lsn), Some(message.lsn))
+        }
+        _ => (None, None),
+    }
+}
+
+/// Apply `transform` to the rows of a data message.
+fn transform_tuples(
+    message: &mut LogicalReplication,
+    relations: &HashMap<Oid, Relation>,
+    transform: &mut TupleTransform,
+) {
+    let (relation_id, rows) = match message {
+        LogicalReplication::Insert(insert) => {
+            (insert.relation_id, [Some(&mut insert.new_data), None, None])
+        }
+        LogicalReplication::Update(update) => (
+            update.relation_id,
+            [
+                update.key_data.as_mut(),
+                update.old_data.as_mut(),
+                Some(&mut update.new_data),
+            ],
+        ),
+        LogicalReplication::Delete(delete) => (
+            delete.relation_id,
+            [delete.key_data.as_mut(), delete.old_data.as_mut(), None],
+        ),
+        _ => return,
+    };
+
+    let Some(relation) = relations.get(&relation_id) else {
+        return;
+    };
+
+    for tuples in rows.into_iter().flatten() {
+        transform(relation, tuples);
+    }
+}
+
+/// What [`StreamCore::recv()`] received.
+pub(super) enum Received {
+    XLogData(XLogData),
+    /// The server ended the stream, with the row of the result set that followed it, if any.
+    End(Option<DataRow>),
+    /// The stream was cancelled with its [`CancelHandle`]; it can still be finished.
+    Cancelled,
+    /// A keepalive reported a position at or past [`StreamCore::wait_lsn`].
+    Reached,
+    /// A keepalive, if [`StreamCore::keepalive_events`] is set.
+    Keepalive(PrimaryKeepalive),
+}
+
+/// The state shared by the logical and physical replication streams: the positions reported
+/// with standby status updates, keepalives, and the read timeout.
+pub(super) struct StreamCore {
+    pub(super) conn: PgReplicationConnection,
+    pub(super) slot: String,
+    pub(super) received_lsn: PgLsn,
+    /// The position of the latest data received, as opposed to the end of WAL in keepalives.
+    pub(super) data_lsn: PgLsn,
+    pub(super) confirmed_lsn: PgLsn,
+    pub(super) status_interval: Duration,
+    pub(super) read_timeout: Option<Duration>,
+    pub(super) initial_status: Option<PgLsn>,
+    /// Set once the first `XLogData` or the consistent point notice was received; shared with
+    /// the notice handler.
+    pub(super) ready: Arc<AtomicBool>,
+    pub(super) status_bytes: Option<u64>,
+    pub(super) status_transactions: Option<u64>,
+    pub(super) transactions_since_status: u64,
+    pub(super) read_only: bool,
+    /// The position reported instead of the confirmed one while feedback is held.
+    pub(super) held_lsn: Option<PgLsn>,
+    pub(super) clock: SharedClock,
+    pub(super) cancel: CancelHandle,
+    pub(super) observer: Option<Arc<dyn ReplicationObserver>>,
+    pub(super) offset_store: Option<Arc<dyn OffsetStore>>,
+    /// The latest position stored in the offset store.
+    pub(super) stored_lsn: PgLsn,
+    pub(super) caught_up_tolerance: u64,
+    pub(super) paused: bool,
+    pub(super) automatic_status: bool,
+    pub(super) message_sizes: MessageSizes,
+    pub(super) keepalive_history: KeepaliveHistory,
+    pub(super) contiguity_check: ContiguityCheck,
+    pub(super) frame_decoder: Option<FrameDecoder>,
+    pub(super) capture: Option<Box<dyn Write + Send>>,
+    /// Set while waiting for the received position to reach it, to return
+    /// [`Received::Reached`] from the keepalive that reports it.
+    pub(super) wait_lsn: Option<PgLsn>,
+    /// Set while keepalives are returned as [`Received::Keepalive`] after they were handled.
+    pub(super) keepalive_events: bool,
+    /// The maximum capacity of the read buffer if it is sized adaptively.
+    max_read_buffer: Option<usize>,
+    /// The maximum size of the payload of a frame.
+    pub(super) max_frame_size: usize,
+    /// The capacity the read buffer was last sized to.
+    read_buffer: usize,
+    last_keepalive: Option<PrimaryKeepalive>,
+    /// When the latest keepalive was received.
+    last_keepalive_received: Option<Instant>,
+    /// Set if a keepalive was received after the latest data.
+    idle: bool,
+    bytes_since_status: u64,
+    last_status: Instant,
+    pub(super) last_received: Instant,
+    /// The confirmed position last reported with a lifecycle event, and when.
+    logged_lsn: PgLsn,
+    last_logged: Option<Instant>,
+    started: bool,
+    /// Set once the server ended the stream, until it was ended on our side as well.
+    ending: Option<Ending>,
+    finished: bool,
+}
+
+/// The progress of ending the stream after the server sent `CopyDone`.
+#[derive(Default)]
+struct Ending {
+    copy_done_sent: bool,
+    row: Option<DataRow>,
+}
+
+impl StreamCore {
+    pub(super) fn new(conn: PgReplicationConnection, slot: String, start_lsn: PgLsn) -> Self {
+        Self {
+            conn,
+            slot,
+            received_lsn: start_lsn,
+            data_lsn: start_lsn,
+            confirmed_lsn: start_lsn,
+            status_interval: Duration::from_secs(10),
+            read_timeout: Some(Duration::from_secs(60)),
+            initial_status: Some(start_lsn),
+            ready: Arc::new(AtomicBool::new(false)),
+            status_bytes: None,
+            status_transactions: None,
+            transactions_since_status: 0,
+            read_only: false,
+            held_lsn: None,
+            clock: SharedClock::default(),
+            cancel: CancelHandle::default(),
+            observer: None,
+            offset_store: None,
+            stored_lsn: PgLsn::INVALID,
+            caught_up_tolerance: CAUGHT_UP_TOLERANCE,
+            paused: false,
+            automatic_status: true,
+            message_sizes: MessageSizes::default(),
+            keepalive_history: KeepaliveHistory::default(),
+            contiguity_check: ContiguityCheck::default(),
+            frame_decoder: None,
+            capture: None,
+            wait_lsn: None,
+            keepalive_events: false,
+            max_read_buffer: None,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            read_buffer: 0,
+            last_keepalive: None,
+            last_keepalive_received: None,
+            idle: false,
+            bytes_since_status: 0,
+            last_status: Instant::now(),
+            last_received: Instant::now(),
+            logged_lsn: start_lsn,
+            last_logged: None,
+            started: false,
+            ending: None,
+            finished: false,
+        }
+    }
+
+    /// Handle a violation of the [`ContiguityCheck`] of the stream, which is enabled.
+    pub(super) fn contiguity_violation(
+        &self,
+        error: ReplicationError,
+    ) -> Result<(), ReplicationError> {
+        match self.contiguity_check {
+            ContiguityCheck::Off => Ok(()),
+            ContiguityCheck::Warn => {
+                tracing::warn!(slot = self.slot, %error, "replication positions are not contiguous");
+                Ok(())
+            }
+            ContiguityCheck::Error => Err(error),
+        }
+    }
+
+    pub(super) fn set_confirmed_lsn(&mut self, lsn: PgLsn) {
+        self.confirmed_lsn = cmp::max(self.confirmed_lsn, lsn);
+    }
+
+    pub(super) fn lag(&self) -> Option<ReplicationLag> {
+        let keepalive = self.last_keepalive?;
+
+        Some(ReplicationLag {
+            bytes: u64::from(keepalive.wal_end).saturating_sub(self.confirmed_lsn.into()),
+            time: self
+                .clock
+                .now()
+                .duration_since(keepalive.system_time())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub(super) fn is_caught_up(&self) -> bool {
+        // the end of WAL may include transactions without changes for this stream, which are
+        // not sent at all; a keepalive after all data was processed means there is nothing left
+        self.lag().is_some_and(|lag| {
+            lag.bytes <= self.caught_up_tolerance
+                || (self.idle && self.confirmed_lsn >= self.data_lsn)
+        })
+    }
+
+    pub(super) fn health(&self) -> ReplicationHealth {
+        ReplicationHealth {
+            connected: !self.finished && !self.cancel.is_cancelled(),
+            ready: self.ready.load(Ordering::Relaxed),
+            paused: self.paused,
+            since_last_message: self.last_received.elapsed(),
+            since_last_keepalive: self.last_keepalive_received.map(|at| at.elapsed()),
+            received_lsn: self.received_lsn,
+            confirmed_lsn: self.confirmed_lsn,
+            server_wal_end: self.last_keepalive.map(|keepalive| keepalive.wal_end),
+            lag: self.lag(),
+            caught_up: self.is_caught_up(),
+        }
+    }
+
+    pub(super) fn set_notice_handler<F>(&mut self, mut handler: F)
+    where
+        F: FnMut(ReplicationNotice) + Send + 'static,
+    {
+        let ready = self.ready.clone();
+
+        self.conn.conn.inner.stream.notice_handler = Some(Box::new(move |notice| {
+            if notice
+                .message()
+                .starts_with("logical decoding found consistent point")
+            {
+                ready.store(true, Ordering::Relaxed);
+            }
+
+            handler(ReplicationNotice::from(notice))
+        }));
+    }
+
+    /// Receive the next `XLogData` message, answering keepalives and sending status updates
+    /// while waiting.
+    ///
+    /// Returns [`Received::End`] once the server ended the stream, and on every call after, and
+    /// [`Received::Cancelled`] once the stream was cancelled. This method is cancel-safe.
+    pub(super) async fn recv(&mut self) -> Result<Received, ReplicationError> {
+        // time spent outside of this method, e.g. processing a message, is not silence
+        let recv_started = Instant::now();
+
+        loop {
+            if self.finished {
+                return Ok(Received::End(None));
+            }
+
+            if self.ending.is_some() {
+                return self.end().await;
+            }
+
+            if self.cancel.is_cancelled() {
+                return Ok(Received::Cancelled);
+            }
+
+            if !self.started {
+                if let Some(lsn) = self.initial_status {
+                    self.set_confirmed_lsn(lsn);
+
+                    if self.automatic_status {
+                        self.send_status_update(false).await?;
+                    }
+                }
+
+                self.started = true;
+            }
+
+            if self.paused {
+                // the server holds back further messages while they are not read, but keeps
+                // the connection open as long as status updates arrive
+                if self.automatic_status && self.last_status.elapsed() >= self.status_interval {
+                    self.send_status_update(false).await?;
+                }
+
+                let wait = self.status_wait();
+                let _ = rt::timeout(wait, self.cancel.cancelled()).await;
+
+                continue;
+            }
+
+            let silence = cmp::max(self.last_received, recv_started).elapsed();
+
+            if let Some(timeout) = self.read_timeout {
+                if silence >= timeout {
+                    return Err(ReplicationError::Timeout { timeout });
+                }
+            }
+
+            if self.automatic_status {
+                if self.last_status.elapsed() >= self.status_interval {
+                    // ask for a keepalive if the server was silent since the last update
+                    let reply_requested = self.last_received < self.last_status;
+
+                    self.send_status_update(reply_requested).await?;
+                    continue;
+                }
+
+                if self.threshold_reached() {
+                    self.send_status_update(false).await?;
+                }
+            }
+
+            let mut wait = self.status_wait();
+
+            if let Some(timeout) = self.read_timeout {
+                wait = cmp::min(wait, timeout - silence);
+            }
+
+            let received = {
+                let mut copy_both = self.conn.copy_both();
+                let recv = pin!(copy_both.recv_copy_data_within(self.max_frame_size));
+                let cancelled = pin!(self.cancel.cancelled());
+
+                // `None` if the stream was cancelled; receiving is cancel-safe, so no message
+                // is lost
+                rt::timeout(wait, future::select(recv, cancelled))
+                    .await
+                    .map(|either| match either {
+                        Either::Left((replication, _)) => Some(replication),
+                        Either::Right(_) => None,
+                    })
+            };
+
+            let data = match received {
+                Ok(Some(data)) => data.map_err(|error| match error {
+                    ReplicationError::Sqlx(error) => {
+                        ReplicationError::from_server(error, &self.slot)
+                    }
+                    error => error,
+                })?,
+                Ok(None) => return Ok(Received::Cancelled),
+                // time for the next status update, or the read timeout elapsed
+                Err(_) => continue,
+            };
+
+            self.last_received = Instant::now();
+
+            let Some(data) = data else {
+                // the server ended the stream; end it on our side as well
+                self.ending = Some(Ending::default());
+
+                return self.end().await;
+            };
+
+            self.message_sizes.record(data.len());
+
+            if let Some(max) = self.max_read_buffer {
+                self.size_read_buffer(self.message_sizes.read_buffer_capacity(max));
+            }
+
+            let data = match &mut self.frame_decoder {
+                Some(decoder) => decoder(data)?,
+                None => data,
+            };
+
+            if let Some(capture) = &mut self.capture {
+                replay::write_frame(capture, &data).map_err(Error::Io)?;
+            }
+
+            if let Some(observer) = &self.observer {
+                observer.on_frame(data.first().copied().unwrap_or_default(), data.len());
+            }
+
+            match Replication::decode(data)? {
+                Replication::XLogData(data) => {
+                    self.ready.store(true, Ordering::Relaxed);
+                    self.idle = false;
+                    self.bytes_since_status += data.data.len() as u64;
+
+                    return Ok(Received::XLogData(data));
+                }
+
+                Replication::PrimaryKeepalive(keepalive) => {
+                    self.received_lsn = cmp::max(self.received_lsn, keepalive.wal_end);
+                    self.last_keepalive = Some(keepalive);
+                    self.keepalive_history.record(keepalive);
+                    self.last_keepalive_received = Some(Instant::now());
+                    self.idle = true;
+
+                    if let Some(observer) = &self.observer {
+                        observer.on_keepalive(&keepalive);
+                    }
+
+                    if keepalive.reply_requested && self.automatic_status {
+                        self.send_status_update(false).await?;
+                    }
+
+                    if self.wait_lsn.is_some_and(|lsn| self.received_lsn >= lsn) {
+                        return Ok(Received::Reached);
+                    }
+
+                    if self.keepalive_events {
+                        return Ok(Received::Keepalive(keepalive));
+                    }
+                }
+            }
+        }
+    }
+
+    /// End the stream on our side after the server ended it with `CopyDone`.
+    ///
+    /// If this is cancelled, e.g. with `recv()` in a `select!`, the next call of `recv()`
+    /// continues where it left off, so `CopyDone` is sent once and the result of the command
+    /// is not lost.
+    async fn end(&mut self) -> Result<Received, ReplicationError> {
+        let ending = self.ending.get_or_insert_with(Ending::default);
+        let stream = &mut self.conn.conn.inner.stream;
+
+        if !ending.copy_done_sent {
+            stream.write_msg(CopyDone)?;
+            ending.copy_done_sent = true;
+        }
+
+        stream.flush().await.map_err(Error::Io)?;
+
+        CopyBothReader::new(stream)
+            .recv_end(&mut ending.row)
+            .await?;
+
+        let row = self.ending.take().and_then(|ending| ending.row);
+        self.finished = true;
+
+        Ok(Received::End(row))
+    }
+
+    pub(super) fn set_adaptive_read_buffer(&mut self, max: Option<usize>) {
+        self.max_read_buffer = max;
+
+        match max {
+            Some(max) => self.size_read_buffer(self.message_sizes.read_buffer_capacity(max)),
+            None => self.size_read_buffer(0),
+        }
+    }
+
+    /// Size the read buffer of the connection to `capacity`, unless it is within a factor of
+    /// two of the current capacity, so that it isn't resized for every frame.
+    fn size_read_buffer(&mut self, capacity: usize) {
+        let current = self.read_buffer;
+
+        if capacity == current
+            || (current > 0 && capacity > current / 2 && capacity < current.saturating_mul(2))
+        {
+            return;
+        }
+
+        let socket = &mut *self.conn.conn.inner.stream;
+        socket.set_read_buffer_capacity(capacity);
+
+        if capacity < current {
+            // release the memory of the larger buffer
+            socket.shrink_buffers();
+        }
+
+        self.read_buffer = capacity;
+    }
+
+    pub(super) async fn finish(mut self) -> Result<PgReplicationConnection, ReplicationError> {
+        self.stop().await?;
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = self.slot,
+            lsn = %self.confirmed_lsn,
+            "replication finished"
+        );
+
+        Ok(self.conn)
+    }
+
+    /// End the stream on our side, after a final status update, leaving the connection ready
+    /// for other commands; further calls to [`recv()`][Self::recv] return [`Received::End`].
+    pub(super) async fn stop(&mut self) -> Result<(), ReplicationError> {
+        if self.ending.is_some() {
+            self.end().await?;
+        }
+
+        if !self.finished {
+            // held feedback ends with the stream
+            self.held_lsn = None;
+
+            if self.automatic_status {
+                self.send_status_update(false).await?;
+            }
+
+            self.conn.conn.inner.stream.send(CopyDone).await?;
+
+            // drain the messages the server sent before it received `CopyDone`
+            let mut copy_both = self.conn.copy_both();
+            copy_both
+                .skip_to_done()
+                .await
+                .map_err(|error| ReplicationError::from_server(error, &self.slot))?;
+            copy_both.recv_end(&mut None).await?;
+            self.finished = true;
+        }
+
+        Ok(())
+    }
+
+    async fn send_status_update(&mut self, reply_requested: bool) -> Result<(), ReplicationError> {
+        // the server ignores an invalid flush position, so the slot is not advanced
+        let flushed = if self.read_only {
+            PgLsn::INVALID
+        } else {
+            self.held_lsn.unwrap_or(self.confirmed_lsn)
+        };
+
+        if let Some(store) = &self.offset_store {
+            // the store must not be behind the slot, so it is written before the server is told
+            if flushed > self.stored_lsn {
+                store.store(&self.slot, flushed).await.map_err(|source| {
+                    ReplicationError::OffsetStore {
+                        slot: self.slot.clone(),
+                        source,
+                    }
+                })?;
+                self.stored_lsn = flushed;
+            }
+        }
+
+        self.send_standby_status(self.received_lsn, flushed, flushed, reply_requested)
+            .await?;
+
+        self.log_confirmed(flushed);
+
+        Ok(())
+    }
+
+    /// Report that the confirmed position advanced to `lsn`, at most once per
+    /// [`CONFIRMED_LOG_INTERVAL`].
+    fn log_confirmed(&mut self, lsn: PgLsn) {
+        if lsn <= self.logged_lsn
+            || self
+                .last_logged
+                .is_some_and(|at| at.elapsed() < CONFIRMED_LOG_INTERVAL)
+        {
+            return;
+        }
+
+        tracing::info!(
+            target: LIFECYCLE_TARGET,
+            slot_name = self.slot,
+            lsn = %lsn,
+            "confirmed position advanced"
+        );
+
+        self.logged_lsn = lsn;
+        self.last_logged = Some(Instant::now());
+    }
+
+    pub(super) async fn send_standby_status(
+        &mut self,
+        write: PgLsn,
+        flush: PgLsn,
+        apply: PgLsn,
+        reply_requested: bool,
+    ) -> Result<(), ReplicationError> {
+        // the connection left `CopyBoth` when the server ended the stream
+        if self.finished {
+            return Err(ReplicationError::StreamEnded);
+        }
+
+        let update = StandbyStatusUpdate {
+            write,
+            flush,
+            apply,
+            timestamp: system_time_to_timestamp(self.clock.now()),
+            reply_requested,
+        };
+
+        self.conn.send_copy_data(&update.encode()).await?;
+        self.last_status = Instant::now();
+        self.bytes_since_status = 0;
+        self.transactions_since_status = 0;
+
+        Ok(())
+    }
+
+    /// How long to wait for the next status update; without automatic status updates, the
+    /// interval only paces the checks of the read timeout and of cancellation.
+    fn status_wait(&self) -> Duration {
+        if self.automatic_status {
+            self.status_interval
+                .saturating_sub(self.last_status.elapsed())
+        } else {
+            self.status_interval
+        }
+    }
+
+    fn threshold_reached(&self) -> bool {
+        let reached =
+            |threshold: Option<u64>, count| count > 0 && threshold.is_some_and(|n| count >= n);
+
+        reached(self.status_bytes, self.bytes_since_status)
+            || reached(self.status_transactions, self.transactions_since_status)
+    }
+}
+
+impl Debug for LogicalReplicationStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogicalReplicationStream")
+            .field("slot", &self.core.slot)
+            .field("received_lsn", &self.core.received_lsn)
+            .field("confirmed_lsn", &self.core.confirmed_lsn)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn it_handles_decode_errors_by_policy() {
+        let data = XLogData {
+            wal_start: PgLsn(0x100),
+            wal_end: PgLsn(0x200),
+            timestamp: 0,
+            data: Bytes::from_static(b"?"),
+        };
+        let error = || Error::Protocol("unknown logical replication message type".into());
+
+        let mut fail = DecodeErrorPolicy::default();
+        assert!(matches!(
+            fail.handle("slot", &data, error()),
+            Err(Error::Protocol(_))
+        ));
+
+        assert!(DecodeErrorPolicy::Skip
+            .handle("slot", &data, error())
+            .is_ok());
+
+        let dead_letters = Arc::new(Mutex::new(Vec::new()));
+        let mut dead_letter = DecodeErrorPolicy::dead_letter({
+            let dead_letters = dead_letters.clone();
+
+            move |data: &XLogData, error: &Error| {
+                (dead_letters.lock().unwrap()).push((
+                    data.wal_start,
+                    data.data.clone(),
+                    error.to_string(),
+                ));
+            }
+        });
+        assert!(dead_letter.handle("slot", &data, error()).is_ok());
+
+        let dead_letters = dead_letters.lock().unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].0, PgLsn(0x100));
+        assert_eq!(dead_letters[0].1, &b"?"[..]);
+        assert!(dead_letters[0]
+            .2
+            .contains("unknown logical replication message type"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::types::Oid;
+
+use super::TupleData;
+
+const OID: u32 = 26;
+const TID: u32 = 27;
+const XID: u32 = 28;
+const CID: u32 = 29;
+const XID8: u32 = 5069;
+
+/// A value of one of the system types `oid`, `xid`, `xid8`, `cid` and `tid`, as decoded by
+/// [`SystemValue::decode()`].
+///
+/// These types are exposed by system columns and catalog-like tables. sqlx only has a type
+/// for `oid` ([`Oid`]), which [`TupleData::try_decode()`] decodes as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemValue {
+    /// An object identifier.
+    Oid(u32),
+    /// A 32-bit transaction ID, which wraps around.
+    Xid(u32),
+    /// A 64-bit transaction ID, including the epoch, which doesn't wrap around.
+    Xid8(u64),
+    /// A command ID within a transaction.
+    Cid(u32),
+    /// The physical location of a row version, as its block number and its offset within the
+    /// block, written `(block,offset)` in the text format.
+    Tid(u32, u16),
+}
+
+impl SystemValue {
+    /// Returns `true` if `type_id` is one of the system types this decodes.
+    pub fn is_system_type(type_id: Oid) -> bool {
+        matches!(type_id.0, OID | TID | XID | CID | XID8)
+    }
+
+    /// Decode a value of a column of a system type from the text or the binary format, given
+    /// the OID of the type ([`Column::type_id`][super::Column::type_id]); `None` for `NULL`.
+    ///
+    /// Returns an error for a type that is not a system type, for a malformed value, and for
+    /// [`TupleData::UnchangedToast`], whose value is not known.
+    pub fn decode(data: &TupleData, type_id: Oid) -> Result<Option<Self>, Error> {
+        if !Self::is_system_type(type_id) {
+            return Err(Error::Decode(
+                format!("type OID {} is not a system type", type_id.0).into(),
+            ));
+        }
+
+        let value = match data {
+            TupleData::Null => return Ok(None),
+            TupleData::UnchangedToast => {
+                return Err(Error::Decode("unchanged TOAST value was not sent".into()));
+            }
+            TupleData::Text(text) => {
+                let text =
+                    std::str::from_utf8(text).map_err(|error| Error::Decode(error.into()))?;
+
+                match type_id.0 {
+                    OID => SystemValue::Oid(parse(text)?),
+                    XID => SystemValue::Xid(parse(text)?),
+                    CID => SystemValue::Cid(parse(text)?),
+                    XID8 => SystemValue::Xid8(parse(text)?),
+                    _ => {
+                        let (block, offset) = text
+                            .strip_prefix('(')
+                            .and_then(|text| text.strip_suffix(')'))
+                            .and_then(|text| text.split_once(','))
+                            .ok_or_else(|| {
+                                Error::Decode(format!("invalid tid value {text:?}").into())
+                            })?;
+
+                        SystemValue::Tid(parse(block)?, parse(offset)?)
+                    }
+                }
+            }
+            TupleData::Binary(bytes) => match (type_id.0, &bytes[..]) {
+                (OID, &[a, b, c, d]) => SystemValue::Oid(u32::from_be_bytes([a, b, c, d])),
+                (XID, &[a, b, c, d]) => SystemValue::Xid(u32::from_be_bytes([a, b, c, d])),
+                (CID, &[a, b, c, d]) => SystemValue::Cid(u32::from_be_bytes([a, b, c, d])),
+                (XID8, &[a, b, c, d, e, f, g, h]) => {
+                    SystemValue::Xid8(u64::from_be_bytes([a, b, c, d, e, f, g, h]))
+                }
+                (TID, &[a, b, c, d, e, f]) => {
+                    SystemValue::Tid(u32::from_be_bytes([a, b, c, d]), u16::from_be_bytes([e, f]))
+                }
+                (_, bytes) => {
+                    return Err(Error::Decode(
+                        format!(
+                            "invalid binary value of {} bytes for type OID {}",
+                            bytes.len(),
+                            type_id.0
+                        )
+                        .into(),
+                    ));
+                }
+            },
+        };
+
+        Ok(Some(value))
+    }
+
+    /// Returns an `oid`, `xid` or `cid` value, or `None` for the other types.
+    pub fn as_u32(&self) -> Option<u32> {
+        match *self {
+            SystemValue::Oid(value) | SystemValue::Xid(value) | SystemValue::Cid(value) => {
+                Some(value)
+            }
+            SystemValue::Xid8(_) | SystemValue::Tid(..) => None,
+        }
+    }
+
+    /// Returns an `xid8` value, or `None` for the other types.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            SystemValue::Xid8(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the block number and offset of a `tid` value, or `None` for the other types.
+    pub fn as_tid(&self) -> Option<(u32, u16)> {
+        match *self {
+            SystemValue::Tid(block, offset) => Some((block, offset)),
+            _ => None,
+        }
+    }
+}
+
+fn parse<T: FromStr>(text: &str) -> Result<T, Error>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    text.parse()
+        .map_err(|error: T::Err| Error::Decode(format!("invalid value {text:?}: {error}").into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn it_decodes_system_types() {
+        let text = |value: &'static str| TupleData::Text(Bytes::from_static(value.as_bytes()));
+        let binary = |value: &'static [u8]| TupleData::Binary(Bytes::from_static(value));
+
+        assert_eq!(
+            SystemValue::decode(&text("(12,3)"), Oid(TID)).unwrap(),
+            Some(SystemValue::Tid(12, 3))
+        );
+        assert_eq!(
+            SystemValue::decode(&binary(b"\0\0\0\x0c\0\x03"), Oid(TID)).unwrap(),
+            Some(SystemValue::Tid(12, 3))
+        );
+        assert!(SystemValue::decode(&text("12,3"), Oid(TID)).is_err());
+        assert!(SystemValue::decode(&text("(12,70000)"), Oid(TID)).is_err());
+
+        assert_eq!(
+            SystemValue::decode(&text("4294967295"), Oid(XID)).unwrap(),
+            Some(SystemValue::Xid(u32::MAX))
+        );
+        assert_eq!(
+            SystemValue::decode(&binary(b"\0\0\x02\xe8"), Oid(CID)).unwrap(),
+            Some(SystemValue::Cid(744))
+        );
+        assert_eq!(
+            SystemValue::decode(&binary(b"\0\0\0\x01\0\0\0\x02"), Oid(XID8)).unwrap(),
+            Some(SystemValue::Xid8((1 << 32) + 2))
+        );
+        assert_eq!(
+            SystemValue::decode(&text("16384"), Oid(OID))
+                .unwrap()
+                .and_then(|value| value.as_u32()),
+            Some(16384)
+        );
+
+        // a value of the wrong size
+        assert!(SystemValue::decode(&binary(b"\0\0\0\x01"), Oid(XID8)).is_err());
+        assert_eq!(
+            SystemValue::decode(&TupleData::Null, Oid(XID)).unwrap(),
+            None
+        );
+        assert!(SystemValue::decode(&TupleData::UnchangedToast, Oid(XID)).is_err());
+        // `int4`
+        assert!(SystemValue::decode(&text("1"), Oid(23)).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+
+use futures_core::stream::Stream;
+use futures_util::stream;
+
+use crate::error::Error;
+use crate::types::Oid;
+
+use super::{
+    LogicalReplication, LogicalReplicationStream, PgReplicationConnection, Relation,
+    ReplicationError, TransactionStream, Tuples,
+};
+
+/// A type that can be decoded from the rows of a replicated table, for a [`TableStream`].
+///
+/// ```rust
+/// # use sqlx::postgres::replication::{FromReplicationRow, Relation, Tuples};
+/// struct User {
+///     id: i32,
+///     name: String,
+/// }
+///
+/// impl FromReplicationRow for User {
+///     type Key = i32;
+///
+///     fn from_row(relation: &Relation, row: &Tuples) -> Result<Self, sqlx::Error> {
+///         Ok(User {
+///             id: Self::key_from_row(relation, row)?,
+///             name: decode(relation, row, "name")?,
+///         })
+///     }
+///
+///     fn key_from_row(relation: &Relation, row: &Tuples) -> Result<i32, sqlx::Error> {
+///         decode(relation, row, "id")
+///     }
+/// }
+///
+/// fn decode<T>(relation: &Relation, row: &Tuples, name: &str) -> Result<T, sqlx::Error>
+/// where
+///     T: for<'r> sqlx::Decode<'r, sqlx::Postgres> + sqlx::Type<sqlx::Postgres>,
+/// {
+///     let index = relation
+///         .columns
+///         .iter()
+///         .position(|column| column.name == name)
+///         .ok_or_else(|| sqlx::Error::ColumnNotFound(name.to_owned()))?;
+///
+///     row[index].try_decode(relation.columns[index].type_id)
+/// }
+/// ```
+pub trait FromReplicationRow: Sized {
+    /// The key that identifies a row, e.g. the value of its primary key.
+    type Key;
+
+    /// Decode a complete row, as inserted, or as it is after an update.
+    ///
+    /// Columns with an unchanged TOAST value are [`TupleData::UnchangedToast`] in the row
+    /// after an update, as their value is not sent.
+    ///
+    /// [`TupleData::UnchangedToast`]: super::TupleData::UnchangedToast
+    fn from_row(relation: &Relation, row: &Tuples) -> Result<Self, Error>;
+
+    /// Decode the key of a row, either from a complete row or from the key the server sent
+    /// for an update or delete, in which the columns that are not part of the key are `NULL`.
+    fn key_from_row(relation: &Relation, row: &Tuples) -> Result<Self::Key, Error>;
+}
+
+/// A change of a row of the table of a [`TableStream`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T: FromReplicationRow> {
+    /// A row was inserted.
+    Insert(T),
+    /// A row was updated.
+    Update {
+        /// The key of the row before the update, which is the key of the row after it if the
+        /// key didn't change.
+        key: T::Key,
+        /// The row after the update.
+        new: T,
+    },
+    /// The row with the key was deleted.
+    Delete(T::Key),
+    /// The table was truncated.
+    Truncate,
+}
+
+/// A stream of the changes of the rows of a single table, decoded into `T`, created with
+/// [`LogicalReplicationStream::table()`].
+///
+/// The relation is identified by name, from the first [`Relation`] message of the table, and
+/// the changes of all other tables are skipped. The changes are received by committed
+/// transaction, like from a [`TransactionStream`]: nothing of a transaction that aborted is
+/// returned, and calling [`recv()`][Self::recv] again after the last change of a transaction
+/// acknowledges it.
+///
+/// If the definition of the table changes while streaming, e.g. because a column was added,
+/// or if it was dropped and created again, [`ReplicationError::SchemaChanged`] is returned
+/// rather than decoding the rows with a definition `T` wasn't written for.
+///
+/// An error, like a row that cannot be decoded, is returned again by every later call to
+/// [`recv()`][Self::recv], so that the change is not skipped.
+pub struct TableStream<T> {
+    transactions: TransactionStream,
+    namespace: String,
+    name: String,
+    /// The definition of the table, from its first `Relation` message.
+    relation: Option<Relation>,
+    /// The changes of the transaction being returned.
+    pending: VecDeque<LogicalReplication>,
+    row: PhantomData<fn() -> T>,
+}
+
+impl<T: FromReplicationRow> TableStream<T> {
+    pub(crate) fn new(
+        stream: LogicalReplicationStream,
+        namespace: impl Into<String>,
+        name: impl Into<String>,
+    ) -> Self {
+        Self {
+            transactions: stream.transactions(),
+            namespace: namespace.into(),
+            name: name.into(),
+            relation: None,
+            pending: VecDeque::new(),
+            row: PhantomData,
+        }
+    }
+
+    /// The underlying stream, e.g. to configure it.
+    pub fn stream_mut(&mut self) -> &mut LogicalReplicationStream {
+        self.transactions.stream_mut()
+    }
+
+    /// The definition of the table, once its first [`Relation`] message was received.
+    pub fn relation(&self) -> Option<&Relation> {
+        self.relation.as_ref()
+    }
+
+    /// Receive the next change of the table, acknowledging the transactions whose changes
+    /// were all returned before.
+    ///
+    /// Returns `Ok(None)` if the server ended the stream.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv(&mut self) -> Result<Option<Change<T>>, ReplicationError> {
+        loop {
+            while let Some(replication) = self.pending.pop_front() {
+                match self.decode(&replication) {
+                    Ok(Some(change)) => return Ok(Some(change)),
+                    Ok(None) => {}
+                    Err(error) => {
+                        // return the error again instead of skipping the change
+                        self.pending.push_front(replication);
+
+                        return Err(error);
+                    }
+                }
+            }
+
+            let Some(transaction) = self.transactions.recv().await? else {
+                return Ok(None);
+            };
+
+            self.pending.extend(transaction.changes);
+        }
+    }
+
+    /// Stop streaming and return the replication connection, acknowledging the transactions
+    /// whose changes were all returned before.
+    pub async fn finish(self) -> Result<PgReplicationConnection, ReplicationError> {
+        if !self.pending.is_empty() {
+            // the transaction in progress must not be acknowledged
+            return self.transactions.into_inner().finish().await;
+        }
+
+        self.transactions.finish().await
+    }
+
+    /// Consume this stream, returning a `Stream` of changes.
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Change<T>, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv().await {
+                Ok(Some(change)) => Some((Ok(change), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+
+    fn decode(
+        &mut self,
+        replication: &LogicalReplication,
+    ) -> Result<Option<Change<T>>, ReplicationError> {
+        let change = match replication {
+            LogicalReplication::Relation(relation) => {
+                self.resolve(relation)?;
+
+                return Ok(None);
+            }
+
+            LogicalReplication::Insert(insert) => {
+                let Some(relation) = self.table(insert.relation_id)? else {
+                    return Ok(None);
+                };
+
+                Change::Insert(T::from_row(relation, &insert.new_data)?)
+            }
+
+            LogicalReplication::Update(update) => {
+                let Some(relation) = self.table(update.relation_id)? else {
+                    return Ok(None);
+                };
+
+                let (before, after) = update.images();
+                let key = before.map_or(after, |before| before.into_inner());
+
+                Change::Update {
+                    key: T::key_from_row(relation, key)?,
+                    new: T::from_row(relation, after)?,
+                }
+            }
+
+            LogicalReplication::Delete(delete) => {
+                let Some(relation) = self.table(delete.relation_id)? else {
+                    return Ok(None);
+                };
+
+                Change::Delete(T::key_from_row(relation, delete.key(relation)?.row())?)
+            }
+
+            LogicalReplication::Truncate(truncate) => {
+                for &relation_id in &truncate.relation_ids {
+                    if self.table(relation_id)?.is_some() {
+                        return Ok(Some(Change::Truncate));
+                    }
+                }
+
+                return Ok(None);
+            }
+
+            _ => return Ok(None),
+        };
+
+        Ok(Some(change))
+    }
+
+    /// The definition of the table if `relation_id` is its OID, resolving it from the relation
+    /// cache of the stream if it wasn't sent in a `Relation` message of a transaction, e.g.
+    /// because the transaction that sent it didn't change the table.
+    fn table(&mut self, relation_id: Oid) -> Result<Option<&Relation>, ReplicationError> {
+        if self.relation.as_ref().map(|relation| relation.relation_id) != Some(relation_id) {
+            let Some(relation) = self.transactions.stream_mut().relation(relation_id) else {
+                return Ok(None);
+            };
+
+            if relation.namespace != self.namespace || relation.name != self.name {
+                return Ok(None);
+            }
+
+            let relation = relation.clone();
+            self.resolve(&relation)?;
+        }
+
+        Ok(self.relation.as_ref())
+    }
+
+    /// Check a `Relation` message, returning `true` if it describes the table.
+    fn resolve(&mut self, relation: &Relation) -> Result<bool, ReplicationError> {
+        if relation.namespace != self.namespace || relation.name != self.name {
+            return Ok(false);
+        }
+
+        match &self.relation {
+            None => self.relation = Some(relation.clone()),
+            Some(expected) if expected.same_definition(relation) => {}
+            Some(_) => {
+                return Err(ReplicationError::SchemaChanged {
+                    relation: relation.qualified_name(),
+                })
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl<T> Debug for TableStream<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TableStream")
+            .field("transactions", &self.transactions)
+            .field("namespace", &self.namespace)
+            .field("name", &self.name)
+            .finish()
+    }
+}
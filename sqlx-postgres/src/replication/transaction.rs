@@ -0,0 +1,1368 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::time::{Duration, SystemTime};
+
+use futures_core::stream::Stream;
+use futures_util::stream;
+
+use crate::types::{Oid, PgLsn};
+
+use super::message::timestamp_to_system_time;
+use super::{
+    Delete, Insert, LogicalReplication, LogicalReplicationStream, PgReplicationConnection,
+    Projection, Relation, ReplicationError, TupleData, Tuples, Update,
+};
+
+/// A committed transaction, received from a [`TransactionStream`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ReplicatedTransaction {
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// The LSN of the commit record of the transaction; see
+    /// [`Commit::commit_lsn`][super::Commit::commit_lsn].
+    pub commit_lsn: PgLsn,
+    /// The end LSN of the transaction, just past its commit record; confirming it
+    /// acknowledges the transaction, while confirming [`commit_lsn`][Self::commit_lsn] would
+    /// not.
+    pub end_lsn: PgLsn,
+    /// The commit timestamp of the transaction, as microseconds since the Postgres epoch
+    /// (`2000-01-01`).
+    pub commit_timestamp: i64,
+    /// `true` if the transaction was streamed while in progress, see
+    /// [`PgOutputOptions::streaming()`][super::PgOutputOptions::streaming].
+    pub streamed: bool,
+    /// The messages of the transaction between its start and its commit, in the order they
+    /// were sent, e.g. [`Relation`][super::Relation], [`Insert`][super::Insert] and
+    /// transactional [`Message`][super::Message]s.
+    ///
+    /// The `xid` of each message is set, regardless of whether the transaction was streamed:
+    /// for a transaction that was not streamed, `pgoutput` only sends the xid with `Begin`,
+    /// so it is set to [`xid`][Self::xid]; for a streamed one, it is the xid of the
+    /// (sub)transaction that made the change, as sent by the server.
+    pub changes: Vec<LogicalReplication>,
+}
+
+impl ReplicatedTransaction {
+    /// The commit time of the transaction.
+    pub fn commit_time(&self) -> SystemTime {
+        timestamp_to_system_time(self.commit_timestamp)
+    }
+
+    /// The time between the commit of the transaction on the server and `now`, usually
+    /// [`SystemTime::now()`].
+    ///
+    /// Returns zero if `now` is before the commit time, e.g. because the clocks of the client
+    /// and the server are not in sync.
+    pub fn lag(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.commit_time()).unwrap_or_default()
+    }
+
+    /// Collapse the changes of each row into its final state, e.g. for a sink that upserts
+    /// rows and doesn't need their intermediate states.
+    ///
+    /// Rows are identified by their relation and the values of its key columns, resolved with
+    /// `lookup`, usually [`LogicalReplicationStream::relation()`]. The changes of a row are
+    /// folded into one change, which takes the place of the last of them:
+    ///
+    /// * an insert followed by updates becomes an insert of the final row;
+    /// * updates followed by updates become one update from the row before the first update
+    ///   (if it was sent) to the final row;
+    /// * an insert followed by a delete, with or without updates in between, is dropped;
+    /// * updates followed by a delete become a delete with the key of the row before the first
+    ///   update, which is the row known downstream if the key was changed.
+    ///
+    /// A delete followed by an insert of the same key is kept as both, and the values of
+    /// columns with an unchanged TOAST value are taken from the change folded into. Changes are
+    /// only folded while the relation is unchanged: a [`Relation`] message, a
+    /// [`Truncate`][super::Truncate] and a change whose key is not known (e.g. for
+    /// `REPLICA IDENTITY NOTHING`) start over for the relation, and the changes before the first
+    /// `Relation` message of a relation in the transaction are kept, as `lookup` only knows
+    /// the relation after it.
+    ///
+    /// The changes of different rows may be reordered relative to each other, so a sink that
+    /// relies on their order, e.g. to check foreign keys or unique constraints on columns other
+    /// than the key, should not coalesce. All other messages are kept in their order.
+    ///
+    /// [`LogicalReplicationStream::relation()`]: super::LogicalReplicationStream::relation
+    pub fn coalesce<'r>(&mut self, lookup: impl Fn(Oid) -> Option<&'r Relation>) {
+        self.changes = coalesce_changes(std::mem::take(&mut self.changes), lookup);
+    }
+
+    /// Check that the transaction is consistent with the structure of the protocol, returning
+    /// the first violation found, e.g. to catch a bug in the decoding or reassembly of
+    /// transactions early; the checks go through all changes, so they are best left to tests
+    /// and debug builds.
+    ///
+    /// The transaction is consistent if:
+    ///
+    /// * its commit LSN is valid and before its end LSN;
+    /// * its changes contain no message that starts or ends a transaction or a segment of one,
+    ///   i.e. it began before all of its changes;
+    /// * each change carries the xid of the transaction if it was not streamed, and an xid if
+    ///   it was, as that of a subtransaction may differ;
+    /// * the relation of each change was sent in an earlier [`Relation`] message of the
+    ///   transaction, or is known to `lookup`, usually [`LogicalReplicationStream::relation()`];
+    /// * each logical decoding message was written before the commit.
+    pub fn validate<'r>(
+        &self,
+        lookup: impl Fn(Oid) -> Option<&'r Relation>,
+    ) -> Result<(), TransactionViolation> {
+        if self.commit_lsn == PgLsn::INVALID || self.commit_lsn >= self.end_lsn {
+            return Err(TransactionViolation::CommitLsn {
+                commit_lsn: self.commit_lsn,
+                end_lsn: self.end_lsn,
+            });
+        }
+
+        let mut relations = HashSet::new();
+
+        for (index, change) in self.changes.iter().enumerate() {
+            let relation_ids: &[Oid] = match change {
+                LogicalReplication::Relation(relation) => {
+                    relations.insert(relation.relation_id);
+                    &[]
+                }
+                LogicalReplication::Insert(insert) => std::slice::from_ref(&insert.relation_id),
+                LogicalReplication::Update(update) => std::slice::from_ref(&update.relation_id),
+                LogicalReplication::Delete(delete) => std::slice::from_ref(&delete.relation_id),
+                LogicalReplication::Truncate(truncate) => &truncate.relation_ids,
+                LogicalReplication::Message(message) if message.lsn >= self.commit_lsn => {
+                    return Err(TransactionViolation::MessageAfterCommit {
+                        index,
+                        lsn: message.lsn,
+                        commit_lsn: self.commit_lsn,
+                    });
+                }
+                LogicalReplication::Message(_) | LogicalReplication::Type(_) => &[],
+                // sent after `Begin`, without an xid
+                LogicalReplication::Origin(_) => continue,
+                boundary => {
+                    return Err(TransactionViolation::Boundary {
+                        index,
+                        kind: boundary.kind(),
+                    });
+                }
+            };
+
+            let xid = change_xid(change);
+
+            if xid.is_none() || (!self.streamed && xid != Some(self.xid)) {
+                return Err(TransactionViolation::Xid { index, found: xid });
+            }
+
+            if let Some(&relation_id) = (relation_ids.iter()).find(|&&relation_id| {
+                !relations.contains(&relation_id) && lookup(relation_id).is_none()
+            }) {
+                return Err(TransactionViolation::UnknownRelation { index, relation_id });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An inconsistency of a [`ReplicatedTransaction`], as found by
+/// [`ReplicatedTransaction::validate()`].
+///
+/// The `index` of a variant is the position of the offending message in
+/// [`changes`][ReplicatedTransaction::changes].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum TransactionViolation {
+    /// The commit LSN is invalid, or not before the end LSN.
+    #[error("commit LSN {commit_lsn} is not before the end LSN {end_lsn}")]
+    CommitLsn { commit_lsn: PgLsn, end_lsn: PgLsn },
+
+    /// A message that starts or ends a transaction or a segment of one, e.g. `Begin` or
+    /// `StreamStop`, is among the changes.
+    #[error("change {index} is a {kind} message")]
+    Boundary { index: usize, kind: &'static str },
+
+    /// A change of a transaction that was not streamed doesn't carry its xid, or a change of a
+    /// streamed one carries none.
+    #[error("change {index} has xid {found:?}")]
+    Xid { index: usize, found: Option<u32> },
+
+    /// A change refers to a relation that was not sent before.
+    #[error("change {index} refers to unknown relation {}", relation_id.0)]
+    UnknownRelation { index: usize, relation_id: Oid },
+
+    /// A logical decoding message was written at or after the commit of the transaction.
+    #[error("message {index} at {lsn} is not before the commit at {commit_lsn}")]
+    MessageAfterCommit {
+        index: usize,
+        lsn: PgLsn,
+        commit_lsn: PgLsn,
+    },
+}
+
+/// A transaction whose messages a [`TransactionStream`] buffers until it commits, as returned
+/// by [`TransactionStream::pending_transactions()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PendingTxInfo {
+    /// The xid of the transaction.
+    pub xid: u32,
+    /// `true` if the transaction is streamed while in progress; its segments are buffered until
+    /// it commits or aborts.
+    pub streamed: bool,
+    /// The size of the values of the buffered messages in bytes, i.e. of their tuples and the
+    /// contents of logical decoding messages, which make up most of their memory.
+    pub buffered_bytes: usize,
+    /// The number of buffered messages.
+    pub message_count: usize,
+    /// When the first message of the transaction was received, by the
+    /// [clock][LogicalReplicationStream::set_clock] of the stream.
+    pub started_at: SystemTime,
+}
+
+/// A stream of committed transactions, created with
+/// [`LogicalReplicationStream::transactions()`].
+///
+/// The messages of each transaction are collected until it commits, and returned together as
+/// a [`ReplicatedTransaction`]. Streamed transactions are reassembled from their segments,
+/// which may be interleaved with the segments of other transactions, and returned once they
+/// commit; the changes of an aborted transaction or subtransaction are dropped. Calling
+/// [`recv()`][Self::recv] again acknowledges the transaction returned before.
+///
+/// Messages that are not part of a transaction, like non-transactional logical decoding
+/// messages, are skipped. Two-phase transactions are not supported.
+pub struct TransactionStream {
+    stream: LogicalReplicationStream,
+    buffer: TransactionBuffer,
+    /// The position to confirm with the next call to `recv()`.
+    ack_lsn: Option<PgLsn>,
+    coalesce: bool,
+    validate: bool,
+}
+
+impl TransactionStream {
+    pub(crate) fn new(stream: LogicalReplicationStream) -> Self {
+        Self {
+            stream,
+            buffer: TransactionBuffer::default(),
+            ack_lsn: None,
+            coalesce: false,
+            validate: false,
+        }
+    }
+
+    /// Collapse the changes of each row within a transaction into its final state with
+    /// [`ReplicatedTransaction::coalesce()`] before returning it; off by default, as it hides
+    /// the intermediate states of rows.
+    pub fn set_coalesce(&mut self, coalesce: bool) {
+        self.coalesce = coalesce;
+    }
+
+    /// Check each transaction with [`ReplicatedTransaction::validate()`] before returning it,
+    /// returning [`ReplicationError::InvalidTransaction`] if it is inconsistent; off by default,
+    /// as the checks go through all changes.
+    ///
+    /// The transaction is checked before it is coalesced, and isn't acknowledged if it fails.
+    pub fn set_validate(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// The underlying stream, e.g. to configure it.
+    pub fn stream_mut(&mut self) -> &mut LogicalReplicationStream {
+        &mut self.stream
+    }
+
+    /// Receive the next committed transaction, acknowledging the transaction returned before.
+    ///
+    /// Returns `Ok(None)` if the server ended the stream.
+    ///
+    /// # Cancel Safety
+    ///
+    /// This method is cancel-safe.
+    pub async fn recv(&mut self) -> Result<Option<ReplicatedTransaction>, ReplicationError> {
+        if let Some(lsn) = self.ack_lsn.take() {
+            self.stream.set_confirmed_lsn(lsn);
+        }
+
+        loop {
+            let Some(replication) = self.stream.recv().await? else {
+                return Ok(None);
+            };
+
+            if let Some(mut transaction) = self.buffer.push(replication, self.stream.now()) {
+                if self.validate {
+                    transaction
+                        .validate(|relation_id| self.stream.relation(relation_id))
+                        .map_err(|violation| ReplicationError::InvalidTransaction {
+                            xid: transaction.xid,
+                            violation,
+                        })?;
+                }
+
+                self.ack_lsn = Some(transaction.end_lsn);
+
+                if self.coalesce {
+                    transaction.coalesce(|relation_id| self.stream.relation(relation_id));
+                }
+
+                return Ok(Some(transaction));
+            }
+        }
+    }
+
+    /// The transactions whose messages are buffered until they commit, e.g. to spot a large
+    /// open transaction that consumes memory.
+    ///
+    /// Besides the transactions streamed while in progress, whose segments can be interleaved,
+    /// this includes the transaction being received, if any.
+    pub fn pending_transactions(&self) -> Vec<PendingTxInfo> {
+        self.buffer.pending()
+    }
+
+    /// The underlying stream, without acknowledging the transaction returned before.
+    pub(crate) fn into_inner(self) -> LogicalReplicationStream {
+        self.stream
+    }
+
+    /// Stop streaming and return the replication connection, acknowledging the transaction
+    /// returned before.
+    pub async fn finish(mut self) -> Result<PgReplicationConnection, ReplicationError> {
+        if let Some(lsn) = self.ack_lsn.take() {
+            self.stream.set_confirmed_lsn(lsn);
+        }
+
+        self.stream.finish().await
+    }
+
+    /// Consume this stream, returning a `Stream` of transactions.
+    ///
+    /// The stream ends if the server ends replication, or after the first error.
+    pub fn into_stream(
+        self,
+    ) -> impl Stream<Item = Result<ReplicatedTransaction, ReplicationError>> + Unpin {
+        Box::pin(stream::unfold(Some(self), |this| async move {
+            let mut this = this?;
+
+            match this.recv().await {
+                Ok(Some(transaction)) => Some((Ok(transaction), Some(this))),
+                Ok(None) => None,
+                // end the stream after the first error
+                Err(error) => Some((Err(error), None)),
+            }
+        }))
+    }
+}
+
+impl Debug for TransactionStream {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionStream")
+            .field("stream", &self.stream)
+            .finish()
+    }
+}
+
+/// Collects the messages of transactions until they commit.
+#[derive(Default)]
+pub(super) struct TransactionBuffer {
+    /// The xid and the messages of the (not streamed) transaction in progress.
+    current: Option<(u32, Buffered)>,
+    /// The top-level xid of the streamed transaction currently being received.
+    streamed_xid: Option<u32>,
+    /// The messages of streamed transactions, by top-level xid.
+    streamed: HashMap<u32, Buffered>,
+}
+
+/// The buffered messages of a transaction.
+struct Buffered {
+    changes: Vec<LogicalReplication>,
+    /// The sum of the `buffered_size()` of the changes.
+    bytes: usize,
+    started_at: SystemTime,
+}
+
+impl Buffered {
+    fn new(started_at: SystemTime) -> Self {
+        Self {
+            changes: Vec::new(),
+            bytes: 0,
+            started_at,
+        }
+    }
+
+    fn push(&mut self, change: LogicalReplication) {
+        self.bytes += buffered_size(&change);
+        self.changes.push(change);
+    }
+
+    fn info(&self, xid: u32, streamed: bool) -> PendingTxInfo {
+        PendingTxInfo {
+            xid,
+            streamed,
+            buffered_bytes: self.bytes,
+            message_count: self.changes.len(),
+            started_at: self.started_at,
+        }
+    }
+}
+
+impl TransactionBuffer {
+    /// Add a message received at `now`, returning the transaction it commits, if any.
+    pub(super) fn push(
+        &mut self,
+        replication: LogicalReplication,
+        now: SystemTime,
+    ) -> Option<ReplicatedTransaction> {
+        match replication {
+            LogicalReplication::Begin(begin) => {
+                self.current = Some((begin.xid, Buffered::new(now)))
+            }
+
+            LogicalReplication::Commit(commit) => {
+                let (xid, buffered) = self.current.take()?;
+
+                return Some(ReplicatedTransaction {
+                    xid,
+                    commit_lsn: commit.commit_lsn,
+                    end_lsn: commit.end_lsn,
+                    commit_timestamp: commit.commit_timestamp,
+                    streamed: false,
+                    changes: buffered.changes,
+                });
+            }
+
+            LogicalReplication::StreamStart(start) => {
+                // a first segment starts the transaction over, e.g. when it is streamed again
+                // after a restart; the changes of later segments are added to it until it
+                // commits, and only the first segment has the `Relation` and `Type` messages
+                if start.first_segment {
+                    self.streamed.insert(start.xid, Buffered::new(now));
+                }
+
+                self.streamed_xid = Some(start.xid);
+            }
+            LogicalReplication::StreamStop => self.streamed_xid = None,
+
+            LogicalReplication::StreamCommit(commit) => {
+                return Some(ReplicatedTransaction {
+                    xid: commit.xid,
+                    commit_lsn: commit.commit_lsn,
+                    end_lsn: commit.end_lsn,
+                    commit_timestamp: commit.commit_timestamp,
+                    streamed: true,
+                    changes: (self.streamed.remove(&commit.xid))
+                        .map(|buffered| buffered.changes)
+                        .unwrap_or_default(),
+                });
+            }
+
+            LogicalReplication::StreamAbort(abort) if abort.is_top_level() => {
+                self.streamed.remove(&abort.xid);
+            }
+
+            // the changes of an aborted subtransaction carry its xid
+            LogicalReplication::StreamAbort(abort) => {
+                if let Some(buffered) = self.streamed.get_mut(&abort.xid) {
+                    buffered
+                        .changes
+                        .retain(|change| change_xid(change) != Some(abort.subxid));
+                    buffered.bytes = buffered.changes.iter().map(buffered_size).sum();
+                }
+            }
+
+            LogicalReplication::Message(message) if !message.transactional => {}
+
+            LogicalReplication::BeginPrepare(_)
+            | LogicalReplication::Prepare(_)
+            | LogicalReplication::CommitPrepared(_)
+            | LogicalReplication::RollbackPrepared(_)
+            | LogicalReplication::StreamPrepare(_) => {}
+
+            change => {
+                if let Some(xid) = self.streamed_xid {
+                    (self.streamed.entry(xid))
+                        .or_insert_with(|| Buffered::new(now))
+                        .push(change);
+                } else if let Some((xid, buffered)) = &mut self.current {
+                    buffered.push(with_xid(change, *xid));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The transactions in progress, starting with the earliest one.
+    fn pending(&self) -> Vec<PendingTxInfo> {
+        let mut pending: Vec<_> = (self.streamed.iter())
+            .map(|(xid, buffered)| buffered.info(*xid, true))
+            .chain((self.current.iter()).map(|(xid, buffered)| buffered.info(*xid, false)))
+            .collect();
+
+        pending.sort_by_key(|info| info.started_at);
+        pending
+    }
+}
+
+/// The size of the values of a message, which make up most of its memory.
+fn buffered_size(change: &LogicalReplication) -> usize {
+    let tuples = |tuples: &Tuples| -> usize {
+        (tuples.iter())
+            .filter_map(TupleData::as_bytes)
+            .map(<[u8]>::len)
+            .sum()
+    };
+
+    match change {
+        LogicalReplication::Insert(insert) => tuples(&insert.new_data),
+        LogicalReplication::Update(update) => {
+            (update.key_data.iter())
+                .chain(&update.old_data)
+                .map(tuples)
+                .sum::<usize>()
+                + tuples(&update.new_data)
+        }
+        LogicalReplication::Delete(delete) => (delete.key_data.iter())
+            .chain(&delete.old_data)
+            .map(tuples)
+            .sum(),
+        LogicalReplication::Message(message) => message.prefix.len() + message.content.len(),
+        _ => 0,
+    }
+}
+
+/// Set the xid of a message of a transaction that was not streamed, which is only sent with
+/// `Begin`.
+fn with_xid(mut change: LogicalReplication, xid: u32) -> LogicalReplication {
+    let change_xid = match &mut change {
+        LogicalReplication::Message(message) => &mut message.xid,
+        LogicalReplication::Relation(relation) => &mut relation.xid,
+        LogicalReplication::Type(ty) => &mut ty.xid,
+        LogicalReplication::Insert(insert) => &mut insert.xid,
+        LogicalReplication::Update(update) => &mut update.xid,
+        LogicalReplication::Delete(delete) => &mut delete.xid,
+        LogicalReplication::Truncate(truncate) => &mut truncate.xid,
+        _ => return change,
+    };
+
+    change_xid.get_or_insert(xid);
+
+    change
+}
+
+/// Fold the changes of each row in `changes`; see [`ReplicatedTransaction::coalesce()`].
+fn coalesce_changes<'r>(
+    changes: Vec<LogicalReplication>,
+    lookup: impl Fn(Oid) -> Option<&'r Relation>,
+) -> Vec<LogicalReplication> {
+    // the relations described again in the transaction are only known from their message on
+    let mut described: HashSet<Oid> = (changes.iter())
+        .filter_map(|change| match change {
+            LogicalReplication::Relation(relation) => Some(relation.relation_id),
+            _ => None,
+        })
+        .collect();
+    let mut relations: HashMap<Oid, Relation> = HashMap::new();
+
+    // the folded changes, where a change folded into a later one is `None`
+    let mut folded: Vec<Option<LogicalReplication>> = Vec::with_capacity(changes.len());
+    // the index of the last insert or update of each row in `folded`, by relation and key
+    let mut rows: HashMap<(Oid, Vec<TupleData>), usize> = HashMap::new();
+
+    for change in changes {
+        let relation_id = match &change {
+            LogicalReplication::Insert(Insert { relation_id, .. })
+            | LogicalReplication::Update(Update { relation_id, .. })
+            | LogicalReplication::Delete(Delete { relation_id, .. }) => *relation_id,
+            other => {
+                if let LogicalReplication::Relation(relation) = other {
+                    described.remove(&relation.relation_id);
+                    relations.insert(relation.relation_id, relation.clone());
+                    rows.retain(|(id, _), _| *id != relation.relation_id);
+                } else if let LogicalReplication::Truncate(truncate) = other {
+                    rows.retain(|(id, _), _| !truncate.relation_ids.contains(id));
+                }
+
+                folded.push(Some(change));
+                continue;
+            }
+        };
+
+        let relation = match relations.get(&relation_id) {
+            Some(relation) => Some(relation),
+            None if described.contains(&relation_id) => None,
+            None => lookup(relation_id),
+        };
+
+        let Some((old_key, new_key)) = relation.and_then(|relation| change_keys(relation, &change))
+        else {
+            rows.retain(|(id, _), _| *id != relation_id);
+            folded.push(Some(change));
+            continue;
+        };
+
+        let previous = rows
+            .remove(&(relation_id, old_key))
+            .and_then(|index| Some((index, folded[index].take()?)));
+
+        let change = match (previous, change) {
+            (Some((_, LogicalReplication::Insert(insert))), LogicalReplication::Update(update)) => {
+                Some(LogicalReplication::Insert(Insert {
+                    xid: update.xid,
+                    relation_id,
+                    new_data: merge_unchanged(update.new_data, &insert.new_data),
+                }))
+            }
+            (Some((_, LogicalReplication::Update(first))), LogicalReplication::Update(update)) => {
+                Some(LogicalReplication::Update(Update {
+                    xid: update.xid,
+                    relation_id,
+                    key_data: first.key_data.or(update.key_data),
+                    old_data: first.old_data.or(update.old_data),
+                    new_data: merge_unchanged(update.new_data, &first.new_data),
+                }))
+            }
+            (Some((_, LogicalReplication::Insert(_))), LogicalReplication::Delete(_)) => None,
+            (Some((_, LogicalReplication::Update(first))), LogicalReplication::Delete(delete)) => {
+                Some(LogicalReplication::Delete(Delete {
+                    xid: delete.xid,
+                    relation_id,
+                    key_data: first.key_data.or(delete.key_data),
+                    old_data: first.old_data.or(delete.old_data),
+                }))
+            }
+            // not a sequence of changes of one row, e.g. an insert after a delete
+            (Some((index, previous)), change) => {
+                folded[index] = Some(previous);
+                Some(change)
+            }
+            (None, change) => Some(change),
+        };
+
+        // a deleted row is not folded into, so that an insert of the same key is kept
+        if let Some(change) = change {
+            if let Some(key) = new_key {
+                rows.insert((relation_id, key), folded.len());
+            }
+
+            folded.push(Some(change));
+        }
+    }
+
+    folded.into_iter().flatten().collect()
+}
+
+/// The key of the row before a change, and the key of the row after it, if any.
+///
+/// Returns `None` if a key is not known, e.g. because the relation has no key columns.
+fn change_keys(
+    relation: &Relation,
+    change: &LogicalReplication,
+) -> Option<(Vec<TupleData>, Option<Vec<TupleData>>)> {
+    let key = |row: &Tuples| -> Option<Vec<TupleData>> {
+        let projection = Projection::key(relation);
+
+        if projection.indices().is_empty() {
+            return None;
+        }
+
+        (projection.indices().iter())
+            .map(|&index| match row.get(index)? {
+                TupleData::UnchangedToast => None,
+                data => Some(data.clone()),
+            })
+            .collect()
+    };
+
+    match change {
+        LogicalReplication::Insert(insert) => {
+            let new_key = key(&insert.new_data)?;
+            Some((new_key.clone(), Some(new_key)))
+        }
+        LogicalReplication::Update(update) => {
+            let new_key = key(&update.new_data)?;
+            let old_key = match update.key_data.as_ref().or(update.old_data.as_ref()) {
+                Some(old) => key(old)?,
+                None => new_key.clone(),
+            };
+
+            Some((old_key, Some(new_key)))
+        }
+        LogicalReplication::Delete(delete) => {
+            let old = delete.key_data.as_ref().or(delete.old_data.as_ref())?;
+            Some((key(old)?, None))
+        }
+        _ => None,
+    }
+}
+
+/// Replace the unchanged TOAST values of `row` with the values of `previous`, the row the
+/// change is folded into.
+fn merge_unchanged(mut row: Tuples, previous: &Tuples) -> Tuples {
+    for (data, previous) in row.0.iter_mut().zip(&previous.0) {
+        if *data == TupleData::UnchangedToast {
+            *data = previous.clone();
+        }
+    }
+
+    row
+}
+
+/// The xid of a message in a streamed transaction, i.e. of its (sub)transaction.
+fn change_xid(change: &LogicalReplication) -> Option<u32> {
+    match change {
+        LogicalReplication::Message(message) => message.xid,
+        LogicalReplication::Relation(relation) => relation.xid,
+        LogicalReplication::Type(ty) => ty.xid,
+        LogicalReplication::Insert(insert) => insert.xid,
+        LogicalReplication::Update(update) => update.xid,
+        LogicalReplication::Delete(delete) => delete.xid,
+        LogicalReplication::Truncate(truncate) => truncate.xid,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx_core::bytes::Bytes;
+
+    use super::super::{
+        Begin, Column, Commit, CommitFlags, Message, Relation, ReplicaIdentity, StreamAbort,
+        StreamCommit, StreamStart, Truncate,
+    };
+    use super::*;
+    use crate::types::Oid;
+    use std::time::UNIX_EPOCH;
+
+    fn message(xid: Option<u32>, lsn: u64) -> LogicalReplication {
+        LogicalReplication::Message(Message {
+            xid,
+            transactional: true,
+            lsn: PgLsn::from(lsn),
+            prefix: "test".into(),
+            content: Bytes::new(),
+        })
+    }
+
+    #[test]
+    fn it_groups_changes_by_transaction() {
+        let mut buffer = TransactionBuffer::default();
+
+        assert!(buffer
+            .push(
+                LogicalReplication::Begin(Begin {
+                    final_lsn: PgLsn::from(0x200),
+                    commit_timestamp: 1_000_000,
+                    xid: 742,
+                }),
+                UNIX_EPOCH
+            )
+            .is_none());
+        assert!(buffer.push(message(None, 0x100), UNIX_EPOCH).is_none());
+        assert!(buffer.push(message(None, 0x180), UNIX_EPOCH).is_none());
+
+        let transaction = buffer
+            .push(
+                LogicalReplication::Commit(Commit {
+                    flags: CommitFlags(0),
+                    commit_lsn: PgLsn::from(0x200),
+                    end_lsn: PgLsn::from(0x230),
+                    commit_timestamp: 1_000_000,
+                }),
+                UNIX_EPOCH,
+            )
+            .unwrap();
+
+        assert_eq!(transaction.xid, 742);
+        assert_eq!(transaction.end_lsn, PgLsn::from(0x230));
+        assert_eq!(transaction.changes.len(), 2);
+        assert!(!transaction.streamed);
+
+        // the xid from `Begin` is attached to the changes
+        assert!(transaction
+            .changes
+            .iter()
+            .all(|change| change_xid(change) == Some(742)));
+    }
+
+    #[test]
+    fn it_reassembles_streamed_transactions() {
+        let mut buffer = TransactionBuffer::default();
+
+        for (subxid, lsn) in [(800, 0x100), (801, 0x180), (800, 0x200)] {
+            buffer.push(
+                LogicalReplication::StreamStart(StreamStart {
+                    xid: 800,
+                    first_segment: lsn == 0x100,
+                }),
+                UNIX_EPOCH,
+            );
+            buffer.push(message(Some(subxid), lsn), UNIX_EPOCH);
+            buffer.push(LogicalReplication::StreamStop, UNIX_EPOCH);
+        }
+
+        buffer.push(
+            LogicalReplication::StreamAbort(StreamAbort {
+                xid: 800,
+                subxid: 801,
+                abort_lsn: None,
+                abort_timestamp: None,
+            }),
+            UNIX_EPOCH,
+        );
+
+        let transaction = buffer
+            .push(
+                LogicalReplication::StreamCommit(StreamCommit {
+                    xid: 800,
+                    flags: CommitFlags(0),
+                    commit_lsn: PgLsn::from(0x280),
+                    end_lsn: PgLsn::from(0x2B0),
+                    commit_timestamp: 1_000_000,
+                }),
+                UNIX_EPOCH,
+            )
+            .unwrap();
+
+        assert_eq!(transaction.xid, 800);
+        assert_eq!(transaction.changes.len(), 2);
+        assert!(transaction.streamed);
+    }
+
+    #[test]
+    fn it_reassembles_interleaved_segments() {
+        let mut buffer = TransactionBuffer::default();
+
+        // a leftover of transaction 800 from before it was streamed again from the start
+        buffer.push(
+            LogicalReplication::StreamStart(StreamStart {
+                xid: 800,
+                first_segment: true,
+            }),
+            UNIX_EPOCH,
+        );
+        buffer.push(message(Some(800), 0x080), UNIX_EPOCH);
+        buffer.push(LogicalReplication::StreamStop, UNIX_EPOCH);
+
+        // 800 spans three segments, 900 two, interleaved with each other
+        for (xid, first_segment, lsn) in [
+            (800, true, 0x100),
+            (900, true, 0x110),
+            (800, false, 0x120),
+            (900, false, 0x130),
+            (800, false, 0x140),
+        ] {
+            buffer.push(
+                LogicalReplication::StreamStart(StreamStart { xid, first_segment }),
+                UNIX_EPOCH,
+            );
+            buffer.push(message(Some(xid), lsn), UNIX_EPOCH);
+            buffer.push(message(Some(xid), lsn + 8), UNIX_EPOCH);
+            buffer.push(LogicalReplication::StreamStop, UNIX_EPOCH);
+        }
+
+        let commit = |xid| {
+            LogicalReplication::StreamCommit(StreamCommit {
+                xid,
+                flags: CommitFlags(0),
+                commit_lsn: PgLsn::from(0x200),
+                end_lsn: PgLsn::from(0x230),
+                commit_timestamp: 1_000_000,
+            })
+        };
+        let lsns = |transaction: &ReplicatedTransaction| -> Vec<u64> {
+            (transaction.changes.iter())
+                .map(|change| match change {
+                    LogicalReplication::Message(message) => message.lsn.into(),
+                    _ => unreachable!(),
+                })
+                .collect()
+        };
+
+        let transaction = buffer.push(commit(900), UNIX_EPOCH).unwrap();
+        assert_eq!(lsns(&transaction), [0x110, 0x118, 0x130, 0x138]);
+
+        let transaction = buffer.push(commit(800), UNIX_EPOCH).unwrap();
+        assert_eq!(
+            lsns(&transaction),
+            [0x100, 0x108, 0x120, 0x128, 0x140, 0x148]
+        );
+
+        assert!(buffer.streamed.is_empty());
+    }
+
+    #[test]
+    fn it_keeps_relations_of_streamed_segments() {
+        let relation = |xid| {
+            LogicalReplication::Relation(Relation {
+                xid: Some(xid),
+                relation_id: Oid(16385),
+                namespace: "public".into(),
+                name: "users".into(),
+                replica_identity: ReplicaIdentity::Default,
+                columns: Vec::new(),
+            })
+        };
+
+        let mut buffer = TransactionBuffer::default();
+
+        // the Relation is sent again in the first segment of each streamed transaction
+        for xid in [800, 801] {
+            buffer.push(
+                LogicalReplication::StreamStart(StreamStart {
+                    xid,
+                    first_segment: true,
+                }),
+                UNIX_EPOCH,
+            );
+            buffer.push(relation(xid), UNIX_EPOCH);
+            buffer.push(message(Some(xid), 0x100), UNIX_EPOCH);
+            buffer.push(LogicalReplication::StreamStop, UNIX_EPOCH);
+        }
+
+        // the Relation carries the top-level xid, so it survives the abort of a subtransaction
+        buffer.push(
+            LogicalReplication::StreamAbort(StreamAbort {
+                xid: 800,
+                subxid: 802,
+                abort_lsn: None,
+                abort_timestamp: None,
+            }),
+            UNIX_EPOCH,
+        );
+
+        for xid in [801, 800] {
+            let transaction = buffer
+                .push(
+                    LogicalReplication::StreamCommit(StreamCommit {
+                        xid,
+                        flags: CommitFlags(0),
+                        commit_lsn: PgLsn::from(0x280),
+                        end_lsn: PgLsn::from(0x2B0),
+                        commit_timestamp: 1_000_000,
+                    }),
+                    UNIX_EPOCH,
+                )
+                .unwrap();
+
+            assert_eq!(transaction.xid, xid);
+            assert!(matches!(
+                transaction.changes[0],
+                LogicalReplication::Relation(Relation { xid: Some(x), .. }) if x == xid
+            ));
+            assert_eq!(transaction.changes.len(), 2);
+        }
+    }
+
+    #[test]
+    fn it_reports_pending_transactions() {
+        let mut buffer = TransactionBuffer::default();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let content = |xid, len| {
+            LogicalReplication::Message(Message {
+                xid: Some(xid),
+                transactional: true,
+                lsn: PgLsn::from(0x100),
+                prefix: "test".into(),
+                content: Bytes::from(vec![0; len]),
+            })
+        };
+
+        for (xid, first_segment, len, secs) in
+            [(800, true, 96, 1), (900, true, 12, 2), (800, false, 996, 3)]
+        {
+            buffer.push(
+                LogicalReplication::StreamStart(StreamStart { xid, first_segment }),
+                at(secs),
+            );
+            buffer.push(content(xid, len), at(secs));
+            buffer.push(LogicalReplication::StreamStop, at(secs));
+        }
+
+        buffer.push(
+            LogicalReplication::Begin(Begin {
+                final_lsn: PgLsn::from(0x200),
+                commit_timestamp: 1_000_000,
+                xid: 1000,
+            }),
+            at(4),
+        );
+        buffer.push(message(None, 0x180), at(5));
+
+        let pending = buffer.pending();
+        assert_eq!(
+            pending,
+            [
+                PendingTxInfo {
+                    xid: 800,
+                    streamed: true,
+                    buffered_bytes: 1100,
+                    message_count: 2,
+                    started_at: at(1),
+                },
+                PendingTxInfo {
+                    xid: 900,
+                    streamed: true,
+                    buffered_bytes: 16,
+                    message_count: 1,
+                    started_at: at(2),
+                },
+                PendingTxInfo {
+                    xid: 1000,
+                    streamed: false,
+                    buffered_bytes: 4,
+                    message_count: 1,
+                    started_at: at(4),
+                },
+            ]
+        );
+
+        // an aborted subtransaction is no longer buffered
+        buffer.push(
+            LogicalReplication::StreamStart(StreamStart {
+                xid: 800,
+                first_segment: false,
+            }),
+            at(6),
+        );
+        buffer.push(content(801, 46), at(6));
+        buffer.push(LogicalReplication::StreamStop, at(6));
+        assert_eq!(buffer.pending()[0].buffered_bytes, 1150);
+
+        buffer.push(
+            LogicalReplication::StreamAbort(StreamAbort {
+                xid: 800,
+                subxid: 801,
+                abort_lsn: None,
+                abort_timestamp: None,
+            }),
+            at(7),
+        );
+        assert_eq!(buffer.pending()[0].buffered_bytes, 1100);
+
+        // committed and aborted transactions are not pending anymore
+        buffer.push(
+            LogicalReplication::StreamAbort(StreamAbort {
+                xid: 900,
+                subxid: 900,
+                abort_lsn: None,
+                abort_timestamp: None,
+            }),
+            at(8),
+        );
+        buffer.push(
+            LogicalReplication::Commit(Commit {
+                flags: CommitFlags(0),
+                commit_lsn: PgLsn::from(0x200),
+                end_lsn: PgLsn::from(0x230),
+                commit_timestamp: 1_000_000,
+            }),
+            at(9),
+        );
+        let xids: Vec<_> = buffer.pending().iter().map(|info| info.xid).collect();
+        assert_eq!(xids, [800]);
+    }
+
+    #[test]
+    fn it_measures_lag_since_commit() {
+        let transaction = ReplicatedTransaction {
+            xid: 742,
+            commit_lsn: PgLsn::from(0x200),
+            end_lsn: PgLsn::from(0x230),
+            commit_timestamp: 1_000_000,
+            streamed: false,
+            changes: Vec::new(),
+        };
+
+        let commit_time = transaction.commit_time();
+        assert_eq!(commit_time, timestamp_to_system_time(1_000_000));
+
+        assert_eq!(
+            transaction.lag(commit_time + Duration::from_millis(250)),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            transaction.lag(commit_time - Duration::from_secs(1)),
+            Duration::ZERO
+        );
+    }
+
+    fn users() -> Relation {
+        let column = |flags, name: &str| Column {
+            flags,
+            name: name.into(),
+            type_id: Oid(23),
+            type_modifier: -1,
+        };
+
+        Relation {
+            xid: None,
+            relation_id: Oid(16385),
+            namespace: "public".into(),
+            name: "users".into(),
+            replica_identity: ReplicaIdentity::Default,
+            columns: vec![column(1, "id"), column(0, "name")],
+        }
+    }
+
+    fn row(id: &'static str, name: Option<&'static str>) -> Tuples {
+        let name = match name {
+            Some(name) => TupleData::Text(Bytes::from_static(name.as_bytes())),
+            None => TupleData::UnchangedToast,
+        };
+
+        Tuples(vec![
+            TupleData::Text(Bytes::from_static(id.as_bytes())),
+            name,
+        ])
+    }
+
+    fn insert(id: &'static str, name: &'static str) -> LogicalReplication {
+        LogicalReplication::Insert(Insert {
+            xid: None,
+            relation_id: Oid(16385),
+            new_data: row(id, Some(name)),
+        })
+    }
+
+    fn update(
+        old_id: Option<&'static str>,
+        id: &'static str,
+        name: Option<&'static str>,
+    ) -> LogicalReplication {
+        LogicalReplication::Update(Update {
+            xid: None,
+            relation_id: Oid(16385),
+            key_data: old_id.map(|old_id| {
+                Tuples(vec![
+                    TupleData::Text(Bytes::from_static(old_id.as_bytes())),
+                    TupleData::Null,
+                ])
+            }),
+            old_data: None,
+            new_data: row(id, name),
+        })
+    }
+
+    fn delete(id: &'static str) -> LogicalReplication {
+        LogicalReplication::Delete(Delete {
+            xid: None,
+            relation_id: Oid(16385),
+            key_data: Some(Tuples(vec![
+                TupleData::Text(Bytes::from_static(id.as_bytes())),
+                TupleData::Null,
+            ])),
+            old_data: None,
+        })
+    }
+
+    fn coalesced(changes: Vec<LogicalReplication>) -> Vec<LogicalReplication> {
+        let relation = users();
+        coalesce_changes(changes, |relation_id| {
+            (relation_id == relation.relation_id).then_some(&relation)
+        })
+    }
+
+    /// A short description of each change, e.g. `I 1 a` or `U 1>2 b`.
+    fn describe(changes: &[LogicalReplication]) -> Vec<String> {
+        let text = |data: &TupleData| match data {
+            TupleData::Text(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+            data => format!("{data:?}"),
+        };
+
+        (changes.iter())
+            .map(|change| match change {
+                LogicalReplication::Insert(insert) => {
+                    format!(
+                        "I {} {}",
+                        text(&insert.new_data[0]),
+                        text(&insert.new_data[1])
+                    )
+                }
+                LogicalReplication::Update(update) => match &update.key_data {
+                    Some(key) => format!(
+                        "U {}>{} {}",
+                        text(&key[0]),
+                        text(&update.new_data[0]),
+                        text(&update.new_data[1])
+                    ),
+                    None => format!(
+                        "U {} {}",
+                        text(&update.new_data[0]),
+                        text(&update.new_data[1])
+                    ),
+                },
+                LogicalReplication::Delete(delete) => {
+                    format!("D {}", text(&delete.key_data.as_ref().unwrap()[0]))
+                }
+                LogicalReplication::Relation(_) => "R".into(),
+                LogicalReplication::Truncate(_) => "T".into(),
+                _ => "M".into(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_coalesces_changes_of_a_row() {
+        // insert then updates: an insert of the final row, in place of the last update
+        assert_eq!(
+            describe(&coalesced(vec![
+                insert("1", "a"),
+                insert("2", "x"),
+                update(None, "1", "b".into()),
+                update(Some("1"), "3", None),
+            ])),
+            ["I 2 x", "I 3 b"]
+        );
+
+        // insert then delete: nothing, also after updates
+        assert_eq!(
+            describe(&coalesced(vec![
+                insert("1", "a"),
+                update(None, "1", "b".into()),
+                message(None, 0x100),
+                delete("1"),
+            ])),
+            ["M"]
+        );
+
+        // updates: one update from the first key to the final row
+        assert_eq!(
+            describe(&coalesced(vec![
+                update(None, "1", "a".into()),
+                update(Some("1"), "2", None),
+                update(None, "2", "c".into()),
+            ])),
+            ["U 1>2 c"]
+        );
+
+        // updates then delete: a delete of the key known downstream
+        assert_eq!(
+            describe(&coalesced(vec![
+                update(Some("1"), "2", "a".into()),
+                update(None, "2", "b".into()),
+                delete("2"),
+            ])),
+            ["D 1"]
+        );
+
+        // delete then insert: both are kept, and the insert is folded into from there
+        assert_eq!(
+            describe(&coalesced(vec![
+                delete("1"),
+                insert("1", "a"),
+                update(None, "1", "b".into()),
+            ])),
+            ["D 1", "I 1 b"]
+        );
+    }
+
+    #[test]
+    fn it_starts_coalescing_over_when_the_relation_changes() {
+        // the changes before the Relation message are not folded, as the relation they were
+        // made with is not known, nor are they folded into by later changes
+        assert_eq!(
+            describe(&coalesced(vec![
+                insert("1", "a"),
+                update(None, "1", "b".into()),
+                LogicalReplication::Relation(users()),
+                update(None, "1", "c".into()),
+                update(None, "1", "d".into()),
+            ])),
+            ["I 1 a", "U 1 b", "R", "U 1 d"]
+        );
+
+        assert_eq!(
+            describe(&coalesced(vec![
+                insert("1", "a"),
+                LogicalReplication::Truncate(Truncate {
+                    xid: None,
+                    options: 0,
+                    relation_ids: vec![Oid(16385)],
+                }),
+                insert("1", "b"),
+                update(None, "1", "c".into()),
+            ])),
+            ["I 1 a", "T", "I 1 c"]
+        );
+
+        // unknown relations are not folded
+        let changes = coalesce_changes(vec![insert("1", "a"), delete("1")], |_| None);
+        assert_eq!(describe(&changes), ["I 1 a", "D 1"]);
+    }
+
+    #[test]
+    fn it_validates_transactions() {
+        let transaction = |streamed, changes: Vec<LogicalReplication>| ReplicatedTransaction {
+            xid: 742,
+            commit_lsn: PgLsn::from(0x200),
+            end_lsn: PgLsn::from(0x230),
+            commit_timestamp: 0,
+            streamed,
+            changes: changes
+                .into_iter()
+                .map(|change| with_xid(change, 742))
+                .collect(),
+        };
+        let relation = users();
+        let known = |relation_id| (relation_id == relation.relation_id).then_some(&relation);
+
+        // the relation is known either from a `Relation` message or from `lookup`
+        let valid = transaction(false, vec![insert("1", "a"), message(None, 0x180)]);
+        assert_eq!(valid.validate(known), Ok(()));
+        assert_eq!(
+            valid.validate(|_| None),
+            Err(TransactionViolation::UnknownRelation {
+                index: 0,
+                relation_id: Oid(16385)
+            })
+        );
+
+        let valid = transaction(
+            false,
+            vec![LogicalReplication::Relation(users()), delete("1")],
+        );
+        assert_eq!(valid.validate(|_| None), Ok(()));
+
+        let mut invalid = transaction(false, vec![insert("1", "a"), message(None, 0x200)]);
+        assert_eq!(
+            invalid.validate(known),
+            Err(TransactionViolation::MessageAfterCommit {
+                index: 1,
+                lsn: PgLsn::from(0x200),
+                commit_lsn: PgLsn::from(0x200),
+            })
+        );
+
+        invalid.changes[1] = LogicalReplication::Begin(Begin {
+            final_lsn: PgLsn::from(0x200),
+            commit_timestamp: 0,
+            xid: 742,
+        });
+        assert_eq!(
+            invalid.validate(known),
+            Err(TransactionViolation::Boundary {
+                index: 1,
+                kind: "Begin"
+            })
+        );
+
+        invalid.changes[1] = message(Some(743), 0x180);
+        assert_eq!(
+            invalid.validate(known),
+            Err(TransactionViolation::Xid {
+                index: 1,
+                found: Some(743)
+            })
+        );
+
+        // the changes of a streamed transaction may carry the xid of a subtransaction
+        invalid.streamed = true;
+        assert_eq!(invalid.validate(known), Ok(()));
+
+        invalid.end_lsn = invalid.commit_lsn;
+        assert_eq!(
+            invalid.validate(known),
+            Err(TransactionViolation::CommitLsn {
+                commit_lsn: PgLsn::from(0x200),
+                end_lsn: PgLsn::from(0x200),
+            })
+        );
+    }
+}
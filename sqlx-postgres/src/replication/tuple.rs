@@ -0,0 +1,1305 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use sqlx_core::bytes::{Buf, Bytes};
+
+use sqlx_core::decode::Decode;
+use sqlx_core::error::mismatched_types;
+use sqlx_core::types::Type;
+
+use crate::error::Error;
+use crate::ext::ustr::UStr;
+use crate::io::ProtocolDecode;
+use crate::type_info::{PgCustomType, PgType, PgTypeKind};
+use crate::types::Oid;
+use crate::value::{PgValueFormat, PgValueRef};
+use crate::{PgTypeInfo, Postgres};
+
+/// The column values of a single row, as sent by `pgoutput` in `Insert`, `Update` and
+/// `Delete` messages.
+///
+/// Columns are in the order of the columns of the matching [`Relation`][super::Relation].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tuples(pub Vec<TupleData>);
+
+/// The value of a single column in [`Tuples`].
+///
+/// Text and binary values reference the buffer the message was read into, so holding on to a
+/// value keeps that whole buffer alive, which can be much larger than the value. Smaller
+/// values are copied out of the buffer instead if a
+/// [copy threshold][super::LogicalReplicationStream::set_copy_threshold] is set.
+///
+/// # Decoding
+///
+/// [`try_decode()`][Self::try_decode] decodes values with the same [`Decode`] implementations
+/// as query results. Values of most types decode from both the text and the binary format,
+/// which is enabled with [`PgOutputOptions::binary()`][super::PgOutputOptions::binary]; the
+/// formats of some types need care:
+///
+/// |Type|Decodes into|Notes|
+/// |----|------------|-----|
+/// | `bool` | `bool` | `t` or `f` in the text format, a single `0` or `1` byte in the binary format; any other value is an error. |
+/// | `bytea` | `Vec<u8>` | The text format in both the `hex` and the `escape` `bytea_output`. The binary format is the raw bytes, half the size of `hex`. |
+/// | `numeric` | `Decimal`, `BigDecimal` | Exactly, with the `rust_decimal` or `bigdecimal` feature. |
+/// | `uuid`, `inet`, `cidr`, `macaddr` | `Uuid`, `IpNetwork` or `IpAddr`, `MacAddress` | With the `uuid`, `ipnetwork` and `mac_address` features. |
+/// | `bit(n)`, `varbit` | `BitVec` | With the `bit-vec` feature. The bits are counted, so a `bit(8)` keeps its leading zeros; its declared length is the [modifier][super::Column::modifier] of the column. |
+/// | `timestamp`, `timestamptz`, `date`, `time` | the types of `chrono` or `time` | With the `chrono` or `time` feature; `infinity` and other values out of the range of the type are an error. |
+/// | `json`, `jsonb` | `serde_json::Value`, `Json<T>` | With the `json` feature, without decoding them as a string first. |
+/// | ranges | [`PgRange<T>`][crate::types::PgRange] | Multiranges are not supported; decode their text format into a `String`. |
+/// | `interval` | [`PgInterval`][crate::types::PgInterval] | The text format only in the default `postgres` `IntervalStyle`. |
+/// | `money` | [`PgMoney`][crate::types::PgMoney] | The binary format only, as the text format depends on the `lc_monetary` of the server, e.g. `$1,234.56`. |
+/// | arrays | `Vec<T>` | Arrays of more than one dimension, or with a lower bound other than 1, are an error; decode them into a `String` from the text format. |
+/// | custom types | `#[derive(sqlx::Type)]` types | Not checked against the column type. The fields of a composite type in the binary format must be of built-in types. |
+/// | enums | `String` | The label; check it with [`EnumType::decode_label()`][super::EnumType::decode_label]. |
+/// | `oid` | [`Oid`] | The other system types, `xid`, `xid8`, `cid` and `tid`, decode with [`SystemValue::decode()`][super::SystemValue::decode]. |
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TupleData {
+    /// The value is `NULL`.
+    Null,
+    /// The value is a TOASTed value that was not changed; the actual value is not sent.
+    UnchangedToast,
+    /// The value in the text format.
+    Text(Bytes),
+    /// The value in the binary format; only sent when the `binary` option is enabled.
+    Binary(Bytes),
+}
+
+/// How [`TupleData::try_decode_with()`] handles a value whose type OID can't be resolved to a
+/// built-in type, e.g. the type of an extension, or a type that was dropped since.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownTypePolicy {
+    /// Return an error. This is the default, so that a value is never silently handed out
+    /// undecoded, nor decoded without checking its type as [`try_decode()`] does.
+    ///
+    /// [`try_decode()`]: TupleData::try_decode
+    #[default]
+    Strict,
+    /// Return the value as received, in [`MaybeDecoded::Raw`].
+    RawFallback,
+}
+
+/// A value decoded by [`TupleData::try_decode_with()`], or the value as received if its type
+/// is not known and [`UnknownTypePolicy::RawFallback`] is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaybeDecoded<'r, T> {
+    /// The decoded value.
+    Value(T),
+    /// The value of a column of an unknown type, as received.
+    Raw(&'r TupleData),
+}
+
+impl<'r, T> MaybeDecoded<'r, T> {
+    /// Returns the decoded value, or `None` for a raw value.
+    pub fn value(self) -> Option<T> {
+        match self {
+            MaybeDecoded::Value(value) => Some(value),
+            MaybeDecoded::Raw(_) => None,
+        }
+    }
+
+    /// Returns the value as received, or `None` for a decoded value.
+    pub fn raw(&self) -> Option<&'r TupleData> {
+        match self {
+            MaybeDecoded::Value(_) => None,
+            MaybeDecoded::Raw(data) => Some(data),
+        }
+    }
+}
+
+impl Tuples {
+    /// Returns the value of the column at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&TupleData> {
+        self.0.get(index)
+    }
+}
+
+impl Deref for Tuples {
+    type Target = [TupleData];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl IntoIterator for Tuples {
+    type Item = TupleData;
+    type IntoIter = std::vec::IntoIter<TupleData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Tuples {
+    type Item = &'a TupleData;
+    type IntoIter = std::slice::Iter<'a, TupleData>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl TupleData {
+    /// Returns `true` if the value is `NULL`.
+    pub fn is_null(&self) -> bool {
+        matches!(self, TupleData::Null)
+    }
+
+    /// Returns the raw bytes of a text or binary value.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            TupleData::Text(bytes) | TupleData::Binary(bytes) => Some(bytes),
+            TupleData::Null | TupleData::UnchangedToast => None,
+        }
+    }
+
+    /// Returns the value as a string slice if it is a text value in valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TupleData::Text(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns a text value as an owned string, or `None` for `NULL`.
+    ///
+    /// Returns an error for an invalid UTF-8 value, for [`TupleData::UnchangedToast`], whose
+    /// value is not known, and for a value in the binary format. Text values are sent in the
+    /// [encoding of the database][super::PgReplicationConnection::server_encoding], so the
+    /// values of a database with another encoding than `UTF8` may not be valid UTF-8.
+    pub fn as_opt_string(&self) -> Result<Option<String>, Error> {
+        match self {
+            TupleData::Null => Ok(None),
+            TupleData::UnchangedToast => {
+                Err(Error::Decode("unchanged TOAST value was not sent".into()))
+            }
+            TupleData::Text(bytes) => std::str::from_utf8(bytes)
+                .map(|value| Some(value.to_owned()))
+                .map_err(|error| Error::Decode(error.into())),
+            TupleData::Binary(_) => Err(Error::Decode(
+                "value is in the binary format; use `try_decode()`".into(),
+            )),
+        }
+    }
+
+    /// Decode the value as `T`, given the OID of the data type of its column
+    /// ([`Column::type_id`][super::Column::type_id]).
+    ///
+    /// The value is decoded with the same [`Decode`] implementations as query results, from
+    /// the text or binary format it was sent in; the [`TupleData`] docs list what the values of
+    /// some types decode into, and from which format. Values of types that are not built in are
+    /// decoded without checking that `T` matches the column type; use
+    /// [`try_decode_custom()`][Self::try_decode_custom] to check the type by name. Decoding
+    /// [`TupleData::UnchangedToast`] is an error, as the value is not known.
+    pub fn try_decode<'r, T>(&'r self, type_id: Oid) -> Result<T, Error>
+    where
+        T: Decode<'r, Postgres> + Type<Postgres>,
+    {
+        if is_array::<T>() {
+            self.check_array_text()?;
+        }
+
+        match (self, PgType::try_from_oid(type_id)) {
+            // the text format of `money` is formatted with `lc_monetary`, e.g. `$1,234.56`
+            (TupleData::Text(_), Some(PgType::Money)) => Err(Error::Decode(
+                "money values in the text format depend on lc_monetary; \
+                 enable the binary format with `PgOutputOptions::binary()`"
+                    .into(),
+            )),
+
+            (_, Some(ty)) => self.decode_checked(PgTypeInfo(ty)),
+
+            // a custom type can't be resolved without querying the catalog, so its compatibility
+            // is not checked; the binary format of a composite type is that of a record, whose
+            // fields are prefixed with their type, and likewise for arrays of composite types
+            (_, None) => {
+                let type_info = if is_array::<T>() {
+                    PgTypeInfo::RECORD_ARRAY
+                } else {
+                    PgTypeInfo::RECORD
+                };
+
+                T::decode(self.value_ref(type_info)?).map_err(Error::Decode)
+            }
+        }
+    }
+
+    /// Decode the value as `T` like [`try_decode()`][Self::try_decode], handling a `type_id`
+    /// that is not a built-in type according to `policy` instead of decoding the value without
+    /// checking its type.
+    ///
+    /// With [`UnknownTypePolicy::RawFallback`], a consumer that mostly wants typed values can
+    /// still handle the bytes of the columns of exotic types, e.g. to forward them as they are.
+    /// Use [`try_decode_custom()`][Self::try_decode_custom] to decode the values of custom
+    /// types whose [`Type`][super::Type] message was received.
+    pub fn try_decode_with<'r, T>(
+        &'r self,
+        type_id: Oid,
+        policy: UnknownTypePolicy,
+    ) -> Result<MaybeDecoded<'r, T>, Error>
+    where
+        T: Decode<'r, Postgres> + Type<Postgres>,
+    {
+        if PgTypeInfo::try_from_oid(type_id).is_some() {
+            return self.try_decode(type_id).map(MaybeDecoded::Value);
+        }
+
+        match policy {
+            UnknownTypePolicy::Strict => Err(Error::Decode(
+                format!("unknown type OID {}; use `try_decode_custom()`", type_id.0).into(),
+            )),
+            UnknownTypePolicy::RawFallback => Ok(MaybeDecoded::Raw(self)),
+        }
+    }
+
+    /// Decode the value of a column of a type that is not built in, given the [`Type`] message
+    /// the server sent for it ([`LogicalReplicationStream::custom_type()`]).
+    ///
+    /// Unlike [`try_decode()`][Self::try_decode], this checks that `T` matches the column type
+    /// by the name of the type, which allows decoding the types of extensions, whose OIDs differ
+    /// between databases, e.g. `citext` into [`PgCiText`][crate::types::PgCiText] or `String`
+    /// and `hstore` into [`PgHstore`][crate::types::PgHstore]. `hstore` is only decoded from
+    /// the binary format, so streaming must be started with
+    /// [`PgOutputOptions::binary(true)`][super::PgOutputOptions::binary]. With the `geo`
+    /// feature, PostGIS `geometry` and `geography` values decode into
+    /// [`Ewkb`][super::geo::Ewkb] from both formats.
+    ///
+    /// Arrays of custom types, e.g. `Vec<Point>` for a `point_type[]` column, are checked by
+    /// the name of their element type, which is the name of the array type without its `_`
+    /// prefix; their elements are decoded like with [`try_decode()`][Self::try_decode].
+    ///
+    /// [`Type`]: super::Type
+    /// [`LogicalReplicationStream::custom_type()`]: super::LogicalReplicationStream::custom_type
+    pub fn try_decode_custom<'r, T>(&'r self, ty: &super::Type) -> Result<T, Error>
+    where
+        T: Decode<'r, Postgres> + Type<Postgres>,
+    {
+        // an array type is named after the type of its elements, with a `_` prefix
+        let element = ty.name.strip_prefix('_').filter(|_| is_array::<T>());
+
+        let Some(element) = element else {
+            return self.decode_checked(custom_type_info(ty.type_id, &ty.name, PgTypeKind::Simple));
+        };
+
+        self.check_array_text()?;
+
+        // the OID of the element type is not sent
+        let element = custom_type_info(Oid(0), element, PgTypeKind::Simple);
+        let type_info = custom_type_info(ty.type_id, &ty.name, PgTypeKind::Array(element));
+
+        if !self.is_null() && !T::compatible(&type_info) {
+            return Err(Error::Decode(mismatched_types::<Postgres, T>(&type_info)));
+        }
+
+        // the elements are decoded like values of unknown types, i.e. composite values from
+        // their binary format as records
+        T::decode(self.value_ref(PgTypeInfo::RECORD_ARRAY)?).map_err(Error::Decode)
+    }
+
+    /// Return an error for the text format of an array that `Vec<T>` would decode wrongly
+    /// instead of failing, e.g. into a `Vec<String>` of parts of the nested arrays.
+    fn check_array_text(&self) -> Result<(), Error> {
+        let TupleData::Text(text) = self else {
+            return Ok(());
+        };
+
+        // `[0:1]={1,2}` has explicit bounds, `{{1,2},{3,4}}` has two dimensions
+        if text.starts_with(b"[") || text.starts_with(b"{{") {
+            return Err(Error::Decode(
+                "only arrays of one dimension with a lower bound of 1 can be decoded; \
+                 decode the value as a `String` instead"
+                    .into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn decode_checked<'r, T>(&'r self, type_info: PgTypeInfo) -> Result<T, Error>
+    where
+        T: Decode<'r, Postgres> + Type<Postgres>,
+    {
+        let value = self.value_ref(type_info)?;
+
+        if value.value.is_some() && !T::compatible(&value.type_info) {
+            return Err(Error::Decode(mismatched_types::<Postgres, T>(
+                &value.type_info,
+            )));
+        }
+
+        T::decode(value).map_err(Error::Decode)
+    }
+
+    fn value_ref(&self, type_info: PgTypeInfo) -> Result<PgValueRef<'_>, Error> {
+        let (value, format) = match self {
+            TupleData::Null => (None, PgValueFormat::Text),
+            TupleData::UnchangedToast => {
+                return Err(Error::Decode("unchanged TOAST value was not sent".into()));
+            }
+            TupleData::Text(bytes) => (Some(&bytes[..]), PgValueFormat::Text),
+            TupleData::Binary(bytes) => (Some(&bytes[..]), PgValueFormat::Binary),
+        };
+
+        Ok(PgValueRef {
+            value,
+            row: None,
+            type_info,
+            format,
+        })
+    }
+
+    /// Decode the value of the column at `index`, which is only used for errors.
+    fn decode(buf: &mut Bytes, index: i16, copy_threshold: usize) -> Result<Self, Error> {
+        if !buf.has_remaining() {
+            return Err(err_protocol!(
+                "unexpected end of tuple data at column {}",
+                index
+            ));
+        }
+
+        match buf.get_u8() {
+            b'n' => Ok(TupleData::Null),
+            b'u' => Ok(TupleData::UnchangedToast),
+            b't' => Ok(TupleData::Text(decode_value(buf, index, copy_threshold)?)),
+            b'b' => Ok(TupleData::Binary(decode_value(buf, index, copy_threshold)?)),
+            marker => Err(err_protocol!(
+                "unknown tuple data marker 0x{:02X} at column {}",
+                marker,
+                index
+            )),
+        }
+    }
+}
+
+/// Returns `true` if `T` decodes arrays, e.g. `Vec<T>`.
+fn is_array<T: Type<Postgres>>() -> bool {
+    T::type_info().0.try_array_element().is_some()
+}
+
+/// Split the binary format of a `bit` or `varbit` value into its number of bits and the bytes
+/// holding them, checking that they match.
+#[cfg(feature = "json")]
+fn binary_bits(value: &[u8]) -> Result<(usize, &[u8]), Error> {
+    let invalid = || Error::Decode(format!("invalid binary bit string {value:?}").into());
+
+    let (len, bits) = value.split_first_chunk::<4>().ok_or_else(invalid)?;
+    let len = usize::try_from(i32::from_be_bytes(*len)).map_err(|_| invalid())?;
+
+    if bits.len() != len.div_ceil(8) {
+        return Err(invalid());
+    }
+
+    Ok((len, bits))
+}
+
+/// The text format of the binary `bit` or `varbit` value `value`, e.g. `101`.
+#[cfg(feature = "json")]
+pub(super) fn bit_string(value: &[u8]) -> Result<String, Error> {
+    let (len, bits) = binary_bits(value)?;
+
+    Ok((0..len)
+        .map(|bit| match bits[bit / 8] & (0x80 >> (bit % 8)) {
+            0 => '0',
+            _ => '1',
+        })
+        .collect())
+}
+
+fn custom_type_info(oid: Oid, name: &str, kind: PgTypeKind) -> PgTypeInfo {
+    PgTypeInfo(PgType::Custom(Arc::new(PgCustomType {
+        oid,
+        name: UStr::new(name),
+        kind,
+    })))
+}
+
+fn decode_value(buf: &mut Bytes, index: i16, copy_threshold: usize) -> Result<Bytes, Error> {
+    if buf.remaining() < 4 {
+        return Err(err_protocol!(
+            "unexpected end of tuple data at column {}",
+            index
+        ));
+    }
+
+    let len = buf.get_i32();
+
+    // other parts of the protocol use a length of -1 for `NULL`, but `pgoutput` sends `'n'`
+    let len = usize::try_from(len)
+        .map_err(|_| err_protocol!("negative tuple value length {} at column {}", len, index))?;
+
+    if buf.remaining() < len {
+        return Err(err_protocol!(
+            "tuple value length {} at column {} exceeds remaining {} bytes",
+            len,
+            index,
+            buf.remaining()
+        ));
+    }
+
+    let value = buf.split_to(len);
+
+    // don't keep the whole buffer alive for a small value
+    if len <= copy_threshold {
+        return Ok(Bytes::copy_from_slice(&value));
+    }
+
+    Ok(value)
+}
+
+impl ProtocolDecode<'_> for Tuples {
+    fn decode_with(mut buf: Bytes, _: ()) -> Result<Self, Error> {
+        Self::decode_from(&mut buf, 0)
+    }
+}
+
+impl Tuples {
+    /// Decode the `TupleData` structure at the front of `buf`, advancing it past the end of the
+    /// structure. Values of at most `copy_threshold` bytes are copied out of `buf`.
+    pub(crate) fn decode_from(buf: &mut Bytes, copy_threshold: usize) -> Result<Self, Error> {
+        if buf.remaining() < 2 {
+            return Err(err_protocol!("unexpected end of tuple data"));
+        }
+
+        let num_columns = buf.get_i16();
+        let mut columns = Vec::with_capacity(usize::try_from(num_columns).unwrap_or(0));
+
+        for index in 0..num_columns {
+            columns.push(TupleData::decode(buf, index, copy_threshold)?);
+        }
+
+        Ok(Tuples(columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_tuples() {
+        const DATA: &[u8] = b"\0\x04t\0\0\0\x011nub\0\0\0\x02\x12\x34";
+
+        let tuples = Tuples::decode(Bytes::from_static(DATA)).unwrap();
+
+        assert_eq!(tuples.len(), 4);
+        assert_eq!(tuples[0].as_str(), Some("1"));
+        assert!(tuples[1].is_null());
+        assert_eq!(tuples[2], TupleData::UnchangedToast);
+        assert_eq!(tuples[3].as_bytes(), Some(&[0x12, 0x34][..]));
+    }
+
+    #[test]
+    fn it_converts_text_values_to_strings() {
+        assert_eq!(
+            TupleData::Text(Bytes::from_static("héllo".as_bytes()))
+                .as_opt_string()
+                .unwrap(),
+            Some("héllo".to_owned())
+        );
+        assert_eq!(TupleData::Null.as_opt_string().unwrap(), None);
+
+        assert!(TupleData::Text(Bytes::from_static(b"\xE9t\xE9"))
+            .as_opt_string()
+            .is_err());
+        assert!(TupleData::UnchangedToast.as_opt_string().is_err());
+        assert!(TupleData::Binary(Bytes::from_static(b"foo"))
+            .as_opt_string()
+            .is_err());
+    }
+
+    #[test]
+    fn it_copies_small_values() {
+        const DATA: &[u8] = b"\0\x02t\0\0\0\x011t\0\0\0\x05large";
+
+        let buf = Bytes::from_static(DATA);
+        let range = buf.as_ptr_range();
+
+        let tuples = Tuples::decode_from(&mut buf.clone(), 4).unwrap();
+        let small = tuples[0].as_bytes().unwrap();
+        let large = tuples[1].as_bytes().unwrap();
+
+        assert_eq!(small, b"1");
+        assert!(!range.contains(&small.as_ptr()));
+
+        // larger values still reference the message
+        assert_eq!(large, b"large");
+        assert!(range.contains(&large.as_ptr()));
+
+        let tuples = Tuples::decode_from(&mut buf.clone(), 0).unwrap();
+        assert!(range.contains(&tuples[0].as_bytes().unwrap().as_ptr()));
+    }
+
+    #[test]
+    fn it_rejects_truncated_tuples() {
+        assert!(Tuples::decode(Bytes::from_static(b"\0")).is_err());
+        assert!(Tuples::decode(Bytes::from_static(b"\0\x02n")).is_err());
+        assert!(Tuples::decode(Bytes::from_static(b"\0\x01t\0\0\0\x05abc")).is_err());
+        assert!(Tuples::decode(Bytes::from_static(b"\0\x01x")).is_err());
+    }
+
+    #[test]
+    fn it_rejects_negative_lengths() {
+        for data in [
+            &b"\0\x01t\xff\xff\xff\xff"[..],
+            b"\0\x01b\xff\xff\xff\xfe\0\0",
+        ] {
+            let error = Tuples::decode(Bytes::copy_from_slice(data)).unwrap_err();
+
+            assert!(
+                matches!(error, Error::Protocol(ref message) if message.starts_with("negative tuple value length")),
+                "unexpected error: {error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_reports_the_column_of_invalid_data() {
+        let error = Tuples::decode(Bytes::from_static(b"\0\x02nz")).unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("unknown tuple data marker 0x7A at column 1"),
+            "unexpected error: {error}"
+        );
+    }
+
+    #[test]
+    fn it_decodes_typed_values() {
+        let text = TupleData::Text(Bytes::from_static(b"42"));
+        let binary = TupleData::Binary(Bytes::from_static(&[0, 0, 0, 42]));
+
+        assert_eq!(text.try_decode::<i32>(Oid(23)).unwrap(), 42);
+        assert_eq!(binary.try_decode::<i32>(Oid(23)).unwrap(), 42);
+        assert_eq!(text.try_decode::<String>(Oid(25)).unwrap(), "42");
+
+        assert_eq!(
+            TupleData::Null.try_decode::<Option<i32>>(Oid(23)).unwrap(),
+            None
+        );
+        assert!(TupleData::Null.try_decode::<i32>(Oid(23)).is_err());
+        assert!(TupleData::UnchangedToast
+            .try_decode::<Option<String>>(Oid(25))
+            .is_err());
+
+        // `int4` is not compatible with `String`
+        assert!(text.try_decode::<String>(Oid(23)).is_err());
+    }
+
+    #[test]
+    fn it_falls_back_to_raw_values_of_unknown_types() {
+        let text = TupleData::Text(Bytes::from_static(b"(1,2)"));
+        let int = TupleData::Text(Bytes::from_static(b"42"));
+
+        assert!(text
+            .try_decode_with::<String>(Oid(16400), UnknownTypePolicy::Strict)
+            .is_err());
+        assert_eq!(
+            text.try_decode_with::<String>(Oid(16400), UnknownTypePolicy::RawFallback)
+                .unwrap(),
+            MaybeDecoded::Raw(&text)
+        );
+
+        // known types are decoded with either policy, and still checked
+        for policy in [UnknownTypePolicy::Strict, UnknownTypePolicy::RawFallback] {
+            assert_eq!(
+                int.try_decode_with::<i32>(Oid(23), policy).unwrap().value(),
+                Some(42)
+            );
+            assert!(int.try_decode_with::<String>(Oid(23), policy).is_err());
+        }
+    }
+
+    // the text format with `bytea_output` set to `hex` (the default) and to `escape`
+    #[test]
+    fn it_decodes_bytea() {
+        const BYTES: &[u8] = b"\x00\x01\\\x7f\x80\xffA'";
+
+        for value in [
+            TupleData::Text(Bytes::from_static(b"\\x00015c7f80ff4127")),
+            TupleData::Text(Bytes::from_static(b"\\000\\001\\\\\\177\\200\\377A'")),
+            TupleData::Binary(Bytes::from_static(BYTES)),
+        ] {
+            assert_eq!(value.try_decode::<Vec<u8>>(Oid(17)).unwrap(), BYTES);
+            assert_eq!(value.try_decode::<[u8; 8]>(Oid(17)).unwrap(), BYTES);
+        }
+
+        // empty in either text format
+        let value = TupleData::Text(Bytes::from_static(b""));
+        assert!(value.try_decode::<Vec<u8>>(Oid(17)).unwrap().is_empty());
+        let value = TupleData::Text(Bytes::from_static(b"\\x"));
+        assert!(value.try_decode::<Vec<u8>>(Oid(17)).unwrap().is_empty());
+
+        // a lone backslash or an escape that is not a byte
+        for text in [&b"abc\\"[..], b"\\400", b"\\12"] {
+            let value = TupleData::Text(Bytes::from_static(text));
+            assert!(value.try_decode::<Vec<u8>>(Oid(17)).is_err());
+        }
+    }
+
+    #[test]
+    fn it_decodes_arrays() {
+        // '{1,2,NULL}'::int4[] in both formats, as sent by `pgoutput`
+        let text = TupleData::Text(Bytes::from_static(b"{1,2,NULL}"));
+        let binary = TupleData::Binary(Bytes::from_static(&[
+            0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 23, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1, 0,
+            0, 0, 4, 0, 0, 0, 2, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]));
+
+        let expected = vec![Some(1), Some(2), None];
+        assert_eq!(
+            text.try_decode::<Vec<Option<i32>>>(Oid(1007)).unwrap(),
+            expected
+        );
+        assert_eq!(
+            binary.try_decode::<Vec<Option<i32>>>(Oid(1007)).unwrap(),
+            expected
+        );
+    }
+
+    /// A composite type like `CREATE TYPE point_type AS (x INT, label TEXT)`, decoded like
+    /// `#[derive(sqlx::Type)]` does.
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        label: Option<String>,
+    }
+
+    impl Type<Postgres> for Point {
+        fn type_info() -> PgTypeInfo {
+            PgTypeInfo::with_name("point_type")
+        }
+    }
+
+    impl crate::PgHasArrayType for Point {
+        fn array_type_info() -> PgTypeInfo {
+            PgTypeInfo::array_of("point_type")
+        }
+    }
+
+    impl Decode<'_, Postgres> for Point {
+        fn decode(value: PgValueRef<'_>) -> Result<Self, sqlx_core::error::BoxDynError> {
+            let mut decoder = crate::types::PgRecordDecoder::new(value)?;
+
+            Ok(Point {
+                x: decoder.try_decode()?,
+                label: decoder.try_decode()?,
+            })
+        }
+    }
+
+    #[test]
+    fn it_decodes_arrays_of_composite_types() {
+        // '{"(1,a)","(2,\"b, c\")",NULL}'::point_type[] in both formats
+        let text = TupleData::Text(Bytes::from_static(br#"{"(1,a)","(2,\"b, c\")",NULL}"#));
+
+        let record = |x: u8, label: &[u8]| {
+            let mut record = vec![0, 0, 0, 2, 0, 0, 0, 23, 0, 0, 0, 4, 0, 0, 0, x, 0, 0, 0, 25];
+            record.extend_from_slice(&u32::try_from(label.len()).unwrap().to_be_bytes());
+            record.extend_from_slice(label);
+            record
+        };
+        let mut array = vec![
+            0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0x40, 0x74, 0, 0, 0, 3, 0, 0, 0, 1,
+        ];
+        for element in [Some(record(1, b"a")), Some(record(2, b"b, c")), None] {
+            match element {
+                Some(element) => {
+                    array.extend_from_slice(&u32::try_from(element.len()).unwrap().to_be_bytes());
+                    array.extend_from_slice(&element);
+                }
+                None => array.extend_from_slice(&[0xFF; 4]),
+            }
+        }
+        let binary = TupleData::Binary(Bytes::from(array));
+
+        let expected = vec![
+            Some(Point {
+                x: 1,
+                label: Some("a".into()),
+            }),
+            Some(Point {
+                x: 2,
+                label: Some("b, c".into()),
+            }),
+            None,
+        ];
+
+        let ty = super::super::Type {
+            xid: None,
+            type_id: Oid(16500),
+            namespace: "public".into(),
+            name: "_point_type".into(),
+        };
+
+        for value in [&text, &binary] {
+            let points: Vec<Option<Point>> = value.try_decode(Oid(16500)).unwrap();
+            assert_eq!(points, expected);
+
+            let points: Vec<Option<Point>> = value.try_decode_custom(&ty).unwrap();
+            assert_eq!(points, expected);
+        }
+
+        // checked by the name of the element type
+        let other = super::super::Type {
+            name: "_other_type".into(),
+            ..ty
+        };
+        assert!(text
+            .try_decode_custom::<Vec<Option<Point>>>(&other)
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_arrays_it_cannot_represent() {
+        for text in [&b"{{1,2},{3,4}}"[..], b"[0:1]={1,2}"] {
+            let value = TupleData::Text(Bytes::from_static(text));
+
+            // rather than e.g. `["{1", "2}", "{3", "4}"]`
+            assert!(value.try_decode::<Vec<String>>(Oid(1009)).is_err());
+            assert!(value.try_decode::<Vec<i32>>(Oid(1007)).is_err());
+
+            assert_eq!(
+                value.try_decode::<String>(Oid(25)).unwrap().as_bytes(),
+                text
+            );
+        }
+    }
+
+    // `numeric_send()` of the values, as sent by `pgoutput` with `binary` enabled
+    #[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+    const NUMERICS: &[(&str, &[u8])] = &[
+        ("12345.678", b"\0\x03\0\x01\0\0\0\x03\0\x01\x09\x29\x1a\x7c"),
+        ("-0.0001", b"\0\x01\xff\xff\x40\0\0\x04\0\x01"),
+        ("0", b"\0\0\0\0\0\0\0\0"),
+        ("100000000", b"\0\x01\0\x02\0\0\0\0\0\x01"),
+    ];
+
+    #[cfg(any(feature = "rust_decimal", feature = "bigdecimal"))]
+    const NUMERIC_NAN: &[u8] = b"\0\0\0\0\xc0\0\0\0";
+
+    // binary values captured with `range_send()` from PostgreSQL 15
+    #[test]
+    fn it_decodes_ranges() {
+        use std::ops::Bound;
+
+        use crate::types::PgRange;
+
+        let expected = PgRange {
+            start: Bound::Included(1),
+            end: Bound::Excluded(10),
+        };
+
+        let value = TupleData::Text(Bytes::from_static(b"[1,10)"));
+        assert_eq!(
+            value.try_decode::<PgRange<i32>>(Oid(3904)).unwrap(),
+            expected
+        );
+
+        let value = TupleData::Binary(Bytes::from_static(
+            b"\x02\0\0\0\x04\0\0\0\x01\0\0\0\x04\0\0\0\x0a",
+        ));
+        assert_eq!(
+            value.try_decode::<PgRange<i32>>(Oid(3904)).unwrap(),
+            expected
+        );
+
+        // `(,5]`, normalized by the server
+        let expected = PgRange {
+            start: Bound::Unbounded,
+            end: Bound::Excluded(6),
+        };
+
+        let value = TupleData::Text(Bytes::from_static(b"(,6)"));
+        assert_eq!(
+            value.try_decode::<PgRange<i32>>(Oid(3904)).unwrap(),
+            expected
+        );
+
+        let value = TupleData::Binary(Bytes::from_static(b"\x08\0\0\0\x04\0\0\0\x06"));
+        assert_eq!(
+            value.try_decode::<PgRange<i32>>(Oid(3904)).unwrap(),
+            expected
+        );
+
+        // not an `int8range`
+        assert!(value.try_decode::<PgRange<i64>>(Oid(3904)).is_err());
+
+        // an `int4multirange`, which has no sqlx type
+        let value = TupleData::Text(Bytes::from_static(b"{[1,3),[5,7)}"));
+        assert_eq!(
+            value.try_decode::<String>(Oid(4451)).unwrap(),
+            "{[1,3),[5,7)}"
+        );
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn it_decodes_rust_decimal() {
+        use rust_decimal::Decimal;
+
+        for (text, binary) in NUMERICS {
+            let expected: Decimal = text.parse().unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<Decimal>(Oid(1700)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<Decimal>(Oid(1700)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        // `Decimal` cannot represent `NaN`
+        let value = TupleData::Binary(Bytes::from_static(NUMERIC_NAN));
+        assert!(value.try_decode::<Decimal>(Oid(1700)).is_err());
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn it_decodes_bigdecimal() {
+        use bigdecimal::BigDecimal;
+
+        // more digits than fit into a `rust_decimal::Decimal`
+        const LARGE: (&str, &[u8]) = (
+            "1234567890123456789012345678.9",
+            b"\0\x08\0\x06\0\0\0\x01\x04\xd2\x16\x2e\x23\x34\x0d\x80\x1e\xd2\x04\xd2\x16\x2e\x23\x28",
+        );
+
+        for (text, binary) in NUMERICS.iter().chain([&LARGE]) {
+            let expected: BigDecimal = text.parse().unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<BigDecimal>(Oid(1700)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<BigDecimal>(Oid(1700)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        let value = TupleData::Binary(Bytes::from_static(NUMERIC_NAN));
+        assert!(value.try_decode::<BigDecimal>(Oid(1700)).is_err());
+    }
+
+    // `timestamp_send()`, `date_send()` and `time_send()` of the values from PostgreSQL 15,
+    // which count from 2000-01-01 rather than from the Unix epoch; `timestamptz` values are
+    // sent like `timestamp` values in UTC
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    const TIMESTAMPS: &[(&str, &[u8])] = &[
+        ("2000-01-01 00:00:00", b"\0\0\0\0\0\0\0\0"),
+        ("1970-01-01 00:00:00", b"\xff\xfc\xa2\xfe\xc4\xc8\x20\0"),
+        (
+            "1999-12-31 23:59:59.999999",
+            b"\xff\xff\xff\xff\xff\xff\xff\xff",
+        ),
+        ("1900-01-01 00:00:00", b"\xff\xf4\xc9\xee\x7c\x0b\x80\0"),
+        ("9999-12-31 23:59:59", b"\x03\x80\xe7\x0b\x91\x2c\x3d\xc0"),
+    ];
+
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    const DATES: &[(&str, &[u8])] = &[
+        ("2000-01-01", b"\0\0\0\0"),
+        ("1970-01-01", b"\xff\xff\xd5\x33"),
+        ("1999-12-31", b"\xff\xff\xff\xff"),
+        ("1900-01-01", b"\xff\xff\x71\x54"),
+        ("9999-12-31", b"\0\x2c\x95\xd3"),
+    ];
+
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    const TIMES: &[(&str, &[u8])] = &[
+        ("00:00:00", b"\0\0\0\0\0\0\0\0"),
+        ("12:34:56.789", b"\0\0\0\x0a\x8b\xe6\x26\x08"),
+        ("23:59:59.999999", b"\0\0\0\x14\x1d\xd7\x5f\xff"),
+    ];
+
+    // `infinity` as sent for `timestamp`, `timestamptz` and `date`
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    const TIMESTAMP_INFINITY: &[u8] = b"\x7f\xff\xff\xff\xff\xff\xff\xff";
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    const DATE_INFINITY: &[u8] = b"\x7f\xff\xff\xff";
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn it_decodes_chrono_values() {
+        use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+        for (text, binary) in TIMESTAMPS {
+            let expected = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f").unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(
+                value.try_decode::<NaiveDateTime>(Oid(1114)).unwrap(),
+                expected
+            );
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<NaiveDateTime>(Oid(1114)).unwrap(),
+                expected,
+                "{text}"
+            );
+            assert_eq!(
+                value.try_decode::<DateTime<Utc>>(Oid(1184)).unwrap(),
+                expected.and_utc(),
+                "{text}"
+            );
+
+            let value = TupleData::Text(Bytes::from(format!("{text}+00")));
+            assert_eq!(
+                value.try_decode::<DateTime<Utc>>(Oid(1184)).unwrap(),
+                expected.and_utc()
+            );
+        }
+
+        for (text, binary) in DATES {
+            let expected = NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<NaiveDate>(Oid(1082)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<NaiveDate>(Oid(1082)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        for (text, binary) in TIMES {
+            let expected = NaiveTime::parse_from_str(text, "%H:%M:%S%.f").unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<NaiveTime>(Oid(1083)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<NaiveTime>(Oid(1083)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        // out of range for chrono, which must not panic
+        let value = TupleData::Binary(Bytes::from_static(TIMESTAMP_INFINITY));
+        assert!(value.try_decode::<NaiveDateTime>(Oid(1114)).is_err());
+        assert!(value.try_decode::<DateTime<Utc>>(Oid(1184)).is_err());
+
+        let value = TupleData::Binary(Bytes::from_static(DATE_INFINITY));
+        assert!(value.try_decode::<NaiveDate>(Oid(1082)).is_err());
+
+        // not a `date`
+        let value = TupleData::Binary(Bytes::from_static(TIMESTAMPS[0].1));
+        assert!(value.try_decode::<NaiveDate>(Oid(1114)).is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn it_decodes_time_values() {
+        use time::macros::{format_description, offset};
+        use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+
+        for (text, binary) in TIMESTAMPS {
+            let expected = PrimitiveDateTime::parse(
+                text,
+                &format_description!(
+                    "[year]-[month]-[day] [hour]:[minute]:[second][optional [.[subsecond]]]"
+                ),
+            )
+            .unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(
+                value.try_decode::<PrimitiveDateTime>(Oid(1114)).unwrap(),
+                expected
+            );
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<PrimitiveDateTime>(Oid(1114)).unwrap(),
+                expected,
+                "{text}"
+            );
+            assert_eq!(
+                value.try_decode::<OffsetDateTime>(Oid(1184)).unwrap(),
+                expected.assume_offset(offset!(UTC)),
+                "{text}"
+            );
+
+            let value = TupleData::Text(Bytes::from(format!("{text}+00")));
+            assert_eq!(
+                value.try_decode::<OffsetDateTime>(Oid(1184)).unwrap(),
+                expected.assume_offset(offset!(UTC))
+            );
+        }
+
+        for (text, binary) in DATES {
+            let expected = Date::parse(text, &format_description!("[year]-[month]-[day]")).unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<Date>(Oid(1082)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<Date>(Oid(1082)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        for (text, binary) in TIMES {
+            let expected = Time::parse(
+                text,
+                &format_description!("[hour]:[minute]:[second][optional [.[subsecond]]]"),
+            )
+            .unwrap();
+
+            let value = TupleData::Text(Bytes::from_static(text.as_bytes()));
+            assert_eq!(value.try_decode::<Time>(Oid(1083)).unwrap(), expected);
+
+            let value = TupleData::Binary(Bytes::from_static(binary));
+            assert_eq!(
+                value.try_decode::<Time>(Oid(1083)).unwrap(),
+                expected,
+                "{text}"
+            );
+        }
+
+        // out of range for `time`, which must not panic
+        let value = TupleData::Binary(Bytes::from_static(TIMESTAMP_INFINITY));
+        assert!(value.try_decode::<PrimitiveDateTime>(Oid(1114)).is_err());
+        assert!(value.try_decode::<OffsetDateTime>(Oid(1184)).is_err());
+
+        let value = TupleData::Binary(Bytes::from_static(DATE_INFINITY));
+        assert!(value.try_decode::<Date>(Oid(1082)).is_err());
+    }
+
+    // binary values captured with `bit_send()` and `varbit_send()` from PostgreSQL 15
+    #[cfg(feature = "bit-vec")]
+    #[test]
+    fn it_decodes_bit_strings() {
+        use bit_vec::BitVec;
+
+        const BIT: Oid = Oid(1560);
+        const VARBIT: Oid = Oid(1562);
+
+        let bits = |bits: &str| -> BitVec { bits.chars().map(|bit| bit == '1').collect() };
+
+        // `B'00000101'::bit(8)`, whose leading zeros are part of the value
+        let value = TupleData::Text(Bytes::from_static(b"00000101"));
+        assert_eq!(value.try_decode::<BitVec>(BIT).unwrap(), bits("00000101"));
+
+        let value = TupleData::Binary(Bytes::from_static(b"\0\0\0\x08\x05"));
+        assert_eq!(value.try_decode::<BitVec>(BIT).unwrap(), bits("00000101"));
+
+        // `B'1011000011'::varbit`, padded to two bytes
+        let value = TupleData::Binary(Bytes::from_static(b"\0\0\0\x0a\xb0\xc0"));
+        assert_eq!(
+            value.try_decode::<BitVec>(VARBIT).unwrap(),
+            bits("1011000011")
+        );
+
+        let value = TupleData::Binary(Bytes::from_static(b"\0\0\0\0"));
+        assert!(value.try_decode::<BitVec>(VARBIT).unwrap().is_empty());
+
+        // the number of bits doesn't match the bytes, or is missing
+        for value in [
+            &b""[..],
+            b"\0\0\x08",
+            b"\0\0\0\x09\x05",
+            b"\0\0\0\x08\x05\0",
+            b"\xff\xff\xff\xff",
+        ] {
+            let value = TupleData::Binary(Bytes::from_static(value));
+            assert!(
+                value.try_decode::<BitVec>(VARBIT).is_err(),
+                "{value:?} decoded as a bit string"
+            );
+        }
+
+        assert!(TupleData::Text(Bytes::from_static(b"102"))
+            .try_decode::<BitVec>(BIT)
+            .is_err());
+
+        // a bit string is not text
+        assert!(TupleData::Text(Bytes::from_static(b"101"))
+            .try_decode::<String>(BIT)
+            .is_err());
+    }
+
+    // binary values captured with `uuid_send()`, `inet_send()`, `cidr_send()` and
+    // `macaddr_send()` from PostgreSQL 15
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn it_decodes_uuid() {
+        use uuid::Uuid;
+
+        let expected = Uuid::from_u128(0xa0eebc99_9c0b_4ef8_bb6d_6bb9bd380a11);
+
+        let value = TupleData::Text(Bytes::from_static(b"a0eebc99-9c0b-4ef8-bb6d-6bb9bd380a11"));
+        assert_eq!(value.try_decode::<Uuid>(Oid(2950)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(
+            b"\xa0\xee\xbc\x99\x9c\x0b\x4e\xf8\xbb\x6d\x6b\xb9\xbd\x38\x0a\x11",
+        ));
+        assert_eq!(value.try_decode::<Uuid>(Oid(2950)).unwrap(), expected);
+
+        // not a string
+        assert!(value.try_decode::<String>(Oid(2950)).is_err());
+
+        // not 16 bytes
+        let value = TupleData::Binary(Bytes::from_static(b"\xa0\xee\xbc\x99"));
+        assert!(value.try_decode::<Uuid>(Oid(2950)).is_err());
+    }
+
+    #[cfg(feature = "ipnetwork")]
+    #[test]
+    fn it_decodes_network_addresses() {
+        use std::net::IpAddr;
+
+        use ipnetwork::IpNetwork;
+
+        // inet
+        let expected: IpNetwork = "192.168.0.1/24".parse().unwrap();
+
+        let value = TupleData::Text(Bytes::from_static(b"192.168.0.1/24"));
+        assert_eq!(value.try_decode::<IpNetwork>(Oid(869)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(b"\x02\x18\x00\x04\xc0\xa8\x00\x01"));
+        assert_eq!(value.try_decode::<IpNetwork>(Oid(869)).unwrap(), expected);
+
+        // a single address
+        let expected: IpAddr = "2001:db8::1".parse().unwrap();
+
+        let value = TupleData::Text(Bytes::from_static(b"2001:db8::1"));
+        assert_eq!(value.try_decode::<IpAddr>(Oid(869)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(
+            b"\x03\x80\x00\x10\x20\x01\x0d\xb8\0\0\0\0\0\0\0\0\0\0\0\x01",
+        ));
+        assert_eq!(value.try_decode::<IpAddr>(Oid(869)).unwrap(), expected);
+
+        // cidr
+        let expected: IpNetwork = "10.0.0.0/8".parse().unwrap();
+
+        let value = TupleData::Text(Bytes::from_static(b"10.0.0.0/8"));
+        assert_eq!(value.try_decode::<IpNetwork>(Oid(650)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(b"\x02\x08\x01\x04\x0a\x00\x00\x00"));
+        assert_eq!(value.try_decode::<IpNetwork>(Oid(650)).unwrap(), expected);
+    }
+
+    #[cfg(feature = "mac_address")]
+    #[test]
+    fn it_decodes_mac_addresses() {
+        use mac_address::MacAddress;
+
+        let expected = MacAddress::new([0x08, 0x00, 0x2b, 0x01, 0x02, 0x03]);
+
+        let value = TupleData::Text(Bytes::from_static(b"08:00:2b:01:02:03"));
+        assert_eq!(value.try_decode::<MacAddress>(Oid(829)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(b"\x08\x00\x2b\x01\x02\x03"));
+        assert_eq!(value.try_decode::<MacAddress>(Oid(829)).unwrap(), expected);
+    }
+
+    #[test]
+    fn it_decodes_bools_in_both_formats() {
+        const BOOL: Oid = Oid(16);
+
+        let text = |value: &'static str| TupleData::Text(Bytes::from_static(value.as_bytes()));
+        let binary = |value: &'static [u8]| TupleData::Binary(Bytes::from_static(value));
+
+        assert!(text("t").try_decode::<bool>(BOOL).unwrap());
+        assert!(!text("f").try_decode::<bool>(BOOL).unwrap());
+        assert!(binary(b"\x01").try_decode::<bool>(BOOL).unwrap());
+        assert!(!binary(b"\0").try_decode::<bool>(BOOL).unwrap());
+        assert_eq!(
+            TupleData::Null.try_decode::<Option<bool>>(BOOL).unwrap(),
+            None
+        );
+
+        for value in [
+            text(""),
+            text("true"),
+            text("1"),
+            binary(b""),
+            binary(b"\x02"),
+            binary(b"\x01\0"),
+        ] {
+            assert!(
+                value.try_decode::<bool>(BOOL).is_err(),
+                "{value:?} decoded as a bool"
+            );
+        }
+    }
+
+    // captured with `interval_send()` and `cash_send()` from PostgreSQL 15
+    #[test]
+    fn it_decodes_intervals_and_money() {
+        use crate::types::{PgInterval, PgMoney};
+
+        // the microseconds, days and months of the interval
+        let expected = PgInterval {
+            months: 14,
+            days: -3,
+            microseconds: 14_706_789_000,
+        };
+
+        let value = TupleData::Binary(Bytes::from_static(
+            b"\0\0\0\x03\x6c\x97\xca\x88\xff\xff\xff\xfd\0\0\0\x0e",
+        ));
+        assert_eq!(value.try_decode::<PgInterval>(Oid(1186)).unwrap(), expected);
+
+        let value = TupleData::Text(Bytes::from_static(b"1 year 2 mons -3 days +04:05:06.789"));
+        assert_eq!(value.try_decode::<PgInterval>(Oid(1186)).unwrap(), expected);
+
+        // `IntervalStyle = iso_8601`
+        let value = TupleData::Text(Bytes::from_static(b"P1Y2M-3DT4H5M6.789S"));
+        assert!(value.try_decode::<PgInterval>(Oid(1186)).is_err());
+
+        // 1234.56 in cents, whatever the currency
+        let value = TupleData::Binary(Bytes::from_static(b"\0\0\0\0\0\x01\xe2\x40"));
+        assert_eq!(
+            value.try_decode::<PgMoney>(Oid(790)).unwrap(),
+            PgMoney(123_456)
+        );
+
+        let value = TupleData::Text(Bytes::from_static(b"$1,234.56"));
+        let error = value.try_decode::<PgMoney>(Oid(790)).unwrap_err();
+        assert!(error.to_string().contains("lc_monetary"), "{error}");
+    }
+
+    // captured with `json_send()` and `jsonb_send()` from PostgreSQL 15
+    #[cfg(feature = "json")]
+    #[test]
+    fn it_decodes_json() {
+        use serde_json::{json, Value};
+
+        let expected = json!({ "a": [1, true, null] });
+
+        for type_id in [Oid(114), Oid(3802)] {
+            let value = TupleData::Text(Bytes::from_static(br#"{"a": [1, true, null]}"#));
+            assert_eq!(value.try_decode::<Value>(type_id).unwrap(), expected);
+        }
+
+        // json
+        let value = TupleData::Binary(Bytes::from_static(br#"{"a": [1, true, null]}"#));
+        assert_eq!(value.try_decode::<Value>(Oid(114)).unwrap(), expected);
+
+        // jsonb, prefixed with the format version
+        let value = TupleData::Binary(Bytes::from_static(b"\x01{\"a\": [1, true, null]}"));
+        assert_eq!(value.try_decode::<Value>(Oid(3802)).unwrap(), expected);
+
+        let value = TupleData::Binary(Bytes::from_static(b"\x02{\"a\": [1, true, null]}"));
+        assert!(value.try_decode::<Value>(Oid(3802)).is_err());
+
+        let value = TupleData::Binary(Bytes::new());
+        assert!(value.try_decode::<Value>(Oid(3802)).is_err());
+    }
+}
@@ -0,0 +1,579 @@
+//! Parsing of the headers of the WAL records in the [`XLogData`][super::XLogData] messages of
+//! physical replication, e.g. to filter or count records by resource manager without decoding
+//! them.
+//!
+//! The WAL format is internal to Postgres and may change in any major version. This parses the
+//! record and page headers of **Postgres 10 to 17**, whose layout is unchanged since 9.5, for
+//! servers built with the default WAL block size (`XLOG_BLCKSZ`) of 8 kB. WAL is written in the
+//! byte order of the server, which is assumed to be little-endian. Decoding the records
+//! themselves, i.e. their block references and the data of their resource manager, is out of
+//! scope.
+//!
+//! ```rust,no_run
+//! # async fn example(
+//! #     stream: &mut sqlx::postgres::replication::PhysicalReplicationStream,
+//! # ) -> Result<(), sqlx::postgres::replication::ReplicationError> {
+//! use std::collections::HashMap;
+//! use sqlx::postgres::replication::wal::WalRecordReader;
+//! use sqlx::postgres::replication::PhysicalReplication;
+//!
+//! let mut reader = WalRecordReader::new();
+//! let mut counts = HashMap::new();
+//!
+//! while let Some(PhysicalReplication::XLogData(data)) = stream.recv().await? {
+//!     for record in reader.push(data.wal_start, &data.data)? {
+//!         *counts.entry(record.header.resource_manager()).or_insert(0) += 1;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp;
+
+use crate::error::Error;
+use crate::types::PgLsn;
+
+/// The size of a WAL page (`XLOG_BLCKSZ`).
+const PAGE_SIZE: u64 = 8192;
+/// The size of the header of the pages other than the first one of a segment.
+const SHORT_PAGE_HEADER_SIZE: usize = 24;
+/// The size of the header of the first page of a segment.
+const LONG_PAGE_HEADER_SIZE: usize = 40;
+/// The page starts with the rest of a record that started on a previous page.
+const XLP_FIRST_IS_CONTRECORD: u16 = 0x0001;
+/// The page has a long header.
+const XLP_LONG_HEADER: u16 = 0x0002;
+const RM_XLOG_ID: u8 = 0;
+/// The record type of a WAL switch in the `XLOG` resource manager; the rest of its segment is
+/// unused.
+const XLOG_SWITCH: u8 = 0x40;
+/// The default WAL segment size, until the long header of a segment tells otherwise.
+const DEFAULT_SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The names of the built-in resource managers, by ID (`rmgrlist.h`).
+const RESOURCE_MANAGERS: [&str; 22] = [
+    "XLOG",
+    "Transaction",
+    "Storage",
+    "CLOG",
+    "Database",
+    "Tablespace",
+    "MultiXact",
+    "RelMap",
+    "Standby",
+    "Heap2",
+    "Heap",
+    "Btree",
+    "Hash",
+    "Gin",
+    "Gist",
+    "Sequence",
+    "SPGist",
+    "BRIN",
+    "CommitTs",
+    "ReplicationOrigin",
+    "Generic",
+    "LogicalMessage",
+];
+
+/// The header of a WAL record (`XLogRecord`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalRecordHeader {
+    /// The total length of the record, including this header (`xl_tot_len`).
+    pub tot_len: u32,
+    /// The xid of the transaction that wrote the record, or `0` (`xl_xid`).
+    pub xid: u32,
+    /// The position of the previous record (`xl_prev`).
+    pub prev: PgLsn,
+    /// The flags of the record in the low 4 bits, and the record type of its resource manager
+    /// in the high 4 bits (`xl_info`); see [`record_type()`][Self::record_type].
+    pub info: u8,
+    /// The ID of the resource manager of the record (`xl_rmid`); see
+    /// [`resource_manager()`][Self::resource_manager].
+    pub rmid: u8,
+    /// The CRC-32C of the record (`xl_crc`), which is not checked.
+    pub crc: u32,
+}
+
+impl WalRecordHeader {
+    /// The size of the header in bytes.
+    pub const SIZE: usize = 24;
+
+    /// Parse a record header from the start of `buf`.
+    pub fn parse(buf: &[u8]) -> Result<Self, Error> {
+        if buf.len() < Self::SIZE {
+            return Err(err_protocol!(
+                "WAL record header: expected {} bytes, got {}",
+                Self::SIZE,
+                buf.len()
+            ));
+        }
+
+        let header = WalRecordHeader {
+            tot_len: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+            xid: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+            prev: PgLsn(u64::from_le_bytes(buf[8..16].try_into().unwrap())),
+            info: buf[16],
+            rmid: buf[17],
+            crc: u32::from_le_bytes(buf[20..24].try_into().unwrap()),
+        };
+
+        if (header.tot_len as usize) < Self::SIZE {
+            return Err(err_protocol!(
+                "WAL record header: invalid record length {}",
+                header.tot_len
+            ));
+        }
+
+        Ok(header)
+    }
+
+    /// The record type within the resource manager, e.g. `0x00` for an insert of the `Heap`
+    /// resource manager (`xl_info & ~XLR_INFO_MASK`).
+    pub fn record_type(&self) -> u8 {
+        self.info & 0xF0
+    }
+
+    /// The name of the resource manager of the record, as shown by `pg_waldump`, e.g. `Heap`;
+    /// `None` for the IDs of custom resource managers (Postgres 15+) and unknown IDs.
+    pub fn resource_manager(&self) -> Option<&'static str> {
+        RESOURCE_MANAGERS.get(usize::from(self.rmid)).copied()
+    }
+
+    /// Returns `true` for a WAL switch (`pg_switch_wal()`), after which the rest of the segment
+    /// is unused.
+    pub fn is_switch(&self) -> bool {
+        self.rmid == RM_XLOG_ID && self.record_type() == XLOG_SWITCH
+    }
+}
+
+/// A WAL record found by a [`WalRecordReader`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WalRecord {
+    /// The position of the record.
+    pub lsn: PgLsn,
+    /// The header of the record.
+    pub header: WalRecordHeader,
+}
+
+/// Finds the WAL records in consecutive [`XLogData`][super::XLogData] messages, and parses
+/// their headers.
+///
+/// WAL is streamed in chunks that start and end anywhere, so records and their headers may
+/// span several messages; the reader keeps the state needed to continue with the next one. The
+/// start of a record is only known at a page boundary, so the reader skips to the next page
+/// when it starts, and whenever a message doesn't continue where the previous one ended.
+#[derive(Debug, Clone)]
+pub struct WalRecordReader {
+    /// The position the next message is expected to start at.
+    lsn: Option<u64>,
+    state: State,
+    /// The bytes of the page header being read, if any.
+    page: Option<Vec<u8>>,
+    /// The bytes of the record header being read, and the position of the record.
+    record: Vec<u8>,
+    record_lsn: u64,
+    segment_size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Skip to the next page, whose header tells where the next record starts.
+    Seek,
+    /// Read the header of the next record.
+    Header,
+    /// Skip `remaining` bytes of a record, or of the padding after it, not counting page
+    /// headers.
+    Skip { remaining: u64, next: Next },
+    /// Skip to the start of the next segment after a WAL switch.
+    SkipSegment,
+}
+
+/// What follows the bytes skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Next {
+    /// The padding that aligns the next record to 8 bytes.
+    Align,
+    Header,
+    /// The rest of the segment after a WAL switch.
+    Segment,
+}
+
+impl Default for WalRecordReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WalRecordReader {
+    /// Create a reader that starts at the next message it is given.
+    pub fn new() -> Self {
+        Self {
+            lsn: None,
+            state: State::Seek,
+            page: None,
+            record: Vec::with_capacity(WalRecordHeader::SIZE),
+            record_lsn: 0,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+        }
+    }
+
+    /// Parse the WAL `data` starting at `wal_start`, as received in an
+    /// [`XLogData`][super::XLogData] message, returning the records whose headers it completes.
+    ///
+    /// If `wal_start` is not where the previous message ended, the reader starts over at the
+    /// next page. An error is returned if a page header or a record header is invalid, which
+    /// points to a server with an unsupported WAL format; the records of the message are lost
+    /// then, and the reader starts over at the next page.
+    pub fn push(&mut self, wal_start: PgLsn, data: &[u8]) -> Result<Vec<WalRecord>, Error> {
+        if self.lsn != Some(wal_start.0) {
+            self.state = State::Seek;
+            self.page = None;
+            self.record.clear();
+        }
+
+        let mut lsn = wal_start.0;
+        let mut records = Vec::new();
+
+        let result = self.read(&mut lsn, data, &mut records);
+
+        self.lsn = Some(wal_start.0 + data.len() as u64);
+
+        if let Err(error) = result {
+            self.state = State::Seek;
+            self.page = None;
+            self.record.clear();
+
+            return Err(error);
+        }
+
+        Ok(records)
+    }
+
+    fn read(
+        &mut self,
+        lsn: &mut u64,
+        mut data: &[u8],
+        records: &mut Vec<WalRecord>,
+    ) -> Result<(), Error> {
+        while !data.is_empty() {
+            if self.state == State::SkipSegment {
+                let len = prefix_len(data, self.segment_size - offset(*lsn, self.segment_size));
+
+                data = &data[len..];
+                *lsn += len as u64;
+
+                if offset(*lsn, self.segment_size) == 0 {
+                    self.state = State::Header;
+                }
+
+                continue;
+            }
+
+            if offset(*lsn, PAGE_SIZE) == 0 || self.page.is_some() {
+                let page = self.page.get_or_insert_with(Vec::new);
+
+                let long = page.len() >= 4
+                    && u16::from_le_bytes([page[2], page[3]]) & XLP_LONG_HEADER != 0;
+                let size = if long {
+                    LONG_PAGE_HEADER_SIZE
+                } else {
+                    SHORT_PAGE_HEADER_SIZE
+                };
+
+                let len = cmp::min(size - page.len(), data.len());
+                page.extend_from_slice(&data[..len]);
+                data = &data[len..];
+                *lsn += len as u64;
+
+                // the size is only known once the flags were read
+                if page.len() == size && (long || u16::from(page[2]) & XLP_LONG_HEADER == 0) {
+                    let page = self.page.take().unwrap();
+                    self.page_header(*lsn - page.len() as u64, &page)?;
+                }
+
+                continue;
+            }
+
+            let page_left = PAGE_SIZE - offset(*lsn, PAGE_SIZE);
+
+            match self.state {
+                State::Seek => {
+                    let len = prefix_len(data, page_left);
+
+                    data = &data[len..];
+                    *lsn += len as u64;
+                }
+
+                State::Header => {
+                    if self.record.is_empty() {
+                        self.record_lsn = *lsn;
+                    }
+
+                    // the header of a record may span two pages
+                    let len = cmp::min(
+                        WalRecordHeader::SIZE - self.record.len(),
+                        prefix_len(data, page_left),
+                    );
+
+                    self.record.extend_from_slice(&data[..len]);
+                    data = &data[len..];
+                    *lsn += len as u64;
+
+                    if self.record.len() == WalRecordHeader::SIZE {
+                        let header = WalRecordHeader::parse(&self.record)?;
+                        self.record.clear();
+
+                        records.push(WalRecord {
+                            lsn: PgLsn(self.record_lsn),
+                            header,
+                        });
+
+                        self.state = State::Skip {
+                            remaining: u64::from(header.tot_len) - WalRecordHeader::SIZE as u64,
+                            next: if header.is_switch() {
+                                Next::Segment
+                            } else {
+                                Next::Align
+                            },
+                        };
+                        self.skipped(*lsn);
+                    }
+                }
+
+                State::Skip { remaining, next } => {
+                    let len = prefix_len(data, cmp::min(remaining, page_left));
+
+                    data = &data[len..];
+                    *lsn += len as u64;
+
+                    self.state = State::Skip {
+                        remaining: remaining - len as u64,
+                        next,
+                    };
+                    self.skipped(*lsn);
+                }
+
+                State::SkipSegment => unreachable!(),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move on once the bytes of a `Skip` state were skipped.
+    fn skipped(&mut self, lsn: u64) {
+        let State::Skip { remaining: 0, next } = self.state else {
+            return;
+        };
+
+        self.state = match next {
+            Next::Align if offset(lsn, 8) != 0 => State::Skip {
+                remaining: 8 - offset(lsn, 8),
+                next: Next::Header,
+            },
+            Next::Align | Next::Header => State::Header,
+            Next::Segment if offset(lsn, self.segment_size) == 0 => State::Header,
+            Next::Segment => State::SkipSegment,
+        };
+    }
+
+    /// Check the header of the page at `page_lsn`, and find the first record on it when
+    /// seeking.
+    fn page_header(&mut self, page_lsn: u64, page: &[u8]) -> Result<(), Error> {
+        let info = u16::from_le_bytes([page[2], page[3]]);
+        let page_addr = u64::from_le_bytes(page[8..16].try_into().unwrap());
+        let rem_len = u32::from_le_bytes(page[16..20].try_into().unwrap());
+
+        if page_addr != page_lsn {
+            return Err(err_protocol!(
+                "WAL page at {} has the address {}",
+                PgLsn(page_lsn),
+                PgLsn(page_addr)
+            ));
+        }
+
+        if info & XLP_LONG_HEADER != 0 {
+            let segment_size = u32::from_le_bytes(page[32..36].try_into().unwrap());
+            let block_size = u32::from_le_bytes(page[36..40].try_into().unwrap());
+
+            if u64::from(block_size) != PAGE_SIZE || !segment_size.is_power_of_two() {
+                return Err(err_protocol!(
+                    "unsupported WAL block size {block_size} or segment size {segment_size}"
+                ));
+            }
+
+            self.segment_size = segment_size.into();
+        }
+
+        if self.state == State::Seek {
+            let page_left = PAGE_SIZE - page.len() as u64;
+
+            if info & XLP_FIRST_IS_CONTRECORD == 0 {
+                self.state = State::Header;
+            } else {
+                // the rest of a record continues on the next page if it doesn't fit on this one
+                let rest = (u64::from(rem_len) + 7) & !7;
+
+                if rest <= page_left {
+                    self.state = State::Skip {
+                        remaining: rest,
+                        next: Next::Header,
+                    };
+                    self.skipped(page_lsn + page.len() as u64);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The offset of `lsn` into a page or segment of `size` bytes, which is a power of two.
+fn offset(lsn: u64, size: u64) -> u64 {
+    lsn & (size - 1)
+}
+
+/// The length of the prefix of `data` of at most `limit` bytes.
+fn prefix_len(data: &[u8], limit: u64) -> usize {
+    usize::try_from(limit).map_or(data.len(), |limit| cmp::min(limit, data.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_header(page_lsn: u64, info: u16, rem_len: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&0xD110_u16.to_le_bytes());
+        header.extend_from_slice(&info.to_le_bytes());
+        header.extend_from_slice(&1_u32.to_le_bytes());
+        header.extend_from_slice(&page_lsn.to_le_bytes());
+        header.extend_from_slice(&rem_len.to_le_bytes());
+        header.extend_from_slice(&[0; 4]);
+
+        if info & XLP_LONG_HEADER != 0 {
+            header.extend_from_slice(&7_u64.to_le_bytes());
+            header.extend_from_slice(&(16_u32 << 20).to_le_bytes());
+            header.extend_from_slice(&8192_u32.to_le_bytes());
+        }
+
+        header
+    }
+
+    fn record(tot_len: u32, prev: u64, rmid: u8, info: u8) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&tot_len.to_le_bytes());
+        record.extend_from_slice(&742_u32.to_le_bytes());
+        record.extend_from_slice(&prev.to_le_bytes());
+        record.extend_from_slice(&[info, rmid, 0, 0]);
+        record.extend_from_slice(&0xDEAD_BEEF_u32.to_le_bytes());
+        record.resize(tot_len as usize, 0xAA);
+        record
+    }
+
+    #[test]
+    fn it_parses_record_headers() {
+        let header = WalRecordHeader::parse(&record(54, 0x0100_0028, 10, 0x80)).unwrap();
+
+        assert_eq!(header.tot_len, 54);
+        assert_eq!(header.xid, 742);
+        assert_eq!(header.prev, PgLsn(0x0100_0028));
+        assert_eq!(header.rmid, 10);
+        assert_eq!(header.resource_manager(), Some("Heap"));
+        assert_eq!(header.record_type(), 0x80);
+        assert_eq!(header.crc, 0xDEAD_BEEF);
+        assert!(!header.is_switch());
+
+        assert!(WalRecordHeader::parse(&record(54, 0, 0, XLOG_SWITCH)[..20]).is_err());
+        assert!(WalRecordHeader::parse(&[0; 24]).is_err());
+        assert!(WalRecordHeader::parse(&record(24, 0, 0, XLOG_SWITCH))
+            .unwrap()
+            .is_switch());
+        assert_eq!(
+            WalRecordHeader::parse(&record(24, 0, 130, 0))
+                .unwrap()
+                .resource_manager(),
+            None
+        );
+    }
+
+    #[test]
+    fn it_reads_records_across_pages_and_messages() {
+        // the first page of a segment, starting with the end of a record of the previous one,
+        // then a record filling the page, and a record whose header spans into the second page
+        let base = 0x0100_0000_u64;
+        let mut wal = page_header(base, XLP_LONG_HEADER | XLP_FIRST_IS_CONTRECORD, 13);
+        wal.resize(wal.len() + 16, 0xFF);
+
+        let first = base + wal.len() as u64;
+        wal.extend(record(8128, base - 64, 1, 0));
+
+        let second = base + wal.len() as u64;
+        let header = record(200, first, 10, 0);
+        wal.extend_from_slice(&header[..8]);
+        wal.extend(page_header(base + PAGE_SIZE, XLP_FIRST_IS_CONTRECORD, 192));
+        wal.extend_from_slice(&header[8..]);
+
+        let third = base + wal.len() as u64;
+        wal.extend(record(24, second, 0, XLOG_SWITCH));
+
+        // chunks of all sizes, including ones that split the page headers
+        for chunk_size in [1, 7, 100, 4096, wal.len()] {
+            let mut reader = WalRecordReader::new();
+            let mut records = Vec::new();
+
+            for (i, chunk) in wal.chunks(chunk_size).enumerate() {
+                let start = PgLsn(base + (i * chunk_size) as u64);
+                records.extend(reader.push(start, chunk).unwrap());
+            }
+
+            let found: Vec<_> = records
+                .iter()
+                .map(|record| (record.lsn.0, record.header.prev.0, record.header.rmid))
+                .collect();
+
+            assert_eq!(
+                found,
+                [
+                    (first, base - 64, 1),
+                    (second, first, 10),
+                    (third, second, 0)
+                ],
+                "chunks of {chunk_size} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn it_seeks_to_the_next_page() {
+        let base = 0x0200_0000_u64;
+
+        // a message that starts in the middle of a page, and a record that continues onto the
+        // next page and covers it completely
+        let mut wal = vec![0xFF; 100];
+        wal.extend(page_header(base + PAGE_SIZE, XLP_FIRST_IS_CONTRECORD, 9000));
+        wal.resize(wal.len() + 8192 - SHORT_PAGE_HEADER_SIZE, 0xFF);
+
+        let page = base + 2 * PAGE_SIZE;
+        wal.extend(page_header(page, XLP_FIRST_IS_CONTRECORD, 9000 - 8168));
+        wal.resize(wal.len() + 832, 0xFF);
+        wal.extend(record(30, page, 11, 0));
+
+        let mut reader = WalRecordReader::new();
+        let start = base + PAGE_SIZE - 100;
+        let records = reader.push(PgLsn(start), &wal).unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].lsn, PgLsn(page + 24 + 832));
+        assert_eq!(records[0].header.resource_manager(), Some("Btree"));
+
+        // a page that doesn't have the expected address
+        let mut reader = WalRecordReader::new();
+        assert!(reader
+            .push(PgLsn(base), &page_header(base + PAGE_SIZE, 0, 0))
+            .is_err());
+    }
+}
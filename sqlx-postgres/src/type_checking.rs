@@ -36,6 +36,8 @@ impl_type_checking!(
 
         sqlx::postgres::types::PgLine,
 
+        sqlx::postgres::types::PgLsn,
+
         #[cfg(feature = "uuid")]
         sqlx::types::Uuid,
 
@@ -146,6 +146,8 @@ pub enum PgType {
     JsonpathArray,
     Money,
     MoneyArray,
+    PgLsn,
+    PgLsnArray,
 
     // https://www.postgresql.org/docs/9.3/datatype-pseudo.html
     Void,
@@ -431,6 +433,8 @@ impl PgType {
             2287 => PgType::RecordArray,
             2950 => PgType::Uuid,
             2951 => PgType::UuidArray,
+            3220 => PgType::PgLsn,
+            3221 => PgType::PgLsnArray,
             3802 => PgType::Jsonb,
             3807 => PgType::JsonbArray,
             3904 => PgType::Int4Range,
@@ -539,6 +543,8 @@ impl PgType {
             PgType::RecordArray => Oid(2287),
             PgType::Uuid => Oid(2950),
             PgType::UuidArray => Oid(2951),
+            PgType::PgLsn => Oid(3220),
+            PgType::PgLsnArray => Oid(3221),
             PgType::Jsonb => Oid(3802),
             PgType::JsonbArray => Oid(3807),
             PgType::Int4Range => Oid(3904),
@@ -661,6 +667,8 @@ impl PgType {
             PgType::JsonpathArray => "JSONPATH[]",
             PgType::Money => "MONEY",
             PgType::MoneyArray => "MONEY[]",
+            PgType::PgLsn => "PG_LSN",
+            PgType::PgLsnArray => "PG_LSN[]",
             PgType::Void => "VOID",
             PgType::Custom(ty) => &ty.name,
             PgType::DeclareWithOid(_) => "?",
@@ -762,6 +770,8 @@ impl PgType {
             PgType::JsonpathArray => "_jsonpath",
             PgType::Money => "money",
             PgType::MoneyArray => "_money",
+            PgType::PgLsn => "pg_lsn",
+            PgType::PgLsnArray => "_pg_lsn",
             PgType::Void => "void",
             PgType::Custom(ty) => &ty.name,
             PgType::DeclareWithOid(_) => "?",
@@ -863,6 +873,8 @@ impl PgType {
             PgType::JsonpathArray => &PgTypeKind::Array(PgTypeInfo(PgType::Jsonpath)),
             PgType::Money => &PgTypeKind::Simple,
             PgType::MoneyArray => &PgTypeKind::Array(PgTypeInfo(PgType::Money)),
+            PgType::PgLsn => &PgTypeKind::Simple,
+            PgType::PgLsnArray => &PgTypeKind::Array(PgTypeInfo(PgType::PgLsn)),
 
             PgType::Void => &PgTypeKind::Pseudo,
 
@@ -931,6 +943,8 @@ impl PgType {
             PgType::Macaddr8Array => Some(Cow::Owned(PgTypeInfo(PgType::Macaddr8))),
             PgType::Money => None,
             PgType::MoneyArray => Some(Cow::Owned(PgTypeInfo(PgType::Money))),
+            PgType::PgLsn => None,
+            PgType::PgLsnArray => Some(Cow::Owned(PgTypeInfo(PgType::PgLsn))),
             PgType::Macaddr => None,
             PgType::MacaddrArray => Some(Cow::Owned(PgTypeInfo(PgType::Macaddr))),
             PgType::Inet => None,
@@ -1177,6 +1191,10 @@ impl PgTypeInfo {
     pub(crate) const MONEY: Self = Self(PgType::Money);
     pub(crate) const MONEY_ARRAY: Self = Self(PgType::MoneyArray);
 
+    // write-ahead log location
+    pub(crate) const PG_LSN: Self = Self(PgType::PgLsn);
+    pub(crate) const PG_LSN_ARRAY: Self = Self(PgType::PgLsnArray);
+
     //
     // date/time types
     // https://www.postgresql.org/docs/current/datatype-datetime.html
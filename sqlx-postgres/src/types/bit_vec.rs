@@ -50,6 +50,14 @@ impl Decode<'_, Postgres> for BitVec {
         match value.format() {
             PgValueFormat::Binary => {
                 let mut bytes = value.as_bytes()?;
+
+                if bytes.remaining() < mem::size_of::<i32>() {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "VARBIT length missing.",
+                    ))?;
+                }
+
                 let len = bytes.get_i32();
 
                 let len = usize::try_from(len).map_err(|_| format!("invalid VARBIT len: {len}"))?;
@@ -97,3 +105,38 @@ impl Decode<'_, Postgres> for BitVec {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_binary(value: &[u8]) -> Result<BitVec, BoxDynError> {
+        <BitVec as Decode<Postgres>>::decode(PgValueRef {
+            value: Some(value),
+            row: None,
+            type_info: PgTypeInfo::VARBIT,
+            format: PgValueFormat::Binary,
+        })
+    }
+
+    #[test]
+    fn it_decodes_binary_bit_strings() {
+        let bits = decode_binary(b"\0\0\0\x0a\xb0\xc0").unwrap();
+        assert_eq!(bits, "1011000011".chars().map(|bit| bit == '1').collect());
+    }
+
+    #[test]
+    fn it_rejects_binary_bit_strings_of_the_wrong_length() {
+        for value in [
+            &b""[..],
+            b"\0\0\x08",
+            b"\0\0\0\x09\x05",
+            b"\0\0\0\x08\x05\0",
+        ] {
+            assert!(
+                decode_binary(value).is_err(),
+                "{value:?} decoded as a bit string"
+            );
+        }
+    }
+}
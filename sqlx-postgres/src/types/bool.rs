@@ -27,7 +27,14 @@ impl Encode<'_, Postgres> for bool {
 impl Decode<'_, Postgres> for bool {
     fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
         Ok(match value.format() {
-            PgValueFormat::Binary => value.as_bytes()?[0] != 0,
+            PgValueFormat::Binary => match value.as_bytes()? {
+                [0] => false,
+                [1] => true,
+
+                bytes => {
+                    return Err(format!("unexpected value {bytes:?} for binary boolean").into());
+                }
+            },
 
             PgValueFormat::Text => match value.as_str()? {
                 "t" => true,
@@ -40,3 +47,30 @@ impl Decode<'_, Postgres> for bool {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_binary(value: &[u8]) -> Result<bool, BoxDynError> {
+        <bool as Decode<Postgres>>::decode(PgValueRef {
+            value: Some(value),
+            row: None,
+            type_info: PgTypeInfo::BOOL,
+            format: PgValueFormat::Binary,
+        })
+    }
+
+    #[test]
+    fn it_decodes_binary_bools() {
+        assert!(decode_binary(&[1]).unwrap());
+        assert!(!decode_binary(&[0]).unwrap());
+    }
+
+    #[test]
+    fn it_rejects_invalid_binary_bools() {
+        for value in [&[][..], &[2], &[1, 0]] {
+            assert!(decode_binary(value).is_err(), "{value:?} decoded as a bool");
+        }
+    }
+}
@@ -71,20 +71,52 @@ impl<'r> Decode<'r, Postgres> for &'r [u8] {
     }
 }
 
-fn text_hex_decode_input(value: PgValueRef<'_>) -> Result<&[u8], BoxDynError> {
-    // BYTEA is formatted as \x followed by hex characters
-    value
-        .as_bytes()?
-        .strip_prefix(b"\\x")
-        .ok_or("text does not start with \\x")
-        .map_err(Into::into)
+/// Decode BYTEA in the text format, which is hex unless `bytea_output` is set to `escape`.
+///
+/// The escape format always escapes a backslash, so a value starting with `\x` is in the hex
+/// format regardless of the setting.
+fn text_decode(value: PgValueRef<'_>) -> Result<Vec<u8>, BoxDynError> {
+    let text = value.as_bytes()?;
+
+    match text.strip_prefix(b"\\x") {
+        Some(hex) => Ok(hex::decode(hex)?),
+        None => escape_decode(text),
+    }
+}
+
+/// Decode the escape format of BYTEA: `\\` for a backslash, `\` followed by three octal
+/// digits for a byte, and all other bytes as is.
+fn escape_decode(mut text: &[u8]) -> Result<Vec<u8>, BoxDynError> {
+    let mut bytes = Vec::with_capacity(text.len());
+
+    while let Some((&byte, rest)) = text.split_first() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            text = rest;
+            continue;
+        }
+
+        match rest {
+            [b'\\', rest @ ..] => {
+                bytes.push(b'\\');
+                text = rest;
+            }
+            [a @ b'0'..=b'3', b @ b'0'..=b'7', c @ b'0'..=b'7', rest @ ..] => {
+                bytes.push((a - b'0') << 6 | (b - b'0') << 3 | (c - b'0'));
+                text = rest;
+            }
+            _ => return Err("invalid escape sequence in BYTEA text".into()),
+        }
+    }
+
+    Ok(bytes)
 }
 
 impl Decode<'_, Postgres> for Box<[u8]> {
     fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
         Ok(match value.format() {
             PgValueFormat::Binary => Box::from(value.as_bytes()?),
-            PgValueFormat::Text => Box::from(hex::decode(text_hex_decode_input(value)?)?),
+            PgValueFormat::Text => text_decode(value)?.into_boxed_slice(),
         })
     }
 }
@@ -93,7 +125,7 @@ impl Decode<'_, Postgres> for Vec<u8> {
     fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
         Ok(match value.format() {
             PgValueFormat::Binary => value.as_bytes()?.to_owned(),
-            PgValueFormat::Text => hex::decode(text_hex_decode_input(value)?)?,
+            PgValueFormat::Text => text_decode(value)?,
         })
     }
 }
@@ -105,7 +137,14 @@ impl<const N: usize> Decode<'_, Postgres> for [u8; N] {
             PgValueFormat::Binary => {
                 bytes = value.as_bytes()?.try_into()?;
             }
-            PgValueFormat::Text => hex::decode_to_slice(text_hex_decode_input(value)?, &mut bytes)?,
+            PgValueFormat::Text => {
+                let text = value.as_bytes()?;
+
+                match text.strip_prefix(b"\\x") {
+                    Some(hex) => hex::decode_to_slice(hex, &mut bytes)?,
+                    None => bytes = escape_decode(text)?.as_slice().try_into()?,
+                }
+            }
         };
         Ok(bytes)
     }
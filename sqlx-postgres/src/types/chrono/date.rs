@@ -50,7 +50,16 @@ impl<'r> Decode<'r, Postgres> for NaiveDate {
                         unreachable!("BUG: days ({days}) as `i32` multiplied into seconds should not overflow `i64`")
                     });
 
-                postgres_epoch_date() + days
+                // `infinity` and `-infinity` are sent as `i32::MAX` and `i32::MIN`, which are out
+                // of range like any other date that chrono cannot represent
+                postgres_epoch_date()
+                    .checked_add_signed(days)
+                    .ok_or_else(|| {
+                        format!(
+                            "DATE value of {} days is out of range for NaiveDate",
+                            days.num_days()
+                        )
+                    })?
             }
 
             PgValueFormat::Text => NaiveDate::parse_from_str(value.as_str()?, "%Y-%m-%d")?,
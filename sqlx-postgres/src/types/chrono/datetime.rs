@@ -53,7 +53,13 @@ impl<'r> Decode<'r, Postgres> for NaiveDateTime {
             PgValueFormat::Binary => {
                 // TIMESTAMP is encoded as the microseconds since the epoch
                 let us = Decode::<Postgres>::decode(value)?;
-                postgres_epoch_datetime() + Duration::microseconds(us)
+
+                // this includes `infinity` and `-infinity`, sent as `i64::MAX` and `i64::MIN`
+                postgres_epoch_datetime()
+                    .checked_add_signed(Duration::microseconds(us))
+                    .ok_or_else(|| {
+                        format!("TIMESTAMP value of {us} microseconds is out of range for NaiveDateTime")
+                    })?
             }
 
             PgValueFormat::Text => {
@@ -45,12 +45,89 @@ impl<'de> Decode<'de, Postgres> for PgInterval {
                 })
             }
 
-            // TODO: Implement parsing of text mode
-            PgValueFormat::Text => {
-                Err("not implemented: decode `INTERVAL` in text mode (unprepared queries)".into())
-            }
+            PgValueFormat::Text => parse_interval(value.as_str()?),
+        }
+    }
+}
+
+/// Parse an interval in the text format of the default `postgres` `IntervalStyle`, e.g.
+/// `1 year 2 mons -3 days +04:05:06.789`.
+fn parse_interval(text: &str) -> Result<PgInterval, BoxDynError> {
+    let unsupported = || {
+        format!("unsupported `INTERVAL` text format {text:?}; only the `postgres` IntervalStyle is supported")
+    };
+
+    let mut interval = PgInterval::default();
+    let mut parts = text.split(' ');
+
+    while let Some(part) = parts.next() {
+        if part.contains(':') {
+            interval.microseconds = parse_interval_time(part).ok_or_else(unsupported)?;
+            continue;
         }
+
+        let n: i32 = part.parse().map_err(|_| unsupported())?;
+
+        let (months, days) = match parts.next() {
+            Some("year" | "years") => (n.checked_mul(12), Some(0)),
+            Some("mon" | "mons") => (Some(n), Some(0)),
+            Some("day" | "days") => (Some(0), Some(n)),
+            _ => return Err(unsupported().into()),
+        };
+
+        interval.months = (months.and_then(|months| interval.months.checked_add(months)))
+            .ok_or_else(unsupported)?;
+        interval.days =
+            (days.and_then(|days| interval.days.checked_add(days))).ok_or_else(unsupported)?;
+    }
+
+    Ok(interval)
+}
+
+/// Parse the time of an interval, `[+-]HH:MM:SS[.ffffff]`, into microseconds.
+fn parse_interval_time(time: &str) -> Option<i64> {
+    let (negative, time) = match time.as_bytes().first()? {
+        b'-' => (true, &time[1..]),
+        b'+' => (false, &time[1..]),
+        _ => (false, time),
+    };
+
+    let mut parts = time.splitn(3, ':');
+    let hours = parse_digits(parts.next()?)?;
+    let minutes = parse_digits(parts.next()?)?;
+    let seconds = parts.next()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds = parse_digits(seconds)?;
+
+    // the fraction has up to 6 digits, of which trailing zeros are omitted
+    let fraction = match fraction.len() {
+        0 => 0,
+        len @ 1..=6 => (len..6).fold(parse_digits(fraction)?, |fraction, _| fraction * 10),
+        _ => return None,
+    };
+
+    let microseconds = hours
+        .checked_mul(60)?
+        .checked_add(minutes)?
+        .checked_mul(60)?
+        .checked_add(seconds)?
+        .checked_mul(1_000_000)?
+        .checked_add(fraction)?;
+
+    Some(if negative {
+        -microseconds
+    } else {
+        microseconds
+    })
+}
+
+/// Parse a non-empty string of ASCII digits.
+fn parse_digits(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
     }
+
+    digits.parse().ok()
 }
 
 impl Encode<'_, Postgres> for PgInterval {
@@ -310,6 +387,47 @@ fn test_encode_interval() {
     );
 }
 
+#[test]
+fn test_decode_interval_text() {
+    let interval = |months, days, microseconds| PgInterval {
+        months,
+        days,
+        microseconds,
+    };
+
+    for (text, expected) in [
+        ("00:00:00", interval(0, 0, 0)),
+        ("1 day", interval(0, 1, 0)),
+        (
+            "1 year 2 mons 3 days 04:05:06.789",
+            interval(14, 3, 14_706_789_000),
+        ),
+        (
+            "-1 years -2 mons +3 days -04:05:06",
+            interval(-14, 3, -14_706_000_000),
+        ),
+        ("-00:00:00.000001", interval(0, 0, -1)),
+        ("1000:00:00", interval(0, 0, 3_600_000_000_000)),
+        ("178956970 years 7 mons", interval(i32::MAX, 0, 0)),
+    ] {
+        assert_eq!(parse_interval(text).unwrap(), expected, "{text}");
+    }
+
+    for text in [
+        "",
+        "1",
+        "1 week",
+        "P1Y2M3DT4H5M6S",
+        "@ 1 day",
+        "04:05",
+        "04:05:06.1234567",
+        "04:-05:06",
+        "178956971 years",
+    ] {
+        assert!(parse_interval(text).is_err(), "{text}");
+    }
+}
+
 #[test]
 fn test_pginterval_std() {
     // Case for positive duration
@@ -85,15 +85,50 @@ where
         let mut buf = value.as_bytes()?;
 
         if value.format() == PgValueFormat::Binary && value.type_info == PgTypeInfo::JSONB {
-            assert_eq!(
-                buf[0], 1,
-                "unsupported JSONB format version {}; please open an issue",
-                buf[0]
-            );
-
-            buf = &buf[1..];
+            buf = match buf.split_first() {
+                Some((1, data)) => data,
+
+                Some((version, _)) => {
+                    return Err(format!(
+                        "unsupported JSONB format version {version}; please open an issue"
+                    )
+                    .into());
+                }
+
+                None => return Err("empty binary JSONB value".into()),
+            };
         }
 
         serde_json::from_slice(buf).map(Json).map_err(Into::into)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_jsonb(value: &[u8]) -> Result<JsonValue, BoxDynError> {
+        <JsonValue as Decode<Postgres>>::decode(PgValueRef {
+            value: Some(value),
+            row: None,
+            type_info: PgTypeInfo::JSONB,
+            format: PgValueFormat::Binary,
+        })
+    }
+
+    #[test]
+    fn it_decodes_binary_jsonb() {
+        assert_eq!(
+            decode_jsonb(b"\x01{\"a\": 1}").unwrap(),
+            serde_json::json!({ "a": 1 })
+        );
+    }
+
+    #[test]
+    fn it_rejects_unsupported_binary_jsonb() {
+        let error = decode_jsonb(b"\x02{\"a\": 1}").unwrap_err();
+        assert!(error.to_string().contains("version 2"), "{error}");
+
+        assert!(decode_jsonb(b"").is_err());
+    }
+}
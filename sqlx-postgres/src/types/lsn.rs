@@ -0,0 +1,263 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::decode::Decode;
+use crate::encode::{Encode, IsNull};
+use crate::error::BoxDynError;
+use crate::types::Type;
+use crate::{PgArgumentBuffer, PgHasArrayType, PgTypeInfo, PgValueFormat, PgValueRef, Postgres};
+
+/// The PostgreSQL [`PG_LSN`] type, a _Log Sequence Number_ (LSN).
+///
+/// An LSN is a 64-bit byte position in the write-ahead log. It is used by the
+/// replication protocol to describe where a stream starts, how far a client has
+/// received and flushed data, and where a replication slot currently stands.
+///
+/// The text representation is two hexadecimal numbers separated by a slash, the upper
+/// and lower 32 bits of the position respectively, e.g. `16/B374D848`.
+///
+/// LSNs are ordered by their unsigned position, so all positions of the replication protocol,
+/// which are `PgLsn`s as well, compare directly, e.g. the `end_lsn` of a commit with the
+/// `wal_end` of a keepalive or a stored checkpoint, and [`cmp::max`][std::cmp::max] and
+/// [`cmp::min`][std::cmp::min] track the highest or lowest position seen.
+///
+/// `PgLsn` is `Copy`, `Eq` and `Hash` as well, consistently with the position it wraps, so it
+/// keys a `HashMap`, `BTreeMap` or `HashSet` directly, e.g. to deduplicate changes by position.
+///
+/// [`PG_LSN`]: https://www.postgresql.org/docs/current/datatype-pg-lsn.html
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct PgLsn(
+    /// The raw byte position in the write-ahead log.
+    pub u64,
+);
+
+impl PgLsn {
+    /// The invalid (zero) LSN, `0/0`.
+    ///
+    /// Passed as the start position of `START_REPLICATION`, this lets the server pick the
+    /// position to start from (the slot's `confirmed_flush_lsn` for logical slots).
+    pub const INVALID: Self = Self(0);
+
+    /// The signed distance in bytes from `other` to this LSN, like `pg_wal_lsn_diff()`:
+    /// positive if this LSN is ahead of `other`, negative if it is behind.
+    ///
+    /// Distances beyond the range of an `i64` saturate at [`i64::MAX`] or [`i64::MIN`].
+    pub fn diff(self, other: PgLsn) -> i64 {
+        if self >= other {
+            i64::try_from(self.0 - other.0).unwrap_or(i64::MAX)
+        } else {
+            i64::try_from(other.0 - self.0).map_or(i64::MIN, |distance| -distance)
+        }
+    }
+
+    /// The name of the WAL segment file on `timeline` that contains this LSN, as found in
+    /// `pg_wal`, e.g. `000000010000000000000001`.
+    ///
+    /// `segment_size` is the WAL segment size of the cluster (`wal_segment_size`, 16 MiB
+    /// unless changed with `initdb --wal-segsize`), in bytes.
+    ///
+    /// Unlike `pg_walfile_name()`, which returns the segment that ends at an LSN on a segment
+    /// boundary, this returns the segment that starts there.
+    ///
+    /// # Panics
+    ///
+    /// If `segment_size` is not a power of two between 1 MiB and 1 GiB, like Postgres
+    /// requires.
+    pub fn segment_name(&self, timeline: u32, segment_size: u64) -> String {
+        assert!(
+            segment_size.is_power_of_two() && (1 << 20..=1 << 30).contains(&segment_size),
+            "invalid WAL segment size {segment_size}"
+        );
+
+        let segment = self.0 / segment_size;
+        let segments_per_id = 0x1_0000_0000 / segment_size;
+
+        format!(
+            "{:08X}{:08X}{:08X}",
+            timeline,
+            segment / segments_per_id,
+            segment % segments_per_id
+        )
+    }
+}
+
+impl Display for PgLsn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}/{:X}", self.0 >> 32, self.0 & 0xFFFF_FFFF)
+    }
+}
+
+impl FromStr for PgLsn {
+    type Err = BoxDynError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hi, lo) = s
+            .split_once('/')
+            .ok_or_else(|| format!("error parsing PG_LSN: expected `XXX/XXX`, got {s:?}"))?;
+
+        let hi = u32::from_str_radix(hi, 16)
+            .map_err(|e| format!("error parsing PG_LSN: invalid upper half in {s:?}: {e}"))?;
+        let lo = u32::from_str_radix(lo, 16)
+            .map_err(|e| format!("error parsing PG_LSN: invalid lower half in {s:?}: {e}"))?;
+
+        Ok(PgLsn((u64::from(hi) << 32) | u64::from(lo)))
+    }
+}
+
+impl From<u64> for PgLsn {
+    fn from(lsn: u64) -> Self {
+        PgLsn(lsn)
+    }
+}
+
+impl From<PgLsn> for u64 {
+    fn from(lsn: PgLsn) -> Self {
+        lsn.0
+    }
+}
+
+impl Type<Postgres> for PgLsn {
+    fn type_info() -> PgTypeInfo {
+        PgTypeInfo::PG_LSN
+    }
+}
+
+impl PgHasArrayType for PgLsn {
+    fn array_type_info() -> PgTypeInfo {
+        PgTypeInfo::PG_LSN_ARRAY
+    }
+}
+
+impl Encode<'_, Postgres> for PgLsn {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> Result<IsNull, BoxDynError> {
+        buf.extend(&self.0.to_be_bytes());
+
+        Ok(IsNull::No)
+    }
+}
+
+impl Decode<'_, Postgres> for PgLsn {
+    fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
+        match value.format() {
+            PgValueFormat::Binary => Ok(PgLsn(BigEndian::read_u64(value.as_bytes()?))),
+            PgValueFormat::Text => value.as_str()?.parse(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeMap, HashSet};
+    use std::hash::{Hash, Hasher};
+
+    use super::PgLsn;
+
+    #[test]
+    fn it_formats_lsn() {
+        assert_eq!(PgLsn(0).to_string(), "0/0");
+        assert_eq!(PgLsn(0x16_B374_D848).to_string(), "16/B374D848");
+        assert_eq!(PgLsn(u64::MAX).to_string(), "FFFFFFFF/FFFFFFFF");
+    }
+
+    #[test]
+    fn it_parses_lsn() {
+        assert_eq!("0/0".parse::<PgLsn>().unwrap(), PgLsn(0));
+        assert_eq!(
+            "16/B374D848".parse::<PgLsn>().unwrap(),
+            PgLsn(0x16_B374_D848)
+        );
+        assert_eq!(
+            "16/b374d848".parse::<PgLsn>().unwrap(),
+            PgLsn(0x16_B374_D848)
+        );
+
+        assert!("16B374D848".parse::<PgLsn>().is_err());
+        assert!("1/2/3".parse::<PgLsn>().is_err());
+        assert!("100000000/0".parse::<PgLsn>().is_err());
+    }
+
+    #[test]
+    fn it_orders_lsn_positions() {
+        let high = PgLsn(0x8000_0000_0000_0000);
+
+        // not negative, as it would be as an `i64`
+        assert!(high > PgLsn(0x16_B374_D848));
+        assert!(PgLsn::INVALID < PgLsn(1));
+
+        assert_eq!(cmp::max(PgLsn(0x16_B374_D848), high), high);
+        assert_eq!(cmp::min(PgLsn(0x16_B374_D848), high), PgLsn(0x16_B374_D848));
+        assert_eq!(
+            [PgLsn(3), PgLsn(1), PgLsn(2)].into_iter().max(),
+            Some(PgLsn(3))
+        );
+    }
+
+    #[test]
+    fn it_diffs_lsn_positions() {
+        assert_eq!(PgLsn(0x16_B374_D848).diff(PgLsn(0x16_B374_D800)), 0x48);
+        assert_eq!(PgLsn(0x16_B374_D800).diff(PgLsn(0x16_B374_D848)), -0x48);
+        assert_eq!(PgLsn(1).diff(PgLsn(1)), 0);
+
+        assert_eq!(PgLsn(u64::MAX).diff(PgLsn::INVALID), i64::MAX);
+        assert_eq!(PgLsn::INVALID.diff(PgLsn(u64::MAX)), i64::MIN);
+    }
+
+    #[test]
+    fn it_keys_maps_by_lsn() {
+        let hash = |lsn: PgLsn| {
+            let mut hasher = DefaultHasher::new();
+            lsn.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // equal positions hash equally, however they were created
+        let parsed: PgLsn = "16/B374D848".parse().unwrap();
+        assert_eq!(parsed, PgLsn::from(0x16_B374_D848));
+        assert_eq!(hash(parsed), hash(PgLsn::from(0x16_B374_D848)));
+
+        let seen: HashSet<_> = [PgLsn(2), PgLsn(1), PgLsn(2)].into_iter().collect();
+        assert_eq!(seen.len(), 2);
+        assert!(seen.contains(&PgLsn(1)));
+
+        let by_position: BTreeMap<_, _> = [(PgLsn(2), "b"), (PgLsn(1), "a")].into();
+        assert_eq!(
+            by_position.keys().copied().collect::<Vec<_>>(),
+            [PgLsn(1), PgLsn(2)]
+        );
+    }
+
+    // the names returned by `pg_walfile_name()` on PostgreSQL 15
+    #[test]
+    fn it_names_wal_segments() {
+        const MIB_16: u64 = 16 << 20;
+
+        let lsn: PgLsn = "0/16B3740".parse().unwrap();
+        assert_eq!(lsn.segment_name(1, MIB_16), "000000010000000000000001");
+
+        let lsn: PgLsn = "16/B374D848".parse().unwrap();
+        assert_eq!(lsn.segment_name(1, MIB_16), "0000000100000016000000B3");
+        assert_eq!(lsn.segment_name(2, MIB_16), "0000000200000016000000B3");
+
+        let lsn = PgLsn(u64::MAX);
+        assert_eq!(lsn.segment_name(1, MIB_16), "00000001FFFFFFFF000000FF");
+
+        // a segment boundary is the start of the next segment
+        let lsn: PgLsn = "1/FF000000".parse().unwrap();
+        assert_eq!(lsn.segment_name(1, MIB_16), "0000000100000001000000FF");
+        assert_eq!(PgLsn(0).segment_name(1, MIB_16), "000000010000000000000000");
+
+        // 1 GiB segments, four per upper half of the LSN
+        let lsn: PgLsn = "1/C0000001".parse().unwrap();
+        assert_eq!(lsn.segment_name(1, 1 << 30), "000000010000000100000003");
+    }
+
+    #[test]
+    #[should_panic = "invalid WAL segment size"]
+    fn it_rejects_invalid_segment_sizes() {
+        PgLsn(0).segment_name(1, 3 << 20);
+    }
+}
@@ -24,6 +24,7 @@
 //! | [`PgPoint]                            | POINT                                                |
 //! | [`PgLine]                             | LINE                                                 |
 //! | [`PgHstore`]                          | HSTORE                                               |
+//! | [`PgLsn`]                             | PG_LSN                                               |
 //!
 //! <sup>1</sup> SQLx generally considers `CITEXT` to be compatible with `String`, `&str`, etc.,
 //! but this wrapper type is available for edge cases, such as `CITEXT[]` which Postgres
@@ -194,6 +195,7 @@ mod hstore;
 mod int;
 mod interval;
 mod lquery;
+mod lsn;
 mod ltree;
 // Not behind a Cargo feature because we require JSON in the driver implementation.
 mod json;
@@ -254,6 +256,7 @@ pub use lquery::PgLQuery;
 pub use lquery::PgLQueryLevel;
 pub use lquery::PgLQueryVariant;
 pub use lquery::PgLQueryVariantFlag;
+pub use lsn::PgLsn;
 pub use ltree::PgLTree;
 pub use ltree::PgLTreeLabel;
 pub use ltree::PgLTreeParseError;
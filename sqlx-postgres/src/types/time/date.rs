@@ -40,7 +40,12 @@ impl<'r> Decode<'r, Postgres> for Date {
             PgValueFormat::Binary => {
                 // DATE is encoded as the days since epoch
                 let days: i32 = Decode::<Postgres>::decode(value)?;
-                PG_EPOCH + Duration::days(days.into())
+
+                // `infinity` and `-infinity` are sent as `i32::MAX` and `i32::MIN`, which are out
+                // of range like any other date that `time` cannot represent
+                PG_EPOCH
+                    .checked_add(Duration::days(days.into()))
+                    .ok_or_else(|| format!("DATE value of {days} days is out of range for Date"))?
             }
 
             PgValueFormat::Text => Date::parse(
@@ -57,15 +57,25 @@ impl<'r> Decode<'r, Postgres> for PrimitiveDateTime {
             PgValueFormat::Binary => {
                 // TIMESTAMP is encoded as the microseconds since the epoch
                 let us = Decode::<Postgres>::decode(value)?;
-                PG_EPOCH.midnight() + Duration::microseconds(us)
+
+                // this includes `infinity` and `-infinity`, sent as `i64::MAX` and `i64::MIN`
+                PG_EPOCH
+                    .midnight()
+                    .checked_add(Duration::microseconds(us))
+                    .ok_or_else(|| {
+                        format!("TIMESTAMP value of {us} microseconds is out of range for PrimitiveDateTime")
+                    })?
             }
 
             PgValueFormat::Text => {
                 let s = value.as_str()?;
 
-                // If there is no decimal point we need to add one.
+                // If there is no decimal point we need to add one, before the time-zone
+                // specifier if there is one.
                 let s = if s.contains('.') {
                     Cow::Borrowed(s)
+                } else if let Some((datetime, offset)) = s.split_once('+') {
+                    Cow::Owned(format!("{datetime}.0+{offset}"))
                 } else {
                     Cow::Owned(format!("{s}.0"))
                 };
@@ -0,0 +1,187 @@
+//! An end-to-end smoke test of logical replication with `pgoutput`, from the server writing
+//! changes to the stream decoding them and confirming its position.
+//!
+//! Unlike the tests in `replication.rs`, this test is skipped if `DATABASE_URL` is not set or the
+//! server doesn't run with `wal_level = logical`, so it can run in any CI job.
+
+use sqlx::postgres::replication::{
+    CreateReplicationSlot, LogicalReplication, PgOutputOptions, PgReplicationConnection,
+    SnapshotAction,
+};
+use sqlx::postgres::types::PgLsn;
+use sqlx::postgres::PgConnection;
+use sqlx::{Connection, Executor};
+use std::env;
+use std::time::Duration;
+
+/// Connect to `DATABASE_URL`, or return `None` if the test should be skipped.
+async fn connect() -> anyhow::Result<Option<PgConnection>> {
+    let Ok(url) = env::var("DATABASE_URL") else {
+        eprintln!("skipping replication smoke test: DATABASE_URL is not set");
+        return Ok(None);
+    };
+
+    let mut conn = PgConnection::connect(&url).await?;
+
+    let wal_level: String = sqlx::query_scalar("SHOW wal_level")
+        .fetch_one(&mut conn)
+        .await?;
+
+    if wal_level != "logical" {
+        eprintln!("skipping replication smoke test: wal_level is {wal_level}, not logical");
+        return Ok(None);
+    }
+
+    Ok(Some(conn))
+}
+
+#[sqlx_macros::test]
+async fn it_streams_changes_end_to_end() -> anyhow::Result<()> {
+    let Some(mut writer) = connect().await? else {
+        return Ok(());
+    };
+
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_smoke_pub;
+DROP TABLE IF EXISTS replication_smoke;
+CREATE TABLE replication_smoke (id INT PRIMARY KEY, name TEXT);
+CREATE PUBLICATION replication_smoke_pub FOR TABLE replication_smoke;
+"#,
+        )
+        .await?;
+
+    let mut conn = PgReplicationConnection::connect(&env::var("DATABASE_URL")?).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_smoke_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_smoke_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_smoke_pub"]),
+        )
+        .await?;
+
+    // one transaction each
+    for statement in [
+        "INSERT INTO replication_smoke (id, name) VALUES (1, 'foo'), (2, 'bar')",
+        "UPDATE replication_smoke SET name = 'baz' WHERE id = 1",
+        "DELETE FROM replication_smoke WHERE id = 2",
+        "TRUNCATE replication_smoke",
+    ] {
+        writer.execute(statement).await?;
+    }
+
+    let mut events = Vec::new();
+    let mut commits = 0;
+    let mut end_lsn = PgLsn::INVALID;
+
+    while commits < 4 {
+        let message = tokio::time::timeout(Duration::from_secs(10), stream.recv())
+            .await??
+            .ok_or_else(|| anyhow::anyhow!("stream ended"))?;
+
+        let event = match message {
+            LogicalReplication::Begin(_) => "begin".to_owned(),
+            LogicalReplication::Relation(relation) => format!("relation {}", relation.name),
+            LogicalReplication::Insert(insert) => {
+                // only set in streamed transactions
+                assert_eq!(insert.xid, None);
+
+                format!(
+                    "insert {} {}",
+                    insert.new_data[0].as_str().unwrap_or_default(),
+                    insert.new_data[1].as_str().unwrap_or_default()
+                )
+            }
+            LogicalReplication::Update(update) => {
+                assert_eq!(update.xid, None);
+
+                format!(
+                    "update {} {}",
+                    update.new_data[0].as_str().unwrap_or_default(),
+                    update.new_data[1].as_str().unwrap_or_default()
+                )
+            }
+            LogicalReplication::Delete(delete) => {
+                assert_eq!(delete.xid, None);
+
+                let key = delete
+                    .key_data
+                    .ok_or_else(|| anyhow::anyhow!("no key sent for delete"))?;
+
+                format!("delete {}", key[0].as_str().unwrap_or_default())
+            }
+            LogicalReplication::Truncate(truncate) => {
+                assert_eq!(truncate.xid, None);
+
+                let names = truncate
+                    .relations(|id| stream.relation(id))
+                    .map(|(_, relation)| relation.map(|relation| relation.name.clone()))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| anyhow::anyhow!("truncated relation not cached"))?;
+
+                assert_eq!(
+                    truncate.to_sql(|id| stream.relation(id))?,
+                    r#"TRUNCATE "public"."replication_smoke""#
+                );
+
+                format!("truncate {}", names.join(","))
+            }
+            LogicalReplication::Commit(commit) => {
+                commits += 1;
+                end_lsn = commit.end_lsn;
+
+                "commit".to_owned()
+            }
+            message => anyhow::bail!("unexpected message {message:?}"),
+        };
+
+        events.push(event);
+    }
+
+    assert_eq!(
+        events,
+        [
+            "begin",
+            "relation replication_smoke",
+            "insert 1 foo",
+            "insert 2 bar",
+            "commit",
+            "begin",
+            "update 1 baz",
+            "commit",
+            "begin",
+            "delete 2",
+            "commit",
+            "begin",
+            // sent again, as truncating invalidates the relation in the cache of the server
+            "relation replication_smoke",
+            "truncate replication_smoke",
+            "commit",
+        ]
+    );
+
+    // the status update sent when finishing advances the slot
+    stream.set_confirmed_lsn(end_lsn);
+    let conn = stream.finish().await?;
+
+    let confirmed: PgLsn = sqlx::query_scalar(
+        "SELECT confirmed_flush_lsn FROM pg_replication_slots \
+         WHERE slot_name = 'replication_smoke_slot'",
+    )
+    .fetch_one(&mut writer)
+    .await?;
+    assert!(confirmed >= end_lsn);
+
+    conn.close().await?;
+
+    Ok(())
+}
@@ -0,0 +1,5648 @@
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::TryStreamExt;
+use sqlx::postgres::replication::{
+    advance_replication_slot, copy_publication_snapshot, copy_publication_table,
+    create_publication_if_missing, decode_logical, enum_types, import_snapshot,
+    publication_row_filters, publication_tables, replication_settings, retained_wal_bytes,
+    slot_position_diff, ApplyStatement, BeforeImage, Change, ChangeSink, ContiguityCheck,
+    CreatePublication, CreateReplicationSlot, DecodeErrorPolicy, DeliveryMode, FromReplicationRow,
+    Insert, LogicalDecodeContext, LogicalReplication, OffsetStore, PgOutputOptions,
+    PgReplicationConnection, PgTableOffsetStore, PhysicalReplication, PrimaryKeepalive, Projection,
+    PublicationWatcher, ReconnectingStream, Relation, ReplayReader, ReplicaIdentity,
+    ReplicationError, ReplicationManager, ReplicationObserver, RetryPolicy, ServerRole,
+    SnapshotAction, StartPosition, StreamEvent, SystemValue, TupleData, Tuples, XLogData,
+};
+use sqlx::postgres::types::{Oid, PgCiText, PgHstore, PgLsn};
+use sqlx::postgres::{PgConnectOptions, PgPool, Postgres};
+use sqlx::{Connection, Executor};
+use sqlx_test::new;
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+async fn replication_connection() -> anyhow::Result<PgReplicationConnection> {
+    Ok(PgReplicationConnection::connect(&env::var("DATABASE_URL")?).await?)
+}
+
+/// Create a table and a publication for it, dropping any leftovers from previous runs.
+async fn setup_publication(table: &str) -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+
+    conn.execute(&*format!(
+        r#"
+DROP PUBLICATION IF EXISTS {table}_pub;
+DROP TABLE IF EXISTS {table};
+CREATE TABLE {table} (id INT PRIMARY KEY, name TEXT);
+CREATE PUBLICATION {table}_pub FOR TABLE {table};
+"#
+    ))
+    .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_identifies_system() -> anyhow::Result<()> {
+    let mut conn = replication_connection().await?;
+
+    assert!(conn.server_version_num().is_some());
+    assert!(conn.parameter_status("server_version").is_some());
+    assert_eq!(conn.parameter_status("integer_datetimes"), Some("on"));
+    assert!(conn.server_encoding().is_some());
+
+    let system = conn.identify_system().await?;
+
+    assert!(!system.systemid.is_empty());
+    assert!(system.timeline >= 1);
+    assert!(system.xlogpos > PgLsn::INVALID);
+
+    // the test server is a primary
+    assert!(!conn.is_in_recovery().await?);
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_sets_application_name() -> anyhow::Result<()> {
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+
+    if options.get_application_name().is_none() {
+        let conn = PgReplicationConnection::connect_with(&options).await?;
+        assert!(conn
+            .parameter_status("application_name")
+            .is_some_and(|name| name.starts_with("sqlx-postgres ")));
+        conn.close().await?;
+    }
+
+    let conn =
+        PgReplicationConnection::connect_with(&options.application_name("orders-consumer")).await?;
+    assert_eq!(
+        conn.parameter_status("application_name"),
+        Some("orders-consumer")
+    );
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_passes_slot_options_through() -> anyhow::Result<()> {
+    let mut conn = replication_connection().await?;
+
+    // the typed options are sent in the parenthesized syntax along with the passed through ones
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_slot_options_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport)
+                .option("unknown_option", "true"),
+        )
+        .await;
+
+    let error = slot.expect_err("unknown option accepted").to_string();
+    assert!(
+        error.contains("unrecognized option: unknown_option"),
+        "{error}"
+    );
+
+    let mut conn = replication_connection().await?;
+
+    let error = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_slot_options_slot", "pgoutput")
+                .option("two_phase", "true"),
+        )
+        .await
+        .expect_err("colliding option accepted");
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_retries_transient_setup_failures() -> anyhow::Result<()> {
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+
+    let (conn, slot) = RetryPolicy::new()
+        .create_replication_slot(
+            &options,
+            &CreateReplicationSlot::logical("replication_retry_slot", "pgoutput").temporary(true),
+        )
+        .await?;
+    assert_eq!(slot.slot_name, "replication_retry_slot");
+
+    // the slot exists as long as `conn` is open, which is a permanent error
+    let start = Instant::now();
+    let error = RetryPolicy::new()
+        .initial_backoff(Duration::from_secs(30))
+        .create_replication_slot(
+            &options,
+            &CreateReplicationSlot::logical("replication_retry_slot", "pgoutput").temporary(true),
+        )
+        .await
+        .expect_err("slot created twice");
+    assert!(error.to_string().contains("already exists"), "{error}");
+    assert!(start.elapsed() < Duration::from_secs(30));
+
+    conn.close().await?;
+
+    // nothing listens on port 1, so every attempt fails to connect
+    let start = Instant::now();
+    let result = RetryPolicy::new()
+        .max_attempts(3)
+        .initial_backoff(Duration::from_millis(50))
+        .connect(&options.clone().port(1))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ReplicationError::Sqlx(sqlx::Error::Io(_)))
+    ));
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_changes() -> anyhow::Result<()> {
+    setup_publication("replication_stream").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_stream_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    assert_eq!(slot.slot_name, "replication_stream_slot");
+    assert_eq!(slot.output_plugin.as_deref(), Some("pgoutput"));
+    assert!(slot.snapshot_name.is_none());
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_stream_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_stream_pub"]),
+        )
+        .await?;
+
+    assert!(!stream.is_ready());
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_stream (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let Some(LogicalReplication::Begin(begin)) = stream.recv().await? else {
+        panic!("expected Begin");
+    };
+
+    assert!(stream.is_ready());
+
+    let Some(LogicalReplication::Relation(relation)) = stream.recv().await? else {
+        panic!("expected Relation");
+    };
+
+    assert_eq!(relation.namespace, "public");
+    assert_eq!(relation.name, "replication_stream");
+    assert_eq!(relation.columns.len(), 2);
+    assert_eq!(relation.columns[0].name, "id");
+    assert_eq!(relation.columns[1].name, "name");
+
+    let cached = stream
+        .relation(relation.relation_id)
+        .expect("relation not cached");
+    assert_eq!(cached.name, "replication_stream");
+
+    let Some(LogicalReplication::Insert(insert)) = stream.recv().await? else {
+        panic!("expected Insert");
+    };
+
+    assert_eq!(insert.relation_id, relation.relation_id);
+    assert_eq!(insert.new_data[0].as_str(), Some("1"));
+    assert_eq!(insert.new_data[1].as_str(), Some("foo"));
+
+    let Some(LogicalReplication::Commit(commit)) = stream.recv().await? else {
+        panic!("expected Commit");
+    };
+
+    assert_eq!(commit.commit_lsn, begin.final_lsn);
+    // the end of the transaction is past its commit record
+    assert!(commit.end_lsn > commit.commit_lsn);
+
+    stream.set_confirmed_lsn(commit.end_lsn);
+    assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+
+    // positions lower than the confirmed one are ignored
+    stream.set_confirmed_lsn(begin.final_lsn);
+    assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+
+    let conn = stream.finish().await?;
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_unsupported_plugin() -> anyhow::Result<()> {
+    setup_publication("replication_mismatch").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_mismatch_slot", "test_decoding")
+            .temporary(true),
+    )
+    .await?;
+
+    let error = conn
+        .start_logical_replication(
+            "replication_mismatch_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_mismatch_pub"]),
+        )
+        .await
+        .unwrap_err();
+
+    // `test_decoding` is not a plugin whose messages can be decoded
+    match error {
+        ReplicationError::UnsupportedPlugin {
+            slot,
+            plugin,
+            known,
+        } => {
+            assert_eq!(slot, "replication_mismatch_slot");
+            assert_eq!(plugin, "test_decoding");
+            assert!(known.iter().any(|known| known == "pgoutput"));
+        }
+        error => panic!("expected UnsupportedPlugin, got {error:?}"),
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_missing_slot() -> anyhow::Result<()> {
+    let conn = replication_connection().await?;
+
+    let error = conn
+        .start_logical_replication(
+            "replication_missing_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_missing_pub"]),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::SlotNotFound { ref slot } if slot == "replication_missing_slot"),
+        "expected SlotNotFound, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_unsupported_options() -> anyhow::Result<()> {
+    setup_publication("replication_options").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_options_slot", "pgoutput").temporary(true),
+    )
+    .await?;
+
+    // `streaming` requires protocol version 2
+    let error = conn
+        .start_logical_replication(
+            "replication_options_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_options_pub"]).streaming(true),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::StartReplication { ref plugin, .. } if plugin == "pgoutput"),
+        "expected StartReplication, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_two_phase_mismatch() -> anyhow::Result<()> {
+    setup_publication("replication_two_phase").await?;
+
+    let mut conn = replication_connection().await?;
+
+    // `two_phase` slots were added in Postgres 14
+    if conn.server_version_num().is_some_and(|v| v < 140000) {
+        return Ok(());
+    }
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_two_phase_slot", "pgoutput")
+                .temporary(true)
+                .two_phase(true),
+        )
+        .await?;
+    assert!(slot.two_phase);
+
+    let slot = conn
+        .replication_slot("replication_two_phase_slot")
+        .await?
+        .expect("slot not found");
+    assert!(slot.two_phase);
+
+    let error = conn
+        .start_logical_replication(
+            "replication_two_phase_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_two_phase_pub"]),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(
+            error,
+            ReplicationError::TwoPhaseMismatch { ref slot, slot_two_phase: true }
+                if slot == "replication_two_phase_slot"
+        ),
+        "expected TwoPhaseMismatch, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reassembles_streamed_transactions() -> anyhow::Result<()> {
+    const ROWS: i32 = 5000;
+
+    setup_publication("replication_streaming").await?;
+
+    // a small `logical_decoding_work_mem` makes the server stream the large transaction
+    let options = env::var("DATABASE_URL")?
+        .parse::<PgConnectOptions>()?
+        .options([("logical_decoding_work_mem", "64kB")]);
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_streaming_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_streaming_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_streaming_pub"])
+                .proto_version(2)
+                .streaming(true),
+        )
+        .await?;
+
+    // interleave a small transaction with the large one, which commits first
+    let mut large = new::<Postgres>().await?;
+    let mut small = new::<Postgres>().await?;
+
+    let mut tx = large.begin().await?;
+
+    tx.execute(&*format!(
+        "INSERT INTO replication_streaming (id, name) \
+         SELECT i, 'large ' || i FROM generate_series(1, {}) i",
+        ROWS / 2
+    ))
+    .await?;
+
+    small
+        .execute("INSERT INTO replication_streaming (id, name) VALUES (0, 'small')")
+        .await?;
+
+    tx.execute(&*format!(
+        "INSERT INTO replication_streaming (id, name) \
+         SELECT i, 'large ' || i FROM generate_series({}, {}) i",
+        ROWS / 2 + 1,
+        ROWS
+    ))
+    .await?;
+
+    tx.commit().await?;
+
+    // route the changes to their transaction by xid
+    let mut in_progress: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut current: Option<(u32, Vec<String>)> = None;
+    let mut committed: Vec<(u32, Vec<String>)> = Vec::new();
+    let mut streamed = false;
+
+    while committed.len() < 2 {
+        let message = stream.recv().await?.expect("stream ended unexpectedly");
+
+        match message {
+            LogicalReplication::Begin(begin) => current = Some((begin.xid, Vec::new())),
+            LogicalReplication::Commit(_) => {
+                committed.push(current.take().expect("Commit without Begin"));
+            }
+            LogicalReplication::StreamStart(start) => {
+                streamed = true;
+
+                if start.first_segment {
+                    assert!(in_progress.insert(start.xid, Vec::new()).is_none());
+                }
+            }
+            LogicalReplication::StreamCommit(commit) => {
+                let rows = in_progress
+                    .remove(&commit.xid)
+                    .expect("StreamCommit for unknown xid");
+
+                committed.push((commit.xid, rows));
+            }
+            LogicalReplication::StreamAbort(abort) => {
+                panic!("unexpected StreamAbort: {abort:?}");
+            }
+            LogicalReplication::Insert(insert) => {
+                let name = insert.new_data[1].as_str().unwrap().to_owned();
+
+                match insert.xid {
+                    Some(xid) => in_progress
+                        .get_mut(&xid)
+                        .expect("Insert for unknown streamed xid")
+                        .push(name),
+                    None => current
+                        .as_mut()
+                        .expect("Insert outside of a transaction")
+                        .1
+                        .push(name),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    assert!(streamed, "expected the large transaction to be streamed");
+    assert!(in_progress.is_empty());
+
+    let (small_xid, small_rows) = &committed[0];
+    let (large_xid, large_rows) = &committed[1];
+
+    assert_ne!(small_xid, large_xid);
+    assert_eq!(small_rows, &["small"]);
+
+    let expected: Vec<String> = (1..=ROWS).map(|i| format!("large {i}")).collect();
+    assert_eq!(large_rows, &expected);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_sends_initial_status_update() -> anyhow::Result<()> {
+    setup_publication("replication_initial").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_initial_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let position = conn.identify_system().await?.xlogpos;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_initial_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_initial_pub"]),
+        )
+        .await?;
+
+    stream.set_initial_status(Some(position));
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_initial (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    assert!(matches!(
+        stream.recv().await?,
+        Some(LogicalReplication::Begin(_))
+    ));
+
+    let confirmed: String = sqlx::query_scalar(
+        "SELECT confirmed_flush_lsn::text FROM pg_replication_slots \
+         WHERE slot_name = 'replication_initial_slot'",
+    )
+    .fetch_one(&mut writer)
+    .await?;
+
+    assert!(confirmed.parse::<PgLsn>().unwrap() >= position);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_imports_exported_snapshots() -> anyhow::Result<()> {
+    setup_publication("replication_snapshot").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_snapshot (id, name) VALUES (1, 'before')")
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_snapshot_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::Export),
+        )
+        .await?;
+
+    writer
+        .execute("INSERT INTO replication_snapshot (id, name) VALUES (2, 'after')")
+        .await?;
+
+    // the copy only sees the rows from before the consistent point of the slot
+    let mut copier = new::<Postgres>().await?;
+    let mut transaction = slot.import_snapshot(&mut copier).await?;
+
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM replication_snapshot ORDER BY id")
+        .fetch_all(&mut *transaction)
+        .await?;
+    assert_eq!(ids, [1]);
+
+    transaction.commit().await?;
+
+    let error = import_snapshot(&mut copier, "00000003-0000001B-1")
+        .await
+        .expect_err("unknown snapshot imported");
+    assert!(
+        matches!(error, ReplicationError::SnapshotImport { ref snapshot, .. } if snapshot == "00000003-0000001B-1"),
+        "{error}"
+    );
+
+    // a snapshot cannot be imported after another query of the transaction
+    let mut transaction = copier.begin().await?;
+    let error = import_snapshot(&mut transaction, slot.snapshot_name.as_deref().unwrap())
+        .await
+        .expect_err("snapshot imported within a transaction");
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+    transaction.rollback().await?;
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_advances_slot() -> anyhow::Result<()> {
+    setup_publication("replication_advance").await?;
+
+    let mut conn = new::<Postgres>().await?;
+
+    // a temporary slot can only be advanced by the session that created it
+    let start: PgLsn = sqlx::query_scalar(
+        "SELECT lsn FROM pg_create_logical_replication_slot('replication_advance_slot', 'pgoutput', true)",
+    )
+    .fetch_one(&mut conn)
+    .await?;
+
+    conn.execute("INSERT INTO replication_advance (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let current: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut conn)
+        .await?;
+
+    let end = advance_replication_slot(&mut conn, "replication_advance_slot", current).await?;
+
+    assert!(end > start);
+    assert!(end <= current);
+
+    let error = advance_replication_slot(&mut conn, "replication_advance_slot", PgLsn(1))
+        .await
+        .unwrap_err();
+
+    match error {
+        ReplicationError::AdvanceBackwards {
+            slot,
+            upto,
+            minimum,
+        } => {
+            assert_eq!(slot, "replication_advance_slot");
+            assert_eq!(upto, PgLsn(1));
+            assert_eq!(minimum, Some(end));
+        }
+        error => panic!("expected AdvanceBackwards, got {error:?}"),
+    }
+
+    let error = advance_replication_slot(&mut conn, "replication_advance_missing", current)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::SlotNotFound { .. }),
+        "expected SlotNotFound, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_retained_wal_bytes() -> anyhow::Result<()> {
+    setup_publication("replication_retained").await?;
+
+    let mut conn = new::<Postgres>().await?;
+
+    sqlx::query(
+        "SELECT pg_create_logical_replication_slot('replication_retained_slot', 'pgoutput', true)",
+    )
+    .execute(&mut conn)
+    .await?;
+
+    let before = retained_wal_bytes(&mut conn, "replication_retained_slot").await?;
+
+    conn.execute("INSERT INTO replication_retained (id, name) SELECT i, 'foo' FROM generate_series(1, 100) i")
+        .await?;
+
+    // nothing was consumed, so all new WAL is retained
+    let after = retained_wal_bytes(&mut conn, "replication_retained_slot").await?;
+    assert!(after > before, "{after} <= {before}");
+
+    let error = retained_wal_bytes(&mut conn, "replication_retained_missing")
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, ReplicationError::SlotNotFound { .. }),
+        "{error}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_rejects_frames_above_the_maximum_size() -> anyhow::Result<()> {
+    setup_publication("replication_max_frame").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_max_frame_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_max_frame_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_max_frame_pub"]),
+        )
+        .await?;
+
+    stream.set_max_frame_size(1024);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_max_frame (id, name) VALUES (1, repeat('x', 4096))")
+        .await?;
+
+    let error = loop {
+        match stream.recv().await {
+            Ok(Some(_)) => continue,
+            Ok(None) => panic!("stream ended unexpectedly"),
+            Err(error) => break error,
+        }
+    };
+
+    assert!(
+        matches!(error, ReplicationError::FrameTooLarge { len, max_len: 1024 } if len > 4096),
+        "expected FrameTooLarge, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_times_out_without_messages() -> anyhow::Result<()> {
+    setup_publication("replication_timeout").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_timeout_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_timeout_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_timeout_pub"]),
+        )
+        .await?;
+
+    // the stream requests a keepalive with each status update while the server is silent
+    stream.set_status_interval(Duration::from_millis(100));
+    stream.set_read_timeout(Some(Duration::from_millis(500)));
+
+    let idle = tokio::time::timeout(Duration::from_secs(2), stream.recv()).await;
+    assert!(idle.is_err(), "expected no message, got {idle:?}");
+
+    // without status updates, the idle server sends nothing
+    stream.set_status_interval(Duration::from_secs(60));
+
+    let error = stream.recv().await.unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::Timeout { timeout } if timeout == Duration::from_millis(500)),
+        "expected Timeout, got {error:?}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_notices_and_server_errors() -> anyhow::Result<()> {
+    setup_publication("replication_terminate").await?;
+
+    // the server logs decoding progress at `LOG` and `DEBUG1`
+    let options = env::var("DATABASE_URL")?
+        .parse::<PgConnectOptions>()?
+        .options([("client_min_messages", "debug1")]);
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_terminate_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_terminate_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_terminate_pub"]),
+        )
+        .await?;
+
+    let notices = Arc::new(Mutex::new(Vec::new()));
+    stream.set_notice_handler({
+        let notices = notices.clone();
+        move |notice| notices.lock().unwrap().push(notice)
+    });
+
+    let mut admin = new::<Postgres>().await?;
+    admin
+        .execute("INSERT INTO replication_terminate (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    while !matches!(stream.recv().await?, Some(LogicalReplication::Commit(_))) {}
+
+    // logged while decoding starts, after `START_REPLICATION` returned
+    assert!(notices.lock().unwrap().iter().any(|notice| notice
+        .message
+        .starts_with("logical decoding found consistent point")));
+
+    sqlx::query(
+        "SELECT pg_terminate_backend(active_pid) FROM pg_replication_slots \
+         WHERE slot_name = 'replication_terminate_slot'",
+    )
+    .execute(&mut admin)
+    .await?;
+
+    let error = stream.recv().await.unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::ServerShutdown { .. }),
+        "expected ServerShutdown, got {error:?}"
+    );
+    assert!(error.is_retryable());
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_from_quoted_publication() -> anyhow::Result<()> {
+    let mut setup = new::<Postgres>().await?;
+
+    setup
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS "Replication Quoted";
+DROP TABLE IF EXISTS replication_quoted;
+CREATE TABLE replication_quoted (id INT PRIMARY KEY, name TEXT);
+CREATE PUBLICATION "Replication Quoted" FOR TABLE replication_quoted;
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_quoted_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_quoted_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["Replication Quoted"]),
+        )
+        .await?;
+
+    setup
+        .execute("INSERT INTO replication_quoted (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let Some(LogicalReplication::Begin(_)) = stream.recv().await? else {
+        panic!("expected Begin");
+    };
+
+    let Some(LogicalReplication::Relation(relation)) = stream.recv().await? else {
+        panic!("expected Relation");
+    };
+
+    assert_eq!(relation.name, "replication_quoted");
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_raw_messages() -> anyhow::Result<()> {
+    setup_publication("replication_raw").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_raw_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_raw_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_raw_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_raw (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let mut tags = Vec::new();
+    let mut context = LogicalDecodeContext::new(1);
+
+    loop {
+        let data = stream.recv_raw().await?.expect("stream ended unexpectedly");
+        assert!(data.wal_start <= stream.received_lsn());
+
+        tags.push(data.data[0]);
+
+        // the raw payload is the undecoded `pgoutput` message
+        let message = decode_logical(&data.data, context)?;
+        context.observe(&message);
+
+        if matches!(message, LogicalReplication::Commit(_)) {
+            break;
+        }
+    }
+
+    assert_eq!(tags, b"BRIC");
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_physical_wal() -> anyhow::Result<()> {
+    let mut conn = replication_connection().await?;
+    let system = conn.identify_system().await?;
+
+    let mut stream = conn
+        .start_physical_replication(None, system.xlogpos, Some(u32::try_from(system.timeline)?))
+        .await?;
+
+    // write some WAL
+    let mut sql = new::<Postgres>().await?;
+    sql.execute(
+        "CREATE TEMPORARY TABLE physical_wal (id INT); INSERT INTO physical_wal VALUES (1)",
+    )
+    .await?;
+
+    let message = stream.recv().await?.expect("stream ended");
+
+    let PhysicalReplication::XLogData(data) = message else {
+        panic!("unexpected message: {message:?}");
+    };
+
+    assert!(data.wal_start >= system.xlogpos);
+    assert!(!data.data.is_empty());
+    assert!(stream.received_lsn() > data.wal_start);
+
+    stream.set_confirmed_lsn(stream.received_lsn());
+    stream.finish().await?.close().await?;
+
+    // a timeline that is not in the server's history
+    let mut conn = replication_connection().await?;
+    let system = conn.identify_system().await?;
+
+    let error = conn
+        .start_physical_replication(
+            None,
+            system.xlogpos,
+            Some(u32::try_from(system.timeline)? + 100),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::Sqlx(sqlx::Error::Database(_))),
+        "{error:?}"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres-wal")]
+#[sqlx_macros::test]
+async fn it_parses_wal_record_headers() -> anyhow::Result<()> {
+    use sqlx::postgres::replication::wal::WalRecordReader;
+
+    let mut conn = replication_connection().await?;
+    let system = conn.identify_system().await?;
+
+    // start at a page, where the reader finds the first record without skipping any
+    let start = PgLsn(system.xlogpos.0 & !8191);
+
+    let mut stream = conn
+        .start_physical_replication(None, start, Some(u32::try_from(system.timeline)?))
+        .await?;
+
+    let mut sql = new::<Postgres>().await?;
+    sql.execute(
+        "DROP TABLE IF EXISTS replication_wal_records; \
+         CREATE TABLE replication_wal_records (id INT); \
+         INSERT INTO replication_wal_records SELECT generate_series(1, 1000); \
+         SELECT pg_switch_wal(); \
+         INSERT INTO replication_wal_records VALUES (1001)",
+    )
+    .await?;
+    let (end,): (PgLsn,) = sqlx::query_as("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut sql)
+        .await?;
+
+    let mut reader = WalRecordReader::new();
+    let mut records = Vec::new();
+    // keepalives may report the end of WAL before its data was received
+    let mut read = start;
+
+    while read < end {
+        if let Some(PhysicalReplication::XLogData(data)) = stream.recv().await? {
+            records.extend(reader.push(data.wal_start, &data.data)?);
+            read = PgLsn(data.wal_start.0 + data.data.len() as u64);
+        }
+    }
+
+    stream.finish().await?.close().await?;
+
+    assert!(records.len() > 1000);
+
+    // every record points to the one before it
+    for pair in records.windows(2) {
+        assert_eq!(pair[1].header.prev, pair[0].lsn, "{pair:?}");
+    }
+
+    let heap = records
+        .iter()
+        .filter(|record| record.header.resource_manager() == Some("Heap"))
+        .count();
+    assert!(heap >= 1000, "{heap} heap records");
+
+    let switch = records
+        .iter()
+        .position(|record| record.header.is_switch())
+        .expect("no WAL switch");
+
+    // the next record starts after the long header of the next segment
+    assert_eq!(records[switch + 1].lsn.0 % (16 * 1024 * 1024), 40);
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_manages_multiple_slots() -> anyhow::Result<()> {
+    let mut manager = ReplicationManager::new();
+
+    for table in ["replication_manager_a", "replication_manager_b"] {
+        setup_publication(table).await?;
+
+        let mut conn = replication_connection().await?;
+        let slot = format!("{table}_slot");
+
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical(&slot, "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+        let stream = conn
+            .start_logical_replication(
+                &slot,
+                PgLsn::INVALID,
+                PgOutputOptions::new([format!("{table}_pub")]),
+            )
+            .await?;
+
+        assert!(manager.add(stream).is_none());
+    }
+
+    assert_eq!(manager.len(), 2);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            "INSERT INTO replication_manager_a (id, name) VALUES (1, 'a'); \
+             INSERT INTO replication_manager_b (id, name) VALUES (2, 'b');",
+        )
+        .await?;
+
+    let mut inserts = HashMap::new();
+
+    while inserts.len() < 2 {
+        let (slot, message) = manager.recv().await.expect("no streams left");
+
+        match message? {
+            LogicalReplication::Insert(insert) => {
+                inserts.insert(slot, insert.new_data[1].as_str().unwrap().to_owned());
+            }
+            LogicalReplication::Commit(commit) => {
+                // each slot confirms its own position
+                let stream = manager.stream_mut(&slot).unwrap();
+                stream.set_confirmed_lsn(commit.end_lsn);
+                assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(inserts["replication_manager_a_slot"], "a");
+    assert_eq!(inserts["replication_manager_b_slot"], "b");
+
+    let stream = manager.remove("replication_manager_a_slot").unwrap();
+    stream.finish().await?.close().await?;
+
+    assert_eq!(
+        manager.slots().collect::<Vec<_>>(),
+        ["replication_manager_b_slot"]
+    );
+
+    for conn in manager.finish().await? {
+        conn.close().await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_tags_transactions_with_their_source() -> anyhow::Result<()> {
+    let mut manager = ReplicationManager::new();
+
+    for table in ["replication_sourced_a", "replication_sourced_b"] {
+        setup_publication(table).await?;
+
+        let mut conn = replication_connection().await?;
+        let slot = format!("{table}_slot");
+
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical(&slot, "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+        let stream = conn
+            .start_logical_replication(
+                &slot,
+                PgLsn::INVALID,
+                PgOutputOptions::new([format!("{table}_pub")]),
+            )
+            .await?;
+
+        assert_eq!(stream.publications(), [format!("{table}_pub")]);
+        manager.add(stream);
+    }
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            "INSERT INTO replication_sourced_a (id, name) VALUES (1, 'a'); \
+             INSERT INTO replication_sourced_b (id, name) VALUES (2, 'b');",
+        )
+        .await?;
+
+    let mut sources = HashMap::new();
+
+    while sources.len() < 2 {
+        let sourced = manager.recv_transaction().await.expect("no streams left");
+        let transaction = sourced.change?;
+
+        let inserts: Vec<_> = transaction
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                LogicalReplication::Insert(insert) => insert.new_data[1].as_str(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(inserts.len(), 1);
+
+        manager
+            .stream_mut(&sourced.slot)
+            .unwrap()
+            .set_confirmed_lsn(transaction.end_lsn);
+
+        sources.insert(inserts[0].to_owned(), (sourced.slot, sourced.publications));
+    }
+
+    assert_eq!(
+        sources["a"],
+        (
+            "replication_sourced_a_slot".to_owned(),
+            vec!["replication_sourced_a_pub".to_owned()]
+        )
+    );
+    assert_eq!(
+        sources["b"],
+        (
+            "replication_sourced_b_slot".to_owned(),
+            vec!["replication_sourced_b_pub".to_owned()]
+        )
+    );
+
+    for conn in manager.finish().await? {
+        conn.close().await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_through_slot_handle() -> anyhow::Result<()> {
+    setup_publication("replication_handle").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_handle_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    assert_eq!(slot.name(), "replication_handle_slot");
+    assert!(slot.temporary);
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_handle_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_handle (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    loop {
+        let message = stream.recv().await?.expect("stream ended unexpectedly");
+
+        if matches!(message, LogicalReplication::Insert(_)) {
+            break;
+        }
+    }
+
+    let mut conn = stream.finish().await?;
+
+    // a physical slot cannot stream logical changes
+    let physical = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::physical("replication_handle_physical").temporary(true),
+        )
+        .await?;
+
+    let other = replication_connection().await?;
+    let error = physical
+        .start_streaming(
+            other,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_handle_pub"]),
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ReplicationError::NotLogicalSlot { .. }),
+        "{error:?}"
+    );
+
+    physical.drop(&mut conn, false).await?;
+    slot.drop(&mut conn, false).await?;
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_only_messages() -> anyhow::Result<()> {
+    setup_publication("replication_bus").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_bus_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_bus_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_bus_pub"]).messages(true),
+        )
+        .await?
+        .only_messages()
+        .with_prefix("bus");
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+INSERT INTO replication_bus (id, name) VALUES (1, 'skipped');
+SELECT pg_logical_emit_message(false, 'other', 'skipped');
+SELECT pg_logical_emit_message(false, 'bus', 'first');
+BEGIN;
+INSERT INTO replication_bus (id, name) VALUES (2, 'skipped');
+SELECT pg_logical_emit_message(true, 'bus', 'second');
+COMMIT;
+"#,
+        )
+        .await?;
+
+    let first = stream.recv().await?.expect("stream ended unexpectedly");
+    assert_eq!(first.prefix, "bus");
+    assert_eq!(&first.content[..], b"first");
+    assert!(!first.transactional);
+
+    let second = stream.recv().await?.expect("stream ended unexpectedly");
+    assert_eq!(&second.content[..], b"second");
+    assert!(second.transactional);
+
+    // a transaction without messages, to observe the slot advancing past the last one
+    writer
+        .execute("INSERT INTO replication_bus (id, name) VALUES (3, 'skipped')")
+        .await?;
+    writer
+        .execute("SELECT pg_logical_emit_message(false, 'bus', 'third')")
+        .await?;
+
+    let third = stream.recv().await?.expect("stream ended unexpectedly");
+    assert_eq!(&third.content[..], b"third");
+
+    // the commits of the skipped transactions were confirmed
+    assert!(stream.stream_mut().confirmed_lsn() > second.lsn);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_sends_status_update_after_threshold() -> anyhow::Result<()> {
+    setup_publication("replication_threshold").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_threshold_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_threshold_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_threshold_pub"]),
+        )
+        .await?;
+
+    // only the threshold can trigger a status update during the test
+    stream.set_status_interval(Duration::from_secs(3600));
+    stream.set_status_transaction_threshold(Some(1));
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_threshold (id, name) VALUES (1, 'foo')")
+        .await?;
+    writer
+        .execute("INSERT INTO replication_threshold (id, name) VALUES (2, 'bar')")
+        .await?;
+
+    let end_lsn = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit.end_lsn;
+        }
+    };
+
+    stream.set_confirmed_lsn(end_lsn);
+
+    // receiving the next transaction sends the update for the first one
+    assert!(matches!(
+        stream.recv().await?,
+        Some(LogicalReplication::Begin(_))
+    ));
+
+    let mut confirmed = PgLsn::INVALID;
+
+    for _ in 0..50 {
+        let lsn: String = sqlx::query_scalar(
+            "SELECT confirmed_flush_lsn::text FROM pg_replication_slots \
+             WHERE slot_name = 'replication_threshold_slot'",
+        )
+        .fetch_one(&mut writer)
+        .await?;
+
+        confirmed = lsn.parse().unwrap();
+
+        if confirmed >= end_lsn {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert!(confirmed >= end_lsn, "{confirmed} < {end_lsn}");
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_committed_transactions() -> anyhow::Result<()> {
+    setup_publication("replication_tx").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_tx_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_tx_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_tx_pub"]),
+        )
+        .await?
+        .transactions();
+    stream.set_validate(true);
+
+    let before = SystemTime::now() - Duration::from_secs(1);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+BEGIN;
+INSERT INTO replication_tx (id, name) VALUES (1, 'first');
+INSERT INTO replication_tx (id, name) VALUES (2, 'second');
+COMMIT;
+"#,
+        )
+        .await?;
+
+    let transaction = stream.recv().await?.expect("stream ended unexpectedly");
+
+    let inserts = transaction
+        .changes
+        .iter()
+        .filter(|change| matches!(change, LogicalReplication::Insert(_)))
+        .count();
+    assert_eq!(inserts, 2);
+    assert!(!transaction.streamed);
+
+    assert!(transaction.commit_time() > before);
+    assert!(transaction.lag(SystemTime::now()) < Duration::from_secs(60));
+    assert_eq!(transaction.lag(before), Duration::ZERO);
+
+    writer
+        .execute("INSERT INTO replication_tx (id, name) VALUES (3, 'third')")
+        .await?;
+
+    let next = stream.recv().await?.expect("stream ended unexpectedly");
+    assert!(next.commit_lsn > transaction.commit_lsn);
+
+    // the first transaction was acknowledged by receiving the next one
+    assert_eq!(stream.stream_mut().confirmed_lsn(), transaction.end_lsn);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+/// A sink that records the kinds of the messages applied to it and its checkpoints, and calls
+/// `on_commit` with the number of commits applied so far after each commit.
+#[derive(Default)]
+struct RecordingSink {
+    applied: Vec<&'static str>,
+    checkpoints: Vec<PgLsn>,
+    on_commit: Option<Box<dyn FnMut(usize) + Send>>,
+}
+
+impl ChangeSink for RecordingSink {
+    fn apply<'a>(
+        &'a mut self,
+        change: &'a LogicalReplication,
+        relation: Option<&'a Relation>,
+    ) -> BoxFuture<'a, Result<(), sqlx::Error>> {
+        Box::pin(async move {
+            self.applied.push(match change {
+                LogicalReplication::Begin(_) => "B",
+                LogicalReplication::Relation(_) => "R",
+                LogicalReplication::Insert(_) => {
+                    assert!(relation.is_some());
+                    "I"
+                }
+                LogicalReplication::Commit(_) => "C",
+                _ => "?",
+            });
+
+            if let (LogicalReplication::Commit(_), Some(on_commit)) = (change, &mut self.on_commit)
+            {
+                let commits = self.applied.iter().filter(|kind| **kind == "C").count();
+                on_commit(commits);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn flush_checkpoint(&mut self, lsn: PgLsn) -> BoxFuture<'_, Result<(), sqlx::Error>> {
+        self.checkpoints.push(lsn);
+        future::ready(Ok(())).boxed()
+    }
+}
+
+async fn slot_confirmed_flush(slot: &str) -> anyhow::Result<PgLsn> {
+    let mut conn = new::<Postgres>().await?;
+
+    Ok(sqlx::query_scalar(
+        "SELECT confirmed_flush_lsn FROM pg_replication_slots WHERE slot_name = $1",
+    )
+    .bind(slot)
+    .fetch_one(&mut conn)
+    .await?)
+}
+
+#[sqlx_macros::test]
+async fn it_pumps_changes_into_a_sink() -> anyhow::Result<()> {
+    setup_publication("replication_sink").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_sink_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_sink_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_sink_pub"]),
+        )
+        .await?;
+    stream.set_status_interval(Duration::from_millis(100));
+
+    let mut writer = new::<Postgres>().await?;
+    for id in 1..=2 {
+        sqlx::query("INSERT INTO replication_sink (id, name) VALUES ($1, 'row')")
+            .bind(id)
+            .execute(&mut writer)
+            .await?;
+    }
+    let current: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut writer)
+        .await?;
+    stream.set_stop_lsn(Some(current));
+
+    let mut sink = RecordingSink::default();
+    let confirmed =
+        tokio::time::timeout(Duration::from_secs(10), stream.pump_into(&mut sink)).await??;
+
+    assert_eq!(sink.applied, ["B", "R", "I", "C", "B", "I", "C"]);
+
+    // the checkpoints end transactions, and the last one is confirmed
+    assert!(!sink.checkpoints.is_empty());
+    assert!(sink.checkpoints.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(sink.checkpoints[0] > slot.consistent_point);
+    assert_eq!(sink.checkpoints.last(), Some(&confirmed));
+    assert_eq!(stream.confirmed_lsn(), confirmed);
+
+    // the last checkpoint was confirmed with the final status update before stopping
+    assert_eq!(
+        slot_confirmed_flush("replication_sink_slot").await?,
+        confirmed
+    );
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_keeps_ready_messages_when_a_checkpoint_is_due() -> anyhow::Result<()> {
+    setup_publication("replication_sink_due").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots
+WHERE slot_name = 'replication_sink_due_slot';
+SELECT pg_create_logical_replication_slot('replication_sink_due_slot', 'pgoutput');
+"#,
+        )
+        .await?;
+
+    for id in 1..=2 {
+        sqlx::query("INSERT INTO replication_sink_due (id, name) VALUES ($1, 'row')")
+            .bind(id)
+            .execute(&mut writer)
+            .await?;
+    }
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication(
+            "replication_sink_due_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_sink_due_pub"]),
+        )
+        .await?;
+
+    let now = Arc::new(Mutex::new(SystemTime::now()));
+    stream.set_clock({
+        let now = now.clone();
+        move || *now.lock().unwrap()
+    });
+
+    let cancel = stream.cancel_handle();
+    let mut sink = RecordingSink {
+        on_commit: Some(Box::new(move |commits| {
+            if commits == 1 {
+                // the checkpoint comes due while the next transaction was already received
+                std::thread::sleep(Duration::from_millis(200));
+                *now.lock().unwrap() += Duration::from_secs(3600);
+            } else {
+                cancel.cancel();
+            }
+        })),
+        ..RecordingSink::default()
+    };
+
+    let confirmed =
+        tokio::time::timeout(Duration::from_secs(10), stream.pump_into(&mut sink)).await??;
+
+    assert_eq!(sink.applied, ["B", "R", "I", "C", "B", "I", "C"]);
+    assert_eq!(sink.checkpoints.len(), 2);
+    assert_eq!(sink.checkpoints.last(), Some(&confirmed));
+
+    // after a cancel, the last checkpoint is confirmed by `finish()`
+    stream.finish().await?.close().await?;
+    assert_eq!(
+        slot_confirmed_flush("replication_sink_due_slot").await?,
+        confirmed
+    );
+
+    writer
+        .execute("SELECT pg_drop_replication_slot('replication_sink_due_slot')")
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct TableRow {
+    id: i32,
+    name: Option<String>,
+}
+
+impl FromReplicationRow for TableRow {
+    type Key = i32;
+
+    fn from_row(relation: &Relation, row: &Tuples) -> Result<Self, sqlx::Error> {
+        Ok(TableRow {
+            id: Self::key_from_row(relation, row)?,
+            name: row
+                .get_by_name(relation, "name")
+                .ok_or_else(|| sqlx::Error::ColumnNotFound("name".into()))?
+                .as_opt_string()?,
+        })
+    }
+
+    fn key_from_row(relation: &Relation, row: &Tuples) -> Result<i32, sqlx::Error> {
+        row.get_by_name(relation, "id")
+            .ok_or_else(|| sqlx::Error::ColumnNotFound("id".into()))?
+            .try_decode(relation.columns[0].type_id)
+    }
+}
+
+#[sqlx_macros::test]
+async fn it_streams_the_rows_of_a_table() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_table_pub;
+DROP TABLE IF EXISTS replication_table, replication_table_other;
+CREATE TABLE replication_table (id INT PRIMARY KEY, name TEXT);
+CREATE TABLE replication_table_other (id INT PRIMARY KEY, name TEXT);
+CREATE PUBLICATION replication_table_pub FOR TABLE replication_table, replication_table_other;
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_table_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_table_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_table_pub"]),
+        )
+        .await?
+        .table::<TableRow>("public", "replication_table");
+
+    writer
+        .execute(
+            r#"
+INSERT INTO replication_table_other (id, name) VALUES (1, 'skipped');
+BEGIN;
+INSERT INTO replication_table (id, name) VALUES (1, 'first');
+ROLLBACK;
+INSERT INTO replication_table (id, name) VALUES (1, 'first');
+UPDATE replication_table SET name = NULL WHERE id = 1;
+UPDATE replication_table SET id = 2 WHERE id = 1;
+DELETE FROM replication_table WHERE id = 2;
+TRUNCATE replication_table, replication_table_other;
+"#,
+        )
+        .await?;
+
+    let mut changes = Vec::new();
+    for _ in 0..5 {
+        changes.push(stream.recv().await?.expect("stream ended unexpectedly"));
+    }
+
+    let row = |id, name: Option<&str>| TableRow {
+        id,
+        name: name.map(str::to_owned),
+    };
+    assert_eq!(
+        changes,
+        [
+            Change::Insert(row(1, Some("first"))),
+            Change::Update {
+                key: 1,
+                new: row(1, None)
+            },
+            Change::Update {
+                key: 1,
+                new: row(2, None)
+            },
+            Change::Delete(2),
+            Change::Truncate,
+        ]
+    );
+    assert_eq!(
+        stream.relation().map(|relation| relation.name.as_str()),
+        Some("replication_table")
+    );
+
+    // rows decoded with a stale definition would be garbage
+    writer
+        .execute(
+            r#"
+ALTER TABLE replication_table ADD COLUMN age INT;
+INSERT INTO replication_table (id, name, age) VALUES (3, 'third', 42);
+"#,
+        )
+        .await?;
+
+    let error = stream.recv().await.expect_err("schema change not detected");
+    assert!(
+        matches!(error, ReplicationError::SchemaChanged { ref relation } if relation == r#""public"."replication_table""#),
+        "{error}"
+    );
+
+    // the change is not skipped
+    let error = stream.recv().await.expect_err("schema change skipped");
+    assert!(matches!(error, ReplicationError::SchemaChanged { .. }));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_suppresses_schema_messages() -> anyhow::Result<()> {
+    setup_publication("replication_schema").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_schema_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_schema_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_schema_pub"]),
+        )
+        .await?;
+
+    stream.set_schema_messages(false);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_schema (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let Some(LogicalReplication::Begin(_)) = stream.recv().await? else {
+        panic!("expected Begin");
+    };
+
+    // the Relation message is skipped, but still cached
+    let Some(LogicalReplication::Insert(insert)) = stream.recv().await? else {
+        panic!("expected Insert");
+    };
+
+    let relation = stream
+        .relation(insert.relation_id)
+        .expect("relation not cached");
+    assert_eq!(relation.name, "replication_schema");
+    assert_eq!(relation.columns.len(), 2);
+
+    let Some(LogicalReplication::Commit(_)) = stream.recv().await? else {
+        panic!("expected Commit");
+    };
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_resets_slot() -> anyhow::Result<()> {
+    setup_publication("replication_reset").await?;
+
+    let create = CreateReplicationSlot::logical("replication_reset_slot", "pgoutput")
+        .snapshot(SnapshotAction::NoExport);
+
+    let mut conn = replication_connection().await?;
+
+    // resetting a missing slot creates it
+    let slot = conn.reset_replication_slot(&create, false).await?;
+    let first = slot.consistent_point;
+
+    let stream = replication_connection()
+        .await?
+        .start_logical_replication(
+            "replication_reset_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_reset_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_reset (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    // invalid options are rejected before the slot is dropped
+    let error = conn
+        .reset_replication_slot(&create.clone().option("two_phase", "true"), true)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+    assert!(conn
+        .replication_slot("replication_reset_slot")
+        .await?
+        .is_some());
+
+    // the active slot is released by terminating the stream's backend
+    let slot = conn.reset_replication_slot(&create, true).await?;
+    assert!(slot.consistent_point > first);
+
+    drop(stream);
+    conn.drop_replication_slot("replication_reset_slot", false)
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_the_backend_holding_a_slot() -> anyhow::Result<()> {
+    setup_publication("replication_active_pid").await?;
+
+    let mut conn = replication_connection().await?;
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_active_pid_slot", "pgoutput")
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut other = replication_connection().await?;
+    assert_eq!(other.slot_active_pid(slot.name()).await?, None);
+
+    let pid = conn.backend_pid();
+    assert_ne!(pid, other.backend_pid());
+
+    let stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_active_pid_pub"]),
+        )
+        .await?;
+
+    // the slot is held by the backend of the stream, not by this connection
+    let error = other
+        .start_logical_replication(
+            slot.name(),
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_active_pid_pub"]),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::SlotInUse { .. }));
+
+    let mut other = replication_connection().await?;
+    assert_eq!(other.slot_active_pid(slot.name()).await?, Some(pid));
+
+    stream.finish().await?.close().await?;
+
+    let error = other
+        .slot_active_pid("replication_active_pid_missing")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::SlotNotFound { .. }));
+
+    other.drop_replication_slot(slot.name(), true).await?;
+    other.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_attaches_to_existing_slot() -> anyhow::Result<()> {
+    setup_publication("replication_attach").await?;
+
+    let mut admin = new::<Postgres>().await?;
+    admin
+        .execute(
+            r#"
+SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots
+WHERE slot_name = 'replication_attach_slot';
+SELECT pg_create_logical_replication_slot('replication_attach_slot', 'pgoutput');
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    assert!(conn
+        .replication_slot("replication_attach_missing")
+        .await?
+        .is_none());
+
+    let slot = conn
+        .replication_slot("replication_attach_slot")
+        .await?
+        .expect("slot not found");
+
+    assert_eq!(slot.output_plugin.as_deref(), Some("pgoutput"));
+    assert!(!slot.temporary);
+    assert_ne!(slot.consistent_point, PgLsn::INVALID);
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_attach_pub"]),
+        )
+        .await?;
+
+    admin
+        .execute("INSERT INTO replication_attach (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let Some(LogicalReplication::Begin(_)) = stream.recv().await? else {
+        panic!("expected Begin");
+    };
+
+    stream.finish().await?.close().await?;
+
+    admin
+        .execute("SELECT pg_drop_replication_slot('replication_attach_slot')")
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_lag() -> anyhow::Result<()> {
+    setup_publication("replication_lag").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_lag_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_lag_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_lag_pub"]),
+        )
+        .await?;
+
+    assert!(stream.lag().is_none());
+
+    // the status update sent after a silent interval requests a keepalive
+    stream.set_status_interval(Duration::from_millis(100));
+    assert!(tokio::time::timeout(Duration::from_secs(1), stream.recv())
+        .await
+        .is_err());
+
+    let lag = stream.lag().expect("no keepalive received");
+    assert!(lag.time < Duration::from_secs(60));
+
+    // the end of WAL reported with the keepalive
+    stream.set_confirmed_lsn(stream.received_lsn());
+    assert_eq!(stream.lag().map(|lag| lag.bytes), Some(0));
+
+    // a clock one hour ahead
+    let ahead = SystemTime::now() + Duration::from_secs(3600);
+    stream.set_clock(move || ahead);
+
+    let lag = stream.lag().expect("no keepalive received");
+    assert!(lag.time >= Duration::from_secs(3600) && lag.time < Duration::from_secs(3660));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_sends_manual_status_updates() -> anyhow::Result<()> {
+    setup_publication("replication_manual_status").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_manual_status_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_manual_status_pub"]),
+        )
+        .await?;
+
+    stream.set_automatic_status(false);
+    stream.set_status_interval(Duration::from_millis(100));
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_manual_status (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let commit = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit;
+        }
+    };
+
+    async fn confirmed_flush(writer: &mut sqlx::PgConnection) -> anyhow::Result<PgLsn> {
+        Ok(sqlx::query_scalar(
+            "SELECT confirmed_flush_lsn FROM pg_replication_slots \
+             WHERE slot_name = 'replication_manual_status_slot'",
+        )
+        .fetch_one(writer)
+        .await?)
+    }
+
+    // the confirmed position is not reported by the stream itself
+    stream.set_confirmed_lsn(commit.end_lsn);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), stream.recv())
+            .await
+            .is_err()
+    );
+    assert_eq!(confirmed_flush(&mut writer).await?, slot.consistent_point);
+
+    stream
+        .send_standby_status(commit.end_lsn, commit.end_lsn, commit.end_lsn, true)
+        .await?;
+
+    // the server processes the update when it reads from the connection
+    let _ = tokio::time::timeout(Duration::from_millis(500), stream.recv()).await;
+    assert_eq!(confirmed_flush(&mut writer).await?, commit.end_lsn);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_confirms_transactions_at_most_once() -> anyhow::Result<()> {
+    setup_publication("replication_at_most_once").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_at_most_once_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_at_most_once_pub"]),
+        )
+        .await?;
+    assert_eq!(stream.delivery_mode(), DeliveryMode::AtLeastOnce);
+    stream.set_delivery_mode(DeliveryMode::AtMostOnce);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_at_most_once (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    // confirmed by the stream, without `set_confirmed_lsn()`
+    let commit = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit;
+        }
+    };
+    assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+
+    // and reported before the commit was returned, while the stream is not polled
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+
+    loop {
+        let confirmed: PgLsn = sqlx::query_scalar(
+            "SELECT confirmed_flush_lsn FROM pg_replication_slots \
+             WHERE slot_name = 'replication_at_most_once_slot'",
+        )
+        .fetch_one(&mut writer)
+        .await?;
+
+        if confirmed >= commit.end_lsn {
+            break;
+        }
+
+        assert!(tokio::time::Instant::now() < deadline, "slot not advanced");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_confirms_consumed_transactions() -> anyhow::Result<()> {
+    setup_publication("replication_confirm_on_commit").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_confirm_on_commit_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_confirm_on_commit_pub"]),
+        )
+        .await?;
+    stream.set_delivery_mode(DeliveryMode::ConfirmOnCommit);
+    let start = stream.confirmed_lsn();
+
+    let mut writer = new::<Postgres>().await?;
+    for id in 1..=2 {
+        writer
+            .execute(&*format!(
+                "INSERT INTO replication_confirm_on_commit (id, name) VALUES ({id}, 'foo')"
+            ))
+            .await?;
+    }
+
+    // not confirmed while the transaction is consumed, nor when its commit is returned
+    let commit = loop {
+        match stream.recv().await?.expect("stream ended unexpectedly") {
+            LogicalReplication::Commit(commit) => break commit,
+            _ => assert_eq!(stream.confirmed_lsn(), start),
+        }
+    };
+    assert_eq!(stream.confirmed_lsn(), start);
+
+    // but once the next message is asked for
+    assert!(matches!(
+        stream.recv().await?,
+        Some(LogicalReplication::Begin(_))
+    ));
+    assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+
+    let commit = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit;
+        }
+    };
+
+    // the commit returned last is confirmed when the stream is finished
+    let conn = stream.finish().await?;
+
+    let confirmed: PgLsn = sqlx::query_scalar(
+        "SELECT confirmed_flush_lsn FROM pg_replication_slots \
+         WHERE slot_name = 'replication_confirm_on_commit_slot'",
+    )
+    .fetch_one(&mut writer)
+    .await?;
+    assert_eq!(confirmed, commit.end_lsn);
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_only_transaction_boundaries() -> anyhow::Result<()> {
+    setup_publication("replication_boundaries").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_boundaries_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_boundaries_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_boundaries_pub"]),
+        )
+        .await?;
+    stream.set_boundaries_only(true);
+    stream.set_delivery_mode(DeliveryMode::AtMostOnce);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_boundaries (id, name) VALUES (1, 'foo')")
+        .await?;
+    writer
+        .execute(
+            r#"
+BEGIN;
+UPDATE replication_boundaries SET name = 'bar' WHERE id = 1;
+DELETE FROM replication_boundaries WHERE id = 1;
+COMMIT;
+"#,
+        )
+        .await?;
+
+    let mut commits = Vec::new();
+    while commits.len() < 2 {
+        match stream.recv().await?.expect("stream ended unexpectedly") {
+            LogicalReplication::Begin(_) => {}
+            LogicalReplication::Commit(commit) => commits.push(commit),
+            message => panic!("unexpected message: {message:?}"),
+        }
+    }
+    assert!(commits[1].commit_lsn > commits[0].commit_lsn);
+    assert!(commits[1].commit_timestamp >= commits[0].commit_timestamp);
+
+    // the relation was skipped along with the rows
+    let relation_id: Oid = sqlx::query_scalar("SELECT 'replication_boundaries'::regclass::oid")
+        .fetch_one(&mut writer)
+        .await?;
+    assert!(stream.relation(relation_id).is_none());
+
+    // the slot advances without anything being confirmed by the monitor
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+
+    loop {
+        let confirmed: PgLsn = sqlx::query_scalar(
+            "SELECT confirmed_flush_lsn FROM pg_replication_slots \
+             WHERE slot_name = 'replication_boundaries_slot'",
+        )
+        .fetch_one(&mut writer)
+        .await?;
+
+        if confirmed >= commits[1].end_lsn {
+            break;
+        }
+
+        assert!(tokio::time::Instant::now() < deadline, "slot not advanced");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_caught_up() -> anyhow::Result<()> {
+    setup_publication("replication_caught_up").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_caught_up_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_caught_up_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_caught_up_pub"]),
+        )
+        .await?;
+
+    // other tests write WAL concurrently, so only the keepalive after the data counts
+    stream.set_caught_up_tolerance(0);
+    assert!(!stream.is_caught_up());
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_caught_up (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let end_lsn = loop {
+        match tokio::time::timeout(Duration::from_secs(10), stream.recv()).await?? {
+            Some(LogicalReplication::Commit(commit)) => break commit.end_lsn,
+            Some(_) => {}
+            None => anyhow::bail!("stream ended"),
+        }
+    };
+
+    // the status update sent after a silent interval requests a keepalive
+    stream.set_status_interval(Duration::from_millis(100));
+    assert!(tokio::time::timeout(Duration::from_secs(1), stream.recv())
+        .await
+        .is_err());
+
+    assert!(stream.lag().is_some());
+    assert!(!stream.is_caught_up());
+
+    stream.set_confirmed_lsn(end_lsn);
+    assert!(stream.is_caught_up());
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_keeps_paused_streams_alive() -> anyhow::Result<()> {
+    setup_publication("replication_pause").await?;
+
+    // the server closes the connection after a second without status updates
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+    let mut conn =
+        PgReplicationConnection::connect_with(&options.options([("wal_sender_timeout", "1s")]))
+            .await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_pause_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_pause_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_pause_pub"]),
+        )
+        .await?;
+
+    stream.set_status_interval(Duration::from_millis(200));
+    stream.pause();
+    assert!(stream.is_paused());
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_pause (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    // nothing is returned while paused, for longer than the server waits for status updates
+    assert!(tokio::time::timeout(Duration::from_secs(3), stream.recv())
+        .await
+        .is_err());
+
+    stream.resume();
+
+    let insert = loop {
+        match tokio::time::timeout(Duration::from_secs(10), stream.recv()).await?? {
+            Some(LogicalReplication::Insert(insert)) => break insert,
+            Some(_) => {}
+            None => anyhow::bail!("stream ended"),
+        }
+    };
+    assert_eq!(insert.new_data[1].as_str(), Some("foo"));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_holds_feedback_while_receiving() -> anyhow::Result<()> {
+    setup_publication("replication_hold").await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_hold_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = slot
+        .start_streaming(
+            conn,
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_hold_pub"]),
+        )
+        .await?;
+    stream.set_status_interval(Duration::from_millis(100));
+    stream.set_confirmed_lsn(slot.consistent_point);
+
+    stream.hold_feedback();
+    let held = stream.held_feedback_lsn().expect("feedback not held");
+    assert_eq!(held, slot.consistent_point);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_hold (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    // the changes are still received and confirmed
+    let commit = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit;
+        }
+    };
+    stream.set_confirmed_lsn(commit.end_lsn);
+    assert_eq!(stream.confirmed_lsn(), commit.end_lsn);
+
+    async fn positions(writer: &mut sqlx::PgConnection) -> anyhow::Result<(PgLsn, PgLsn)> {
+        Ok(sqlx::query_as(
+            "SELECT s.confirmed_flush_lsn, r.write_lsn FROM pg_replication_slots s \
+             JOIN pg_stat_replication r ON r.pid = s.active_pid \
+             WHERE s.slot_name = 'replication_hold_slot'",
+        )
+        .fetch_one(writer)
+        .await?)
+    }
+
+    // but the server only sees the received position advance
+    let _ = tokio::time::timeout(Duration::from_millis(500), stream.recv()).await;
+    let (flushed, written) = positions(&mut writer).await?;
+    assert_eq!(flushed, held);
+    assert!(written >= commit.end_lsn);
+
+    // until the feedback is released
+    stream.release_feedback();
+    assert_eq!(stream.held_feedback_lsn(), None);
+
+    let _ = tokio::time::timeout(Duration::from_millis(500), stream.recv()).await;
+    let (flushed, _) = positions(&mut writer).await?;
+    assert_eq!(flushed, commit.end_lsn);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_replays_changes_in_read_only_mode() -> anyhow::Result<()> {
+    setup_publication("replication_replay").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            "SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots \
+             WHERE slot_name = 'replication_replay_slot'",
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_replay_slot", "pgoutput")
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    writer
+        .execute("INSERT INTO replication_replay (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    // the walsender doesn't support starting logical replication twice on one connection
+    for _ in 0..2 {
+        let mut stream = slot
+            .start_streaming(
+                replication_connection().await?,
+                PgLsn::INVALID,
+                PgOutputOptions::new(["replication_replay_pub"]),
+            )
+            .await?;
+
+        stream.set_read_only(true);
+
+        let Some(LogicalReplication::Begin(_)) = stream.recv().await? else {
+            panic!("expected Begin");
+        };
+
+        let commit = loop {
+            if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+                break commit;
+            }
+        };
+
+        // confirming has no effect on the slot
+        stream.set_confirmed_lsn(commit.end_lsn);
+        stream.finish().await?.close().await?;
+    }
+
+    let confirmed: Option<PgLsn> = sqlx::query_scalar(
+        "SELECT confirmed_flush_lsn FROM pg_replication_slots WHERE slot_name = $1",
+    )
+    .bind(slot.name())
+    .fetch_one(&mut writer)
+    .await?;
+
+    assert_eq!(confirmed, Some(slot.consistent_point));
+
+    slot.drop(&mut conn, false).await?;
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "replication_point")]
+struct ReplicationPoint {
+    x: i32,
+    label: String,
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_arrays_and_composites() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_arrays_pub;
+DROP TABLE IF EXISTS replication_arrays;
+DROP TYPE IF EXISTS replication_point;
+CREATE TYPE replication_point AS (x INT, label TEXT);
+CREATE TABLE replication_arrays (
+    id INT PRIMARY KEY, numbers INT[], tags TEXT[], point replication_point, points replication_point[]
+);
+CREATE PUBLICATION replication_arrays_pub FOR TABLE replication_arrays;
+"#,
+        )
+        .await?;
+
+    for binary in [false, true] {
+        let slot_name = format!("replication_arrays_{binary}_slot");
+
+        let mut conn = replication_connection().await?;
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical(&slot_name, "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+        let mut stream = conn
+            .start_logical_replication(
+                &slot_name,
+                PgLsn::INVALID,
+                PgOutputOptions::new(["replication_arrays_pub"]).binary(binary),
+            )
+            .await?;
+
+        writer
+            .execute(&*format!(
+                "INSERT INTO replication_arrays VALUES \
+                 ({}, '{{1,2,NULL}}', '{{\"a b\",c}}', ROW(7, 'seven'), \
+                 ARRAY[ROW(1, 'one, uno'), NULL, ROW(2, 'two')]::replication_point[])",
+                i32::from(binary)
+            ))
+            .await?;
+
+        let insert = loop {
+            if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+                break insert;
+            }
+        };
+
+        let relation = stream
+            .relation(insert.relation_id)
+            .expect("relation not cached");
+        let columns = &relation.columns;
+
+        let numbers: Vec<Option<i32>> = insert.new_data[1].try_decode(columns[1].type_id)?;
+        assert_eq!(numbers, [Some(1), Some(2), None]);
+
+        let tags: Vec<String> = insert.new_data[2].try_decode(columns[2].type_id)?;
+        assert_eq!(tags, ["a b", "c"]);
+
+        let point: ReplicationPoint = insert.new_data[3].try_decode(columns[3].type_id)?;
+        assert_eq!(
+            point,
+            ReplicationPoint {
+                x: 7,
+                label: "seven".into()
+            }
+        );
+
+        let expected = [
+            Some(ReplicationPoint {
+                x: 1,
+                label: "one, uno".into(),
+            }),
+            None,
+            Some(ReplicationPoint {
+                x: 2,
+                label: "two".into(),
+            }),
+        ];
+
+        let points: Vec<Option<ReplicationPoint>> =
+            insert.new_data[4].try_decode(columns[4].type_id)?;
+        assert_eq!(points, expected);
+
+        let ty = stream
+            .custom_type(columns[4].type_id)
+            .expect("type not cached");
+        assert_eq!(ty.name, "_replication_point");
+        let points: Vec<Option<ReplicationPoint>> = insert.new_data[4].try_decode_custom(ty)?;
+        assert_eq!(points, expected);
+
+        stream.finish().await?.close().await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_bytea_in_every_format() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_bytea_pub;
+DROP TABLE IF EXISTS replication_bytea;
+CREATE TABLE replication_bytea (id INT PRIMARY KEY, data BYTEA);
+CREATE PUBLICATION replication_bytea_pub FOR TABLE replication_bytea;
+"#,
+        )
+        .await?;
+
+    const DATA: &[u8] = b"\x00\x01\\\x7f\x80\xffA'";
+
+    // the text format follows `bytea_output` of the replication connection
+    for (bytea_output, binary) in [("hex", false), ("escape", false), ("escape", true)] {
+        let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+        let mut conn = PgReplicationConnection::connect_with(
+            &options.options([("bytea_output", bytea_output)]),
+        )
+        .await?;
+
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical("replication_bytea_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+        let mut stream = conn
+            .start_logical_replication(
+                "replication_bytea_slot",
+                PgLsn::INVALID,
+                PgOutputOptions::new(["replication_bytea_pub"]).binary(binary),
+            )
+            .await?;
+
+        sqlx::query("INSERT INTO replication_bytea (id, data) VALUES ($1, $2)")
+            .bind(1)
+            .bind(DATA)
+            .execute(&mut writer)
+            .await?;
+
+        let insert = loop {
+            if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+                break insert;
+            }
+        };
+
+        let value = &insert.new_data[1];
+        assert_eq!(matches!(value, TupleData::Binary(_)), binary);
+
+        if bytea_output == "escape" && !binary {
+            assert!(!value.as_bytes().unwrap().starts_with(b"\\x"));
+        }
+
+        let type_id = stream
+            .relation(insert.relation_id)
+            .expect("relation not cached")
+            .columns[1]
+            .type_id;
+        assert_eq!(value.try_decode::<Vec<u8>>(type_id)?, DATA);
+
+        stream.finish().await?.close().await?;
+        writer.execute("DELETE FROM replication_bytea").await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_extension_types() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+CREATE EXTENSION IF NOT EXISTS hstore;
+CREATE EXTENSION IF NOT EXISTS citext;
+DROP PUBLICATION IF EXISTS replication_extensions_pub;
+DROP TABLE IF EXISTS replication_extensions;
+CREATE TABLE replication_extensions (id INT PRIMARY KEY, attributes HSTORE, email CITEXT);
+CREATE PUBLICATION replication_extensions_pub FOR TABLE replication_extensions;
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_extensions_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_extensions_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_extensions_pub"]).binary(true),
+        )
+        .await?;
+
+    writer
+        .execute(
+            "INSERT INTO replication_extensions VALUES \
+             (1, 'color => red, size => NULL', 'Foo@Example.com')",
+        )
+        .await?;
+
+    let insert = loop {
+        if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+            break insert;
+        }
+    };
+
+    let relation = stream
+        .relation(insert.relation_id)
+        .expect("relation not cached");
+    let attributes = stream
+        .custom_type(relation.columns[1].type_id)
+        .expect("hstore type not cached");
+    let email = stream
+        .custom_type(relation.columns[2].type_id)
+        .expect("citext type not cached");
+
+    assert_eq!(attributes.name, "hstore");
+
+    let decoded: PgHstore = insert.new_data[1].try_decode_custom(attributes)?;
+    assert_eq!(decoded.get("color"), Some(&Some("red".to_owned())));
+    assert_eq!(decoded.get("size"), Some(&None));
+
+    let decoded: PgCiText = insert.new_data[2].try_decode_custom(email)?;
+    assert_eq!(decoded.0, "Foo@Example.com");
+
+    let decoded: String = insert.new_data[2].try_decode_custom(email)?;
+    assert_eq!(decoded, "Foo@Example.com");
+
+    // the type is checked by name
+    assert!(insert.new_data[1]
+        .try_decode_custom::<PgCiText>(attributes)
+        .is_err());
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_lists_publication_tables() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_tables_pub;
+DROP SCHEMA IF EXISTS replication_tables CASCADE;
+CREATE SCHEMA replication_tables;
+CREATE TABLE replication_tables.orders (id INT PRIMARY KEY, total INT, note TEXT);
+CREATE TABLE replication_tables.users (id INT PRIMARY KEY, name TEXT);
+CREATE TABLE replication_tables.skipped (id INT PRIMARY KEY);
+CREATE PUBLICATION replication_tables_pub FOR
+    TABLE replication_tables.orders (id, total) WHERE (total > 100),
+    TABLE replication_tables.users;
+"#,
+    )
+    .await?;
+
+    let tables = publication_tables(&mut conn, "replication_tables_pub").await?;
+
+    assert_eq!(tables.len(), 2);
+
+    assert_eq!(
+        tables[0].qualified_name(),
+        r#""replication_tables"."orders""#
+    );
+    assert_eq!(
+        tables[0].columns.as_deref(),
+        Some(&["id".to_owned(), "total".to_owned()][..])
+    );
+    assert_eq!(tables[0].row_filter.as_deref(), Some("(total > 100)"));
+
+    assert_eq!(tables[1].name, "users");
+    assert_eq!(tables[1].columns.as_ref().map(Vec::len), Some(2));
+    assert_eq!(tables[1].row_filter, None);
+
+    assert!(publication_tables(&mut conn, "replication_tables_missing")
+        .await?
+        .is_empty());
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_watches_publication_tables() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_watch_pub;
+DROP SCHEMA IF EXISTS replication_watch CASCADE;
+CREATE SCHEMA replication_watch;
+CREATE TABLE replication_watch.orders (id INT PRIMARY KEY);
+CREATE TABLE replication_watch.users (id INT PRIMARY KEY);
+CREATE PUBLICATION replication_watch_pub FOR TABLE replication_watch.orders;
+"#,
+    )
+    .await?;
+
+    let mut watcher = PublicationWatcher::new("replication_watch_pub");
+    watcher.set_interval(Duration::from_millis(50));
+
+    // the first query records the tables
+    assert_eq!(watcher.check(&mut conn).await?, None);
+    assert_eq!(watcher.tables().map(<[_]>::len), Some(1));
+    assert_eq!(watcher.check(&mut conn).await?, None);
+
+    let mut orchestrator = new::<Postgres>().await?;
+    orchestrator
+        .execute(
+            "ALTER PUBLICATION replication_watch_pub \
+             ADD TABLE replication_watch.users; \
+             ALTER PUBLICATION replication_watch_pub \
+             DROP TABLE replication_watch.orders",
+        )
+        .await?;
+
+    let change = tokio::time::timeout(Duration::from_secs(5), watcher.changed(&mut conn)).await??;
+
+    assert_eq!(change.added.len(), 1);
+    assert_eq!(change.added[0].name, "users");
+    assert_eq!(change.removed.len(), 1);
+    assert_eq!(change.removed[0].name, "orders");
+    assert!(change.altered.is_empty());
+
+    assert_eq!(watcher.tables().unwrap()[0].name, "users");
+
+    // nothing changed since
+    assert!(
+        tokio::time::timeout(Duration::from_millis(200), watcher.changed(&mut conn))
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_copies_filtered_publication_tables() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_copy_pub;
+DROP SCHEMA IF EXISTS replication_copy CASCADE;
+CREATE SCHEMA replication_copy;
+CREATE TABLE replication_copy.orders (id INT PRIMARY KEY, total INT, note TEXT);
+INSERT INTO replication_copy.orders VALUES (1, 50, 'small'), (2, 150, 'large'), (3, 200, NULL);
+CREATE PUBLICATION replication_copy_pub FOR
+    TABLE replication_copy.orders (id, total) WHERE (total > 100);
+"#,
+    )
+    .await?;
+
+    let mut replication = replication_connection().await?;
+    let slot = replication
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_copy_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::Export),
+        )
+        .await?;
+
+    conn.execute("INSERT INTO replication_copy.orders VALUES (4, 300, 'after')")
+        .await?;
+
+    let mut copier = new::<Postgres>().await?;
+    let mut transaction = slot.import_snapshot(&mut copier).await?;
+
+    let tables = publication_tables(&mut *transaction, "replication_copy_pub").await?;
+    assert_eq!(tables.len(), 1);
+
+    // only the replicated rows and columns from before the consistent point are copied
+    let chunks: Vec<_> = copy_publication_table(&mut transaction, &tables[0])
+        .await?
+        .try_collect()
+        .await?;
+    assert_eq!(chunks.concat(), b"2\t150\n3\t200\n");
+
+    transaction.commit().await?;
+    replication.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_copies_publication_snapshots() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_snapshot_pub;
+DROP SCHEMA IF EXISTS replication_snapshot CASCADE;
+CREATE SCHEMA replication_snapshot;
+CREATE TABLE replication_snapshot.users (id INT PRIMARY KEY, name TEXT);
+CREATE TABLE replication_snapshot.orders (id INT PRIMARY KEY, total INT);
+INSERT INTO replication_snapshot.users VALUES (1, 'foo'), (2, 'bar');
+INSERT INTO replication_snapshot.orders SELECT i, i * 10 FROM generate_series(1, 1000) i;
+CREATE PUBLICATION replication_snapshot_pub FOR TABLES IN SCHEMA replication_snapshot;
+"#,
+    )
+    .await?;
+
+    let mut replication = replication_connection().await?;
+    let slot = replication
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_snapshot_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::Export),
+        )
+        .await?;
+
+    conn.execute("INSERT INTO replication_snapshot.users VALUES (3, 'after')")
+        .await?;
+
+    let mut copier = new::<Postgres>().await?;
+    let mut transaction = slot.import_snapshot(&mut copier).await?;
+
+    let mut users = Vec::new();
+    let copied = copy_publication_snapshot(
+        &mut transaction,
+        "replication_snapshot_pub",
+        |table, row| {
+            if table.name == "users" {
+                users.push(row);
+            }
+            Ok(())
+        },
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    // only the rows from before the consistent point are copied, one row at a time
+    let copied: Vec<_> = copied
+        .iter()
+        .map(|(table, rows)| (table.name.as_str(), *rows))
+        .collect();
+    assert_eq!(copied, [("orders", 1000), ("users", 2)]);
+    assert_eq!(users, [&b"1\tfoo\n"[..], &b"2\tbar\n"[..]]);
+
+    // an error of the consumer stops the copy
+    let mut transaction = copier.begin().await?;
+    let error = copy_publication_snapshot(&mut transaction, "replication_snapshot_pub", |_, _| {
+        Err(ReplicationError::InvalidOptions {
+            reason: "stop".into(),
+        })
+    })
+    .await
+    .unwrap_err();
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+
+    replication.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_lists_publication_row_filters() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_filters_pub, replication_filters_all_pub;
+DROP SCHEMA IF EXISTS replication_filters CASCADE;
+CREATE SCHEMA replication_filters;
+CREATE TABLE replication_filters.orders (id INT PRIMARY KEY, total INT, region TEXT);
+CREATE TABLE replication_filters.users (id INT PRIMARY KEY, name TEXT);
+CREATE PUBLICATION replication_filters_pub FOR
+    TABLE replication_filters.orders WHERE (total > 100 AND region = 'eu'),
+    TABLE replication_filters.users;
+CREATE PUBLICATION replication_filters_all_pub FOR TABLES IN SCHEMA replication_filters;
+"#,
+    )
+    .await?;
+
+    let filters = publication_row_filters(&mut conn, "replication_filters_pub").await?;
+
+    assert_eq!(filters.len(), 2);
+    assert_eq!(
+        filters[0].qualified_name(),
+        r#""replication_filters"."orders""#
+    );
+    assert_eq!(
+        filters[0].filter.as_deref(),
+        Some("((total > 100) AND (region = 'eu'::text))")
+    );
+    assert_eq!(filters[1].name, "users");
+    assert_eq!(filters[1].filter, None);
+
+    // the tables of a schema are not listed, and cannot be filtered
+    assert!(
+        publication_row_filters(&mut conn, "replication_filters_all_pub")
+            .await?
+            .is_empty()
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_cancels_pending_recv() -> anyhow::Result<()> {
+    setup_publication("replication_cancel").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_cancel_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_cancel_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_cancel_pub"]),
+        )
+        .await?;
+
+    let cancel = stream.cancel_handle();
+    assert!(!cancel.is_cancelled());
+
+    tokio::spawn({
+        let cancel = cancel.clone();
+
+        async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        }
+    });
+
+    // no changes are written, so only the cancellation ends the wait
+    let message = tokio::time::timeout(Duration::from_secs(5), stream.recv()).await??;
+    assert!(message.is_none());
+    assert!(cancel.is_cancelled());
+
+    // the stream stays cancelled, and can still be finished
+    assert!(stream.recv().await?.is_none());
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_receives_batches() -> anyhow::Result<()> {
+    setup_publication("replication_batch").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_batch_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_batch_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_batch_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+BEGIN;
+INSERT INTO replication_batch (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c');
+COMMIT;
+BEGIN;
+INSERT INTO replication_batch (id, name) VALUES (4, 'd'), (5, 'e'), (6, 'f');
+COMMIT;
+"#,
+        )
+        .await?;
+
+    // waits for the first message only
+    let batch = stream.next_batch(2).await?;
+    assert!((1..=2).contains(&batch.len()));
+    assert!(matches!(batch[0], LogicalReplication::Begin(_)));
+
+    // continued until the end of the first transaction
+    stream.set_whole_transaction_batches(true);
+
+    let batch = stream.next_batch(1).await?;
+    assert!(matches!(batch.last(), Some(LogicalReplication::Commit(_))));
+
+    let batch = stream.next_batch(100).await?;
+    assert!(matches!(batch.first(), Some(LogicalReplication::Begin(_))));
+    assert!(matches!(batch.last(), Some(LogicalReplication::Commit(_))));
+
+    let inserts = batch
+        .iter()
+        .filter(|message| matches!(message, LogicalReplication::Insert(_)))
+        .count();
+    assert_eq!(inserts, 3);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_starts_from_latest_position() -> anyhow::Result<()> {
+    setup_publication("replication_latest").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_latest_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    // committed after the slot was created, but before streaming started
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_latest (id, name) VALUES (1, 'skipped')")
+        .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_latest_slot",
+            StartPosition::Latest,
+            PgOutputOptions::new(["replication_latest_pub"]),
+        )
+        .await?;
+
+    writer
+        .execute("INSERT INTO replication_latest (id, name) VALUES (2, 'streamed')")
+        .await?;
+
+    let insert = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match stream.recv().await? {
+                Some(LogicalReplication::Insert(insert)) => return anyhow::Ok(insert),
+                Some(_) => {}
+                None => anyhow::bail!("stream ended unexpectedly"),
+            }
+        }
+    })
+    .await??;
+
+    assert_eq!(insert.new_data[0].as_str(), Some("2"));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reads_replication_settings() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+
+    let settings = replication_settings(&mut conn).await?;
+
+    // required by the other tests
+    assert_eq!(settings.wal_level, "logical");
+    assert!(settings.is_logical());
+    assert!(settings.max_replication_slots > 0);
+    assert!(settings.max_wal_senders > 0);
+
+    let timeout: String = sqlx::query_scalar("SHOW wal_sender_timeout")
+        .fetch_one(&mut conn)
+        .await?;
+
+    if timeout == "0" {
+        assert_eq!(settings.wal_sender_timeout, None);
+    } else {
+        assert!(settings.wal_sender_timeout.is_some());
+    }
+
+    // the replication connection reads the same setting before creating a logical slot
+    let mut replication = replication_connection().await?;
+    assert_eq!(replication.wal_level().await?, settings.wal_level);
+
+    for check_wal_level in [true, false] {
+        let slot = replication
+            .create_replication_slot(
+                &CreateReplicationSlot::logical("replication_wal_level_slot", "pgoutput")
+                    .temporary(true)
+                    .snapshot(SnapshotAction::NoExport)
+                    .check_wal_level(check_wal_level),
+            )
+            .await?;
+        replication
+            .drop_replication_slot(slot.name(), false)
+            .await?;
+    }
+
+    replication.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_transforms_tuples() -> anyhow::Result<()> {
+    setup_publication("replication_redact").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("ALTER TABLE replication_redact REPLICA IDENTITY FULL")
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_redact_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_redact_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_redact_pub"]),
+        )
+        .await?;
+
+    stream.set_tuple_transform(|relation, tuples| {
+        for (column, value) in relation.columns.iter().zip(tuples.0.iter_mut()) {
+            if column.name == "name" {
+                *value = TupleData::Null;
+            }
+        }
+    });
+
+    writer
+        .execute(
+            r#"
+INSERT INTO replication_redact (id, name) VALUES (1, 'secret');
+UPDATE replication_redact SET name = 'other secret' WHERE id = 1;
+"#,
+        )
+        .await?;
+
+    let mut changes = Vec::new();
+
+    while changes.len() < 2 {
+        match stream.recv().await? {
+            Some(message @ (LogicalReplication::Insert(_) | LogicalReplication::Update(_))) => {
+                changes.push(message)
+            }
+            Some(_) => {}
+            None => panic!("stream ended unexpectedly"),
+        }
+    }
+
+    let LogicalReplication::Insert(insert) = &changes[0] else {
+        panic!("expected Insert");
+    };
+    assert_eq!(insert.new_data[0].as_str(), Some("1"));
+    assert!(insert.new_data[1].is_null());
+
+    let LogicalReplication::Update(update) = &changes[1] else {
+        panic!("expected Update");
+    };
+    let old_data = update.old_data.as_ref().expect("no old row sent");
+    assert_eq!(old_data[0].as_str(), Some("1"));
+    assert!(old_data[1].is_null());
+    assert!(update.new_data[1].is_null());
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_rebootstraps_lost_slots() -> anyhow::Result<()> {
+    setup_publication("replication_rebootstrap").await?;
+
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+    let output_options = PgOutputOptions::new(["replication_rebootstrap_pub"]);
+
+    // without a handler, the missing slot is reported
+    let mut stream = ReconnectingStream::new(
+        options.clone(),
+        "replication_rebootstrap_slot",
+        output_options.clone(),
+    );
+
+    assert!(matches!(
+        stream.recv().await,
+        Err(ReplicationError::SlotNotFound { .. })
+    ));
+
+    let lost = Arc::new(Mutex::new(None));
+
+    let mut stream =
+        ReconnectingStream::new(options, "replication_rebootstrap_slot", output_options)
+            .on_slot_lost({
+                let lost = lost.clone();
+
+                move |mut conn, slot_lost| {
+                    *lost.lock().unwrap() = Some(slot_lost.clone());
+
+                    async move {
+                        // a temporary slot lives as long as the connection streaming from it
+                        conn.create_replication_slot(
+                            &CreateReplicationSlot::logical(slot_lost.slot, "pgoutput")
+                                .temporary(true)
+                                .snapshot(SnapshotAction::NoExport),
+                        )
+                        .await?;
+
+                        Ok(conn)
+                    }
+                }
+            });
+
+    stream.set_confirmed_lsn(PgLsn::from(0x100));
+
+    // connects on the first call
+    assert!(stream.stream_mut().is_none());
+
+    let writer = tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        let mut writer = new::<Postgres>().await?;
+        writer
+            .execute("INSERT INTO replication_rebootstrap (id, name) VALUES (1, 'foo')")
+            .await?;
+
+        anyhow::Ok(())
+    });
+
+    let insert = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            match stream.recv().await? {
+                Some(LogicalReplication::Insert(insert)) => return anyhow::Ok(insert),
+                Some(_) => {}
+                None => anyhow::bail!("stream ended unexpectedly"),
+            }
+        }
+    })
+    .await??;
+
+    writer.await??;
+    assert_eq!(insert.new_data[0].as_str(), Some("1"));
+
+    let lost = lost.lock().unwrap().take().expect("handler was not called");
+    assert_eq!(lost.slot, "replication_rebootstrap_slot");
+    assert!(!lost.invalidated);
+    assert_eq!(lost.confirmed_lsn, PgLsn::from(0x100));
+
+    // the position of the lost slot is reset
+    assert_eq!(stream.confirmed_lsn(), PgLsn::INVALID);
+
+    stream
+        .finish()
+        .await?
+        .expect("not connected")
+        .close()
+        .await?;
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct FrameRecorder(Mutex<Vec<(u8, usize)>>);
+
+impl ReplicationObserver for FrameRecorder {
+    fn on_frame(&self, kind: u8, len: usize) {
+        self.0.lock().unwrap().push((kind, len));
+    }
+}
+
+#[derive(Default)]
+struct KeepaliveCounter(Mutex<Vec<PgLsn>>);
+
+impl ReplicationObserver for KeepaliveCounter {
+    fn on_keepalive(&self, keepalive: &PrimaryKeepalive) {
+        self.0.lock().unwrap().push(keepalive.wal_end);
+    }
+}
+
+#[derive(Default)]
+struct DecodeErrorRecorder(Mutex<Vec<(PgLsn, bool)>>);
+
+impl ReplicationObserver for DecodeErrorRecorder {
+    fn on_decode_error(&self, data: &XLogData, _error: &sqlx::Error, skipped: bool) {
+        self.0.lock().unwrap().push((data.wal_start, skipped));
+    }
+}
+
+#[sqlx_macros::test]
+async fn it_counts_decode_errors() -> anyhow::Result<()> {
+    setup_publication("replication_decode_errors").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_decode_errors_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_decode_errors_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_decode_errors_pub"]),
+        )
+        .await?;
+
+    // replace the tag of each `Insert` with an unknown one, after the header of the `XLogData`
+    stream.set_frame_decoder(|frame| {
+        let mut frame = frame.to_vec();
+
+        if frame.first() == Some(&b'w') && frame.get(25) == Some(&b'I') {
+            frame[25] = b'?';
+        }
+
+        Ok(frame.into())
+    });
+    stream.set_decode_error_policy(DecodeErrorPolicy::Skip);
+
+    let recorder = Arc::new(DecodeErrorRecorder::default());
+    stream.set_observer(recorder.clone());
+    assert_eq!(stream.decode_errors(), 0);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_decode_errors (id, name) VALUES (1, 'foo'), (2, 'bar')")
+        .await?;
+
+    // the inserts are skipped
+    let mut messages = Vec::new();
+    loop {
+        let message = stream.recv().await?.expect("stream ended unexpectedly");
+        let commit = matches!(message, LogicalReplication::Commit(_));
+        messages.push(message);
+
+        if commit {
+            break;
+        }
+    }
+    assert!(matches!(
+        &messages[..],
+        [
+            LogicalReplication::Begin(_),
+            LogicalReplication::Relation(_),
+            LogicalReplication::Commit(_)
+        ]
+    ));
+
+    assert_eq!(stream.decode_errors(), 2);
+
+    let errors = recorder.0.lock().unwrap().clone();
+    assert_eq!(errors.len(), 2);
+    assert!(errors.iter().all(|&(_, skipped)| skipped));
+    assert!(errors[0].0 < errors[1].0);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_treats_keepalives_before_data_as_activity() -> anyhow::Result<()> {
+    setup_publication("replication_keepalives").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_keepalives_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_keepalives_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_keepalives_pub"]),
+        )
+        .await?;
+
+    let counter = Arc::new(KeepaliveCounter::default());
+    stream.set_observer(counter.clone());
+
+    // only keepalives arrive, requested with each status update; the short read timeout is
+    // reset by each of them
+    stream.set_status_interval(Duration::from_millis(100));
+    stream.set_read_timeout(Some(Duration::from_millis(500)));
+    assert!(tokio::time::timeout(Duration::from_secs(2), stream.recv())
+        .await
+        .is_err());
+
+    assert!(counter.0.lock().unwrap().len() >= 2);
+    assert!(stream.since_last_message() < Duration::from_millis(500));
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_keepalives (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let message = stream.recv().await?.expect("stream ended unexpectedly");
+    assert!(matches!(message, LogicalReplication::Begin(_)));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_observes_frames() -> anyhow::Result<()> {
+    setup_publication("replication_frames").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_frames_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_frames_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_frames_pub"]),
+        )
+        .await?;
+
+    let recorder = Arc::new(FrameRecorder::default());
+    stream.set_observer(recorder.clone());
+
+    // ask for a keepalive right away
+    stream.set_status_interval(Duration::from_millis(100));
+    assert!(tokio::time::timeout(Duration::from_secs(1), stream.recv())
+        .await
+        .is_err());
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_frames (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let mut raw_len = 0;
+
+    loop {
+        let data = stream.recv_raw().await?.expect("stream ended unexpectedly");
+        raw_len += data.data.len();
+
+        // Commit
+        if data.data[0] == b'C' {
+            break;
+        }
+    }
+
+    let frames = recorder.0.lock().unwrap().clone();
+    assert!(frames.iter().any(|&(kind, _)| kind == b'k'));
+
+    // Begin, Relation, Insert and Commit, each with the 25 byte `XLogData` header
+    let xlog_frames: Vec<_> = frames.iter().filter(|&&(kind, _)| kind == b'w').collect();
+    assert_eq!(xlog_frames.len(), 4);
+    assert_eq!(
+        xlog_frames.iter().map(|&&(_, len)| len).sum::<usize>(),
+        raw_len + 4 * 25
+    );
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_accepts_relations_in_streamed_segments() -> anyhow::Result<()> {
+    const ROWS: i32 = 2000;
+
+    setup_publication("replication_segments").await?;
+
+    let options = env::var("DATABASE_URL")?
+        .parse::<PgConnectOptions>()?
+        .options([("logical_decoding_work_mem", "64kB")]);
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_segments_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_segments_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_segments_pub"])
+                .proto_version(2)
+                .streaming(true),
+        )
+        .await?
+        .transactions();
+
+    // two large transactions on the same table, streamed in interleaved segments; the server
+    // sends the Relation again in the first segment of each of them
+    let mut first = new::<Postgres>().await?;
+    let mut second = new::<Postgres>().await?;
+
+    let insert = |from: i32| {
+        format!(
+            "INSERT INTO replication_segments (id, name) \
+             SELECT i, 'row ' || i FROM generate_series({from}, {}) i",
+            from + ROWS - 1
+        )
+    };
+
+    let mut first_tx = first.begin().await?;
+    first_tx.execute(&*insert(0)).await?;
+
+    let mut second_tx = second.begin().await?;
+    second_tx.execute(&*insert(ROWS)).await?;
+
+    first_tx.execute(&*insert(2 * ROWS)).await?;
+
+    second_tx.commit().await?;
+    first_tx.commit().await?;
+
+    for expected_rows in [ROWS, 2 * ROWS] {
+        let transaction = tokio::time::timeout(Duration::from_secs(30), stream.recv())
+            .await??
+            .expect("stream ended unexpectedly");
+
+        assert!(transaction.streamed);
+
+        let mut relations = 0;
+        let mut inserts = 0;
+
+        for change in &transaction.changes {
+            match change {
+                LogicalReplication::Relation(relation) => {
+                    assert!(relation.xid.is_some());
+                    relations += 1;
+                }
+                LogicalReplication::Insert(insert) => {
+                    assert!(stream.stream_mut().relation(insert.relation_id).is_some());
+                    inserts += 1;
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(relations, 1);
+        assert_eq!(inserts, expected_rows);
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_sizes_the_read_buffer_adaptively() -> anyhow::Result<()> {
+    setup_publication("replication_sizes").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_sizes_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_sizes_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_sizes_pub"]),
+        )
+        .await?;
+    stream.set_adaptive_read_buffer(Some(1 << 20));
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_sizes (id, name) VALUES (1, repeat('x', 3000000))")
+        .await?;
+    writer
+        .execute("INSERT INTO replication_sizes (id, name) VALUES (2, 'foo'), (3, 'bar')")
+        .await?;
+
+    // frames larger than the buffer are still received in full
+    let mut names = Vec::new();
+    while names.len() < 3 {
+        if let LogicalReplication::Insert(insert) =
+            stream.recv().await?.expect("stream ended unexpectedly")
+        {
+            let name = insert.new_data[1].as_str().unwrap_or_default();
+            names.push(name.len());
+        }
+    }
+    assert_eq!(names, [3000000, 3, 3]);
+
+    let sizes = stream.message_sizes();
+    assert!(sizes.count() >= 7, "{sizes:?}");
+    assert!(sizes.max() > 3000000);
+    assert!(sizes.total_bytes() > 3000000);
+    assert!(sizes.percentile(50) < 1024);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_extracts_the_keys_of_deleted_rows() -> anyhow::Result<()> {
+    setup_publication("replication_delete_keys").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_delete_keys_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_delete_keys_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_delete_keys_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_delete_keys (id, name) VALUES (1, 'foo'), (2, 'bar')")
+        .await?;
+    writer
+        .execute("DELETE FROM replication_delete_keys WHERE id = 1")
+        .await?;
+    writer
+        .execute("ALTER TABLE replication_delete_keys REPLICA IDENTITY FULL")
+        .await?;
+    writer
+        .execute("DELETE FROM replication_delete_keys WHERE id = 2")
+        .await?;
+
+    let mut keys = Vec::new();
+    while keys.len() < 2 {
+        let message = stream.recv().await?.expect("stream ended unexpectedly");
+
+        if let LogicalReplication::Delete(delete) = message {
+            let relation = stream
+                .relation(delete.relation_id)
+                .expect("unknown relation");
+            let key = delete.key(relation)?;
+
+            keys.push((key.get::<i32>("id")?, key.is_full(), key.len()));
+        }
+    }
+
+    // the primary key, then all columns of the old row
+    assert_eq!(keys, [(1, false, 1), (2, true, 2)]);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_connects_with_keepalives_and_a_timeout() -> anyhow::Result<()> {
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+    let options = options
+        .keepalives_idle(Duration::from_secs(30))
+        .keepalives_interval(Duration::from_secs(5))
+        .keepalives_count(3)
+        .connect_timeout(Duration::from_secs(10));
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+    conn.identify_system().await?;
+    conn.close().await?;
+
+    // a server that accepts the connection but never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+
+    let options = options
+        .host("127.0.0.1")
+        .port(port)
+        .ssl_mode(sqlx::postgres::PgSslMode::Disable)
+        .connect_timeout(Duration::from_millis(200));
+
+    let started = Instant::now();
+    let error = PgReplicationConnection::connect_with(&options)
+        .await
+        .expect_err("connected to a server that doesn't answer");
+
+    assert!(
+        matches!(
+            &error,
+            ReplicationError::Sqlx(sqlx::Error::Io(error))
+                if error.kind() == std::io::ErrorKind::TimedOut
+        ),
+        "{error:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_waits_for_an_lsn() -> anyhow::Result<()> {
+    setup_publication("replication_wait").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_wait_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_wait_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_wait_pub"]),
+        )
+        .await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_wait (id, name) VALUES (1, 'foo')")
+        .await?;
+    let lsn: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut writer)
+        .await?;
+
+    // the messages received while waiting are returned
+    let messages =
+        tokio::time::timeout(Duration::from_secs(10), stream.wait_for_lsn(lsn)).await??;
+    assert!(messages
+        .iter()
+        .any(|message| matches!(message, LogicalReplication::Insert(_))));
+    assert!(matches!(
+        messages.last(),
+        Some(LogicalReplication::Commit(_))
+    ));
+
+    // reached from a keepalive if nothing is published after the target
+    writer
+        .execute("CREATE TABLE IF NOT EXISTS replication_wait_other (id INT)")
+        .await?;
+    let lsn: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut writer)
+        .await?;
+
+    let messages =
+        tokio::time::timeout(Duration::from_secs(10), stream.wait_for_lsn(lsn)).await??;
+    assert!(messages.is_empty(), "{messages:?}");
+    assert!(stream.received_lsn() >= lsn);
+
+    // returns right away once reached
+    assert!(stream.wait_for_lsn(lsn).await?.is_empty());
+
+    // and the stream goes on
+    writer
+        .execute("INSERT INTO replication_wait (id, name) VALUES (2, 'bar')")
+        .await?;
+
+    loop {
+        if let LogicalReplication::Insert(insert) =
+            stream.recv().await?.expect("stream ended unexpectedly")
+        {
+            assert_eq!(insert.new_data[1].as_str(), Some("bar"));
+            break;
+        }
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_enum_labels() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_enums_pub;
+DROP TABLE IF EXISTS replication_enums;
+DROP TYPE IF EXISTS replication_mood;
+CREATE TYPE replication_mood AS ENUM ('sad', 'ok', 'happy');
+CREATE TABLE replication_enums (id INT PRIMARY KEY, mood replication_mood);
+CREATE PUBLICATION replication_enums_pub FOR TABLE replication_enums;
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_enums_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    // enum values are sent as their label in the binary format as well
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_enums_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_enums_pub"]).binary(true),
+        )
+        .await?;
+
+    writer
+        .execute("INSERT INTO replication_enums (id, mood) VALUES (1, 'happy'), (2, NULL)")
+        .await?;
+
+    let mut rows = Vec::new();
+    while rows.len() < 2 {
+        if let LogicalReplication::Insert(insert) =
+            stream.recv().await?.expect("stream ended unexpectedly")
+        {
+            rows.push(insert);
+        }
+    }
+
+    let relation = stream
+        .relation(rows[0].relation_id)
+        .expect("unknown relation");
+    let type_ids: Vec<Oid> = relation
+        .columns
+        .iter()
+        .map(|column| column.type_id)
+        .collect();
+
+    // only the enum is returned, not `int4`
+    let types = enum_types(&mut writer, &type_ids).await?;
+    assert_eq!(types.len(), 1);
+    assert_eq!(types[0].type_id, relation.columns[1].type_id);
+    assert_eq!(types[0].name, "replication_mood");
+    assert_eq!(types[0].labels, ["sad", "ok", "happy"]);
+
+    assert_eq!(types[0].decode_label(&rows[0].new_data[1])?, Some("happy"));
+    assert_eq!(types[0].decode_label(&rows[1].new_data[1])?, None);
+    assert_eq!(
+        rows[0].new_data[1].try_decode::<String>(types[0].type_id)?,
+        "happy"
+    );
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_reports_stream_health() -> anyhow::Result<()> {
+    setup_publication("replication_health").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_health_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_health_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_health_pub"]),
+        )
+        .await?;
+
+    let health = stream.health();
+    assert!(health.connected);
+    assert!(!health.paused);
+    assert_eq!(health.since_last_keepalive, None);
+    assert_eq!(health.server_wal_end, None);
+    assert_eq!(health.lag, None);
+    assert!(!health.caught_up);
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_health (id, name) VALUES (1, 'foo')")
+        .await?;
+    let lsn: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut writer)
+        .await?;
+
+    let messages =
+        tokio::time::timeout(Duration::from_secs(10), stream.wait_for_lsn(lsn)).await??;
+    let Some(LogicalReplication::Commit(commit)) = messages.last() else {
+        panic!("no commit received: {messages:?}");
+    };
+    stream.set_confirmed_lsn(commit.end_lsn);
+
+    // a keepalive was asked for while waiting
+    let health = stream.health();
+    assert!(health.connected);
+    assert!(health.ready);
+    assert!(health.since_last_keepalive.is_some());
+    assert!(health.server_wal_end.is_some());
+    assert_eq!(health.confirmed_lsn, commit.end_lsn);
+    assert!(health.received_lsn >= lsn);
+    assert_eq!(
+        health.lag.map(|lag| lag.bytes),
+        stream.lag().map(|lag| lag.bytes)
+    );
+    assert_eq!(health.caught_up, stream.is_caught_up());
+
+    stream.cancel_handle().cancel();
+    assert!(!stream.health().connected);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_maps_keys_of_an_index_replica_identity() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_index_key_pub;
+DROP TABLE IF EXISTS replication_index_key;
+CREATE TABLE replication_index_key (
+    id INT PRIMARY KEY,
+    tenant TEXT NOT NULL,
+    payload TEXT,
+    code INT NOT NULL
+);
+-- the columns of the index are in another order than in the table
+CREATE UNIQUE INDEX replication_index_key_idx ON replication_index_key (code, tenant);
+ALTER TABLE replication_index_key REPLICA IDENTITY USING INDEX replication_index_key_idx;
+CREATE PUBLICATION replication_index_key_pub FOR TABLE replication_index_key;
+"#,
+        )
+        .await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_index_key_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_index_key_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_index_key_pub"]),
+        )
+        .await?;
+
+    writer
+        .execute(
+            "INSERT INTO replication_index_key (id, tenant, payload, code) \
+             VALUES (1, 'acme', 'foo', 42)",
+        )
+        .await?;
+    writer
+        .execute("UPDATE replication_index_key SET code = 43 WHERE id = 1")
+        .await?;
+    writer
+        .execute("DELETE FROM replication_index_key WHERE id = 1")
+        .await?;
+
+    let mut update = None;
+    let delete = loop {
+        match stream.recv().await?.expect("stream ended unexpectedly") {
+            LogicalReplication::Update(message) => update = Some(message),
+            LogicalReplication::Delete(message) => break message,
+            _ => {}
+        }
+    };
+    let update = update.expect("no update received");
+
+    let relation = stream
+        .relation(delete.relation_id)
+        .expect("unknown relation");
+    assert_eq!(relation.replica_identity, ReplicaIdentity::Index);
+
+    // the key columns are flagged in the table order, and the key is sent in the table order,
+    // with the other columns `NULL`
+    let key_columns: Vec<_> = Projection::key(relation)
+        .indices()
+        .iter()
+        .map(|&index| relation.columns[index].name.as_str())
+        .collect();
+    assert_eq!(key_columns, ["tenant", "code"]);
+
+    let key = delete.key(relation)?;
+    assert_eq!(key.names().collect::<Vec<_>>(), ["tenant", "code"]);
+    assert_eq!(key.get::<String>("tenant")?, "acme");
+    assert_eq!(key.get::<i32>("code")?, 43);
+    assert_eq!(key.row().len(), relation.columns.len());
+    assert!(key.row()[0].is_null() && key.row()[2].is_null());
+
+    // the old key of an update that changed it
+    let (before, _) = update.images();
+    let Some(BeforeImage::Key(old_key)) = before else {
+        panic!("no old key sent: {before:?}");
+    };
+    assert_eq!(
+        old_key
+            .get_by_name(relation, "code")
+            .map(|data| data.try_decode::<i32>(relation.columns[3].type_id))
+            .transpose()?,
+        Some(42)
+    );
+    assert_eq!(
+        old_key
+            .get_by_name(relation, "tenant")
+            .and_then(TupleData::as_str),
+        Some("acme")
+    );
+
+    // both keys decode into a tuple of the key columns
+    assert_eq!(
+        update.key(relation)?.decode::<(String, i32)>()?,
+        ("acme".to_owned(), 42)
+    );
+    assert_eq!(key.decode::<(&str, i32)>()?, ("acme", 43));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_archives_physical_wal() -> anyhow::Result<()> {
+    let mut conn = replication_connection().await?;
+    let system = conn.identify_system().await?;
+
+    let mut stream = conn
+        .start_physical_replication(None, system.xlogpos, Some(u32::try_from(system.timeline)?))
+        .await?;
+
+    // write some WAL
+    let mut sql = new::<Postgres>().await?;
+    sql.execute(
+        "CREATE TEMPORARY TABLE physical_archive (id INT); INSERT INTO physical_archive VALUES (1)",
+    )
+    .await?;
+
+    tokio::spawn({
+        let cancel = stream.cancel_handle();
+
+        async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            cancel.cancel();
+        }
+    });
+
+    let mut wal = Vec::new();
+    let archived = tokio::time::timeout(Duration::from_secs(5), stream.archive(&mut wal)).await??;
+
+    assert_eq!(archived.start_lsn, system.xlogpos);
+    assert!(archived.end_lsn > archived.start_lsn);
+    assert_eq!(archived.next_timeline, None);
+    assert_eq!(wal.len() as u64, archived.end_lsn.0 - archived.start_lsn.0);
+
+    // the WAL written is confirmed
+    assert_eq!(stream.confirmed_lsn(), archived.end_lsn);
+    assert_eq!(stream.received_lsn(), archived.end_lsn);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_persists_offsets_in_a_table() -> anyhow::Result<()> {
+    setup_publication("replication_offset").await?;
+
+    let mut admin = new::<Postgres>().await?;
+    admin
+        .execute(
+            r#"
+SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots
+WHERE slot_name = 'replication_offset_slot';
+SELECT pg_create_logical_replication_slot('replication_offset_slot', 'pgoutput');
+DROP TABLE IF EXISTS replication_offset_store;
+"#,
+        )
+        .await?;
+
+    let pool = PgPool::connect(&env::var("DATABASE_URL")?).await?;
+    let store = PgTableOffsetStore::new(pool).table(None, "replication_offset_store");
+    store.create_table().await?;
+    assert_eq!(store.load("replication_offset_slot").await?, None);
+
+    let options = || PgOutputOptions::new(["replication_offset_pub"]);
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication_with_store("replication_offset_slot", store.clone(), options())
+        .await?;
+
+    admin
+        .execute("INSERT INTO replication_offset (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let end_lsn = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit.end_lsn;
+        }
+    };
+
+    // the final status update of the stream stores the confirmed position
+    stream.set_confirmed_lsn(end_lsn);
+    stream.finish().await?.close().await?;
+    assert_eq!(store.load("replication_offset_slot").await?, Some(end_lsn));
+
+    // a stored position that is ahead of the slot skips the changes before it
+    admin
+        .execute("INSERT INTO replication_offset (id, name) VALUES (2, 'bar')")
+        .await?;
+    let current: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut admin)
+        .await?;
+    store.store("replication_offset_slot", current).await?;
+    admin
+        .execute("INSERT INTO replication_offset (id, name) VALUES (3, 'baz')")
+        .await?;
+
+    // stored positions never move backwards
+    store.store("replication_offset_slot", end_lsn).await?;
+    assert_eq!(store.load("replication_offset_slot").await?, Some(current));
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication_with_store("replication_offset_slot", store.clone(), options())
+        .await?;
+
+    let insert = loop {
+        if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+            break insert;
+        }
+    };
+    assert_eq!(insert.new_data[0].as_str(), Some("3"));
+
+    stream.finish().await?.close().await?;
+
+    admin
+        .execute(
+            "SELECT pg_drop_replication_slot('replication_offset_slot'); \
+             DROP TABLE replication_offset_store",
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_receives_values_larger_than_the_read_buffer() -> anyhow::Result<()> {
+    setup_publication("replication_large_value").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_large_value_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_large_value_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_large_value_pub"]),
+        )
+        .await?;
+    // the frame of the insert is far larger than the read buffer, and than a single read
+    stream.set_adaptive_read_buffer(Some(64 * 1024));
+
+    // 16 MiB that don't compress well, so the value is also stored out of line
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            "INSERT INTO replication_large_value (id, name) \
+             SELECT 1, string_agg(md5(i::text), '') FROM generate_series(1, 524288) i",
+        )
+        .await?;
+    let expected: String =
+        sqlx::query_scalar("SELECT name FROM replication_large_value WHERE id = 1")
+            .fetch_one(&mut writer)
+            .await?;
+    assert_eq!(expected.len(), 16 << 20);
+
+    let insert = loop {
+        match stream.recv().await?.expect("stream ended") {
+            LogicalReplication::Insert(insert) => break insert,
+            _ => continue,
+        }
+    };
+
+    assert_eq!(insert.new_data[1].as_str(), Some(&*expected));
+    assert!(stream.message_sizes().max() > 16 << 20);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_into_a_channel() -> anyhow::Result<()> {
+    use futures::StreamExt;
+
+    setup_publication("replication_channel").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_channel_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let stream = conn
+        .start_logical_replication(
+            "replication_channel_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_channel_pub"]),
+        )
+        .await?;
+    // a single message fits, so the channel is full while the transactions are streamed
+    let (mut receiver, channel) = stream.into_channel(1);
+
+    let mut writer = new::<Postgres>().await?;
+    for id in 1..=5 {
+        writer
+            .execute(&*format!(
+                "INSERT INTO replication_channel (id, name) VALUES ({id}, 'foo')"
+            ))
+            .await?;
+    }
+
+    let mut ids = Vec::new();
+    let mut end_lsn = None;
+    while ids.len() < 5 {
+        let message = tokio::time::timeout(Duration::from_secs(30), receiver.next())
+            .await?
+            .expect("channel ended");
+
+        match message {
+            LogicalReplication::Insert(insert) => {
+                ids.push(insert.new_data[0].as_str().unwrap().to_owned());
+            }
+            LogicalReplication::Commit(commit) => {
+                channel.confirm(commit.end_lsn);
+                end_lsn = Some(commit.end_lsn);
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(ids, ["1", "2", "3", "4", "5"]);
+
+    let conn = channel.finish().await?;
+    assert!(end_lsn.is_some());
+
+    // the task ended, so the channel ends once the messages left in it were received
+    while receiver.next().await.is_some() {}
+
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_checks_the_server_role() -> anyhow::Result<()> {
+    let mut conn = replication_connection().await?;
+
+    // the test server is a primary
+    assert_eq!(conn.server_role().await?, ServerRole::Primary);
+    conn.check_role(ServerRole::Primary).await?;
+
+    conn.set_expected_role(Some(ServerRole::Standby));
+    let error = conn
+        .start_physical_replication(None, PgLsn::INVALID, None)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            ReplicationError::UnexpectedRole {
+                expected: ServerRole::Standby,
+                actual: ServerRole::Primary,
+            }
+        ),
+        "{error:?}"
+    );
+    assert_eq!(
+        error.to_string(),
+        "connected to a primary, expected a standby"
+    );
+
+    let mut conn = replication_connection().await?;
+    conn.set_expected_role(Some(ServerRole::Primary));
+    let system = conn.identify_system().await?;
+
+    let stream = conn
+        .start_physical_replication(None, system.xlogpos, None)
+        .await?;
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_seeds_the_relation_cache() -> anyhow::Result<()> {
+    setup_publication("replication_warm_start").await?;
+
+    let mut admin = new::<Postgres>().await?;
+    admin
+        .execute(
+            r#"
+SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots
+WHERE slot_name = 'replication_warm_start_slot';
+SELECT pg_create_logical_replication_slot('replication_warm_start_slot', 'pgoutput');
+"#,
+        )
+        .await?;
+
+    let options = || PgOutputOptions::new(["replication_warm_start_pub"]);
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication("replication_warm_start_slot", PgLsn::INVALID, options())
+        .await?;
+
+    admin
+        .execute("INSERT INTO replication_warm_start (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let end_lsn = loop {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            break commit.end_lsn;
+        }
+    };
+
+    let relations: Vec<Relation> = stream.relations().cloned().collect();
+    assert_eq!(relations.len(), 1);
+    let relation_id = relations[0].relation_id;
+    assert!(!stream.is_seeded_relation(relation_id));
+
+    stream.set_confirmed_lsn(end_lsn);
+    stream.finish().await?.close().await?;
+
+    admin
+        .execute(
+            "ALTER TABLE replication_warm_start ADD COLUMN extra INT; \
+             INSERT INTO replication_warm_start (id, name, extra) VALUES (2, 'bar', 3)",
+        )
+        .await?;
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication("replication_warm_start_slot", end_lsn, options())
+        .await?;
+    stream.seed_relations(relations.clone());
+
+    // the seeded definition is cached before the server sent any
+    assert!(stream.is_seeded_relation(relation_id));
+    assert_eq!(stream.relation(relation_id).unwrap().columns.len(), 2);
+
+    // the server still sends the current definition, which replaces the seeded one
+    let relation = loop {
+        if let Some(LogicalReplication::Relation(relation)) = stream.recv().await? {
+            break relation;
+        }
+    };
+    assert!(!relations[0].same_definition(&relation));
+    assert!(!stream.is_seeded_relation(relation_id));
+    assert_eq!(stream.relation(relation_id).unwrap().columns.len(), 3);
+
+    // definitions sent by the server are not replaced
+    stream.seed_relations(relations);
+    assert!(!stream.is_seeded_relation(relation_id));
+    assert_eq!(stream.relation(relation_id).unwrap().columns.len(), 3);
+
+    stream.finish().await?.close().await?;
+
+    admin
+        .execute("SELECT pg_drop_replication_slot('replication_warm_start_slot')")
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_checks_the_contiguity_of_positions() -> anyhow::Result<()> {
+    setup_publication("replication_contiguity").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_contiguity_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_contiguity_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_contiguity_pub"]),
+        )
+        .await?;
+    stream.set_contiguity_check(ContiguityCheck::Error);
+
+    // the transaction that started first commits last, so its changes have lower positions
+    // than the commit of the transaction sent before it
+    let mut first = new::<Postgres>().await?;
+    let mut second = new::<Postgres>().await?;
+    first
+        .execute("BEGIN; INSERT INTO replication_contiguity (id, name) VALUES (1, 'foo')")
+        .await?;
+    second
+        .execute("BEGIN; INSERT INTO replication_contiguity (id, name) VALUES (2, 'bar')")
+        .await?;
+    second.execute("COMMIT").await?;
+    first.execute("COMMIT").await?;
+
+    let mut commits = Vec::new();
+    while commits.len() < 2 {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            commits.push(commit.end_lsn);
+        }
+    }
+    assert!(commits[0] < commits[1]);
+
+    stream.finish().await?.close().await?;
+
+    // physical WAL is contiguous from the start position
+    let mut conn = replication_connection().await?;
+    let system = conn.identify_system().await?;
+
+    let mut stream = conn
+        .start_physical_replication(None, system.xlogpos, None)
+        .await?;
+    stream.set_contiguity_check(ContiguityCheck::Error);
+
+    first
+        .execute("INSERT INTO replication_contiguity (id, name) VALUES (3, 'baz')")
+        .await?;
+    first
+        .execute("INSERT INTO replication_contiguity (id, name) VALUES (4, 'qux')")
+        .await?;
+
+    let mut chunks = 0;
+    while chunks < 2 {
+        if let Some(PhysicalReplication::XLogData(_)) =
+            tokio::time::timeout(Duration::from_secs(10), stream.recv()).await??
+        {
+            chunks += 1;
+        }
+    }
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_frames_with_a_frame_decoder() -> anyhow::Result<()> {
+    setup_publication("replication_frame_decoder").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_frame_decoder_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_frame_decoder_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_frame_decoder_pub"]),
+        )
+        .await?;
+
+    // a pass-through decoder, which sees every frame before it is decoded
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    stream.set_frame_decoder({
+        let frames = frames.clone();
+
+        move |frame| {
+            frames.lock().unwrap().push(frame[0]);
+
+            if frame[0] == b'w' && frame.get(25) == Some(&b'I') {
+                return Err(sqlx::Error::Protocol("refusing the insert".into()));
+            }
+
+            Ok(frame)
+        }
+    });
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_frame_decoder (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let error = loop {
+        match stream.recv().await {
+            Ok(Some(LogicalReplication::Insert(_))) => panic!("the insert was decoded"),
+            Ok(_) => continue,
+            Err(error) => break error,
+        }
+    };
+    assert!(error.to_string().contains("refusing the insert"), "{error}");
+    assert!(frames.lock().unwrap().contains(&b'w'));
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_replays_captured_streams() -> anyhow::Result<()> {
+    /// A writer whose output is read after the stream is finished.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    setup_publication("replication_capture").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_capture_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_capture_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_capture_pub"]),
+        )
+        .await?;
+
+    let capture = SharedBuffer::default();
+    stream.set_capture(capture.clone());
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_capture (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let mut live = Vec::new();
+    while let Some(message) = stream.recv().await? {
+        let commit = matches!(message, LogicalReplication::Commit(_));
+        live.push(format!("{message:?}"));
+
+        if commit {
+            break;
+        }
+    }
+
+    stream.finish().await?.close().await?;
+
+    let capture = capture.0.lock().unwrap().clone();
+    let replayed: Vec<_> = ReplayReader::new(&capture[..], LogicalDecodeContext::new(1))
+        .map(|message| message.map(|message| format!("{message:?}")))
+        .collect::<Result<_, _>>()?;
+
+    assert_eq!(replayed, live);
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_applies_changes_to_a_copy() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_apply_pub;
+DROP SCHEMA IF EXISTS replication_apply, replication_apply_copy CASCADE;
+CREATE SCHEMA replication_apply;
+CREATE SCHEMA replication_apply_copy;
+CREATE TYPE replication_apply.mood AS ENUM ('happy', 'sad');
+CREATE TABLE replication_apply.users (
+    id INT PRIMARY KEY, name TEXT, mood replication_apply.mood, tags INT[], body TEXT
+);
+ALTER TABLE replication_apply.users ALTER COLUMN body SET STORAGE EXTERNAL;
+CREATE TABLE replication_apply_copy.users (LIKE replication_apply.users INCLUDING ALL);
+CREATE PUBLICATION replication_apply_pub FOR TABLE replication_apply.users;
+"#,
+    )
+    .await?;
+
+    let mut replication = replication_connection().await?;
+    replication
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_apply_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+    let mut stream = replication
+        .start_logical_replication(
+            "replication_apply_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_apply_pub"]),
+        )
+        .await?;
+
+    conn.execute(
+        r#"
+INSERT INTO replication_apply.users VALUES
+    (1, 'foo', 'happy', '{1,2}', repeat('x', 10000)),
+    (2, 'bar', NULL, NULL, NULL),
+    (3, 'baz', 'sad', '{}', 'short');
+UPDATE replication_apply.users SET name = 'foo2', mood = 'sad' WHERE id = 1;
+UPDATE replication_apply.users SET id = 4 WHERE id = 2;
+DELETE FROM replication_apply.users WHERE id = 3;
+"#,
+    )
+    .await?;
+
+    let mut target = new::<Postgres>().await?;
+    // the relations renamed to the copies of the tables in the other schema
+    let mut relations = HashMap::new();
+    let mut applied = 0;
+
+    loop {
+        let Some(change) = stream.recv().await? else {
+            panic!("stream ended");
+        };
+
+        match &change {
+            LogicalReplication::Relation(relation) => {
+                let mut relation = relation.clone();
+                relation.namespace = "replication_apply_copy".to_owned();
+                relations.insert(relation.relation_id, relation);
+            }
+            LogicalReplication::Commit(_) => break,
+            _ => {}
+        }
+
+        let statement = ApplyStatement::from_change(
+            &change,
+            |relation_id| relations.get(&relation_id),
+            |type_id| stream.custom_type(type_id),
+        )?;
+
+        if let Some(statement) = statement {
+            statement.execute(&mut target).await?;
+            applied += 1;
+        }
+    }
+
+    assert_eq!(applied, 6);
+
+    // the unchanged TOAST value of the first update is kept in the copy
+    let query = "SELECT id, name, mood::text, tags, body FROM {} ORDER BY id";
+    let source: Vec<(
+        i32,
+        Option<String>,
+        Option<String>,
+        Option<Vec<i32>>,
+        Option<String>,
+    )> = sqlx::query_as(&query.replace("{}", "replication_apply.users"))
+        .fetch_all(&mut conn)
+        .await?;
+    let copy: Vec<(
+        i32,
+        Option<String>,
+        Option<String>,
+        Option<Vec<i32>>,
+        Option<String>,
+    )> = sqlx::query_as(&query.replace("{}", "replication_apply_copy.users"))
+        .fetch_all(&mut target)
+        .await?;
+    assert_eq!(copy.len(), 2);
+    assert_eq!(copy, source);
+
+    // an insert that is applied again is an upsert
+    let insert = LogicalReplication::Insert(Insert {
+        xid: None,
+        relation_id: Oid(0),
+        new_data: Tuples(vec![
+            TupleData::Text("1".into()),
+            TupleData::Text("again".into()),
+            TupleData::Null,
+            TupleData::Null,
+            TupleData::Null,
+        ]),
+    });
+    let relation = relations.values().next().unwrap();
+    ApplyStatement::from_change(&insert, |_| Some(relation), |_| None)?
+        .unwrap()
+        .execute(&mut target)
+        .await?;
+
+    let name: String =
+        sqlx::query_scalar("SELECT name FROM replication_apply_copy.users WHERE id = 1")
+            .fetch_one(&mut target)
+            .await?;
+    assert_eq!(name, "again");
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_validates_start_options() -> anyhow::Result<()> {
+    setup_publication("replication_validate").await?;
+
+    let mut conn = replication_connection().await?;
+    let slot = conn
+        .create_replication_slot(
+            &CreateReplicationSlot::logical("replication_validate_slot", "pgoutput")
+                .temporary(true),
+        )
+        .await?;
+
+    let options = PgOutputOptions::new(["replication_validate_pub"]);
+    let validation = conn
+        .validate_start("replication_validate_slot", &options, PgLsn::INVALID)
+        .await?;
+    assert_eq!(validation.slot.slot_name, "replication_validate_slot");
+    assert_eq!(validation.start_lsn, slot.consistent_point);
+    assert!(validation.server_lsn >= slot.consistent_point);
+
+    // each failing check leaves the connection usable for the next one
+    let error = conn
+        .validate_start(
+            "replication_validate_slot",
+            &options.clone().streaming(true),
+            PgLsn::INVALID,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+
+    let error = conn
+        .validate_start(
+            "replication_validate_slot",
+            &PgOutputOptions::new(["replication_validate_pub", "replication_missing_pub"]),
+            PgLsn::INVALID,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(error, ReplicationError::InvalidOptions { ref reason } if reason.contains("replication_missing_pub")),
+        "expected InvalidOptions, got {error:?}"
+    );
+
+    let error = conn
+        .validate_start(
+            "replication_validate_missing_slot",
+            &options,
+            PgLsn::INVALID,
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::SlotNotFound { .. }));
+
+    let error = conn
+        .validate_start(
+            "replication_validate_slot",
+            &options,
+            PgLsn(validation.server_lsn.0 + (1 << 32)),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ReplicationError::InvalidOptions { .. }));
+
+    // replication can still be started after the checks
+    let stream = conn
+        .start_logical_replication("replication_validate_slot", PgLsn::INVALID, options)
+        .await?;
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_creates_missing_publications() -> anyhow::Result<()> {
+    let mut conn = new::<Postgres>().await?;
+    conn.execute(
+        r#"
+DROP PUBLICATION IF EXISTS replication_create_pub, replication_create_denied_pub;
+DROP TABLE IF EXISTS replication_create;
+CREATE TABLE replication_create (id INT PRIMARY KEY, name TEXT);
+DROP ROLE IF EXISTS replication_create_role;
+CREATE ROLE replication_create_role;
+"#,
+    )
+    .await?;
+
+    let publication = CreatePublication::new("replication_create_pub")
+        .table("public", "replication_create")
+        .publish_truncate(false);
+
+    assert!(create_publication_if_missing(&mut conn, &publication).await?);
+    // an existing publication is left as it is
+    assert!(
+        !create_publication_if_missing(&mut conn, &publication.clone().publish_truncate(true))
+            .await?
+    );
+
+    let (tables, truncate): (i64, bool) = sqlx::query_as(
+        "SELECT (SELECT count(*) FROM pg_publication_tables WHERE pubname = p.pubname), \
+         pubtruncate FROM pg_publication p WHERE pubname = 'replication_create_pub'",
+    )
+    .fetch_one(&mut conn)
+    .await?;
+    assert_eq!((tables, truncate), (1, false));
+
+    // without the privileges to create it
+    conn.execute("SET ROLE replication_create_role").await?;
+    let error = create_publication_if_missing(
+        &mut conn,
+        &CreatePublication::new("replication_create_denied_pub").all_tables(true),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(error, ReplicationError::PublicationPermissionDenied { ref publication, .. } if publication == "replication_create_denied_pub"),
+        "expected PublicationPermissionDenied, got {error:?}"
+    );
+    conn.execute("RESET ROLE").await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_yields_keepalive_events() -> anyhow::Result<()> {
+    setup_publication("replication_events").await?;
+
+    let mut conn = replication_connection().await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_events_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_events_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_events_pub"]),
+        )
+        .await?;
+
+    // keepalives are requested with each status update
+    stream.set_status_interval(Duration::from_millis(100));
+
+    let event = stream
+        .recv_event()
+        .await?
+        .expect("stream ended unexpectedly");
+    assert!(matches!(event, StreamEvent::Keepalive(_)), "{event:?}");
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute("INSERT INTO replication_events (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let mut events = stream.into_event_stream();
+    loop {
+        match events.try_next().await?.expect("stream ended unexpectedly") {
+            StreamEvent::Keepalive(_) => continue,
+            StreamEvent::Data(message) => {
+                assert!(
+                    matches!(message, LogicalReplication::Begin(_)),
+                    "{message:?}"
+                );
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_decodes_system_types() -> anyhow::Result<()> {
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+DROP PUBLICATION IF EXISTS replication_system_pub;
+DROP TABLE IF EXISTS replication_system;
+CREATE TABLE replication_system (
+    id INT PRIMARY KEY, object OID, tx XID, tx8 XID8, command CID, location TID
+);
+CREATE PUBLICATION replication_system_pub FOR TABLE replication_system;
+"#,
+        )
+        .await?;
+
+    for binary in [false, true] {
+        let mut conn = replication_connection().await?;
+
+        conn.create_replication_slot(
+            &CreateReplicationSlot::logical("replication_system_slot", "pgoutput")
+                .temporary(true)
+                .snapshot(SnapshotAction::NoExport),
+        )
+        .await?;
+
+        let mut stream = conn
+            .start_logical_replication(
+                "replication_system_slot",
+                PgLsn::INVALID,
+                PgOutputOptions::new(["replication_system_pub"]).binary(binary),
+            )
+            .await?;
+
+        writer
+            .execute(
+                "INSERT INTO replication_system VALUES \
+                 (1, 16384, '4294967295', '4294967298', '7', '(4294967295,65535)')",
+            )
+            .await?;
+
+        let insert = loop {
+            if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+                break insert;
+            }
+        };
+
+        let relation = stream
+            .relation(insert.relation_id)
+            .expect("relation not cached");
+        let values = relation.columns[1..]
+            .iter()
+            .zip(&insert.new_data[1..])
+            .map(|(column, value)| SystemValue::decode(value, column.type_id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            values,
+            [
+                Some(SystemValue::Oid(16384)),
+                Some(SystemValue::Xid(u32::MAX)),
+                Some(SystemValue::Xid8((1 << 32) + 2)),
+                Some(SystemValue::Cid(7)),
+                Some(SystemValue::Tid(u32::MAX, u16::MAX)),
+            ]
+        );
+        assert_eq!(
+            insert.new_data[1].try_decode::<Oid>(relation.columns[1].type_id)?,
+            Oid(16384)
+        );
+
+        stream.finish().await?.close().await?;
+        writer.execute("DELETE FROM replication_system").await?;
+    }
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_stops_at_the_stop_lsn() -> anyhow::Result<()> {
+    setup_publication("replication_stop").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    writer
+        .execute(
+            r#"
+SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots
+WHERE slot_name = 'replication_stop_slot';
+SELECT pg_create_logical_replication_slot('replication_stop_slot', 'pgoutput');
+"#,
+        )
+        .await?;
+
+    for id in 1..=3 {
+        sqlx::query("INSERT INTO replication_stop (id, name) VALUES ($1, 'foo')")
+            .bind(id)
+            .execute(&mut writer)
+            .await?;
+    }
+
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication(
+            "replication_stop_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_stop_pub"]),
+        )
+        .await?;
+
+    // find the commit positions of the transactions
+    let mut commits = Vec::new();
+    while commits.len() < 3 {
+        if let Some(LogicalReplication::Commit(commit)) = stream.recv().await? {
+            commits.push(commit.commit_lsn);
+        }
+    }
+    stream.finish().await?.close().await?;
+
+    // the second transaction commits exactly at the stop position and is delivered
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication(
+            "replication_stop_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_stop_pub"]),
+        )
+        .await?;
+    stream.set_stop_lsn(Some(commits[1]));
+
+    let mut inserted = Vec::new();
+    while let Some(message) = stream.recv().await? {
+        if let LogicalReplication::Insert(insert) = message {
+            inserted.push(insert.new_data[0].try_decode::<i32>(Oid(23))?);
+        }
+    }
+    assert_eq!(inserted, [1, 2]);
+    assert!(stream.recv().await?.is_none());
+
+    // the connection can be used for other commands
+    let mut conn = stream.finish().await?;
+    conn.identify_system().await?;
+    conn.close().await?;
+
+    // while idle, a keepalive reports that the stop position was reached
+    let mut stream = replication_connection()
+        .await?
+        .start_logical_replication(
+            "replication_stop_slot",
+            commits[2],
+            PgOutputOptions::new(["replication_stop_pub"]),
+        )
+        .await?;
+    stream.set_status_interval(Duration::from_millis(100));
+    let current: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut writer)
+        .await?;
+    stream.set_stop_lsn(Some(current));
+
+    let mut inserted = Vec::new();
+    while let Some(message) = tokio::time::timeout(Duration::from_secs(10), stream.recv()).await?? {
+        if let LogicalReplication::Insert(insert) = message {
+            inserted.push(insert.new_data[0].try_decode::<i32>(Oid(23))?);
+        }
+    }
+    assert_eq!(inserted, [3]);
+
+    stream.finish().await?.close().await?;
+
+    writer
+        .execute("SELECT pg_drop_replication_slot('replication_stop_slot')")
+        .await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_stops_before_streamed_transactions_past_the_stop_lsn() -> anyhow::Result<()> {
+    setup_publication("replication_stop_streaming").await?;
+
+    // a small `logical_decoding_work_mem` makes the server stream the large transaction
+    let options = env::var("DATABASE_URL")?
+        .parse::<PgConnectOptions>()?
+        .options([("logical_decoding_work_mem", "64kB")]);
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_stop_streaming_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    // the large transaction starts before the stop position, and commits after it; both are
+    // written before streaming starts, so that no keepalive reports the stop position between
+    // the commits
+    let mut large = new::<Postgres>().await?;
+    let mut small = new::<Postgres>().await?;
+
+    let mut tx = large.begin().await?;
+    tx.execute(
+        "INSERT INTO replication_stop_streaming (id, name) \
+         SELECT i, 'large ' || i FROM generate_series(1, 5000) i",
+    )
+    .await?;
+
+    small
+        .execute("INSERT INTO replication_stop_streaming (id, name) VALUES (0, 'small')")
+        .await?;
+    // just before the commit record of the large transaction, which is written next
+    let stop_lsn: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn() - 1")
+        .fetch_one(&mut small)
+        .await?;
+
+    tx.commit().await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_stop_streaming_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_stop_streaming_pub"])
+                .proto_version(2)
+                .streaming(true),
+        )
+        .await?;
+    stream.set_stop_lsn(Some(stop_lsn));
+
+    let mut streamed = false;
+    let mut committed = 0;
+    while let Some(message) = tokio::time::timeout(Duration::from_secs(10), stream.recv()).await?? {
+        match message {
+            LogicalReplication::StreamStart(_) => streamed = true,
+            LogicalReplication::Commit(_) => committed += 1,
+            LogicalReplication::StreamCommit(commit) => {
+                panic!(
+                    "received the commit at {} past the stop position",
+                    commit.commit_lsn
+                )
+            }
+            _ => {}
+        }
+    }
+
+    assert!(streamed, "the large transaction was not streamed");
+    assert_eq!(committed, 1);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_authenticates_with_scram() -> anyhow::Result<()> {
+    let mut admin = new::<Postgres>().await?;
+    admin
+        .execute(
+            r#"
+SET password_encryption = 'scram-sha-256';
+DROP ROLE IF EXISTS replication_scram;
+CREATE ROLE replication_scram LOGIN REPLICATION PASSWORD 'password';
+"#,
+        )
+        .await?;
+
+    let verifier: String =
+        sqlx::query_scalar("SELECT rolpassword FROM pg_authid WHERE rolname = 'replication_scram'")
+            .fetch_one(&mut admin)
+            .await?;
+    assert!(verifier.starts_with("SCRAM-SHA-256$"));
+
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+    let options = options.username("replication_scram");
+
+    // the server must ask for the password of the role, e.g. with a `pg_hba.conf` line
+    // `host all replication_scram all scram-sha-256`, as logical replication connections are
+    // matched by their database like other connections
+    match PgReplicationConnection::connect_with(&options.clone().password("wrong")).await {
+        Ok(conn) => {
+            conn.close().await?;
+            return Ok(());
+        }
+        Err(ReplicationError::Sqlx(sqlx::Error::Database(error))) => {
+            assert_eq!(error.code().as_deref(), Some("28P01"), "{error}");
+        }
+        Err(error) => panic!("unexpected error: {error:?}"),
+    }
+
+    let mut conn = PgReplicationConnection::connect_with(&options.password("password")).await?;
+    conn.identify_system().await?;
+    conn.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_diffs_slot_positions() -> anyhow::Result<()> {
+    setup_publication("replication_slot_diff").await?;
+
+    let mut conn = new::<Postgres>().await?;
+
+    // temporary slots, dropped with the connection
+    conn.execute(
+        r#"
+SELECT pg_create_logical_replication_slot('replication_slot_diff_old', 'pgoutput', true);
+SELECT pg_create_logical_replication_slot('replication_slot_diff_new', 'pgoutput', true);
+SELECT pg_create_physical_replication_slot('replication_slot_diff_physical', false, true);
+INSERT INTO replication_slot_diff (id, name) SELECT i, 'foo' FROM generate_series(1, 100) i;
+"#,
+    )
+    .await?;
+
+    let current: PgLsn = sqlx::query_scalar("SELECT pg_current_wal_lsn()")
+        .fetch_one(&mut conn)
+        .await?;
+    let advanced =
+        advance_replication_slot(&mut conn, "replication_slot_diff_new", current).await?;
+
+    let diff = slot_position_diff(
+        &mut conn,
+        "replication_slot_diff_new",
+        "replication_slot_diff_old",
+    )
+    .await?;
+    let old = diff
+        .second
+        .expect("logical slot without confirmed position");
+    assert_eq!(diff.first, Some(advanced));
+    assert_eq!(diff.bytes(), Some(advanced.diff(old)));
+    assert!(diff.bytes().unwrap() > 0);
+
+    // the other way round, the old slot is behind
+    let diff = slot_position_diff(
+        &mut conn,
+        "replication_slot_diff_old",
+        "replication_slot_diff_new",
+    )
+    .await?;
+    assert!(diff.bytes().unwrap() < 0);
+
+    // a physical slot has no confirmed position
+    let diff = slot_position_diff(
+        &mut conn,
+        "replication_slot_diff_old",
+        "replication_slot_diff_physical",
+    )
+    .await?;
+    assert_eq!(diff.second, None);
+    assert_eq!(diff.bytes(), None);
+
+    let error = slot_position_diff(
+        &mut conn,
+        "replication_slot_diff_old",
+        "replication_slot_diff_missing",
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(error, ReplicationError::SlotNotFound { ref slot } if slot == "replication_slot_diff_missing"),
+        "{error}"
+    );
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_streams_over_a_unix_socket() -> anyhow::Result<()> {
+    setup_publication("replication_socket").await?;
+
+    let mut writer = new::<Postgres>().await?;
+    let directories: String = sqlx::query_scalar("SHOW unix_socket_directories")
+        .fetch_one(&mut writer)
+        .await?;
+    let port: i32 = sqlx::query_scalar("SELECT current_setting('port')::int")
+        .fetch_one(&mut writer)
+        .await?;
+
+    // the socket is only reachable if the server runs on this host
+    let Some(directory) = directories.split(',').map(str::trim).find(|directory| {
+        Path::new(directory)
+            .join(format!(".s.PGSQL.{port}"))
+            .exists()
+    }) else {
+        return Ok(());
+    };
+
+    let options: PgConnectOptions = env::var("DATABASE_URL")?.parse()?;
+    // keepalives don't apply to Unix-domain sockets and are ignored
+    let options = options
+        .socket(directory)
+        .application_name("replication_socket")
+        .keepalives_idle(Duration::from_secs(30));
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_socket_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_socket_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_socket_pub"]),
+        )
+        .await?;
+
+    writer
+        .execute("INSERT INTO replication_socket (id, name) VALUES (1, 'foo')")
+        .await?;
+
+    let insert = loop {
+        if let Some(LogicalReplication::Insert(insert)) = stream.recv().await? {
+            break insert;
+        }
+    };
+    assert_eq!(insert.new_data[1].as_str(), Some("foo"));
+
+    // a walsender of a Unix-domain socket connection has no client address
+    let local: bool = sqlx::query_scalar(
+        "SELECT client_addr IS NULL FROM pg_stat_replication \
+         WHERE application_name = 'replication_socket'",
+    )
+    .fetch_one(&mut writer)
+    .await?;
+    assert!(local);
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
+
+#[sqlx_macros::test]
+async fn it_aborts_streamed_subtransactions() -> anyhow::Result<()> {
+    const ROWS: i32 = 3000;
+
+    setup_publication("replication_subabort").await?;
+
+    // a small `logical_decoding_work_mem` makes the server stream the large transaction
+    let options = env::var("DATABASE_URL")?
+        .parse::<PgConnectOptions>()?
+        .options([("logical_decoding_work_mem", "64kB")]);
+
+    let mut conn = PgReplicationConnection::connect_with(&options).await?;
+
+    conn.create_replication_slot(
+        &CreateReplicationSlot::logical("replication_subabort_slot", "pgoutput")
+            .temporary(true)
+            .snapshot(SnapshotAction::NoExport),
+    )
+    .await?;
+
+    let mut stream = conn
+        .start_logical_replication(
+            "replication_subabort_slot",
+            PgLsn::INVALID,
+            PgOutputOptions::new(["replication_subabort_pub"])
+                .proto_version(2)
+                .streaming(true),
+        )
+        .await?;
+
+    // the rows of the savepoint are streamed before it is rolled back
+    let mut writer = new::<Postgres>().await?;
+    let mut tx = writer.begin().await?;
+    tx.execute(&*format!(
+        "INSERT INTO replication_subabort (id, name) \
+         SELECT i, 'kept' FROM generate_series(1, {ROWS}) i"
+    ))
+    .await?;
+    tx.execute("SAVEPOINT discarded").await?;
+    tx.execute(&*format!(
+        "INSERT INTO replication_subabort (id, name) \
+         SELECT i, 'discarded' FROM generate_series({}, {}) i",
+        ROWS + 1,
+        2 * ROWS
+    ))
+    .await?;
+    tx.execute("ROLLBACK TO SAVEPOINT discarded").await?;
+    tx.execute(&*format!(
+        "INSERT INTO replication_subabort (id, name) VALUES ({}, 'kept')",
+        2 * ROWS + 1
+    ))
+    .await?;
+    tx.commit().await?;
+
+    let mut rows: HashMap<u32, Vec<String>> = HashMap::new();
+    let mut subtransaction_aborts = 0;
+
+    let committed = loop {
+        match stream.recv().await?.expect("stream ended unexpectedly") {
+            LogicalReplication::Insert(insert) => {
+                let xid = insert
+                    .xid
+                    .expect("Insert of a transaction that is not streamed");
+                let name = insert.new_data[1].as_str().unwrap().to_owned();
+                rows.entry(xid).or_default().push(name);
+            }
+            LogicalReplication::StreamAbort(abort) => {
+                assert!(!abort.is_top_level(), "{abort:?}");
+                assert_ne!(abort.xid, abort.subxid);
+
+                // only the changes of the subtransaction are discarded
+                rows.remove(&abort.subxid);
+                subtransaction_aborts += 1;
+            }
+            LogicalReplication::StreamCommit(_) => break rows,
+            _ => {}
+        }
+    };
+
+    assert_eq!(subtransaction_aborts, 1);
+    let names: Vec<_> = committed.into_values().flatten().collect();
+    assert_eq!(names.len(), ROWS as usize + 1);
+    assert!(names.iter().all(|name| name == "kept"));
+
+    stream.finish().await?.close().await?;
+
+    Ok(())
+}
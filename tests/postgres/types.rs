@@ -3,7 +3,7 @@ extern crate time_ as time;
 use std::net::SocketAddr;
 use std::ops::Bound;
 
-use sqlx::postgres::types::{Oid, PgCiText, PgInterval, PgMoney, PgRange};
+use sqlx::postgres::types::{Oid, PgCiText, PgInterval, PgLsn, PgMoney, PgRange};
 use sqlx::postgres::Postgres;
 use sqlx_test::{new, test_decode_type, test_prepared_type, test_type};
 
@@ -97,6 +97,11 @@ test_type!(i8(
 
 test_type!(Oid(Postgres, "325235::oid" == Oid(325235),));
 
+test_type!(pg_lsn<PgLsn>(Postgres,
+    "'0/0'::pg_lsn" == PgLsn(0),
+    "'16/B374D848'::pg_lsn" == PgLsn(0x16_B374_D848),
+));
+
 test_type!(i16(
     Postgres,
     "-2144::smallint" == -2144_i16,